@@ -0,0 +1,247 @@
+// 生成版本信息，src/version 用 include! 引入：版本号、git 提交、构建时间、打开的特性。
+// SOURCE_DATE_EPOCH 设置时用它作为构建时间，方便重现构建；不在 git 仓库里时提交显示成 unknown。
+//
+// 打开 symbols 特性时生成嵌入内核的符号表，格式见 src/debug/symbols.rs
+// KERNEL_SYMBOLS 指向上一次构建出的内核 ELF，从它的 .symtab 里取出函数符号并还原成 Rust 的路径；
+// 也可以是 `nm -n -C` 的输出。没有设置时生成空表。
+// 表总是补齐到 CAPACITY 字节，这样嵌入真正的符号表前后内核的布局不变
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// 和 src/debug/symbols.rs 里的一致
+const CAPACITY: usize = 1024 * 1024;
+// 每隔这么多个符号存一次完整的名字和地址，查找时先二分找到块再在块里顺序解码
+const BLOCK: usize = 32;
+// 名字超过这么长时截断，内核解码时用定长的缓冲区
+const MAX_NAME: usize = 255;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    version();
+    symbols();
+}
+
+fn version() {
+    // 切换分支或者提交之后重新生成
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/index");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    let git = |args: &[&str]| {
+        Command::new("git").args(args).output().ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    };
+    let hash = match git(&["rev-parse", "--short=12", "HEAD"]) {
+        Some(hash) if git(&["status", "--porcelain", "--untracked-files=no"]).map_or(false, |s| !s.is_empty()) => {
+            format!("{}-dirty", hash)
+        }
+        Some(hash) => hash,
+        None => String::from("unknown"),
+    };
+    let timestamp = env::var("SOURCE_DATE_EPOCH").ok()
+        .and_then(|epoch| epoch.parse().ok())
+        .unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs());
+    let mut features: Vec<String> = env::vars()
+        .filter_map(|(key, _)| key.strip_prefix("CARGO_FEATURE_").map(|name| name.to_lowercase().replace('_', "-")))
+        .collect();
+    features.sort();
+
+    let code = format!(
+        "pub const VERSION: &str = {:?};\n\
+         pub const GIT_HASH: &str = {:?};\n\
+         pub const BUILD_TIMESTAMP: i64 = {};\n\
+         pub const PROFILE: &str = {:?};\n\
+         pub const FEATURES: &[&str] = &{:?};\n",
+        env::var("CARGO_PKG_VERSION").unwrap(),
+        hash,
+        timestamp,
+        env::var("PROFILE").unwrap(),
+        features,
+    );
+    let out = PathBuf::from(env::var_os("OUT_DIR").unwrap()).join("version.rs");
+    fs::write(out, code).expect("cannot write version info");
+}
+
+fn symbols() {
+    println!("cargo:rerun-if-env-changed=KERNEL_SYMBOLS");
+    if env::var_os("CARGO_FEATURE_SYMBOLS").is_none() {
+        return;
+    }
+
+    let mut symbols = Vec::new();
+    if let Some(path) = env::var_os("KERNEL_SYMBOLS") {
+        println!("cargo:rerun-if-changed={}", PathBuf::from(&path).display());
+        let file = fs::read(&path).expect("cannot read KERNEL_SYMBOLS");
+        symbols = if file.starts_with(b"\x7fELF") {
+            elf_symbols(&file).expect("KERNEL_SYMBOLS is not a 64-bit little-endian ELF with a symbol table")
+        } else {
+            String::from_utf8_lossy(&file).lines().filter_map(parse_line).collect()
+        };
+    }
+    symbols.sort();
+    symbols.dedup_by_key(|(address, _)| *address);
+    let mut table = encode(&symbols);
+    if table.len() > CAPACITY {
+        println!("cargo:warning=symbol table is {} bytes, dropped all symbols (limit {})", table.len(), CAPACITY);
+        table = encode(&[]);
+    }
+    table.resize(CAPACITY, 0);
+
+    let out = PathBuf::from(env::var_os("OUT_DIR").unwrap()).join("ksyms.bin");
+    fs::write(out, table).expect("cannot write symbol table");
+}
+
+// 压缩的格式，所有整数都是小端：
+//   "KSYM"、符号数 u32、块数 u32
+//   每块一项索引：第一个符号的地址 u64、块的数据相对数据区开头的偏移 u32
+//   数据区：每个符号依次是和上一个符号的地址差、和上一个名字相同的前缀长度、剩下部分的长度（都是 LEB128），再接剩下部分
+//   每块的第一个符号地址差和前缀长度都是 0，可以单独解码
+fn encode(symbols: &[(u64, String)]) -> Vec<u8> {
+    let blocks: Vec<&[(u64, String)]> = symbols.chunks(BLOCK).collect();
+    let mut index = Vec::new();
+    let mut data = Vec::new();
+    for block in &blocks {
+        index.extend_from_slice(&block[0].0.to_le_bytes());
+        index.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        let (mut previous_address, mut previous_name) = (block[0].0, &b""[..]);
+        for (address, name) in block.iter() {
+            let name = &name.as_bytes()[..name.len().min(MAX_NAME)];
+            let shared = previous_name.iter().zip(name).take_while(|(a, b)| a == b).count();
+            leb128(&mut data, address - previous_address);
+            leb128(&mut data, shared as u64);
+            leb128(&mut data, (name.len() - shared) as u64);
+            data.extend_from_slice(&name[shared..]);
+            previous_address = *address;
+            previous_name = name;
+        }
+    }
+    let mut table = b"KSYM".to_vec();
+    table.extend_from_slice(&(symbols.len() as u32).to_le_bytes());
+    table.extend_from_slice(&(blocks.len() as u32).to_le_bytes());
+    table.extend(index);
+    table.extend(data);
+    table
+}
+
+fn leb128(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+// 从 ELF 的 .symtab 里取出所有定义了的函数符号
+fn elf_symbols(file: &[u8]) -> Option<Vec<(u64, String)>> {
+    let u16_at = |offset: usize| Some(u16::from_le_bytes(file.get(offset..offset + 2)?.try_into().ok()?));
+    let u32_at = |offset: usize| Some(u32::from_le_bytes(file.get(offset..offset + 4)?.try_into().ok()?));
+    let u64_at = |offset: usize| Some(u64::from_le_bytes(file.get(offset..offset + 8)?.try_into().ok()?));
+    // ELFCLASS64、小端
+    if file.get(4) != Some(&2) || file.get(5) != Some(&1) {
+        return None;
+    }
+    let section_offset = u64_at(0x28)? as usize;
+    let section_size = u16_at(0x3A)? as usize;
+    let sections = u16_at(0x3C)? as usize;
+    let section = |i: usize| section_offset + i * section_size;
+    // SHT_SYMTAB，它的 sh_link 是对应的字符串表
+    let symtab = (0..sections).map(section).find(|&header| u32_at(header + 4) == Some(2))?;
+    let strtab = section(u32_at(symtab + 0x28)? as usize);
+    let (symbols_start, symbols_size) = (u64_at(symtab + 0x18)? as usize, u64_at(symtab + 0x20)? as usize);
+    let strings = file.get(u64_at(strtab + 0x18)? as usize..)?;
+
+    let mut symbols = Vec::new();
+    for symbol in file.get(symbols_start..symbols_start + symbols_size)?.chunks_exact(24) {
+        let info = symbol[4];
+        let section_index = u16::from_le_bytes([symbol[6], symbol[7]]);
+        // STT_FUNC，并且定义在某个节里
+        if info & 0xF != 2 || section_index == 0 {
+            continue;
+        }
+        let name_offset = u32::from_le_bytes(symbol[0..4].try_into().unwrap()) as usize;
+        let address = u64::from_le_bytes(symbol[8..16].try_into().unwrap());
+        let name = strings.get(name_offset..)?;
+        let name = String::from_utf8_lossy(&name[..name.iter().position(|&b| b == 0)?]);
+        if !name.is_empty() {
+            symbols.push((address, demangle(&name)));
+        }
+    }
+    Some(symbols)
+}
+
+// 还原 Rust 旧的符号修饰：_ZN 后面是一串 "<长度><名字>"，以 E 结束，最后一段是 "h" 加 16 位十六进制的哈希。
+// 别的格式原样返回
+fn demangle(symbol: &str) -> String {
+    let Some(mut rest) = symbol.strip_prefix("_ZN") else { return symbol.to_string() };
+    let mut parts = Vec::new();
+    while !rest.starts_with('E') {
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        let Ok(len) = rest[..digits].parse::<usize>() else { return symbol.to_string() };
+        let Some(part) = rest.get(digits..digits + len) else { return symbol.to_string() };
+        parts.push(part);
+        rest = &rest[digits + len..];
+    }
+    if let Some(hash) = parts.last().and_then(|last| last.strip_prefix('h')) {
+        if hash.len() == 16 && hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            parts.pop();
+        }
+    }
+    parts.iter().map(|part| unescape(part)).collect::<Vec<_>>().join("::")
+}
+
+// 名字里不能出现在符号中的字符写成 $..$ 的形式，:: 写成 ..
+fn unescape(part: &str) -> String {
+    // 以 $ 开头的段前面补了一个下划线
+    let part = if part.starts_with("_$") { &part[1..] } else { part };
+    let mut out = String::new();
+    let mut rest = part;
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix("..") {
+            out.push_str("::");
+            rest = after;
+        } else if rest.starts_with('$') {
+            let Some(end) = rest[1..].find('$') else { break };
+            let code = &rest[1..end + 1];
+            let ch = match code {
+                "SP" => Some('@'),
+                "BP" => Some('*'),
+                "RF" => Some('&'),
+                "LT" => Some('<'),
+                "GT" => Some('>'),
+                "LP" => Some('('),
+                "RP" => Some(')'),
+                "C" => Some(','),
+                _ => code.strip_prefix('u').and_then(|hex| u32::from_str_radix(hex, 16).ok()).and_then(char::from_u32),
+            };
+            match ch {
+                Some(ch) => out.push(ch),
+                None => out.push_str(&rest[..end + 2]),
+            }
+            rest = &rest[end + 2..];
+        } else {
+            let ch = rest.chars().next().unwrap();
+            out.push(ch);
+            rest = &rest[ch.len_utf8()..];
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+// nm 的一行是 "<地址> <类型> <名字>"，只保留代码段里的符号（类型 t 或 T）
+fn parse_line(line: &str) -> Option<(u64, String)> {
+    let mut fields = line.splitn(3, ' ');
+    let address = u64::from_str_radix(fields.next()?, 16).ok()?;
+    let kind = fields.next()?;
+    let name = fields.next()?.trim();
+    (matches!(kind, "t" | "T") && !name.is_empty()).then(|| (address, name.to_string()))
+}
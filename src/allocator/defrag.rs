@@ -0,0 +1,200 @@
+// 空闲时整理堆
+// 释放时已经和相邻的空闲区域合并，但长时间运行后还是会留下很多夹在已分配块之间的小空闲块。
+// shell 等待输入时每次调用 idle 做一小步，关中断的时间很短，不会让交互卡顿：
+// 1. 把空闲链表里的一个区域摘下来再放回去，和地址上相邻、但没有合并的空闲区域合并；
+// 2. 缓存的数据放在 Movable 里，只能通过句柄临时访问，没人访问时可以搬家：
+//    一块数据两边都是空闲区域时，把它复制到别处，原来的位置和两边合成一块
+
+use alloc::alloc::{GlobalAlloc, Layout};
+use alloc::vec::Vec;
+use core::ptr::copy_nonoverlapping;
+use core::slice;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use x86_64::instructions::interrupts;
+
+use crate::allocator::linked_list::LinkedListAllocator;
+use crate::allocator::ALLOCATOR;
+use crate::sync::IrqSafeMutex;
+
+// 最多登记的可搬动数据块，登记表本身不能分配内存
+const MAX_MOVABLE: usize = 1024;
+// 每次 idle 最多检查的数据块
+const SCAN_PER_STEP: usize = 32;
+
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    ptr: *mut u8,
+    len: usize,
+    // 正在 Movable::with 里访问的次数，不为 0 时不能搬
+    pinned: usize,
+}
+
+// 指针只在持有登记表的锁时读写
+unsafe impl Send for Slot {}
+
+static SLOTS: IrqSafeMutex<[Option<Slot>; MAX_MOVABLE]> = IrqSafeMutex::named("movable slots", [None; MAX_MOVABLE]);
+// 下一次从空闲链表的哪个区域、登记表的哪个位置开始检查
+static REGION_CURSOR: AtomicUsize = AtomicUsize::new(0);
+static SLOT_CURSOR: AtomicUsize = AtomicUsize::new(0);
+static MERGED: AtomicUsize = AtomicUsize::new(0);
+static MOVED: AtomicUsize = AtomicUsize::new(0);
+
+/// 可以被整理程序搬动的字节缓冲区
+///
+/// 登记表满了时退化成普通的 Vec，照样能用，只是不会被搬动
+#[derive(Debug)]
+pub struct Movable {
+    inner: Inner,
+}
+
+#[derive(Debug)]
+enum Inner {
+    Slot(usize),
+    Fixed(Vec<u8>),
+}
+
+impl Movable {
+    pub fn new(data: Vec<u8>) -> Self {
+        if data.is_empty() {
+            return Movable { inner: Inner::Fixed(data) };
+        }
+        let len = data.len();
+        // 按 len 字节、1 字节对齐重新分配，搬家时用同样的布局
+        let ptr = unsafe { ALLOCATOR.alloc(layout(len)) };
+        if ptr.is_null() {
+            return Movable { inner: Inner::Fixed(data) };
+        }
+        unsafe { copy_nonoverlapping(data.as_ptr(), ptr, len) };
+        let index = {
+            let mut slots = SLOTS.lock();
+            slots.iter().position(|slot| slot.is_none()).map(|index| {
+                slots[index] = Some(Slot { ptr, len, pinned: 0 });
+                index
+            })
+        };
+        match index {
+            Some(index) => Movable { inner: Inner::Slot(index) },
+            None => {
+                unsafe { ALLOCATOR.dealloc(ptr, layout(len)) };
+                Movable { inner: Inner::Fixed(data) }
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.inner {
+            Inner::Slot(index) => SLOTS.lock()[*index].map_or(0, |slot| slot.len),
+            Inner::Fixed(data) => data.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 在 `f` 执行期间访问数据，这段时间里数据不会被搬动
+    pub fn with<R>(&self, f: impl FnOnce(&[u8]) -> R) -> R {
+        let index = match &self.inner {
+            Inner::Slot(index) => *index,
+            Inner::Fixed(data) => return f(data),
+        };
+        // 只在加减计数时持有锁，f 里可以分配内存，也可以访问其他 Movable
+        let slot = {
+            let mut slots = SLOTS.lock();
+            let slot = slots[index].as_mut().expect("movable slot is gone");
+            slot.pinned += 1;
+            *slot
+        };
+        let result = f(unsafe { slice::from_raw_parts(slot.ptr, slot.len) });
+        if let Some(slot) = SLOTS.lock()[index].as_mut() {
+            slot.pinned -= 1;
+        }
+        result
+    }
+}
+
+impl Drop for Movable {
+    fn drop(&mut self) {
+        if let Inner::Slot(index) = self.inner {
+            let slot = SLOTS.lock()[index].take();
+            if let Some(slot) = slot {
+                unsafe { ALLOCATOR.dealloc(slot.ptr, layout(slot.len)) };
+            }
+        }
+    }
+}
+
+fn layout(len: usize) -> Layout {
+    Layout::from_size_align(len, 1).expect("movable buffer too large")
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DefragStats {
+    /// 合并掉的空闲区域数
+    pub merged: usize,
+    /// 搬动过的数据块数
+    pub moved: usize,
+    /// 当前登记的可搬动数据块数
+    pub movable: usize,
+}
+
+pub fn stats() -> DefragStats {
+    let movable = SLOTS.lock().iter().flatten().count();
+    DefragStats { merged: MERGED.load(Ordering::Relaxed), moved: MOVED.load(Ordering::Relaxed), movable }
+}
+
+/// 做一小步整理
+///
+/// 由 shell 在等待输入的空闲时间里调用
+pub fn idle() {
+    if !super::is_initialized() {
+        return;
+    }
+    merge_step();
+    move_step();
+}
+
+fn merge_step() {
+    let index = REGION_CURSOR.load(Ordering::Relaxed);
+    let merged = interrupts::without_interrupts(|| ALLOCATOR.lock().recoalesce(index));
+    match merged {
+        Some(true) => {
+            MERGED.fetch_add(1, Ordering::Relaxed);
+        }
+        Some(false) => REGION_CURSOR.store(index + 1, Ordering::Relaxed),
+        None => REGION_CURSOR.store(0, Ordering::Relaxed),
+    }
+}
+
+// 找一个没在访问、两边都是空闲区域的数据块搬走：新位置从最大的空闲区域的开头分出来，
+// 左边是已分配的块，不会再被搬回来；原来的位置和两边合成一块，空闲区域少了一个
+fn move_step() {
+    let start = SLOT_CURSOR.load(Ordering::Relaxed);
+    {
+        let mut slots = SLOTS.lock();
+        for index in (start..start + SCAN_PER_STEP).map(|i| i % MAX_MOVABLE) {
+            let Some(slot) = slots[index].as_mut() else { continue };
+            if slot.pinned > 0 {
+                continue;
+            }
+            let (start, end) = LinkedListAllocator::block_range(slot.ptr, layout(slot.len));
+            if ALLOCATOR.lock().free_neighbours(start, end) != (true, true) {
+                continue;
+            }
+            // 不让缓存释放内存：回收时可能要释放 Movable，而这里正拿着登记表的锁
+            let ptr = unsafe { ALLOCATOR.try_alloc(layout(slot.len)) };
+            if ptr.is_null() {
+                break;
+            }
+            unsafe {
+                copy_nonoverlapping(slot.ptr, ptr, slot.len);
+                ALLOCATOR.dealloc(slot.ptr, layout(slot.len));
+            }
+            slot.ptr = ptr;
+            MOVED.fetch_add(1, Ordering::Relaxed);
+            break;
+        }
+    }
+    SLOT_CURSOR.store((start + SCAN_PER_STEP) % MAX_MOVABLE, Ordering::Relaxed);
+}
@@ -4,7 +4,7 @@
 use core::alloc::{GlobalAlloc, Layout};
 use core::mem;
 
-use crate::allocator::{align_up, Locked};
+use crate::allocator::{align_up, redzone, shrinker, Locked};
 
 struct ListNode {
     size: usize,
@@ -47,6 +47,51 @@ impl LinkedListAllocator {
         self.add_free_region(heap_start, heap_size);
     }
 
+    /// 空闲链表中所有区域的总大小和区域个数
+    pub fn free_stats(&self) -> (usize, usize) {
+        let (mut bytes, mut regions) = (0, 0);
+        let mut current = &self.head;
+        while let Some(region) = current.next.as_deref() {
+            bytes += region.size;
+            regions += 1;
+            current = region;
+        }
+        (bytes, regions)
+    }
+
+    /// 最大的空闲区域的大小，链表按大小从大到小排列，就是第一个
+    pub fn largest_free(&self) -> usize {
+        self.head.next.as_deref().map_or(0, |region| region.size)
+    }
+
+    /// 地址区间 [start, end) 的左边、右边是否紧挨着空闲区域
+    pub fn free_neighbours(&self, start: usize, end: usize) -> (bool, bool) {
+        let (mut left, mut right) = (false, false);
+        let mut current = &self.head;
+        while let Some(region) = current.next.as_deref() {
+            left |= region.end_addr() == start;
+            right |= region.start_addr() == end;
+            current = region;
+        }
+        (left, right)
+    }
+
+    /// 把链表中第 `index` 个区域摘下来再放回去，放回时会和地址相邻的空闲区域合并
+    ///
+    /// 返回区域个数是否减少了，`index` 超出链表长度时返回 None
+    pub fn recoalesce(&mut self, index: usize) -> Option<bool> {
+        let (_, before) = self.free_stats();
+        let mut current = &mut self.head;
+        for _ in 0..index {
+            current = current.next.as_deref_mut()?;
+        }
+        let region = current.next.take()?;
+        current.next = region.next.take();
+        let (addr, size) = (region.start_addr(), region.size);
+        unsafe { self.add_free_region(addr, size) };
+        Some(self.free_stats().1 < before)
+    }
+
     /// 将指定的内存区域增加到链表中
     unsafe fn add_free_region(&mut self, addr: usize, size: usize) {
         // 确保这个空闲区域和链表是适配的
@@ -90,6 +135,46 @@ impl LinkedListAllocator {
         current.next = Some(&mut *node_ptr);
     }
 
+    /// 把从 `addr` 开始的空闲区域从链表中摘下来，没有时返回 None
+    fn take_region_at(&mut self, addr: usize) -> Option<&'static mut ListNode> {
+        let mut current = &mut self.head;
+        while current.next.as_ref()?.start_addr() != addr {
+            current = current.next.as_mut().unwrap();
+        }
+        let region = current.next.take().unwrap();
+        current.next = region.next.take();
+        Some(region)
+    }
+
+    /// 在原地把从 `addr` 开始的 `old_size` 字节的已分配块调整成 `new_size` 字节，大小都是 size_align 调整过的
+    ///
+    /// 缩小时把尾部还给链表；变大时要求紧跟在后面的是足够大的空闲区域。做不到时返回 false，什么都不改
+    unsafe fn resize_in_place(&mut self, addr: usize, old_size: usize, new_size: usize) -> bool {
+        if new_size <= old_size {
+            let tail = old_size - new_size;
+            if tail == 0 {
+                return true;
+            }
+            if tail < mem::size_of::<ListNode>() {
+                return false;
+            }
+            self.add_free_region(addr + new_size, tail);
+            return true;
+        }
+        let need = new_size - old_size;
+        let Some(region) = self.take_region_at(addr + old_size) else { return false };
+        let (start, size) = (region.start_addr(), region.size);
+        if size < need || (size > need && size - need < mem::size_of::<ListNode>()) {
+            // 放回去，和两边都不相邻（左边是这个已分配块），不会合并
+            self.add_free_region(start, size);
+            return false;
+        }
+        if size > need {
+            self.add_free_region(start + need, size - need);
+        }
+        true
+    }
+
     /// 寻找一个满足给定大小的空闲区域，并把它从链表中移除
     ///
     /// 返回列表节点和可用区域的起始地址
@@ -120,7 +205,12 @@ impl LinkedListAllocator {
     /// 成功时返回分配起始地址
     fn alloc_from_region(region: &ListNode, size: usize, align: usize)
                          -> Result<usize, ()>{
-        let alloc_start = align_up(region.start_addr(), align);
+        let mut alloc_start = align_up(region.start_addr(), align);
+        let padding = alloc_start - region.start_addr();
+        if padding > 0 && padding < mem::size_of::<ListNode>() {
+            // 对齐留下的空隙要还给链表，放不下一个ListNode时往后挪一个对齐单位
+            alloc_start = align_up(region.start_addr() + mem::size_of::<ListNode>(), align);
+        }
         let alloc_end = alloc_start.checked_add(size).ok_or(())?;
 
         if alloc_end > region.end_addr(){
@@ -140,7 +230,7 @@ impl LinkedListAllocator {
     /// 调整给出的布局，使得其内存区域也能满足存储一个链表节点的需求
     ///
     /// 返回调整后的布局大小和对齐方式
-    fn size_align(layout: Layout) -> (usize, usize) {
+    pub(super) fn size_align(layout: Layout) -> (usize, usize) {
         let layout = layout
             .align_to(mem::align_of::<ListNode>())
             .expect("adjusting alignment failed")
@@ -148,32 +238,87 @@ impl LinkedListAllocator {
         let size = layout.size().max(mem::size_of::<ListNode>());
         (size, layout.align())
     }
+
+    /// 分配给调用者的指针 `ptr` 实际占用的地址区间 [start, end)，包括 heapcheck 的红区
+    pub(super) fn block_range(ptr: *mut u8, layout: Layout) -> (usize, usize) {
+        let (size, _) = Self::size_align(redzone::outer(layout));
+        let start = redzone::raw_start(ptr, layout);
+        (start, start + size)
+    }
 }
 
-unsafe impl GlobalAlloc for Locked<LinkedListAllocator> {
-    unsafe fn alloc(&self, layout:Layout)->*mut u8{
-        // 进行布局调整
-        let (size, align) = LinkedListAllocator::size_align(layout);
+impl Locked<LinkedListAllocator> {
+    /// 只在空闲链表里找，不让缓存释放内存；找不到时返回空指针
+    pub(super) unsafe fn try_alloc(&self, layout: Layout) -> *mut u8 {
+        // 进行布局调整，heapcheck 时加上红区
+        let (size, align) = LinkedListAllocator::size_align(redzone::outer(layout));
         let mut allocator = self.lock();
 
         if let Some((region,alloc_start)) = allocator.find_region(size,align) {
             // 找到了，进行分配
+            let (region_start, region_end) = (region.start_addr(), region.end_addr());
             let alloc_end = alloc_start.checked_add(size).expect("overflow");
-            let excess_size = region.end_addr() - alloc_end;
+            let excess_size = region_end - alloc_end;
             if excess_size > 0 {
                 // 有剩余空间，把它加入到链表中
                 allocator.add_free_region(alloc_end, excess_size);
             }
-            alloc_start as *mut u8
-        } else {
-            // 没找到，返回空指针
-            core::ptr::null_mut()
+            if alloc_start > region_start {
+                // 对齐留下的空隙也放回去
+                allocator.add_free_region(region_start, alloc_start - region_start);
+            }
+            shrinker::allocated(size);
+            drop(allocator);
+            return redzone::on_alloc(alloc_start as *mut u8, layout);
+        }
+        core::ptr::null_mut()
+    }
+}
+
+unsafe impl GlobalAlloc for Locked<LinkedListAllocator> {
+    unsafe fn alloc(&self, layout:Layout)->*mut u8{
+        let (size, _) = LinkedListAllocator::size_align(redzone::outer(layout));
+        loop {
+            let ptr = self.try_alloc(layout);
+            if !ptr.is_null() {
+                return ptr;
+            }
+            // 没找到，让缓存释放一些内存再试，什么都释放不出来时返回空指针
+            if shrinker::reclaim(size) == 0 {
+                return ptr;
+            }
         }
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        let (size,_) = LinkedListAllocator::size_align(layout);
+        // 先检查红区，发现破坏时 panic 不会拿着分配器的锁
+        let ptr = redzone::on_dealloc(ptr, layout);
+        let (size,_) = LinkedListAllocator::size_align(redzone::outer(layout));
 
         self.lock().add_free_region(ptr as usize, size);
+        shrinker::freed(size);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        // heapcheck 的红区跟着块的大小走，这时总是重新分配
+        if !redzone::ENABLED {
+            let (old, _) = LinkedListAllocator::size_align(layout);
+            let (new, _) = LinkedListAllocator::size_align(new_layout);
+            if self.lock().resize_in_place(ptr as usize, old, new) {
+                if new > old {
+                    shrinker::allocated(new - old);
+                } else {
+                    shrinker::freed(old - new);
+                }
+                return ptr;
+            }
+        }
+        let new_ptr = self.alloc(new_layout);
+        if !new_ptr.is_null() {
+            core::ptr::copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size));
+            self.dealloc(ptr, layout);
+        }
+        new_ptr
     }
 }
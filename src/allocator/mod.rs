@@ -2,19 +2,26 @@
 use alloc::alloc::{GlobalAlloc, Layout};
 // 引入 `null_mut` 函数，它返回一个空指针（即 `NULL`）
 use core::ptr::null_mut;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 // 引入x86_64架构相关的分页模块和类型，包括页映射错误、帧分配器、页表标志等，以及虚拟地址类型 `VirtAddr
 use x86_64::{
     structures::paging::{
-        mapper::MapToError, FrameAllocator, Mapper, Page, PageTableFlags, Size4KiB,
+        mapper::MapToError, FrameAllocator, Mapper, PageSize, PageTableFlags, Size2MiB, Size4KiB,
     },
     VirtAddr,
 };
 
 use crate::allocator::linked_list::LinkedListAllocator;
+use crate::config;
+use crate::memory::{layout, wx};
+use crate::memory::vmm::map_fixed_huge;
 
 // 引入自定义的 `BumpAllocator` 分配器，用于堆内存管理
 pub mod bump;
+pub mod defrag;
 mod linked_list;
+mod redzone;
+pub mod shrinker;
 // 定义一个通用的锁结构体 `Locked`, 它包含一个互斥锁 (`spin::Mutex`) 来保护内部数据
 pub struct Locked<A> {
     inner:spin::Mutex<A>,
@@ -33,6 +40,10 @@ impl<A> Locked<A> {
     pub fn lock(&self) -> spin::MutexGuard<A> {
         self.inner.lock()
     }
+
+    pub fn try_lock(&self) -> Option<spin::MutexGuard<A>> {
+        self.inner.try_lock()
+    }
 }
 
 // 实现向上对齐函数，将地址按给定对齐大小进行对齐。例如，如果地址是1000，且对齐大小是1024，则返回1024
@@ -56,45 +67,89 @@ unsafe impl GlobalAlloc for Dummy {
 
 #[global_allocator]
 static ALLOCATOR: Locked<LinkedListAllocator> = Locked::new(LinkedListAllocator::new());
+// 堆初始化完成之后才能分配内存，启动早期的控制台输出据此决定是否保留历史
+static HEAP_READY: AtomicBool = AtomicBool::new(false);
+
+pub const HEAP_START: usize = layout::HEAP.start as usize;
+// 默认的堆大小，启动参数 heap= 可以修改，按 2MiB 取整后限制在 MIN_HEAP_SIZE..=MAX_HEAP_SIZE 之间
+pub const HEAP_SIZE: usize = 60 * 1024 * 1024;
+pub const MIN_HEAP_SIZE: usize = 16 * 1024 * 1024;
+pub const MAX_HEAP_SIZE: usize = layout::HEAP.size as usize;
 
-pub const HEAP_START: usize = 0x_0001_0000_0000;
-pub const HEAP_SIZE: usize = 60 * 1024 * 1024; // 10 MiB
+static SIZE: AtomicUsize = AtomicUsize::new(HEAP_SIZE);
+
+/// 实际的堆大小
+pub fn heap_size() -> usize {
+    SIZE.load(Ordering::Relaxed)
+}
 
 // 初始化堆：
 // 1. **计算页面范围**：从起始地址到结束地址，确定需要多少页。
 // 2. 创建虚拟地址对象并计算出对应的页对象范围，以便后续映射物理帧
-pub fn init_heap (
-    mapper: &mut impl Mapper<Size4KiB>,
-    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
- ) ->Result<(), MapToError<Size4KiB>> {
-    let page_range = {
-        let heap_start = VirtAddr::new(HEAP_START as u64);
-        let heap_end = heap_start + HEAP_SIZE - 1u64;
-        let heap_start_page = Page::containing_address(heap_start);
-        let heap_end_page = Page::containing_address(heap_end);
-        Page::range_inclusive(heap_start_page, heap_end_page)
-    };
-    // 循环遍历每个页面**：
-    // - 为每个页面分配物理帧，并检查是否成功。如果失败则返回错误。
-    // - 设置页面表标志，使其可读可写。
-    // - 使用mapper将虚拟页面映射到物理帧，并刷新缓存以确保映射生效
-    for page in page_range {
-        let frame = frame_allocator
-            .allocate_frame()
-            .ok_or(MapToError::FrameAllocationFailed)?;
-        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
-        unsafe {
-            mapper.map_to(page, frame, flags, frame_allocator)?.flush()
-        };
-    }
+pub fn init_heap<M, A>(
+    mapper: &mut M,
+    frame_allocator: &mut A,
+ ) ->Result<(), MapToError<Size4KiB>>
+where
+    M: Mapper<Size4KiB> + Mapper<Size2MiB>,
+    A: FrameAllocator<Size4KiB> + FrameAllocator<Size2MiB>,
+{
+    // 堆位于固定的虚拟地址，通过 memory::vmm 分配物理帧并映射为可读可写；堆起始地址 2MiB 对齐，基本都能用上大页
+    let size = config::heap_size().map_or(HEAP_SIZE, |size| {
+        size.clamp(MIN_HEAP_SIZE, MAX_HEAP_SIZE).next_multiple_of(Size2MiB::SIZE as usize)
+    });
+    SIZE.store(size, Ordering::Relaxed);
+    let flags = wx::data_flags(PageTableFlags::PRESENT | PageTableFlags::WRITABLE);
+    map_fixed_huge(mapper, frame_allocator, VirtAddr::new(HEAP_START as u64), size as u64, flags)?;
     // 初始化全局分配器：设置堆起始位置和大小。这一步必须放在安全块里，因为它操作的是裸指针，不受Rust编译器保护
     unsafe {
-        ALLOCATOR.lock().init(HEAP_START, HEAP_SIZE);
+        ALLOCATOR.lock().init(HEAP_START, size);
     }
+    HEAP_READY.store(true, Ordering::Release);
 
     Ok(())
 }
 
+/// 堆是否已经可以分配内存
+pub fn is_initialized() -> bool {
+    HEAP_READY.load(Ordering::Acquire)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct HeapStats {
+    pub size: usize,
+    pub free: usize,
+    pub free_regions: usize,
+    /// 最大的空闲区域，一次最多能分配这么多
+    pub largest_free: usize,
+}
+
+impl HeapStats {
+    /// 碎片化程度的百分比：空闲空间里不在最大的空闲区域中的部分，没有空闲空间时为 0
+    pub fn fragmentation(&self) -> usize {
+        if self.free == 0 {
+            return 0;
+        }
+        100 - self.largest_free * 100 / self.free
+    }
+}
+
+// 堆的使用情况，遍历空闲链表得到
+pub fn heap_stats() -> HeapStats {
+    let ((free, free_regions), largest_free) = x86_64::instructions::interrupts::without_interrupts(|| {
+        let allocator = ALLOCATOR.lock();
+        (allocator.free_stats(), allocator.largest_free())
+    });
+    HeapStats { size: heap_size(), free, free_regions, largest_free }
+}
+
+/// 和 heap_stats 一样，但不等锁，分配器正被占用时返回 None；panic 时用
+pub fn try_heap_stats() -> Option<HeapStats> {
+    let allocator = ALLOCATOR.try_lock()?;
+    let (free, free_regions) = allocator.free_stats();
+    Some(HeapStats { size: heap_size(), free, free_regions, largest_free: allocator.largest_free() })
+}
+
 #[allow(dead_code)]
 pub fn test_allocator() {
     use alloc::boxed::Box;
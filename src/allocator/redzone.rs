@@ -0,0 +1,143 @@
+// 堆破坏检测
+// 打开 heapcheck 特性后，每块分配的前后各加一段红区：前面是头部（大小、分配时的调用栈、魔数）和填满 CANARY 的字节，
+// 后面是 REDZONE 个 CANARY 字节。释放时检查魔数、大小和两边的红区，发现越界写、重复释放或者按错误的大小释放，
+// 就把这块内存的地址、大小、分配时的调用栈和被改写的位置写到串口，再 panic 输出释放处的调用栈。
+// 检查通过后整块填成 POISON，释放后还在用的指针读到的是一眼就能认出来的 0x6b6b...。
+// 调用栈沿帧指针取几层返回地址，嵌入了符号表时显示成函数名。
+// 没有打开特性时这里的函数原样返回，分配不占额外的内存
+
+use core::alloc::Layout;
+
+/// 是否打开了 heapcheck
+pub(super) const ENABLED: bool = cfg!(feature = "heapcheck");
+
+#[cfg(feature = "heapcheck")]
+pub(super) use imp::{on_alloc, on_dealloc, outer, raw_start};
+
+/// 实际向空闲链表要的布局
+#[cfg(not(feature = "heapcheck"))]
+#[inline(always)]
+pub(super) fn outer(layout: Layout) -> Layout {
+    layout
+}
+
+/// 从空闲链表分到 `raw` 之后调用，返回给调用者的指针
+#[cfg(not(feature = "heapcheck"))]
+#[inline(always)]
+pub(super) unsafe fn on_alloc(raw: *mut u8, _layout: Layout) -> *mut u8 {
+    raw
+}
+
+/// 释放之前调用，检查红区，返回要还给空闲链表的指针
+#[cfg(not(feature = "heapcheck"))]
+#[inline(always)]
+pub(super) unsafe fn on_dealloc(ptr: *mut u8, _layout: Layout) -> *mut u8 {
+    ptr
+}
+
+/// 调用者拿到的指针 `ptr` 所在的整块内存的起始地址
+#[cfg(not(feature = "heapcheck"))]
+#[inline(always)]
+pub(super) fn raw_start(ptr: *mut u8, _layout: Layout) -> usize {
+    ptr as usize
+}
+
+#[cfg(feature = "heapcheck")]
+mod imp {
+    use core::alloc::Layout;
+    use core::fmt::{self, Write};
+    use core::mem::{align_of, size_of};
+    use core::ptr::write_bytes;
+    use core::slice;
+
+    use crate::allocator::align_up;
+    use crate::debug;
+    use crate::io::qemu::SerialStream;
+
+    // 每边红区的字节数
+    const REDZONE: usize = 16;
+    const CANARY: u8 = 0xFD;
+    const POISON: u8 = 0x6B;
+    // 记下的调用栈层数，以及跳过的分配器自己的层数（on_alloc、try_alloc、GlobalAlloc::alloc）
+    const CALLERS: usize = 6;
+    const SKIP: usize = 3;
+    const LIVE: u64 = 0x4556_494C_5041_4548; // "HEAPLIVE"
+    const FREED: u64 = 0x4545_5246_5041_4548; // "HEAPFREE"
+
+    // 释放后空闲链表的节点会盖住开头，魔数放在最后，重复释放时还能认出来
+    #[repr(C)]
+    struct Header {
+        size: usize,
+        callers: [usize; CALLERS],
+        magic: u64,
+    }
+
+    fn front(align: usize) -> usize {
+        align_up(size_of::<Header>() + REDZONE, align.max(align_of::<Header>()))
+    }
+
+    pub(in crate::allocator) fn outer(layout: Layout) -> Layout {
+        let size = front(layout.align()) + layout.size() + REDZONE;
+        Layout::from_size_align(size, layout.align().max(align_of::<Header>())).expect("allocation too large")
+    }
+
+    pub(in crate::allocator) fn raw_start(ptr: *mut u8, layout: Layout) -> usize {
+        ptr as usize - front(layout.align())
+    }
+
+    #[inline(never)]
+    pub(in crate::allocator) unsafe fn on_alloc(raw: *mut u8, layout: Layout) -> *mut u8 {
+        let front = front(layout.align());
+        let mut frames = [0; SKIP + CALLERS];
+        let count = debug::collect_frames(&mut frames).max(SKIP);
+        let mut callers = [0; CALLERS];
+        callers[..count - SKIP].copy_from_slice(&frames[SKIP..count]);
+        (raw as *mut Header).write(Header { size: layout.size(), callers, magic: LIVE });
+        write_bytes(raw.add(size_of::<Header>()), CANARY, front - size_of::<Header>());
+        let ptr = raw.add(front);
+        write_bytes(ptr.add(layout.size()), CANARY, REDZONE);
+        ptr
+    }
+
+    pub(in crate::allocator) unsafe fn on_dealloc(ptr: *mut u8, layout: Layout) -> *mut u8 {
+        let front = front(layout.align());
+        let raw = ptr.sub(front);
+        let header = &*(raw as *const Header);
+        match header.magic {
+            LIVE => {}
+            FREED => report(ptr, layout, Some(header), format_args!("double free")),
+            _ => report(ptr, layout, None, format_args!("header overwritten")),
+        }
+        if header.size != layout.size() {
+            report(ptr, layout, Some(header), format_args!("freed as {} bytes", layout.size()));
+        }
+        let before = slice::from_raw_parts(raw.add(size_of::<Header>()), front - size_of::<Header>());
+        if let Some(i) = before.iter().rposition(|&byte| byte != CANARY) {
+            let offset = before.len() - i;
+            report(ptr, layout, Some(header), format_args!("underflow, byte -{} overwritten", offset));
+        }
+        let after = slice::from_raw_parts(ptr.add(layout.size()), REDZONE);
+        if let Some(i) = after.iter().position(|&byte| byte != CANARY) {
+            let offset = layout.size() + i;
+            report(ptr, layout, Some(header), format_args!("overflow, byte {} overwritten", offset));
+        }
+        (*(raw as *mut Header)).magic = FREED;
+        write_bytes(raw.add(size_of::<Header>()), POISON, front - size_of::<Header>() + layout.size() + REDZONE);
+        raw
+    }
+
+    // 和 kassert! 一样只写串口：可能正拿着图形的锁
+    #[cold]
+    fn report(ptr: *mut u8, layout: Layout, header: Option<&Header>, problem: fmt::Arguments) -> ! {
+        let mut out = SerialStream;
+        let _ = writeln!(out, "heap corruption: {}", problem);
+        let _ = writeln!(out, "  block {:p}, {} bytes, align {}", ptr, layout.size(), layout.align());
+        if let Some(header) = header {
+            let _ = writeln!(out, "  allocated at:");
+            for (i, &address) in header.callers.iter().take_while(|&&address| address != 0).enumerate() {
+                let _ = debug::write_frame(&mut out, i, address);
+            }
+        }
+        panic!("heap corruption: {} ({} bytes at {:p})", problem, layout.size(), ptr);
+    }
+}
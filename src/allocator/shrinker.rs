@@ -0,0 +1,109 @@
+// 内存压力和缓存回收
+// 各种缓存实现 Shrinker 并注册进来。分配失败时先依次让缓存释放内存再重试，都释放不出来才返回空指针；
+// 堆的剩余空间低于水位线时记下低内存状态，由 GUI 在空闲时提示
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::allocator::heap_size;
+use crate::sync::IrqSafeMutex;
+
+pub trait Shrinker: Sync {
+    /// 名字，用于显示
+    fn name(&self) -> &'static str;
+
+    /// 大约能释放多少字节
+    fn count(&self) -> usize;
+
+    /// 尽量释放至少 `target` 字节，返回估计释放了多少字节
+    ///
+    /// 在分配失败时调用，可能处在任意上下文中（包括持有其他锁或者在中断里）：
+    /// 拿不到自己的锁时直接返回 0，不要等待，也不要分配内存
+    fn shrink(&self, target: usize) -> usize;
+}
+
+const MAX_SHRINKERS: usize = 8;
+// 剩余空间低于堆大小的 1/16 进入低内存状态，回到 1/8 以上才解除，避免在水位线附近反复切换
+const LOW_WATERMARK_DIVISOR: usize = 16;
+const HIGH_WATERMARK_DIVISOR: usize = 8;
+
+// 回收时不能分配内存，所以用定长数组
+static SHRINKERS: IrqSafeMutex<[Option<&'static dyn Shrinker>; MAX_SHRINKERS]> =
+    IrqSafeMutex::named("shrinkers", [None; MAX_SHRINKERS]);
+// 正在回收，防止 shrink 里的分配失败再次进入回收
+static RECLAIMING: AtomicBool = AtomicBool::new(false);
+static USED: AtomicUsize = AtomicUsize::new(0);
+static LOW_MEMORY: AtomicBool = AtomicBool::new(false);
+// 低内存状态变化后还没有被 take_change 取走
+static CHANGED: AtomicBool = AtomicBool::new(false);
+
+/// 注册缓存，满了返回 false
+pub fn register(shrinker: &'static dyn Shrinker) -> bool {
+    let mut shrinkers = SHRINKERS.lock();
+    match shrinkers.iter_mut().find(|slot| slot.is_none()) {
+        Some(slot) => {
+            *slot = Some(shrinker);
+            true
+        }
+        None => false,
+    }
+}
+
+/// 依次调用各个缓存的 shrink，直到释放了 `target` 字节，返回释放的总字节数
+///
+/// 调用时不能持有分配器的锁
+pub(super) fn reclaim(target: usize) -> usize {
+    if RECLAIMING.swap(true, Ordering::Acquire) {
+        return 0;
+    }
+    // 复制出来再调用，shrink 释放内存时不持有注册表的锁；注册表正被占用时放弃
+    let shrinkers = SHRINKERS.try_lock().map(|shrinkers| *shrinkers).unwrap_or([None; MAX_SHRINKERS]);
+    let mut freed = 0;
+    for shrinker in shrinkers.iter().flatten() {
+        if freed >= target {
+            break;
+        }
+        freed += shrinker.shrink(target - freed);
+    }
+    RECLAIMING.store(false, Ordering::Release);
+    freed
+}
+
+/// 所有缓存大约能释放的字节数
+pub fn reclaimable() -> usize {
+    let shrinkers = *SHRINKERS.lock();
+    shrinkers.iter().flatten().map(|shrinker| shrinker.count()).sum()
+}
+
+/// 已注册的缓存和各自大约能释放的字节数
+pub fn for_each(mut f: impl FnMut(&'static str, usize)) {
+    let shrinkers = *SHRINKERS.lock();
+    for shrinker in shrinkers.iter().flatten() {
+        f(shrinker.name(), shrinker.count());
+    }
+}
+
+// 分配器每次分配、释放后更新用量，越过水位线时切换低内存状态
+pub(super) fn allocated(size: usize) {
+    let used = USED.fetch_add(size, Ordering::Relaxed) + size;
+    let heap = heap_size();
+    if heap.saturating_sub(used) < heap / LOW_WATERMARK_DIVISOR && !LOW_MEMORY.swap(true, Ordering::Relaxed) {
+        CHANGED.store(true, Ordering::Release);
+    }
+}
+
+pub(super) fn freed(size: usize) {
+    let used = USED.fetch_sub(size, Ordering::Relaxed) - size;
+    let heap = heap_size();
+    if heap.saturating_sub(used) > heap / HIGH_WATERMARK_DIVISOR && LOW_MEMORY.swap(false, Ordering::Relaxed) {
+        CHANGED.store(true, Ordering::Release);
+    }
+}
+
+pub fn is_low_memory() -> bool {
+    LOW_MEMORY.load(Ordering::Relaxed)
+}
+
+/// 低内存状态自上次调用以来是否变化过
+pub fn take_change() -> bool {
+    CHANGED.swap(false, Ordering::Acquire)
+}
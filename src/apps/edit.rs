@@ -0,0 +1,377 @@
+// 文本编辑器
+// Buffer 按行保存文字和光标，负责插入、删除和移动光标；窗口里的 Editor 控件用 text::wrap 按窗口宽度折行，
+// 滚动到光标所在的行再画出来。方向键、Home 和 End 由键盘换成 io::keyboard 里的私用区字符，和普通字符一样送到控件。
+// 还没有 VFS，打开时先找保存过的文件，再按资源名找 assets 里的文本（只读）；Ctrl+S 保存到内存里，重启后丢失。
+// 有了文件系统以后只需要把 load 和 save 换成读写文件
+
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, TryReserveError};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use embedded_graphics::pixelcolor::Rgb888;
+use spin::Mutex;
+
+use crate::assets;
+use crate::graphic;
+use crate::graphic::font::{self, FontId};
+use crate::graphic::text;
+use crate::gui::clipboard;
+use crate::gui::widgets::{self, Bounds, Event, Widget};
+use crate::gui::window::{Window, BORDER, CLIENT_COLOR, TITLE_BAR_HEIGHT, WINDOW_MANAGER};
+use crate::io::keyboard::{self, DOWN, END, HOME, LEFT, RIGHT, UP};
+use crate::rgb888;
+
+const WIDTH: usize = 560;
+const HEIGHT: usize = 400;
+const MARGIN: usize = 4;
+const STATUS_HEIGHT: usize = 20;
+const TEXT_SIZE: f32 = 16.0;
+const LINE_HEIGHT: usize = 18;
+// Tab 换成这么多个空格
+const TAB_WIDTH: usize = 4;
+/// Ctrl+S
+pub const SAVE: char = '\x13';
+
+const PAPER_COLOR: Rgb888 = rgb888!(0xFFFFFFu32);
+const TEXT_COLOR: Rgb888 = rgb888!(0x212121u32);
+const CURSOR_COLOR: Rgb888 = rgb888!(0x0277BDu32);
+
+// 保存过的文件，名字到内容
+static FILES: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
+/// 读出文件，没有保存过时找同名的资源；都没有或者资源不是 UTF-8 文本时返回 None
+pub fn load(name: &str) -> Option<String> {
+    if let Some(text) = FILES.lock().get(name) {
+        return Some(text.clone());
+    }
+    let data = assets::data(name)?;
+    core::str::from_utf8(&data).ok().map(String::from)
+}
+
+pub fn save(name: &str, text: &str) {
+    FILES.lock().insert(String::from(name), String::from(text));
+}
+
+/// 保存过的文件的名字和字节数
+pub fn files() -> Vec<(String, usize)> {
+    FILES.lock().iter().map(|(name, text)| (name.clone(), text.len())).collect()
+}
+
+/// 编辑中的文字，至少有一行；光标的列按字符计
+pub struct Buffer {
+    lines: Vec<String>,
+    line: usize,
+    column: usize,
+    modified: bool,
+}
+
+impl Buffer {
+    /// "\r\n" 当作换行，Tab 换成空格，其他控制字符丢掉
+    pub fn new(text: &str) -> Self {
+        let lines = text.split('\n').map(|line| {
+            let mut clean = String::new();
+            for ch in line.chars() {
+                match ch {
+                    '\t' => clean.extend([' '; TAB_WIDTH]),
+                    ch if !ch.is_control() => clean.push(ch),
+                    _ => {}
+                }
+            }
+            clean
+        }).collect();
+        Self { lines, line: 0, column: 0, modified: false }
+    }
+
+    pub fn text(&self) -> String {
+        self.lines.join("\n")
+    }
+
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    /// 光标所在的行和列，都从 0 开始
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.line, self.column)
+    }
+
+    /// 上次保存之后改过
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    pub fn set_saved(&mut self) {
+        self.modified = false;
+    }
+
+    fn current(&self) -> &String {
+        &self.lines[self.line]
+    }
+
+    fn chars(&self) -> usize {
+        self.current().chars().count()
+    }
+
+    // 光标在这一行里的字节位置
+    fn offset(&self) -> usize {
+        let line = self.current();
+        line.char_indices().nth(self.column).map_or(line.len(), |(i, _)| i)
+    }
+
+    /// 在光标处插入一个字符，'\n' 把这一行从光标处断开
+    pub fn insert(&mut self, ch: char) {
+        match ch {
+            '\n' => {
+                let offset = self.offset();
+                let rest = self.lines[self.line].split_off(offset);
+                self.line += 1;
+                self.column = 0;
+                self.lines.insert(self.line, rest);
+            }
+            '\t' => {
+                for _ in 0..TAB_WIDTH {
+                    self.insert(' ');
+                }
+            }
+            ch if ch.is_control() || keyboard::is_special(ch) => return,
+            ch => {
+                let offset = self.offset();
+                self.lines[self.line].insert(offset, ch);
+                self.column += 1;
+            }
+        }
+        self.modified = true;
+    }
+
+    pub fn insert_str(&mut self, text: &str) {
+        for ch in text.chars().filter(|&ch| ch != '\r') {
+            self.insert(ch);
+        }
+    }
+
+    /// 删掉光标前的字符，在行首时和上一行合并；什么都没删时返回 false
+    pub fn backspace(&mut self) -> bool {
+        if self.column == 0 && self.line == 0 {
+            return false;
+        }
+        self.move_left();
+        self.delete()
+    }
+
+    /// 删掉光标处的字符，在行尾时和下一行合并；什么都没删时返回 false
+    pub fn delete(&mut self) -> bool {
+        if self.column < self.chars() {
+            let offset = self.offset();
+            self.lines[self.line].remove(offset);
+        } else if self.line + 1 < self.lines.len() {
+            let next = self.lines.remove(self.line + 1);
+            self.lines[self.line].push_str(&next);
+        } else {
+            return false;
+        }
+        self.modified = true;
+        true
+    }
+
+    /// 删掉光标所在的整行，返回它的内容
+    pub fn delete_line(&mut self) -> String {
+        self.modified = true;
+        self.column = 0;
+        if self.lines.len() == 1 {
+            return core::mem::take(&mut self.lines[0]);
+        }
+        let line = self.lines.remove(self.line);
+        self.line = self.line.min(self.lines.len() - 1);
+        line
+    }
+
+    pub fn move_left(&mut self) {
+        if self.column > 0 {
+            self.column -= 1;
+        } else if self.line > 0 {
+            self.line -= 1;
+            self.column = self.chars();
+        }
+    }
+
+    pub fn move_right(&mut self) {
+        if self.column < self.chars() {
+            self.column += 1;
+        } else if self.line + 1 < self.lines.len() {
+            self.line += 1;
+            self.column = 0;
+        }
+    }
+
+    pub fn move_up(&mut self) {
+        if self.line > 0 {
+            self.line -= 1;
+            self.column = self.column.min(self.chars());
+        }
+    }
+
+    pub fn move_down(&mut self) {
+        if self.line + 1 < self.lines.len() {
+            self.line += 1;
+            self.column = self.column.min(self.chars());
+        }
+    }
+
+    pub fn move_home(&mut self) {
+        self.column = 0;
+    }
+
+    pub fn move_end(&mut self) {
+        self.column = self.chars();
+    }
+}
+
+// 折行之后的一行：属于第几行，从这一行的哪个字节开始
+struct Row<'a> {
+    line: usize,
+    start: usize,
+    text: &'a str,
+}
+
+struct Editor {
+    bounds: Bounds,
+    name: String,
+    buffer: Buffer,
+    // 最上面显示的是折行之后的第几行
+    top: usize,
+    // 状态行上的提示，下一次按键时清除
+    message: Option<&'static str>,
+}
+
+impl Editor {
+    fn text_width(&self) -> usize {
+        self.bounds.width.saturating_sub(2 * MARGIN)
+    }
+
+    fn visible_rows(&self) -> usize {
+        (self.bounds.height.saturating_sub(STATUS_HEIGHT + MARGIN) / LINE_HEIGHT).max(1)
+    }
+
+    fn rows(&self) -> Vec<Row<'_>> {
+        let mut rows = Vec::new();
+        for (index, line) in self.buffer.lines().iter().enumerate() {
+            for text in text::wrap(line, FontId::MONOSPACE, TEXT_SIZE, self.text_width()) {
+                // wrap 返回的是原来那一行的切片
+                let start = text.as_ptr() as usize - line.as_ptr() as usize;
+                rows.push(Row { line: index, start, text });
+            }
+        }
+        rows
+    }
+
+    // 光标在第几个折行之后的行，以及在这一行里的横坐标
+    fn cursor_row(&self, rows: &[Row]) -> (usize, usize) {
+        let (line, _) = self.buffer.cursor();
+        let offset = self.buffer.offset();
+        let index = rows.iter().rposition(|row| row.line == line && row.start <= offset).unwrap_or(0);
+        let row = &rows[index];
+        let before = &self.buffer.lines()[line][row.start..offset];
+        (index, text::measure(before, FontId::MONOSPACE, TEXT_SIZE).min(self.text_width()))
+    }
+
+    // 滚动到能看见光标
+    fn scroll_to_cursor(&mut self) {
+        let (row, _) = self.cursor_row(&self.rows());
+        let visible = self.visible_rows();
+        if row < self.top {
+            self.top = row;
+        } else if row >= self.top + visible {
+            self.top = row + 1 - visible;
+        }
+    }
+
+    fn status(&self) -> String {
+        let (line, column) = self.buffer.cursor();
+        let modified = if self.buffer.is_modified() { " *" } else { "" };
+        let hint = self.message.unwrap_or("Ctrl+S save");
+        format!("{}{}  Ln {}, Col {}  {}", self.name, modified, line + 1, column + 1, hint)
+    }
+}
+
+impl Widget for Editor {
+    fn bounds(&self) -> Bounds {
+        self.bounds
+    }
+
+    fn draw(&self, window: &mut Window, focused: bool) {
+        let Bounds { x, y, width, height } = self.bounds;
+        window.fill_rect(x, y, width, STATUS_HEIGHT, CLIENT_COLOR);
+        window.draw_text(x + 1, y + MARGIN, width, &self.status(), font::gui_font(), TEXT_COLOR);
+        let top = x + STATUS_HEIGHT;
+        window.fill_rect(top, y, width, height.saturating_sub(STATUS_HEIGHT), PAPER_COLOR);
+        let rows = self.rows();
+        for (i, row) in rows.iter().skip(self.top).take(self.visible_rows()).enumerate() {
+            window.draw_text_sized(top + i * LINE_HEIGHT, y + MARGIN, self.text_width(), row.text, FontId::MONOSPACE,
+                                   TEXT_SIZE, TEXT_COLOR);
+        }
+        if focused {
+            let (row, column) = self.cursor_row(&rows);
+            if let Some(i) = row.checked_sub(self.top).filter(|&i| i < self.visible_rows()) {
+                window.fill_rect(top + i * LINE_HEIGHT + 1, y + MARGIN + column, 2, LINE_HEIGHT - 2, CURSOR_COLOR);
+            }
+        }
+    }
+
+    fn handle_event(&mut self, event: &Event) -> bool {
+        let Event::Key(ch) = *event else { return false };
+        self.message = None;
+        let buffer = &mut self.buffer;
+        match ch {
+            UP => buffer.move_up(),
+            DOWN => buffer.move_down(),
+            LEFT => buffer.move_left(),
+            RIGHT => buffer.move_right(),
+            HOME => buffer.move_home(),
+            END => buffer.move_end(),
+            '\x08' => {
+                buffer.backspace();
+            }
+            '\x7f' => {
+                buffer.delete();
+            }
+            '\r' => buffer.insert('\n'),
+            SAVE => {
+                save(&self.name, &buffer.text());
+                buffer.set_saved();
+                self.message = Some("Saved");
+            }
+            // 和 nano 一样，复制和剪切都按整行
+            clipboard::COPY => {
+                let (line, _) = buffer.cursor();
+                clipboard::set_text(&buffer.lines()[line]);
+                self.message = Some("Line copied");
+            }
+            clipboard::CUT => clipboard::set_text(&buffer.delete_line()),
+            clipboard::PASTE => buffer.insert_str(&clipboard::get_text()),
+            ch => buffer.insert(ch),
+        }
+        self.scroll_to_cursor();
+        true
+    }
+
+    fn focusable(&self) -> bool {
+        true
+    }
+}
+
+/// 在窗口里打开 `name`，文件不存在时从空白开始，保存后才会创建
+pub fn open(name: &str) -> Result<(), TryReserveError> {
+    let text = load(name);
+    let x = graphic::height().saturating_sub(HEIGHT) / 2;
+    let y = graphic::width().saturating_sub(WIDTH) / 2;
+    let window = WINDOW_MANAGER.lock().create(&format!("Edit - {}", name), x, y, WIDTH, HEIGHT)?;
+    let bounds = Bounds::new(0, 0, WIDTH - 2 * BORDER, HEIGHT - TITLE_BAR_HEIGHT - 2 * BORDER);
+    let message = if text.is_none() { Some("New file") } else { None };
+    let buffer = Buffer::new(&text.unwrap_or_default());
+    widgets::add(window, Box::new(Editor { bounds, name: String::from(name), buffer, top: 0, message }));
+    widgets::focus(window, 0);
+    Ok(())
+}
+
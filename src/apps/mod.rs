@@ -0,0 +1,11 @@
+// 应用程序
+// 用窗口、控件和精灵搭起来的小程序：贪吃蛇和文本编辑器。和 gui 里的工具窗口相比它们有自己的状态，
+// 需要不停推进的程序由 shell 空闲时调用 poll，自己按 io::timer 的时间决定这一次要不要动
+
+pub mod edit;
+pub mod snake;
+
+/// 推进打开着的程序，由 shell 在空闲时调用
+pub fn poll() {
+    snake::poll();
+}
@@ -0,0 +1,426 @@
+// 贪吃蛇
+// Game 只管规则：蛇在 rows 行 cols 列的格子里走，吃到食物变长，撞到墙或者自己就结束，不碰屏幕，测试可以直接驱动它。
+// 窗口里只有一个能获得焦点的 Board 控件，方向键、WASD 和空格通过控件的键盘事件送来。蛇按 io::timer 的时间前进，越长走得越快，
+// 每走一步用精灵把整个棋盘画到窗口上再请求重画，窗口的图层按帧率合成，用来观察连续重画时的输入延迟和帧率
+
+use alloc::boxed::Box;
+use alloc::collections::{TryReserveError, VecDeque};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::time::Duration;
+
+use embedded_graphics::pixelcolor::Rgb888;
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+use crate::graphic;
+use crate::graphic::canvas::Canvas;
+use crate::graphic::font;
+use crate::graphic::image::Image;
+use crate::graphic::sprite::Sprite;
+use crate::gui::widgets::{self, Bounds, Event, Widget};
+use crate::gui::window::{Window, WindowId, BORDER, CLIENT_COLOR, TITLE_BAR_HEIGHT, WINDOW_MANAGER};
+use crate::io::keyboard::{DOWN, LEFT, RIGHT, UP};
+use crate::io::timer::uptime;
+use crate::rand;
+use crate::rgb888;
+
+/// 棋盘的大小
+pub const ROWS: usize = 20;
+pub const COLS: usize = 24;
+// 一格的像素数
+const CELL: usize = 16;
+const MARGIN: usize = 8;
+const STATUS_HEIGHT: usize = 22;
+// 开始时每步的间隔，每吃一个食物缩短一点，最短到 FASTEST
+const SLOWEST: Duration = Duration::from_millis(150);
+const SPEEDUP: Duration = Duration::from_millis(5);
+const FASTEST: Duration = Duration::from_millis(60);
+// 最多记住两次还没走的转向，连按两个方向键掉头时不会丢掉一次
+const MAX_TURNS: usize = 2;
+
+const BOARD_COLOR: Rgb888 = rgb888!(0x263238u32);
+const GRID_COLOR: Rgb888 = rgb888!(0x2E3C43u32);
+const BODY_COLOR: Rgb888 = rgb888!(0x66BB6Au32);
+const HEAD_COLOR: Rgb888 = rgb888!(0x2E7D32u32);
+const EYE_COLOR: Rgb888 = rgb888!(0xFFFFFFu32);
+const FOOD_COLOR: Rgb888 = rgb888!(0xEF5350u32);
+const TEXT_COLOR: Rgb888 = rgb888!(0x212121u32);
+
+/// 格子的位置（行，列）
+pub type Cell = (usize, usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    fn opposite(self) -> Self {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        }
+    }
+
+    // 沿这个方向走一格，走出棋盘时返回 None
+    fn advance(self, (row, col): Cell, rows: usize, cols: usize) -> Option<Cell> {
+        match self {
+            Direction::Up => Some((row.checked_sub(1)?, col)),
+            Direction::Down => (row + 1 < rows).then_some((row + 1, col)),
+            Direction::Left => Some((row, col.checked_sub(1)?)),
+            Direction::Right => (col + 1 < cols).then_some((row, col + 1)),
+        }
+    }
+}
+
+/// 走一步的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Moved,
+    Ate,
+    /// 撞到墙或者自己，游戏结束
+    Crashed,
+    /// 蛇占满了棋盘，游戏结束
+    Won,
+}
+
+pub struct Game {
+    rows: usize,
+    cols: usize,
+    // 第一个是头
+    body: VecDeque<Cell>,
+    // 每一格有没有被蛇占着，按行存放
+    occupied: Vec<bool>,
+    direction: Direction,
+    turns: VecDeque<Direction>,
+    food: Option<Cell>,
+    score: usize,
+    over: bool,
+}
+
+impl Game {
+    /// 长度为 3 的蛇在中间一行向右走，食物随机放；棋盘至少要有 3 列
+    pub fn new(rows: usize, cols: usize) -> Self {
+        assert!(rows > 0 && cols >= 3);
+        let mut game = Self {
+            rows,
+            cols,
+            body: VecDeque::new(),
+            occupied: vec![false; rows * cols],
+            direction: Direction::Right,
+            turns: VecDeque::new(),
+            food: None,
+            score: 0,
+            over: false,
+        };
+        let (row, col) = (rows / 2, cols / 2);
+        for offset in 0..3 {
+            let cell = (row, col.max(2) - offset);
+            game.body.push_back(cell);
+            game.occupied[cell.0 * cols + cell.1] = true;
+        }
+        game.spawn_food();
+        game
+    }
+
+    pub fn head(&self) -> Cell {
+        self.body[0]
+    }
+
+    /// 从头到尾的每一格
+    pub fn body(&self) -> impl Iterator<Item = Cell> + '_ {
+        self.body.iter().copied()
+    }
+
+    pub fn length(&self) -> usize {
+        self.body.len()
+    }
+
+    pub fn food(&self) -> Option<Cell> {
+        self.food
+    }
+
+    /// 吃到的食物数
+    pub fn score(&self) -> usize {
+        self.score
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.over
+    }
+
+    /// 把食物放到 `cell`，格子在棋盘外或者被蛇占着时返回 false
+    pub fn place_food(&mut self, cell: Cell) -> bool {
+        if cell.0 >= self.rows || cell.1 >= self.cols || self.occupied[cell.0 * self.cols + cell.1] {
+            return false;
+        }
+        self.food = Some(cell);
+        true
+    }
+
+    // 在空着的格子里随机放一个食物，没有空格时不放
+    fn spawn_food(&mut self) {
+        let free = self.occupied.len() - self.body.len();
+        self.food = (free > 0).then(|| {
+            let nth = rand::below(free as u64) as usize;
+            let index = self.occupied.iter().enumerate().filter(|&(_, &used)| !used).nth(nth).unwrap().0;
+            (index / self.cols, index % self.cols)
+        });
+    }
+
+    /// 转向，下一步生效；和前一次的方向相同或者相反时忽略
+    pub fn steer(&mut self, direction: Direction) {
+        let last = self.turns.back().copied().unwrap_or(self.direction);
+        if self.over || self.turns.len() >= MAX_TURNS || direction == last || direction == last.opposite() {
+            return;
+        }
+        self.turns.push_back(direction);
+    }
+
+    /// 走一步，游戏结束后一直返回 Crashed 或者 Won
+    pub fn step(&mut self) -> Step {
+        if self.over {
+            return if self.food.is_none() { Step::Won } else { Step::Crashed };
+        }
+        if let Some(direction) = self.turns.pop_front() {
+            self.direction = direction;
+        }
+        let Some(head) = self.direction.advance(self.head(), self.rows, self.cols) else {
+            self.over = true;
+            return Step::Crashed;
+        };
+        let ate = self.food == Some(head);
+        // 不吃东西时尾巴先让开，头可以跟着走进尾巴刚离开的格子
+        let tail = if ate { None } else { self.body.pop_back() };
+        if let Some(tail) = tail {
+            self.occupied[tail.0 * self.cols + tail.1] = false;
+        }
+        if self.occupied[head.0 * self.cols + head.1] {
+            if let Some(tail) = tail {
+                self.body.push_back(tail);
+                self.occupied[tail.0 * self.cols + tail.1] = true;
+            }
+            self.over = true;
+            return Step::Crashed;
+        }
+        self.body.push_front(head);
+        self.occupied[head.0 * self.cols + head.1] = true;
+        if !ate {
+            return Step::Moved;
+        }
+        self.score += 1;
+        self.spawn_food();
+        if self.food.is_none() {
+            self.over = true;
+            return Step::Won;
+        }
+        Step::Ate
+    }
+}
+
+struct Sprites {
+    head: Sprite,
+    body: Sprite,
+    food: Sprite,
+}
+
+lazy_static! {
+    // 启动时没有贪吃蛇的图片，第一次打开时用画布画出来，之后每一步都直接贴
+    static ref SPRITES: Sprites = {
+        let size = CELL as isize;
+        let mut body = Image::new(CELL, CELL);
+        body.fill_rounded_rect(1, 1, CELL - 2, CELL - 2, 4, BODY_COLOR);
+        let mut head = Image::new(CELL, CELL);
+        head.fill_rounded_rect(1, 1, CELL - 2, CELL - 2, 4, HEAD_COLOR);
+        head.fill_circle(size / 2, size / 3, 2, EYE_COLOR);
+        head.fill_circle(size / 2, size - 1 - size / 3, 2, EYE_COLOR);
+        let mut food = Image::new(CELL, CELL);
+        food.fill_circle(size / 2, size / 2, CELL / 2 - 2, FOOD_COLOR);
+        Sprites { head: Sprite::new(head), body: Sprite::new(body), food: Sprite::new(food) }
+    };
+}
+
+struct Session {
+    window: WindowId,
+    game: Game,
+    paused: bool,
+    // 下一步的时间
+    next: Duration,
+}
+
+impl Session {
+    fn interval(&self) -> Duration {
+        SLOWEST.saturating_sub(SPEEDUP * self.game.score() as u32).max(FASTEST)
+    }
+
+    // 到时间就走一步，返回是否需要重画
+    fn advance(&mut self, now: Duration) -> bool {
+        if self.paused || self.game.is_over() || now < self.next {
+            return false;
+        }
+        if let Step::Crashed | Step::Won = self.game.step() {
+            BEST.fetch_max(self.game.score(), Ordering::Relaxed);
+        }
+        self.next = now + self.interval();
+        true
+    }
+
+    fn restart(&mut self) {
+        self.game = Game::new(ROWS, COLS);
+        self.paused = false;
+        self.next = uptime() + self.interval();
+    }
+
+    fn status(&self, focused: bool) -> String {
+        let state = if self.game.is_over() {
+            if self.game.food().is_none() { "You win! Space to play again" } else { "Game over, space to restart" }
+        } else if !focused {
+            "Click to play"
+        } else if self.paused {
+            "Paused, space to resume"
+        } else {
+            "Arrows/WASD to steer, space to pause"
+        };
+        format!("Score {}  Best {}  {}", self.game.score(), BEST.load(Ordering::Relaxed), state)
+    }
+}
+
+static SESSION: Mutex<Option<Session>> = Mutex::new(None);
+// 这次开机以来的最高分
+static BEST: AtomicUsize = AtomicUsize::new(0);
+
+// 画状态栏和棋盘，键盘事件交给 Session
+struct Board {
+    bounds: Bounds,
+}
+
+impl Widget for Board {
+    fn bounds(&self) -> Bounds {
+        self.bounds
+    }
+
+    fn draw(&self, window: &mut Window, focused: bool) {
+        let session = SESSION.lock();
+        let Some(session) = session.as_ref() else { return };
+        let Bounds { x, y, width, .. } = self.bounds;
+        window.fill_rect(x, y, width, STATUS_HEIGHT, CLIENT_COLOR);
+        window.draw_text(x + 2, y, width, &session.status(focused), font::gui_font(), TEXT_COLOR);
+        let top = x + STATUS_HEIGHT;
+        window.fill_rect(top, y, COLS * CELL, ROWS * CELL, BOARD_COLOR);
+        for row in 1..ROWS {
+            window.fill_rect(top + row * CELL, y, COLS * CELL, 1, GRID_COLOR);
+        }
+        for col in 1..COLS {
+            window.fill_rect(top, y + col * CELL, 1, ROWS * CELL, GRID_COLOR);
+        }
+        let at = |(row, col): Cell| (top + row * CELL, y + col * CELL);
+        if let Some(food) = session.game.food() {
+            let (fx, fy) = at(food);
+            SPRITES.food.draw(window, fx, fy);
+        }
+        for (i, cell) in session.game.body().enumerate() {
+            let (cx, cy) = at(cell);
+            let sprite = if i == 0 { &SPRITES.head } else { &SPRITES.body };
+            sprite.draw(window, cx, cy);
+        }
+    }
+
+    fn handle_event(&mut self, event: &Event) -> bool {
+        let Event::Key(ch) = *event else { return false };
+        let mut session = SESSION.lock();
+        let Some(session) = session.as_mut() else { return false };
+        let direction = match ch.to_ascii_lowercase() {
+            UP | 'w' => Direction::Up,
+            DOWN | 's' => Direction::Down,
+            LEFT | 'a' => Direction::Left,
+            RIGHT | 'd' => Direction::Right,
+            ' ' if session.game.is_over() => {
+                session.restart();
+                return true;
+            }
+            ' ' => {
+                session.paused = !session.paused;
+                session.next = uptime() + session.interval();
+                return true;
+            }
+            _ => return false,
+        };
+        session.game.steer(direction);
+        // 暂停时按方向键接着玩
+        if !session.paused {
+            return false;
+        }
+        session.paused = false;
+        session.next = uptime() + session.interval();
+        true
+    }
+
+    fn focusable(&self) -> bool {
+        true
+    }
+}
+
+/// 打开贪吃蛇，已经打开时提到最上层
+pub fn show() -> Result<(), TryReserveError> {
+    if let Some(window) = SESSION.lock().as_ref().map(|session| session.window) {
+        let mut manager = WINDOW_MANAGER.lock();
+        if manager.window(window).is_some() {
+            manager.raise(window);
+            return Ok(());
+        }
+    }
+    let width = COLS * CELL + 2 * MARGIN + 2 * BORDER;
+    let height = TITLE_BAR_HEIGHT + 2 * BORDER + 2 * MARGIN + STATUS_HEIGHT + ROWS * CELL;
+    let x = graphic::height().saturating_sub(height) / 2;
+    let y = graphic::width().saturating_sub(width) / 2;
+    let window = WINDOW_MANAGER.lock().create("Snake", x, y, width, height)?;
+    let mut session = Session { window, game: Game::new(ROWS, COLS), paused: false, next: Duration::ZERO };
+    session.next = uptime() + session.interval();
+    *SESSION.lock() = Some(session);
+    let bounds = Bounds::new(MARGIN, MARGIN, COLS * CELL, STATUS_HEIGHT + ROWS * CELL);
+    widgets::add(window, Box::new(Board { bounds }));
+    widgets::focus(window, 0);
+    Ok(())
+}
+
+/// 到时间时走一步并重画，窗口已经关掉时清理；窗口不在最上层时自动暂停
+///
+/// 由 apps::poll 调用
+pub fn poll() {
+    let Some(window) = SESSION.lock().as_ref().map(|session| session.window) else { return };
+    let focused = {
+        let manager = WINDOW_MANAGER.lock();
+        if manager.window(window).is_none() {
+            drop(manager);
+            widgets::remove_all(window);
+            *SESSION.lock() = None;
+            return;
+        }
+        manager.focused() == Some(window)
+    };
+    let dirty = {
+        let mut session = SESSION.lock();
+        let Some(session) = session.as_mut() else { return };
+        let pause = !focused && !session.paused && !session.game.is_over();
+        if pause {
+            session.paused = true;
+        }
+        pause | session.advance(uptime())
+    };
+    if dirty {
+        widgets::refresh(window);
+    }
+}
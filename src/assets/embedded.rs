@@ -0,0 +1,38 @@
+// 编进内核的资源
+// 名字按用途分目录：fonts/、icons/、sounds/、wallpapers/
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::assets::{Data, Source};
+
+/// 内置的等宽字体，控制台总是用它
+pub const MONOSPACE_FONT: &str = "fonts/vonwaon-16px.ttf";
+
+static ASSETS: &[(&str, &[u8])] = &[
+    (MONOSPACE_FONT, include_bytes!("../../assets/VonwaonBitmap-16px.ttf")),
+    ("icons/cursor.bmp", include_bytes!("../../assets/cursor.bmp")),
+    ("icons/window-close.qoi", include_bytes!("../../assets/window_close_btn.qoi")),
+    ("wallpapers/default.bmp", include_bytes!("../../assets/OS_background.bmp")),
+];
+
+/// 编进内核的资源，不经过缓存，也不会被别的来源覆盖
+pub fn get(name: &str) -> Option<&'static [u8]> {
+    ASSETS.iter().find(|(n, _)| *n == name).map(|(_, data)| *data)
+}
+
+pub struct Embedded;
+
+impl Source for Embedded {
+    fn name(&self) -> &'static str {
+        "embedded"
+    }
+
+    fn load(&self, name: &str) -> Option<Data> {
+        get(name).map(Data::Static)
+    }
+
+    fn names(&self) -> Vec<String> {
+        ASSETS.iter().map(|(name, _)| String::from(*name)).collect()
+    }
+}
@@ -0,0 +1,343 @@
+// 资源管理
+// 字体、图标、声音和壁纸按名字取用，名字按用途分目录，比如 icons/cursor.bmp。
+// 资源按优先级依次从几个来源查找：编进内核的数据、initrd、磁盘。现在只有编进内核的一个来源，
+// 有了 initrd 和文件系统之后实现 Source 并用 register_source 登记，取资源的代码不需要修改。
+// 解码好的图片和从其他来源读出的数据放在缓存里，交出去的是引用计数的句柄；缓存注册为 Shrinker，
+// 内存紧张时淘汰最久没用、并且只剩缓存自己持有的资源；缓存超过 CACHE_LIMIT 时也按同样的规则淘汰。
+// 要反复画的图片用 sprite 取，所有精灵共用缓存里解码好的那一份像素，不会每次重新解码。
+// 字体登记进 graphic::font 的注册表之后不能撤销，只按名字记下 FontId，不参与回收。
+// 任何来源里 theme/ 下的同名资源优先于原来的资源，比如 theme/icons/cursor.bmp 换掉光标；
+// 换了主题的资源后调用 reload 丢掉缓存，gui::reload_theme 会重新取用并重画
+
+pub mod embedded;
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem::size_of;
+use core::ops::Deref;
+
+use embedded_graphics::pixelcolor::Rgb888;
+use lazy_static::lazy_static;
+use spin::{Mutex, RwLock};
+
+use crate::allocator::shrinker::{self, Shrinker};
+use crate::graphic::font::{self, FontId};
+use crate::graphic::image::Image;
+use crate::graphic::sprite::Sprite;
+
+/// 来源的优先级，数字小的先查
+pub const PRIORITY_EMBEDDED: u8 = 0;
+pub const PRIORITY_INITRD: u8 = 1;
+pub const PRIORITY_DISK: u8 = 2;
+
+/// 主题资源的目录，见 load
+pub const THEME_DIR: &str = "theme/";
+
+/// 缓存的大小上限，超过时淘汰不再使用的资源；还在使用的资源不受限制
+pub const CACHE_LIMIT: usize = 16 << 20;
+
+/// 资源的原始数据：编进内核的直接引用，其他来源读出来的共享一份
+#[derive(Debug, Clone)]
+pub enum Data {
+    Static(&'static [u8]),
+    Shared(Arc<[u8]>),
+}
+
+impl Deref for Data {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Data::Static(data) => data,
+            Data::Shared(data) => data,
+        }
+    }
+}
+
+/// 资源的来源
+pub trait Source: Sync {
+    /// 名字，用于显示
+    fn name(&self) -> &'static str;
+
+    /// 名为 `name` 的资源的原始数据，没有时返回 None
+    fn load(&self, name: &str) -> Option<Data>;
+
+    /// 所有资源的名字
+    fn names(&self) -> Vec<String>;
+}
+
+/// 缓存里的资源的句柄，复制只增加引用计数
+#[derive(Debug)]
+pub struct Handle<T: ?Sized>(Arc<T>);
+
+impl<T: ?Sized> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Handle(self.0.clone())
+    }
+}
+
+impl<T: ?Sized> Deref for Handle<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Form {
+    Data,
+    Image,
+}
+
+enum Cached {
+    Data(Data),
+    Image(Handle<Image>),
+}
+
+impl Cached {
+    // 大约占用的字节数
+    fn footprint(&self) -> usize {
+        match self {
+            Cached::Data(data) => data.len(),
+            Cached::Image(image) => image.width() * image.height() * size_of::<Option<Rgb888>>(),
+        }
+    }
+
+    // 只有缓存自己还持有，淘汰掉才真正释放内存
+    fn is_unused(&self) -> bool {
+        match self {
+            Cached::Data(Data::Shared(data)) => Arc::strong_count(data) == 1,
+            Cached::Data(Data::Static(_)) => false,
+            Cached::Image(image) => Arc::strong_count(&image.0) == 1 && !image.is_shared(),
+        }
+    }
+}
+
+struct Cache {
+    // 值是资源和最近一次使用的时间
+    entries: BTreeMap<(Form, String), (Cached, u64)>,
+    clock: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl Cache {
+    fn get(&mut self, form: Form, name: &str) -> Option<&Cached> {
+        self.clock += 1;
+        let clock = self.clock;
+        match self.entries.get_mut(&(form, String::from(name))) {
+            Some((cached, used)) => {
+                self.hits += 1;
+                *used = clock;
+                Some(cached)
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn insert(&mut self, form: Form, name: &str, cached: Cached) {
+        self.clock += 1;
+        let clock = self.clock;
+        self.entries.insert((form, String::from(name)), (cached, clock));
+        while self.bytes() > CACHE_LIMIT && self.evict().is_some() {}
+    }
+
+    fn bytes(&self) -> usize {
+        self.entries.values().map(|(cached, _)| cached.footprint()).sum()
+    }
+
+    // 淘汰最久没用的一项，返回大约释放的字节数，没有可以淘汰的时返回 None；不分配内存
+    fn evict(&mut self) -> Option<usize> {
+        let oldest = self.entries.values().filter(|(cached, _)| cached.is_unused()).map(|(_, used)| *used).min()?;
+        let mut freed = 0;
+        self.entries.retain(|_, (cached, used)| {
+            if *used == oldest {
+                freed = cached.footprint();
+            }
+            *used != oldest
+        });
+        Some(freed)
+    }
+}
+
+lazy_static! {
+    static ref SOURCES: RwLock<Vec<(u8, &'static dyn Source)>> =
+        RwLock::new(vec![(PRIORITY_EMBEDDED, &embedded::Embedded as &'static dyn Source)]);
+    static ref CACHE: Mutex<Cache> = {
+        shrinker::register(&ASSET_CACHE_SHRINKER);
+        Mutex::new(Cache { entries: BTreeMap::new(), clock: 0, hits: 0, misses: 0 })
+    };
+}
+
+static FONTS: Mutex<BTreeMap<String, FontId>> = Mutex::new(BTreeMap::new());
+
+/// 登记一个来源，同样优先级的排在已有的后面；已经缓存的资源会重新查找
+pub fn register_source(priority: u8, source: &'static dyn Source) {
+    let mut sources = SOURCES.write();
+    let index = sources.iter().position(|(p, _)| *p > priority).unwrap_or(sources.len());
+    sources.insert(index, (priority, source));
+    drop(sources);
+    reload();
+}
+
+// 按优先级找到第一个有这个资源的来源，theme/ 下有同名资源时用它
+fn load(name: &str) -> Option<Data> {
+    let sources = SOURCES.read();
+    let find = |name: &str| sources.iter().find_map(|(_, source)| source.load(name));
+    if !name.starts_with(THEME_DIR) {
+        if let Some(data) = find(&format!("{}{}", THEME_DIR, name)) {
+            return Some(data);
+        }
+    }
+    find(name)
+}
+
+/// 丢掉缓存，之后取资源时重新从来源读取；已经交出去的句柄仍然有效
+pub fn reload() {
+    CACHE.lock().entries.clear();
+}
+
+/// 资源的原始数据，比如声音
+pub fn data(name: &str) -> Option<Data> {
+    if let Some(Cached::Data(data)) = CACHE.lock().get(Form::Data, name) {
+        return Some(data.clone());
+    }
+    let data = load(name)?;
+    // 编进内核的数据不用缓存
+    if let Data::Shared(_) = data {
+        CACHE.lock().insert(Form::Data, name, Cached::Data(data.clone()));
+    }
+    Some(data)
+}
+
+/// 解码好的图片，支持 BMP 和 QOI；没有这个资源或者解码失败时返回 None
+pub fn image(name: &str) -> Option<Handle<Image>> {
+    if let Some(Cached::Image(image)) = CACHE.lock().get(Form::Image, name) {
+        return Some(image.clone());
+    }
+    // 直接解码，原始数据不放进缓存
+    let data = load(name)?;
+    let Some(image) = decode_image(&data) else {
+        log::warn!("assets: cannot decode {}", name);
+        return None;
+    };
+    let handle = Handle(Arc::new(image));
+    CACHE.lock().insert(Form::Image, name, Cached::Image(handle.clone()));
+    Some(handle)
+}
+
+/// 共用缓存里那份图片的精灵，画多少次都只解码一次
+pub fn sprite(name: &str) -> Option<Sprite> {
+    image(name).map(|image| Sprite::new((*image).clone()))
+}
+
+// 按文件头判断格式；BMP 每像素 32 位时带透明度
+fn decode_image(data: &[u8]) -> Option<Image> {
+    const BMP_BITS_PER_PIXEL: usize = 28;
+
+    match data {
+        [b'q', b'o', b'i', b'f', ..] => Image::from_qoi(data),
+        [b'B', b'M', ..] if data.get(BMP_BITS_PER_PIXEL) == Some(&32) => Image::from_bmp_32rgba(data),
+        [b'B', b'M', ..] => Image::from_bmp(data),
+        _ => None,
+    }
+}
+
+/// 把 TTF 字体登记进字体注册表，同一个名字只登记一次
+pub fn font(name: &str) -> Option<FontId> {
+    // 内置的等宽字体在字体注册表建立时就登记了
+    if name == embedded::MONOSPACE_FONT {
+        return Some(FontId::MONOSPACE);
+    }
+    if let Some(&id) = FONTS.lock().get(name) {
+        return Some(id);
+    }
+    let id = match load(name)? {
+        Data::Static(data) => font::register(data),
+        Data::Shared(data) => font::register_owned(data.to_vec()),
+    };
+    let Some(id) = id else {
+        log::warn!("assets: cannot parse font {}", name);
+        return None;
+    };
+    FONTS.lock().insert(String::from(name), id);
+    Some(id)
+}
+
+/// 一个资源，给 assets 命令显示
+pub struct Info {
+    pub name: String,
+    /// 提供它的来源
+    pub source: &'static str,
+    /// 缓存占用的字节数，没有缓存时为 0
+    pub cached: usize,
+}
+
+/// 所有来源里的资源，被优先级高的来源挡住的不列出来
+pub fn list() -> Vec<Info> {
+    let mut assets: Vec<Info> = Vec::new();
+    for (_, source) in SOURCES.read().iter() {
+        for name in source.names() {
+            if !assets.iter().any(|info| info.name == name) {
+                assets.push(Info { name, source: source.name(), cached: 0 });
+            }
+        }
+    }
+    let cache = CACHE.lock();
+    for ((_, name), (cached, _)) in cache.entries.iter() {
+        if let Some(info) = assets.iter_mut().find(|info| info.name == *name) {
+            info.cached += cached.footprint();
+        }
+    }
+    assets
+}
+
+/// 缓存的统计
+#[derive(Debug, Clone, Copy)]
+pub struct Stats {
+    pub entries: usize,
+    /// 缓存大约占用的字节数
+    pub bytes: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+pub fn stats() -> Stats {
+    let cache = CACHE.lock();
+    Stats { entries: cache.entries.len(), bytes: cache.bytes(), hits: cache.hits, misses: cache.misses }
+}
+
+struct AssetCacheShrinker;
+
+static ASSET_CACHE_SHRINKER: AssetCacheShrinker = AssetCacheShrinker;
+
+impl Shrinker for AssetCacheShrinker {
+    fn name(&self) -> &'static str {
+        "asset cache"
+    }
+
+    fn count(&self) -> usize {
+        let cache = CACHE.lock();
+        cache.entries.values().filter(|(cached, _)| cached.is_unused()).map(|(cached, _)| cached.footprint()).sum()
+    }
+
+    fn shrink(&self, target: usize) -> usize {
+        let Some(mut cache) = CACHE.try_lock() else { return 0 };
+        let mut freed = 0;
+        while freed < target {
+            match cache.evict() {
+                Some(bytes) => freed += bytes,
+                None => break,
+            }
+        }
+        freed
+    }
+}
@@ -0,0 +1,192 @@
+// 微基准测试
+// 打开 bench 特性时编译，在 QEMU 里反复运行分配器和图形的几条热路径，用 TSC 计时：
+//   alloc    连续分配 ALLOCATIONS 个小块，再全部释放
+//   fill     在离屏的全屏图层上填满整个屏幕
+//   text     在离屏的全屏图层上画 GLYPHS 个等宽字符
+//   compose  把所有图层合成一遍写进显存，只在图形模式下运行
+// 每项先不计时地跑一轮热身（字形缓存、空闲链表都准备好），再跑 ROUNDS 轮，取最短、中位数和最长的一轮。
+// 结果每项一行写到串口，以 BENCH 开头，后面是空格分开的 key=value，时间是一整轮的纳秒数：
+//   BENCH name=alloc iters=1000 rounds=15 min_ns=... median_ns=... max_ns=... tsc_mhz=...
+//   BENCH name=compose skipped=text-mode
+// 脚本按 BENCH 开头挑出这些行。shell 的 bench [名字] 命令运行全部或其中一项，cargo test --features bench 运行 tests/bench.rs
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::arch::x86_64::_rdtsc;
+use core::fmt;
+use core::hint::black_box;
+
+use embedded_graphics::pixelcolor::Rgb888;
+
+use crate::graphic::font::FontId;
+use crate::graphic::text::{self, Align};
+use crate::graphic::{self, Writer, GD};
+use crate::i18n::{self, Msg};
+use crate::io::VIDEO_MODE;
+use crate::perf;
+use crate::shell::{self, Command};
+use crate::shell_println;
+
+/// 计时的轮数
+pub const ROUNDS: usize = 15;
+// alloc 每轮分配的块数
+const ALLOCATIONS: usize = 1000;
+// text 每轮画的字符数
+const GLYPHS: usize = 1000;
+const FONT_SIZE: f32 = 16.0;
+const LINE_HEIGHT: usize = 18;
+
+/// 一项基准测试
+pub struct Benchmark {
+    pub name: &'static str,
+    /// 每轮里重复的操作次数，报告的时间是一整轮的
+    pub iterations: usize,
+    // 不能运行时返回原因
+    skip: fn() -> Option<&'static str>,
+    run: fn(&mut Bencher),
+}
+
+pub static BENCHMARKS: [Benchmark; 4] = [
+    Benchmark { name: "alloc", iterations: ALLOCATIONS, skip: never, run: alloc },
+    Benchmark { name: "fill", iterations: 1, skip: never, run: fill },
+    Benchmark { name: "text", iterations: GLYPHS, skip: never, run: text },
+    Benchmark { name: "compose", iterations: 1, skip: text_mode, run: compose },
+];
+
+/// 交给每项测试的计时器，准备工作放在 iter 外面就不计入时间
+pub struct Bencher {
+    cycles: Vec<u64>,
+}
+
+impl Bencher {
+    /// 热身一轮，再把 `f` 计时运行 ROUNDS 轮
+    pub fn iter(&mut self, mut f: impl FnMut()) {
+        f();
+        self.cycles.clear();
+        for _ in 0..ROUNDS {
+            let start = unsafe { _rdtsc() };
+            f();
+            self.cycles.push(unsafe { _rdtsc() }.wrapping_sub(start));
+        }
+    }
+}
+
+/// 一项测试的结果，Display 输出成 BENCH 行
+pub enum Outcome {
+    Done { name: &'static str, iterations: usize, min: u64, median: u64, max: u64 },
+    Skipped { name: &'static str, reason: &'static str },
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Outcome::Done { name, iterations, min, median, max } => {
+                let ns = |cycles| perf::cycles_to_duration(cycles).as_nanos();
+                write!(f, "BENCH name={} iters={} rounds={} min_ns={} median_ns={} max_ns={} tsc_mhz={}", name,
+                       iterations, ROUNDS, ns(min), ns(median), ns(max), perf::tsc_hz() / 1_000_000)
+            }
+            Outcome::Skipped { name, reason } => write!(f, "BENCH name={} skipped={}", name, reason),
+        }
+    }
+}
+
+impl Benchmark {
+    pub fn run(&self) -> Outcome {
+        if let Some(reason) = (self.skip)() {
+            return Outcome::Skipped { name: self.name, reason };
+        }
+        let mut bencher = Bencher { cycles: Vec::with_capacity(ROUNDS) };
+        (self.run)(&mut bencher);
+        let cycles = &mut bencher.cycles;
+        assert!(!cycles.is_empty(), "benchmark {} did not call Bencher::iter", self.name);
+        cycles.sort_unstable();
+        Outcome::Done {
+            name: self.name,
+            iterations: self.iterations,
+            min: cycles[0],
+            median: cycles[cycles.len() / 2],
+            max: cycles[cycles.len() - 1],
+        }
+    }
+}
+
+/// 按名字找一项测试
+pub fn find(name: &str) -> Option<&'static Benchmark> {
+    BENCHMARKS.iter().find(|benchmark| benchmark.name == name)
+}
+
+/// 注册 shell 的 bench 命令
+pub fn register_command() {
+    shell::register(Command {
+        name: "bench",
+        help: Msg::HelpBench,
+        run: bench_command,
+    });
+}
+
+fn bench_command(args: &[&str]) {
+    let selected: Vec<&Benchmark> = match args.first() {
+        Some(&name) => match find(name) {
+            Some(benchmark) => Vec::from([benchmark]),
+            None => {
+                shell_println!("{}", i18n::fill(Msg::UnknownBenchmark, &[&"bench", &name]));
+                return;
+            }
+        },
+        None => BENCHMARKS.iter().collect(),
+    };
+    // 第一次用 TSC 频率时要校准，放在计时之前
+    perf::tsc_hz();
+    for benchmark in selected {
+        shell_println!("{}", benchmark.run());
+    }
+}
+
+fn never() -> Option<&'static str> {
+    None
+}
+
+fn text_mode() -> Option<&'static str> {
+    VIDEO_MODE.lock().is_text().then_some("text-mode")
+}
+
+fn alloc(b: &mut Bencher) {
+    let mut blocks: Vec<Box<[u64; 4]>> = Vec::with_capacity(ALLOCATIONS);
+    b.iter(|| {
+        for i in 0..ALLOCATIONS {
+            blocks.push(Box::new([i as u64; 4]));
+        }
+        black_box(&blocks);
+        blocks.clear();
+    });
+}
+
+fn fill(b: &mut Bencher) {
+    let mut layer = Writer::new();
+    let (width, height) = (graphic::width(), graphic::height());
+    let mut shade = 0u8;
+    b.iter(|| {
+        shade = shade.wrapping_add(1);
+        layer.display_rect(0, 0, width, height, Rgb888::new(shade, 0, 0));
+    });
+    black_box(&layer);
+}
+
+fn text(b: &mut Bencher) {
+    let mut layer = Writer::new();
+    let (width, height) = (graphic::width(), graphic::height());
+    // 可打印的 ASCII 字符轮流出现
+    let content: String = (0..GLYPHS).map(|i| (b'!' + (i % 94) as u8) as char).collect();
+    b.iter(|| {
+        let color = Rgb888::new(255, 255, 255);
+        text::draw_text(&mut layer, 0, 0, width, height, &content, FontId::MONOSPACE, FONT_SIZE, LINE_HEIGHT,
+                        Align::Left, color);
+    });
+    black_box(&layer);
+}
+
+fn compose(b: &mut Bencher) {
+    let (width, height) = (graphic::width(), graphic::height());
+    b.iter(|| GD.lock().render(0, 0, height, width));
+}
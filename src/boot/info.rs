@@ -0,0 +1,75 @@
+// 引导信息
+// 不同的引导协议交给内核的信息格式不同，内核只通过 BootInfo 取用：物理内存映射到的偏移、内存映射和引导程序设置好的帧缓冲。
+// bootloader crate 的 BIOS 引导不设置帧缓冲，图形由 graphic::vbe 自己切换；Multiboot2（见 multiboot2）可以带上 GRUB 设置好的帧缓冲。
+// 内存映射统一用 bootloader 的 MemoryMap 表示，帧分配器和内存概况不用区分引导协议。
+// kernel_main 调用 record 记下引导协议和帧缓冲，之后的模块用 protocol 和 framebuffer 取
+
+use bootloader::bootinfo::MemoryMap;
+use spin::Once;
+
+/// 引导程序设置好的线性帧缓冲
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Framebuffer {
+    /// 物理地址
+    pub address: u64,
+    pub width: usize,
+    pub height: usize,
+    /// 每行的字节数
+    pub pitch: usize,
+    /// 每个像素的位数
+    pub bpp: usize,
+}
+
+pub trait BootInfo {
+    /// 引导协议的名字，用于显示
+    fn protocol(&self) -> &'static str;
+
+    /// 全部物理内存映射到的虚拟地址
+    fn physical_memory_offset(&self) -> u64;
+
+    fn memory_map(&self) -> &MemoryMap;
+
+    /// 引导程序设置好的帧缓冲，没有时返回 None
+    fn framebuffer(&self) -> Option<Framebuffer>;
+}
+
+impl BootInfo for bootloader::BootInfo {
+    fn protocol(&self) -> &'static str {
+        "bootloader"
+    }
+
+    fn physical_memory_offset(&self) -> u64 {
+        self.physical_memory_offset
+    }
+
+    fn memory_map(&self) -> &MemoryMap {
+        &self.memory_map
+    }
+
+    fn framebuffer(&self) -> Option<Framebuffer> {
+        None
+    }
+}
+
+static PROTOCOL: Once<&'static str> = Once::new();
+static FRAMEBUFFER: Once<Option<Framebuffer>> = Once::new();
+
+/// 记下引导协议和帧缓冲，由 kernel_main 在启动最早期调用
+pub fn record(info: &impl BootInfo) {
+    PROTOCOL.call_once(|| info.protocol());
+    FRAMEBUFFER.call_once(|| info.framebuffer());
+    log::info!("Booted by {}", info.protocol());
+    if let Some(fb) = info.framebuffer() {
+        log::info!("Boot framebuffer {}x{}x{} at {:#x}", fb.width, fb.height, fb.bpp, fb.address);
+    }
+}
+
+/// 引导协议，record 之前为 None
+pub fn protocol() -> Option<&'static str> {
+    PROTOCOL.get().copied()
+}
+
+/// 引导程序设置好的帧缓冲
+pub fn framebuffer() -> Option<Framebuffer> {
+    FRAMEBUFFER.get().copied().flatten()
+}
@@ -0,0 +1,157 @@
+// 启动顺序
+// 堆初始化之后的子系统和驱动各自用一个 Unit 描述：名字、依赖的其他单元和初始化函数，放在各自的模块里。
+// kernel_main 把所有单元交给 run，run 每次取登记顺序里第一个依赖都已经处理过的单元初始化，依赖失败或者不存在时跳过它，
+// 记下每个单元的结果和用时，boot 命令可以查看。加一个驱动只需要写好它的 Unit 再登记到 kernel_main 的列表里。
+// 要用启动时的帧分配器的单元从 Context 里取，帧分配器交给 memory 以后就取不到了，所以 frame-pool 单元要依赖它们。
+// 引导协议的差别由 info 的 BootInfo 隔开，见 info 和 multiboot2
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::time::Duration;
+
+use spin::Mutex;
+use x86_64::structures::paging::OffsetPageTable;
+
+use crate::io::timer::uptime;
+use crate::memory::BootInfoFrameAllocator;
+
+pub mod info;
+pub mod multiboot2;
+
+/// 初始化失败的原因
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InitError {
+    /// 依赖的单元没有登记
+    MissingDependency(&'static str),
+    /// 依赖关系有环
+    Cycle,
+    /// 启动用的帧分配器已经交给 memory
+    NoFrameAllocator,
+    Failed(String),
+}
+
+impl fmt::Display for InitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InitError::MissingDependency(name) => write!(f, "missing dependency {}", name),
+            InitError::Cycle => f.write_str("dependency cycle"),
+            InitError::NoFrameAllocator => f.write_str("boot frame allocator already handed over"),
+            InitError::Failed(reason) => f.write_str(reason),
+        }
+    }
+}
+
+/// 启动单元，`C` 是初始化函数拿到的上下文
+pub struct Unit<C = Context> {
+    pub name: &'static str,
+    /// 要先初始化的单元
+    pub depends: &'static [&'static str],
+    pub init: fn(&mut C) -> Result<(), InitError>,
+}
+
+/// 初始化的结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    Ready,
+    Failed(InitError),
+    /// 依赖的单元没有初始化成功，没有运行
+    Skipped(&'static str),
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Ready => f.write_str("ready"),
+            Status::Failed(error) => write!(f, "failed: {}", error),
+            Status::Skipped(dependency) => write!(f, "skipped, {} is not ready", dependency),
+        }
+    }
+}
+
+/// 一个单元的初始化记录
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub name: &'static str,
+    pub status: Status,
+    pub time: Duration,
+}
+
+/// 内核启动单元的上下文：页表和启动时的帧分配器
+pub struct Context {
+    pub mapper: OffsetPageTable<'static>,
+    frames: Option<BootInfoFrameAllocator>,
+}
+
+impl Context {
+    pub fn new(mapper: OffsetPageTable<'static>, frames: BootInfoFrameAllocator) -> Self {
+        Self { mapper, frames: Some(frames) }
+    }
+
+    /// 页表和启动时的帧分配器
+    pub fn paging(&mut self) -> Result<(&mut OffsetPageTable<'static>, &mut BootInfoFrameAllocator), InitError> {
+        let frames = self.frames.as_mut().ok_or(InitError::NoFrameAllocator)?;
+        Ok((&mut self.mapper, frames))
+    }
+
+    /// 取走启动时的帧分配器
+    pub fn take_frames(&mut self) -> Result<BootInfoFrameAllocator, InitError> {
+        self.frames.take().ok_or(InitError::NoFrameAllocator)
+    }
+}
+
+// 所有 run 过的单元，按初始化的顺序
+static RECORDS: Mutex<Vec<Record>> = Mutex::new(Vec::new());
+
+/// 按依赖顺序初始化 `units`，返回这一批的记录
+///
+/// 依赖都已经处理过的单元里先登记的先初始化
+pub fn run<C>(units: &[&Unit<C>], context: &mut C) -> Vec<Record> {
+    let mut records: Vec<Record> = Vec::new();
+    let status_of = |records: &[Record], name: &str| records.iter().find(|r| r.name == name).map(|r| r.status.clone());
+    while records.len() < units.len() {
+        let pending = units.iter().filter(|unit| status_of(&records, unit.name).is_none());
+        let ready = pending.clone().find(|unit| {
+            unit.depends.iter().all(|&dep| status_of(&records, dep).is_some() || !units.iter().any(|u| u.name == dep))
+        });
+        // 剩下的单元互相等待
+        let Some(unit) = ready else {
+            let stuck: Vec<&Unit<C>> = pending.copied().collect();
+            for unit in stuck {
+                records.push(finish(unit.name, Status::Failed(InitError::Cycle), Duration::ZERO));
+            }
+            break;
+        };
+        let missing = unit.depends.iter().find(|&&dep| !units.iter().any(|u| u.name == dep));
+        let failed = unit.depends.iter().find(|&&dep| status_of(&records, dep) != Some(Status::Ready));
+        let record = match (missing, failed) {
+            (Some(&dep), _) => finish(unit.name, Status::Failed(InitError::MissingDependency(dep)), Duration::ZERO),
+            (None, Some(&dep)) => finish(unit.name, Status::Skipped(dep), Duration::ZERO),
+            (None, None) => {
+                let start = uptime();
+                let status = match (unit.init)(context) {
+                    Ok(()) => Status::Ready,
+                    Err(error) => Status::Failed(error),
+                };
+                finish(unit.name, status, uptime().saturating_sub(start))
+            }
+        };
+        records.push(record);
+    }
+    RECORDS.lock().extend(records.iter().cloned());
+    records
+}
+
+// 输出一个单元的结果
+fn finish(name: &'static str, status: Status, time: Duration) -> Record {
+    match status {
+        Status::Ready => log::debug!("boot: {} ready in {} ms", name, time.as_millis()),
+        ref status => log::warn!("boot: {} {}", name, status),
+    }
+    Record { name, status, time }
+}
+
+/// 初始化过的所有单元，按初始化的顺序
+pub fn records() -> Vec<Record> {
+    RECORDS.lock().clone()
+}
@@ -0,0 +1,186 @@
+// Multiboot2 引导信息
+// GRUB 这类 Multiboot2 引导程序把信息放在一串 8 字节对齐的标签里，这里读出内存映射、帧缓冲和引导参数，
+// 内存映射转换成 bootloader 的 MemoryMap，和 bootloader crate 引导时一样交给帧分配器。
+// Multiboot2 把内核和引导信息占用的内存也标成可用，parse 时要给出它们的物理地址范围，从可用区域里挖掉。
+// 进入长模式、建立页表的 32 位入口代码和链接脚本还没有，引导信息由入口代码按恒等映射交给这里
+
+use core::ops::Range;
+use core::str;
+
+use bootloader::bootinfo::{FrameRange, MemoryMap, MemoryRegion, MemoryRegionType};
+
+use crate::boot::info::{BootInfo, Framebuffer};
+
+/// 引导程序交给内核的 eax
+pub const BOOTLOADER_MAGIC: u32 = 0x36D7_6289;
+
+// MemoryMap 最多能放的区域数
+const MAX_REGIONS: usize = 64;
+const PAGE_SIZE: u64 = 4096;
+
+const TAG_END: u32 = 0;
+const TAG_COMMAND_LINE: u32 = 1;
+const TAG_MEMORY_MAP: u32 = 6;
+const TAG_FRAMEBUFFER: u32 = 8;
+// 帧缓冲的类型，只支持直接写 RGB 的
+const FRAMEBUFFER_RGB: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    /// 总长度或者标签的长度超出范围
+    Truncated,
+    NoMemoryMap,
+    /// 内存映射的区域太多，MemoryMap 放不下
+    TooManyRegions,
+}
+
+/// 解析好的 Multiboot2 引导信息
+pub struct Multiboot2 {
+    memory_map: MemoryMap,
+    framebuffer: Option<Framebuffer>,
+    command_line: Option<&'static str>,
+}
+
+impl Multiboot2 {
+    /// 引导参数
+    pub fn command_line(&self) -> Option<&'static str> {
+        self.command_line
+    }
+}
+
+impl BootInfo for Multiboot2 {
+    fn protocol(&self) -> &'static str {
+        "multiboot2"
+    }
+
+    /// 入口代码恒等映射物理内存
+    fn physical_memory_offset(&self) -> u64 {
+        0
+    }
+
+    fn memory_map(&self) -> &MemoryMap {
+        &self.memory_map
+    }
+
+    fn framebuffer(&self) -> Option<Framebuffer> {
+        self.framebuffer
+    }
+}
+
+/// 解析从物理地址 `address` 开始的引导信息 `info`，`kernel` 是内核映像的物理地址范围
+pub fn parse(info: &'static [u8], address: u64, kernel: Range<u64>) -> Result<Multiboot2, ParseError> {
+    let total = read_u32(info, 0).ok_or(ParseError::Truncated)? as usize;
+    let info = info.get(..total).ok_or(ParseError::Truncated)?;
+    let mut result = Multiboot2 { memory_map: MemoryMap::new(), framebuffer: None, command_line: None };
+    let mut has_memory_map = false;
+    let holes = [
+        (kernel, MemoryRegionType::Kernel),
+        (address..address + total as u64, MemoryRegionType::BootInfo),
+    ];
+    let mut offset = 8;
+    while offset + 8 <= total {
+        let kind = read_u32(info, offset).ok_or(ParseError::Truncated)?;
+        let size = read_u32(info, offset + 4).ok_or(ParseError::Truncated)? as usize;
+        let tag = info.get(offset..offset + size).filter(|_| size >= 8).ok_or(ParseError::Truncated)?;
+        match kind {
+            TAG_END => break,
+            TAG_COMMAND_LINE => {
+                let text = &tag[8..];
+                let text = &text[..text.iter().position(|&b| b == 0).unwrap_or(text.len())];
+                result.command_line = str::from_utf8(text).ok();
+            }
+            TAG_MEMORY_MAP => {
+                parse_memory_map(tag, &holes, &mut result.memory_map)?;
+                has_memory_map = true;
+            }
+            TAG_FRAMEBUFFER => result.framebuffer = parse_framebuffer(tag),
+            _ => {}
+        }
+        // 标签按 8 字节对齐
+        offset += (size + 7) & !7;
+    }
+    if !has_memory_map {
+        return Err(ParseError::NoMemoryMap);
+    }
+    result.memory_map.sort();
+    Ok(result)
+}
+
+// 每一项是基址、长度、类型和保留的 4 字节
+fn parse_memory_map(tag: &[u8], holes: &[(Range<u64>, MemoryRegionType)], map: &mut MemoryMap) -> Result<(), ParseError> {
+    let entry_size = read_u32(tag, 8).ok_or(ParseError::Truncated)? as usize;
+    if entry_size < 24 {
+        return Err(ParseError::Truncated);
+    }
+    for entry in tag.get(16..).ok_or(ParseError::Truncated)?.chunks_exact(entry_size) {
+        let (Some(base), Some(length), Some(kind)) = (read_u64(entry, 0), read_u64(entry, 8), read_u32(entry, 16)) else {
+            return Err(ParseError::Truncated);
+        };
+        let region_type = match kind {
+            1 => MemoryRegionType::Usable,
+            3 => MemoryRegionType::AcpiReclaimable,
+            4 => MemoryRegionType::AcpiNvs,
+            5 => MemoryRegionType::BadMemory,
+            _ => MemoryRegionType::Reserved,
+        };
+        let end = base.saturating_add(length);
+        if region_type == MemoryRegionType::Usable {
+            add_usable(map, base, end, holes)?;
+        } else {
+            add(map, base, end, region_type)?;
+        }
+    }
+    Ok(())
+}
+
+// 可用区域挖掉 `holes` 之后加进去，挖掉的部分按它们的类型加
+fn add_usable(map: &mut MemoryMap, start: u64, end: u64, holes: &[(Range<u64>, MemoryRegionType)]) -> Result<(), ParseError> {
+    let Some(((hole, region_type), rest)) = holes.split_first() else {
+        return add(map, start, end, MemoryRegionType::Usable);
+    };
+    if hole.end <= start || hole.start >= end {
+        return add_usable(map, start, end, rest);
+    }
+    add_usable(map, start, hole.start.max(start), rest)?;
+    add(map, hole.start.max(start), hole.end.min(end), *region_type)?;
+    add_usable(map, hole.end.min(end), end, rest)
+}
+
+// 可用区域只取完整的页，其他区域把部分占用的页也算进去
+fn add(map: &mut MemoryMap, start: u64, end: u64, region_type: MemoryRegionType) -> Result<(), ParseError> {
+    let (start, end) = if region_type == MemoryRegionType::Usable {
+        (start.div_ceil(PAGE_SIZE) * PAGE_SIZE, end / PAGE_SIZE * PAGE_SIZE)
+    } else {
+        (start / PAGE_SIZE * PAGE_SIZE, end.div_ceil(PAGE_SIZE) * PAGE_SIZE)
+    };
+    if start >= end {
+        return Ok(());
+    }
+    if map.len() >= MAX_REGIONS {
+        return Err(ParseError::TooManyRegions);
+    }
+    map.add_region(MemoryRegion { range: FrameRange::new(start, end), region_type });
+    Ok(())
+}
+
+// 地址、每行字节数、宽、高、每像素位数和类型
+fn parse_framebuffer(tag: &[u8]) -> Option<Framebuffer> {
+    let address = read_u64(tag, 8)?;
+    let pitch = read_u32(tag, 16)? as usize;
+    let width = read_u32(tag, 20)? as usize;
+    let height = read_u32(tag, 24)? as usize;
+    let bpp = *tag.get(28)? as usize;
+    if *tag.get(29)? != FRAMEBUFFER_RGB {
+        log::warn!("multiboot2: framebuffer is not direct RGB, ignored");
+        return None;
+    }
+    Some(Framebuffer { address, width, height, pitch, bpp })
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(bytes.get(offset..offset + 8)?.try_into().ok()?))
+}
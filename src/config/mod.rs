@@ -0,0 +1,158 @@
+// 内核配置
+// 启动参数写成 key=value，用空白或换行分隔，# 到行尾是注释，同一个键写了多次时以最后一次为准：
+//   video=1024x768 loglevel=debug heap=128M
+// 从 QEMU 的 fw_cfg 文件 opt/cjn_os/cmdline 读取，可以用 -fw_cfg name=opt/cjn_os/cmdline,string=... 直接写在命令行上，
+// 也可以用 file=kernel.cfg 给出一个配置文件。磁盘上还没有文件系统，有了 FAT32 之后再加上从磁盘读配置文件。
+// 启动最早期读进固定大小的静态缓冲区，不需要堆；各模块通过下面的 getter 取值，没有设置或者值不合法时用自己的默认值
+
+use core::str;
+use core::time::Duration;
+
+use log::LevelFilter;
+use spin::Once;
+
+use crate::drivers::fw_cfg;
+use crate::i18n::Language;
+
+/// 启动参数所在的 fw_cfg 文件
+pub const FW_CFG_FILE: &str = "opt/cjn_os/cmdline";
+// 更长的部分被丢掉
+const MAX_LEN: usize = 4096;
+
+struct Cmdline {
+    bytes: [u8; MAX_LEN],
+    len: usize,
+}
+
+static CMDLINE: Once<Cmdline> = Once::new();
+
+// 认识的键和检查值是否合法的函数
+const OPTIONS: [(&str, fn(&str) -> bool); 7] = [
+    ("video", |value| parse_video(value).is_some()),
+    ("loglevel", |value| value.parse::<LevelFilter>().is_ok()),
+    ("heap", |value| parse_size(value).is_some()),
+    ("wx", |value| matches!(value, "warn" | "panic")),
+    ("screensaver", |value| value.parse::<u64>().is_ok()),
+    ("lock", |value| !value.is_empty()),
+    ("lang", |value| Language::by_name(value).is_some()),
+];
+// 值不写进日志的键
+const SECRETS: [&str; 1] = ["lock"];
+
+/// 分辨率，色深没写时为 None
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Video {
+    pub width: usize,
+    pub height: usize,
+    pub bpp: Option<usize>,
+}
+
+/// 读取启动参数，需在其他模块取值之前调用；重复调用不会重新读取
+pub fn init() {
+    CMDLINE.call_once(|| {
+        let mut cmdline = Cmdline { bytes: [0; MAX_LEN], len: 0 };
+        if let Some(file) = fw_cfg::find(FW_CFG_FILE) {
+            cmdline.len = fw_cfg::read(file, &mut cmdline.bytes);
+        }
+        cmdline
+    });
+}
+
+/// 全部启动参数，init 之前是空的
+pub fn cmdline() -> &'static str {
+    let Some(cmdline) = CMDLINE.get() else { return "" };
+    let bytes = &cmdline.bytes[..cmdline.len];
+    // 不是合法的 UTF-8 时只用前面合法的部分
+    str::from_utf8(bytes).unwrap_or_else(|error| str::from_utf8(&bytes[..error.valid_up_to()]).unwrap_or_default())
+}
+
+/// `cmdline` 里的所有参数，只写了键的参数值为空
+pub fn options(cmdline: &str) -> impl Iterator<Item = (&str, &str)> {
+    cmdline
+        .lines()
+        .flat_map(|line| line.split('#').next().unwrap_or_default().split_whitespace())
+        .map(|option| option.split_once('=').unwrap_or((option, "")))
+}
+
+/// 在 `cmdline` 里查找 `key`
+pub fn lookup<'a>(cmdline: &'a str, key: &str) -> Option<&'a str> {
+    options(cmdline).filter(|(name, _)| *name == key).last().map(|(_, value)| value)
+}
+
+/// 启动参数里 `key` 的值
+pub fn get(key: &str) -> Option<&'static str> {
+    lookup(cmdline(), key)
+}
+
+/// 解析 `1024x768` 或者 `1024x768x24` 这样的分辨率
+pub fn parse_video(value: &str) -> Option<Video> {
+    let mut parts = value.split('x');
+    let width = parts.next()?.parse().ok()?;
+    let height = parts.next()?.parse().ok()?;
+    let bpp = match parts.next() {
+        Some(bpp) => Some(bpp.parse().ok()?),
+        None => None,
+    };
+    if parts.next().is_some() || width == 0 || height == 0 {
+        return None;
+    }
+    Some(Video { width, height, bpp })
+}
+
+/// 解析 `4096`、`512K`、`128M`、`1G` 这样的字节数，单位不分大小写
+pub fn parse_size(value: &str) -> Option<u64> {
+    let (digits, shift) = match value.as_bytes().last()?.to_ascii_uppercase() {
+        b'K' => (&value[..value.len() - 1], 10),
+        b'M' => (&value[..value.len() - 1], 20),
+        b'G' => (&value[..value.len() - 1], 30),
+        _ => (value, 0),
+    };
+    digits.parse::<u64>().ok()?.checked_mul(1 << shift)
+}
+
+/// video=，图形模式的初始分辨率
+pub fn video() -> Option<Video> {
+    get("video").and_then(parse_video)
+}
+
+/// loglevel=，默认的日志级别
+pub fn log_level() -> Option<LevelFilter> {
+    get("loglevel").and_then(|value| value.parse().ok())
+}
+
+/// heap=，堆的大小
+pub fn heap_size() -> Option<usize> {
+    get("heap").and_then(parse_size).map(|size| size as usize)
+}
+
+/// wx=panic，启动时发现可写又可执行的页就 panic，默认只警告
+pub fn wx_panic() -> bool {
+    get("wx") == Some("panic")
+}
+
+/// screensaver=，闲置多少秒之后启动屏幕保护程序，0 表示不启动
+pub fn screensaver() -> Option<Duration> {
+    get("screensaver").and_then(|value| value.parse().ok()).map(Duration::from_secs)
+}
+
+/// lock=，屏幕保护程序退出时要输入的密码，不能含空白
+pub fn lock_passphrase() -> Option<&'static str> {
+    get("lock").filter(|value| !value.is_empty())
+}
+
+/// lang=，界面语言
+pub fn language() -> Option<Language> {
+    get("lang").and_then(Language::by_name)
+}
+
+/// 把启动参数写到日志里，不认识的键和不合法的值给出警告，密码只说设置了；在日志初始化之后调用
+pub fn log_options() {
+    for (key, value) in options(cmdline()) {
+        match OPTIONS.iter().find(|(name, _)| *name == key) {
+            Some((_, valid)) if valid(value) && SECRETS.contains(&key) => log::info!("config: {} is set", key),
+            Some((_, valid)) if valid(value) => log::info!("config: {}={}", key, value),
+            Some(_) => log::warn!("config: invalid value for {}: \"{}\"", key, value),
+            None => log::warn!("config: unknown option {}", key),
+        }
+    }
+}
@@ -0,0 +1,136 @@
+// x87 FPU 和 SSE 的状态
+// 每个 CPU 启动时调用 init：打开 CR0/CR4 里的相关位，支持 XSAVE 时在 XCR0 里打开 x87、SSE（和 AVX），再把 FPU 复位。
+// FpuState 是一块保存区，支持 XSAVE 并且区域放得下时用 XSAVE/XRSTOR，否则用 FXSAVE/FXRSTOR。
+// 需要保存浮点状态的地方：
+// - 进入用户程序前保存内核的状态、给程序一个干净的状态，回来后恢复（以后有了线程，每个线程的上下文里放一个）
+// - 中断处理函数要做浮点运算时用 preserve 包起来，不弄乱被打断的代码的 XMM 寄存器
+
+use core::arch::asm;
+use core::arch::x86_64::__cpuid_count;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use x86_64::registers::control::{Cr0, Cr0Flags, Cr4, Cr4Flags};
+
+use crate::cpu;
+
+// 保存区的大小，x87、SSE 和 AVX 一起是 832 字节
+const AREA_SIZE: usize = 1024;
+// XCR0 的状态位
+const XCR0_X87: u64 = 1 << 0;
+const XCR0_SSE: u64 = 1 << 1;
+const XCR0_AVX: u64 = 1 << 2;
+// 复位后的控制字：屏蔽所有异常，64 位精度，就近舍入
+const DEFAULT_FCW: u16 = 0x037F;
+const DEFAULT_MXCSR: u32 = 0x1F80;
+// 保存区里控制字和 MXCSR 的位置，FXSAVE 和 XSAVE 的前 512 字节格式相同
+const FCW_OFFSET: usize = 0;
+const MXCSR_OFFSET: usize = 24;
+
+// 是否用 XSAVE，以及 XSAVE 要保存的状态
+static USE_XSAVE: AtomicBool = AtomicBool::new(false);
+static XSAVE_MASK: AtomicU64 = AtomicU64::new(0);
+
+/// 一份 x87/SSE（和 AVX）寄存器的状态
+#[repr(C, align(64))]
+#[derive(Clone)]
+pub struct FpuState {
+    area: [u8; AREA_SIZE],
+}
+
+impl FpuState {
+    /// 复位后的状态
+    pub const fn new() -> Self {
+        let mut area = [0; AREA_SIZE];
+        let fcw = DEFAULT_FCW.to_le_bytes();
+        area[FCW_OFFSET] = fcw[0];
+        area[FCW_OFFSET + 1] = fcw[1];
+        let mxcsr = DEFAULT_MXCSR.to_le_bytes();
+        let mut i = 0;
+        while i < 4 {
+            area[MXCSR_OFFSET + i] = mxcsr[i];
+            i += 1;
+        }
+        FpuState { area }
+    }
+
+    /// 把当前 CPU 的状态存进来
+    pub fn save(&mut self) {
+        let ptr = self.area.as_mut_ptr();
+        if USE_XSAVE.load(Ordering::Relaxed) {
+            let mask = XSAVE_MASK.load(Ordering::Relaxed);
+            unsafe {
+                asm!("xsave64 [{}]", in(reg) ptr, in("eax") mask as u32, in("edx") (mask >> 32) as u32,
+                     options(nostack, preserves_flags));
+            }
+        } else {
+            unsafe { asm!("fxsave64 [{}]", in(reg) ptr, options(nostack, preserves_flags)) };
+        }
+    }
+
+    /// 把保存的状态装回当前 CPU
+    pub fn restore(&self) {
+        let ptr = self.area.as_ptr();
+        if USE_XSAVE.load(Ordering::Relaxed) {
+            let mask = XSAVE_MASK.load(Ordering::Relaxed);
+            unsafe {
+                asm!("xrstor64 [{}]", in(reg) ptr, in("eax") mask as u32, in("edx") (mask >> 32) as u32,
+                     options(nostack, preserves_flags));
+            }
+        } else {
+            unsafe { asm!("fxrstor64 [{}]", in(reg) ptr, options(nostack, preserves_flags)) };
+        }
+    }
+
+    /// 保存的 MXCSR
+    pub fn mxcsr(&self) -> u32 {
+        u32::from_le_bytes(self.area[MXCSR_OFFSET..MXCSR_OFFSET + 4].try_into().unwrap())
+    }
+}
+
+impl Default for FpuState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 打开当前 CPU 的 FPU 和 SSE 并复位，每个 CPU 都要调用一次
+pub fn init() {
+    let features = cpu::features();
+    if !(features.sse && features.fxsr) {
+        return;
+    }
+    unsafe {
+        Cr0::update(|flags| {
+            flags.remove(Cr0Flags::EMULATE_COPROCESSOR | Cr0Flags::TASK_SWITCHED);
+            flags.insert(Cr0Flags::MONITOR_COPROCESSOR | Cr0Flags::NUMERIC_ERROR);
+        });
+        Cr4::update(|flags| flags.insert(Cr4Flags::OSFXSR | Cr4Flags::OSXMMEXCPT_ENABLE));
+    }
+    if features.xsave {
+        let mask = if features.avx { XCR0_X87 | XCR0_SSE | XCR0_AVX } else { XCR0_X87 | XCR0_SSE };
+        unsafe {
+            Cr4::update(|flags| flags.insert(Cr4Flags::OSXSAVE));
+            asm!("xsetbv", in("ecx") 0, in("eax") mask as u32, in("edx") (mask >> 32) as u32,
+                 options(nomem, nostack, preserves_flags));
+        }
+        // 打开的状态需要的保存区大小
+        let size = unsafe { __cpuid_count(0xD, 0) }.ebx as usize;
+        XSAVE_MASK.store(mask, Ordering::Relaxed);
+        USE_XSAVE.store(size <= AREA_SIZE, Ordering::Relaxed);
+    }
+    unsafe {
+        asm!("fninit", options(nomem, nostack, preserves_flags));
+        asm!("ldmxcsr [{}]", in(reg) &DEFAULT_MXCSR, options(nostack, preserves_flags, readonly));
+    }
+}
+
+/// 保存当前的浮点状态，执行 `f`，再恢复
+///
+/// 中断处理函数里要做浮点运算时用它包起来
+pub fn preserve<R>(f: impl FnOnce() -> R) -> R {
+    let mut state = FpuState::new();
+    state.save();
+    let result = f();
+    state.restore();
+    result
+}
@@ -0,0 +1,208 @@
+// CPU 功能检测
+// 启动时用 CPUID 读一次厂商、型号和功能位，存在 FEATURES 里，其他模块通过 features() 查询，不再各自执行 CPUID。
+// init 在 BSP 上输出摘要并打开需要操作系统配合的功能（FPU 和 SSE，见 fpu；NX），AP 启动时调用 enable 打开同样的功能。
+// 检测在堆初始化之前进行，这里不能分配内存
+
+pub mod fpu;
+
+use core::arch::x86_64::{CpuidResult, __cpuid, __cpuid_count};
+use core::fmt;
+
+use lazy_static::lazy_static;
+use x86_64::registers::control::{Cr4, Cr4Flags};
+use x86_64::registers::model_specific::{Efer, EferFlags};
+
+// 扩展功能叶的起点
+const EXTENDED_LEAF: u32 = 0x8000_0000;
+
+#[derive(Debug, Clone)]
+pub struct Features {
+    vendor: [u8; 12],
+    brand: [u8; 48],
+    pub family: u32,
+    pub model: u32,
+    pub stepping: u32,
+    pub tsc: bool,
+    pub fxsr: bool,
+    pub sse: bool,
+    pub sse2: bool,
+    pub sse3: bool,
+    pub ssse3: bool,
+    pub sse4_1: bool,
+    pub sse4_2: bool,
+    pub avx: bool,
+    pub avx2: bool,
+    pub xsave: bool,
+    pub rdrand: bool,
+    pub rdseed: bool,
+    pub x2apic: bool,
+    pub nx: bool,
+    /// 机器检查异常和机器检查架构
+    pub mce: bool,
+    pub mca: bool,
+    /// 支持 1GiB 大页
+    pub page_1gb: bool,
+}
+
+lazy_static! {
+    static ref FEATURES: Features = detect();
+}
+
+fn bit(register: u32, bit: u32) -> bool {
+    register & (1 << bit) != 0
+}
+
+// 寄存器里的 ASCII 字节按顺序拼起来
+fn ascii<const N: usize>(registers: &[u32]) -> [u8; N] {
+    let mut bytes = [0; N];
+    for (chunk, register) in bytes.chunks_mut(4).zip(registers) {
+        chunk.copy_from_slice(&register.to_le_bytes());
+    }
+    bytes
+}
+
+// 去掉末尾的 0 和两边的空格
+fn text(bytes: &[u8]) -> &str {
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    core::str::from_utf8(&bytes[..len]).unwrap_or("").trim()
+}
+
+// 不支持的叶当作全是 0
+fn cpuid(leaf: u32, max: u32) -> CpuidResult {
+    if leaf <= max { unsafe { __cpuid_count(leaf, 0) } } else { CpuidResult { eax: 0, ebx: 0, ecx: 0, edx: 0 } }
+}
+
+fn detect() -> Features {
+    let leaf0 = unsafe { __cpuid(0) };
+    let max_leaf = leaf0.eax;
+    let leaf1 = cpuid(1, max_leaf);
+    let leaf7 = cpuid(7, max_leaf);
+    let max_extended = unsafe { __cpuid(EXTENDED_LEAF) }.eax;
+    let extended1 = cpuid(EXTENDED_LEAF + 1, max_extended);
+    // 处理器名称在 0x80000002 到 0x80000004 三个叶里
+    let mut brand = [0; 12];
+    for (i, chunk) in brand.chunks_mut(4).enumerate() {
+        let r = cpuid(EXTENDED_LEAF + 2 + i as u32, max_extended);
+        chunk.copy_from_slice(&[r.eax, r.ebx, r.ecx, r.edx]);
+    }
+
+    // 家族是 0xF 时加上扩展家族，家族是 6 或 0xF 时型号加上扩展型号
+    let base_family = (leaf1.eax >> 8) & 0xF;
+    let family = if base_family == 0xF { base_family + ((leaf1.eax >> 20) & 0xFF) } else { base_family };
+    let mut model = (leaf1.eax >> 4) & 0xF;
+    if base_family == 0x6 || base_family == 0xF {
+        model |= ((leaf1.eax >> 16) & 0xF) << 4;
+    }
+
+    Features {
+        vendor: ascii(&[leaf0.ebx, leaf0.edx, leaf0.ecx]),
+        brand: ascii(&brand),
+        family,
+        model,
+        stepping: leaf1.eax & 0xF,
+        tsc: bit(leaf1.edx, 4),
+        fxsr: bit(leaf1.edx, 24),
+        sse: bit(leaf1.edx, 25),
+        sse2: bit(leaf1.edx, 26),
+        sse3: bit(leaf1.ecx, 0),
+        ssse3: bit(leaf1.ecx, 9),
+        sse4_1: bit(leaf1.ecx, 19),
+        sse4_2: bit(leaf1.ecx, 20),
+        avx: bit(leaf1.ecx, 28),
+        avx2: bit(leaf7.ebx, 5),
+        xsave: bit(leaf1.ecx, 26),
+        rdrand: bit(leaf1.ecx, 30),
+        rdseed: bit(leaf7.ebx, 18),
+        x2apic: bit(leaf1.ecx, 21),
+        nx: bit(extended1.edx, 20),
+        mce: bit(leaf1.edx, 7),
+        mca: bit(leaf1.edx, 14),
+        page_1gb: bit(extended1.edx, 26),
+    }
+}
+
+impl Features {
+    /// 厂商字符串，例如 "GenuineIntel"
+    pub fn vendor(&self) -> &str {
+        text(&self.vendor)
+    }
+
+    /// 处理器名称，没有时为空
+    pub fn brand(&self) -> &str {
+        text(&self.brand)
+    }
+
+    /// 支持的功能的名字
+    pub fn flags(&self) -> impl Iterator<Item = &'static str> {
+        [
+            (self.tsc, "tsc"),
+            (self.fxsr, "fxsr"),
+            (self.sse, "sse"),
+            (self.sse2, "sse2"),
+            (self.sse3, "sse3"),
+            (self.ssse3, "ssse3"),
+            (self.sse4_1, "sse4.1"),
+            (self.sse4_2, "sse4.2"),
+            (self.avx, "avx"),
+            (self.avx2, "avx2"),
+            (self.xsave, "xsave"),
+            (self.rdrand, "rdrand"),
+            (self.rdseed, "rdseed"),
+            (self.x2apic, "x2apic"),
+            (self.nx, "nx"),
+            (self.mce, "mce"),
+            (self.mca, "mca"),
+            (self.page_1gb, "pdpe1gb"),
+        ]
+        .into_iter()
+        .filter_map(|(present, name)| present.then_some(name))
+    }
+}
+
+impl fmt::Display for Features {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = if self.brand().is_empty() { self.vendor() } else { self.brand() };
+        write!(f, "{} (family {:#x}, model {:#x}, stepping {})", name, self.family, self.model, self.stepping)
+    }
+}
+
+// 用空格分开的功能名
+struct Flags<'a>(&'a Features);
+
+impl fmt::Display for Flags<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, name) in self.0.flags().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            f.write_str(name)?;
+        }
+        Ok(())
+    }
+}
+
+/// 启动时检测到的 CPU 功能
+pub fn features() -> &'static Features {
+    &FEATURES
+}
+
+/// 在 BSP 上检测功能、输出摘要，并打开当前 CPU 上的功能
+pub fn init() {
+    let features = features();
+    log::info!("CPU: {}", features);
+    log::info!("CPU features: {}", Flags(features));
+    enable();
+}
+
+/// 打开当前 CPU 上需要操作系统设置的功能，每个 CPU 都要调用一次
+pub fn enable() {
+    fpu::init();
+    // 打开之后页表里的 NO_EXECUTE 才有效，见 memory::wx
+    if features().nx {
+        unsafe { Efer::update(|flags| flags.insert(EferFlags::NO_EXECUTE_ENABLE)) };
+    }
+    // 硬件错误报告成机器检查异常，而不是直接关机，见 interrupts::exceptions
+    if features().mce {
+        unsafe { Cr4::update(|flags| flags.insert(Cr4Flags::MACHINE_CHECK_EXCEPTION)) };
+    }
+}
@@ -0,0 +1,292 @@
+// 主机控制通道
+// 在 COM3 上接受 JSON-RPC 2.0 请求，让主机上的测试脚本直接驱动内核，不用去解析给人看的日志。
+// QEMU 把 COM3 接到 TCP 端口（见 Cargo.toml 的 run-args），每行一个请求，每个请求回一行响应：
+//   {"jsonrpc":"2.0","id":1,"method":"stats"}
+//   {"jsonrpc":"2.0","id":1,"result":{"uptime_ms":1234,...}}
+// 支持的方法：
+//   ping                                   回 "pong"
+//   stats                                  运行时间、堆、CPU 和已经显示的帧
+//   screenshot {x,y,width,height,pixels}   区域（默认整个屏幕）的校验和，pixels 为 true 时附上 RRGGBB 的十六进制像素
+//   key {text}                             把文字当作键盘输入
+//   mouse {dx,dy,left,right,middle,wheel}  注入一个鼠标事件，dy 和 wheel 向上为正
+//   selftest                               运行图形自检
+//   latency {source,count}                 开始测量输入延迟（source 是 key 或 mouse）；不带 source 时返回上一次的结果
+// 串口用轮询收发，由 shell 主循环在空闲时调用 poll；没有 COM3 时什么也不做
+
+pub mod json;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use embedded_graphics::pixelcolor::RgbColor;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::allocator::heap_stats;
+use crate::debug::control::json::Value;
+use crate::graphic::{self, present, Region, GD};
+use crate::io::mouse::{self, MouseEvent};
+use crate::io::qemu::uart::Uart;
+use crate::io::{keyboard, timer};
+use crate::smp;
+use crate::trace::latency::{self, Report, Source, Stage};
+
+const COM3: u16 = 0x3E8;
+const BAUD_RATE: u32 = 115_200;
+// 一行请求的最大长度，超过的部分被丢弃，整行按解析错误处理
+const MAX_LINE: usize = 4096;
+// 一次最多返回的像素数，115200 波特下大约 10 秒
+const MAX_PIXELS: usize = 64 * 64 * 4;
+
+// JSON-RPC 规定的错误码
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+static PORT: Uart = Uart::new("com3", COM3);
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+struct Line {
+    bytes: Vec<u8>,
+    overflowed: bool,
+}
+
+static LINE: Mutex<Line> = Mutex::new(Line { bytes: Vec::new(), overflowed: false });
+
+/// 检查 COM3 是否存在并初始化
+pub fn init() {
+    if !PORT.is_present() {
+        return;
+    }
+    PORT.init_polled(BAUD_RATE);
+    ENABLED.store(true, Ordering::Relaxed);
+    log::info!("control: JSON-RPC channel listening on COM3");
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// 收取 COM3 上的数据，每收齐一行处理一个请求
+pub fn poll() {
+    if !enabled() {
+        return;
+    }
+    while let Some(byte) = PORT.try_receive() {
+        let request = {
+            let mut line = LINE.lock();
+            match byte {
+                b'\n' => {
+                    let bytes = core::mem::take(&mut line.bytes);
+                    let overflowed = core::mem::replace(&mut line.overflowed, false);
+                    Some((bytes, overflowed))
+                }
+                b'\r' => None,
+                _ if line.bytes.len() < MAX_LINE => {
+                    line.bytes.push(byte);
+                    None
+                }
+                _ => {
+                    line.overflowed = true;
+                    None
+                }
+            }
+        };
+        match request {
+            Some((bytes, false)) if bytes.is_empty() => {}
+            Some((bytes, false)) => respond(&String::from_utf8_lossy(&bytes)),
+            Some((_, true)) => send(&error(Value::Null, PARSE_ERROR, "request too long")),
+            None => {}
+        }
+    }
+}
+
+fn respond(text: &str) {
+    let Some(request) = json::parse(text) else {
+        send(&error(Value::Null, PARSE_ERROR, "parse error"));
+        return;
+    };
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let Some(method) = request.get("method").and_then(Value::as_str) else {
+        send(&error(id, INVALID_REQUEST, "missing method"));
+        return;
+    };
+    let params = request.get("params").cloned().unwrap_or(Value::Object(Vec::new()));
+    let response = match call(method, &params) {
+        Ok(result) => Value::object([("jsonrpc", "2.0".into()), ("id", id), ("result", result)]),
+        Err((code, message)) => error(id, code, message),
+    };
+    send(&response);
+}
+
+fn error(id: Value, code: i64, message: &str) -> Value {
+    let error = Value::object([("code", code.into()), ("message", message.into())]);
+    Value::object([("jsonrpc", "2.0".into()), ("id", id), ("error", error)])
+}
+
+fn send(response: &Value) {
+    let mut out = UartWriter;
+    let _ = writeln!(out, "{}", response);
+}
+
+struct UartWriter;
+
+impl Write for UartWriter {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for byte in s.bytes() {
+            PORT.send(byte);
+        }
+        Ok(())
+    }
+}
+
+type CallResult = Result<Value, (i64, &'static str)>;
+
+fn call(method: &str, params: &Value) -> CallResult {
+    match method {
+        "ping" => Ok("pong".into()),
+        "stats" => Ok(stats()),
+        "screenshot" => screenshot(params),
+        "key" => key(params),
+        "mouse" => mouse_event(params),
+        "selftest" => Ok(Value::object([("passed", graphic::selftest::font().into())])),
+        "latency" => latency(params),
+        _ => Err((METHOD_NOT_FOUND, "method not found")),
+    }
+}
+
+fn stats() -> Value {
+    let heap = heap_stats();
+    Value::object([
+        ("uptime_ms", (timer::uptime().as_millis() as u64).into()),
+        ("ticks", timer::ticks().into()),
+        ("heap", Value::object([
+            ("size", heap.size.into()),
+            ("free", heap.free.into()),
+            ("free_regions", heap.free_regions.into()),
+            ("largest_free", heap.largest_free.into()),
+            ("fragmentation", heap.fragmentation().into()),
+        ])),
+        ("cpus", smp::cpu_count().into()),
+        ("frame", present::current_frame().into()),
+        ("screen", Value::object([("width", graphic::width().into()), ("height", graphic::height().into())])),
+    ])
+}
+
+// 缺省的参数用 default，类型不对时报错
+fn param_usize(params: &Value, key: &str, default: usize) -> Result<usize, (i64, &'static str)> {
+    match params.get(key) {
+        None => Ok(default),
+        Some(value) => value.as_i64()
+            .and_then(|n| usize::try_from(n).ok())
+            .ok_or((INVALID_PARAMS, "expected a non-negative integer")),
+    }
+}
+
+fn param_bool(params: &Value, key: &str) -> Result<bool, (i64, &'static str)> {
+    match params.get(key) {
+        None => Ok(false),
+        Some(value) => value.as_bool().ok_or((INVALID_PARAMS, "expected a boolean")),
+    }
+}
+
+// 坐标和其他图形代码一样，x 是行，y 是列
+fn screenshot(params: &Value) -> CallResult {
+    let (screen_width, screen_height) = (graphic::width(), graphic::height());
+    let x = param_usize(params, "x", 0)?.min(screen_height);
+    let y = param_usize(params, "y", 0)?.min(screen_width);
+    let width = param_usize(params, "width", screen_width)?.min(screen_width - y);
+    let height = param_usize(params, "height", screen_height)?.min(screen_height - x);
+    let with_pixels = param_bool(params, "pixels")?;
+    if with_pixels && width * height > MAX_PIXELS {
+        return Err((INVALID_PARAMS, "region too large for pixels, ask for a smaller one"));
+    }
+
+    // 等着下一帧的改动先合成上去
+    crate::gui::frame::flush();
+    let gd = GD.lock();
+    let checksum = gd.checksum(Region::new(x, y, x + height, y + width));
+    let mut result = Vec::from([
+        (String::from("width"), width.into()),
+        (String::from("height"), height.into()),
+        (String::from("checksum"), checksum.into()),
+    ]);
+    if with_pixels {
+        let mut pixels = String::with_capacity(width * height * 6);
+        for row in x..x + height {
+            for column in y..y + width {
+                let color = gd.read_pixel(row, column);
+                let _ = write!(pixels, "{:02x}{:02x}{:02x}", color.r(), color.g(), color.b());
+            }
+        }
+        result.push((String::from("pixels"), pixels.into()));
+    }
+    Ok(Value::Object(result))
+}
+
+fn key(params: &Value) -> CallResult {
+    let text = params.get("text").and_then(Value::as_str).ok_or((INVALID_PARAMS, "expected text"))?;
+    // 输入队列也在中断里用，关着中断放进去
+    interrupts::without_interrupts(|| text.chars().for_each(keyboard::push_key));
+    Ok(text.chars().count().into())
+}
+
+fn mouse_event(params: &Value) -> CallResult {
+    let delta = |key: &str| match params.get(key) {
+        None => Ok(0),
+        Some(value) => value.as_i64()
+            .and_then(|n| i16::try_from(n).ok())
+            .ok_or((INVALID_PARAMS, "expected a 16-bit integer")),
+    };
+    let event = MouseEvent {
+        dx: delta("dx")?,
+        dy: delta("dy")?,
+        left: param_bool(params, "left")?,
+        right: param_bool(params, "right")?,
+        middle: param_bool(params, "middle")?,
+        wheel: delta("wheel")?,
+    };
+    interrupts::without_interrupts(|| mouse::push_event(event));
+    Ok(Value::Null)
+}
+
+fn latency(params: &Value) -> CallResult {
+    let Some(source) = params.get("source") else {
+        return Ok(latency::last_report().as_ref().map_or(Value::Null, report));
+    };
+    let source = source.as_str().and_then(Source::by_name).ok_or((INVALID_PARAMS, "expected key or mouse"))?;
+    let count = param_usize(params, "count", 10)?;
+    if count == 0 {
+        return Err((INVALID_PARAMS, "count must be positive"));
+    }
+    if !latency::start(source, count) {
+        return Err((INVALID_REQUEST, "a measurement is already running"));
+    }
+    Ok(Value::Null)
+}
+
+// 每一步的延迟以微秒为单位
+fn report(report: &Report) -> Value {
+    let micros = |d: core::time::Duration| Value::from(d.as_secs_f64() * 1e6);
+    let stages = Stage::ALL[1..].iter().map(|&stage| (stage.name(), &report.stages[stage as usize]))
+        .chain([("total", &report.total)])
+        .map(|(name, stats)| match stats.average() {
+            Some(average) => Value::object([
+                ("stage", name.into()),
+                ("count", stats.count.into()),
+                ("min_us", micros(stats.min)),
+                ("avg_us", micros(average)),
+                ("max_us", micros(stats.max)),
+            ]),
+            None => Value::object([("stage", name.into()), ("count", 0usize.into())]),
+        })
+        .collect();
+    Value::object([
+        ("source", report.source.name().into()),
+        ("samples", report.samples.into()),
+        ("stages", Value::Array(stages)),
+    ])
+}
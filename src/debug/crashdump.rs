@@ -0,0 +1,241 @@
+// 崩溃转储
+// panic 时跟在串口上的文字报告后面输出一份二进制的 minidump，图形模式下屏幕已经看不清时问题也不会丢掉。
+// 转储以 base64 写到 COM1，夹在 "-----BEGIN CRASHDUMP-----" 和 "-----END CRASHDUMP-----" 之间，
+// 和截图的格式一样，主机上的脚本用同一段代码取出来再解析。解码后的整数都是小端：
+//   文件头  MAGIC（8 字节）、格式版本 u16
+//   段      类型 u16、内容长度 u32、内容，一个接一个，类型 0 表示结束
+//   结尾    前面所有字节的 CRC32 u32（多项式 0xEDB88320，和 GPT 一样）
+// 字符串是 u16 长度加 UTF-8，可能被截断在字符中间。段的内容：
+//   1 INFO       系统名、版本、git 哈希、panic 消息（含位置）、启动以来的纳秒 u64
+//   2 REGISTERS  rsp rbp rflags cr0 cr2 cr3 cr4 各 u64
+//   3 FAULT      异常名、rip rsp rflags cs ss 各 u64、有无错误码 u8 和错误码 u64、CPU u32、用户程序、最后的喂狗位置
+//   4 BACKTRACE  层数 u32，每层返回地址 u64
+//   5 LOG        条数 u32，每条序号 u64、级别 u8（1 ERROR 到 5 TRACE）、时间纳秒 u64、模块、消息
+//   6 HEAP       堆大小、空闲字节数、空闲区域数、最大的空闲区域，各 u64
+//   7 INPUT      条数 u32，每条设备号 u8、时间纳秒 u64、类型 u8 和两个 i32 参数：
+//                1 按键（字符的码位，0）、2 移动（dx，dy）、3 鼠标按键（0 左 1 右 2 中，是否按下）、4 滚轮（格数，0）
+// 没有异常时没有 FAULT 段；日志、堆、输入的锁正被 panic 的代码持有时对应的段也不输出，解析时不认识的段按长度跳过。
+// 每段先用计数器算出长度再真正输出，整个过程不分配内存。还没有文件系统，写成 /crash/dump-NNN.bin 要等 FAT32 加进来
+
+use core::fmt::{self, Write};
+
+use crate::allocator;
+use crate::debug::panic::Report;
+use crate::graphic::screenshot::Base64;
+use crate::io::input::{self, Event, InputEvent, MouseButton};
+use crate::io::timer::uptime;
+use crate::logger::ring;
+use crate::version;
+
+/// 解码后的前 8 个字节
+pub const MAGIC: [u8; 8] = *b"CJNDUMP\0";
+/// 格式版本，段的内容变化时加一
+pub const VERSION: u16 = 1;
+// panic 消息最多保留的字节数
+const MESSAGE_LEN: usize = 512;
+
+/// 段的类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u16)]
+pub enum Section {
+    End = 0,
+    Info = 1,
+    Registers = 2,
+    Fault = 3,
+    Backtrace = 4,
+    Log = 5,
+    Heap = 6,
+    Input = 7,
+}
+
+// 转储的输出目标，计数器和真正的输出各实现一份
+trait Out {
+    fn bytes(&mut self, bytes: &[u8]);
+
+    fn u8(&mut self, value: u8) {
+        self.bytes(&[value]);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn i32(&mut self, value: i32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn str(&mut self, s: &str) {
+        let bytes = &s.as_bytes()[..s.len().min(u16::MAX as usize)];
+        self.u16(bytes.len() as u16);
+        self.bytes(bytes);
+    }
+}
+
+// 只数字节数，用来算段的长度
+struct Counter(usize);
+
+impl Out for Counter {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.0 += bytes.len();
+    }
+}
+
+// 边算 CRC 边以 base64 输出，记下第一个错误
+struct Frame<'a, W: Write> {
+    encoder: Base64<'a, W>,
+    crc: u32,
+    result: fmt::Result,
+}
+
+impl<W: Write> Out for Frame<'_, W> {
+    fn bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.crc ^= byte as u32;
+            for _ in 0..8 {
+                self.crc = if self.crc & 1 != 0 { (self.crc >> 1) ^ 0xEDB8_8320 } else { self.crc >> 1 };
+            }
+        }
+        if self.result.is_ok() {
+            self.result = self.encoder.write(bytes);
+        }
+    }
+}
+
+// 定长的缓冲区，写不下的部分被截掉
+struct Buffer {
+    bytes: [u8; MESSAGE_LEN],
+    len: usize,
+}
+
+impl Buffer {
+    fn as_str(&self) -> &str {
+        // 截断处可能落在字符中间，只取完整的部分
+        match core::str::from_utf8(&self.bytes[..self.len]) {
+            Ok(s) => s,
+            Err(error) => core::str::from_utf8(&self.bytes[..error.valid_up_to()]).unwrap_or(""),
+        }
+    }
+}
+
+impl Write for Buffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = s.len().min(MESSAGE_LEN - self.len);
+        self.bytes[self.len..self.len + end].copy_from_slice(&s.as_bytes()[..end]);
+        self.len += end;
+        Ok(())
+    }
+}
+
+/// 把 panic 的报告连同日志、堆和最近的输入事件写成转储，输出到 `out`；由 panic::handle 调用
+pub(crate) fn write(out: &mut impl Write, report: &Report) -> fmt::Result {
+    writeln!(out, "-----BEGIN CRASHDUMP-----")?;
+    let mut frame = Frame { encoder: Base64::new(out), crc: u32::MAX, result: Ok(()) };
+    frame.bytes(&MAGIC);
+    frame.u16(VERSION);
+
+    let now = uptime();
+    let mut message = Buffer { bytes: [0; MESSAGE_LEN], len: 0 };
+    let _ = write!(message, "{}", report.info);
+    section(&mut frame, Section::Info, &|out: &mut dyn Out| {
+        out.str(version::NAME);
+        out.str(version::VERSION);
+        out.str(version::GIT_HASH);
+        out.str(message.as_str());
+        out.u64(now.as_nanos() as u64);
+    });
+    section(&mut frame, Section::Registers, &|out: &mut dyn Out| {
+        let r = &report.registers;
+        [r.rsp, r.rbp, r.rflags, r.cr0, r.cr2, r.cr3, r.cr4].into_iter().for_each(|value| out.u64(value));
+    });
+    if let Some(fault) = report.fault {
+        let mut checkpoint = Buffer { bytes: [0; MESSAGE_LEN], len: 0 };
+        if let Some(location) = fault.checkpoint {
+            let _ = write!(checkpoint, "{}", location);
+        }
+        section(&mut frame, Section::Fault, &|out: &mut dyn Out| {
+            out.str(fault.name);
+            [fault.rip, fault.rsp, fault.rflags, fault.cs, fault.ss].into_iter().for_each(|value| out.u64(value));
+            out.u8(fault.error_code.is_some() as u8);
+            out.u64(fault.error_code.unwrap_or(0));
+            out.u32(fault.cpu as u32);
+            out.str(fault.program.unwrap_or(""));
+            out.str(checkpoint.as_str());
+        });
+    }
+    section(&mut frame, Section::Backtrace, &|out: &mut dyn Out| {
+        out.u32(report.frames.len() as u32);
+        report.frames.iter().for_each(|&address| out.u64(address as u64));
+    });
+    // 持有日志的锁直到这一段写完，两遍看到的是同样的内容
+    if let Some(log) = ring::try_snapshot() {
+        section(&mut frame, Section::Log, &|out: &mut dyn Out| {
+            out.u32(log.iter().count() as u32);
+            for entry in log.iter() {
+                out.u64(entry.sequence);
+                out.u8(entry.level as u8);
+                out.u64(entry.time.as_nanos() as u64);
+                out.str(entry.target());
+                out.str(entry.message());
+            }
+        });
+    }
+    if let Some(heap) = allocator::try_heap_stats() {
+        section(&mut frame, Section::Heap, &|out: &mut dyn Out| {
+            let values = [heap.size, heap.free, heap.free_regions, heap.largest_free];
+            values.into_iter().for_each(|value| out.u64(value as u64));
+        });
+    }
+    if let Some(history) = input::try_history() {
+        section(&mut frame, Section::Input, &|out: &mut dyn Out| {
+            out.u32(history.iter().flatten().count() as u32);
+            history.iter().flatten().for_each(|event| write_event(out, event));
+        });
+    }
+    frame.u16(Section::End as u16);
+    frame.u32(0);
+
+    let crc = !frame.crc;
+    frame.result?;
+    let mut encoder = frame.encoder;
+    encoder.write(&crc.to_le_bytes())?;
+    encoder.finish()?;
+    writeln!(out, "-----END CRASHDUMP-----")
+}
+
+// 输出一段：先数出内容的长度写在段头里，再输出内容，所以 `body` 要跑两遍，两遍的输出必须一样
+fn section(out: &mut dyn Out, kind: Section, body: &dyn Fn(&mut dyn Out)) {
+    let mut counter = Counter(0);
+    body(&mut counter);
+    out.u16(kind as u16);
+    out.u32(counter.0 as u32);
+    body(out);
+}
+
+fn write_event(out: &mut dyn Out, event: &Event) {
+    out.u8(event.device.0);
+    out.u64(event.time.as_nanos() as u64);
+    let (kind, a, b) = match event.event {
+        InputEvent::Key(ch) => (1, ch as i32, 0),
+        InputEvent::MouseMove { dx, dy } => (2, dx as i32, dy as i32),
+        InputEvent::MouseButton { button, pressed } => {
+            let button = match button {
+                MouseButton::Left => 0,
+                MouseButton::Right => 1,
+                MouseButton::Middle => 2,
+            };
+            (3, button, pressed as i32)
+        }
+        InputEvent::Wheel(delta) => (4, delta as i32, 0),
+    };
+    out.u8(kind);
+    out.i32(a);
+    out.i32(b);
+}
@@ -0,0 +1,621 @@
+// GDB 远程调试
+// 在 COM2 上实现 GDB 远程串行协议的最小子集：读写寄存器、读写内存、软件断点（int3）、继续和单步。
+// QEMU 把 COM2 接到 TCP 端口（见 Cargo.toml 的 run-args），在另一个终端里连上去：
+//   gdb target/x86_64-cjn_os/debug/cjn-os -ex "target remote localhost:4321"
+// 内核停在断点上时才处理 GDB 的命令，所以连接后先用 shell 的 gdb 命令或在 GDB 里按 Ctrl+C 让内核停下来。
+// 断点和单步异常不走 x86-interrupt 调用约定：那样拿不到通用寄存器，这里用汇编入口把寄存器全部压栈，
+// GDB 改过的寄存器在返回时原样恢复。停下来时关着中断，串口用轮询收发。
+// 打开 symbols 特性时 monitor sym 可以在内核的符号表里查地址和函数名，GDB 没有加载内核 ELF 时也能用。
+// 没有 COM2 时（只给了一个 -serial）什么也不做，断点异常照旧由 interrupts 模块的处理函数记日志
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use spin::Mutex;
+use x86_64::instructions::segmentation::{Segment, DS, ES, FS, GS};
+use x86_64::registers::control::{Cr0, Cr0Flags};
+use x86_64::structures::idt::InterruptDescriptorTable;
+use x86_64::VirtAddr;
+
+use crate::debug::symbols;
+use crate::io::qemu::uart::Uart;
+use crate::memory;
+
+const COM2: u16 = 0x2F8;
+const BAUD_RATE: u32 = 115_200;
+// 一个包的最大长度，qSupported 里告诉 GDB
+const PACKET_SIZE: usize = 1024;
+const MAX_BREAKPOINTS: usize = 32;
+const INT3: u8 = 0xCC;
+// RFLAGS 的单步标志
+const TRAP_FLAG: u64 = 1 << 8;
+// GDB 运行时按 Ctrl+C 发来的字节
+const INTERRUPT: u8 = 0x03;
+
+// 停下来的原因，对应 Unix 信号
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+
+static PORT: Uart = Uart::new("com2", COM2);
+static ENABLED: AtomicBool = AtomicBool::new(false);
+// 计时器中断里收到 Ctrl+C，接下来的断点要报告成 SIGINT
+static INTERRUPT_REQUESTED: AtomicBool = AtomicBool::new(false);
+static STATE: Mutex<State> = Mutex::new(State { breakpoints: [None; MAX_BREAKPOINTS], attached: false });
+
+struct State {
+    breakpoints: [Option<Breakpoint>; MAX_BREAKPOINTS],
+    // GDB 已经连上，恢复运行后再停下时要主动报告
+    attached: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Breakpoint {
+    address: u64,
+    original: u8,
+}
+
+/// 汇编入口压栈后的内容，从低地址到高地址；rip 之后是 CPU 压的中断栈帧
+#[derive(Debug)]
+#[repr(C)]
+pub struct TrapFrame {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+    pub vector: u64,
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+// 进入时栈上有 CPU 压的 5 个值（压之前对齐到 16 字节），再压向量号和 15 个通用寄存器，
+// 调用前再减 8 让栈对齐到 16 字节
+core::arch::global_asm!(
+    ".global gdb_debug_entry",
+    "gdb_debug_entry:",
+    "    push 1",
+    "    jmp 2f",
+    ".global gdb_breakpoint_entry",
+    "gdb_breakpoint_entry:",
+    "    push 3",
+    "2:",
+    "    push rax",
+    "    push rbx",
+    "    push rcx",
+    "    push rdx",
+    "    push rsi",
+    "    push rdi",
+    "    push rbp",
+    "    push r8",
+    "    push r9",
+    "    push r10",
+    "    push r11",
+    "    push r12",
+    "    push r13",
+    "    push r14",
+    "    push r15",
+    "    mov rdi, rsp",
+    "    sub rsp, 8",
+    "    cld",
+    "    call {trap}",
+    "    add rsp, 8",
+    "    pop r15",
+    "    pop r14",
+    "    pop r13",
+    "    pop r12",
+    "    pop r11",
+    "    pop r10",
+    "    pop r9",
+    "    pop r8",
+    "    pop rbp",
+    "    pop rdi",
+    "    pop rsi",
+    "    pop rdx",
+    "    pop rcx",
+    "    pop rbx",
+    "    pop rax",
+    "    add rsp, 8",
+    "    iretq",
+    trap = sym trap,
+);
+
+extern "C" {
+    fn gdb_debug_entry();
+    fn gdb_breakpoint_entry();
+}
+
+/// 检查 COM2 是否存在并初始化，需在加载 IDT 之前调用
+pub fn init() {
+    if !PORT.is_present() {
+        return;
+    }
+    PORT.init_polled(BAUD_RATE);
+    ENABLED.store(true, Ordering::Relaxed);
+    log::info!("gdb: remote stub listening on COM2");
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// 有 COM2 时把断点和单步异常接到 GDB stub 上
+pub fn install(idt: &mut InterruptDescriptorTable) {
+    if !enabled() {
+        return;
+    }
+    unsafe {
+        idt.breakpoint.set_handler_addr(VirtAddr::new(gdb_breakpoint_entry as usize as u64));
+        idt.debug.set_handler_addr(VirtAddr::new(gdb_debug_entry as usize as u64));
+    }
+}
+
+/// 停下来交给 GDB，GDB 让内核继续后返回
+#[inline(always)]
+pub fn breakpoint() {
+    x86_64::instructions::interrupts::int3();
+}
+
+/// 由计时器中断调用：GDB 按了 Ctrl+C 时停下来
+pub fn poll() {
+    if enabled() && PORT.try_receive() == Some(INTERRUPT) {
+        INTERRUPT_REQUESTED.store(true, Ordering::Relaxed);
+        breakpoint();
+    }
+}
+
+// 断点和单步异常的处理函数，返回时按 frame 恢复寄存器
+extern "sysv64" fn trap(frame: &mut TrapFrame) {
+    let mut state = STATE.lock();
+    let signal = if INTERRUPT_REQUESTED.swap(false, Ordering::Relaxed) { SIGINT } else { SIGTRAP };
+    frame.rflags &= !TRAP_FLAG;
+    // 停在自己插的断点上时 rip 已经越过了 int3，退回到断点的地址
+    if frame.vector == 3 && state.find(frame.rip.wrapping_sub(1)).is_some() {
+        frame.rip -= 1;
+    }
+
+    let mut reply = Reply::new();
+    if state.attached {
+        let _ = write!(reply, "S{:02x}", signal);
+        send_packet(reply.as_bytes());
+    }
+    let mut packet = [0; PACKET_SIZE];
+    loop {
+        let len = read_packet(&mut packet);
+        state.attached = true;
+        reply.clear();
+        match state.handle(&packet[..len], frame, signal, &mut reply) {
+            Action::Stay => send_packet(reply.as_bytes()),
+            Action::Resume => return,
+            Action::Detach => {
+                send_packet(b"OK");
+                state.remove_all();
+                state.attached = false;
+                return;
+            }
+        }
+    }
+}
+
+enum Action {
+    // 回复后继续等下一个命令
+    Stay,
+    // 不回复，恢复运行，再停下时报告
+    Resume,
+    // 回复 OK，拆掉所有断点后恢复运行
+    Detach,
+}
+
+impl State {
+    fn handle(&mut self, packet: &[u8], frame: &mut TrapFrame, signal: u8, reply: &mut Reply) -> Action {
+        let Some((&command, args)) = packet.split_first() else { return Action::Stay };
+        let result = match command {
+            b'?' => write!(reply, "S{:02x}", signal),
+            b'g' => write_registers(frame, reply),
+            b'G' => {
+                read_registers(frame, args);
+                reply.write_str("OK")
+            }
+            b'p' => match parse_hex(args).and_then(|n| register(frame, n as usize)) {
+                Some((value, size)) => write_hex(reply, &value.to_le_bytes()[..size]),
+                None => reply.write_str("E00"),
+            },
+            b'P' => match split(args, b'=').and_then(|(n, value)| Some((parse_hex(n)?, decode_le(value)?))) {
+                Some((n, value)) if set_register(frame, n as usize, value) => reply.write_str("OK"),
+                _ => reply.write_str("E00"),
+            },
+            b'm' => match parse_range(args) {
+                Some((address, len)) if readable(address, len) => {
+                    // 回复里每个字节占两个字符
+                    let len = len.min(PACKET_SIZE as u64 / 2);
+                    let bytes = unsafe { core::slice::from_raw_parts(address as *const u8, len as usize) };
+                    write_hex(reply, bytes)
+                }
+                _ => reply.write_str("E14"),
+            },
+            b'M' => match split(args, b':').and_then(|(range, data)| Some((parse_range(range)?, data))) {
+                Some(((address, len), data)) if data.len() as u64 == len * 2 && readable(address, len) => {
+                    for (i, pair) in data.chunks(2).enumerate() {
+                        if let Some(byte) = hex_byte(pair) {
+                            poke(address + i as u64, byte);
+                        }
+                    }
+                    reply.write_str("OK")
+                }
+                _ => reply.write_str("E14"),
+            },
+            b'Z' | b'z' => match parse_breakpoint(args) {
+                // 只支持软件断点
+                Some((0, address)) => {
+                    let ok = if command == b'Z' { self.insert(address) } else { self.remove(address) };
+                    reply.write_str(if ok { "OK" } else { "E0e" })
+                }
+                _ => Ok(()),
+            },
+            b'c' | b's' => {
+                if let Some(address) = parse_hex(args) {
+                    frame.rip = address;
+                }
+                if command == b's' {
+                    frame.rflags |= TRAP_FLAG;
+                }
+                return Action::Resume;
+            }
+            b'D' | b'k' => return Action::Detach,
+            b'H' => reply.write_str("OK"),
+            b'q' if args.starts_with(b"Supported") => write!(reply, "PacketSize={:x}", PACKET_SIZE),
+            b'q' if args == b"Attached" => reply.write_str("1"),
+            b'q' if args.starts_with(b"Rcmd,") => monitor(&args[5..], reply),
+            // 不支持的命令回复空包
+            _ => Ok(()),
+        };
+        if result.is_err() {
+            reply.clear();
+            let _ = reply.write_str("E01");
+        }
+        Action::Stay
+    }
+
+    fn find(&self, address: u64) -> Option<usize> {
+        self.breakpoints.iter().position(|b| b.is_some_and(|b| b.address == address))
+    }
+
+    fn insert(&mut self, address: u64) -> bool {
+        if self.find(address).is_some() {
+            return true;
+        }
+        let Some(slot) = self.breakpoints.iter().position(Option::is_none) else { return false };
+        if !readable(address, 1) {
+            return false;
+        }
+        let original = unsafe { (address as *const u8).read_volatile() };
+        poke(address, INT3);
+        self.breakpoints[slot] = Some(Breakpoint { address, original });
+        true
+    }
+
+    fn remove(&mut self, address: u64) -> bool {
+        let Some(slot) = self.find(address) else { return false };
+        if let Some(breakpoint) = self.breakpoints[slot].take() {
+            poke(breakpoint.address, breakpoint.original);
+        }
+        true
+    }
+
+    fn remove_all(&mut self) {
+        for slot in self.breakpoints.iter_mut() {
+            if let Some(breakpoint) = slot.take() {
+                poke(breakpoint.address, breakpoint.original);
+            }
+        }
+    }
+}
+
+// 寄存器按 GDB 的 amd64 顺序：rax rbx rcx rdx rsi rdi rbp rsp r8-r15 rip 各 8 字节，
+// 之后 eflags cs ss ds es fs gs 各 4 字节。浮点寄存器不发，GDB 把它们当作不可用
+const REGISTERS: usize = 24;
+
+// 第 n 个寄存器的值和字节数
+fn register(frame: &TrapFrame, n: usize) -> Option<(u64, usize)> {
+    let value = match n {
+        0 => frame.rax,
+        1 => frame.rbx,
+        2 => frame.rcx,
+        3 => frame.rdx,
+        4 => frame.rsi,
+        5 => frame.rdi,
+        6 => frame.rbp,
+        7 => frame.rsp,
+        8 => frame.r8,
+        9 => frame.r9,
+        10 => frame.r10,
+        11 => frame.r11,
+        12 => frame.r12,
+        13 => frame.r13,
+        14 => frame.r14,
+        15 => frame.r15,
+        16 => frame.rip,
+        17 => frame.rflags,
+        18 => frame.cs,
+        19 => frame.ss,
+        20 => DS::get_reg().0 as u64,
+        21 => ES::get_reg().0 as u64,
+        22 => FS::get_reg().0 as u64,
+        23 => GS::get_reg().0 as u64,
+        _ => return None,
+    };
+    Some((value, if n <= 16 { 8 } else { 4 }))
+}
+
+// 修改第 n 个寄存器，段寄存器不能改
+fn set_register(frame: &mut TrapFrame, n: usize, value: u64) -> bool {
+    let slot = match n {
+        0 => &mut frame.rax,
+        1 => &mut frame.rbx,
+        2 => &mut frame.rcx,
+        3 => &mut frame.rdx,
+        4 => &mut frame.rsi,
+        5 => &mut frame.rdi,
+        6 => &mut frame.rbp,
+        7 => &mut frame.rsp,
+        8 => &mut frame.r8,
+        9 => &mut frame.r9,
+        10 => &mut frame.r10,
+        11 => &mut frame.r11,
+        12 => &mut frame.r12,
+        13 => &mut frame.r13,
+        14 => &mut frame.r14,
+        15 => &mut frame.r15,
+        16 => &mut frame.rip,
+        17 => &mut frame.rflags,
+        _ => return n < REGISTERS,
+    };
+    *slot = value;
+    true
+}
+
+fn write_registers(frame: &TrapFrame, reply: &mut Reply) -> fmt::Result {
+    for n in 0..REGISTERS {
+        if let Some((value, size)) = register(frame, n) {
+            write_hex(reply, &value.to_le_bytes()[..size])?;
+        }
+    }
+    Ok(())
+}
+
+// G 包：和 g 的回复格式相同，可以只给出前面一部分寄存器
+fn read_registers(frame: &mut TrapFrame, mut data: &[u8]) {
+    for n in 0..REGISTERS {
+        let size = if n <= 16 { 16 } else { 8 };
+        let Some(value) = data.get(..size).and_then(decode_le) else { return };
+        set_register(frame, n, value);
+        data = &data[size..];
+    }
+}
+
+// 地址所在的页是否都已映射
+fn readable(address: u64, len: u64) -> bool {
+    let Some(end) = address.checked_add(len.max(1) - 1) else { return false };
+    let mut page = address & !0xFFF;
+    while page <= end {
+        match VirtAddr::try_new(page) {
+            Ok(addr) if memory::translate(addr).is_some() => {}
+            _ => return false,
+        }
+        page += 0x1000;
+    }
+    true
+}
+
+// 写一个字节，暂时关掉写保护，内核代码所在的只读页也能写进断点
+fn poke(address: u64, byte: u8) {
+    let protected = Cr0::read().contains(Cr0Flags::WRITE_PROTECT);
+    unsafe {
+        Cr0::update(|flags| flags.remove(Cr0Flags::WRITE_PROTECT));
+        (address as *mut u8).write_volatile(byte);
+        if protected {
+            Cr0::update(|flags| flags.insert(Cr0Flags::WRITE_PROTECT));
+        }
+    }
+}
+
+// 等待一个完整的包 $<数据>#<校验和>，校验正确时回复 +，否则回复 - 让 GDB 重发；返回数据的长度
+fn read_packet(buffer: &mut [u8; PACKET_SIZE]) -> usize {
+    'packet: loop {
+        while receive() != b'$' {}
+        let (mut len, mut sum) = (0, 0u8);
+        loop {
+            match receive() {
+                b'#' => break,
+                // 上一个包不完整，重新开始
+                b'$' => {
+                    len = 0;
+                    sum = 0;
+                }
+                byte => {
+                    if len == PACKET_SIZE {
+                        PORT.send(b'-');
+                        continue 'packet;
+                    }
+                    buffer[len] = byte;
+                    len += 1;
+                    sum = sum.wrapping_add(byte);
+                }
+            }
+        }
+        let checksum = [receive(), receive()];
+        if hex_byte(&checksum) == Some(sum) {
+            PORT.send(b'+');
+            return len;
+        }
+        PORT.send(b'-');
+    }
+}
+
+// 发送一个包，直到 GDB 回复 +
+fn send_packet(data: &[u8]) {
+    const HEX: &[u8; 16] = b"0123456789abcdef";
+
+    loop {
+        PORT.send(b'$');
+        let mut sum = 0u8;
+        for &byte in data {
+            PORT.send(byte);
+            sum = sum.wrapping_add(byte);
+        }
+        PORT.send(b'#');
+        PORT.send(HEX[(sum >> 4) as usize]);
+        PORT.send(HEX[(sum & 0xF) as usize]);
+        loop {
+            match receive() {
+                b'+' => return,
+                b'-' => break,
+                _ => {}
+            }
+        }
+    }
+}
+
+fn receive() -> u8 {
+    loop {
+        if let Some(byte) = PORT.try_receive() {
+            return byte;
+        }
+        core::hint::spin_loop();
+    }
+}
+
+// GDB 的 monitor 命令，命令和输出都是十六进制编码的文本。
+// 现在只有 sym：monitor sym 0x<地址> 显示地址所在的函数，monitor sym <函数名> 显示函数的地址
+fn monitor(command: &[u8], reply: &mut Reply) -> fmt::Result {
+    let mut buffer = [0; PACKET_SIZE / 2];
+    let mut len = 0;
+    for pair in command.chunks(2) {
+        let Some(byte) = hex_byte(pair) else { return reply.write_str("E01") };
+        buffer[len] = byte;
+        len += 1;
+    }
+    let command = core::str::from_utf8(&buffer[..len]).unwrap_or("");
+    let mut out = HexOutput(reply);
+    let Some(("sym", arg)) = command.split_once(' ') else {
+        return writeln!(out, "usage: monitor sym 0x<address>|<function>");
+    };
+    let arg = arg.trim();
+    if !symbols::available() {
+        return writeln!(out, "no symbol table, build with --features symbols");
+    }
+    match arg.strip_prefix("0x").map(|hex| parse_hex(hex.as_bytes())) {
+        Some(Some(address)) => match symbols::resolve(address as usize) {
+            Some(symbol) => writeln!(out, "{:#018x} {}", address, symbol),
+            None => writeln!(out, "{:#018x} ??", address),
+        },
+        Some(None) => writeln!(out, "bad address {}", arg),
+        None => match symbols::lookup(arg) {
+            Some(address) => writeln!(out, "{} is at {:#018x}", arg, address),
+            None => writeln!(out, "no function {}", arg),
+        },
+    }
+}
+
+// 写进去的文本按十六进制编码到回复里
+struct HexOutput<'a>(&'a mut Reply);
+
+impl Write for HexOutput<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        write_hex(self.0, s.as_bytes())
+    }
+}
+
+// 回复的缓冲区，固定大小，停在断点上时不分配内存
+struct Reply {
+    data: [u8; PACKET_SIZE],
+    len: usize,
+}
+
+impl Reply {
+    fn new() -> Self {
+        Self { data: [0; PACKET_SIZE], len: 0 }
+    }
+
+    fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+}
+
+impl Write for Reply {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        if end > PACKET_SIZE {
+            return Err(fmt::Error);
+        }
+        self.data[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+fn write_hex(out: &mut impl Write, bytes: &[u8]) -> fmt::Result {
+    bytes.iter().try_for_each(|byte| write!(out, "{:02x}", byte))
+}
+
+fn hex_digit(ch: u8) -> Option<u8> {
+    (ch as char).to_digit(16).map(|digit| digit as u8)
+}
+
+// 两个十六进制字符组成的字节
+fn hex_byte(pair: &[u8]) -> Option<u8> {
+    match pair {
+        [high, low] => Some((hex_digit(*high)? << 4) | hex_digit(*low)?),
+        _ => None,
+    }
+}
+
+// 大端的十六进制数，用于地址、长度和寄存器编号
+fn parse_hex(s: &[u8]) -> Option<u64> {
+    if s.is_empty() || s.len() > 16 {
+        return None;
+    }
+    s.iter().try_fold(0u64, |value, &ch| Some((value << 4) | hex_digit(ch)? as u64))
+}
+
+// 按小端字节序编码的寄存器值，最多 8 字节
+fn decode_le(s: &[u8]) -> Option<u64> {
+    if s.len() % 2 != 0 || s.len() > 16 {
+        return None;
+    }
+    s.chunks(2).enumerate().try_fold(0u64, |value, (i, pair)| Some(value | (hex_byte(pair)? as u64) << (i * 8)))
+}
+
+fn split(s: &[u8], separator: u8) -> Option<(&[u8], &[u8])> {
+    let i = s.iter().position(|&ch| ch == separator)?;
+    Some((&s[..i], &s[i + 1..]))
+}
+
+// "地址,长度"
+fn parse_range(s: &[u8]) -> Option<(u64, u64)> {
+    let (address, len) = split(s, b',')?;
+    Some((parse_hex(address)?, parse_hex(len)?))
+}
+
+// "类型,地址,大小"
+fn parse_breakpoint(s: &[u8]) -> Option<(u64, u64)> {
+    let (kind, rest) = split(s, b',')?;
+    let (address, _) = split(rest, b',')?;
+    Some((parse_hex(kind)?, parse_hex(address)?))
+}
@@ -0,0 +1,125 @@
+// 断言和调用栈
+// kassert! 失败时把表达式、位置和当前上下文写到串口，再通过 panic 停机，panic 处理函数接着输出调用栈；
+// kdebug_assert! 只在 debug 构建中检查，release 构建中被编译掉，适合放在画像素这样的热路径上。
+// 内容只写串口：断言可能在持有图形锁时失败，这时再往屏幕上写会死锁。
+// 调用栈沿着帧指针（rbp）回溯，目标配置里打开了 frame-pointer。嵌入了符号表（symbols 特性）时返回地址显示成函数名+偏移，
+// 否则只输出地址，用 addr2line -e <内核 ELF> <地址> 对照源码。
+// panic 处理函数调用 panic::handle，它除了写串口还会把信息画成全屏的蓝色画面，并由 crashdump 在串口上输出一份二进制转储。
+// 交互式调试用 gdb 模块，GDB 通过 COM2 连上来；自动化测试用 control 模块，脚本通过 COM3 发 JSON-RPC 请求。
+// 主循环或 AP 上的任务卡住时由 watchdog 模块报告
+
+pub mod control;
+pub mod crashdump;
+pub mod gdb;
+pub mod panic;
+pub mod symbols;
+pub mod watchdog;
+
+use core::fmt::{self, Write};
+
+use x86_64::instructions::interrupts;
+
+use crate::io::qemu::SerialStream;
+
+// 最多回溯的层数
+const MAX_FRAMES: usize = 32;
+// 帧指针只在当前栈附近有效，离开这个范围就认为已经到了栈底
+const MAX_STACK_SPAN: usize = 1024 * 1024;
+
+/// 检查条件，不成立时输出上下文并 panic；附加的参数和 format! 一样
+#[macro_export]
+macro_rules! kassert {
+    ($cond:expr $(,)?) => {
+        if !$cond {
+            $crate::debug::assert_failed(stringify!($cond), None, file!(), line!());
+        }
+    };
+    ($cond:expr, $($arg:tt)+) => {
+        if !$cond {
+            $crate::debug::assert_failed(stringify!($cond), Some(format_args!($($arg)+)), file!(), line!());
+        }
+    };
+}
+
+/// 只在 debug 构建中检查的 kassert!
+#[macro_export]
+macro_rules! kdebug_assert {
+    ($($arg:tt)*) => {
+        if cfg!(debug_assertions) {
+            $crate::kassert!($($arg)*);
+        }
+    };
+}
+
+#[doc(hidden)]
+#[cold]
+pub fn assert_failed(expr: &str, message: Option<fmt::Arguments>, file: &str, line: u32) -> ! {
+    let mut out = SerialStream;
+    let _ = writeln!(out, "assertion failed: {}", expr);
+    if let Some(message) = message {
+        let _ = writeln!(out, "  message: {}", message);
+    }
+    let _ = writeln!(out, "  at {}:{}", file, line);
+    let _ = writeln!(out, "  context: {}", context());
+    match message {
+        Some(message) => panic!("assertion failed: {}: {} ({}:{})", expr, message, file, line),
+        None => panic!("assertion failed: {} ({}:{})", expr, file, line),
+    }
+}
+
+// 内核没有线程，只能区分是否关着中断（在中断处理函数里或者 without_interrupts 中）
+fn context() -> &'static str {
+    if interrupts::are_enabled() {
+        "kernel main loop, interrupts enabled"
+    } else {
+        "interrupt handler or interrupts disabled"
+    }
+}
+
+/// 输出调用栈，每行一个返回地址，由近及远
+#[inline(never)]
+pub fn backtrace(out: &mut impl Write) -> fmt::Result {
+    let mut frames = [0; MAX_FRAMES];
+    let count = collect_frames(&mut frames);
+    writeln!(out, "backtrace:")?;
+    for (i, &address) in frames[..count].iter().enumerate() {
+        write_frame(out, i, address)?;
+    }
+    Ok(())
+}
+
+/// 把调用栈上的返回地址依次填进 `frames`，从调用者开始，返回层数
+#[inline(never)]
+pub fn collect_frames(frames: &mut [usize]) -> usize {
+    let mut rbp: usize;
+    unsafe { core::arch::asm!("mov {}, rbp", out(reg) rbp, options(nomem, nostack, preserves_flags)) };
+    let start = rbp;
+    let mut count = 0;
+    while count < frames.len() {
+        // 栈帧里依次是调用者的 rbp 和返回地址
+        if rbp == 0 || rbp % 8 != 0 || rbp < start || rbp - start > MAX_STACK_SPAN {
+            break;
+        }
+        let (next, return_address) = unsafe { (*(rbp as *const usize), *((rbp + 8) as *const usize)) };
+        if return_address == 0 {
+            break;
+        }
+        frames[count] = return_address;
+        count += 1;
+        // 栈向下增长，调用者的帧在更高的地址
+        if next <= rbp {
+            break;
+        }
+        rbp = next;
+    }
+    count
+}
+
+/// 输出一层调用栈，有符号表时附上函数名
+pub fn write_frame(out: &mut impl Write, index: usize, address: usize) -> fmt::Result {
+    write!(out, "  #{:<2} {:#018x}", index, address)?;
+    if let Some(symbol) = symbols::resolve(address) {
+        write!(out, " {}", symbol)?;
+    }
+    writeln!(out)
+}
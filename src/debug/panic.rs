@@ -0,0 +1,251 @@
+// panic 画面
+// panic 时关中断，记下控制寄存器和调用栈，先完整地写到串口，再换成全屏的蓝色画面显示同样的内容，然后停机。
+// 画面不经过图层合成，也不用 rusttype 字体：那些路径要分配内存、要拿字形缓存等锁，panic 可能正好发生在里面。
+// 图形模式下直接在正在显示的页上用 embedded-graphics 自带的点阵字体画，文本模式下换成蓝底白字写 VGA 缓冲区；
+// 需要的锁如果被 panic 的代码持有就强行解开，反正之后不会再回到那段代码。
+// 异常处理函数先用 record_fault 记下异常时的栈帧再 panic，画面上会显示出错的指令地址，以及当时在哪个 CPU 上、
+// 运行的是哪个用户程序或者内核最后一次喂看门狗的位置。
+// 串口上的文字报告之后还跟着一份 crashdump 模块输出的二进制转储，另外带上日志、堆和最近的输入事件，主机上的脚本可以解析
+
+use core::fmt::{self, Write};
+use core::panic::{Location, PanicInfo};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use embedded_graphics::mono_font::ascii::FONT_8X13;
+use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::pixelcolor::Rgb888;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+use embedded_graphics::text::{Baseline, Text};
+use spin::{Mutex, MutexGuard};
+use x86_64::instructions::interrupts;
+use x86_64::registers::control::{Cr0, Cr2, Cr3, Cr4};
+use x86_64::structures::idt::InterruptStackFrame;
+
+use crate::debug::{collect_frames, crashdump, symbols, watchdog, write_frame, MAX_FRAMES};
+use crate::graphic::{PhysicalWriter, GD};
+use crate::io::qemu::SerialStream;
+use crate::io::VIDEO_MODE;
+use crate::smp::percpu;
+use crate::usermode;
+use crate::version;
+use crate::vga_buffer::{self, Color};
+
+const BACKGROUND: Rgb888 = Rgb888::new(0x00, 0x24, 0x8C);
+const FOREGROUND: Rgb888 = Rgb888::new(0xFF, 0xFF, 0xFF);
+// 图形模式下文字离屏幕边缘的距离，像素
+const MARGIN: usize = 16;
+// 文本模式只有 25 行，调用栈只显示前几层，完整的在串口上
+const TEXT_MODE_FRAMES: usize = 8;
+
+// 已经在处理 panic，画面代码里再 panic 时不要重入
+static PANICKING: AtomicBool = AtomicBool::new(false);
+// 最近一次致命异常时的栈帧
+static FAULT: Mutex<Option<Fault>> = Mutex::new(None);
+
+/// 致命异常发生时 CPU 压栈的内容
+#[derive(Debug, Clone, Copy)]
+pub struct Fault {
+    pub name: &'static str,
+    pub rip: u64,
+    pub rsp: u64,
+    pub rflags: u64,
+    pub cs: u64,
+    pub ss: u64,
+    pub error_code: Option<u64>,
+    /// 出错的 CPU
+    pub cpu: usize,
+    /// 正在运行的用户程序
+    pub program: Option<&'static str>,
+    /// 这个 CPU 最后一次喂看门狗的位置，大致说明内核在做什么
+    pub checkpoint: Option<&'static Location<'static>>,
+}
+
+/// 由异常处理函数在 panic 之前调用
+pub fn record_fault(name: &'static str, frame: &InterruptStackFrame, error_code: Option<u64>) {
+    let fault = Fault {
+        name,
+        rip: frame.instruction_pointer.as_u64(),
+        rsp: frame.stack_pointer.as_u64(),
+        rflags: frame.cpu_flags,
+        cs: frame.code_segment,
+        ss: frame.stack_segment,
+        error_code,
+        cpu: percpu::cpu_id(),
+        program: usermode::current_program(),
+        checkpoint: watchdog::checkpoint(),
+    };
+    if let Some(mut slot) = FAULT.try_lock() {
+        *slot = Some(fault);
+    }
+}
+
+// panic 时的寄存器，通用寄存器已经被 panic 的调用链改掉了，只记录栈和控制寄存器
+pub(crate) struct Registers {
+    pub(crate) rsp: u64,
+    pub(crate) rbp: u64,
+    pub(crate) rflags: u64,
+    pub(crate) cr0: u64,
+    pub(crate) cr2: u64,
+    pub(crate) cr3: u64,
+    pub(crate) cr4: u64,
+}
+
+impl Registers {
+    #[inline(always)]
+    fn capture() -> Self {
+        let (rsp, rbp, rflags): (u64, u64, u64);
+        unsafe {
+            core::arch::asm!("mov {}, rsp", out(reg) rsp, options(nomem, nostack, preserves_flags));
+            core::arch::asm!("mov {}, rbp", out(reg) rbp, options(nomem, nostack, preserves_flags));
+            core::arch::asm!("pushfq", "pop {}", out(reg) rflags, options(nomem, preserves_flags));
+        }
+        Self {
+            rsp,
+            rbp,
+            rflags,
+            cr0: Cr0::read_raw(),
+            cr2: Cr2::read().as_u64(),
+            cr3: Cr3::read().0.start_address().as_u64(),
+            cr4: Cr4::read_raw(),
+        }
+    }
+}
+
+pub(crate) struct Report<'a> {
+    pub(crate) info: &'a PanicInfo<'a>,
+    pub(crate) registers: Registers,
+    pub(crate) fault: Option<Fault>,
+    pub(crate) frames: &'a [usize],
+}
+
+impl Report<'_> {
+    // 输出全部内容，调用栈最多 max_frames 层
+    fn write(&self, out: &mut impl Write, max_frames: usize) -> fmt::Result {
+        writeln!(out, "KERNEL PANIC - {} {} ({})", version::NAME, version::VERSION, version::GIT_HASH)?;
+        writeln!(out, "{}", self.info)?;
+        writeln!(out)?;
+        if let Some(fault) = self.fault {
+            write!(out, "{} at rip={:#018x}", fault.name, fault.rip)?;
+            if let Some(symbol) = symbols::resolve(fault.rip as usize) {
+                write!(out, " {}", symbol)?;
+            }
+            writeln!(out)?;
+            write!(out, "  rsp={:#018x} rflags={:#010x} cs={:#06x} ss={:#06x}",
+                   fault.rsp, fault.rflags, fault.cs, fault.ss)?;
+            if let Some(code) = fault.error_code {
+                write!(out, " error={:#x}", code)?;
+            }
+            writeln!(out)?;
+            write!(out, "  on cpu{}", fault.cpu)?;
+            match (fault.program, fault.checkpoint) {
+                (Some(program), _) => writeln!(out, " running user program {}", program)?,
+                (None, Some(location)) => writeln!(out, " in the kernel, last checkpoint {}", location)?,
+                (None, None) => writeln!(out, " in the kernel")?,
+            }
+        }
+        let r = &self.registers;
+        writeln!(out, "registers:")?;
+        writeln!(out, "  rsp={:#018x} rbp={:#018x} rflags={:#010x}", r.rsp, r.rbp, r.rflags)?;
+        writeln!(out, "  cr0={:#010x} cr2={:#018x} cr3={:#018x} cr4={:#010x}", r.cr0, r.cr2, r.cr3, r.cr4)?;
+        writeln!(out, "backtrace:")?;
+        for (i, &address) in self.frames.iter().take(max_frames).enumerate() {
+            write_frame(out, i, address)?;
+        }
+        if self.frames.len() > max_frames {
+            writeln!(out, "  ... {} more on the serial port", self.frames.len() - max_frames)?;
+        }
+        writeln!(out)?;
+        write!(out, "System halted.")
+    }
+}
+
+/// panic 处理函数：输出到串口和屏幕后停机
+pub fn handle(info: &PanicInfo) -> ! {
+    interrupts::disable();
+    if PANICKING.swap(true, Ordering::SeqCst) {
+        let _ = writeln!(SerialStream, "panic while panicking: {}", info);
+        crate::hlt_loop();
+    }
+    let registers = Registers::capture();
+    let fault = FAULT.try_lock().and_then(|fault| *fault);
+    let mut frames = [0; MAX_FRAMES];
+    let count = collect_frames(&mut frames);
+    let report = Report { info, registers, fault, frames: &frames[..count] };
+
+    // 先写串口，画面出问题时至少还有这份
+    let _ = report.write(&mut SerialStream, MAX_FRAMES);
+    let _ = writeln!(SerialStream);
+    let _ = crashdump::write(&mut SerialStream, &report);
+    if steal(&*VIDEO_MODE).is_text() {
+        let mut writer = steal(&*vga_buffer::WRITER);
+        writer.clear_with_color(Color::White, Color::Blue);
+        let _ = report.write(&mut *writer, TEXT_MODE_FRAMES);
+    } else {
+        let mut gd = steal(&*GD);
+        let size = gd.size();
+        let _ = gd.fill_solid(&Rectangle::new(Point::zero(), size), BACKGROUND);
+        let _ = report.write(&mut Screen::new(&mut *gd), MAX_FRAMES);
+    }
+    crate::hlt_loop();
+}
+
+// 拿到锁；被 panic 的代码持有时强行解开
+fn steal<T>(mutex: &'static Mutex<T>) -> MutexGuard<'static, T> {
+    if let Some(guard) = mutex.try_lock() {
+        return guard;
+    }
+    unsafe { mutex.force_unlock() };
+    mutex.lock()
+}
+
+// 在显存上逐字符输出，自动换行，超出屏幕的部分丢弃
+struct Screen<'a> {
+    gd: &'a mut PhysicalWriter,
+    row: usize,
+    column: usize,
+    rows: usize,
+    columns: usize,
+}
+
+impl<'a> Screen<'a> {
+    fn new(gd: &'a mut PhysicalWriter) -> Self {
+        let (screen, glyph) = (OriginDimensions::size(&*gd), FONT_8X13.character_size);
+        Self {
+            gd,
+            row: 0,
+            column: 0,
+            rows: (screen.height as usize).saturating_sub(2 * MARGIN) / glyph.height as usize,
+            columns: (screen.width as usize).saturating_sub(2 * MARGIN) / glyph.width as usize,
+        }
+    }
+
+    fn put_char(&mut self, ch: char) {
+        if ch == '\n' || self.column >= self.columns {
+            self.row += 1;
+            self.column = 0;
+            if ch == '\n' {
+                return;
+            }
+        }
+        if self.row >= self.rows {
+            return;
+        }
+        let size = FONT_8X13.character_size;
+        let position = Point::new(
+            (MARGIN + self.column * size.width as usize) as i32,
+            (MARGIN + self.row * size.height as usize) as i32,
+        );
+        let mut buf = [0; 4];
+        let style = MonoTextStyle::new(&FONT_8X13, FOREGROUND);
+        let _ = Text::with_baseline(ch.encode_utf8(&mut buf), position, style, Baseline::Top).draw(&mut *self.gd);
+        self.column += 1;
+    }
+}
+
+impl Write for Screen<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        s.chars().for_each(|ch| self.put_char(ch));
+        Ok(())
+    }
+}
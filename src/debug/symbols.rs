@@ -0,0 +1,207 @@
+// 内核符号表
+// 打开 symbols 特性时，build.rs 从 KERNEL_SYMBOLS 指向的内核 ELF 里取出函数符号，还原成 Rust 的路径，
+// 压缩之后放进内核的 .ksyms 节，调用栈、异常报告和 GDB stub 里的地址就能显示成 函数名+偏移，shell 的 addr2line 命令也用它。
+// 符号表要等内核链接好才能生成，所以需要构建两次：
+//   cargo bootimage --features symbols
+//   KERNEL_SYMBOLS=target/x86_64-cjn_os/debug/cjn-os cargo bootimage --features symbols
+// build.rs 总是把表补齐到固定大小，两次构建的内核布局相同，第一次得到的地址在第二次仍然有效。
+// 表按地址排好序，名字只存和前一个不同的后缀，每 32 个符号一块，块的开头有索引，查找时先二分找到块再在块里顺序解码。
+// 没有打开特性时表是空的，只输出地址
+
+use core::fmt;
+use core::str;
+
+// 和 build.rs 里的一致
+#[cfg(feature = "symbols")]
+const CAPACITY: usize = 1024 * 1024;
+const MAX_NAME: usize = 255;
+// 魔数、符号数和块数
+const HEADER_SIZE: usize = 12;
+// 每块的索引：第一个符号的地址和块的偏移
+const INDEX_ENTRY_SIZE: usize = 12;
+
+#[cfg(feature = "symbols")]
+#[used]
+#[link_section = ".ksyms"]
+static KSYMS: [u8; CAPACITY] = *include_bytes!(concat!(env!("OUT_DIR"), "/ksyms.bin"));
+
+#[cfg(feature = "symbols")]
+fn table() -> &'static [u8] {
+    &KSYMS
+}
+
+#[cfg(not(feature = "symbols"))]
+fn table() -> &'static [u8] {
+    &[]
+}
+
+/// 一个地址解析出的函数名和相对函数开头的偏移
+#[derive(Clone)]
+pub struct Symbol {
+    name: [u8; MAX_NAME],
+    len: usize,
+    /// 函数的起始地址
+    pub start: usize,
+    pub offset: usize,
+}
+
+impl Symbol {
+    pub fn name(&self) -> &str {
+        // 截断的名字可能断在多字节字符中间
+        match str::from_utf8(&self.name[..self.len]) {
+            Ok(name) => name,
+            Err(error) => str::from_utf8(&self.name[..error.valid_up_to()]).unwrap_or(""),
+        }
+    }
+}
+
+/// 输出成 函数名+偏移
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}+{:#x}", self.name(), self.offset)
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Symbol({})", self)
+    }
+}
+
+// 拆开的表：块的索引和数据区
+struct Table {
+    index: &'static [u8],
+    data: &'static [u8],
+    count: usize,
+    blocks: usize,
+}
+
+impl Table {
+    // 格式不对时返回 None
+    fn open() -> Option<Table> {
+        let table = table();
+        if table.get(..4)? != b"KSYM" {
+            return None;
+        }
+        let count = u32::from_le_bytes(table.get(4..8)?.try_into().ok()?) as usize;
+        let blocks = u32::from_le_bytes(table.get(8..12)?.try_into().ok()?) as usize;
+        let index = table.get(HEADER_SIZE..HEADER_SIZE + blocks * INDEX_ENTRY_SIZE)?;
+        Some(Table { index, data: &table[HEADER_SIZE + index.len()..], count, blocks })
+    }
+
+    // 第 i 块第一个符号的地址
+    fn block_start(&self, i: usize) -> usize {
+        let entry = &self.index[i * INDEX_ENTRY_SIZE..];
+        u64::from_le_bytes(entry[..8].try_into().unwrap()) as usize
+    }
+
+    fn block_offset(&self, i: usize) -> usize {
+        let entry = &self.index[i * INDEX_ENTRY_SIZE + 8..];
+        u32::from_le_bytes(entry[..4].try_into().unwrap()) as usize
+    }
+
+    // 按地址顺序解码第 i 块，对每个符号调用 `f(地址, 名字)`，f 返回 false 时停下
+    fn decode(&self, i: usize, mut f: impl FnMut(usize, &[u8]) -> bool) -> Option<()> {
+        let end = if i + 1 < self.blocks { self.block_offset(i + 1) } else { self.data.len() };
+        let mut reader = Reader { data: self.data.get(self.block_offset(i)..end)?, position: 0 };
+        let mut name = [0; MAX_NAME];
+        let mut address = self.block_start(i);
+        while reader.position < reader.data.len() {
+            let delta = reader.leb128()? as usize;
+            let (shared, len) = (reader.leb128()? as usize, reader.leb128()? as usize);
+            // 最后一块后面是补齐用的 0，名字不会是空的
+            if shared + len == 0 || shared + len > MAX_NAME {
+                break;
+            }
+            address += delta;
+            name[shared..shared + len].copy_from_slice(reader.bytes(len)?);
+            if !f(address, &name[..shared + len]) {
+                break;
+            }
+        }
+        Some(())
+    }
+}
+
+/// 内核里是否嵌入了符号表
+pub fn available() -> bool {
+    count() > 0
+}
+
+/// 嵌入的符号个数
+pub fn count() -> usize {
+    Table::open().map_or(0, |table| table.count)
+}
+
+/// 地址所在的函数，找不到时返回 None
+pub fn resolve(address: usize) -> Option<Symbol> {
+    let table = Table::open()?;
+    // 二分找到最后一个起始地址不大于 address 的块
+    let (mut low, mut high) = (0, table.blocks);
+    while low < high {
+        let middle = (low + high) / 2;
+        if table.block_start(middle) <= address {
+            low = middle + 1;
+        } else {
+            high = middle;
+        }
+    }
+    let block = low.checked_sub(1)?;
+    let mut symbol = Symbol { name: [0; MAX_NAME], len: 0, start: 0, offset: 0 };
+    table.decode(block, |start, name| {
+        if start > address {
+            return false;
+        }
+        symbol.name[..name.len()].copy_from_slice(name);
+        symbol.len = name.len();
+        symbol.start = start;
+        true
+    })?;
+    symbol.offset = address - symbol.start;
+    Some(symbol)
+}
+
+/// 按完整的名字查找函数的起始地址，要逐块解码整张表
+pub fn lookup(name: &str) -> Option<usize> {
+    let table = Table::open()?;
+    let mut found = None;
+    for block in 0..table.blocks {
+        table.decode(block, |start, candidate| {
+            if candidate == name.as_bytes() {
+                found = Some(start);
+            }
+            found.is_none()
+        })?;
+        if found.is_some() {
+            break;
+        }
+    }
+    found
+}
+
+// 顺序读取 LEB128 整数和字节串
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn leb128(&mut self) -> Option<u64> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = *self.data.get(self.position)?;
+            self.position += 1;
+            value |= ((byte & 0x7F) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.position..self.position + len)?;
+        self.position += len;
+        Some(bytes)
+    }
+}
@@ -0,0 +1,200 @@
+// 看门狗和软死锁检测
+// 每个 CPU 一个喂狗记录：主循环每转一圈调用 feed，AP 开始执行任务时 feed、任务结束后 disarm。
+// 时钟中断每隔 CHECK_PERIOD 检查一次，喂过狗、之后超过 timeout 没再喂的 CPU 认为卡住了，报告最后一次喂狗的位置；
+// 卡住的是 BSP 时，时钟中断打断的正是卡住的代码，再附上被打断的指令地址和调用栈。
+// 报告写到串口并记进日志环形缓冲区，不走 log：卡住的代码可能正拿着控制台或图形的锁，在中断里上屏会死锁。
+// 打开 panic 后检测到卡住直接 panic，panic 画面上有被打断的位置和完整的调用栈。
+// 关着中断卡住时时钟中断进不来，要等中断重新打开才能报告
+
+use core::fmt::{self, Write};
+use core::panic::Location;
+use core::ptr::null_mut;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, Ordering};
+use core::time::Duration;
+
+use log::Level;
+use x86_64::structures::idt::InterruptStackFrame;
+
+use crate::debug::{self, symbols};
+use crate::io::qemu::SerialStream;
+use crate::io::timer::{self, uptime, TIMER_HZ};
+use crate::logger::ring;
+use crate::smp::percpu;
+
+// 能监视的 CPU 数，编号更大的 CPU 不监视
+const MAX_CPUS: usize = 16;
+// 检查间隔，时钟中断次数
+const CHECK_PERIOD: u64 = TIMER_HZ as u64 / 10;
+// 没有喂狗
+const DISARMED: u64 = u64::MAX;
+
+/// 默认的超时时间
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(20);
+
+struct Watch {
+    // 最后一次喂狗时的时钟中断次数
+    fed: AtomicU64,
+    location: AtomicPtr<Location<'static>>,
+    // 这次卡住已经报告过
+    reported: AtomicBool,
+}
+
+impl Watch {
+    const fn new() -> Self {
+        Self { fed: AtomicU64::new(DISARMED), location: AtomicPtr::new(null_mut()), reported: AtomicBool::new(false) }
+    }
+
+    fn location(&self) -> Option<&'static Location<'static>> {
+        unsafe { self.location.load(Ordering::Relaxed).as_ref() }
+    }
+}
+
+static WATCHES: [Watch; MAX_CPUS] = [const { Watch::new() }; MAX_CPUS];
+static ENABLED: AtomicBool = AtomicBool::new(true);
+static PANIC: AtomicBool = AtomicBool::new(false);
+static TIMEOUT: AtomicU64 = AtomicU64::new(DEFAULT_TIMEOUT.as_secs() * TIMER_HZ as u64);
+// 检测到卡住的次数
+static LOCKUPS: AtomicU64 = AtomicU64::new(0);
+
+/// 一个被监视的 CPU 的状态
+pub struct Status {
+    pub cpu: usize,
+    /// 距离最后一次喂狗的时间
+    pub since: Duration,
+    /// 最后一次喂狗的位置
+    pub location: Option<&'static Location<'static>>,
+}
+
+fn current() -> Option<&'static Watch> {
+    WATCHES.get(percpu::cpu_id())
+}
+
+fn ticks_to_duration(ticks: u64) -> Duration {
+    Duration::from_millis(ticks * 1000 / TIMER_HZ as u64)
+}
+
+/// 喂狗，当前 CPU 从现在开始受监视
+#[track_caller]
+pub fn feed() {
+    let Some(watch) = current() else { return };
+    watch.location.store(Location::caller() as *const _ as *mut _, Ordering::Relaxed);
+    watch.reported.store(false, Ordering::Relaxed);
+    watch.fed.store(timer::ticks(), Ordering::Release);
+}
+
+/// 当前 CPU 已经受监视时喂狗，用在 sleep 这类本身就要等很久的地方
+#[track_caller]
+pub fn touch() {
+    if current().is_some_and(|watch| watch.fed.load(Ordering::Acquire) != DISARMED) {
+        feed();
+    }
+}
+
+/// 当前 CPU 最后一次喂狗的位置，异常报告用它说明内核当时在做什么；没有受监视时为 None
+pub fn checkpoint() -> Option<&'static Location<'static>> {
+    current().filter(|watch| watch.fed.load(Ordering::Acquire) != DISARMED).and_then(Watch::location)
+}
+
+/// 当前 CPU 不再受监视，比如 AP 执行完任务回去等待
+pub fn disarm() {
+    if let Some(watch) = current() {
+        watch.fed.store(DISARMED, Ordering::Release);
+    }
+}
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// 检测到卡住时 panic
+pub fn set_panic(panic: bool) {
+    PANIC.store(panic, Ordering::Relaxed);
+}
+
+pub fn panics() -> bool {
+    PANIC.load(Ordering::Relaxed)
+}
+
+/// 设置超时时间，不短于检查间隔
+pub fn set_timeout(timeout: Duration) {
+    let ticks = timeout.as_millis() as u64 * TIMER_HZ as u64 / 1000;
+    TIMEOUT.store(ticks.max(CHECK_PERIOD), Ordering::Relaxed);
+}
+
+pub fn timeout() -> Duration {
+    ticks_to_duration(TIMEOUT.load(Ordering::Relaxed))
+}
+
+/// 启动以来检测到卡住的次数
+pub fn lockups() -> u64 {
+    LOCKUPS.load(Ordering::Relaxed)
+}
+
+/// 受监视的 CPU
+pub fn status() -> impl Iterator<Item = Status> {
+    let now = timer::ticks();
+    WATCHES.iter().enumerate().filter_map(move |(cpu, watch)| {
+        let fed = watch.fed.load(Ordering::Acquire);
+        let since = ticks_to_duration(now.saturating_sub(fed));
+        (fed != DISARMED).then(|| Status { cpu, since, location: watch.location() })
+    })
+}
+
+/// 由 BSP 的时钟中断处理函数调用，`frame` 是被打断的代码
+pub fn check(frame: &InterruptStackFrame) {
+    let now = timer::ticks();
+    if now % CHECK_PERIOD != 0 || !is_enabled() {
+        return;
+    }
+    let timeout = TIMEOUT.load(Ordering::Relaxed);
+    for (cpu, watch) in WATCHES.iter().enumerate() {
+        let fed = watch.fed.load(Ordering::Acquire);
+        if fed == DISARMED || now.saturating_sub(fed) < timeout || watch.reported.swap(true, Ordering::Relaxed) {
+            continue;
+        }
+        LOCKUPS.fetch_add(1, Ordering::Relaxed);
+        let lockup = Lockup { cpu, stuck: ticks_to_duration(now - fed), location: watch.location() };
+        if panics() {
+            debug::panic::record_fault("SOFT LOCKUP", frame, None);
+            panic!("{}", lockup);
+        }
+        report(lockup, (cpu == percpu::cpu_id()).then_some(frame));
+    }
+}
+
+// 报告的第一行
+struct Lockup {
+    cpu: usize,
+    stuck: Duration,
+    location: Option<&'static Location<'static>>,
+}
+
+impl fmt::Display for Lockup {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "soft lockup: cpu{} stuck for {}s", self.cpu, self.stuck.as_secs())?;
+        match self.location {
+            Some(location) => write!(f, ", last fed at {}", location),
+            None => Ok(()),
+        }
+    }
+}
+
+// `frame` 是卡住的 CPU 被打断的位置，卡住的是别的 CPU 时为 None
+fn report(lockup: Lockup, frame: Option<&InterruptStackFrame>) {
+    let mut out = SerialStream;
+    let _ = writeln!(out, "{}", lockup);
+    ring::push(Level::Warn, uptime(), module_path!(), format_args!("{}", lockup));
+    let Some(frame) = frame else { return };
+    let rip = frame.instruction_pointer.as_u64() as usize;
+    let _ = write!(out, "  interrupted at {:#018x}", rip);
+    if let Some(symbol) = symbols::resolve(rip) {
+        let _ = write!(out, " {}", symbol);
+    }
+    let _ = writeln!(out);
+    // 从中断处理函数的栈帧往上走，经过被打断的代码
+    let _ = debug::backtrace(&mut out);
+}
@@ -0,0 +1,259 @@
+// Intel AC'97 声卡
+// QEMU 的 -device AC97 模拟的是 ICH 里的 AC'97 控制器。BAR0 是混音器（NAM）的 I/O 端口，BAR1 是总线主控（NABM）的 I/O 端口。
+// 只用 PCM 输出：32 个缓冲区组成一个环，缓冲区描述符表（BDL）和缓冲区都用 dma::alloc_coherent 分配。
+// write 把样本填进 LVI（最后一个有效的缓冲区）后面的空缓冲区再把 LVI 往后移，设备播完 LVI 那个缓冲区就停下，再写 LVI 时接着播。
+// 不用中断，sound::poll 空闲时补充样本。采样率固定为 48 kHz，不打开可变采样率，转换由 sound 负责
+
+use alloc::boxed::Box;
+use core::ptr;
+use core::sync::atomic::{fence, Ordering};
+use core::time::Duration;
+
+use crate::drivers::pci::PciDriver;
+use crate::io::pci::{DeviceMatch, PciDevice};
+use crate::io::port::{self, ClaimError, Port, PortValue};
+use crate::io::timer::uptime;
+use crate::memory::dma::{self, DmaBuffer, MASK_32};
+use crate::sound::{self, AudioDevice};
+
+// 混音器寄存器
+const NAM_RESET: u16 = 0x00;
+const NAM_MASTER_VOLUME: u16 = 0x02;
+const NAM_PCM_OUT_VOLUME: u16 = 0x18;
+// 音量 0 是最大，PCM 输出的 0x0808 是 0 dB
+const VOLUME_MAX: u16 = 0x0000;
+const VOLUME_0DB: u16 = 0x0808;
+
+// 总线主控寄存器，PCM 输出的那一组在 0x10
+const PO_BDBAR: u16 = 0x10;
+const PO_CIV: u16 = 0x14;
+const PO_LVI: u16 = 0x15;
+const PO_SR: u16 = 0x16;
+const PO_PICB: u16 = 0x18;
+const PO_CR: u16 = 0x1B;
+const GLOB_CNT: u16 = 0x2C;
+const GLOB_STA: u16 = 0x30;
+
+// 运行、复位这一组寄存器
+const CR_RPBM: u8 = 1 << 0;
+const CR_RR: u8 = 1 << 1;
+// 已经停下；第 2 到 4 位是中断状态，写 1 清零
+const SR_DCH: u16 = 1 << 0;
+const SR_CLEAR: u16 = 0x1C;
+// 解除冷复位
+const GLOB_CNT_COLD_RESET: u32 = 1 << 1;
+// 主编解码器就绪
+const GLOB_STA_CODEC_READY: u32 = 1 << 8;
+
+const BUFFERS: usize = 32;
+const DESCRIPTOR_SIZE: usize = 8;
+// 每个缓冲区的样本数（左右声道各算一个），大约 21 毫秒
+const BUFFER_SAMPLES: usize = 2048;
+const BUFFER_LEN: usize = BUFFER_SAMPLES * 2;
+const RATE: u32 = 48000;
+const RESET_TIMEOUT: Duration = Duration::from_millis(100);
+// 两组寄存器占用的端口数
+const NAM_PORTS: u16 = 0x100;
+const NABM_PORTS: u16 = 0x40;
+
+pub static DRIVER: PciDriver = PciDriver {
+    name: "ac97",
+    matches: &[DeviceMatch::Class { class: 0x04, subclass: 0x01, prog_if: None }],
+    probe,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ac97Error {
+    /// BAR0 或者 BAR1 不是 I/O 端口
+    NoPorts,
+    /// 分配不到缓冲区
+    NoMemory,
+    /// 复位没有完成
+    ResetTimeout,
+    /// 已经有一块声卡了
+    Busy,
+    /// 端口被别的驱动占用
+    Ports(ClaimError),
+}
+
+fn probe(device: &PciDevice) -> bool {
+    match Ac97::new(device) {
+        Ok(card) => sound::attach(Box::new(card)),
+        Err(error) => {
+            log::warn!("ac97: initialization failed: {:?}", error);
+            false
+        }
+    }
+}
+
+#[repr(C)]
+struct BufferDescriptor {
+    addr: u32,
+    samples: u16,
+    flags: u16,
+}
+
+pub struct Ac97 {
+    mixer: u16,
+    bus: u16,
+    descriptors: DmaBuffer,
+    buffers: DmaBuffer,
+    // 下一个要填的缓冲区
+    next: usize,
+    started: bool,
+}
+
+impl Ac97 {
+    fn new(device: &PciDevice) -> Result<Self, Ac97Error> {
+        if sound::device_name().is_some() {
+            return Err(Ac97Error::Busy);
+        }
+        let (mixer, bus) = (device.bar(0), device.bar(1));
+        if mixer & 1 == 0 || bus & 1 == 0 {
+            return Err(Ac97Error::NoPorts);
+        }
+        let alloc = |size: usize| dma::alloc_coherent(device, size, DESCRIPTOR_SIZE, MASK_32).map_err(|_| Ac97Error::NoMemory);
+        let descriptors = alloc(BUFFERS * DESCRIPTOR_SIZE)?;
+        let buffers = alloc(BUFFERS * BUFFER_LEN)?;
+        device.enable_bus_master();
+
+        let (mixer, bus) = ((mixer & !0x3) as u16, (bus & !0x3) as u16);
+        port::claim("ac97", mixer, NAM_PORTS).map_err(Ac97Error::Ports)?;
+        if let Err(error) = port::claim("ac97", bus, NABM_PORTS) {
+            port::release(mixer);
+            return Err(Ac97Error::Ports(error));
+        }
+        // 从这里开始出错时由 drop 释放端口
+        let card = Ac97 { mixer, bus, descriptors, buffers, next: 0, started: false };
+        unsafe {
+            card.nabm(GLOB_CNT).write(GLOB_CNT_COLD_RESET);
+        }
+        wait(|| unsafe { card.nabm::<u32>(GLOB_STA).read() } & GLOB_STA_CODEC_READY != 0)?;
+        unsafe {
+            // 写任意值复位混音器，之后打开音量
+            card.nam(NAM_RESET).write(0u16);
+            card.nam(NAM_MASTER_VOLUME).write(VOLUME_MAX);
+            card.nam(NAM_PCM_OUT_VOLUME).write(VOLUME_0DB);
+        }
+        card.reset()?;
+        Ok(card)
+    }
+
+    // 混音器寄存器
+    fn nam<T: PortValue>(&self, offset: u16) -> Port<T> {
+        Port::new(self.mixer + offset)
+    }
+
+    // 总线主控寄存器
+    fn nabm<T: PortValue>(&self, offset: u16) -> Port<T> {
+        Port::new(self.bus + offset)
+    }
+
+    // 停下并复位 PCM 输出，重新设置描述符表的地址
+    fn reset(&self) -> Result<(), Ac97Error> {
+        unsafe {
+            self.nabm(PO_CR).write(0u8);
+            self.nabm(PO_CR).write(CR_RR);
+        }
+        wait(|| unsafe { self.nabm::<u8>(PO_CR).read() } & CR_RR == 0)?;
+        unsafe {
+            self.nabm(PO_SR).write(SR_CLEAR);
+            self.nabm(PO_BDBAR).write(self.descriptors.bus().as_u64() as u32);
+        }
+        Ok(())
+    }
+
+    // 设备播到最后一个有效的缓冲区停下了，或者还没开始
+    fn halted(&self) -> bool {
+        !self.started || unsafe { self.nabm::<u16>(PO_SR).read() } & SR_DCH != 0
+    }
+
+    // 排着队的缓冲区数，包括正在播放的那个
+    fn queued_buffers(&self) -> usize {
+        if self.halted() {
+            return 0;
+        }
+        let (civ, lvi) = unsafe { (self.nabm::<u8>(PO_CIV).read() as usize, self.nabm::<u8>(PO_LVI).read() as usize) };
+        (lvi + BUFFERS - civ) % BUFFERS + 1
+    }
+}
+
+// 等到 `done` 成立
+fn wait(done: impl Fn() -> bool) -> Result<(), Ac97Error> {
+    let deadline = uptime() + RESET_TIMEOUT;
+    while !done() {
+        if uptime() >= deadline {
+            return Err(Ac97Error::ResetTimeout);
+        }
+        core::hint::spin_loop();
+    }
+    Ok(())
+}
+
+impl AudioDevice for Ac97 {
+    fn name(&self) -> &'static str {
+        DRIVER.name
+    }
+
+    fn rate(&self) -> u32 {
+        RATE
+    }
+
+    fn write(&mut self, samples: &[i16]) -> usize {
+        let mut written = 0;
+        let mut free = BUFFERS - self.queued_buffers();
+        while written < samples.len() && free > 0 {
+            let chunk = &samples[written..(written + BUFFER_SAMPLES).min(samples.len())];
+            let index = self.next;
+            let buffer = &self.buffers;
+            let descriptor = BufferDescriptor {
+                addr: buffer.bus().as_u64() as u32 + (index * BUFFER_LEN) as u32,
+                samples: chunk.len() as u16,
+                flags: 0,
+            };
+            unsafe {
+                ptr::copy_nonoverlapping(chunk.as_ptr(), buffer.ptr().add(index * BUFFER_LEN).cast::<i16>(), chunk.len());
+                ptr::write_volatile(self.descriptors.ptr().cast::<BufferDescriptor>().add(index), descriptor);
+            }
+            // 先写好缓冲区和描述符，设备才能看到新的 LVI
+            fence(Ordering::SeqCst);
+            unsafe {
+                self.nabm(PO_LVI).write(index as u8);
+                // 第一次要打开 DMA；之后停在旧的 LVI 上时，写新的 LVI 就会接着播
+                if !self.started {
+                    self.nabm(PO_CR).write(CR_RPBM);
+                }
+            }
+            self.started = true;
+            self.next = (index + 1) % BUFFERS;
+            written += chunk.len();
+            free -= 1;
+        }
+        written
+    }
+
+    fn queued(&self) -> usize {
+        match self.queued_buffers() {
+            0 => 0,
+            // 正在播放的缓冲区还剩 PICB 个样本，后面的按满的算
+            buffers => (buffers - 1) * BUFFER_SAMPLES + unsafe { self.nabm::<u16>(PO_PICB).read() } as usize,
+        }
+    }
+
+    fn stop(&mut self) {
+        if let Err(error) = self.reset() {
+            log::warn!("ac97: reset failed: {:?}", error);
+        }
+        self.next = 0;
+        self.started = false;
+    }
+}
+
+impl Drop for Ac97 {
+    // 先让设备停下来，之后才能释放缓冲区和端口
+    fn drop(&mut self) {
+        unsafe { self.nabm(PO_CR).write(0u8) };
+        port::release(self.mixer);
+        port::release(self.bus);
+    }
+}
@@ -0,0 +1,42 @@
+// ACPI 表
+// 在 BIOS 只读区中找到 RSDP，再通过 RSDT 按签名查找其他表（HPET、MADT 等）。
+// 表都在物理内存里，通过 bootloader 建立的物理内存整体映射读取
+
+use core::ptr::read_unaligned;
+use x86_64::PhysAddr;
+
+use crate::memory::phys_to_virt;
+
+// RSDP 在 BIOS 只读区中按 16 字节对齐存放
+const BIOS_AREA_START: u64 = 0xE0000;
+const BIOS_AREA_END: u64 = 0x100000;
+
+/// 所有表共有的表头长度
+pub const HEADER_SIZE: u64 = 36;
+
+/// 读取物理内存中的数据，ACPI 表不保证对齐
+pub fn read_phys<T: Copy>(addr: u64) -> T {
+    unsafe { read_unaligned(phys_to_virt(PhysAddr::new(addr)).as_ptr::<T>()) }
+}
+
+/// 按签名查找表，返回表头的物理地址
+pub fn find_table(signature: &[u8; 4]) -> Option<u64> {
+    let rsdp = (BIOS_AREA_START..BIOS_AREA_END)
+        .step_by(16)
+        .find(|&addr| read_phys::<[u8; 8]>(addr) == *b"RSD PTR ")?;
+    let rsdt = read_phys::<u32>(rsdp + 16) as u64;
+    if read_phys::<[u8; 4]>(rsdt) != *b"RSDT" {
+        return None;
+    }
+
+    // 表头之后是 32 位的表指针数组
+    let entries = (table_length(rsdt) - HEADER_SIZE) / 4;
+    (0..entries)
+        .map(|i| read_phys::<u32>(rsdt + HEADER_SIZE + i * 4) as u64)
+        .find(|&table| read_phys::<[u8; 4]>(table) == *signature)
+}
+
+/// 表的总长度，包括表头
+pub fn table_length(table: u64) -> u64 {
+    read_phys::<u32>(table + 4) as u64
+}
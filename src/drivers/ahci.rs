@@ -0,0 +1,354 @@
+// AHCI SATA 控制器
+// 按 PCI 类别码（大容量存储 / SATA / AHCI）找到控制器，寄存器（ABAR）在 BAR5 里，用 memory::map_mmio 映射。
+// 每个接了 SATA 硬盘的端口是一个块设备，按顺序叫 sata0、sata1……，交给 drivers::block。
+// 每个端口只用 0 号命令槽：命令列表、接收 FIS 区和命令表放在同一页 DMA 内存里，数据先经过一块物理连续的中转缓冲区，
+// 读写用 READ/WRITE DMA EXT，一条命令最多传中转缓冲区那么多。命令同步执行，轮询 PxCI 等它完成，不用中断。
+// 只支持 512 字节扇区的硬盘，光驱（ATAPI）和端口倍增器不支持。
+// QEMU 里这样接一块硬盘：
+// -device ahci,id=ahci -drive id=disk,file=disk.img,if=none,format=raw -device ide-hd,drive=disk,bus=ahci.0
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use core::ptr;
+use core::sync::atomic::{fence, AtomicUsize, Ordering};
+use core::time::Duration;
+
+use x86_64::{PhysAddr, VirtAddr};
+
+use crate::drivers::block::{self, BlockDevice, BlockError};
+use crate::drivers::pci::PciDriver;
+use crate::io::pci::{DeviceMatch, PciDevice};
+use crate::io::timer::uptime;
+use crate::memory::{self, dma::{self, DmaBuffer, MASK_32, MASK_64}};
+
+// 通用寄存器和 32 个端口的寄存器
+const ABAR_SIZE: u64 = 0x100 + 0x80 * 32;
+const CAP: usize = 0x00;
+const GHC: usize = 0x04;
+const PI: usize = 0x0C;
+// 支持 64 位地址
+const CAP_S64A: u32 = 1 << 31;
+const GHC_IE: u32 = 1 << 1;
+const GHC_AE: u32 = 1 << 31;
+
+// 端口寄存器的偏移，相对于端口的起始地址
+const PORT_BASE: usize = 0x100;
+const PORT_SIZE: usize = 0x80;
+const PX_CLB: usize = 0x00;
+const PX_CLBU: usize = 0x04;
+const PX_FB: usize = 0x08;
+const PX_FBU: usize = 0x0C;
+const PX_IS: usize = 0x10;
+const PX_IE: usize = 0x14;
+const PX_CMD: usize = 0x18;
+const PX_TFD: usize = 0x20;
+const PX_SIG: usize = 0x24;
+const PX_SSTS: usize = 0x28;
+const PX_SERR: usize = 0x30;
+const PX_CI: usize = 0x38;
+
+const CMD_ST: u32 = 1 << 0;
+const CMD_FRE: u32 = 1 << 4;
+const CMD_FR: u32 = 1 << 14;
+const CMD_CR: u32 = 1 << 15;
+// 任务文件里的错误、忙、请求数据
+const TFD_ERR: u32 = 1 << 0;
+const TFD_DRQ: u32 = 1 << 3;
+const TFD_BSY: u32 = 1 << 7;
+// 中断状态里的任务文件错误
+const IS_TFES: u32 = 1 << 30;
+// SSTS 的设备检测字段：有设备并且已经建立通信
+const SSTS_DET_PRESENT: u32 = 3;
+// 硬盘的签名
+const SIG_ATA: u32 = 0x0000_0101;
+
+// 一页 DMA 内存里的布局：命令列表要 1 KiB 对齐，接收 FIS 区 256 字节对齐，命令表 128 字节对齐
+const COMMAND_LIST: usize = 0x000;
+const RECEIVED_FIS: usize = 0x400;
+const COMMAND_TABLE: usize = 0x500;
+const PRDT: usize = 0x80;
+const PORT_MEMORY: usize = 4096;
+const PORT_MEMORY_ALIGN: usize = 1024;
+
+const FIS_TYPE_H2D: u8 = 0x27;
+// H2D FIS 的第二个字节：这是一条命令而不是控制寄存器的更新
+const FIS_COMMAND: u8 = 1 << 7;
+const FIS_LEN_DWORDS: u32 = 5;
+// 命令头里数据方向是写（主机到设备）
+const HEADER_WRITE: u32 = 1 << 6;
+// LBA 寻址
+const DEVICE_LBA: u8 = 1 << 6;
+
+const ATA_READ_DMA_EXT: u8 = 0x25;
+const ATA_WRITE_DMA_EXT: u8 = 0x35;
+const ATA_IDENTIFY: u8 = 0xEC;
+
+const SECTOR_SIZE: usize = 512;
+// 中转缓冲区的大小，也就是一条命令最多读写的字节数
+const BOUNCE_LEN: usize = 64 * 1024;
+const STOP_TIMEOUT: Duration = Duration::from_millis(500);
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub static DRIVER: PciDriver = PciDriver {
+    name: "ahci",
+    matches: &[DeviceMatch::Class { class: 0x01, subclass: 0x06, prog_if: Some(0x01) }],
+    probe,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AhciError {
+    /// BAR5 不是内存空间
+    NoMmio,
+    /// 寄存器映射不上
+    MapFailed,
+    /// 分配不到命令列表或者中转缓冲区
+    NoMemory,
+    /// 端口停不下来，或者命令没有在规定时间内完成
+    Timeout,
+    /// 设备报告了错误
+    DeviceError,
+}
+
+impl From<AhciError> for BlockError {
+    fn from(error: AhciError) -> Self {
+        match error {
+            AhciError::Timeout => BlockError::Timeout,
+            _ => BlockError::Io,
+        }
+    }
+}
+
+// 已经找到的硬盘数，用来起名
+static DISKS: AtomicUsize = AtomicUsize::new(0);
+
+fn probe(device: &PciDevice) -> bool {
+    let bar = device.bar(5);
+    if bar & 1 != 0 {
+        log::warn!("ahci: initialization failed: {:?}", AhciError::NoMmio);
+        return false;
+    }
+    let Ok(region) = memory::map_mmio(PhysAddr::new((bar & !0xF) as u64), ABAR_SIZE) else {
+        log::warn!("ahci: initialization failed: {:?}", AhciError::MapFailed);
+        return false;
+    };
+    let hba = Registers { base: region.start() };
+    device.enable_bus_master();
+    // 切到 AHCI 模式，不用中断
+    hba.write(GHC, (hba.read(GHC) | GHC_AE) & !GHC_IE);
+    let mask = if hba.read(CAP) & CAP_S64A != 0 { MASK_64 } else { MASK_32 };
+
+    let implemented = hba.read(PI);
+    for port in (0..32).filter(|port| implemented & (1 << port) != 0) {
+        let registers = Registers { base: hba.base + PORT_BASE + PORT_SIZE * port };
+        if !registers.has_disk() {
+            continue;
+        }
+        match Disk::new(device, registers, mask) {
+            Ok(disk) => block::attach(Box::new(disk)),
+            Err(error) => log::warn!("ahci: port {}: {:?}", port, error),
+        }
+    }
+    true
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Registers {
+    base: VirtAddr,
+}
+
+impl Registers {
+    fn read(&self, offset: usize) -> u32 {
+        unsafe { ptr::read_volatile((self.base + offset).as_ptr()) }
+    }
+
+    fn write(&self, offset: usize, value: u32) {
+        unsafe { ptr::write_volatile((self.base + offset).as_mut_ptr(), value) }
+    }
+
+    // 端口上接了已经建立通信的硬盘
+    fn has_disk(&self) -> bool {
+        self.read(PX_SSTS) & 0xF == SSTS_DET_PRESENT && self.read(PX_SIG) == SIG_ATA
+    }
+
+    // 等到 `offset` 寄存器里的 `bits` 都清零
+    fn wait_clear(&self, offset: usize, bits: u32, timeout: Duration) -> Result<(), AhciError> {
+        let deadline = uptime() + timeout;
+        while self.read(offset) & bits != 0 {
+            if uptime() >= deadline {
+                return Err(AhciError::Timeout);
+            }
+            core::hint::spin_loop();
+        }
+        Ok(())
+    }
+
+    // 停止处理命令列表和接收 FIS，之后才能修改它们的地址或者释放内存
+    fn stop(&self) -> Result<(), AhciError> {
+        self.write(PX_CMD, self.read(PX_CMD) & !CMD_ST);
+        self.wait_clear(PX_CMD, CMD_CR, STOP_TIMEOUT)?;
+        self.write(PX_CMD, self.read(PX_CMD) & !CMD_FRE);
+        self.wait_clear(PX_CMD, CMD_FR, STOP_TIMEOUT)
+    }
+}
+
+// 命令列表里的一项
+#[repr(C)]
+struct CommandHeader {
+    // 低 5 位是 FIS 的长度（双字数），高 16 位是 PRDT 的项数
+    flags: u32,
+    // 设备实际传输的字节数
+    transferred: u32,
+    table: u64,
+    reserved: [u32; 4],
+}
+
+// 物理区域描述符表里的一项
+#[repr(C)]
+struct PrdtEntry {
+    addr: u64,
+    reserved: u32,
+    // 字节数减一
+    count: u32,
+}
+
+pub struct Disk {
+    name: String,
+    model: String,
+    registers: Registers,
+    memory: DmaBuffer,
+    bounce: DmaBuffer,
+    sectors: u64,
+}
+
+impl Disk {
+    fn new(device: &PciDevice, registers: Registers, mask: u64) -> Result<Self, AhciError> {
+        let memory = dma::alloc_coherent(device, PORT_MEMORY, PORT_MEMORY_ALIGN, mask).map_err(|_| AhciError::NoMemory)?;
+        let bounce = dma::alloc_coherent(device, BOUNCE_LEN, SECTOR_SIZE, mask).map_err(|_| AhciError::NoMemory)?;
+        registers.stop()?;
+        let command_list = memory.bus().as_u64() + COMMAND_LIST as u64;
+        let received_fis = memory.bus().as_u64() + RECEIVED_FIS as u64;
+        registers.write(PX_CLB, command_list as u32);
+        registers.write(PX_CLBU, (command_list >> 32) as u32);
+        registers.write(PX_FB, received_fis as u32);
+        registers.write(PX_FBU, (received_fis >> 32) as u32);
+        // 清掉已有的错误和中断状态，写 1 清零
+        registers.write(PX_SERR, u32::MAX);
+        registers.write(PX_IS, u32::MAX);
+        registers.write(PX_IE, 0);
+        registers.write(PX_CMD, registers.read(PX_CMD) | CMD_FRE);
+        registers.wait_clear(PX_TFD, TFD_BSY | TFD_DRQ, COMMAND_TIMEOUT)?;
+        registers.write(PX_CMD, registers.read(PX_CMD) | CMD_ST);
+
+        let mut disk = Disk { name: String::new(), model: String::new(), registers, memory, bounce, sectors: 0 };
+        disk.identify()?;
+        disk.name = format!("sata{}", DISKS.fetch_add(1, Ordering::Relaxed));
+        Ok(disk)
+    }
+
+    // 读出型号和扇区数
+    fn identify(&mut self) -> Result<(), AhciError> {
+        self.command(ATA_IDENTIFY, 0, 0, SECTOR_SIZE, false)?;
+        let data = self.bounce.as_slice();
+        let word = |i: usize| u16::from_le_bytes([data[2 * i], data[2 * i + 1]]);
+        // 型号在第 27 到 46 个字，每个字里的两个字符是反的
+        let model: String = (27..47).flat_map(|i| word(i).to_be_bytes()).map(char::from).collect();
+        self.model = String::from(model.trim());
+        // 第 83 个字的第 10 位表示支持 48 位 LBA，扇区数在第 100 到 103 个字，否则在第 60、61 个字
+        self.sectors = if word(83) & (1 << 10) != 0 {
+            (100..104).rev().fold(0, |sectors, i| sectors << 16 | word(i) as u64)
+        } else {
+            (word(61) as u64) << 16 | word(60) as u64
+        };
+        Ok(())
+    }
+
+    // 用 0 号命令槽执行一条命令，数据经过中转缓冲区的前 `len` 字节
+    fn command(&mut self, command: u8, lba: u64, sectors: u16, len: usize, write: bool) -> Result<(), AhciError> {
+        let registers = self.registers;
+        registers.wait_clear(PX_TFD, TFD_BSY | TFD_DRQ, COMMAND_TIMEOUT)?;
+
+        let table = self.memory.bus().as_u64() + COMMAND_TABLE as u64;
+        let flags = FIS_LEN_DWORDS | if write { HEADER_WRITE } else { 0 } | 1 << 16;
+        let header = CommandHeader { flags, transferred: 0, table, reserved: [0; 4] };
+        let entry = PrdtEntry { addr: self.bounce.bus().as_u64(), reserved: 0, count: len as u32 - 1 };
+        let lba = lba.to_le_bytes();
+        let count = sectors.to_le_bytes();
+        let fis: [u8; 20] = [
+            FIS_TYPE_H2D, FIS_COMMAND, command, 0,
+            lba[0], lba[1], lba[2], DEVICE_LBA,
+            lba[3], lba[4], lba[5], 0,
+            count[0], count[1], 0, 0,
+            0, 0, 0, 0,
+        ];
+        unsafe {
+            let memory = self.memory.ptr();
+            ptr::write_volatile(memory.add(COMMAND_LIST).cast::<CommandHeader>(), header);
+            ptr::copy_nonoverlapping(fis.as_ptr(), memory.add(COMMAND_TABLE), fis.len());
+            ptr::write_volatile(memory.add(COMMAND_TABLE + PRDT).cast::<PrdtEntry>(), entry);
+        }
+        // 先写好命令，设备才能看到发出的命令
+        fence(Ordering::SeqCst);
+        registers.write(PX_IS, u32::MAX);
+        registers.write(PX_CI, 1);
+
+        let deadline = uptime() + COMMAND_TIMEOUT;
+        while registers.read(PX_CI) & 1 != 0 {
+            if registers.read(PX_IS) & IS_TFES != 0 {
+                return Err(AhciError::DeviceError);
+            }
+            if uptime() >= deadline {
+                return Err(AhciError::Timeout);
+            }
+            core::hint::spin_loop();
+        }
+        fence(Ordering::SeqCst);
+        if registers.read(PX_TFD) & TFD_ERR != 0 {
+            return Err(AhciError::DeviceError);
+        }
+        Ok(())
+    }
+}
+
+impl BlockDevice for Disk {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn block_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        self.sectors
+    }
+
+    fn read_blocks(&mut self, lba: u64, buffer: &mut [u8]) -> Result<(), BlockError> {
+        for (i, chunk) in buffer.chunks_mut(BOUNCE_LEN).enumerate() {
+            let lba = lba + (i * BOUNCE_LEN / SECTOR_SIZE) as u64;
+            self.command(ATA_READ_DMA_EXT, lba, (chunk.len() / SECTOR_SIZE) as u16, chunk.len(), false)?;
+            chunk.copy_from_slice(&self.bounce.as_slice()[..chunk.len()]);
+        }
+        Ok(())
+    }
+
+    fn write_blocks(&mut self, lba: u64, data: &[u8]) -> Result<(), BlockError> {
+        for (i, chunk) in data.chunks(BOUNCE_LEN).enumerate() {
+            let lba = lba + (i * BOUNCE_LEN / SECTOR_SIZE) as u64;
+            self.bounce.as_mut_slice()[..chunk.len()].copy_from_slice(chunk);
+            self.command(ATA_WRITE_DMA_EXT, lba, (chunk.len() / SECTOR_SIZE) as u16, chunk.len(), true)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Disk {
+    // 先让端口停下来，之后才能释放命令列表和中转缓冲区
+    fn drop(&mut self) {
+        let _ = self.registers.stop();
+    }
+}
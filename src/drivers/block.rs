@@ -0,0 +1,152 @@
+// 块设备
+// 磁盘驱动实现 BlockDevice 并用 attach 交给这里，按名字（比如 sata0）取用。
+// read / write 先检查长度和范围再交给驱动，驱动只需要处理整块、不越界的请求。
+// 每个设备有自己的锁，分区（fs::partition）持有整个磁盘的 Device，读写时转给它
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+
+use spin::Mutex;
+
+use crate::boot::Unit;
+use crate::drivers::{ahci, pci};
+
+pub trait BlockDevice: Send {
+    /// 设备名，比如 sata0
+    fn name(&self) -> &str;
+    /// 型号，用于显示
+    fn model(&self) -> &str;
+    /// 每块的字节数
+    fn block_size(&self) -> usize;
+    /// 总块数
+    fn block_count(&self) -> u64;
+    /// 从第 `lba` 块开始读满 `buffer`，长度是块大小的整数倍
+    fn read_blocks(&mut self, lba: u64, buffer: &mut [u8]) -> Result<(), BlockError>;
+    /// 从第 `lba` 块开始写入 `data`，长度是块大小的整数倍
+    fn write_blocks(&mut self, lba: u64, data: &[u8]) -> Result<(), BlockError>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockError {
+    /// 没有这个设备
+    NoDevice,
+    /// 长度不是块大小的整数倍
+    Unaligned,
+    /// 超出设备末尾
+    OutOfRange,
+    /// 设备报告了错误
+    Io,
+    /// 设备没有在规定时间内完成
+    Timeout,
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BlockError::NoDevice => "no such device",
+            BlockError::Unaligned => "length is not a multiple of the block size",
+            BlockError::OutOfRange => "beyond the end of the device",
+            BlockError::Io => "I/O error",
+            BlockError::Timeout => "timed out",
+        })
+    }
+}
+
+/// 块设备的信息
+pub struct Info {
+    pub name: String,
+    pub model: String,
+    pub block_size: usize,
+    pub block_count: u64,
+}
+
+/// 一个登记了的块设备
+pub type Device = Arc<Mutex<Box<dyn BlockDevice>>>;
+
+// 设备名和设备，按名字查找时不用锁住每个设备
+static DEVICES: Mutex<Vec<(String, Device)>> = Mutex::new(Vec::new());
+
+/// 注册磁盘驱动，需要在 memory::install_frame_allocator 之后调用，驱动要分配 DMA 内存
+pub fn init() {
+    pci::register(&ahci::DRIVER);
+}
+
+pub static UNIT: Unit = Unit {
+    name: "block",
+    depends: &["pci", "frame-pool"],
+    init: |_| {
+        init();
+        Ok(())
+    },
+};
+
+/// 驱动初始化好设备后调用
+pub fn attach(device: Box<dyn BlockDevice>) {
+    log::info!("block: {} attached, {} blocks of {} bytes", device.name(), device.block_count(), device.block_size());
+    DEVICES.lock().push((String::from(device.name()), Arc::new(Mutex::new(device))));
+}
+
+fn info(device: &dyn BlockDevice) -> Info {
+    Info {
+        name: String::from(device.name()),
+        model: String::from(device.model()),
+        block_size: device.block_size(),
+        block_count: device.block_count(),
+    }
+}
+
+pub fn list() -> Vec<Info> {
+    // 先复制出来再逐个加锁，不在持有登记表的锁时等待设备
+    let devices: Vec<Device> = DEVICES.lock().iter().map(|(_, device)| device.clone()).collect();
+    devices.iter().map(|device| info(device.lock().as_ref())).collect()
+}
+
+/// 名为 `name` 的设备
+pub fn get(name: &str) -> Option<Device> {
+    DEVICES.lock().iter().find(|(device, _)| device == name).map(|(_, device)| device.clone())
+}
+
+/// 名为 `name` 的设备的信息
+pub fn find(name: &str) -> Option<Info> {
+    get(name).map(|device| info(device.lock().as_ref()))
+}
+
+impl Info {
+    /// 检查从第 `lba` 块开始、长 `len` 字节的请求：长度要是块大小的整数倍，并且不超出设备末尾
+    pub fn check(&self, lba: u64, len: usize) -> Result<(), BlockError> {
+        check(self.block_size, self.block_count, lba, len)
+    }
+}
+
+fn check(block_size: usize, block_count: u64, lba: u64, len: usize) -> Result<(), BlockError> {
+    if len % block_size != 0 {
+        return Err(BlockError::Unaligned);
+    }
+    let end = lba.checked_add((len / block_size) as u64).ok_or(BlockError::OutOfRange)?;
+    if end > block_count {
+        return Err(BlockError::OutOfRange);
+    }
+    Ok(())
+}
+
+// 对名为 `name` 的设备执行 `f`，长度为 `len` 字节的请求先检查对齐和范围
+fn with_device<R>(name: &str, lba: u64, len: usize, f: impl FnOnce(&mut dyn BlockDevice) -> Result<R, BlockError>) -> Result<R, BlockError> {
+    let device = get(name).ok_or(BlockError::NoDevice)?;
+    let mut device = device.lock();
+    check(device.block_size(), device.block_count(), lba, len)?;
+    f(device.as_mut())
+}
+
+/// 从设备 `name` 的第 `lba` 块开始读满 `buffer`
+pub fn read(name: &str, lba: u64, buffer: &mut [u8]) -> Result<(), BlockError> {
+    let len = buffer.len();
+    with_device(name, lba, len, |device| if len == 0 { Ok(()) } else { device.read_blocks(lba, buffer) })
+}
+
+/// 从设备 `name` 的第 `lba` 块开始写入 `data`
+pub fn write(name: &str, lba: u64, data: &[u8]) -> Result<(), BlockError> {
+    with_device(name, lba, data.len(), |device| if data.is_empty() { Ok(()) } else { device.write_blocks(lba, data) })
+}
@@ -0,0 +1,428 @@
+// Intel 8254x（e1000）网卡
+// QEMU 的 -device e1000 模拟的是 82540EM。寄存器在 BAR0 的 MMIO 里，用 memory::map_mmio 映射到内核虚拟地址；
+// 接收和发送各一个描述符环，环和每个包的缓冲区都用 dma::alloc_coherent 分配。
+// 收到包时设备发中断，中断处理函数读 ICR 确认并记下有包到了，net::poll 取包时才扫描接收环，
+// 没有新中断时 receive 直接返回。拿不到中断线时退回到每次都扫描接收环。只支持一块网卡
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::ptr;
+use core::sync::atomic::{fence, AtomicBool, Ordering};
+use core::time::Duration;
+
+use spin::Once;
+use x86_64::structures::idt::InterruptStackFrame;
+use x86_64::{PhysAddr, VirtAddr};
+
+use crate::drivers::pci::PciDriver;
+use crate::interrupts::{self, Irq};
+use crate::io::pci::{DeviceMatch, PciDevice};
+use crate::io::timer::uptime;
+use crate::memory::{self, dma::{self, DmaBuffer, MASK_64}};
+use crate::net::{self, ethernet::MacAddr, NetDevice};
+
+const VENDOR_ID: u16 = 0x8086;
+// 寄存器空间的大小
+const REGISTERS_SIZE: u64 = 0x20000;
+
+// 寄存器的偏移
+const CTRL: usize = 0x0000;
+const EERD: usize = 0x0014;
+const ICR: usize = 0x00C0;
+const IMS: usize = 0x00D0;
+const IMC: usize = 0x00D8;
+const RCTL: usize = 0x0100;
+const TCTL: usize = 0x0400;
+const TIPG: usize = 0x0410;
+const RDBAL: usize = 0x2800;
+const RDBAH: usize = 0x2804;
+const RDLEN: usize = 0x2808;
+const RDH: usize = 0x2810;
+const RDT: usize = 0x2818;
+const TDBAL: usize = 0x3800;
+const TDBAH: usize = 0x3804;
+const TDLEN: usize = 0x3808;
+const TDH: usize = 0x3810;
+const TDT: usize = 0x3818;
+// 组播地址表，128 个 32 位的表项
+const MTA: usize = 0x5200;
+const RAL: usize = 0x5400;
+const RAH: usize = 0x5404;
+
+const CTRL_ASDE: u32 = 1 << 5;
+const CTRL_SLU: u32 = 1 << 6;
+const CTRL_RST: u32 = 1 << 26;
+const EERD_START: u32 = 1 << 0;
+const EERD_DONE: u32 = 1 << 4;
+// RAH 里的地址有效位
+const RAH_AV: u32 = 1 << 31;
+// 中断原因：接收定时器（收到包）、接收环快用完、接收溢出
+const INT_RXDMT0: u32 = 1 << 4;
+const INT_RXO: u32 = 1 << 6;
+const INT_RXT0: u32 = 1 << 7;
+const INT_RECEIVE: u32 = INT_RXDMT0 | INT_RXO | INT_RXT0;
+// 打开接收、收广播、去掉 CRC，缓冲区大小用默认的 2048 字节
+const RCTL_EN: u32 = 1 << 1;
+const RCTL_BAM: u32 = 1 << 15;
+const RCTL_SECRC: u32 = 1 << 26;
+// 打开发送、补齐短包，冲突阈值和全双工的冲突距离用手册推荐的值
+const TCTL_EN: u32 = 1 << 1;
+const TCTL_PSP: u32 = 1 << 3;
+const TCTL_CT: u32 = 0x10 << 4;
+const TCTL_COLD: u32 = 0x40 << 12;
+// 手册推荐的发送包间隔
+const TIPG_DEFAULT: u32 = 0x0060_200A;
+
+// 描述符的状态和命令
+const STATUS_DD: u8 = 1 << 0;
+const STATUS_EOP: u8 = 1 << 1;
+const CMD_EOP: u8 = 1 << 0;
+const CMD_IFCS: u8 = 1 << 1;
+const CMD_RS: u8 = 1 << 3;
+
+// 环的长度要是 128 字节的整数倍，也就是 8 个描述符的整数倍，起始地址按 16 字节对齐
+const RECEIVE_DESCRIPTORS: usize = 32;
+const TRANSMIT_DESCRIPTORS: usize = 16;
+const DESCRIPTOR_SIZE: usize = 16;
+const RING_ALIGN: usize = 16;
+const RECEIVE_BUFFER_LEN: usize = 2048;
+const TRANSMIT_BUFFER_LEN: usize = 4096;
+const RESET_TIMEOUT: Duration = Duration::from_millis(10);
+
+pub static DRIVER: PciDriver = PciDriver {
+    name: "e1000",
+    matches: &[
+        // 82540EM，QEMU 的默认型号
+        DeviceMatch::Id { vendor_id: VENDOR_ID, device_id: 0x100E },
+        // 82545EM
+        DeviceMatch::Id { vendor_id: VENDOR_ID, device_id: 0x100F },
+    ],
+    probe,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum E1000Error {
+    /// BAR0 不是内存空间
+    NoMmio,
+    /// 寄存器映射不上
+    MapFailed,
+    /// 分配不到描述符环或者缓冲区
+    NoMemory,
+    /// 复位没有完成
+    ResetTimeout,
+    /// 已经有一块 e1000 了
+    Busy,
+}
+
+fn probe(device: &PciDevice) -> bool {
+    match E1000::new(device) {
+        Ok(nic) => {
+            log::info!("e1000: MAC {}", nic.mac);
+            net::attach(Box::new(nic));
+            true
+        }
+        Err(error) => {
+            log::warn!("e1000: initialization failed: {:?}", error);
+            false
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Registers {
+    base: VirtAddr,
+}
+
+impl Registers {
+    fn read(&self, offset: usize) -> u32 {
+        unsafe { ptr::read_volatile((self.base + offset).as_ptr()) }
+    }
+
+    fn write(&self, offset: usize, value: u32) {
+        unsafe { ptr::write_volatile((self.base + offset).as_mut_ptr(), value) }
+    }
+
+    // 从 EEPROM 读一个 16 位的字
+    fn read_eeprom(&self, address: u8) -> Option<u16> {
+        self.write(EERD, (address as u32) << 8 | EERD_START);
+        let deadline = uptime() + RESET_TIMEOUT;
+        while uptime() < deadline {
+            let value = self.read(EERD);
+            if value & EERD_DONE != 0 {
+                return Some((value >> 16) as u16);
+            }
+            core::hint::spin_loop();
+        }
+        None
+    }
+}
+
+// 中断处理函数用的寄存器，和收到包的标记
+static REGISTERS: Once<Registers> = Once::new();
+static RECEIVED: AtomicBool = AtomicBool::new(false);
+
+// 读 ICR 同时清除了中断原因；共用中断线的别的设备发中断时读到的是 0
+fn interrupt(_frame: &InterruptStackFrame) {
+    let Some(registers) = REGISTERS.get() else { return };
+    if registers.read(ICR) & INT_RECEIVE != 0 {
+        RECEIVED.store(true, Ordering::Release);
+    }
+}
+
+#[repr(C)]
+struct ReceiveDescriptor {
+    addr: u64,
+    len: u16,
+    checksum: u16,
+    status: u8,
+    errors: u8,
+    special: u16,
+}
+
+#[repr(C)]
+struct TransmitDescriptor {
+    addr: u64,
+    len: u16,
+    cso: u8,
+    cmd: u8,
+    status: u8,
+    css: u8,
+    special: u16,
+}
+
+pub struct E1000 {
+    registers: Registers,
+    mac: MacAddr,
+    receive_ring: DmaBuffer,
+    transmit_ring: DmaBuffer,
+    receive_buffers: Vec<DmaBuffer>,
+    transmit_buffers: Vec<DmaBuffer>,
+    // 下一个要检查的接收描述符，和下一个要用的发送描述符
+    receive_next: usize,
+    transmit_next: usize,
+    // 没有中断，每次都扫描接收环
+    polled: bool,
+}
+
+impl E1000 {
+    fn new(device: &PciDevice) -> Result<Self, E1000Error> {
+        if REGISTERS.is_completed() {
+            return Err(E1000Error::Busy);
+        }
+        let bar = device.bar(0);
+        if bar & 1 != 0 {
+            return Err(E1000Error::NoMmio);
+        }
+        let mut phys = (bar & !0xF) as u64;
+        // 64 位的 BAR，高 32 位在 BAR1
+        if (bar >> 1) & 0x3 == 0x2 {
+            phys |= (device.bar(1) as u64) << 32;
+        }
+        // 先分配好内存，失败时设备还没有碰过
+        let alloc = |size: usize| dma::alloc_coherent(device, size, RING_ALIGN, MASK_64).map_err(|_| E1000Error::NoMemory);
+        let buffers = |count: usize, size: usize| (0..count).map(|_| alloc(size)).collect::<Result<Vec<_>, _>>();
+        let receive_ring = alloc(RECEIVE_DESCRIPTORS * DESCRIPTOR_SIZE)?;
+        let transmit_ring = alloc(TRANSMIT_DESCRIPTORS * DESCRIPTOR_SIZE)?;
+        let receive_buffers = buffers(RECEIVE_DESCRIPTORS, RECEIVE_BUFFER_LEN)?;
+        let transmit_buffers = buffers(TRANSMIT_DESCRIPTORS, TRANSMIT_BUFFER_LEN)?;
+
+        let region = memory::map_mmio(PhysAddr::new(phys), REGISTERS_SIZE).map_err(|_| E1000Error::MapFailed)?;
+        let registers = Registers { base: region.start() };
+        device.enable_bus_master();
+        reset(&registers)?;
+        registers.write(CTRL, registers.read(CTRL) | CTRL_SLU | CTRL_ASDE);
+
+        let mut nic = E1000 {
+            registers,
+            mac: read_mac(&registers),
+            receive_ring,
+            transmit_ring,
+            receive_buffers,
+            transmit_buffers,
+            receive_next: 0,
+            transmit_next: 0,
+            polled: false,
+        };
+        for i in 0..128 {
+            registers.write(MTA + 4 * i, 0);
+        }
+        nic.setup_receive();
+        nic.setup_transmit();
+
+        let (line, pin) = device.interrupt();
+        REGISTERS.call_once(|| registers);
+        if pin != 0 && interrupts::register_handler(Irq::Line(line), "e1000", interrupt).is_ok() {
+            registers.read(ICR);
+            registers.write(IMS, INT_RECEIVE);
+        } else {
+            log::warn!("e1000: IRQ {} not supported, polling", line);
+            nic.polled = true;
+        }
+        Ok(nic)
+    }
+
+    fn setup_receive(&mut self) {
+        let ring = self.receive_ring.ptr().cast::<ReceiveDescriptor>();
+        for (i, buffer) in self.receive_buffers.iter().enumerate() {
+            let descriptor = ReceiveDescriptor { addr: buffer.bus().as_u64(), len: 0, checksum: 0, status: 0, errors: 0, special: 0 };
+            unsafe { ptr::write_volatile(ring.add(i), descriptor) };
+        }
+        let bus = self.receive_ring.bus().as_u64();
+        let registers = &self.registers;
+        registers.write(RDBAL, bus as u32);
+        registers.write(RDBAH, (bus >> 32) as u32);
+        registers.write(RDLEN, (RECEIVE_DESCRIPTORS * DESCRIPTOR_SIZE) as u32);
+        // 头尾之间的描述符归设备所有，留一个空位区分满和空
+        registers.write(RDH, 0);
+        registers.write(RDT, (RECEIVE_DESCRIPTORS - 1) as u32);
+        registers.write(RCTL, RCTL_EN | RCTL_BAM | RCTL_SECRC);
+    }
+
+    fn setup_transmit(&mut self) {
+        let ring = self.transmit_ring.ptr().cast::<TransmitDescriptor>();
+        for (i, buffer) in self.transmit_buffers.iter().enumerate() {
+            // 一开始都是发送完的状态
+            let descriptor = TransmitDescriptor {
+                addr: buffer.bus().as_u64(), len: 0, cso: 0, cmd: 0, status: STATUS_DD, css: 0, special: 0,
+            };
+            unsafe { ptr::write_volatile(ring.add(i), descriptor) };
+        }
+        let bus = self.transmit_ring.bus().as_u64();
+        let registers = &self.registers;
+        registers.write(TDBAL, bus as u32);
+        registers.write(TDBAH, (bus >> 32) as u32);
+        registers.write(TDLEN, (TRANSMIT_DESCRIPTORS * DESCRIPTOR_SIZE) as u32);
+        registers.write(TDH, 0);
+        registers.write(TDT, 0);
+        registers.write(TIPG, TIPG_DEFAULT);
+        registers.write(TCTL, TCTL_EN | TCTL_PSP | TCTL_CT | TCTL_COLD);
+    }
+
+    fn receive_descriptor(&self, index: usize) -> *mut ReceiveDescriptor {
+        unsafe { self.receive_ring.ptr().cast::<ReceiveDescriptor>().add(index) }
+    }
+
+    fn transmit_descriptor(&self, index: usize) -> *mut TransmitDescriptor {
+        unsafe { self.transmit_ring.ptr().cast::<TransmitDescriptor>().add(index) }
+    }
+
+    // 下一个接收描述符设备已经写好了
+    fn receive_ready(&self) -> bool {
+        let status = unsafe { ptr::read_volatile(ptr::addr_of!((*self.receive_descriptor(self.receive_next)).status)) };
+        status & STATUS_DD != 0
+    }
+}
+
+fn reset(registers: &Registers) -> Result<(), E1000Error> {
+    registers.write(IMC, u32::MAX);
+    registers.write(CTRL, registers.read(CTRL) | CTRL_RST);
+    let deadline = uptime() + RESET_TIMEOUT;
+    while registers.read(CTRL) & CTRL_RST != 0 {
+        if uptime() >= deadline {
+            return Err(E1000Error::ResetTimeout);
+        }
+        core::hint::spin_loop();
+    }
+    // 复位之后中断又被打开了，再关一次并清掉已有的中断原因
+    registers.write(IMC, u32::MAX);
+    registers.read(ICR);
+    Ok(())
+}
+
+// 复位后设备一般已经把 EEPROM 里的地址装进了第 0 个接收地址寄存器；没有时自己读出来填进去
+fn read_mac(registers: &Registers) -> MacAddr {
+    let (low, high) = (registers.read(RAL), registers.read(RAH));
+    if high & RAH_AV != 0 {
+        let mut mac = [0u8; 6];
+        mac[..4].copy_from_slice(&low.to_le_bytes());
+        mac[4..].copy_from_slice(&high.to_le_bytes()[..2]);
+        return MacAddr(mac);
+    }
+    let words = [0, 1, 2].map(|address| registers.read_eeprom(address).unwrap_or(0));
+    let mac = MacAddr(core::array::from_fn(|i| (words[i / 2] >> (8 * (i % 2))) as u8));
+    registers.write(RAL, u32::from_le_bytes([mac.0[0], mac.0[1], mac.0[2], mac.0[3]]));
+    registers.write(RAH, u16::from_le_bytes([mac.0[4], mac.0[5]]) as u32 | RAH_AV);
+    mac
+}
+
+impl NetDevice for E1000 {
+    fn name(&self) -> &'static str {
+        DRIVER.name
+    }
+
+    fn mac(&self) -> MacAddr {
+        self.mac
+    }
+
+    fn transmit(&mut self, frame: &[u8]) -> bool {
+        let index = self.transmit_next;
+        let descriptor = self.transmit_descriptor(index);
+        let status = unsafe { ptr::read_volatile(ptr::addr_of!((*descriptor).status)) };
+        if frame.len() > TRANSMIT_BUFFER_LEN || status & STATUS_DD == 0 {
+            return false;
+        }
+        let buffer = &self.transmit_buffers[index];
+        unsafe {
+            ptr::copy_nonoverlapping(frame.as_ptr(), buffer.ptr(), frame.len());
+            ptr::write_volatile(descriptor, TransmitDescriptor {
+                addr: buffer.bus().as_u64(),
+                len: frame.len() as u16,
+                cso: 0,
+                cmd: CMD_EOP | CMD_IFCS | CMD_RS,
+                status: 0,
+                css: 0,
+                special: 0,
+            });
+        }
+        self.transmit_next = (index + 1) % TRANSMIT_DESCRIPTORS;
+        // 先写好描述符，设备才能看到新的尾指针
+        fence(Ordering::SeqCst);
+        self.registers.write(TDT, self.transmit_next as u32);
+        true
+    }
+
+    fn receive(&mut self) -> Option<Vec<u8>> {
+        if !self.polled && !RECEIVED.load(Ordering::Acquire) {
+            return None;
+        }
+        loop {
+            if !self.receive_ready() {
+                // 环空了，等下一次中断；清掉标记之后再看一次，免得漏掉清掉之前刚到的包
+                RECEIVED.store(false, Ordering::Release);
+                if !self.receive_ready() {
+                    return None;
+                }
+            }
+            fence(Ordering::SeqCst);
+            let index = self.receive_next;
+            let descriptor = unsafe { ptr::read_volatile(self.receive_descriptor(index)) };
+            // 一个包放不下一个缓冲区或者有错误时丢掉，正常的以太网帧不会跨缓冲区
+            let frame = (descriptor.status & STATUS_EOP != 0 && descriptor.errors == 0).then(|| {
+                let len = (descriptor.len as usize).min(RECEIVE_BUFFER_LEN);
+                let mut frame = Vec::with_capacity(len);
+                unsafe {
+                    ptr::copy_nonoverlapping(self.receive_buffers[index].ptr(), frame.as_mut_ptr(), len);
+                    frame.set_len(len);
+                }
+                frame
+            });
+            // 描述符马上还给设备
+            unsafe { ptr::write_volatile(ptr::addr_of_mut!((*self.receive_descriptor(index)).status), 0) };
+            fence(Ordering::SeqCst);
+            self.registers.write(RDT, index as u32);
+            self.receive_next = (index + 1) % RECEIVE_DESCRIPTORS;
+            if frame.is_some() {
+                return frame;
+            }
+        }
+    }
+}
+
+impl Drop for E1000 {
+    // 先让设备停下来，之后才能释放描述符环和缓冲区
+    fn drop(&mut self) {
+        self.registers.write(IMC, u32::MAX);
+        self.registers.write(RCTL, 0);
+        self.registers.write(TCTL, 0);
+    }
+}
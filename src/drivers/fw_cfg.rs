@@ -0,0 +1,79 @@
+// QEMU 固件配置接口(fw_cfg)
+// QEMU 通过它把 -fw_cfg 给出的文件交给客户机：往选择端口写条目号，再从数据端口逐字节读出内容。
+// 条目 0 是签名 "QEMU"，条目 0x19 是文件目录，目录里每一项是大端的大小、条目号和以 0 结尾的文件名。
+// 只用 I/O 端口方式，不用 DMA，启动最早期（堆和日志都还没有）也能读
+
+use crate::io::port::{self, Port};
+
+const SELECTOR: Port<u16> = Port::new(0x510);
+const DATA: Port<u8> = Port::new(0x511);
+
+const SIGNATURE: u16 = 0x0000;
+const FILE_DIR: u16 = 0x0019;
+// 目录项：大小 4 字节、条目号 2 字节、保留 2 字节、文件名 56 字节
+const FILE_NAME_LEN: usize = 56;
+
+/// 一个 -fw_cfg 文件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct File {
+    pub select: u16,
+    pub size: u32,
+}
+
+// 选中条目，之后从头读它的内容
+fn select(entry: u16) {
+    unsafe { SELECTOR.write(entry) };
+}
+
+fn read_bytes(buffer: &mut [u8]) {
+    for byte in buffer {
+        *byte = unsafe { DATA.read() };
+    }
+}
+
+fn read_u32_be() -> u32 {
+    let mut bytes = [0; 4];
+    read_bytes(&mut bytes);
+    u32::from_be_bytes(bytes)
+}
+
+/// 是否在 QEMU 里运行，有 fw_cfg 设备时登记它的端口
+pub fn is_present() -> bool {
+    let mut signature = [0; 4];
+    select(SIGNATURE);
+    read_bytes(&mut signature);
+    let present = &signature == b"QEMU";
+    if present {
+        port::claim_or_log("fw_cfg", SELECTOR.number(), 2);
+    }
+    present
+}
+
+/// 按名字找文件，比如 "opt/cjn_os/cmdline"
+pub fn find(name: &str) -> Option<File> {
+    if !is_present() {
+        return None;
+    }
+    select(FILE_DIR);
+    let count = read_u32_be();
+    for _ in 0..count {
+        let size = read_u32_be();
+        let mut select = [0; 4];
+        read_bytes(&mut select);
+        let mut file_name = [0; FILE_NAME_LEN];
+        read_bytes(&mut file_name);
+        let len = file_name.iter().position(|&byte| byte == 0).unwrap_or(FILE_NAME_LEN);
+        if &file_name[..len] == name.as_bytes() {
+            return Some(File { select: u16::from_be_bytes([select[0], select[1]]), size });
+        }
+    }
+    None
+}
+
+/// 从头读文件的内容，最多读满 `buffer`，返回读到的字节数
+pub fn read(file: File, buffer: &mut [u8]) -> usize {
+    let len = buffer.len().min(file.size as usize);
+    select(file.select);
+    read_bytes(&mut buffer[..len]);
+    len
+}
@@ -0,0 +1,76 @@
+// 设备热插拔
+// 重新扫描 PCI 总线，和上一次的结果比较得出新增和移除的设备，逐个通知订阅者。
+// 事件同时写进日志，在图形模式下会显示到屏幕上
+
+use alloc::vec::Vec;
+
+use spin::Mutex;
+
+use crate::boot::Unit;
+use crate::io::pci::{pci_enumerate, PciDevice};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceEvent {
+    Added(PciDevice),
+    Removed(PciDevice),
+}
+
+// 上一次扫描到的设备
+static DEVICES: Mutex<Vec<PciDevice>> = Mutex::new(Vec::new());
+static SUBSCRIBERS: Mutex<Vec<fn(&DeviceEvent)>> = Mutex::new(Vec::new());
+
+/// 记录启动时已有的设备，需在堆初始化之后调用
+pub fn init() {
+    let devices = pci_enumerate();
+    log::debug!("{} PCI devices present at boot", devices.len());
+    *DEVICES.lock() = devices;
+}
+
+pub static UNIT: Unit = Unit {
+    name: "hotplug",
+    depends: &[],
+    init: |_| {
+        init();
+        Ok(())
+    },
+};
+
+/// 订阅热插拔事件
+pub fn subscribe(callback: fn(&DeviceEvent)) {
+    SUBSCRIBERS.lock().push(callback);
+}
+
+/// 重新扫描 PCI 总线并通知订阅者，返回这次发现的变化
+pub fn rescan() -> Vec<DeviceEvent> {
+    let current = pci_enumerate();
+    let mut events = Vec::new();
+    {
+        let mut previous = DEVICES.lock();
+        for device in previous.iter() {
+            if !current.contains(device) {
+                events.push(DeviceEvent::Removed(*device));
+            }
+        }
+        for device in current.iter() {
+            if !previous.contains(device) {
+                events.push(DeviceEvent::Added(*device));
+            }
+        }
+        *previous = current;
+    }
+
+    // 复制一份订阅者再通知，回调里可以再订阅
+    let subscribers = SUBSCRIBERS.lock().clone();
+    for event in events.iter() {
+        match event {
+            DeviceEvent::Added(d) => log::info!("PCI device added: {:02x}:{:02x}.{} {:04x}:{:04x}",
+                                                d.bus, d.device, d.function, d.vendor_id, d.device_id),
+            DeviceEvent::Removed(d) => log::info!("PCI device removed: {:02x}:{:02x}.{} {:04x}:{:04x}",
+                                                  d.bus, d.device, d.function, d.vendor_id, d.device_id),
+        }
+        for subscriber in subscribers.iter() {
+            subscriber(event);
+        }
+    }
+    events
+}
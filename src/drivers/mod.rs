@@ -0,0 +1,14 @@
+// 设备驱动
+
+pub mod ac97;
+pub mod acpi;
+pub mod ahci;
+pub mod block;
+pub mod e1000;
+pub mod fw_cfg;
+pub mod hotplug;
+pub mod pci;
+pub mod pcspeaker;
+pub mod rtc;
+pub mod virtio;
+pub mod xhci;
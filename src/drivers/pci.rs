@@ -0,0 +1,93 @@
+// PCI 驱动匹配
+// 驱动注册时给出一组 DeviceMatch 规则，可以是精确的厂商/设备 ID，也可以是设备类别（比如任何 AHCI 控制器），
+// 这样换了 QEMU 的机器配置、同类设备的型号不同也能被认领。
+// 一个设备有多个驱动可选时，精确 ID 匹配的优先于按类别匹配的，同等条件下先注册的优先；
+// 驱动的 probe 返回 false 表示不接管这个设备，轮到下一个驱动。
+// 启动时和热插拔新增设备时都会尝试绑定，设备移除时解除绑定
+
+use alloc::vec::Vec;
+
+use spin::Mutex;
+
+use crate::boot::Unit;
+use crate::drivers::hotplug::{self, DeviceEvent};
+use crate::io::pci::{pci_enumerate, DeviceMatch, PciDevice};
+
+pub struct PciDriver {
+    pub name: &'static str,
+    pub matches: &'static [DeviceMatch],
+    /// 接管设备，返回 false 表示不支持
+    pub probe: fn(&PciDevice) -> bool,
+}
+
+static DRIVERS: Mutex<Vec<&'static PciDriver>> = Mutex::new(Vec::new());
+// 已经绑定的设备和驱动名
+static BINDINGS: Mutex<Vec<(PciDevice, &'static str)>> = Mutex::new(Vec::new());
+
+/// 绑定启动时已有的设备，之后热插拔新增的设备也会自动绑定；需在 hotplug::init 之后调用
+pub fn init() {
+    for device in pci_enumerate() {
+        bind(&device);
+    }
+    hotplug::subscribe(on_hotplug);
+}
+
+pub static UNIT: Unit = Unit {
+    name: "pci",
+    depends: &["hotplug"],
+    init: |_| {
+        init();
+        Ok(())
+    },
+};
+
+/// 注册驱动，并尝试绑定还没有驱动的现有设备
+pub fn register(driver: &'static PciDriver) {
+    DRIVERS.lock().push(driver);
+    for device in pci_enumerate() {
+        if driver_of(&device).is_none() {
+            bind(&device);
+        }
+    }
+}
+
+/// 设备绑定的驱动名
+pub fn driver_of(device: &PciDevice) -> Option<&'static str> {
+    BINDINGS.lock().iter().find(|(d, _)| d == device).map(|(_, name)| *name)
+}
+
+// 匹配的优先级，数字越小越优先；不匹配时返回 None
+fn rank(driver: &PciDriver, device: &PciDevice) -> Option<u8> {
+    driver.matches.iter()
+        .filter(|rule| rule.matches(device))
+        .map(|rule| match rule {
+            DeviceMatch::Id { .. } => 0,
+            DeviceMatch::Class { prog_if: Some(_), .. } => 1,
+            DeviceMatch::Class { prog_if: None, .. } => 2,
+        })
+        .min()
+}
+
+fn bind(device: &PciDevice) {
+    let mut candidates: Vec<(u8, &'static PciDriver)> = DRIVERS.lock().iter()
+        .filter_map(|&driver| rank(driver, device).map(|rank| (rank, driver)))
+        .collect();
+    // 稳定排序，同等优先级保持注册顺序
+    candidates.sort_by_key(|&(rank, _)| rank);
+    // probe 里可能再注册驱动或者查询绑定，不能拿着锁调用
+    for (_, driver) in candidates {
+        if (driver.probe)(device) {
+            log::info!("PCI {:02x}:{:02x}.{} bound to driver {}",
+                       device.bus, device.device, device.function, driver.name);
+            BINDINGS.lock().push((*device, driver.name));
+            return;
+        }
+    }
+}
+
+fn on_hotplug(event: &DeviceEvent) {
+    match event {
+        DeviceEvent::Added(device) => bind(device),
+        DeviceEvent::Removed(device) => BINDINGS.lock().retain(|(d, _)| d != device),
+    }
+}
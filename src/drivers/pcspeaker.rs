@@ -0,0 +1,48 @@
+// PC 喇叭
+// PIT 通道 2 产生方波，端口 0x61 的低两位打开通道 2 的门控并把它的输出接到喇叭上。
+// beep 不等声音结束就返回，时钟中断里的周期回调到时间后关掉喇叭。
+// QEMU 要用 -machine pcspk-audiodev=<id> 接上声音后端才听得到
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+use spin::Once;
+
+use crate::io::timer::pit::{self, PORT_B};
+use crate::io::timer::{self, uptime};
+
+// 通道 2 的门控和喇叭的数据位
+const GATE: u8 = 1 << 0;
+const SPEAKER: u8 = 1 << 1;
+// 检查是否该关掉喇叭的间隔
+const CHECK_PERIOD: Duration = Duration::from_millis(5);
+
+// 关掉喇叭的时间（uptime 的纳秒数），0 表示没有在响
+static DEADLINE: AtomicU64 = AtomicU64::new(0);
+static REGISTERED: Once = Once::new();
+
+/// 以 `hz` 的频率响 `duration`，正在响的声音被换掉
+pub fn beep(hz: u32, duration: Duration) {
+    REGISTERED.call_once(|| timer::register_periodic(CHECK_PERIOD, check));
+    pit::set_channel2_frequency(hz);
+    unsafe { PORT_B.write(PORT_B.read() | GATE | SPEAKER) };
+    DEADLINE.store((uptime() + duration).as_nanos() as u64, Ordering::Release);
+}
+
+/// 马上停止
+pub fn stop() {
+    DEADLINE.store(0, Ordering::Release);
+    unsafe { PORT_B.write(PORT_B.read() & !(GATE | SPEAKER)) };
+}
+
+// 在时钟中断里调用
+fn check() {
+    let deadline = DEADLINE.load(Ordering::Acquire);
+    if deadline == 0 || (uptime().as_nanos() as u64) < deadline {
+        return;
+    }
+    // 期间又调用了 beep 时不关
+    if DEADLINE.compare_exchange(deadline, 0, Ordering::AcqRel, Ordering::Relaxed).is_ok() {
+        unsafe { PORT_B.write(PORT_B.read() & !(GATE | SPEAKER)) };
+    }
+}
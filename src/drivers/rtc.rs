@@ -0,0 +1,213 @@
+// 实时时钟(RTC)驱动
+// 从CMOS读取日期和时间，处理更新中标志和BCD编码，对外提供 now()。
+// RTC 的闹钟在时、分、秒和设定值相同时产生 IRQ 8，每天都会再响一次，由 io::alarm 决定响的时候做什么
+
+use core::fmt;
+
+use spin::Once;
+use x86_64::instructions::interrupts;
+
+use crate::io::port::{self, Port};
+
+const CURRENT_YEAR: u32 = 2023;
+
+const ADDRESS: Port<u8> = Port::new(0x70);
+const DATA: Port<u8> = Port::new(0x71);
+
+fn get_update_in_progress_flag() -> u8 {
+    unsafe {
+        ADDRESS.write(0x0A);
+        DATA.read() & 0x80
+    }
+}
+
+// 选寄存器和读写之间不能被 RTC 中断打断，中断处理函数也要读寄存器 C
+#[allow(non_snake_case)]
+fn get_RTC_register(reg: u8) -> u8 {
+    interrupts::without_interrupts(|| unsafe {
+        ADDRESS.write(reg);
+        DATA.read()
+    })
+}
+
+#[allow(non_snake_case)]
+fn set_RTC_register(reg: u8, value: u8) {
+    interrupts::without_interrupts(|| unsafe {
+        ADDRESS.write(reg);
+        DATA.write(value);
+    })
+}
+
+// 寄存器 B：闹钟中断使能、24 小时制、二进制格式
+const ALARM_INTERRUPT: u8 = 0x20;
+const HOUR_24: u8 = 0x02;
+const BINARY: u8 = 0x04;
+// 寄存器 C：闹钟中断标志
+const ALARM_FLAG: u8 = 0x20;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub struct DateTime {
+    pub second: u8,
+    pub minute: u8,
+    pub hour: u8,
+    pub day: u8,
+    pub month: u8,
+    pub year: u32,
+}
+
+#[allow(non_snake_case)]
+pub fn read_RTC() -> DateTime {
+    let mut time = DateTime::default();
+
+    // 第一次获取时间
+    while get_update_in_progress_flag() > 0 {}
+    time.second = get_RTC_register(0x00);
+    time.minute = get_RTC_register(0x02);
+    time.hour = get_RTC_register(0x04);
+    time.day = get_RTC_register(0x07);
+    time.month = get_RTC_register(0x08);
+    time.year = get_RTC_register(0x09) as u32;
+
+    // 第二次获取时间
+    loop {
+        let last_time = time;
+        while get_update_in_progress_flag() > 0 {}
+        time.second = get_RTC_register(0x00);
+        time.minute = get_RTC_register(0x02);
+        time.hour = get_RTC_register(0x04);
+        time.day = get_RTC_register(0x07);
+        time.month = get_RTC_register(0x08);
+        time.year = get_RTC_register(0x09) as u32;
+        if time == last_time { break; }
+    }
+
+    // 处理时间格式
+    let register_b = get_RTC_register(0x0B);
+    if (register_b & 0x04) == 0 {
+        time.second = (time.second & 0x0F) + ((time.second / 16) * 10);
+        time.minute = (time.minute & 0x0F) + ((time.minute / 16) * 10);
+        time.hour = ((time.hour & 0x0F) + (((time.hour & 0x70) / 16) * 10)) | (time.hour & 0x80);
+        time.day = (time.day & 0x0F) + ((time.day / 16) * 10);
+        time.month = (time.month & 0x0F) + ((time.month / 16) * 10);
+        time.year = (time.year & 0x0F) + ((time.year / 16) * 10);
+    }
+
+    // 如有必要，转换为24小时制
+    if (register_b & 0x02) == 0 && (time.hour & 0x80) > 0 {
+        time.hour = ((time.hour & 0x7F) + 12) % 24;
+    }
+
+    // 计算完整的4位数年份
+    time.year += CURRENT_YEAR - (CURRENT_YEAR % 100);
+
+    time
+}
+
+/// 设定闹钟的时间（UTC），不改变闹钟中断是否打开
+pub fn set_alarm(hour: u8, minute: u8, second: u8) {
+    let register_b = get_RTC_register(0x0B);
+    let encode = |value: u8| if register_b & BINARY == 0 { value / 10 * 16 + value % 10 } else { value };
+    // 12 小时制下 0 点是 12 点，下午的时间最高位置 1
+    let hour = if register_b & HOUR_24 == 0 {
+        let pm = if hour >= 12 { 0x80 } else { 0 };
+        encode(if hour % 12 == 0 { 12 } else { hour % 12 }) | pm
+    } else {
+        encode(hour)
+    };
+    set_RTC_register(0x01, encode(second));
+    set_RTC_register(0x03, encode(minute));
+    set_RTC_register(0x05, hour);
+}
+
+/// 打开或关闭闹钟中断
+pub fn enable_alarm(enable: bool) {
+    interrupts::without_interrupts(|| {
+        let register_b = get_RTC_register(0x0B);
+        let register_b = if enable { register_b | ALARM_INTERRUPT } else { register_b & !ALARM_INTERRUPT };
+        set_RTC_register(0x0B, register_b);
+    });
+    // 清掉已经挂起的中断，否则 RTC 不会再产生中断
+    acknowledge();
+}
+
+/// 读寄存器 C 应答 RTC 中断，返回这次是不是闹钟响了
+pub fn acknowledge() -> bool {
+    get_RTC_register(0x0C) & ALARM_FLAG != 0
+}
+
+// 启动时读到的时间
+static BOOT_TIME: Once<DateTime> = Once::new();
+
+/// 当前的 UTC 时间
+pub fn now() -> DateTime {
+    read_RTC()
+}
+
+/// 记录并返回启动时间，重复调用返回第一次记录的值
+pub fn init() -> DateTime {
+    port::claim_or_log("rtc", ADDRESS.number(), 2);
+    *BOOT_TIME.call_once(read_RTC)
+}
+
+/// 启动时间，init 之前为 None
+pub fn boot_time() -> Option<DateTime> {
+    BOOT_TIME.get().copied()
+}
+
+impl DateTime {
+    /// 距 1970-01-01 00:00:00 的秒数
+    pub fn timestamp(&self) -> i64 {
+        let days = days_from_civil(self.year as i64, self.month as u32, self.day as u32);
+        days * 86400 + self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64
+    }
+
+    pub fn from_timestamp(timestamp: i64) -> DateTime {
+        let days = timestamp.div_euclid(86400);
+        let seconds = timestamp.rem_euclid(86400);
+        let (year, month, day) = civil_from_days(days);
+        DateTime {
+            second: (seconds % 60) as u8,
+            minute: (seconds / 60 % 60) as u8,
+            hour: (seconds / 3600) as u8,
+            day: day as u8,
+            month: month as u8,
+            year: year as u32,
+        }
+    }
+
+    /// 加上时区偏移，正确处理跨日、跨月和跨年
+    pub fn with_offset_hours(&self, hours: i64) -> DateTime {
+        DateTime::from_timestamp(self.timestamp() + hours * 3600)
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+               self.year, self.month, self.day, self.hour, self.minute, self.second)
+    }
+}
+
+// 公历日期转换为距 1970-01-01 的天数
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month as i64 + 9) % 12) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+// 距 1970-01-01 的天数转换为公历日期
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
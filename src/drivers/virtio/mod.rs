@@ -0,0 +1,245 @@
+// virtio 设备的公共部分
+// 只实现 legacy（virtio 0.9.5）的 PCI 接口：寄存器在 BAR0 的 I/O 端口里；每个队列是一段物理连续的内存，
+// 依次放描述符表、可用环和按页对齐的已用环，把起始页号写给设备。QEMU 的 virtio-*-pci 默认是 transitional 设备，
+// 同时支持 legacy 接口（设备 ID 0x1000 起）；只支持新接口的设备（0x1040 起）不在这里处理。
+// 驱动轮询已用环，不使用中断
+
+pub mod net;
+
+use alloc::vec::Vec;
+use core::ptr;
+use core::sync::atomic::{fence, Ordering};
+
+use crate::io::pci::PciDevice;
+use crate::io::port::{self, ClaimError, Port, PortValue};
+use crate::memory::dma::{self, BusAddr, DmaBuffer};
+
+pub const VENDOR_ID: u16 = 0x1AF4;
+
+// legacy 寄存器相对 BAR0 的偏移
+const DEVICE_FEATURES: u16 = 0x00;
+const GUEST_FEATURES: u16 = 0x04;
+const QUEUE_ADDRESS: u16 = 0x08;
+const QUEUE_SIZE: u16 = 0x0C;
+const QUEUE_SELECT: u16 = 0x0E;
+const QUEUE_NOTIFY: u16 = 0x10;
+const DEVICE_STATUS: u16 = 0x12;
+// 没有打开 MSI-X 时设备自己的配置从这里开始
+const DEVICE_CONFIG: u16 = 0x14;
+
+// 设备状态
+const ACKNOWLEDGE: u8 = 1;
+const DRIVER: u8 = 2;
+const DRIVER_OK: u8 = 4;
+const FAILED: u8 = 0x80;
+
+// 描述符的标志
+const DESC_NEXT: u16 = 1;
+const DESC_WRITE: u16 = 2;
+
+const PAGE_SIZE: usize = 4096;
+// 队列地址按页号写进 32 位寄存器，设备能访问的地址不超过 44 位
+const QUEUE_MASK: u64 = (1 << 44) - 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VirtioError {
+    /// BAR0 不是 I/O 端口，不是 legacy 设备
+    NotLegacy,
+    /// 设备没有这个队列
+    NoQueue,
+    /// 分配不到内存，或者设备访问不到分配的内存
+    NoMemory,
+    /// 端口被别的驱动占用
+    Ports(ClaimError),
+}
+
+/// legacy PCI 接口的寄存器
+pub struct Transport {
+    base: u16,
+}
+
+impl Transport {
+    /// 以 `owner` 的名义登记公共寄存器和 `config_len` 字节的设备配置占用的端口
+    pub fn new(device: &PciDevice, owner: &'static str, config_len: u16) -> Result<Self, VirtioError> {
+        let bar = device.bar(0);
+        if bar & 1 == 0 {
+            return Err(VirtioError::NotLegacy);
+        }
+        let base = (bar & !0x3) as u16;
+        port::claim(owner, base, DEVICE_CONFIG + config_len).map_err(VirtioError::Ports)?;
+        device.enable_bus_master();
+        Ok(Transport { base })
+    }
+
+    fn register<T: PortValue>(&self, offset: u16) -> Port<T> {
+        Port::new(self.base + offset)
+    }
+
+    /// 复位设备并协商特性：只打开 `wanted` 里设备也支持的，返回协商的结果
+    pub fn begin(&self, wanted: u32) -> u32 {
+        self.set_status(0);
+        self.set_status(ACKNOWLEDGE);
+        self.set_status(ACKNOWLEDGE | DRIVER);
+        let features = unsafe { self.register::<u32>(DEVICE_FEATURES).read() } & wanted;
+        unsafe { self.register(GUEST_FEATURES).write(features) };
+        features
+    }
+
+    /// 队列都准备好之后调用，设备开始工作
+    pub fn finish(&self) {
+        self.set_status(ACKNOWLEDGE | DRIVER | DRIVER_OK);
+    }
+
+    /// 初始化失败时告诉设备，设备会停止工作
+    pub fn fail(&self) {
+        self.set_status(FAILED);
+    }
+
+    /// 设备配置空间里的一个字节
+    pub fn config_u8(&self, offset: u16) -> u8 {
+        unsafe { self.register(DEVICE_CONFIG + offset).read() }
+    }
+
+    /// 通知设备队列里有新的缓冲区
+    pub fn notify(&self, queue: u16) {
+        unsafe { self.register(QUEUE_NOTIFY).write(queue) };
+    }
+
+    fn set_status(&self, status: u8) {
+        unsafe { self.register(DEVICE_STATUS).write(status) };
+    }
+}
+
+impl Drop for Transport {
+    fn drop(&mut self) {
+        port::release(self.base);
+    }
+}
+
+/// 交给设备的一段内存
+#[derive(Debug, Clone, Copy)]
+pub struct Buffer {
+    pub addr: BusAddr,
+    pub len: u32,
+    /// 设备往里写（接收），否则设备从里面读（发送）
+    pub writable: bool,
+}
+
+#[repr(C)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+/// 一个 virtqueue
+pub struct Virtqueue {
+    size: u16,
+    memory: DmaBuffer,
+    used_offset: usize,
+    free: Vec<u16>,
+    last_used: u16,
+}
+
+impl Virtqueue {
+    /// 设置设备的第 `index` 个队列，大小由设备决定
+    pub fn new(transport: &Transport, device: &PciDevice, index: u16) -> Result<Self, VirtioError> {
+        unsafe { transport.register(QUEUE_SELECT).write(index) };
+        let size: u16 = unsafe { transport.register(QUEUE_SIZE).read() };
+        if size == 0 {
+            return Err(VirtioError::NoQueue);
+        }
+        let descriptors = 16 * size as usize;
+        let available = 6 + 2 * size as usize;
+        let used = 6 + 8 * size as usize;
+        let used_offset = align_up(descriptors + available);
+        let memory = dma::alloc_coherent(device, used_offset + align_up(used), PAGE_SIZE, QUEUE_MASK)
+            .map_err(|_| VirtioError::NoMemory)?;
+        unsafe { transport.register(QUEUE_ADDRESS).write((memory.bus().as_u64() / PAGE_SIZE as u64) as u32) };
+        Ok(Virtqueue {
+            size,
+            memory,
+            used_offset,
+            free: (0..size).rev().collect(),
+            last_used: 0,
+        })
+    }
+
+    /// 描述符的个数，链头的编号小于它
+    pub fn size(&self) -> u16 {
+        self.size
+    }
+
+    /// 把几段内存串成一个描述符链放进可用环，返回链头的编号；描述符不够时返回 None
+    pub fn push(&mut self, buffers: &[Buffer]) -> Option<u16> {
+        if buffers.is_empty() || buffers.len() > self.free.len() {
+            return None;
+        }
+        let ids: Vec<u16> = (0..buffers.len()).map(|_| self.free.pop().unwrap()).collect();
+        for (i, (buffer, &id)) in buffers.iter().zip(&ids).enumerate() {
+            let mut flags = if buffer.writable { DESC_WRITE } else { 0 };
+            let next = ids.get(i + 1).copied().unwrap_or(0);
+            if i + 1 < ids.len() {
+                flags |= DESC_NEXT;
+            }
+            unsafe {
+                ptr::write_volatile(self.descriptor(id), Descriptor { addr: buffer.addr.as_u64(), len: buffer.len, flags, next });
+            }
+        }
+        let head = ids[0];
+        unsafe {
+            let index = ptr::read_volatile(self.available(1));
+            ptr::write_volatile(self.available(2 + (index % self.size) as usize), head);
+            // 先写好环里的内容，设备才能看到新的下标
+            fence(Ordering::SeqCst);
+            ptr::write_volatile(self.available(1), index.wrapping_add(1));
+        }
+        Some(head)
+    }
+
+    /// 取出一个设备用完的描述符链，返回链头的编号和设备写入的字节数
+    pub fn pop_used(&mut self) -> Option<(u16, u32)> {
+        let used = unsafe { ptr::read_volatile(self.used(1)) };
+        if used == self.last_used {
+            return None;
+        }
+        fence(Ordering::SeqCst);
+        let element = self.used(2 + 4 * (self.last_used % self.size) as usize).cast::<u32>();
+        let (head, len) = unsafe { (ptr::read_volatile(element) as u16, ptr::read_volatile(element.add(1))) };
+        self.last_used = self.last_used.wrapping_add(1);
+        // 把链上的描述符都放回空闲列表
+        let mut id = head;
+        loop {
+            self.free.push(id);
+            let descriptor = unsafe { ptr::read_volatile(self.descriptor(id)) };
+            if descriptor.flags & DESC_NEXT == 0 {
+                break;
+            }
+            id = descriptor.next;
+        }
+        Some((head, len))
+    }
+
+    fn base(&self) -> *mut u8 {
+        self.memory.ptr()
+    }
+
+    fn descriptor(&self, id: u16) -> *mut Descriptor {
+        unsafe { self.base().cast::<Descriptor>().add(id as usize) }
+    }
+
+    // 可用环按 u16 编号：0 是标志，1 是下标，之后是环
+    fn available(&self, index: usize) -> *mut u16 {
+        unsafe { self.base().add(16 * self.size as usize).cast::<u16>().add(index) }
+    }
+
+    // 已用环也按 u16 编号：0 是标志，1 是下标，之后每项是 4 个 u16（编号和长度各 32 位）
+    fn used(&self, index: usize) -> *mut u16 {
+        unsafe { self.base().add(self.used_offset).cast::<u16>().add(index) }
+    }
+}
+
+fn align_up(size: usize) -> usize {
+    (size + PAGE_SIZE - 1) & !(PAGE_SIZE - 1)
+}
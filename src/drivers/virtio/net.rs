@@ -0,0 +1,173 @@
+// virtio 网卡
+// 队列 0 接收、队列 1 发送。每个缓冲区占一个帧：开头是 virtio 的网络头，后面是以太网帧，
+// 头和帧分成两个描述符交给设备，这样不需要协商 ANY_LAYOUT。不协商校验和卸载和合并接收缓冲区，网络头总是 10 字节、全部为 0
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::ptr;
+
+use crate::drivers::pci::PciDriver;
+use crate::drivers::virtio::{Buffer, Transport, Virtqueue, VirtioError, VENDOR_ID};
+use crate::io::pci::{DeviceMatch, PciDevice};
+use crate::memory::dma::{self, BusAddr, DmaBuffer, MASK_64};
+use crate::net::{self, ethernet::MacAddr, NetDevice};
+
+/// 设备在配置空间里给出 MAC 地址
+const FEATURE_MAC: u32 = 1 << 5;
+// 设备配置：MAC 地址和链路状态
+const CONFIG_LEN: u16 = 8;
+
+const RECEIVE_QUEUE: u16 = 0;
+const TRANSMIT_QUEUE: u16 = 1;
+const HEADER_LEN: usize = 10;
+// 以太网帧放在缓冲区里的位置
+const DATA_OFFSET: usize = 64;
+const BUFFER_LEN: usize = 4096;
+const DATA_LEN: usize = BUFFER_LEN - DATA_OFFSET;
+const RECEIVE_BUFFERS: usize = 32;
+const TRANSMIT_BUFFERS: usize = 16;
+
+pub static DRIVER: PciDriver = PciDriver {
+    name: "virtio-net",
+    matches: &[DeviceMatch::Id { vendor_id: VENDOR_ID, device_id: 0x1000 }],
+    probe,
+};
+
+fn probe(device: &PciDevice) -> bool {
+    match VirtioNet::new(device) {
+        Ok(nic) => {
+            log::info!("virtio-net: MAC {}", nic.mac);
+            net::attach(Box::new(nic));
+            true
+        }
+        Err(error) => {
+            log::warn!("virtio-net: initialization failed: {:?}", error);
+            false
+        }
+    }
+}
+
+// 网络头和 `len` 字节的帧两段
+fn buffers(buffer: &DmaBuffer, len: usize, writable: bool) -> [Buffer; 2] {
+    let bus = buffer.bus().as_u64();
+    [
+        Buffer { addr: BusAddr::new(bus), len: HEADER_LEN as u32, writable },
+        Buffer { addr: BusAddr::new(bus + DATA_OFFSET as u64), len: len as u32, writable },
+    ]
+}
+
+pub struct VirtioNet {
+    transport: Transport,
+    mac: MacAddr,
+    receive: Virtqueue,
+    transmit: Virtqueue,
+    receive_buffers: Vec<DmaBuffer>,
+    transmit_buffers: Vec<DmaBuffer>,
+    // 描述符链头对应的缓冲区
+    receive_slots: Vec<usize>,
+    transmit_slots: Vec<usize>,
+    transmit_free: Vec<usize>,
+}
+
+impl VirtioNet {
+    fn new(device: &PciDevice) -> Result<Self, VirtioError> {
+        let transport = Transport::new(device, "virtio-net", CONFIG_LEN)?;
+        let features = transport.begin(FEATURE_MAC);
+        let queues = Virtqueue::new(&transport, device, RECEIVE_QUEUE)
+            .and_then(|receive| Ok((receive, Virtqueue::new(&transport, device, TRANSMIT_QUEUE)?)));
+        let buffers = (0..RECEIVE_BUFFERS + TRANSMIT_BUFFERS)
+            .map(|_| dma::alloc_coherent(device, BUFFER_LEN, BUFFER_LEN, MASK_64).map_err(|_| VirtioError::NoMemory))
+            .collect::<Result<Vec<_>, _>>();
+        let ((receive, transmit), mut receive_buffers) = match (queues, buffers) {
+            (Ok(queues), Ok(buffers)) => (queues, buffers),
+            (Err(error), _) | (_, Err(error)) => {
+                transport.fail();
+                return Err(error);
+            }
+        };
+        let transmit_buffers = receive_buffers.split_off(RECEIVE_BUFFERS);
+        // 没有 MAC 特性时用 QEMU 默认的地址
+        let mac = if features & FEATURE_MAC != 0 {
+            MacAddr(core::array::from_fn(|i| transport.config_u8(i as u16)))
+        } else {
+            MacAddr([0x52, 0x54, 0x00, 0x12, 0x34, 0x56])
+        };
+        let mut nic = VirtioNet {
+            mac,
+            receive_slots: alloc::vec![0; receive.size() as usize],
+            transmit_slots: alloc::vec![0; transmit.size() as usize],
+            transmit_free: (0..TRANSMIT_BUFFERS).collect(),
+            transport,
+            receive,
+            transmit,
+            receive_buffers,
+            transmit_buffers,
+        };
+        for slot in 0..RECEIVE_BUFFERS {
+            nic.post_receive(slot);
+        }
+        nic.transport.finish();
+        nic.transport.notify(RECEIVE_QUEUE);
+        Ok(nic)
+    }
+
+    fn post_receive(&mut self, slot: usize) {
+        let buffers = buffers(&self.receive_buffers[slot], DATA_LEN, true);
+        if let Some(head) = self.receive.push(&buffers) {
+            self.receive_slots[head as usize] = slot;
+        }
+    }
+
+    // 回收设备已经发送完的缓冲区
+    fn reclaim(&mut self) {
+        while let Some((head, _)) = self.transmit.pop_used() {
+            self.transmit_free.push(self.transmit_slots[head as usize]);
+        }
+    }
+}
+
+impl NetDevice for VirtioNet {
+    fn name(&self) -> &'static str {
+        DRIVER.name
+    }
+
+    fn mac(&self) -> MacAddr {
+        self.mac
+    }
+
+    fn transmit(&mut self, frame: &[u8]) -> bool {
+        self.reclaim();
+        if frame.len() > DATA_LEN {
+            return false;
+        }
+        let Some(slot) = self.transmit_free.pop() else { return false };
+        let buffer = &self.transmit_buffers[slot];
+        unsafe {
+            ptr::write_bytes(buffer.ptr(), 0, HEADER_LEN);
+            ptr::copy_nonoverlapping(frame.as_ptr(), buffer.ptr().add(DATA_OFFSET), frame.len());
+        }
+        let buffers = buffers(buffer, frame.len(), false);
+        let Some(head) = self.transmit.push(&buffers) else {
+            self.transmit_free.push(slot);
+            return false;
+        };
+        self.transmit_slots[head as usize] = slot;
+        self.transport.notify(TRANSMIT_QUEUE);
+        true
+    }
+
+    fn receive(&mut self) -> Option<Vec<u8>> {
+        let (head, len) = self.receive.pop_used()?;
+        let slot = self.receive_slots[head as usize];
+        let len = (len as usize).saturating_sub(HEADER_LEN).min(DATA_LEN);
+        let mut frame = Vec::with_capacity(len);
+        unsafe {
+            ptr::copy_nonoverlapping(self.receive_buffers[slot].ptr().add(DATA_OFFSET), frame.as_mut_ptr(), len);
+            frame.set_len(len);
+        }
+        // 缓冲区马上还给设备
+        self.post_receive(slot);
+        self.transport.notify(RECEIVE_QUEUE);
+        Some(frame)
+    }
+}
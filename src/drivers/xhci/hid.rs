@@ -0,0 +1,154 @@
+// USB HID 引导协议
+// 切到引导协议（SET_PROTOCOL 0）后键盘和鼠标的报告格式是固定的，不用解析报告描述符。
+// 键盘报告 8 字节：修饰键位图、保留、最多 6 个按着的键（HID 用法码）；和上一次的报告比较得出按下和松开，
+// 用法码换成 pc_keyboard 的 KeyCode，和 PS/2 键盘一样交给 interrupts::key_event。
+// 鼠标报告前 3 字节：按键、X、Y 的相对移动，Y 向下为正，有第 4 字节时是滚轮，换成 io::mouse 的 MouseEvent
+
+use pc_keyboard::KeyCode;
+
+use crate::io::mouse::MouseEvent;
+
+// 按键太多时 6 个位置都是这个，报告没有意义
+const ROLLOVER_ERROR: u8 = 0x01;
+
+// 修饰键位图从低位起依次是：左 Ctrl、左 Shift、左 Alt、左 GUI、右 Ctrl、右 Shift、右 Alt、右 GUI；GUI 键不处理
+const MODIFIERS: [Option<KeyCode>; 8] = [
+    Some(KeyCode::ControlLeft),
+    Some(KeyCode::ShiftLeft),
+    Some(KeyCode::AltLeft),
+    None,
+    Some(KeyCode::ControlRight),
+    Some(KeyCode::ShiftRight),
+    Some(KeyCode::AltRight),
+    None,
+];
+
+// 用法码 0x04 开始的 a 到 z
+const LETTERS: [KeyCode; 26] = [
+    KeyCode::A, KeyCode::B, KeyCode::C, KeyCode::D, KeyCode::E, KeyCode::F, KeyCode::G, KeyCode::H, KeyCode::I,
+    KeyCode::J, KeyCode::K, KeyCode::L, KeyCode::M, KeyCode::N, KeyCode::O, KeyCode::P, KeyCode::Q, KeyCode::R,
+    KeyCode::S, KeyCode::T, KeyCode::U, KeyCode::V, KeyCode::W, KeyCode::X, KeyCode::Y, KeyCode::Z,
+];
+
+// 用法码 0x1E 开始的 1 到 9、0
+const DIGITS: [KeyCode; 10] = [
+    KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4, KeyCode::Key5,
+    KeyCode::Key6, KeyCode::Key7, KeyCode::Key8, KeyCode::Key9, KeyCode::Key0,
+];
+
+// 用法码 0x3A 开始的 F1 到 F12
+const FUNCTION_KEYS: [KeyCode; 12] = [
+    KeyCode::F1, KeyCode::F2, KeyCode::F3, KeyCode::F4, KeyCode::F5, KeyCode::F6,
+    KeyCode::F7, KeyCode::F8, KeyCode::F9, KeyCode::F10, KeyCode::F11, KeyCode::F12,
+];
+
+// 用法码 0x59 开始的小键盘 1 到 9、0
+const NUMPAD_DIGITS: [KeyCode; 10] = [
+    KeyCode::Numpad1, KeyCode::Numpad2, KeyCode::Numpad3, KeyCode::Numpad4, KeyCode::Numpad5,
+    KeyCode::Numpad6, KeyCode::Numpad7, KeyCode::Numpad8, KeyCode::Numpad9, KeyCode::Numpad0,
+];
+
+/// 键盘用法码对应的按键，不认识的返回 None
+pub fn key_code(usage: u8) -> Option<KeyCode> {
+    Some(match usage {
+        0x04..=0x1D => LETTERS[(usage - 0x04) as usize],
+        0x1E..=0x27 => DIGITS[(usage - 0x1E) as usize],
+        0x28 => KeyCode::Enter,
+        0x29 => KeyCode::Escape,
+        0x2A => KeyCode::Backspace,
+        0x2B => KeyCode::Tab,
+        0x2C => KeyCode::Spacebar,
+        0x2D => KeyCode::Minus,
+        0x2E => KeyCode::Equals,
+        0x2F => KeyCode::BracketSquareLeft,
+        0x30 => KeyCode::BracketSquareRight,
+        // 0x32 是非美式键盘上 Enter 旁边的 # 键，和反斜杠在同一个位置
+        0x31 | 0x32 => KeyCode::BackSlash,
+        0x33 => KeyCode::SemiColon,
+        0x34 => KeyCode::Quote,
+        0x35 => KeyCode::BackTick,
+        0x36 => KeyCode::Comma,
+        0x37 => KeyCode::Fullstop,
+        0x38 => KeyCode::Slash,
+        0x39 => KeyCode::CapsLock,
+        0x3A..=0x45 => FUNCTION_KEYS[(usage - 0x3A) as usize],
+        0x47 => KeyCode::ScrollLock,
+        0x49 => KeyCode::Insert,
+        0x4A => KeyCode::Home,
+        0x4B => KeyCode::PageUp,
+        0x4C => KeyCode::Delete,
+        0x4D => KeyCode::End,
+        0x4E => KeyCode::PageDown,
+        0x4F => KeyCode::ArrowRight,
+        0x50 => KeyCode::ArrowLeft,
+        0x51 => KeyCode::ArrowDown,
+        0x52 => KeyCode::ArrowUp,
+        0x53 => KeyCode::NumpadLock,
+        0x54 => KeyCode::NumpadSlash,
+        0x55 => KeyCode::NumpadStar,
+        0x56 => KeyCode::NumpadMinus,
+        0x57 => KeyCode::NumpadPlus,
+        0x58 => KeyCode::NumpadEnter,
+        0x59..=0x62 => NUMPAD_DIGITS[(usage - 0x59) as usize],
+        0x63 => KeyCode::NumpadPeriod,
+        _ => return None,
+    })
+}
+
+/// 一个引导协议键盘，记着上一次的报告
+#[derive(Debug, Default)]
+pub struct BootKeyboard {
+    modifiers: u8,
+    keys: [u8; 6],
+}
+
+impl BootKeyboard {
+    pub const fn new() -> Self {
+        BootKeyboard { modifiers: 0, keys: [0; 6] }
+    }
+
+    /// 处理一个报告，对每个按下（true）和松开（false）的键调用 `emit`；先报告松开的键
+    pub fn report(&mut self, report: &[u8], mut emit: impl FnMut(KeyCode, bool)) {
+        let Some(&[modifiers, _, ref keys @ ..]) = report.get(..8) else {
+            return;
+        };
+        if keys.iter().all(|&usage| usage == ROLLOVER_ERROR) {
+            return;
+        }
+        let keys: [u8; 6] = keys.try_into().unwrap();
+        let (old_modifiers, old_keys) = (self.modifiers, self.keys);
+        for &usage in old_keys.iter().filter(|&&usage| usage != 0 && !keys.contains(&usage)) {
+            if let Some(code) = key_code(usage) {
+                emit(code, false);
+            }
+        }
+        for (bit, code) in MODIFIERS.iter().enumerate() {
+            let (was, is) = (old_modifiers & (1 << bit) != 0, modifiers & (1 << bit) != 0);
+            if let Some(code) = code.filter(|_| was != is) {
+                emit(code, is);
+            }
+        }
+        for &usage in keys.iter().filter(|&&usage| usage != 0 && !old_keys.contains(&usage)) {
+            if let Some(code) = key_code(usage) {
+                emit(code, true);
+            }
+        }
+        self.modifiers = modifiers;
+        self.keys = keys;
+    }
+}
+
+/// 解码引导协议的鼠标报告，不到 3 字节时返回 None
+pub fn mouse_event(report: &[u8]) -> Option<MouseEvent> {
+    let &[buttons, x, y, ..] = report else {
+        return None;
+    };
+    Some(MouseEvent {
+        dx: x as i8 as i16,
+        dy: -(y as i8 as i16),
+        left: buttons & 0x01 != 0,
+        right: buttons & 0x02 != 0,
+        middle: buttons & 0x04 != 0,
+        wheel: report.get(3).map_or(0, |&wheel| wheel as i8 as i16),
+    })
+}
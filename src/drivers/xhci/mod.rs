@@ -0,0 +1,819 @@
+// xHCI USB 主控制器
+// 真机上不一定有 PS/2 模拟，USB 键盘和鼠标要自己驱动。按 PCI 类别码（串行总线 / USB / xHCI）找到控制器，寄存器在 BAR0 里。
+// 先从 BIOS 手里要回控制器（USB Legacy Support），复位后建立设备上下文表、命令环和一个事件环，不用中断：
+// 初始化时同步等待命令完成，之后由 poll（shell 空闲时调用）处理事件环。
+// 只枚举根集线器端口上直接接着的设备，不支持外接集线器；每个设备取第一个 HID 引导协议接口（键盘或鼠标），
+// 切到引导协议后在它的中断 IN 端点上始终挂着一个传输，报告到了就解码（见 hid），再挂上下一个。
+// 键盘的按键交给 interrupts::key_event，和 PS/2 键盘走同一条路；鼠标事件放进 io::mouse 的队列。
+// USB 键盘自己不重复按键，按住不放只算按一次；指示灯也不更新。
+// QEMU 里这样接：-device qemu-xhci -device usb-kbd -device usb-mouse
+
+pub mod hid;
+
+use alloc::vec::Vec;
+use core::ptr;
+use core::sync::atomic::{fence, Ordering};
+use core::time::Duration;
+
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::{PhysAddr, VirtAddr};
+
+use crate::boot::Unit;
+use crate::drivers::pci::{self, PciDriver};
+use crate::drivers::xhci::hid::BootKeyboard;
+use crate::io::input::{self, DeviceId, DeviceKind};
+use crate::io::mouse;
+use crate::io::pci::{DeviceMatch, PciDevice};
+use crate::io::timer::uptime;
+use crate::memory::{self, dma::{self, DmaBuffer, MASK_32, MASK_64}};
+
+// 能力寄存器
+const CAPLENGTH: usize = 0x00;
+const HCSPARAMS1: usize = 0x04;
+const HCSPARAMS2: usize = 0x08;
+const HCCPARAMS1: usize = 0x10;
+const DBOFF: usize = 0x14;
+const RTSOFF: usize = 0x18;
+// 64 位地址、64 字节的上下文
+const HCC_AC64: u32 = 1 << 0;
+const HCC_CSZ: u32 = 1 << 2;
+
+// 操作寄存器，相对于 CAPLENGTH
+const USBCMD: usize = 0x00;
+const USBSTS: usize = 0x04;
+const CRCR: usize = 0x18;
+const DCBAAP: usize = 0x30;
+const CONFIG: usize = 0x38;
+const PORTSC: usize = 0x400;
+const PORT_SIZE: usize = 0x10;
+const CMD_RUN: u32 = 1 << 0;
+const CMD_RESET: u32 = 1 << 1;
+const STS_HALTED: u32 = 1 << 0;
+const STS_NOT_READY: u32 = 1 << 11;
+
+// 端口状态：已连接、已启用、复位，速度在 10 到 13 位；17 到 23 位是写 1 清零的变化位，写回时不能带上，
+// 启用位也是写 1 关闭
+const PORT_CONNECTED: u32 = 1 << 0;
+const PORT_ENABLED: u32 = 1 << 1;
+const PORT_RESET: u32 = 1 << 4;
+const PORT_RESET_CHANGE: u32 = 1 << 21;
+const PORT_CHANGES: u32 = 0x7F << 17;
+
+// 0 号中断器的寄存器，相对于 RTSOFF
+const ERSTSZ: usize = 0x28;
+const ERSTBA: usize = 0x30;
+const ERDP: usize = 0x38;
+// 事件处理中，写 1 清零
+const ERDP_BUSY: u64 = 1 << 3;
+
+// 扩展能力：USB Legacy Support，BIOS 和系统各占一个所有权位
+const EXT_LEGACY: u32 = 1;
+const LEGACY_BIOS_OWNED: u32 = 1 << 16;
+const LEGACY_OS_OWNED: u32 = 1 << 24;
+
+// TRB 类型，在控制字的 10 到 15 位
+const TRB_NORMAL: u32 = 1;
+const TRB_SETUP: u32 = 2;
+const TRB_DATA: u32 = 3;
+const TRB_STATUS: u32 = 4;
+const TRB_LINK: u32 = 6;
+const TRB_ENABLE_SLOT: u32 = 9;
+const TRB_DISABLE_SLOT: u32 = 10;
+const TRB_ADDRESS_DEVICE: u32 = 11;
+const TRB_CONFIGURE_ENDPOINT: u32 = 12;
+const TRB_EVALUATE_CONTEXT: u32 = 13;
+const TRB_TRANSFER_EVENT: u32 = 32;
+const TRB_COMMAND_COMPLETION: u32 = 33;
+// 控制字里的标志
+const TRB_CYCLE: u32 = 1 << 0;
+const TRB_TOGGLE_CYCLE: u32 = 1 << 1;
+const TRB_SHORT_OK: u32 = 1 << 2;
+const TRB_IOC: u32 = 1 << 5;
+const TRB_IMMEDIATE: u32 = 1 << 6;
+const TRB_DIR_IN: u32 = 1 << 16;
+// 建立阶段的数据方向：没有数据、读
+const SETUP_NO_DATA: u32 = 0;
+const SETUP_IN: u32 = 3 << 16;
+
+// 完成码
+const COMPLETION_SUCCESS: u8 = 1;
+const COMPLETION_SHORT_PACKET: u8 = 13;
+
+// 端点类型
+const ENDPOINT_CONTROL: u32 = 4;
+const ENDPOINT_INTERRUPT_IN: u32 = 7;
+
+// 端口速度
+const SPEED_FULL: u32 = 1;
+const SPEED_LOW: u32 = 2;
+const SPEED_HIGH: u32 = 3;
+
+// 标准请求和 HID 类请求
+const GET_DESCRIPTOR: u8 = 6;
+const SET_CONFIGURATION: u8 = 9;
+const HID_SET_IDLE: u8 = 0x0A;
+const HID_SET_PROTOCOL: u8 = 0x0B;
+const REQUEST_IN: u8 = 0x80;
+const REQUEST_CLASS_INTERFACE: u8 = 0x21;
+const DESCRIPTOR_DEVICE: u16 = 1;
+const DESCRIPTOR_CONFIGURATION: u16 = 2;
+const DESCRIPTOR_INTERFACE: u8 = 4;
+const DESCRIPTOR_ENDPOINT: u8 = 5;
+// HID 类、引导接口子类，协议 1 是键盘、2 是鼠标
+const CLASS_HID: u8 = 3;
+const SUBCLASS_BOOT: u8 = 1;
+const PROTOCOL_KEYBOARD: u8 = 1;
+const PROTOCOL_MOUSE: u8 = 2;
+
+const PAGE_SIZE: usize = 4096;
+const TRB_SIZE: usize = 16;
+// 一个环占一页，最后一项是指回开头的链接 TRB
+const RING_TRBS: usize = PAGE_SIZE / TRB_SIZE;
+// 最多启用的设备槽
+const MAX_SLOTS: usize = 32;
+const RESET_TIMEOUT: Duration = Duration::from_secs(1);
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub static DRIVER: PciDriver = PciDriver {
+    name: "xhci",
+    matches: &[DeviceMatch::Class { class: 0x0C, subclass: 0x03, prog_if: Some(0x30) }],
+    probe,
+};
+
+/// 注册 USB 主控制器驱动，驱动要分配 DMA 内存
+pub fn init() {
+    pci::register(&DRIVER);
+}
+
+pub static UNIT: Unit = Unit {
+    name: "usb",
+    depends: &["pci", "frame-pool"],
+    init: |_| {
+        init();
+        Ok(())
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XhciError {
+    /// BAR0 不是内存空间
+    NoMmio,
+    /// 寄存器映射不上
+    MapFailed,
+    /// 分配不到 DMA 内存
+    NoMemory,
+    /// 控制器停不下来、复位不完，或者命令、传输没有在规定时间内完成
+    Timeout,
+    /// 命令或者传输的完成码不是成功
+    Failed(u8),
+}
+
+static CONTROLLERS: Mutex<Vec<Controller>> = Mutex::new(Vec::new());
+
+fn probe(device: &PciDevice) -> bool {
+    match Controller::new(device) {
+        Ok(mut controller) => {
+            controller.enumerate();
+            log::info!("xhci: {} HID devices", controller.devices.len());
+            CONTROLLERS.lock().push(controller);
+            true
+        }
+        Err(error) => {
+            log::warn!("xhci: initialization failed: {:?}", error);
+            false
+        }
+    }
+}
+
+/// 处理所有控制器收到的报告，shell 空闲时调用
+pub fn poll() {
+    for controller in CONTROLLERS.lock().iter_mut() {
+        controller.poll();
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Registers {
+    base: VirtAddr,
+}
+
+impl Registers {
+    fn read(&self, offset: usize) -> u32 {
+        unsafe { ptr::read_volatile((self.base + offset).as_ptr()) }
+    }
+
+    fn write(&self, offset: usize, value: u32) {
+        unsafe { ptr::write_volatile((self.base + offset).as_mut_ptr(), value) }
+    }
+
+    // 64 位寄存器分两次写，先写低 32 位
+    fn write64(&self, offset: usize, value: u64) {
+        self.write(offset, value as u32);
+        self.write(offset + 4, (value >> 32) as u32);
+    }
+
+    // 等到 `offset` 寄存器里的 `bits` 等于 `value`
+    fn wait(&self, offset: usize, bits: u32, value: u32, timeout: Duration) -> Result<(), XhciError> {
+        let deadline = uptime() + timeout;
+        while self.read(offset) & bits != value {
+            if uptime() >= deadline {
+                return Err(XhciError::Timeout);
+            }
+            core::hint::spin_loop();
+        }
+        Ok(())
+    }
+}
+
+// 所有环上的一项
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct Trb {
+    parameter: u64,
+    status: u32,
+    control: u32,
+}
+
+impl Trb {
+    fn new(kind: u32, parameter: u64, status: u32, flags: u32) -> Self {
+        Trb { parameter, status, control: kind << 10 | flags }
+    }
+
+    fn kind(&self) -> u32 {
+        (self.control >> 10) & 0x3F
+    }
+
+    fn completion(&self) -> u8 {
+        (self.status >> 24) as u8
+    }
+
+    fn slot(&self) -> u8 {
+        (self.control >> 24) as u8
+    }
+
+    // 传输事件里的端点编号
+    fn endpoint(&self) -> u8 {
+        ((self.control >> 16) & 0x1F) as u8
+    }
+
+    fn check(self) -> Result<Self, XhciError> {
+        match self.completion() {
+            COMPLETION_SUCCESS | COMPLETION_SHORT_PACKET => Ok(self),
+            code => Err(XhciError::Failed(code)),
+        }
+    }
+}
+
+// 命令环和传输环，由软件写入、控制器读取
+struct Ring {
+    memory: DmaBuffer,
+    enqueue: usize,
+    cycle: bool,
+}
+
+impl Ring {
+    fn new(device: &PciDevice, mask: u64) -> Result<Self, XhciError> {
+        let memory = dma::alloc_coherent(device, PAGE_SIZE, PAGE_SIZE, mask).map_err(|_| XhciError::NoMemory)?;
+        let ring = Ring { memory, enqueue: 0, cycle: true };
+        let link = Trb::new(TRB_LINK, ring.bus(), 0, TRB_TOGGLE_CYCLE);
+        unsafe { ptr::write_volatile(ring.slot(RING_TRBS - 1), link) };
+        Ok(ring)
+    }
+
+    fn bus(&self) -> u64 {
+        self.memory.bus().as_u64()
+    }
+
+    fn slot(&self, index: usize) -> *mut Trb {
+        unsafe { self.memory.ptr().add(index * TRB_SIZE).cast() }
+    }
+
+    // 放进一项，返回它的总线地址；先按相反的循环位写好，最后才翻转循环位交给控制器
+    fn push(&mut self, trb: Trb) -> u64 {
+        let address = self.bus() + (self.enqueue * TRB_SIZE) as u64;
+        let cycle = if self.cycle { TRB_CYCLE } else { 0 };
+        unsafe {
+            let slot = self.slot(self.enqueue);
+            ptr::write_volatile(slot, Trb { control: (trb.control & !TRB_CYCLE) | (cycle ^ TRB_CYCLE), ..trb });
+            fence(Ordering::SeqCst);
+            ptr::write_volatile(ptr::addr_of_mut!((*slot).control), trb.control | cycle);
+        }
+        self.enqueue += 1;
+        // 到了链接 TRB，把它交给控制器，然后回到开头并翻转循环位
+        if self.enqueue == RING_TRBS - 1 {
+            unsafe {
+                let link = self.slot(self.enqueue);
+                let control = ptr::read_volatile(ptr::addr_of!((*link).control)) & !TRB_CYCLE;
+                ptr::write_volatile(ptr::addr_of_mut!((*link).control), control | cycle);
+            }
+            self.enqueue = 0;
+            self.cycle = !self.cycle;
+        }
+        address
+    }
+}
+
+// 事件环，由控制器写入、软件读取；第一页是环，后面是只有一项的段表
+struct EventRing {
+    memory: DmaBuffer,
+    dequeue: usize,
+    cycle: bool,
+}
+
+impl EventRing {
+    fn new(device: &PciDevice, mask: u64) -> Result<Self, XhciError> {
+        let memory = dma::alloc_coherent(device, 2 * PAGE_SIZE, PAGE_SIZE, mask).map_err(|_| XhciError::NoMemory)?;
+        let table = [memory.bus().as_u64(), RING_TRBS as u64];
+        unsafe { ptr::write_volatile(memory.ptr().add(PAGE_SIZE).cast::<[u64; 2]>(), table) };
+        Ok(EventRing { memory, dequeue: 0, cycle: true })
+    }
+
+    fn table(&self) -> u64 {
+        self.memory.bus().as_u64() + PAGE_SIZE as u64
+    }
+
+    // 取出下一个事件，循环位不对表示控制器还没有写
+    fn next(&mut self) -> Option<Trb> {
+        let slot = unsafe { self.memory.ptr().add(self.dequeue * TRB_SIZE).cast::<Trb>() };
+        let control = unsafe { ptr::read_volatile(ptr::addr_of!((*slot).control)) };
+        if (control & TRB_CYCLE != 0) != self.cycle {
+            return None;
+        }
+        // 循环位对了之后再读其余字段
+        fence(Ordering::SeqCst);
+        let trb = unsafe { ptr::read_volatile(slot) };
+        self.dequeue += 1;
+        if self.dequeue == RING_TRBS {
+            self.dequeue = 0;
+            self.cycle = !self.cycle;
+        }
+        Some(trb)
+    }
+
+    // 告诉控制器已经处理到哪里
+    fn acknowledge(&self, interrupter: Registers) {
+        let dequeue = self.memory.bus().as_u64() + (self.dequeue * TRB_SIZE) as u64;
+        interrupter.write64(ERDP, dequeue | ERDP_BUSY);
+    }
+}
+
+// 设备的输出上下文由控制器维护，输入上下文用来给命令传参数；各占一页
+struct Contexts {
+    output: DmaBuffer,
+    input: DmaBuffer,
+    size: usize,
+}
+
+impl Contexts {
+    fn new(device: &PciDevice, mask: u64, size: usize) -> Result<Self, XhciError> {
+        let alloc = || dma::alloc_coherent(device, PAGE_SIZE, PAGE_SIZE, mask).map_err(|_| XhciError::NoMemory);
+        Ok(Contexts { output: alloc()?, input: alloc()?, size })
+    }
+
+    // 清空输入上下文，只让 `add` 里的上下文生效
+    fn prepare(&mut self, add: u32) {
+        let input = self.input.as_mut_slice();
+        input.fill(0);
+        input[4..8].copy_from_slice(&add.to_le_bytes());
+    }
+
+    // 输入上下文里第 `index` 个上下文（0 是设备槽，1 是 0 号端点）的第 `dword` 个双字
+    fn set(&mut self, index: usize, dword: usize, value: u32) {
+        let offset = self.size * (index + 1) + dword * 4;
+        self.input.as_mut_slice()[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+}
+
+// 接管的 HID 设备
+enum Kind {
+    Keyboard(BootKeyboard),
+    // io::input 的登记表满了时没有设备号，报告被丢弃
+    Mouse(Option<DeviceId>),
+}
+
+struct Device {
+    slot: u8,
+    // 中断 IN 端点的上下文编号
+    endpoint: u8,
+    kind: Kind,
+    max_packet: usize,
+    ring: Ring,
+    report: DmaBuffer,
+    _control: Ring,
+    _contexts: Contexts,
+}
+
+// 配置描述符里找到的引导协议接口
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BootInterface {
+    configuration: u8,
+    interface: u8,
+    protocol: u8,
+    endpoint: u8,
+    max_packet: u16,
+    interval: u8,
+}
+
+pub struct Controller {
+    pci: PciDevice,
+    mask: u64,
+    operational: Registers,
+    interrupter: Registers,
+    doorbells: Registers,
+    ports: usize,
+    context_size: usize,
+    dcbaa: DmaBuffer,
+    _scratchpad: Vec<DmaBuffer>,
+    commands: Ring,
+    events: EventRing,
+    // 读描述符用的缓冲区
+    buffer: DmaBuffer,
+    devices: Vec<Device>,
+}
+
+impl Controller {
+    fn new(pci: &PciDevice) -> Result<Self, XhciError> {
+        let bar = pci.bar(0);
+        if bar & 1 != 0 {
+            return Err(XhciError::NoMmio);
+        }
+        // 64 位 BAR 的高 32 位在下一个 BAR 里
+        let mut phys = (bar & !0xF) as u64;
+        if (bar >> 1) & 3 == 2 {
+            phys |= (pci.bar(1) as u64) << 32;
+        }
+        // 先映射能力寄存器，算出要映射多大再映射整个寄存器区
+        let caps = memory::map_mmio(PhysAddr::new(phys), PAGE_SIZE as u64).map_err(|_| XhciError::MapFailed)?;
+        let caps = Registers { base: caps.start() };
+        let operational = caps.read(CAPLENGTH) as usize & 0xFF;
+        let params = caps.read(HCSPARAMS1);
+        let (slots, ports) = ((params & 0xFF) as usize, (params >> 24) as usize);
+        let (runtime, doorbells) = (caps.read(RTSOFF) as usize & !0x1F, caps.read(DBOFF) as usize & !0x3);
+        let size = (operational + PORTSC + PORT_SIZE * ports).max(runtime + 0x40).max(doorbells + 4 * (slots + 1));
+        let region = memory::map_mmio(PhysAddr::new(phys), size as u64).map_err(|_| XhciError::MapFailed)?;
+        let base = region.start();
+        let hcc = caps.read(HCCPARAMS1);
+        let mask = if hcc & HCC_AC64 != 0 { MASK_64 } else { MASK_32 };
+        pci.enable_bus_master();
+        take_ownership(Registers { base }, hcc);
+
+        let operational = Registers { base: base + operational };
+        // 先停下来再复位
+        operational.write(USBCMD, operational.read(USBCMD) & !CMD_RUN);
+        operational.wait(USBSTS, STS_HALTED, STS_HALTED, RESET_TIMEOUT)?;
+        operational.write(USBCMD, CMD_RESET);
+        operational.wait(USBCMD, CMD_RESET, 0, RESET_TIMEOUT)?;
+        operational.wait(USBSTS, STS_NOT_READY, 0, RESET_TIMEOUT)?;
+
+        let slots = slots.min(MAX_SLOTS);
+        let alloc = |size| dma::alloc_coherent(pci, size, PAGE_SIZE, mask).map_err(|_| XhciError::NoMemory);
+        let mut dcbaa = alloc(PAGE_SIZE)?;
+        // 控制器要的暂存页，数组的地址放在设备上下文表的第 0 项
+        let hcs2 = caps.read(HCSPARAMS2);
+        let count = ((hcs2 >> 27) & 0x1F | ((hcs2 >> 21) & 0x1F) << 5) as usize;
+        let mut scratchpad = Vec::new();
+        if count > 0 {
+            let mut array = alloc(count * 8)?;
+            for i in 0..count {
+                let page = alloc(PAGE_SIZE)?;
+                array.as_mut_slice()[i * 8..i * 8 + 8].copy_from_slice(&page.bus().as_u64().to_le_bytes());
+                scratchpad.push(page);
+            }
+            dcbaa.as_mut_slice()[..8].copy_from_slice(&array.bus().as_u64().to_le_bytes());
+            scratchpad.push(array);
+        }
+        let commands = Ring::new(pci, mask)?;
+        let events = EventRing::new(pci, mask)?;
+        let buffer = alloc(PAGE_SIZE)?;
+
+        operational.write(CONFIG, slots as u32);
+        operational.write64(DCBAAP, dcbaa.bus().as_u64());
+        operational.write64(CRCR, commands.bus() | TRB_CYCLE as u64);
+        let interrupter = Registers { base: base + runtime };
+        interrupter.write(ERSTSZ, 1);
+        interrupter.write64(ERDP, events.memory.bus().as_u64());
+        // 段表地址最后写，写了之后事件环才生效
+        interrupter.write64(ERSTBA, events.table());
+        operational.write(USBCMD, CMD_RUN);
+        operational.wait(USBSTS, STS_HALTED, 0, RESET_TIMEOUT)?;
+
+        Ok(Controller {
+            pci: *pci,
+            mask,
+            operational,
+            interrupter,
+            doorbells: Registers { base: base + doorbells },
+            ports,
+            context_size: if hcc & HCC_CSZ != 0 { 64 } else { 32 },
+            dcbaa,
+            _scratchpad: scratchpad,
+            commands,
+            events,
+            buffer,
+            devices: Vec::new(),
+        })
+    }
+
+    // 逐个复位接了设备的端口并初始化上面的设备
+    fn enumerate(&mut self) {
+        for port in 1..=self.ports {
+            let offset = PORTSC + PORT_SIZE * (port - 1);
+            if self.operational.read(offset) & PORT_CONNECTED == 0 {
+                continue;
+            }
+            if let Err(error) = self.reset_port(offset) {
+                log::warn!("xhci: port {}: reset failed: {:?}", port, error);
+                continue;
+            }
+            let speed = (self.operational.read(offset) >> 10) & 0xF;
+            if let Err(error) = self.attach(port as u8, speed) {
+                log::warn!("xhci: port {}: {:?}", port, error);
+            }
+        }
+    }
+
+    // USB 3 的端口连接时自己启用，USB 2 的端口要复位之后才启用
+    fn reset_port(&self, offset: usize) -> Result<(), XhciError> {
+        let registers = self.operational;
+        let status = registers.read(offset);
+        if status & PORT_ENABLED != 0 {
+            return Ok(());
+        }
+        registers.write(offset, (status & !(PORT_ENABLED | PORT_CHANGES)) | PORT_RESET);
+        registers.wait(offset, PORT_RESET_CHANGE, PORT_RESET_CHANGE, RESET_TIMEOUT)?;
+        let status = registers.read(offset);
+        registers.write(offset, (status & !(PORT_ENABLED | PORT_CHANGES)) | PORT_RESET_CHANGE);
+        if registers.read(offset) & PORT_ENABLED == 0 {
+            return Err(XhciError::Timeout);
+        }
+        Ok(())
+    }
+
+    // 启用一个设备槽，给设备分配地址；是 HID 引导协议设备时接管它，否则关掉设备槽
+    fn attach(&mut self, port: u8, speed: u32) -> Result<(), XhciError> {
+        let slot = self.command(Trb::new(TRB_ENABLE_SLOT, 0, 0, 0))?.slot();
+        let mut contexts = Contexts::new(&self.pci, self.mask, self.context_size)?;
+        let mut control = Ring::new(&self.pci, self.mask)?;
+        self.set_context(slot, contexts.output.bus().as_u64());
+        match self.configure(slot, port, speed, &mut contexts, &mut control) {
+            Ok(Some((interface, ring, report))) => {
+                let (kind, name) = if interface.protocol == PROTOCOL_KEYBOARD {
+                    (Kind::Keyboard(BootKeyboard::new()), "keyboard")
+                } else {
+                    (Kind::Mouse(input::register_device("USB mouse", DeviceKind::Mouse)), "mouse")
+                };
+                log::info!("xhci: port {}: USB {}", port, name);
+                let mut device = Device {
+                    slot,
+                    endpoint: interface.endpoint,
+                    kind,
+                    max_packet: interface.max_packet as usize,
+                    ring,
+                    report,
+                    _control: control,
+                    _contexts: contexts,
+                };
+                self.queue_report(&mut device);
+                self.devices.push(device);
+                Ok(())
+            }
+            other => {
+                // 关掉设备槽之后控制器不再访问上下文，才能释放它们
+                self.command(Trb::new(TRB_DISABLE_SLOT, 0, 0, (slot as u32) << 24))?;
+                self.set_context(slot, 0);
+                other.map(|_| ())
+            }
+        }
+    }
+
+    fn set_context(&mut self, slot: u8, address: u64) {
+        let offset = slot as usize * 8;
+        self.dcbaa.as_mut_slice()[offset..offset + 8].copy_from_slice(&address.to_le_bytes());
+    }
+
+    // 分配地址、读描述符；找到引导协议接口时切到引导协议并打开它的中断端点，返回接口、传输环和报告缓冲区
+    #[allow(clippy::type_complexity)]
+    fn configure(&mut self, slot: u8, port: u8, speed: u32, contexts: &mut Contexts, control: &mut Ring)
+                 -> Result<Option<(BootInterface, Ring, DmaBuffer)>, XhciError> {
+        // 全速设备的 0 号端点包长要从设备描述符里读，先按 8 字节
+        let max_packet = match speed {
+            SPEED_LOW | SPEED_FULL => 8,
+            SPEED_HIGH => 64,
+            _ => 512,
+        };
+        contexts.prepare(0b11);
+        contexts.set(0, 0, speed << 20 | 1 << 27);
+        contexts.set(0, 1, (port as u32) << 16);
+        contexts.set(1, 1, 3 << 1 | ENDPOINT_CONTROL << 3 | max_packet << 16);
+        contexts.set(1, 2, control.bus() as u32 | TRB_CYCLE);
+        contexts.set(1, 3, (control.bus() >> 32) as u32);
+        contexts.set(1, 4, 8);
+        let input = contexts.input.bus().as_u64();
+        self.command(Trb::new(TRB_ADDRESS_DEVICE, input, 0, (slot as u32) << 24))?;
+
+        self.control(slot, control, REQUEST_IN, GET_DESCRIPTOR, DESCRIPTOR_DEVICE << 8, 0, 8)?;
+        let device_max_packet = self.buffer.as_slice()[7] as u32;
+        if speed == SPEED_FULL && device_max_packet != max_packet && device_max_packet != 0 {
+            contexts.prepare(0b10);
+            contexts.set(1, 1, 3 << 1 | ENDPOINT_CONTROL << 3 | device_max_packet << 16);
+            self.command(Trb::new(TRB_EVALUATE_CONTEXT, input, 0, (slot as u32) << 24))?;
+        }
+
+        self.control(slot, control, REQUEST_IN, GET_DESCRIPTOR, DESCRIPTOR_CONFIGURATION << 8, 0, 9)?;
+        let total = u16::from_le_bytes([self.buffer.as_slice()[2], self.buffer.as_slice()[3]]).min(PAGE_SIZE as u16);
+        self.control(slot, control, REQUEST_IN, GET_DESCRIPTOR, DESCRIPTOR_CONFIGURATION << 8, 0, total)?;
+        let Some(interface) = find_boot_interface(&self.buffer.as_slice()[..total as usize]) else {
+            return Ok(None);
+        };
+
+        self.control(slot, control, 0, SET_CONFIGURATION, interface.configuration as u16, 0, 0)?;
+        self.control(slot, control, REQUEST_CLASS_INTERFACE, HID_SET_PROTOCOL, 0, interface.interface as u16, 0)?;
+        // 键盘只在按键变化时报告；有的设备不支持，失败了也没关系
+        if interface.protocol == PROTOCOL_KEYBOARD {
+            let _ = self.control(slot, control, REQUEST_CLASS_INTERFACE, HID_SET_IDLE, 0, interface.interface as u16, 0);
+        }
+
+        // 中断端点的间隔按 2 的幂个 125 微秒算，全速和低速设备的 bInterval 是毫秒数
+        let interval = match speed {
+            SPEED_LOW | SPEED_FULL => (interface.interval.max(1) as u32 * 8).ilog2(),
+            _ => interface.interval.clamp(1, 16) as u32 - 1,
+        };
+        let ring = Ring::new(&self.pci, self.mask)?;
+        let report = dma::alloc_coherent(&self.pci, interface.max_packet as usize, 64, self.mask)
+            .map_err(|_| XhciError::NoMemory)?;
+        let endpoint = interface.endpoint as usize;
+        let packet = interface.max_packet as u32;
+        contexts.prepare(1 | 1 << endpoint);
+        contexts.set(0, 0, speed << 20 | (endpoint as u32) << 27);
+        contexts.set(0, 1, (port as u32) << 16);
+        contexts.set(endpoint, 0, interval << 16);
+        contexts.set(endpoint, 1, 3 << 1 | ENDPOINT_INTERRUPT_IN << 3 | packet << 16);
+        contexts.set(endpoint, 2, ring.bus() as u32 | TRB_CYCLE);
+        contexts.set(endpoint, 3, (ring.bus() >> 32) as u32);
+        contexts.set(endpoint, 4, packet << 16 | packet);
+        self.command(Trb::new(TRB_CONFIGURE_ENDPOINT, input, 0, (slot as u32) << 24))?;
+        Ok(Some((interface, ring, report)))
+    }
+
+    // 在 0 号端点上做一次控制传输，读到的数据放在 buffer 里
+    #[allow(clippy::too_many_arguments)]
+    fn control(&mut self, slot: u8, ring: &mut Ring, request_type: u8, request: u8, value: u16, index: u16, length: u16)
+               -> Result<(), XhciError> {
+        let setup = request_type as u64 | (request as u64) << 8 | (value as u64) << 16 | (index as u64) << 32
+            | (length as u64) << 48;
+        let direction = if length > 0 { SETUP_IN } else { SETUP_NO_DATA };
+        ring.push(Trb::new(TRB_SETUP, setup, 8, TRB_IMMEDIATE | direction));
+        if length > 0 {
+            ring.push(Trb::new(TRB_DATA, self.buffer.bus().as_u64(), length as u32, TRB_DIR_IN));
+        }
+        // 状态阶段的方向和数据阶段相反，没有数据阶段时是 IN
+        let status_direction = if length > 0 { 0 } else { TRB_DIR_IN };
+        ring.push(Trb::new(TRB_STATUS, 0, 0, TRB_IOC | status_direction));
+        self.ring_doorbell(slot, 1);
+        self.wait(|event| event.kind() == TRB_TRANSFER_EVENT && event.slot() == slot && event.endpoint() == 1)?
+            .check()
+            .map(|_| ())
+    }
+
+    // 执行一条命令，返回命令完成事件
+    fn command(&mut self, trb: Trb) -> Result<Trb, XhciError> {
+        let address = self.commands.push(trb);
+        self.ring_doorbell(0, 0);
+        self.wait(|event| event.kind() == TRB_COMMAND_COMPLETION && event.parameter == address)?.check()
+    }
+
+    fn ring_doorbell(&self, slot: u8, target: u8) {
+        fence(Ordering::SeqCst);
+        self.doorbells.write(slot as usize * 4, target as u32);
+    }
+
+    // 等待满足 `matches` 的事件，期间收到的其他事件照常处理
+    fn wait(&mut self, matches: impl Fn(&Trb) -> bool) -> Result<Trb, XhciError> {
+        let deadline = uptime() + COMMAND_TIMEOUT;
+        loop {
+            while let Some(event) = self.events.next() {
+                if matches(&event) {
+                    self.events.acknowledge(self.interrupter);
+                    return Ok(event);
+                }
+                self.dispatch(&event);
+            }
+            self.events.acknowledge(self.interrupter);
+            if uptime() >= deadline {
+                return Err(XhciError::Timeout);
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    fn poll(&mut self) {
+        let mut handled = false;
+        while let Some(event) = self.events.next() {
+            self.dispatch(&event);
+            handled = true;
+        }
+        if handled {
+            self.events.acknowledge(self.interrupter);
+        }
+    }
+
+    // 处理中断端点的传输事件，其他事件（比如端口状态变化）忽略
+    fn dispatch(&mut self, event: &Trb) {
+        if event.kind() != TRB_TRANSFER_EVENT {
+            return;
+        }
+        let Some(index) = self.devices.iter().position(|d| d.slot == event.slot() && d.endpoint == event.endpoint()) else {
+            return;
+        };
+        let mut device = self.devices.swap_remove(index);
+        match event.check() {
+            Ok(_) => {
+                // 状态的低 24 位是没有传完的字节数
+                let length = device.max_packet.saturating_sub((event.status & 0xFF_FFFF) as usize);
+                let report = &device.report.as_slice()[..length];
+                match &mut device.kind {
+                    Kind::Keyboard(keyboard) => keyboard.report(report, crate::interrupts::key_event),
+                    Kind::Mouse(id) => {
+                        if let (Some(event), Some(id)) = (hid::mouse_event(report), *id) {
+                            interrupts::without_interrupts(|| mouse::push_event_from(id, event));
+                        }
+                    }
+                }
+                self.queue_report(&mut device);
+            }
+            // 端点停下来了，不再挂传输
+            Err(error) => log::warn!("xhci: slot {}: report failed: {:?}", device.slot, error),
+        }
+        self.devices.push(device);
+    }
+
+    // 在中断端点上挂一个读报告的传输
+    fn queue_report(&self, device: &mut Device) {
+        let trb = Trb::new(TRB_NORMAL, device.report.bus().as_u64(), device.max_packet as u32, TRB_IOC | TRB_SHORT_OK);
+        device.ring.push(trb);
+        self.ring_doorbell(device.slot, device.endpoint);
+    }
+}
+
+// 从 BIOS 手里要回控制器：设置系统所有权位，等 BIOS 放手，再关掉它的 SMI
+fn take_ownership(caps: Registers, hcc: u32) {
+    let mut offset = ((hcc >> 16) as usize) << 2;
+    while offset != 0 {
+        let capability = caps.read(offset);
+        if capability & 0xFF == EXT_LEGACY {
+            caps.write(offset, capability | LEGACY_OS_OWNED);
+            if caps.wait(offset, LEGACY_BIOS_OWNED, 0, RESET_TIMEOUT).is_err() {
+                log::warn!("xhci: BIOS did not release the controller");
+            }
+            caps.write(offset + 4, caps.read(offset + 4) & !0x0000_E011);
+            return;
+        }
+        offset = match (capability >> 8) & 0xFF {
+            0 => 0,
+            next => offset + ((next as usize) << 2),
+        };
+    }
+}
+
+// 在配置描述符里找第一个带中断 IN 端点的 HID 引导协议接口
+fn find_boot_interface(config: &[u8]) -> Option<BootInterface> {
+    let configuration = *config.get(5)?;
+    let mut current: Option<BootInterface> = None;
+    let mut offset = 0;
+    while offset + 2 <= config.len() {
+        let length = config[offset] as usize;
+        if length < 2 || offset + length > config.len() {
+            return None;
+        }
+        let descriptor = &config[offset..offset + length];
+        match descriptor[1] {
+            DESCRIPTOR_INTERFACE if length >= 9 => {
+                let (class, subclass, protocol) = (descriptor[5], descriptor[6], descriptor[7]);
+                current = (class == CLASS_HID && subclass == SUBCLASS_BOOT
+                    && matches!(protocol, PROTOCOL_KEYBOARD | PROTOCOL_MOUSE))
+                    .then_some(BootInterface {
+                        configuration,
+                        interface: descriptor[2],
+                        protocol,
+                        endpoint: 0,
+                        max_packet: 0,
+                        interval: 0,
+                    });
+            }
+            // 端点地址的最高位是方向，属性的低两位 3 是中断端点
+            DESCRIPTOR_ENDPOINT if length >= 7 => {
+                if let Some(mut interface) = current.filter(|_| descriptor[2] & 0x80 != 0 && descriptor[3] & 3 == 3) {
+                    // IN 端点的上下文编号是端点号的两倍加一
+                    interface.endpoint = (descriptor[2] & 0xF) * 2 + 1;
+                    interface.max_packet = u16::from_le_bytes([descriptor[4], descriptor[5]]) & 0x7FF;
+                    interface.interval = descriptor[6];
+                    return Some(interface);
+                }
+            }
+            _ => {}
+        }
+        offset += length;
+    }
+    None
+}
@@ -0,0 +1,294 @@
+// 块缓存
+// 夹在文件系统和块设备之间，每个设备一份，按块缓存，满了淘汰最久没用的块。
+// 读不在缓存里的块时连同后面几块一起读进来（预读），遍历目录、顺序读文件时不用一块一块地去问磁盘。
+// 写只改缓存里的块并标记为脏，脏块在这几种时候写回磁盘：被淘汰时、调用 sync 时、
+// 以及 shell 空闲时调用 idle 发现它脏了超过 DIRTY_EXPIRE。写回时相邻的脏块合成一次请求。
+// 缓存注册为 Shrinker，内存紧张时淘汰干净的块；脏块要先写回磁盘，回收时不能做 I/O，所以不动
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+use crate::allocator::shrinker::{self, Shrinker};
+use crate::drivers::block::{self, BlockError};
+use crate::io::timer::uptime;
+
+// 每个设备最多缓存的字节数
+const CAPACITY: usize = 1024 * 1024;
+// 读不在缓存里的块时至少读这么多字节
+const READ_AHEAD: usize = 16 * 1024;
+/// 脏块最多在缓存里留这么久，之后由 idle 写回
+pub const DIRTY_EXPIRE: Duration = Duration::from_secs(5);
+
+/// 一个设备的缓存的统计
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// 在缓存里找到的块
+    pub hits: u64,
+    /// 要去读磁盘的块
+    pub misses: u64,
+    /// 预读进来、请求本身没有要的块
+    pub read_ahead: u64,
+    /// 写回磁盘的块
+    pub written_back: u64,
+    /// 发给设备的读请求和写请求
+    pub device_reads: u64,
+    pub device_writes: u64,
+}
+
+struct Block {
+    data: Box<[u8]>,
+    // 变脏的时间，干净的块为 None
+    dirty_since: Option<Duration>,
+    // 最近一次使用的时间
+    used: u64,
+}
+
+struct Cache {
+    info: block::Info,
+    // 最多缓存的块数
+    capacity: usize,
+    blocks: BTreeMap<u64, Block>,
+    clock: u64,
+    stats: Stats,
+}
+
+impl Cache {
+    fn open(device: &str) -> Result<Self, BlockError> {
+        let info = block::find(device).ok_or(BlockError::NoDevice)?;
+        Ok(Cache {
+            capacity: (CAPACITY / info.block_size).max(1),
+            info,
+            blocks: BTreeMap::new(),
+            clock: 0,
+            stats: Stats::default(),
+        })
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn read(&mut self, device: &str, lba: u64, buffer: &mut [u8]) -> Result<(), BlockError> {
+        let requested = (buffer.len() / self.info.block_size) as u64;
+        for (i, chunk) in buffer.chunks_mut(self.info.block_size).enumerate() {
+            let lba = lba + i as u64;
+            if self.blocks.contains_key(&lba) {
+                self.stats.hits += 1;
+            } else {
+                self.stats.misses += 1;
+                self.fill(device, lba, requested - i as u64)?;
+            }
+            let used = self.tick();
+            let block = self.blocks.get_mut(&lba).unwrap();
+            block.used = used;
+            chunk.copy_from_slice(&block.data);
+        }
+        Ok(())
+    }
+
+    // 从第 `lba` 块开始读进来，请求还要 `wanted` 块；遇到已经缓存的块就停下，它可能是脏的，不能被磁盘上的旧数据盖掉
+    fn fill(&mut self, device: &str, lba: u64, wanted: u64) -> Result<(), BlockError> {
+        let read_ahead = (READ_AHEAD / self.info.block_size) as u64;
+        let limit = wanted.max(read_ahead).min(self.info.block_count - lba).min(self.capacity as u64);
+        let count = (0..limit).take_while(|i| !self.blocks.contains_key(&(lba + i))).count();
+        self.make_room(device, count)?;
+
+        let mut data = vec![0; count * self.info.block_size];
+        self.stats.device_reads += 1;
+        block::read(device, lba, &mut data)?;
+        self.stats.read_ahead += (count as u64).saturating_sub(wanted);
+        let used = self.tick();
+        for (i, chunk) in data.chunks(self.info.block_size).enumerate() {
+            self.blocks.insert(lba + i as u64, Block { data: Box::from(chunk), dirty_since: None, used });
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, device: &str, lba: u64, data: &[u8]) -> Result<(), BlockError> {
+        let now = uptime();
+        for (i, chunk) in data.chunks(self.info.block_size).enumerate() {
+            let lba = lba + i as u64;
+            let used = self.tick();
+            if let Some(block) = self.blocks.get_mut(&lba) {
+                block.data.copy_from_slice(chunk);
+                block.dirty_since.get_or_insert(now);
+                block.used = used;
+            } else {
+                // 整块覆盖，不用先读
+                self.make_room(device, 1)?;
+                self.blocks.insert(lba, Block { data: Box::from(chunk), dirty_since: Some(now), used });
+            }
+        }
+        Ok(())
+    }
+
+    // 淘汰最久没用的块，直到还能再放下 `count` 块；脏块先写回
+    fn make_room(&mut self, device: &str, count: usize) -> Result<(), BlockError> {
+        while self.blocks.len() + count > self.capacity {
+            let Some((&lba, _)) = self.blocks.iter().min_by_key(|(_, block)| block.used) else { break };
+            let block = self.blocks.remove(&lba).unwrap();
+            if block.dirty_since.is_some() {
+                if let Err(error) = self.write_back(device, lba, &block.data) {
+                    self.blocks.insert(lba, block);
+                    return Err(error);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write_back(&mut self, device: &str, lba: u64, data: &[u8]) -> Result<(), BlockError> {
+        self.stats.device_writes += 1;
+        block::write(device, lba, data)?;
+        self.stats.written_back += (data.len() / self.info.block_size) as u64;
+        Ok(())
+    }
+
+    // 写回在 `before` 之前变脏的块，相邻的合成一次请求
+    fn flush(&mut self, device: &str, before: Duration) -> Result<(), BlockError> {
+        let due = |block: &Block| block.dirty_since.is_some_and(|since| since <= before);
+        let dirty: Vec<u64> = self.blocks.iter().filter(|(_, block)| due(block)).map(|(&lba, _)| lba).collect();
+        let mut start = 0;
+        while start < dirty.len() {
+            let mut end = start + 1;
+            while end < dirty.len() && dirty[end] == dirty[end - 1] + 1 {
+                end += 1;
+            }
+            let mut data = Vec::with_capacity((end - start) * self.info.block_size);
+            for lba in &dirty[start..end] {
+                data.extend_from_slice(&self.blocks[lba].data);
+            }
+            self.write_back(device, dirty[start], &data)?;
+            for lba in &dirty[start..end] {
+                self.blocks.get_mut(lba).unwrap().dirty_since = None;
+            }
+            start = end;
+        }
+        Ok(())
+    }
+
+    fn dirty(&self) -> usize {
+        self.blocks.values().filter(|block| block.dirty_since.is_some()).count()
+    }
+
+    // 淘汰最久没用的一个干净的块，返回释放的字节数，没有时返回 None；不分配内存
+    fn evict_clean(&mut self) -> Option<usize> {
+        let (&lba, _) = self.blocks.iter().filter(|(_, block)| block.dirty_since.is_none()).min_by_key(|(_, block)| block.used)?;
+        self.blocks.remove(&lba);
+        Some(self.info.block_size)
+    }
+}
+
+lazy_static! {
+    // 以设备名为键
+    static ref CACHES: Mutex<BTreeMap<String, Cache>> = {
+        shrinker::register(&BLOCK_CACHE_SHRINKER);
+        Mutex::new(BTreeMap::new())
+    };
+}
+
+// 对设备 `device` 的缓存执行 `f`，第一次用到时建立缓存；请求先检查对齐和范围
+fn with_cache<R>(device: &str, lba: u64, len: usize, f: impl FnOnce(&mut Cache) -> Result<R, BlockError>) -> Result<R, BlockError> {
+    let mut caches = CACHES.lock();
+    if !caches.contains_key(device) {
+        let cache = Cache::open(device)?;
+        caches.insert(String::from(device), cache);
+    }
+    let cache = caches.get_mut(device).unwrap();
+    cache.info.check(lba, len)?;
+    f(cache)
+}
+
+/// 经过缓存从设备 `device` 的第 `lba` 块开始读满 `buffer`
+pub fn read(device: &str, lba: u64, buffer: &mut [u8]) -> Result<(), BlockError> {
+    with_cache(device, lba, buffer.len(), |cache| cache.read(device, lba, buffer))
+}
+
+/// 经过缓存从设备 `device` 的第 `lba` 块开始写入 `data`，不等写回磁盘就返回
+pub fn write(device: &str, lba: u64, data: &[u8]) -> Result<(), BlockError> {
+    with_cache(device, lba, data.len(), |cache| cache.write(device, lba, data))
+}
+
+/// 把所有设备的脏块写回磁盘；某个设备出错时继续写其他设备，返回第一个错误
+pub fn sync() -> Result<(), BlockError> {
+    let now = uptime();
+    let mut result = Ok(());
+    for (device, cache) in CACHES.lock().iter_mut() {
+        if let Err(error) = cache.flush(device, now) {
+            log::warn!("cache: writing back {} failed: {}", device, error);
+            if result.is_ok() {
+                result = Err(error);
+            }
+        }
+    }
+    result
+}
+
+/// 写回脏了超过 DIRTY_EXPIRE 的块
+///
+/// 由 shell 在等待输入的空闲时间里调用
+pub fn idle() {
+    let Some(before) = uptime().checked_sub(DIRTY_EXPIRE) else { return };
+    let Some(mut caches) = CACHES.try_lock() else { return };
+    for (device, cache) in caches.iter_mut() {
+        if let Err(error) = cache.flush(device, before) {
+            log::warn!("cache: writing back {} failed: {}", device, error);
+        }
+    }
+}
+
+/// 一个设备的缓存，给 sync 命令显示
+pub struct Info {
+    pub device: String,
+    pub block_size: usize,
+    /// 缓存的块数和其中的脏块数
+    pub cached: usize,
+    pub dirty: usize,
+    pub stats: Stats,
+}
+
+pub fn list() -> Vec<Info> {
+    CACHES.lock().iter().map(|(device, cache)| Info {
+        device: device.clone(),
+        block_size: cache.info.block_size,
+        cached: cache.blocks.len(),
+        dirty: cache.dirty(),
+        stats: cache.stats,
+    }).collect()
+}
+
+struct BlockCacheShrinker;
+
+static BLOCK_CACHE_SHRINKER: BlockCacheShrinker = BlockCacheShrinker;
+
+impl Shrinker for BlockCacheShrinker {
+    fn name(&self) -> &'static str {
+        "block cache"
+    }
+
+    fn count(&self) -> usize {
+        CACHES.lock().values().map(|cache| (cache.blocks.len() - cache.dirty()) * cache.info.block_size).sum()
+    }
+
+    fn shrink(&self, target: usize) -> usize {
+        let Some(mut caches) = CACHES.try_lock() else { return 0 };
+        let mut freed = 0;
+        for cache in caches.values_mut() {
+            while freed < target {
+                match cache.evict_clean() {
+                    Some(bytes) => freed += bytes,
+                    None => break,
+                }
+            }
+        }
+        freed
+    }
+}
@@ -0,0 +1,23 @@
+// 文件系统
+// 现在只有块缓存和分区表：文件系统读写磁盘都经过 cache，不直接调用 drivers::block；
+// 分区登记成 sata0p1 这样的块设备，挂载时和整个磁盘一样按名字使用。
+// VFS 和具体的文件系统（FAT32）之后加在缓存上面
+
+use crate::boot::Unit;
+
+pub mod cache;
+pub mod partition;
+
+/// 扫描磁盘的分区表，在 drivers::block::init 之后调用
+pub fn init() {
+    partition::init();
+}
+
+pub static UNIT: Unit = Unit {
+    name: "fs",
+    depends: &["block"],
+    init: |_| {
+        init();
+        Ok(())
+    },
+};
@@ -0,0 +1,280 @@
+// 分区表
+// 从整个磁盘读出分区表（先看 MBR，是保护性 MBR 时读 GPT），每个分区作为一个块设备登记到 drivers::block，
+// 名字是磁盘名加 p 加分区号，比如 sata0p1；读写时把块号加上分区的起点转给磁盘。
+// 文件系统按这个名字挂载分区，和挂载整个磁盘没有区别。
+// GPT 的头和分区项都检查 CRC32，主 GPT 坏了用磁盘末尾的备份。MBR 的扩展分区（逻辑分区）不支持。
+// 同一块磁盘不要既按整个磁盘又按分区使用，块缓存是分开的，会看到对方的旧数据
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::drivers::block::{self, BlockDevice, BlockError, Device};
+
+// MBR 的分区项和签名
+const MBR_ENTRIES: usize = 446;
+const MBR_ENTRY_SIZE: usize = 16;
+const MBR_SIGNATURE: usize = 510;
+const MBR_TYPE_EMPTY: u8 = 0x00;
+const MBR_TYPE_GPT: u8 = 0xEE;
+const MBR_TYPE_EXTENDED: [u8; 3] = [0x05, 0x0F, 0x85];
+
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+const GPT_HEADER_LBA: u64 = 1;
+const GPT_MIN_HEADER_SIZE: usize = 92;
+const GPT_MIN_ENTRY_SIZE: usize = 128;
+// 规范要求至少留 128 项的空间，一般也就这么多
+const GPT_MAX_ENTRIES: usize = 1024;
+
+// GPT 里的类型 GUID，按磁盘上的字节顺序（前三段是小端）
+const GUID_EFI_SYSTEM: [u8; 16] =
+    [0x28, 0x73, 0x2A, 0xC1, 0x1F, 0xF8, 0xD2, 0x11, 0xBA, 0x4B, 0x00, 0xA0, 0xC9, 0x3E, 0xC9, 0x3B];
+const GUID_BASIC_DATA: [u8; 16] =
+    [0xA2, 0xA0, 0xD0, 0xEB, 0xE5, 0xB9, 0x33, 0x44, 0x87, 0xC0, 0x68, 0xB6, 0xB7, 0x26, 0x99, 0xC7];
+const GUID_LINUX: [u8; 16] =
+    [0xAF, 0x3D, 0xC6, 0x0F, 0x83, 0x84, 0x72, 0x47, 0x8E, 0x79, 0x3D, 0x69, 0xD8, 0x47, 0x7D, 0xE4];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionError {
+    /// 读磁盘出错
+    Block(BlockError),
+    /// 没有 MBR 签名
+    NoTable,
+    /// 主 GPT 和备份都坏了
+    BadGpt,
+}
+
+impl From<BlockError> for PartitionError {
+    fn from(error: BlockError) -> Self {
+        PartitionError::Block(error)
+    }
+}
+
+impl fmt::Display for PartitionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PartitionError::Block(error) => write!(f, "{}", error),
+            PartitionError::NoTable => f.write_str("no partition table"),
+            PartitionError::BadGpt => f.write_str("corrupted GPT"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheme {
+    Mbr,
+    Gpt,
+}
+
+/// 分区表里的一项
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// 分区号，从 1 开始：MBR 是表项的位置，GPT 是分区项的序号
+    pub index: usize,
+    /// 起始块号和块数
+    pub start: u64,
+    pub count: u64,
+    /// 分区类型，比如 FAT32、EFI system
+    pub kind: &'static str,
+    /// GPT 分区的名字，MBR 没有
+    pub label: String,
+}
+
+/// 读出设备 `device` 的分区表
+pub fn read_table(device: &str) -> Result<(Scheme, Vec<Entry>), PartitionError> {
+    let info = block::find(device).ok_or(BlockError::NoDevice)?;
+    let mut sector = vec![0; info.block_size];
+    block::read(device, 0, &mut sector)?;
+    if sector.len() < MBR_SIGNATURE + 2 || sector[MBR_SIGNATURE..MBR_SIGNATURE + 2] != [0x55, 0xAA] {
+        return Err(PartitionError::NoTable);
+    }
+    let entries: Vec<&[u8]> = sector[MBR_ENTRIES..MBR_SIGNATURE].chunks(MBR_ENTRY_SIZE).collect();
+    if entries.iter().any(|entry| entry[4] == MBR_TYPE_GPT) {
+        let last = info.block_count - 1;
+        let table = read_gpt(device, info.block_size, info.block_count, GPT_HEADER_LBA).or_else(|_| {
+            log::warn!("partition: {}: primary GPT is corrupted, using the backup", device);
+            read_gpt(device, info.block_size, info.block_count, last)
+        })?;
+        return Ok((Scheme::Gpt, table));
+    }
+
+    let mut table = Vec::new();
+    for (i, entry) in entries.iter().enumerate() {
+        let kind = entry[4];
+        let start = u32::from_le_bytes(entry[8..12].try_into().unwrap()) as u64;
+        let count = u32::from_le_bytes(entry[12..16].try_into().unwrap()) as u64;
+        if kind == MBR_TYPE_EMPTY || count == 0 {
+            continue;
+        }
+        if MBR_TYPE_EXTENDED.contains(&kind) {
+            log::warn!("partition: {}: extended partition {} not supported", device, i + 1);
+            continue;
+        }
+        if start + count > info.block_count {
+            log::warn!("partition: {}: partition {} is beyond the end of the disk", device, i + 1);
+            continue;
+        }
+        table.push(Entry { index: i + 1, start, count, kind: mbr_kind(kind), label: String::new() });
+    }
+    Ok((Scheme::Mbr, table))
+}
+
+// 读第 `lba` 块上的 GPT 头和它指向的分区项
+fn read_gpt(device: &str, block_size: usize, block_count: u64, lba: u64) -> Result<Vec<Entry>, PartitionError> {
+    let mut header = vec![0; block_size];
+    block::read(device, lba, &mut header)?;
+    let u32_at = |data: &[u8], offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+    let u64_at = |data: &[u8], offset: usize| u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+
+    let header_size = u32_at(&header, 12) as usize;
+    if &header[..8] != GPT_SIGNATURE || !(GPT_MIN_HEADER_SIZE..=block_size).contains(&header_size) {
+        return Err(PartitionError::BadGpt);
+    }
+    // 算 CRC 时头里自己的 CRC 字段当作 0
+    let mut copy = header[..header_size].to_vec();
+    copy[16..20].fill(0);
+    if crc32(&copy) != u32_at(&header, 16) {
+        return Err(PartitionError::BadGpt);
+    }
+
+    let entries_lba = u64_at(&header, 72);
+    let entries = u32_at(&header, 80) as usize;
+    let entry_size = u32_at(&header, 84) as usize;
+    if entries > GPT_MAX_ENTRIES || entry_size < GPT_MIN_ENTRY_SIZE || entry_size % GPT_MIN_ENTRY_SIZE != 0 {
+        return Err(PartitionError::BadGpt);
+    }
+    let len = entries * entry_size;
+    let blocks = len.div_ceil(block_size);
+    if entries_lba.checked_add(blocks as u64).is_none_or(|end| end > block_count) {
+        return Err(PartitionError::BadGpt);
+    }
+    let mut data = vec![0; blocks * block_size];
+    block::read(device, entries_lba, &mut data)?;
+    if crc32(&data[..len]) != u32_at(&header, 88) {
+        return Err(PartitionError::BadGpt);
+    }
+
+    let mut table = Vec::new();
+    for (i, entry) in data[..len].chunks(entry_size).enumerate() {
+        let guid: [u8; 16] = entry[..16].try_into().unwrap();
+        if guid == [0; 16] {
+            continue;
+        }
+        // 结束块号是包含在内的
+        let (first, last) = (u64_at(entry, 32), u64_at(entry, 40));
+        if first > last || last >= block_count {
+            log::warn!("partition: {}: GPT entry {} has a bad range", device, i + 1);
+            continue;
+        }
+        let label = char::decode_utf16(entry[56..128].chunks(2).map(|unit| u16::from_le_bytes([unit[0], unit[1]])))
+            .map_while(Result::ok)
+            .take_while(|&c| c != '\0')
+            .collect();
+        table.push(Entry { index: i + 1, start: first, count: last - first + 1, kind: gpt_kind(&guid), label });
+    }
+    Ok(table)
+}
+
+fn mbr_kind(kind: u8) -> &'static str {
+    match kind {
+        0x01 => "FAT12",
+        0x04 | 0x06 | 0x0E => "FAT16",
+        0x07 => "NTFS/exFAT",
+        0x0B | 0x0C => "FAT32",
+        0x82 => "Linux swap",
+        0x83 => "Linux",
+        0xEF => "EFI system",
+        _ => "unknown",
+    }
+}
+
+fn gpt_kind(guid: &[u8; 16]) -> &'static str {
+    match *guid {
+        GUID_EFI_SYSTEM => "EFI system",
+        GUID_BASIC_DATA => "basic data",
+        GUID_LINUX => "Linux",
+        _ => "unknown",
+    }
+}
+
+// GPT 用的 CRC32（多项式 0xEDB88320），数据不多，逐位计算
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = u32::MAX;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// 磁盘上的一个分区
+pub struct Partition {
+    name: String,
+    model: String,
+    disk: Device,
+    start: u64,
+    count: u64,
+    block_size: usize,
+}
+
+impl BlockDevice for Partition {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn block_count(&self) -> u64 {
+        self.count
+    }
+
+    // 块层已经检查过请求没有超出分区
+    fn read_blocks(&mut self, lba: u64, buffer: &mut [u8]) -> Result<(), BlockError> {
+        self.disk.lock().read_blocks(self.start + lba, buffer)
+    }
+
+    fn write_blocks(&mut self, lba: u64, data: &[u8]) -> Result<(), BlockError> {
+        self.disk.lock().write_blocks(self.start + lba, data)
+    }
+}
+
+/// 读出设备 `device` 的分区表，把每个分区登记为块设备，返回分区数
+pub fn scan(device: &str) -> Result<usize, PartitionError> {
+    let disk = block::get(device).ok_or(BlockError::NoDevice)?;
+    let block_size = block::find(device).ok_or(BlockError::NoDevice)?.block_size;
+    let (scheme, table) = read_table(device)?;
+    log::info!("partition: {}: {:?}, {} partitions", device, scheme, table.len());
+    for entry in &table {
+        let model = if entry.label.is_empty() { String::from(entry.kind) } else { format!("{} ({})", entry.label, entry.kind) };
+        block::attach(Box::new(Partition {
+            name: format!("{}p{}", device, entry.index),
+            model,
+            disk: disk.clone(),
+            start: entry.start,
+            count: entry.count,
+            block_size,
+        }));
+    }
+    Ok(table.len())
+}
+
+/// 扫描已经登记的所有磁盘的分区表
+pub fn init() {
+    for info in block::list() {
+        match scan(&info.name) {
+            Ok(_) | Err(PartitionError::NoTable) => {}
+            Err(error) => log::warn!("partition: {}: {}", info.name, error),
+        }
+    }
+}
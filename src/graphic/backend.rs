@@ -0,0 +1,110 @@
+// 显示后端
+// 显存从哪里来、怎么切换分辨率和翻页由后端决定：QEMU/Bochs 的 BGA（见 vbe）可以切换分辨率，显存放得下两页时双缓冲；
+// 引导程序设置好的帧缓冲（UEFI GOP、Multiboot2，见 boot::info）只能用引导时的模式，也只有一页；
+// 两个都没有时用 VGA 的 320x200x256 模式（见 mode13h）。
+// 进入图形模式时按 BACKENDS 的顺序用第一个 probe 成功的后端，之后切换分辨率、翻页都交给它
+
+use spin::Once;
+use x86_64::structures::paging::OffsetPageTable;
+use x86_64::{PhysAddr, VirtAddr};
+
+use crate::boot::info;
+use crate::graphic::mode13h::Mode13h;
+use crate::graphic::vbe::{self, ModeError};
+use crate::memory::vmm::map_mmio;
+use crate::memory::BootInfoFrameAllocator;
+
+/// 显示模式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mode {
+    pub width: usize,
+    pub height: usize,
+    pub bpp: usize,
+    /// 每行占用的字节数，可能比 width * bpp / 8 大
+    pub pitch: usize,
+}
+
+impl Mode {
+    /// 每行没有空隙的模式
+    pub fn packed(width: usize, height: usize, bpp: usize) -> Self {
+        Mode { width, height, bpp, pitch: width * bpp / 8 }
+    }
+}
+
+pub trait Backend: Sync {
+    fn name(&self) -> &'static str;
+
+    /// 有这个设备时返回 true
+    fn probe(&self) -> bool;
+
+    /// 进入默认模式并映射显存，返回显存的虚拟地址和模式
+    unsafe fn enter(&self, mapper: &mut OffsetPageTable, frames: &mut BootInfoFrameAllocator) -> Result<(VirtAddr, Mode), ModeError>;
+
+    /// 切换分辨率和色深，显存已经在 enter 时映射好
+    unsafe fn set_mode(&self, width: usize, height: usize, bpp: usize) -> Result<Mode, ModeError>;
+
+    /// 当前模式下显存里的页数，两页时用 set_y_offset 翻页
+    fn pages(&self) -> usize;
+
+    /// 从虚拟画面的第几行开始显示
+    unsafe fn set_y_offset(&self, y: usize);
+}
+
+/// 按优先级排列的后端
+pub static BACKENDS: [&dyn Backend; 3] = [&vbe::Bga, &BootFramebuffer, &Mode13h];
+
+static ACTIVE: Once<&'static dyn Backend> = Once::new();
+
+/// 找到第一个可用的后端并进入图形模式，没有可用的后端时返回 NotGraphic
+pub fn enter(mapper: &mut OffsetPageTable, frames: &mut BootInfoFrameAllocator) -> Result<(VirtAddr, Mode), ModeError> {
+    let backend = BACKENDS.iter().copied().find(|backend| backend.probe()).ok_or(ModeError::NotGraphic)?;
+    let entered = unsafe { backend.enter(mapper, frames)? };
+    log::info!("Display backend {}", backend.name());
+    ACTIVE.call_once(|| backend);
+    Ok(entered)
+}
+
+/// 正在使用的后端，还没有进入图形模式时为 None
+pub fn active() -> Option<&'static dyn Backend> {
+    ACTIVE.get().copied()
+}
+
+/// 引导程序设置好的帧缓冲
+pub struct BootFramebuffer;
+
+impl BootFramebuffer {
+    fn mode() -> Option<Mode> {
+        let fb = info::framebuffer().filter(|fb| fb.bpp == 24 || fb.bpp == 32)?;
+        Some(Mode { width: fb.width, height: fb.height, bpp: fb.bpp, pitch: fb.pitch })
+    }
+}
+
+impl Backend for BootFramebuffer {
+    fn name(&self) -> &'static str {
+        "boot framebuffer"
+    }
+
+    fn probe(&self) -> bool {
+        Self::mode().is_some()
+    }
+
+    unsafe fn enter(&self, mapper: &mut OffsetPageTable, frames: &mut BootInfoFrameAllocator) -> Result<(VirtAddr, Mode), ModeError> {
+        let (Some(fb), Some(mode)) = (info::framebuffer(), Self::mode()) else {
+            return Err(ModeError::NotGraphic);
+        };
+        let region = map_mmio(mapper, frames, PhysAddr::new(fb.address), (mode.pitch * mode.height) as u64)
+            .map_err(|_| ModeError::OutOfVideoMemory)?;
+        Ok((region.start(), mode))
+    }
+
+    // 只能用引导时的模式
+    unsafe fn set_mode(&self, width: usize, height: usize, bpp: usize) -> Result<Mode, ModeError> {
+        Self::mode().filter(|mode| (mode.width, mode.height, mode.bpp) == (width, height, bpp)).ok_or(ModeError::Unsupported)
+    }
+
+    fn pages(&self) -> usize {
+        1
+    }
+
+    unsafe fn set_y_offset(&self, _y: usize) {}
+}
@@ -0,0 +1,201 @@
+// 制表符和方块元素
+// 内置字体里的制表符（U+2500..U+257F）和方块元素（U+2580..U+259F）是全角的，也不画满整格，
+// 表格、进度条在图形控制台上既对不齐也接不上。这些字符不用字体，按格子的大小直接画出来：
+// 宽度和空格相同，高度是一整行（font::line_pitch），相邻格子里的线和方块正好连在一起。
+// 虚线当作实线画，圆角当作直角画
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+// 每个制表符四个方向上的线，每个方向 2 位，从低位开始依次是上、右、下、左；值见 NONE..DOUBLE
+const LINES: [u8; 128] = [
+    0x44, 0x88, 0x11, 0x22, 0x44, 0x88, 0x11, 0x22, 0x44, 0x88, 0x11, 0x22, 0x14, 0x18, 0x24, 0x28,  // U+2500
+    0x50, 0x90, 0x60, 0xA0, 0x05, 0x09, 0x06, 0x0A, 0x41, 0x81, 0x42, 0x82, 0x15, 0x19, 0x16, 0x25,  // U+2510
+    0x26, 0x1A, 0x29, 0x2A, 0x51, 0x91, 0x52, 0x61, 0x62, 0x92, 0xA1, 0xA2, 0x54, 0x94, 0x58, 0x98,  // U+2520
+    0x64, 0xA4, 0x68, 0xA8, 0x45, 0x85, 0x49, 0x89, 0x46, 0x86, 0x4A, 0x8A, 0x55, 0x95, 0x59, 0x99,  // U+2530
+    0x56, 0x65, 0x66, 0x96, 0x5A, 0xA5, 0x69, 0x9A, 0xA9, 0xA6, 0x6A, 0xAA, 0x44, 0x88, 0x11, 0x22,  // U+2540
+    0xCC, 0x33, 0x1C, 0x34, 0x3C, 0xD0, 0x70, 0xF0, 0x0D, 0x07, 0x0F, 0xC1, 0x43, 0xC3, 0x1D, 0x37,  // U+2550
+    0x3F, 0xD1, 0x73, 0xF3, 0xDC, 0x74, 0xFC, 0xCD, 0x47, 0xCF, 0xDD, 0x77, 0xFF, 0x14, 0x50, 0x41,  // U+2560
+    0x05, 0x00, 0x00, 0x00, 0x40, 0x01, 0x04, 0x10, 0x80, 0x02, 0x08, 0x20, 0x48, 0x21, 0x84, 0x12,  // U+2570
+];
+
+const NONE: u8 = 0;
+const LIGHT: u8 = 1;
+const HEAVY: u8 = 2;
+const DOUBLE: u8 = 3;
+
+// 方向，和 LINES 里的顺序一致
+const UP: usize = 0;
+const RIGHT: usize = 1;
+const DOWN: usize = 2;
+const LEFT: usize = 3;
+
+// 象限方块 U+2596..U+259F 占的象限：左上 1、右上 2、左下 4、右下 8
+const QUADRANTS: [u8; 10] = [4, 8, 1, 13, 9, 7, 11, 2, 6, 14];
+
+/// 是否是这里画的字符
+pub fn contains(ch: char) -> bool {
+    matches!(ch, '\u{2500}'..='\u{259F}')
+}
+
+/// 把 `ch` 画成 height 行、width 列的覆盖率（按行存放，0 或 255），`ch` 必须满足 contains
+pub fn rasterize(ch: char, height: usize, width: usize) -> Vec<u8> {
+    let mut cell = Cell { coverage: vec![0; width * height], height, width };
+    match ch {
+        '\u{2571}'..='\u{2573}' => cell.diagonals(ch),
+        '\u{2500}'..='\u{257F}' => cell.lines(LINES[ch as usize - 0x2500]),
+        _ => cell.block(ch),
+    }
+    cell.coverage
+}
+
+struct Cell {
+    coverage: Vec<u8>,
+    height: usize,
+    width: usize,
+}
+
+impl Cell {
+    fn set(&mut self, x: isize, y: isize) {
+        if x >= 0 && y >= 0 && (x as usize) < self.height && (y as usize) < self.width {
+            self.coverage[x as usize * self.width + y as usize] = 255;
+        }
+    }
+
+    fn fill(&mut self, rows: (usize, usize), cols: (usize, usize)) {
+        for x in rows.0..rows.1.min(self.height) {
+            for y in cols.0..cols.1.min(self.width) {
+                self.coverage[x * self.width + y] = 255;
+            }
+        }
+    }
+
+    // 细线的粗细，总是奇数，线能以格子中心对称
+    fn thickness(&self) -> usize {
+        self.height / 40 * 2 + 1
+    }
+
+    fn weight_thickness(&self, weight: u8) -> usize {
+        match weight {
+            HEAVY => self.thickness() + 2,
+            NONE => 0,
+            _ => self.thickness(),
+        }
+    }
+
+    // 从格子中心向 `dir` 方向画一条线：沿线的方向上从距中心 start 的地方画到格子边缘，
+    // 和线垂直的方向上以 offset 为中心、宽 thickness。距离都是有符号的，中心是 0
+    fn arm(&mut self, dir: usize, start: isize, offset: isize, thickness: usize) {
+        let (cx, cy) = ((self.height / 2) as isize, (self.width / 2) as isize);
+        let half = (thickness / 2) as isize;
+        let length = self.height.max(self.width) as isize;
+        for a in start..length {
+            for b in offset - half..=offset + half {
+                match dir {
+                    UP => self.set(cx - a, cy + b),
+                    RIGHT => self.set(cx + b, cy + a),
+                    DOWN => self.set(cx + a, cy + b),
+                    _ => self.set(cx + b, cy - a),
+                }
+            }
+        }
+    }
+
+    fn lines(&mut self, bits: u8) {
+        let weights: [u8; 4] = core::array::from_fn(|dir| bits >> (dir * 2) & 3);
+        let t = self.thickness();
+        let half = (t / 2) as isize;
+        // 双线的两条线离中心的距离
+        let o = t as isize;
+        for dir in [UP, RIGHT, DOWN, LEFT] {
+            let weight = weights[dir];
+            if weight == NONE {
+                continue;
+            }
+            // 垂直方向上的两个方向，先是偏移为负的一侧（上或左）
+            let (minus, plus) = if dir == UP || dir == DOWN { (LEFT, RIGHT) } else { (UP, DOWN) };
+            let (wm, wp) = (weights[minus], weights[plus]);
+            if weight == DOUBLE {
+                for (side, opposite, offset) in [(wm, wp, -o), (wp, wm, o)] {
+                    let start = match (side, opposite) {
+                        // 同一侧也是双线：内侧的线在那边双线的内侧拐弯
+                        (DOUBLE, _) => o - half,
+                        (LIGHT | HEAVY, _) => -((self.weight_thickness(side) / 2) as isize),
+                        (_, LIGHT | HEAVY) => -((self.weight_thickness(opposite) / 2) as isize),
+                        // 外侧的线在那边双线的外侧拐弯
+                        (_, DOUBLE) => -o - half,
+                        _ => 0,
+                    };
+                    self.arm(dir, start, offset, t);
+                }
+            } else {
+                let start = if wm == DOUBLE || wp == DOUBLE {
+                    // 双线从中间穿过时接在近的那条线上（对面也有线时横穿过去），否则一直画到远的那条线
+                    match (wm != NONE && wp != NONE, weights[(dir + 2) % 4] != NONE) {
+                        (true, true) => 0,
+                        (true, false) => o - half,
+                        _ => -o - half,
+                    }
+                } else {
+                    -((self.weight_thickness(wm).max(self.weight_thickness(wp)) / 2) as isize)
+                };
+                self.arm(dir, start, 0, self.weight_thickness(weight));
+            }
+        }
+    }
+
+    fn diagonals(&mut self, ch: char) {
+        let half = (self.thickness() / 2) as isize;
+        let (h, w) = (self.height.max(2) as isize, self.width.max(2) as isize);
+        for x in 0..h {
+            let y = x * (w - 1) / (h - 1);
+            for b in -half..=half {
+                // U+2572 左上到右下，U+2571 右上到左下，U+2573 两条都画
+                if ch != '\u{2571}' {
+                    self.set(x, y + b);
+                }
+                if ch != '\u{2572}' {
+                    self.set(x, w - 1 - y + b);
+                }
+            }
+        }
+    }
+
+    fn block(&mut self, ch: char) {
+        let (h, w) = (self.height, self.width);
+        // n/8 的长度，四舍五入
+        let eighths = |length: usize, n: usize| (length * n + 4) / 8;
+        match ch {
+            '\u{2580}' => self.fill((0, h / 2), (0, w)),
+            '\u{2581}'..='\u{2588}' => self.fill((h - eighths(h, ch as usize - 0x2580), h), (0, w)),
+            '\u{2589}'..='\u{258F}' => self.fill((0, h), (0, eighths(w, 0x2590 - ch as usize))),
+            '\u{2590}' => self.fill((0, h), (w / 2, w)),
+            '\u{2591}'..='\u{2593}' => {
+                // 阴影用点阵表示：浅 1/4、中 1/2、深 3/4
+                for x in 0..h {
+                    for y in 0..w {
+                        let on = match ch {
+                            '\u{2591}' => x % 2 == 0 && y % 2 == 0,
+                            '\u{2592}' => (x + y) % 2 == 0,
+                            _ => x % 2 == 0 || y % 2 == 0,
+                        };
+                        if on {
+                            self.coverage[x * w + y] = 255;
+                        }
+                    }
+                }
+            }
+            '\u{2594}' => self.fill((0, eighths(h, 1).max(1)), (0, w)),
+            '\u{2595}' => self.fill((0, h), (w - eighths(w, 1).max(1), w)),
+            _ => {
+                let quadrants = QUADRANTS[ch as usize - 0x2596];
+                let (mx, my) = (h / 2, w / 2);
+                for (bit, rows, cols) in [(1, (0, mx), (0, my)), (2, (0, mx), (my, w)), (4, (mx, h), (0, my)), (8, (mx, h), (my, w))] {
+                    if quadrants & bit != 0 {
+                        self.fill(rows, cols);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,263 @@
+// 几何图元
+// Writer、PhysicalWriter 和窗口客户区都实现 Canvas，只需要提供带边界检查的 put_pixel，
+// 线段、圆、多边形、圆角矩形和渐变都由这里的默认实现完成。能读回像素的画布再实现 get_pixel，
+// 抗锯齿的文字用 blend_pixel 按覆盖率和底色混合，读不回底色时退回到按一半覆盖率取舍。
+// 坐标和 graphic 模块一致：x 是行，y 是列；图元的坐标可以为负或超出画布，超出的部分被裁掉。
+// 另外提供 draw_pixels 和 fill_area，用来给画布实现 embedded-graphics 的 DrawTarget
+
+use alloc::vec::Vec;
+use core::cmp::{max, min};
+
+use embedded_graphics::pixelcolor::Rgb888;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+
+use crate::graphic::color::alpha_mix_final;
+
+/// 渐变的方向
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Gradient {
+    // 从左到右
+    Horizontal,
+    // 从上到下
+    Vertical,
+}
+
+pub trait Canvas {
+    /// 画布的高和宽（行数，列数）
+    fn size(&self) -> (usize, usize);
+
+    /// 画一个像素，超出画布的部分被忽略
+    fn put_pixel(&mut self, x: usize, y: usize, color: Rgb888);
+
+    /// 读回 (x, y) 处的像素，透明、超出画布或者不能读回时返回 None
+    fn get_pixel(&self, _x: usize, _y: usize) -> Option<Rgb888> {
+        None
+    }
+
+    /// 按覆盖率 `alpha`（0.0 到 1.0）把 `color` 混合到 (x, y) 处的像素上
+    fn blend_pixel(&mut self, x: usize, y: usize, color: Rgb888, alpha: f32) {
+        if alpha >= 1.0 {
+            self.put_pixel(x, y, color);
+            return;
+        }
+        match self.get_pixel(x, y) {
+            Some(below) if alpha > 0.0 => self.put_pixel(x, y, alpha_mix_final(color, alpha, below)),
+            None if alpha > 0.5 => self.put_pixel(x, y, color),
+            _ => {}
+        }
+    }
+
+    /// 画第 x 行的 y0..y1 列，调用者保证都在画布内；实现可以覆盖成更快的版本
+    fn fill_span(&mut self, x: usize, y0: usize, y1: usize, color: Rgb888) {
+        for y in y0..y1 {
+            self.put_pixel(x, y, color);
+        }
+    }
+
+    /// 画一个像素，坐标可以为负
+    fn plot(&mut self, x: isize, y: isize, color: Rgb888) {
+        if x >= 0 && y >= 0 {
+            self.put_pixel(x as usize, y as usize, color);
+        }
+    }
+
+    /// 画第 x 行的 y0..=y1 列，超出画布的部分被裁掉
+    fn hline(&mut self, x: isize, y0: isize, y1: isize, color: Rgb888) {
+        let (height, width) = self.size();
+        if x < 0 || x as usize >= height || y1 < 0 || y0 > y1 {
+            return;
+        }
+        let start = max(y0, 0) as usize;
+        let end = min(y1 as usize + 1, width);
+        if start < end {
+            self.fill_span(x as usize, start, end, color);
+        }
+    }
+
+    /// Bresenham 画线，两个端点都画
+    fn draw_line(&mut self, x0: isize, y0: isize, x1: isize, y1: isize, color: Rgb888) {
+        let (dx, dy) = ((x1 - x0).abs(), -(y1 - y0).abs());
+        let (sx, sy) = (if x0 < x1 { 1 } else { -1 }, if y0 < y1 { 1 } else { -1 });
+        let (mut x, mut y, mut err) = (x0, y0, dx + dy);
+        loop {
+            self.plot(x, y, color);
+            if x == x1 && y == y1 {
+                break;
+            }
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+    }
+
+    /// 矩形边框
+    fn draw_rect(&mut self, x: isize, y: isize, w: usize, h: usize, color: Rgb888) {
+        self.draw_rounded_rect(x, y, w, h, 0, color);
+    }
+
+    /// 以 (cx, cy) 为圆心的圆
+    fn draw_circle(&mut self, cx: isize, cy: isize, r: usize, color: Rgb888) {
+        for_each_octant_point(r, |a, b| {
+            for (dx, dy) in [(a, b), (b, a)] {
+                self.plot(cx + dx, cy + dy, color);
+                self.plot(cx + dx, cy - dy, color);
+                self.plot(cx - dx, cy + dy, color);
+                self.plot(cx - dx, cy - dy, color);
+            }
+        });
+    }
+
+    fn fill_circle(&mut self, cx: isize, cy: isize, r: usize, color: Rgb888) {
+        let r = r as isize;
+        for dx in -r..=r {
+            let dy = isqrt(r * r - dx * dx);
+            self.hline(cx + dx, cy - dy, cy + dy, color);
+        }
+    }
+
+    /// 依次连接各个顶点，最后一个顶点连回第一个
+    fn draw_polygon(&mut self, points: &[(isize, isize)], color: Rgb888) {
+        for (i, &(x0, y0)) in points.iter().enumerate() {
+            let (x1, y1) = points[(i + 1) % points.len()];
+            self.draw_line(x0, y0, x1, y1, color);
+        }
+    }
+
+    /// 按奇偶规则逐行填充多边形
+    fn fill_polygon(&mut self, points: &[(isize, isize)], color: Rgb888) {
+        let Some(top) = points.iter().map(|p| p.0).min() else { return };
+        let bottom = points.iter().map(|p| p.0).max().unwrap();
+        let mut crossings = Vec::new();
+        for x in top..=bottom {
+            crossings.clear();
+            for (i, &(x0, y0)) in points.iter().enumerate() {
+                let (x1, y1) = points[(i + 1) % points.len()];
+                // 每条边包含上端点、不包含下端点，顶点不会被算两次
+                if (x0 <= x && x < x1) || (x1 <= x && x < x0) {
+                    crossings.push(y0 + (x - x0) * (y1 - y0) / (x1 - x0));
+                }
+            }
+            crossings.sort_unstable();
+            for pair in crossings.chunks_exact(2) {
+                self.hline(x, pair[0], pair[1], color);
+            }
+        }
+        // 水平的下边不在上面的扫描范围内，补画轮廓
+        self.draw_polygon(points, color);
+    }
+
+    /// 圆角矩形边框，半径不超过短边的一半
+    fn draw_rounded_rect(&mut self, x: isize, y: isize, w: usize, h: usize, r: usize, color: Rgb888) {
+        if w == 0 || h == 0 {
+            return;
+        }
+        let r = min(r, min(w, h) / 2) as isize;
+        let (ex, ey) = (x + h as isize - 1, y + w as isize - 1);
+        self.hline(x, y + r, ey - r, color);
+        self.hline(ex, y + r, ey - r, color);
+        self.draw_line(x + r, y, ex - r, y, color);
+        self.draw_line(x + r, ey, ex - r, ey, color);
+        if r == 0 {
+            return;
+        }
+        // 四个角各画四分之一圆
+        for_each_octant_point(r as usize, |a, b| {
+            for (dx, dy) in [(a, b), (b, a)] {
+                self.plot(x + r - dx, y + r - dy, color);
+                self.plot(x + r - dx, ey - r + dy, color);
+                self.plot(ex - r + dx, y + r - dy, color);
+                self.plot(ex - r + dx, ey - r + dy, color);
+            }
+        });
+    }
+
+    fn fill_rounded_rect(&mut self, x: isize, y: isize, w: usize, h: usize, r: usize, color: Rgb888) {
+        let r = min(r, min(w, h) / 2) as isize;
+        let (h, ey) = (h as isize, y + w as isize - 1);
+        for i in 0..h {
+            // 这一行到圆角圆心的距离
+            let d = max(r - i, i - (h - 1 - r)).max(0);
+            let inset = r - isqrt(r * r - d * d);
+            self.hline(x + i, y + inset, ey - inset, color);
+        }
+    }
+
+    /// 用 from 到 to 的渐变填满矩形
+    fn fill_gradient(&mut self, x: isize, y: isize, w: usize, h: usize, from: Rgb888, to: Rgb888, direction: Gradient) {
+        match direction {
+            Gradient::Vertical => {
+                for i in 0..h {
+                    let color = alpha_mix_final(to, ratio(i, h), from);
+                    self.hline(x + i as isize, y, y + w as isize - 1, color);
+                }
+            }
+            Gradient::Horizontal => {
+                for j in 0..w {
+                    let color = alpha_mix_final(to, ratio(j, w), from);
+                    for i in 0..h {
+                        self.plot(x + i as isize, y + j as isize, color);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// DrawTarget::draw_iter 的实现，embedded-graphics 的 Point 中 x 是列、y 是行，和这里相反
+pub fn draw_pixels<C: Canvas + ?Sized>(canvas: &mut C, pixels: impl IntoIterator<Item = Pixel<Rgb888>>) {
+    for Pixel(point, color) in pixels {
+        canvas.plot(point.y as isize, point.x as isize, color);
+    }
+}
+
+/// DrawTarget::fill_solid 的实现，按行填充裁剪后的矩形
+pub fn fill_area<C: Canvas + ?Sized>(canvas: &mut C, area: &Rectangle, color: Rgb888) {
+    let (height, width) = canvas.size();
+    let area = area.intersection(&Rectangle::new(Point::zero(), Size::new(width as u32, height as u32)));
+    if let Some(bottom_right) = area.bottom_right() {
+        for x in area.top_left.y..=bottom_right.y {
+            canvas.fill_span(x as usize, area.top_left.x as usize, bottom_right.x as usize + 1, color);
+        }
+    }
+}
+
+// 中点画圆法，给出第一个八分圆上的点 (a, b)，a >= b >= 0，其余七个由调用者按对称性得到
+fn for_each_octant_point(r: usize, mut f: impl FnMut(isize, isize)) {
+    let (mut a, mut b, mut err) = (r as isize, 0isize, 1 - r as isize);
+    while a >= b {
+        f(a, b);
+        b += 1;
+        if err < 0 {
+            err += 2 * b + 1;
+        } else {
+            a -= 1;
+            err += 2 * (b - a) + 1;
+        }
+    }
+}
+
+// 整数平方根，向下取整
+fn isqrt(n: isize) -> isize {
+    if n <= 0 {
+        return 0;
+    }
+    let mut x = n;
+    let mut y = (x + 1) / 2;
+    while y < x {
+        x = y;
+        y = (x + n / x) / 2;
+    }
+    x
+}
+
+// 渐变中第 i 步的比例，两端分别是 0 和 1
+fn ratio(i: usize, n: usize) -> f32 {
+    if n <= 1 { 0.0 } else { i as f32 / (n - 1) as f32 }
+}
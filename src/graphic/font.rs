@@ -1,21 +1,280 @@
+// 字体和字形缓存
+// 字体登记在注册表里，用 FontId 指定。0 号是内置的等宽字体，控制台总是用它，别的字体缺字时也退回到它；
+// GUI 的标题和控件用 gui_font，登记了比例字体后可以用 set_gui_font 换掉。
+// 用 rusttype 光栅化一个字形很慢，控制台每输出一个字符都要来一次。glyph 把光栅化好的覆盖率按 (字体, 字符, 字号) 缓存起来，
+// 满了淘汰最久没用的；缓存注册为 Shrinker，内存紧张时也可以被回收。
+// 大字号的标题可以用 glyph_supersampled：按两倍字号光栅化再缩小一半，同样放进缓存。
+// 制表符和方块元素不用字体里的字形，由 boxdraw 按格子画出来
+
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem::size_of;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
 use lazy_static::lazy_static;
-use rusttype::{Font, HMetrics, Scale, ScaledGlyph};
+use rusttype::{point, Font, HMetrics, Rect, Scale, ScaledGlyph};
+use spin::{Mutex, RwLock};
+use x86_64::instructions::interrupts;
+
+use crate::allocator::defrag::Movable;
+use crate::allocator::shrinker::{self, Shrinker};
+use crate::assets::embedded;
+use crate::graphic::boxdraw;
+use crate::perf;
+
+// 缓存的字形数，常用汉字加上 ASCII 足够
+const CACHE_CAPACITY: usize = 512;
+
+/// 注册表里的字体
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FontId(usize);
+
+impl FontId {
+    /// 内置的等宽字体
+    pub const MONOSPACE: FontId = FontId(0);
+}
 
-const FONT_DATA: &[u8] = include_bytes!("../../assets/VonwaonBitmap-16px.ttf");
+// GUI 使用的字体
+static GUI_FONT: AtomicUsize = AtomicUsize::new(0);
 
-// 使用 `lazy_static!` 宏定义一个静态变量 `FONT`, 初始化为从字节数组中加载的字体对象
+// 使用 `lazy_static!` 宏定义一个静态变量 `FONTS`, 初始化为只有从字节数组中加载的内置字体
 lazy_static! {
-    pub(super) static ref FONT: Font<'static> = Font::try_from_bytes(FONT_DATA).unwrap();
+    static ref FONTS: RwLock<Vec<Font<'static>>> = {
+        let data = embedded::get(embedded::MONOSPACE_FONT).expect("Built-in font is missing");
+        RwLock::new(vec![Font::try_from_bytes(data).unwrap()])
+    };
+    static ref CACHE: Mutex<GlyphCache> = {
+        shrinker::register(&GLYPH_CACHE_SHRINKER);
+        Mutex::new(GlyphCache { glyphs: BTreeMap::new(), clock: 0 })
+    };
+}
+
+/// 登记嵌入内核的 TTF 字体，数据无法解析时返回 None
+pub fn register(data: &'static [u8]) -> Option<FontId> {
+    add(Font::try_from_bytes(data)?)
 }
 
-pub fn get_font(ch: char, size: f32) -> (ScaledGlyph<'static>, HMetrics){
+/// 登记运行时读入的 TTF 字体
+pub fn register_owned(data: Vec<u8>) -> Option<FontId> {
+    add(Font::try_from_vec(data)?)
+}
+
+fn add(font: Font<'static>) -> Option<FontId> {
+    Some(interrupts::without_interrupts(|| {
+        let mut fonts = FONTS.write();
+        fonts.push(font);
+        FontId(fonts.len() - 1)
+    }))
+}
+
+pub fn gui_font() -> FontId {
+    FontId(GUI_FONT.load(Ordering::Relaxed))
+}
+
+/// 换掉 GUI 使用的字体，之后重画的标题和控件生效
+pub fn set_gui_font(font: FontId) {
+    GUI_FONT.store(font.0, Ordering::Relaxed);
+}
+
+pub fn get_font(font: FontId, ch: char, size: f32) -> (ScaledGlyph<'static>, HMetrics){
     let scale = Scale::uniform(size);
-    let glyph_id = FONT.glyph(ch);
+    let fonts = FONTS.read();
+    let mut glyph_id = fonts.get(font.0).unwrap_or(&fonts[0]).glyph(ch);
+    // 缺字时用内置字体
+    if glyph_id.id().0 == 0 {
+        glyph_id = fonts[0].glyph(ch);
+    }
     let glyph = glyph_id.scaled(scale);
     let h_metrics = glyph.h_metrics();
     (glyph,h_metrics)
 }
 
+/// 光栅化好的字形
+#[derive(Debug)]
+pub struct Glyph {
+    /// 前进宽度，不含字距
+    pub advance: f32,
+    // 像素包围盒左上角相对原点的偏移，min_x 是列方向，min_y 是行方向（基线以上为负）
+    min_x: i32,
+    min_y: i32,
+    width: usize,
+    height: usize,
+    // 覆盖率，按行存放，0 到 255；放在 Movable 里，整理堆时可以搬走
+    coverage: Movable,
+}
+
+impl Glyph {
+    fn rasterize(font: FontId, ch: char, size: f32) -> Self {
+        perf::scope_timer!("rasterize");
+        // 制表符和方块元素按格子画满一整行，宽度和空格相同
+        if boxdraw::contains(ch) {
+            let advance = get_font(font, ' ', size).1.advance_width;
+            let (height, width) = (line_pitch(size), advance as usize + 1);
+            let coverage = boxdraw::rasterize(ch, height, width);
+            let coverage = Movable::new(coverage);
+            return Self { advance, min_x: 0, min_y: -(size as usize as i32), width, height, coverage };
+        }
+        let (glyph, hm) = get_font(font, ch, size);
+        let glyph = glyph.positioned(point(0.0, 0.0));
+        // draw 给出的坐标相对像素包围盒，位置也要按它算
+        let bbox = glyph.pixel_bounding_box().unwrap_or(Rect { min: point(0, 0), max: point(0, 0) });
+        let (width, height) = (bbox.width() as usize, bbox.height() as usize);
+        let mut coverage = vec![0; width * height];
+        glyph.draw(|y, x, v| coverage[x as usize * width + y as usize] = (v * 255.0) as u8);
+        let coverage = Movable::new(coverage);
+        Self { advance: hm.advance_width, min_x: bbox.min.x, min_y: bbox.min.y, width, height, coverage }
+    }
+
+    /// 高和宽（行数，列数）
+    pub fn size(&self) -> (usize, usize) {
+        (self.height, self.width)
+    }
+
+    /// 放在行高为 `line_height` 的一行里时左上角的位置，见 placement
+    pub fn offset(&self, line_height: usize) -> (isize, isize) {
+        placement(self.min_x, self.min_y, line_height)
+    }
+
+    /// 依次给出每个像素的位置（行，列）和覆盖率，位置相对这一行的左上角，基线在行高的底部；
+    /// 伸出这一行顶部或左边的像素被裁掉
+    pub fn for_each_pixel(&self, line_height: usize, mut f: impl FnMut(usize, usize, f32)) {
+        let (x_offset, y_offset) = self.offset(line_height);
+        self.coverage.with(|coverage| {
+            for i in 0..self.height {
+                for j in 0..self.width {
+                    let (x, y) = (x_offset + i as isize, y_offset + j as isize);
+                    if x >= 0 && y >= 0 {
+                        f(x as usize, y as usize, coverage[i * self.width + j] as f32 / 255.0);
+                    }
+                }
+            }
+        })
+    }
+
+    // 缩小一半，每 2×2 个像素的覆盖率取平均。包围盒先向左上扩到偶数坐标，缩小后的位置才和原来的字号对得上
+    fn downsample(&self) -> Self {
+        let (pad_x, pad_y) = (self.min_y.rem_euclid(2) as usize, self.min_x.rem_euclid(2) as usize);
+        let (height, width) = ((self.height + pad_x + 1) / 2, (self.width + pad_y + 1) / 2);
+        let coverage = self.coverage.with(|source| {
+            let sample = |i: usize, j: usize| match (i.checked_sub(pad_x), j.checked_sub(pad_y)) {
+                (Some(i), Some(j)) if i < self.height && j < self.width => source[i * self.width + j] as u32,
+                _ => 0,
+            };
+            let mut coverage = vec![0; width * height];
+            for i in 0..height {
+                for j in 0..width {
+                    let sum = sample(2 * i, 2 * j) + sample(2 * i, 2 * j + 1) + sample(2 * i + 1, 2 * j) + sample(2 * i + 1, 2 * j + 1);
+                    coverage[i * width + j] = (sum / 4) as u8;
+                }
+            }
+            coverage
+        });
+        Self {
+            advance: self.advance / 2.0,
+            min_x: (self.min_x - pad_y as i32) / 2,
+            min_y: (self.min_y - pad_x as i32) / 2,
+            width,
+            height,
+            coverage: Movable::new(coverage),
+        }
+    }
+
+    // 大约占用的字节数
+    fn footprint(&self) -> usize {
+        size_of::<Self>() + self.coverage.len()
+    }
+}
+
+/// 字形在一行里的位置：像素包围盒左上角在 (min_x, min_y)（相对原点，min_y 在基线以上为负）的字形，
+/// 放进行高为 `line_height`、基线在底部的一行时，左上角相对这一行左上角的偏移（行，列），可能为负
+pub fn placement(min_x: i32, min_y: i32, line_height: usize) -> (isize, isize) {
+    (line_height as isize + min_y as isize, min_x as isize)
+}
+
+/// 一行文字占的高度：字号加上 1/4 的行距。控制台和终端窗口按它排列各行，制表符也画满这个高度，上下两行正好接上
+pub fn line_pitch(size: f32) -> usize {
+    size as usize + size as usize / 4
+}
+
+struct GlyphCache {
+    // 键是字体、字符、字号和是否超采样，值是字形和最近一次使用的时间
+    glyphs: BTreeMap<(FontId, char, u32, bool), (Arc<Glyph>, u64)>,
+    clock: u64,
+}
+
+impl GlyphCache {
+    // 淘汰最久没用的字形，返回大约释放的字节数
+    fn evict(&mut self) -> usize {
+        let oldest = self.glyphs.iter().min_by_key(|(_, (_, used))| *used).map(|(key, _)| *key);
+        oldest.and_then(|key| self.glyphs.remove(&key)).map_or(0, |(glyph, _)| glyph.footprint())
+    }
+}
+
+/// 取 `ch` 用 `font` 在 `size` 字号下光栅化好的字形，没有缓存时光栅化并放进缓存
+pub fn glyph(font: FontId, ch: char, size: f32) -> Arc<Glyph> {
+    cached((font, ch, size.to_bits(), false), || Glyph::rasterize(font, ch, size))
+}
+
+/// 和 glyph 一样，但按两倍字号光栅化再缩小一半，边缘的过渡更平滑，给大字号的标题用；前进宽度和位置与 glyph 给出的一致
+pub fn glyph_supersampled(font: FontId, ch: char, size: f32) -> Arc<Glyph> {
+    cached((font, ch, size.to_bits(), true), || glyph(font, ch, size * 2.0).downsample())
+}
+
+fn cached(key: (FontId, char, u32, bool), rasterize: impl FnOnce() -> Glyph) -> Arc<Glyph> {
+    let cached = interrupts::without_interrupts(|| {
+        let mut cache = CACHE.lock();
+        cache.clock += 1;
+        let clock = cache.clock;
+        cache.glyphs.get_mut(&key).map(|(glyph, used)| {
+            *used = clock;
+            glyph.clone()
+        })
+    });
+    if let Some(glyph) = cached {
+        return glyph;
+    }
+    // 光栅化时不持有锁
+    let glyph = Arc::new(rasterize());
+    interrupts::without_interrupts(|| {
+        let mut cache = CACHE.lock();
+        if cache.glyphs.len() >= CACHE_CAPACITY {
+            cache.evict();
+        }
+        let clock = cache.clock;
+        cache.glyphs.insert(key, (glyph.clone(), clock));
+    });
+    glyph
+}
+
+struct GlyphCacheShrinker;
+
+static GLYPH_CACHE_SHRINKER: GlyphCacheShrinker = GlyphCacheShrinker;
+
+impl Shrinker for GlyphCacheShrinker {
+    fn name(&self) -> &'static str {
+        "glyph cache"
+    }
+
+    fn count(&self) -> usize {
+        interrupts::without_interrupts(|| {
+            CACHE.lock().glyphs.values().map(|(glyph, _)| glyph.footprint()).sum()
+        })
+    }
+
+    fn shrink(&self, target: usize) -> usize {
+        interrupts::without_interrupts(|| {
+            let Some(mut cache) = CACHE.try_lock() else { return 0 };
+            let mut freed = 0;
+            while freed < target && !cache.glyphs.is_empty() {
+                freed += cache.evict();
+            }
+            freed
+        })
+    }
+}
+
 // 步骤:
 // 1. 导入`alloc`库中的`format`和`ToString`，用于字符串格式化。
 // 2. 导入`lazy_static`宏，用于定义静态变量。
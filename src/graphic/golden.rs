@@ -0,0 +1,113 @@
+// 画面断言，给在 QEMU 里跑的图形测试用
+// 渲染完之后检查屏幕上的一块区域：和期望的校验和比较（check_hash），或者和嵌入内核的参考图逐像素比较（check_image）；
+// 画在内存里的画布（Surface、Image）用 check_canvas 比较，不需要图形模式。小的参考图可以用字符画写在代码里（from_ascii）。
+// 不一致时日志里输出 "golden: <名字> FAILED"，并把实际画面和差异图编码成 BMP、以 base64 写到串口，
+// 夹在 "-----BEGIN BMP <名字>-----" 和 "-----END BMP-----" 之间，CI 脚本可以据此判断结果并取出图片。
+// 差异图里相同的像素变暗变灰，不同的像素标成红色
+
+use embedded_graphics::pixelcolor::Rgb888;
+use embedded_graphics::prelude::*;
+
+use crate::graphic::canvas::Canvas;
+use crate::graphic::image::Image;
+use crate::graphic::screenshot::write_bmp;
+use crate::graphic::{Region, GD};
+use crate::io::qemu::SerialStream;
+
+const MISMATCH_COLOR: Rgb888 = Rgb888::new(0xFF, 0, 0);
+
+/// 屏幕上这块区域的校验和，先用它记下期望值
+pub fn hash(region: Region) -> u32 {
+    GD.lock().checksum(region)
+}
+
+/// 检查区域的校验和，不一致时把实际画面写到串口
+pub fn check_hash(name: &str, region: Region, expected: u32) -> bool {
+    let actual = hash(region);
+    if actual == expected {
+        log::info!("golden: {} ok", name);
+        return true;
+    }
+    log::error!("golden: {} FAILED, checksum {:08x}, expected {:08x}", name, actual, expected);
+    dump(name, region, |_, _, color| color);
+    false
+}
+
+/// 把 `golden` 放在 (x, y) 处逐像素比较，参考图里透明的像素不比较；不一致时把实际画面和差异图写到串口
+pub fn check_image(name: &str, x: usize, y: usize, golden: &Image) -> bool {
+    let region = Region { sx: x, sy: y, ex: x + golden.height(), ey: y + golden.width() };
+    let mismatched = {
+        let gd = GD.lock();
+        let mut count = 0;
+        for i in 0..golden.height() {
+            for j in 0..golden.width() {
+                if golden.pixel(i, j).map_or(false, |expected| expected != gd.read_pixel(x + i, y + j)) {
+                    count += 1;
+                }
+            }
+        }
+        count
+    };
+    if mismatched == 0 {
+        log::info!("golden: {} ok", name);
+        return true;
+    }
+    log::error!("golden: {} FAILED, {} pixels differ", name, mismatched);
+    dump(name, region, |_, _, color| color);
+    dump(name, region, |i, j, color| difference(golden, i, j, Some(color)));
+    false
+}
+
+/// 同 check_image，但比较的是画布 `canvas` 上 (x, y) 处的区域；画布上透明的像素和参考图里不透明的像素算作不一致
+pub fn check_canvas<C: Canvas + ?Sized>(name: &str, canvas: &C, x: usize, y: usize, golden: &Image) -> bool {
+    let actual = |i, j| canvas.get_pixel(x + i, y + j);
+    let mut mismatched = 0;
+    for i in 0..golden.height() {
+        for j in 0..golden.width() {
+            if golden.pixel(i, j).is_some_and(|expected| Some(expected) != actual(i, j)) {
+                mismatched += 1;
+            }
+        }
+    }
+    if mismatched == 0 {
+        log::info!("golden: {} ok", name);
+        return true;
+    }
+    log::error!("golden: {} FAILED, {} pixels differ", name, mismatched);
+    let (height, width) = (golden.height(), golden.width());
+    let _ = write_bmp(&mut SerialStream, name, height, width, |i, j| actual(i, j).unwrap_or(Rgb888::BLACK));
+    let _ = write_bmp(&mut SerialStream, name, height, width, |i, j| difference(golden, i, j, actual(i, j)));
+    false
+}
+
+/// 用字符画写的参考图，每个字符串是一行：`on` 字符是 `color`，其余字符是 `background`
+pub fn from_ascii(rows: &[&str], on: char, color: Rgb888, background: Rgb888) -> Image {
+    let width = rows.iter().map(|row| row.chars().count()).max().unwrap_or(0);
+    let mut image = Image::new(width, rows.len());
+    for (i, row) in rows.iter().enumerate() {
+        for j in 0..width {
+            let lit = row.chars().nth(j) == Some(on);
+            image.put_pixel(i, j, if lit { color } else { background });
+        }
+    }
+    image
+}
+
+// 差异图的像素：和参考图不同的标成红色，相同的变暗变灰
+fn difference(golden: &Image, i: usize, j: usize, color: Option<Rgb888>) -> Rgb888 {
+    match (golden.pixel(i, j), color) {
+        (Some(expected), actual) if Some(expected) != actual => MISMATCH_COLOR,
+        (_, Some(color)) => {
+            let gray = ((color.r() as u32 + color.g() as u32 + color.b() as u32) / 6) as u8;
+            Rgb888::new(gray, gray, gray)
+        }
+        (_, None) => Rgb888::BLACK,
+    }
+}
+
+// 把屏幕区域里的像素经过 `map`（参数是区域内的行、列和实际颜色）编码成 BMP 写到串口
+fn dump(name: &str, region: Region, map: impl Fn(usize, usize, Rgb888) -> Rgb888) {
+    let gd = GD.lock();
+    let (height, width) = (region.ex - region.sx, region.ey - region.sy);
+    let _ = write_bmp(&mut SerialStream, name, height, width, |i, j| map(i, j, gd.read_pixel(region.sx + i, region.sy + j)));
+}
@@ -0,0 +1,271 @@
+// 图片解码，支持 BMP 和 QOI（压缩率接近 PNG，解码简单得多，适合放壁纸和图标）
+// 解码器把像素逐个交给 PixelSink：可以解码成 Image 留着反复使用，
+// 也可以用 At 直接写到图层或窗口上（按位置偏移并裁剪），大图片不需要先解码到临时缓冲区再复制一遍。
+// Image 的像素放在 Arc 里，复制 Image 只增加引用计数，可以在多个图层和窗口之间共享；
+// 需要修改时 pixels_mut 按写时复制拿到独占的一份。Image 本身也是画布，可以用 Canvas 的画图函数画出精灵
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use embedded_graphics::pixelcolor::Rgb888;
+use embedded_graphics::prelude::*;
+use tinybmp::{Bmp, ChannelMasks, RawBmp, RawPixel};
+
+use crate::graphic::canvas::Canvas;
+
+/// 接收解码出的像素
+pub trait PixelSink {
+    /// 知道图片大小后、第一个像素之前调用一次
+    fn begin(&mut self, _width: usize, _height: usize) {}
+
+    /// 第 x 行第 y 列的像素，透明的像素不会传进来
+    fn put_pixel(&mut self, x: usize, y: usize, color: Rgb888);
+}
+
+/// 把图片直接画到画布的 (x, y) 处，超出画布的部分被裁掉
+pub struct At<'a, C: Canvas + ?Sized> {
+    canvas: &'a mut C,
+    x: usize,
+    y: usize,
+}
+
+impl<'a, C: Canvas + ?Sized> At<'a, C> {
+    pub fn new(canvas: &'a mut C, x: usize, y: usize) -> Self {
+        Self { canvas, x, y }
+    }
+}
+
+impl<C: Canvas + ?Sized> PixelSink for At<'_, C> {
+    fn put_pixel(&mut self, x: usize, y: usize, color: Rgb888) {
+        self.canvas.put_pixel(self.x + x, self.y + y, color);
+    }
+}
+
+/// 解码不带透明度的 BMP，失败时返回 false
+pub fn decode_bmp(data: &[u8], sink: &mut impl PixelSink) -> bool {
+    let bmp = match Bmp::<Rgb888>::from_slice(data) {
+        Ok(bmp) => bmp,
+        Err(error) => {
+            log::error!("Failed to parse BMP: {:?}", error);
+            return false;
+        }
+    };
+    let size = bmp.size();
+    sink.begin(size.width as usize, size.height as usize);
+    for Pixel(position, color) in bmp.pixels() {
+        sink.put_pixel(position.y as usize, position.x as usize, color);
+    }
+    true
+}
+
+/// 解码 32 位带透明度的 BMP，透明度不到一半的像素当作透明，失败时返回 false
+pub fn decode_bmp_32rgba(data: &[u8], sink: &mut impl PixelSink) -> bool {
+    let bmp = match RawBmp::from_slice(data) {
+        Ok(bmp) => bmp,
+        Err(error) => {
+            log::error!("Failed to parse BMP: {:?}", error);
+            return false;
+        }
+    };
+    let cm = bmp.header().channel_masks.unwrap_or(ChannelMasks {
+        blue: 0x000000FF,
+        green: 0x0000FF00,
+        red: 0x00FF0000,
+        alpha: 0xFF000000,
+    });
+    let (rr, gr, br) = (cm.red.trailing_zeros(), cm.green.trailing_zeros(), cm.blue.trailing_zeros());
+    // 没有透明通道时全部不透明
+    let ar = cm.alpha.trailing_zeros() % 32;
+    let asize = if cm.alpha == 0 { 0.0 } else { (cm.alpha >> ar) as f32 };
+
+    let size = bmp.header().image_size;
+    sink.begin(size.width as usize, size.height as usize);
+    for RawPixel { position, color } in bmp.pixels() {
+        let alpha = if cm.alpha == 0 { 1.0 } else { ((color & cm.alpha) >> ar) as f32 / asize };
+        if alpha > 0.5 {
+            let rgb_color = Rgb888::new(((color & cm.red) >> rr) as u8, ((color & cm.green) >> gr) as u8, ((color & cm.blue) >> br) as u8);
+            sink.put_pixel(position.y as usize, position.x as usize, rgb_color);
+        }
+    }
+    true
+}
+
+/// 解码 QOI 图片，透明度不到一半的像素当作透明，失败时返回 false
+///
+/// 格式见 https://qoiformat.org/qoi-specification.pdf
+pub fn decode_qoi(data: &[u8], sink: &mut impl PixelSink) -> bool {
+    const HEADER_SIZE: usize = 14;
+
+    if data.len() < HEADER_SIZE || &data[..4] != b"qoif" {
+        log::error!("Failed to parse QOI: bad header");
+        return false;
+    }
+    let width = u32::from_be_bytes(data[4..8].try_into().unwrap()) as usize;
+    let height = u32::from_be_bytes(data[8..12].try_into().unwrap()) as usize;
+    // 规范规定的上限，防止损坏的文件让分配失败
+    if width == 0 || height == 0 || width.checked_mul(height).map_or(true, |n| n > 400_000_000) {
+        log::error!("Failed to parse QOI: bad size {}x{}", width, height);
+        return false;
+    }
+    sink.begin(width, height);
+    if qoi_pixels(&data[HEADER_SIZE..], width, height, sink).is_none() {
+        log::error!("Failed to parse QOI: truncated data");
+        return false;
+    }
+    true
+}
+
+// 解码 QOI 的数据部分，数据不完整时返回 None
+fn qoi_pixels(data: &[u8], width: usize, height: usize, sink: &mut impl PixelSink) -> Option<()> {
+    const OP_RGB: u8 = 0xFE;
+    const OP_RGBA: u8 = 0xFF;
+
+    let mut index = [[0u8; 4]; 64];
+    let mut px = [0u8, 0, 0, 255];
+    let mut run = 0;
+    let mut pos = 0;
+    let mut byte = || {
+        let b = data.get(pos).copied();
+        pos += 1;
+        b
+    };
+    for i in 0..width * height {
+        if run > 0 {
+            run -= 1;
+        } else {
+            let op = byte()?;
+            match op {
+                OP_RGB => {
+                    px[0] = byte()?;
+                    px[1] = byte()?;
+                    px[2] = byte()?;
+                }
+                OP_RGBA => {
+                    px[0] = byte()?;
+                    px[1] = byte()?;
+                    px[2] = byte()?;
+                    px[3] = byte()?;
+                }
+                _ => match op >> 6 {
+                    // QOI_OP_INDEX
+                    0 => px = index[(op & 0x3F) as usize],
+                    // QOI_OP_DIFF
+                    1 => {
+                        px[0] = px[0].wrapping_add((op >> 4 & 0x03).wrapping_sub(2));
+                        px[1] = px[1].wrapping_add((op >> 2 & 0x03).wrapping_sub(2));
+                        px[2] = px[2].wrapping_add((op & 0x03).wrapping_sub(2));
+                    }
+                    // QOI_OP_LUMA
+                    2 => {
+                        let next = byte()?;
+                        let dg = (op & 0x3F).wrapping_sub(32);
+                        px[0] = px[0].wrapping_add(dg.wrapping_add(next >> 4).wrapping_sub(8));
+                        px[1] = px[1].wrapping_add(dg);
+                        px[2] = px[2].wrapping_add(dg.wrapping_add(next & 0x0F).wrapping_sub(8));
+                    }
+                    // QOI_OP_RUN
+                    _ => run = op & 0x3F,
+                },
+            }
+            let hash = px[0] as usize * 3 + px[1] as usize * 5 + px[2] as usize * 7 + px[3] as usize * 11;
+            index[hash % 64] = px;
+        }
+        if px[3] > 127 {
+            sink.put_pixel(i / width, i % width, Rgb888::new(px[0], px[1], px[2]));
+        }
+    }
+    Some(())
+}
+
+#[derive(Debug, Clone)]
+pub struct Image {
+    width: usize,
+    height: usize,
+    // 按行存放，None 表示透明
+    pixels: Arc<Vec<Option<Rgb888>>>,
+}
+
+// 解码成 Image
+impl PixelSink for Image {
+    fn begin(&mut self, width: usize, height: usize) {
+        *self = Self::transparent(width, height);
+    }
+
+    fn put_pixel(&mut self, x: usize, y: usize, color: Rgb888) {
+        let width = self.width;
+        if let Some(pixel) = self.pixels_mut().get_mut(x * width + y) {
+            *pixel = Some(color);
+        }
+    }
+}
+
+impl Image {
+    /// 解码不带透明度的 BMP
+    pub fn from_bmp(data: &[u8]) -> Option<Self> {
+        Self::decode(|image| decode_bmp(data, image))
+    }
+
+    /// 解码 32 位带透明度的 BMP，透明度不到一半的像素当作透明
+    pub fn from_bmp_32rgba(data: &[u8]) -> Option<Self> {
+        Self::decode(|image| decode_bmp_32rgba(data, image))
+    }
+
+    /// 解码 QOI 图片，透明度不到一半的像素当作透明
+    pub fn from_qoi(data: &[u8]) -> Option<Self> {
+        Self::decode(|image| decode_qoi(data, image))
+    }
+
+    fn decode(decoder: impl FnOnce(&mut Self) -> bool) -> Option<Self> {
+        let mut image = Self::transparent(0, 0);
+        decoder(&mut image).then_some(image)
+    }
+
+    fn transparent(width: usize, height: usize) -> Self {
+        Self { width, height, pixels: Arc::new(alloc::vec![None; width * height]) }
+    }
+
+    /// 宽 `width` 列、高 `height` 行的透明图片
+    pub fn new(width: usize, height: usize) -> Self {
+        Self::transparent(width, height)
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// 第 x 行第 y 列的像素，透明或超出图片时返回 None
+    pub fn pixel(&self, x: usize, y: usize) -> Option<Rgb888> {
+        if x < self.height && y < self.width { self.pixels[x * self.width + y] } else { None }
+    }
+
+    /// 像素和其他副本共享
+    pub fn is_shared(&self) -> bool {
+        Arc::strong_count(&self.pixels) > 1
+    }
+
+    /// 可修改的像素，和其他副本共享时先复制一份
+    pub fn pixels_mut(&mut self) -> &mut [Option<Rgb888>] {
+        Arc::make_mut(&mut self.pixels).as_mut_slice()
+    }
+}
+
+impl Canvas for Image {
+    fn size(&self) -> (usize, usize) {
+        (self.height, self.width)
+    }
+
+    fn put_pixel(&mut self, x: usize, y: usize, color: Rgb888) {
+        if x < self.height && y < self.width {
+            let width = self.width;
+            self.pixels_mut()[x * width + y] = Some(color);
+        }
+    }
+
+    fn get_pixel(&self, x: usize, y: usize) -> Option<Rgb888> {
+        (x < self.height && y < self.width).then(|| self.pixels[x * self.width + y]).flatten()
+    }
+}
@@ -0,0 +1,220 @@
+// 图层管理
+// 图层按 z 值从下往上叠放，GL 里的顺序就是叠放顺序，z 相同时后加入（或者后调整）的在上面。
+// 每个图层有一个不会变的 LayerHandle：增删图层、调整顺序之后下标会变，句柄不变，其他模块都通过句柄访问图层。
+// 启动时只有背景、控制台和鼠标三个图层，窗口和通知的图层由窗口管理器和 gui::toast 按需加入。
+// 最下面的图层是背景，合成时作为底色，其他图层的 z 要比它大；鼠标图层的 z 最大，只有屏幕保护程序的图层盖在它上面。
+// 画抗锯齿的文字时图层上透明的地方要和下面的内容混合，with_layer_blended 按合成时的顺序从下往上锁住它下面的图层，
+// 在图层的透明处读回下面各层合成的颜色；混合的结果写死在图层里，下面的内容后来变了也不会跟着变
+
+use alloc::collections::TryReserveError;
+use alloc::vec::Vec;
+use core::ops::Deref;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use embedded_graphics::pixelcolor::Rgb888;
+use spin::{Mutex, MutexGuard};
+use x86_64::instructions::interrupts;
+
+use crate::graphic::canvas::Canvas;
+use crate::graphic::color::alpha_mix_final;
+use crate::graphic::{Writer, GL};
+
+/// 图层的句柄
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LayerHandle(usize);
+
+/// 背景和状态栏的底色
+pub const BACKGROUND: LayerHandle = LayerHandle(0);
+/// 文本控制台和状态栏的文字
+pub const CONSOLE: LayerHandle = LayerHandle(1);
+/// 鼠标
+pub const CURSOR: LayerHandle = LayerHandle(2);
+
+pub const Z_BACKGROUND: i32 = i32::MIN;
+pub const Z_CONSOLE: i32 = 0;
+/// 窗口的图层都用这个 z，提到最上层时重新设置一次
+pub const Z_WINDOW: i32 = 100;
+/// 通知在所有窗口之上
+pub const Z_TOAST: i32 = 1000;
+pub const Z_CURSOR: i32 = i32::MAX;
+/// 屏幕保护程序和鼠标的 z 相同，后加入所以连鼠标也盖住
+pub const Z_SCREENSAVER: i32 = i32::MAX;
+
+static NEXT_HANDLE: AtomicUsize = AtomicUsize::new(3);
+
+/// GL 中的一个图层：句柄和 z 值不用加锁就能读，查找图层时不会碰到别人正锁着的图层
+pub struct Layer {
+    handle: LayerHandle,
+    z: i32,
+    writer: Mutex<Writer>,
+}
+
+impl Layer {
+    pub fn handle(&self) -> LayerHandle {
+        self.handle
+    }
+
+    pub fn z(&self) -> i32 {
+        self.z
+    }
+}
+
+impl Deref for Layer {
+    type Target = Mutex<Writer>;
+
+    fn deref(&self) -> &Mutex<Writer> {
+        &self.writer
+    }
+}
+
+// 启动时的三个图层，鼠标图层一直显示
+pub(super) fn builtin() -> Vec<Layer> {
+    let mut cursor = Writer::new();
+    cursor.enable = true;
+    [(BACKGROUND, Z_BACKGROUND, Writer::new()), (CONSOLE, Z_CONSOLE, Writer::new()), (CURSOR, Z_CURSOR, cursor)]
+        .into_iter()
+        .map(|(handle, z, writer)| Layer { handle, z, writer: Mutex::new(writer) })
+        .collect()
+}
+
+// 按 z 值插入，排在所有 z 不大于它的图层之上
+fn insert(layers: &mut Vec<Layer>, layer: Layer) {
+    let index = layers.iter().position(|l| l.z > layer.z).unwrap_or(layers.len());
+    layers.insert(index, layer);
+}
+
+fn index_of(layers: &[Layer], handle: LayerHandle) -> Option<usize> {
+    layers.iter().position(|layer| layer.handle == handle)
+}
+
+/// 加入一个图层，放在所有 z 不大于 `z` 的图层之上
+pub fn add_layer(layer: Writer, z: i32) -> LayerHandle {
+    let handle = LayerHandle(NEXT_HANDLE.fetch_add(1, Ordering::Relaxed));
+    let layer = Layer { handle, z, writer: Mutex::new(layer) };
+    interrupts::without_interrupts(|| insert(&mut GL.write(), layer));
+    handle
+}
+
+/// 新建一个占满屏幕的透明图层，默认不显示；内存不够时返回错误
+pub fn create_layer(z: i32) -> Result<LayerHandle, TryReserveError> {
+    Ok(add_layer(Writer::try_new()?, z))
+}
+
+/// 移除图层，返回它的内容；调用者负责请求重新合成它原来占的区域
+pub fn remove_layer(handle: LayerHandle) -> Option<Writer> {
+    interrupts::without_interrupts(|| {
+        let mut layers = GL.write();
+        let index = index_of(&layers, handle)?;
+        Some(layers.remove(index).writer.into_inner())
+    })
+}
+
+/// 调整图层的 z 值，放到所有 z 不大于它的图层之上，图层不存在时返回 false
+pub fn set_z_order(handle: LayerHandle, z: i32) -> bool {
+    interrupts::without_interrupts(|| {
+        let mut layers = GL.write();
+        let Some(index) = index_of(&layers, handle) else { return false };
+        let mut layer = layers.remove(index);
+        layer.z = z;
+        // 叠放顺序变了，图层盖住的地方要重新合成
+        {
+            let mut writer = layer.lock();
+            if let Some(area) = writer.extent() {
+                writer.touch(area, false);
+            }
+        }
+        insert(&mut layers, layer);
+        true
+    })
+}
+
+pub fn z_order(handle: LayerHandle) -> Option<i32> {
+    let layers = GL.read();
+    index_of(&layers, handle).map(|index| layers[index].z)
+}
+
+/// 显示或者隐藏图层，图层不存在时返回 false
+pub fn set_visible(handle: LayerHandle, visible: bool) -> bool {
+    with_layer(handle, |layer| {
+        if layer.enable != visible {
+            layer.enable = visible;
+            if let Some(area) = layer.extent() {
+                layer.touch(area, false);
+            }
+        }
+    })
+    .is_some()
+}
+
+/// 对图层执行 `f`，图层不存在时返回 None
+pub fn with_layer<R>(handle: LayerHandle, f: impl FnOnce(&mut Writer) -> R) -> Option<R> {
+    let layers = GL.read();
+    let index = index_of(&layers, handle)?;
+    let mut layer = layers[index].lock();
+    Some(f(&mut layer))
+}
+
+/// 和 with_layer 一样，但交给 `f` 的画布在图层透明的地方读回下面各层合成的颜色，Canvas::blend_pixel 据此混合
+pub fn with_layer_blended<R>(handle: LayerHandle, f: impl FnOnce(&mut Blended<'_>) -> R) -> Option<R> {
+    let layers = GL.read();
+    let index = index_of(&layers, handle)?;
+    // 和合成时一样从下往上加锁
+    let below: Vec<_> = layers[..index].iter().map(|layer| layer.lock()).collect();
+    let mut layer = layers[index].lock();
+    Some(f(&mut Blended { layer: &mut layer, below }))
+}
+
+/// with_layer_blended 交给调用者的画布
+pub struct Blended<'a> {
+    layer: &'a mut Writer,
+    // 从下往上，第一个是背景
+    below: Vec<MutexGuard<'a, Writer>>,
+}
+
+impl Blended<'_> {
+    // 下面各层在 (x, y) 处合成的颜色，不算阴影
+    fn below(&self, x: usize, y: usize) -> Rgb888 {
+        let Some((background, layers)) = self.below.split_first() else { return Rgb888::new(0, 0, 0) };
+        let visible = |layer: &&MutexGuard<Writer>| layer.enable && layer.opacity() > 0.0;
+        // 从上往下找第一个完全盖住这个像素的图层，再从它往上依次混合
+        let cover = layers.iter().rposition(|layer| visible(&layer) && layer.opacity() >= 1.0 && layer.pixel(x, y).1);
+        let (mut color, start) = match cover {
+            Some(index) => (layers[index].pixel(x, y).0, index + 1),
+            None => (background.pixel(x, y).0, 0),
+        };
+        for layer in layers[start..].iter().filter(visible) {
+            let (fg, opaque) = layer.pixel(x, y);
+            if opaque {
+                color = alpha_mix_final(fg, layer.opacity(), color);
+            }
+        }
+        color
+    }
+}
+
+impl Canvas for Blended<'_> {
+    fn size(&self) -> (usize, usize) {
+        Canvas::size(&*self.layer)
+    }
+
+    fn put_pixel(&mut self, x: usize, y: usize, color: Rgb888) {
+        self.layer.put_pixel(x, y, color);
+    }
+
+    fn get_pixel(&self, x: usize, y: usize) -> Option<Rgb888> {
+        let (height, width) = Canvas::size(&*self.layer);
+        if x >= height || y >= width {
+            return None;
+        }
+        self.layer.get_pixel(x, y).or_else(|| Some(self.below(x, y)))
+    }
+
+    fn fill_span(&mut self, x: usize, y0: usize, y1: usize, color: Rgb888) {
+        self.layer.fill_span(x, y0, y1, color);
+    }
+}
+
+/// 从下往上所有图层的句柄和 z 值
+pub fn layers() -> Vec<(LayerHandle, i32)> {
+    GL.read().iter().map(|layer| (layer.handle, layer.z)).collect()
+}
@@ -1,83 +1,283 @@
-// 引入 `alloc` 库中的 `format` 宏，用于格式化字符串
-use alloc::{format, vec};
+// 引入 `alloc` 库中的 `vec` 宏，用于创建动态数组
+use alloc::vec;
 use alloc::boxed::Box;
+use alloc::collections::TryReserveError;
 use alloc::vec::Vec;
+use core::convert::Infallible;
 use core::fmt;
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::time::Duration;
 // 引入 `core` 库中的 `min` 函数，用于计算两个值的较小值
-use core::cmp::min;
+use core::cmp::{max, min};
 // 引入 `embedded_graphics` 库中的颜色类型 `Rgb888` 和预导出的所有内容（prelude），以及另一个颜色类型 `Bgr888`
 use embedded_graphics::{pixelcolor::Rgb888, prelude::*};
+use embedded_graphics::primitives::Rectangle;
 // 引入 `lazy_static` 宏，用于声明静态变量并进行延迟初始化
 use lazy_static::lazy_static;
-use rusttype::{point, Rect, ScaledGlyph};
-use spin::{Mutex, RwLock};
-use tinybmp::{Bmp, ChannelMasks, RawBmp, RawPixel};
-// 引入 `volatile` 库中的 `Volatile` 类型，用于对内存中可能随时改变的数据进行读写操作
-use volatile::Volatile;
+use spin::{Mutex, Once, RwLock};
 use x86_64::instructions::interrupts;
 // 引入 x86_64 架构相关的分页模块和类型，包括帧分配器、偏移页表、页面以及虚拟地址 (`VirtAddr`) 类型
-use x86_64::structures::paging::{FrameAllocator, OffsetPageTable, Page, Size4KiB};
+use x86_64::structures::paging::OffsetPageTable;
 use x86_64::VirtAddr;
 
-use crate::graphic::color::alpha_mix;
-use crate::graphic::font::get_font;
+use crate::assets;
+use crate::boot::Unit;
+use crate::config;
+use crate::graphic::backend::Mode;
+use crate::graphic::canvas::{draw_pixels, fill_area, Canvas};
+use crate::graphic::color::{alpha_mix, alpha_mix_final};
+use crate::graphic::font::{glyph, FontId, Glyph};
+use crate::graphic::image::At;
+use crate::graphic::layer::Layer;
+use crate::graphic::present::PresentFeedback;
+use crate::graphic::shadow::{Shadow, SHADOW_COLOR};
+use crate::graphic::surface::{self, Surface};
 use crate::graphic::text::TEXT_WRITER;
-use crate::io::qemu::qemu_print;
-use crate::io::VIDEO_MODE;
-use crate::rgb888;
+use crate::graphic::vbe::ModeError;
+use crate::graphic::video::VideoSurface;
+use crate::io::{timer, VIDEO_MODE};
+use crate::memory::BootInfoFrameAllocator;
+use crate::perf;
+use crate::{kdebug_assert, rgb888};
 
 pub mod vbe;
+pub mod backend;
+pub mod mode13h;
 pub mod font;
+pub mod boxdraw;
 pub mod text;
 pub mod color;
+pub mod canvas;
+pub mod image;
+pub mod video;
+pub mod sprite;
+pub mod shadow;
+pub mod golden;
+pub mod screenshot;
+pub mod selftest;
+pub mod present;
+pub mod surface;
+pub mod layer;
+
+pub use screenshot::screenshot;
 
 // 定义一个表示像素数据的结构体，包含红色、绿色和蓝色分量。使用C语言风格布局保证字段顺序一致性，并实现一些常用的trait如Debug、Clone等，以方便使用和调试
 
 // 相关配置 
-// 定义屏幕宽度和高度为800像素
-pub const WIDTH: usize = 800;
-pub const HEIGHT: usize = 600;
-
-// 定义一个屏幕缓冲区结构体，它是一个二维数组，每个元素都是具有易变特性的像素。这里使用透明属性使得Buffer与其内部数组具有相同布局
-#[repr(transparent)]
-pub struct Buffer {
-    chars: [[Volatile<Rgb888>; WIDTH]; HEIGHT],
+// 默认分辨率 800x600，32 位色深，启动参数 video= 和运行时的 set_mode 可以修改
+pub const DEFAULT_WIDTH: usize = 800;
+pub const DEFAULT_HEIGHT: usize = 600;
+pub const DEFAULT_BPP: usize = 32;
+
+// fill_row 每次复制的字节数
+const FILL_CHUNK: usize = 256;
+
+// 当前的分辨率和色深
+static SCREEN_WIDTH: AtomicUsize = AtomicUsize::new(DEFAULT_WIDTH);
+static SCREEN_HEIGHT: AtomicUsize = AtomicUsize::new(DEFAULT_HEIGHT);
+static SCREEN_BPP: AtomicUsize = AtomicUsize::new(DEFAULT_BPP);
+static SCREEN_PITCH: AtomicUsize = AtomicUsize::new(DEFAULT_WIDTH * DEFAULT_BPP / 8);
+
+/// 当前屏幕宽度（列数）
+pub fn width() -> usize {
+    SCREEN_WIDTH.load(Ordering::Relaxed)
 }
 
-// 定义显示器结构体，它包含了一个缓冲区对象.
-pub struct PhysicalWriter(&'static mut Buffer);
+/// 当前屏幕高度（行数）
+pub fn height() -> usize {
+    SCREEN_HEIGHT.load(Ordering::Relaxed)
+}
+
+/// 当前色深
+pub fn bpp() -> usize {
+    SCREEN_BPP.load(Ordering::Relaxed)
+}
+
+/// 当前的显示模式
+pub fn mode() -> Mode {
+    Mode { width: width(), height: height(), bpp: bpp(), pitch: SCREEN_PITCH.load(Ordering::Relaxed) }
+}
+
+fn store_mode(mode: Mode) {
+    SCREEN_WIDTH.store(mode.width, Ordering::Relaxed);
+    SCREEN_HEIGHT.store(mode.height, Ordering::Relaxed);
+    SCREEN_BPP.store(mode.bpp, Ordering::Relaxed);
+    SCREEN_PITCH.store(mode.pitch, Ordering::Relaxed);
+}
+
+// 定义显示器结构体，它直接写显存。显存中每个像素按 B、G、R(、保留) 的顺序存放
+//
+// 显存放得下两页时使用双缓冲：合成时画到后台页，画完改 YOffset 翻页，屏幕上不会看到画了一半的内容
+pub struct PhysicalWriter {
+    base: *mut u8,
+    width: usize,
+    height: usize,
+    // 每行占用的字节数
+    pitch: usize,
+    bytes_per_pixel: usize,
+    // 显存里的页数和当前显示的页
+    pages: usize,
+    front: usize,
+    // 后台页比前台页旧的区域，下次合成时要一起画
+    stale: Option<Region>,
+    // 正在播放的视频，合成时它覆盖的区域不混合图层
+    video: Option<VideoSurface>,
+}
+
+// 显存只通过 GD 的锁访问
+unsafe impl Send for PhysicalWriter {}
+
+/// 屏幕上的一块矩形区域：行 sx..ex，列 sy..ey
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub sx: usize,
+    pub sy: usize,
+    pub ex: usize,
+    pub ey: usize,
+}
+
+impl Region {
+    /// 超出屏幕的部分会被裁掉
+    pub fn new(sx: usize, sy: usize, ex: usize, ey: usize) -> Self {
+        let (height, width) = (height(), width());
+        Self { sx: min(sx, height), sy: min(sy, width), ex: min(ex, height), ey: min(ey, width) }
+    }
 
-#[derive(Clone, Debug)]
+    pub fn is_empty(&self) -> bool {
+        self.sx >= self.ex || self.sy >= self.ey
+    }
+
+    pub fn union(self, other: Region) -> Region {
+        Region {
+            sx: min(self.sx, other.sx),
+            sy: min(self.sy, other.sy),
+            ex: max(self.ex, other.ex),
+            ey: max(self.ey, other.ey),
+        }
+    }
+
+    pub fn intersects(&self, other: &Region) -> bool {
+        self.sx < other.ex && other.sx < self.ex && self.sy < other.ey && other.sy < self.ey
+    }
+
+    pub fn contains(&self, other: &Region) -> bool {
+        self.sx <= other.sx && self.sy <= other.sy && self.ex >= other.ex && self.ey >= other.ey
+    }
+}
+
+// 图层很大，不实现 Clone，避免无意中整块复制
+//
+// 图层的内容是一块放在屏幕 (x, y) 处的 Surface，默认占满整个屏幕；窗口的图层和窗口一样大。
+// 所有方法的坐标都是屏幕坐标，画到 Surface 外面的部分被裁掉
+#[derive(Debug)]
 pub struct Writer {
-    pub data: Vec<Vec<(Rgb888, bool)>>,
+    pub data: Surface,
     pub enable: bool,
+    // 左上角在屏幕上的位置
+    x: usize,
+    y: usize,
+    // Surface 的大小
+    width: usize,
+    height: usize,
+    // 整个图层的不透明度，0.0 完全透明，1.0 完全不透明
+    opacity: f32,
+    // 自上次合成以来改动过的区域
+    dirty: Option<Region>,
+    // 所有不透明像素的包围盒，只增不减（整块清空时除外），合成时跳过不相交的图层
+    bounds: Option<Region>,
+    // 图层投下的阴影，合成时压暗下面的内容
+    shadow: Option<Shadow>,
+    // 还没合成的改动第一次提交的时间
+    submitted: Option<Duration>,
+    // 已经合成、等待上屏的那一帧和它包含的改动的提交时间
+    presenting: Option<(u64, Duration)>,
+    // 最近一次确认上屏的改动
+    presented: Option<PresentFeedback>,
 }
 
+// 显存被映射到的虚拟地址，由 memory::vmm 分配，进入宽屏模式时设置
+static FRAMEBUFFER: Once<VirtAddr> = Once::new();
+
 // 使用lazy_static宏创建一个全局静态缓冲区对象，并将其包装在互斥锁中以确保线程安全。通过不安全代码将虚拟地址转换为指向缓冲区的指针
 lazy_static! {
     // 这个是最底层的显存
     pub static ref GD: Mutex<PhysicalWriter> = {
-        Mutex::new(PhysicalWriter(unsafe {&mut *(Page::<Size4KiB>::containing_address(VirtAddr::new(0xC000_0000)).start_address().as_mut_ptr() as *mut Buffer) }))
+        let base = FRAMEBUFFER.get().expect("Framebuffer is not mapped yet");
+        let mut writer = PhysicalWriter {
+            base: base.as_mut_ptr(), width: 0, height: 0, pitch: 0, bytes_per_pixel: 0, pages: 1, front: 0, stale: None,
+            video: None,
+        };
+        writer.set_geometry(mode());
+        Mutex::new(writer)
     };
 
-    // 多层叠加显示
-    pub static ref GL: RwLock<Vec<Mutex<Writer>>> = {
-        let mut v:Vec<Mutex<Writer>> = vec![];
-        v.reserve(5);
-        for _ in 0..5{
-            v.push(Mutex::new(Writer::new()));
-        }
-        RwLock::new(v)
-    };
+    // 多层叠加显示，从下往上排列，通过 layer 模块的句柄访问
+    pub static ref GL: RwLock<Vec<Layer>> = RwLock::new(layer::builtin());
 }
 
-// 定义进入宽屏模式的方法，通过调用外部模块vbe的方法来实现具体操作
+// 进入图形模式，显存和模式由 backend 选出的后端提供；没有可用的后端时留在 VGA 文本模式
 pub fn enter_wide_mode(
     mapper: &mut OffsetPageTable,
-    frame_allocator: &mut impl FrameAllocator<Size4KiB>) {
-    unsafe { vbe::bga_enter_wide(mapper, frame_allocator); 
-    }
+    frame_allocator: &mut BootInfoFrameAllocator) -> Result<(), ModeError> {
+    let (base, mode) = backend::enter(mapper, frame_allocator)?;
+    store_mode(mode);
+    FRAMEBUFFER.call_once(|| base);
     VIDEO_MODE.lock().set_graphic();
+    // 启动参数 video= 指定了别的分辨率时再切换过去，不支持时留在默认模式
+    if let Some(video) = config::video() {
+        let bpp = video.bpp.unwrap_or(DEFAULT_BPP);
+        if let Err(error) = set_mode(video.width, video.height, bpp) {
+            log::warn!("video={}x{}x{} is not usable: {:?}", video.width, video.height, bpp, error);
+        }
+    }
+    Ok(())
+}
+
+pub static UNIT: Unit = Unit {
+    name: "video",
+    depends: &[],
+    init: |context| {
+        log::info!("The OS is leaving VGA now...");
+        let (mapper, frames) = context.paging()?;
+        // 没有显示后端时留在文本模式，不影响依赖它的单元
+        if let Err(error) = enter_wide_mode(mapper, frames) {
+            log::warn!("No usable display ({:?}), staying in VGA text mode", error);
+        }
+        Ok(())
+    },
+};
+
+/// 切换分辨率和色深
+///
+/// 占满屏幕的图层按新的大小重新分配，所有图层的内容被清空，需要调用者重绘（见 gui::set_mode）
+pub fn set_mode(width: usize, height: usize, bpp: usize) -> Result<(), ModeError> {
+    let Some(backend) = backend::active() else {
+        return Err(ModeError::NotGraphic);
+    };
+    interrupts::without_interrupts(|| {
+        let mut gd = GD.lock();
+        let mode = unsafe { backend.set_mode(width, height, bpp)? };
+        gd.set_geometry(mode);
+        // 视频的显示区域是按原来的分辨率算的
+        gd.video = None;
+        let screen = Region::new(0, 0, self::height(), self::width());
+        store_mode(mode);
+        // 占满屏幕的图层跟着分辨率变，窗口的图层只清空，由窗口管理器重新摆放
+        for layer in GL.read().iter() {
+            let mut layer = layer.lock();
+            if layer.area() == screen {
+                layer.resize(width, height);
+            } else {
+                layer.clear();
+            }
+        }
+        text::resize();
+        Ok::<(), ModeError>(())
+    })?;
+    // 日志可能要上屏，不能在持有 GD 时输出
+    log::info!("Display mode set to {}x{}x{}", width, height, bpp);
+    Ok(())
 }
 
 // 实现显示器结构体的方法：
@@ -85,31 +285,151 @@ pub fn enter_wide_mode(
 // - display_pixel_rgb888：根据RGB888颜色值写像素，同样不做边界检查，并且通过BUFFER全局变量获取实际显示缓冲区
 
 impl PhysicalWriter {
+    fn set_geometry(&mut self, mode: Mode) {
+        self.width = mode.width;
+        self.height = mode.height;
+        self.bytes_per_pixel = mode.bpp / 8;
+        self.pitch = mode.pitch;
+        // 设置模式后显示的是第 0 页，两页的内容都不可信
+        self.pages = backend::active().map_or(1, |backend| backend.pages());
+        self.front = 0;
+        self.stale = Some(Region { sx: 0, sy: 0, ex: height, ey: width });
+    }
+
+    fn page(&self, index: usize) -> *mut u8 {
+        unsafe { self.base.add(index * self.height * self.pitch) }
+    }
+
+    unsafe fn write_pixel(&self, page: *mut u8, x: usize, y: usize, color: Rgb888) {
+        kdebug_assert!(x < self.height && y < self.width, "pixel ({}, {}) outside {}x{} screen", x, y, self.height, self.width);
+        let pixel = page.add(x * self.pitch + y * self.bytes_per_pixel);
+        match self.bytes_per_pixel {
+            4 => {
+                let value = ((color.r() as u32) << 16) | ((color.g() as u32) << 8) | color.b() as u32;
+                (pixel as *mut u32).write_volatile(value);
+            }
+            1 => pixel.write_volatile(mode13h::color_index(color)),
+            _ => {
+                pixel.write_volatile(color.b());
+                pixel.add(1).write_volatile(color.g());
+                pixel.add(2).write_volatile(color.r());
+            }
+        }
+    }
+
+    // 把颜色按显存格式写到 `bytes` 开头，8 位色深时是调色板下标
+    fn encode(&self, bytes: &mut [u8], color: Rgb888) {
+        if self.bytes_per_pixel == 1 {
+            bytes[0] = mode13h::color_index(color);
+            return;
+        }
+        bytes[..3].copy_from_slice(&[color.b(), color.g(), color.r()]);
+        if self.bytes_per_pixel == 4 {
+            bytes[3] = 0;
+        }
+    }
+
+    // 把已经编码好的一行像素复制到第 page 页的 (x, y) 处，超出屏幕的部分被裁掉
+    unsafe fn copy_row(&self, page: *mut u8, x: usize, y: usize, bytes: &[u8]) {
+        if x >= self.height || y >= self.width {
+            return;
+        }
+        let len = min(bytes.len(), (self.width - y) * self.bytes_per_pixel);
+        ptr::copy_nonoverlapping(bytes.as_ptr(), page.add(x * self.pitch + y * self.bytes_per_pixel), len);
+    }
+
+    /// 把一行像素写到正在显示的页上 (x, y) 开始的位置，超出屏幕的部分被裁掉
+    ///
+    /// 先编码成显存格式再整行复制，比逐像素写快得多
+    pub fn blit_row(&mut self, x: usize, y: usize, pixels: &[Rgb888]) {
+        let mut bytes = vec![0; pixels.len() * self.bytes_per_pixel];
+        for (chunk, &color) in bytes.chunks_exact_mut(self.bytes_per_pixel).zip(pixels) {
+            self.encode(chunk, color);
+        }
+        unsafe { self.copy_row(self.page(self.front), x, y, &bytes) };
+    }
+
+    /// 把正在显示的页上第 x 行的 y0..y1 列填成同一种颜色
+    pub fn fill_row(&mut self, x: usize, y0: usize, y1: usize, color: Rgb888) {
+        let y1 = min(y1, self.width);
+        if x >= self.height || y0 >= y1 {
+            return;
+        }
+        let start = unsafe { self.page(self.front).add(x * self.pitch + y0 * self.bytes_per_pixel) };
+        let len = (y1 - y0) * self.bytes_per_pixel;
+        // 所有字节都一样（黑色、灰色的 24 位像素）时直接 memset
+        let mut pixel = [0; 4];
+        self.encode(&mut pixel, color);
+        let pixel = &pixel[..self.bytes_per_pixel];
+        if pixel.iter().all(|&byte| byte == pixel[0]) {
+            unsafe { ptr::write_bytes(start, pixel[0], len) };
+            return;
+        }
+        // 否则先在栈上铺满一块，再一块一块复制过去
+        let mut chunk = [0; FILL_CHUNK];
+        let chunk_len = FILL_CHUNK / self.bytes_per_pixel * self.bytes_per_pixel;
+        for bytes in chunk[..chunk_len].chunks_exact_mut(self.bytes_per_pixel) {
+            bytes.copy_from_slice(pixel);
+        }
+        let mut offset = 0;
+        while offset < len {
+            let n = min(chunk_len, len - offset);
+            unsafe { ptr::copy_nonoverlapping(chunk.as_ptr(), start.add(offset), n) };
+            offset += n;
+        }
+    }
+
     // 写像素
-    // color是一个按照_RGB格式给出颜色的数字
+    // color是一个按照_RGB格式给出颜色的数字，直接写到正在显示的页上
     // 因为这个函数在关键路径上，所以就不检查边界了
     pub unsafe fn display_pixel(&mut self, x: usize, y: usize, color: Rgb888) {
-        self.0.chars[x][y].write(color);
+        self.write_pixel(self.page(self.front), x, y, color);
     }
 
     pub fn display_pixel_safe(&mut self, x: usize, y: usize, color: Rgb888) {
-        if x < HEIGHT && y < WIDTH {
-            self.0.chars[x][y].write(color);
+        if x < self.height && y < self.width {
+            unsafe { self.display_pixel(x, y, color) };
+        }
+    }
+
+    /// 正在显示的页上 (x, y) 处的像素，超出屏幕时返回黑色
+    pub fn read_pixel(&self, x: usize, y: usize) -> Rgb888 {
+        if x >= self.height || y >= self.width {
+            return DEFAULT_RGB888;
+        }
+        let pixel = unsafe { self.page(self.front).add(x * self.pitch + y * self.bytes_per_pixel) };
+        if self.bytes_per_pixel == 1 {
+            return mode13h::index_color(unsafe { pixel.read_volatile() });
+        }
+        // 24 位和 32 位色深下前三个字节都是 B、G、R
+        let [b, g, r] = [0, 1, 2].map(|i| unsafe { pixel.add(i).read_volatile() });
+        Rgb888::new(r, g, b)
+    }
+
+    /// 正在显示的页上这块区域的校验和（FNV-1a），用来比较两次运行画出来的画面是否相同
+    pub fn checksum(&self, region: Region) -> u32 {
+        let mut hash = 0x811C9DC5u32;
+        for x in region.sx..min(region.ex, self.height) {
+            for y in region.sy..min(region.ey, self.width) {
+                let color = self.read_pixel(x, y);
+                for byte in [color.b(), color.g(), color.r()] {
+                    hash = (hash ^ byte as u32).wrapping_mul(0x01000193);
+                }
+            }
         }
+        hash
     }
 
     // 定义矩形绘制方法：
     //  - 根据输入参数计算结束位置；
     //  - 打印调试信息；
-    //  - 循环遍历每个点并调用display_pixel方法绘制矩形.
+    //  - 逐行调用fill_row填充矩形.
     pub fn display_rect(&mut self, x: usize, y: usize, w: usize, h: usize, color: Rgb888) {
-        let x_end = min(x + h, HEIGHT);
-        let y_end = min(y + w, WIDTH);
-        qemu_print(format!("{},{},{},{}\n", x, y, x_end, y_end).as_str());
+        let x_end = min(x + h, self.height);
+        let y_end = min(y + w, self.width);
+        log::trace!("display_rect {},{},{},{}", x, y, x_end, y_end);
         for i in x..x_end {
-            for j in y..y_end {
-                unsafe { self.display_pixel(i, j, color); };
-            }
+            self.fill_row(i, y, y_end, color);
         }
     }
 
@@ -118,40 +438,26 @@ impl PhysicalWriter {
     // - 遍历每个像素并调用display_pixel_rgb88方法绘制图像；
     // - 如果解析失败，则打印错误信息
     pub fn display_img(&mut self, x: usize, y: usize, bmp_data: &[u8]) {
-        match Bmp::<Rgb888>::from_slice(bmp_data) {
-            Ok(bmp) => {
-                for Pixel(position, color) in bmp.pixels() {
-                    unsafe { self.display_pixel(x + position.y as usize, y + position.x as usize, color); };
-                }
-            }
-            Err(error) => {
-                qemu_print(format!("{:?}\n", error).as_str());
-            }
-        }
+        image::decode_bmp(bmp_data, &mut At::new(self, x, y));
     }
-    pub fn display_font(&mut self, glyph: ScaledGlyph, x_pos: usize, y_pos: usize, size: f32, line_height: usize, fg_color: Rgb888, bg_color: Rgb888) {
-        let bbox = glyph.exact_bounding_box().unwrap_or(Rect {
-            min: point(0.0, 0.0),
-            max: point(size, size),
-        });
-
-        let x_offset = (line_height as f32 + bbox.min.y) as usize;
-        //qemu_print(format!("{:?},{:?},{:?}\n",ch,bbox,x_offset).as_str());
-        let glyph = glyph.positioned(point(0.0, 0.0));
-        glyph.draw(|y, x, v| {
+    pub fn display_font(&mut self, glyph: &Glyph, x_pos: usize, y_pos: usize, line_height: usize, fg_color: Rgb888, bg_color: Rgb888) {
+        perf::scope_timer!("display_font");
+        glyph.for_each_pixel(line_height, |x, y, v| {
             let (color, _) = alpha_mix(fg_color, v, bg_color, 1.0);
-            self.display_pixel_safe(x_offset + x_pos + x as usize, y_pos + y as usize + bbox.min.x as usize, color);
+            self.display_pixel_safe(x_pos + x, y_pos + y, color);
         })
     }
 
+    /// 用内置的等宽字体写一行字
+    ///
     /// 敬请注意：此方法不检查换行
     pub unsafe fn display_font_string(&mut self, s: &str, x_pos: usize, y_pos: usize, size: f32, line_height: usize, fg_color: Rgb888, bg_color: Rgb888) {
         let mut y_pos = y_pos;
         for ch in s.chars() {
-            if y_pos >= WIDTH { return; }
-            let (glyph, hm) = get_font(ch, size);
-            self.display_font(glyph, x_pos, y_pos, size, line_height, fg_color, bg_color);
-            y_pos += hm.advance_width as usize + 1usize;
+            if y_pos >= self.width { return; }
+            let glyph = glyph(FontId::MONOSPACE, ch, size);
+            self.display_font(&glyph, x_pos, y_pos, line_height, fg_color, bg_color);
+            y_pos += glyph.advance as usize + 1usize;
         }
     }
 }
@@ -159,10 +465,165 @@ impl PhysicalWriter {
 const DEFAULT_RGB888: Rgb888 = Rgb888::new(0, 0, 0);
 
 impl Writer {
+    /// 占满整个屏幕的图层
     pub fn new() -> Self {
+        Self::with_surface(0, 0, Surface::new(width(), height()))
+    }
+
+    /// 和 new 一样，但内存不够时返回错误而不是 panic
+    pub fn try_new() -> Result<Self, TryReserveError> {
+        Self::try_with_size(0, 0, width(), height())
+    }
+
+    /// 放在屏幕 (x, y) 处、宽 `width` 高 `height` 的图层，用于按需创建的图层（比如窗口），内存不够时返回错误
+    pub fn try_with_size(x: usize, y: usize, width: usize, height: usize) -> Result<Self, TryReserveError> {
+        Ok(Self::with_surface(x, y, Surface::try_new(width, height)?))
+    }
+
+    fn with_surface(x: usize, y: usize, data: Surface) -> Self {
         Self {
-            data: vec![vec![(DEFAULT_RGB888, false); WIDTH]; HEIGHT],
+            width: data.width(),
+            height: data.height(),
+            data,
             enable: false,
+            x,
+            y,
+            opacity: 1.0,
+            dirty: None,
+            bounds: None,
+            shadow: None,
+            submitted: None,
+            presenting: None,
+            presented: None,
+        }
+    }
+
+    /// 图层在屏幕上占的区域
+    pub fn area(&self) -> Region {
+        Region::new(self.x, self.y, self.x + self.height, self.y + self.width)
+    }
+
+    /// 把图层移到屏幕 (x, y) 处并改成宽 `width` 高 `height`，原有内容被清空
+    ///
+    /// 内存不够时返回错误，图层保持不变；缩小以后再改回原来的大小不会失败
+    pub fn place(&mut self, x: usize, y: usize, width: usize, height: usize) -> Result<(), TryReserveError> {
+        self.data.resize(width, height)?;
+        let old = self.area();
+        (self.x, self.y, self.width, self.height) = (x, y, width, height);
+        self.bounds = None;
+        self.touch(old, false);
+        self.touch(self.area(), false);
+        Ok(())
+    }
+
+    /// 清空整个图层，位置和大小不变
+    pub fn clear(&mut self) {
+        self.data.clear();
+        self.bounds = None;
+        self.touch(self.area(), false);
+    }
+
+    /// 屏幕上 (x, y) 处的像素，在图层外面时是透明的
+    pub fn pixel(&self, x: usize, y: usize) -> (Rgb888, bool) {
+        match (x.checked_sub(self.x), y.checked_sub(self.y)) {
+            (Some(x), Some(y)) => self.data.pixel(x, y),
+            _ => surface::TRANSPARENT,
+        }
+    }
+
+    // 屏幕上的区域和图层相交的部分，换算成 Surface 里的坐标
+    fn local(&self, region: Region) -> Region {
+        let area = self.area();
+        let (sx, sy) = (max(region.sx, area.sx), max(region.sy, area.sy));
+        let (ex, ey) = (min(region.ex, area.ex), min(region.ey, area.ey));
+        Region::new(sx - self.x, sy - self.y, max(ex, sx) - self.x, max(ey, sy) - self.y)
+    }
+
+    // 记录改动，`opaque` 表示这块区域画上了不透明的像素
+    fn touch(&mut self, region: Region, opaque: bool) {
+        if region.is_empty() {
+            return;
+        }
+        self.dirty = Some(self.dirty.map_or(region, |d| d.union(region)));
+        if self.submitted.is_none() {
+            self.submitted = Some(timer::uptime());
+        }
+        if opaque {
+            self.bounds = Some(self.bounds.map_or(region, |b| b.union(region)));
+        }
+    }
+
+    /// 直接修改 `data` 之后需要调用，告诉合成器这块区域变了
+    ///
+    /// 直接修改只能擦除像素或在已有内容的范围内移动，否则包围盒会不准
+    pub fn mark_dirty(&mut self, region: Region) {
+        self.touch(region, false);
+    }
+
+    pub fn opacity(&self) -> f32 {
+        self.opacity
+    }
+
+    /// 设置整个图层的不透明度，用于半透明窗口、阴影和淡入淡出
+    pub fn set_opacity(&mut self, opacity: f32) {
+        self.opacity = opacity.clamp(0.0, 1.0);
+        if let Some(bounds) = self.bounds {
+            self.touch(bounds, false);
+        }
+    }
+
+    /// 设置图层的阴影，None 表示没有阴影
+    pub fn set_shadow(&mut self, shadow: Option<Shadow>) {
+        let old = self.shadow.as_ref().map(Shadow::region);
+        for region in old.into_iter().chain(shadow.as_ref().map(Shadow::region)) {
+            self.touch(region, false);
+        }
+        self.shadow = shadow;
+    }
+
+    // 合成时需要考虑这个图层的区域：不透明的像素加上阴影
+    fn extent(&self) -> Option<Region> {
+        let shadow = self.shadow.as_ref().map(Shadow::region);
+        match (self.bounds, shadow) {
+            (Some(bounds), Some(shadow)) => Some(bounds.union(shadow)),
+            (bounds, shadow) => bounds.or(shadow),
+        }
+    }
+
+    /// 按新的分辨率重新分配成占满屏幕的图层，原有内容被清空
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.data = Surface::new(width, height);
+        (self.x, self.y, self.width, self.height) = (0, 0, width, height);
+        self.dirty = Some(Region::new(0, 0, height, width));
+        self.bounds = None;
+        self.shadow = None;
+    }
+
+    /// 取出并清空改动区域
+    pub fn take_dirty(&mut self) -> Option<Region> {
+        self.dirty.take()
+    }
+
+    /// 最近一次提交的改动在哪一帧、什么时候显示出来；还没显示过时返回 None
+    pub fn present_feedback(&self) -> Option<PresentFeedback> {
+        let latest = self.presenting.and_then(|(frame, submitted)| {
+            present::frame_time(frame).map(|presented| PresentFeedback { frame, submitted, presented })
+        });
+        latest.or(self.presented)
+    }
+
+    // 合成完 frame 之后调用：改动都已经合成进去了，等这一帧上屏
+    fn composed(&mut self, frame: u64) {
+        // 上一帧在这次合成之前就已经上屏了
+        if let Some((frame, submitted)) = self.presenting.take() {
+            if let Some(presented) = present::frame_time(frame) {
+                self.presented = Some(PresentFeedback { frame, submitted, presented });
+            }
+        }
+        if self.dirty.is_none() {
+            if let Some(submitted) = self.submitted.take() {
+                self.presenting = Some((frame, submitted));
+            }
         }
     }
 
@@ -171,192 +632,370 @@ impl Writer {
     ///
     /// 因为这个函数在关键路径上，所以就不检查边界了
     pub unsafe fn display_pixel(&mut self, x: usize, y: usize, color: Rgb888) {
-        self.data[x][y] = (color, true);
+        kdebug_assert!(self.area().contains(&Region::new(x, y, x + 1, y + 1)), "pixel ({}, {}) outside layer {:?}", x, y, self.area());
+        self.data[x - self.x][y - self.y] = (color, true);
+        self.touch(Region::new(x, y, x + 1, y + 1), true);
     }
 
     pub fn display_pixel_safe(&mut self, x: usize, y: usize, color: Rgb888) {
-        if x < HEIGHT && y < WIDTH {
-            self.data[x][y] = (color, true);
+        if self.area().contains(&Region::new(x, y, x + 1, y + 1)) {
+            self.data[x - self.x][y - self.y] = (color, true);
+            self.touch(Region::new(x, y, x + 1, y + 1), true);
         }
     }
 
     pub fn display_rect(&mut self, x: usize, y: usize, w: usize, h: usize, color: Rgb888) {
-        let x_end = min(x + h, HEIGHT);
-        let y_end = min(y + w, WIDTH);
-        for i in x..x_end {
-            for j in y..y_end {
-                self.data[i][j] = (color, true);
-            }
+        let local = self.local(Region::new(x, y, x + h, y + w));
+        for i in local.sx..local.ex {
+            self.data[i][local.sy..local.ey].fill((color, true));
         }
+        self.touch(Region::new(local.sx + self.x, local.sy + self.y, local.ex + self.x, local.ey + self.y), true);
     }
 
-    pub fn display_img(&mut self, x: usize, y: usize, bmp_data: &[u8]) {
-        match Bmp::<Rgb888>::from_slice(bmp_data) {
-            Ok(bmp) => {
-                for Pixel(position, color) in bmp.pixels() {
-                    self.data[x + position.y as usize][y + position.x as usize] = (color, true);                }
-            }
-            Err(error) => {
-                qemu_print(format!("{:?}\n", error).as_str());
-            }
+    /// 把一块区域恢复成透明
+    pub fn clear_rect(&mut self, x: usize, y: usize, w: usize, h: usize) {
+        let local = self.local(Region::new(x, y, x + h, y + w));
+        for i in local.sx..local.ex {
+            self.data[i][local.sy..local.ey].fill(surface::TRANSPARENT);
         }
+        let region = Region::new(local.sx + self.x, local.sy + self.y, local.ex + self.x, local.ey + self.y);
+        self.touch(region, false);
+        if self.bounds.map_or(false, |b| region.contains(&b)) {
+            self.bounds = None;
+        }
+    }
+
+    // 图片都直接解码到图层上，超出图层的部分被裁掉；每次调用都重新解码，要反复画的图片用 display_asset
+    pub fn display_img(&mut self, x: usize, y: usize, bmp_data: &[u8]) {
+        image::decode_bmp(bmp_data, &mut At::new(self, x, y));
     }
 
     pub fn display_img_32rgba(&mut self, x: usize, y: usize, bmp_data: &[u8]) {
-        match RawBmp::from_slice(bmp_data) {
-            Ok(bmp) => {
-                let cm = match bmp.header().channel_masks {
-                    None => {
-                        ChannelMasks {
-                            blue: 0x000000FF,
-                            green: 0x0000FF00,
-                            red: 0x00FF0000,
-                            alpha: 0xFF000000,
-                        }
-                    },
-                    Some(cm) => cm
-                };
-                let (mut rr, mut br, mut gr, mut ar) = (0, 0, 0, 0);
-                let mut rm = cm.red;
-                while rm & 1 == 0 {
-                    rr += 1;
-                    rm >>= 1;
-                }
-                let mut gm = cm.green;
-                while gm & 1 == 0 {
-                    gr += 1;
-                    gm >>= 1;
-                }
-                let mut bm = cm.blue;
-                while bm & 1 == 0 {
-                    br += 1;
-                    bm >>= 1;
-                }
-                let mut am = cm.alpha;
-                while am & 1 == 0 {
-                    ar += 1;
-                    am >>= 1;
-                }
-                let asize = (cm.alpha >> ar) as f32;
-
-                for RawPixel { position, color } in bmp.pixels() {
-                    let rgb_color = Rgb888::new(((color & cm.red) >> rr) as u8, ((color & cm.green) >> gr) as u8, ((color & cm.blue) >> br) as u8);
-                    let alpha = ((color & cm.alpha) >> ar) as f32 / asize;
-                    //qemu_print(format!("{:?},{:?}", rgb_color, alpha).as_str());
-                    if alpha > 0.5 {
-                        self.display_pixel_safe(x + position.y as usize, y + position.x as usize, rgb_color);
-                    }
-                }
-            }
-            Err(error) => {
-                qemu_print(format!("{:?}\n", error).as_str());
-            }
-        }
+        image::decode_bmp_32rgba(bmp_data, &mut At::new(self, x, y));
     }
 
-    pub fn display_font(&mut self, glyph: ScaledGlyph, x_pos: usize, y_pos: usize, size: f32, line_height: usize, color: Rgb888) {
-        let bbox = glyph.exact_bounding_box().unwrap_or(Rect {
-            min: point(0.0, 0.0),
-            max: point(size, size),
-        });
+    /// 把名为 `name` 的图片资源画在 (x, y)，解码好的图片留在资源缓存里；没有这个资源时返回 false
+    pub fn display_asset(&mut self, x: usize, y: usize, name: &str) -> bool {
+        let Some(sprite) = assets::sprite(name) else { return false };
+        sprite.draw(self, x, y);
+        true
+    }
 
-        let x_offset = (line_height as f32 + bbox.min.y) as usize;
-        //qemu_print(format!("{:?},{:?},{:?}\n",ch,bbox,x_offset).as_str());
+    /// 显示 QOI 图片，透明的像素不画
+    pub fn display_qoi(&mut self, x: usize, y: usize, qoi_data: &[u8]) {
+        image::decode_qoi(qoi_data, &mut At::new(self, x, y));
+    }
 
-        let glyph = glyph.positioned(point(0.0, 0.0));
-        glyph.draw(|y, x, v| {
-            if v > 0.5 {
-                self.display_pixel_safe(x_offset + x_pos + x as usize, y_pos + y as usize + bbox.min.x as usize, color);
+    /// 画一个字形，整个字形只记录一次改动
+    pub fn display_font(&mut self, glyph: &Glyph, x_pos: usize, y_pos: usize, line_height: usize, color: Rgb888) {
+        perf::scope_timer!("display_font");
+        let area = self.area();
+        let mut drawn: Option<Region> = None;
+        let data = &mut self.data;
+        glyph.for_each_pixel(line_height, |x, y, v| {
+            let (x, y) = (x_pos + x, y_pos + y);
+            if v > 0.5 && x >= area.sx && x < area.ex && y >= area.sy && y < area.ey {
+                data[x - area.sx][y - area.sy] = (color, true);
+                let pixel = Region::new(x, y, x + 1, y + 1);
+                drawn = Some(drawn.map_or(pixel, |d| d.union(pixel)));
             }
         });
+        if let Some(region) = drawn {
+            self.touch(region, true);
+        }
     }
 
+    /// 用内置的等宽字体写一行字
+    ///
     /// 敬请注意：此方法不检查换行
     pub unsafe fn display_font_string(&mut self, s: &str, x_pos: usize, y_pos: usize, size: f32, line_height: usize, color: Rgb888) {
         let mut y_pos = y_pos;
         for ch in s.chars() {
-            if y_pos >= WIDTH { return; }
-            let (glyph, hm) = get_font(ch, size);
-            self.display_font(glyph, x_pos, y_pos, size, line_height, color);
-            y_pos += hm.advance_width as usize + 1usize;
+            if y_pos >= self.y + self.width { return; }
+            let glyph = glyph(FontId::MONOSPACE, ch, size);
+            self.display_font(&glyph, x_pos, y_pos, line_height, color);
+            y_pos += glyph.advance as usize + 1usize;
         }
     }
 
-    ///将图像整体移动
+    /// 把屏幕上第 sx..ex 行整体上移 `lines` 行，空出来的行变成透明
+    ///
+    /// 整块移动内存，不逐像素复制，用于控制台滚屏
+    pub fn scroll_up(&mut self, sx: usize, ex: usize, lines: usize) {
+        let local = self.local(Region::new(sx, self.y, ex, self.y + self.width));
+        if local.is_empty() {
+            return;
+        }
+        self.data.scroll_up(local.sx, local.ex, lines);
+        // 内容可能移到包围盒外面，和包围盒相交时把整块算进去
+        let region = Region::new(local.sx + self.x, self.y, local.ex + self.x, self.y + self.width);
+        let opaque = self.bounds.map_or(false, |b| b.intersects(&region));
+        self.touch(region, opaque);
+    }
+
+    ///将图像在图层内整体移动
     pub fn move_to(&mut self, dx: i32, dy: i32) {
         let x_iter: Box<dyn Iterator<Item=usize>> = if dx > 0 {
-            Box::new(0..HEIGHT)
+            Box::new(0..self.height)
         } else {
-            Box::new((0..HEIGHT).rev())
+            Box::new((0..self.height).rev())
         };
         for i in x_iter {
             let y_iter: Box<dyn Iterator<Item=usize>> = if dy > 0 {
-                Box::new(0..WIDTH)
+                Box::new(0..self.width)
             } else {
-                Box::new((0..WIDTH).rev())
+                Box::new((0..self.width).rev())
             };
             for j in y_iter {
-                if ((i as i32 - dx) as usize) < HEIGHT && ((j as i32 - dy) as usize) < WIDTH {
+                if ((i as i32 - dx) as usize) < self.height && ((j as i32 - dy) as usize) < self.width {
                     self.data[i][j] = self.data[(i as i32 - dx) as usize][(j as i32 - dy) as usize];
                 } else {
-                    self.data[i][j] = (DEFAULT_RGB888, false);
+                    self.data[i][j] = surface::TRANSPARENT;
                 }
             }
         }
+        let shift = |v: usize, d: i32| (v as i32 + d).max(0) as usize;
+        self.dirty = Some(self.area());
+        self.bounds = self.bounds
+            .map(|b| Region::new(shift(b.sx, dx), shift(b.sy, dy), shift(b.ex, dx), shift(b.ey, dy)))
+            .filter(|b| !b.is_empty());
+    }
+}
+
+// 画布的大小到图层的右下角为止，图层左上方的部分被裁掉
+impl Canvas for Writer {
+    fn size(&self) -> (usize, usize) {
+        (self.x + self.height, self.y + self.width)
+    }
+
+    fn put_pixel(&mut self, x: usize, y: usize, color: Rgb888) {
+        self.display_pixel_safe(x, y, color);
+    }
+
+    // 只读回这个图层自己的像素，要和下面的图层混合用 layer::with_layer_blended
+    fn get_pixel(&self, x: usize, y: usize) -> Option<Rgb888> {
+        let (color, opaque) = self.pixel(x, y);
+        opaque.then_some(color)
+    }
+
+    fn fill_span(&mut self, x: usize, y0: usize, y1: usize, color: Rgb888) {
+        self.display_rect(x, y0, y1.saturating_sub(y0), 1, color);
+    }
+}
+
+impl Canvas for PhysicalWriter {
+    fn size(&self) -> (usize, usize) {
+        (self.height, self.width)
+    }
+
+    fn fill_span(&mut self, x: usize, y0: usize, y1: usize, color: Rgb888) {
+        self.fill_row(x, y0, y1, color);
+    }
+
+    fn put_pixel(&mut self, x: usize, y: usize, color: Rgb888) {
+        self.display_pixel_safe(x, y, color);
+    }
+
+    fn get_pixel(&self, x: usize, y: usize) -> Option<Rgb888> {
+        (x < self.height && y < self.width).then(|| self.read_pixel(x, y))
+    }
+}
+
+// 实现 embedded-graphics 的 DrawTarget，它的各种图形、文字样式和图片格式都可以直接画到图层和显存上
+impl OriginDimensions for Writer {
+    fn size(&self) -> Size {
+        Size::new((self.y + self.width) as u32, (self.x + self.height) as u32)
+    }
+}
+
+impl DrawTarget for Writer {
+    type Color = Rgb888;
+    type Error = Infallible;
+
+    fn draw_iter<I: IntoIterator<Item = Pixel<Rgb888>>>(&mut self, pixels: I) -> Result<(), Infallible> {
+        draw_pixels(self, pixels);
+        Ok(())
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Rgb888) -> Result<(), Infallible> {
+        fill_area(self, area, color);
+        Ok(())
+    }
+}
+
+impl OriginDimensions for PhysicalWriter {
+    fn size(&self) -> Size {
+        Size::new(self.width as u32, self.height as u32)
+    }
+}
+
+impl DrawTarget for PhysicalWriter {
+    type Color = Rgb888;
+    type Error = Infallible;
+
+    fn draw_iter<I: IntoIterator<Item = Pixel<Rgb888>>>(&mut self, pixels: I) -> Result<(), Infallible> {
+        draw_pixels(self, pixels);
+        Ok(())
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Rgb888) -> Result<(), Infallible> {
+        fill_area(self, area, color);
+        Ok(())
     }
 }
 
 impl PhysicalWriter {
+    /// 重新合成一块区域（行 sx..ex，列 sy..ey）并写到显存
+    ///
+    /// 逐像素从上往下找第一个完全不透明的像素，没有就用背景层，再把它上面半透明图层的像素依次混合上去；
+    /// 和区域不相交的图层、完全透明的图层直接跳过，不复制图层数据。
+    /// 背景层总是参与合成，其他图层需要 enable（见 layer::set_visible），鼠标图层一直显示。
+    /// 双缓冲时画到后台页后翻页
     pub fn render(&mut self, sx: usize, sy: usize, ex: usize, ey: usize) {
-        //qemu_print(format!("Start Render... Now is {:?}\n", TIME.lock()).as_str());
-        if sx < HEIGHT && sy < WIDTH && ex <= HEIGHT && ey <= WIDTH {
-            if GL.read().len() == 0 { return; }
-            let p_lock = GL.read();
-            let lock = p_lock[p_lock.len() - 1].lock();
-            let mut graph: Box<Vec<Vec<(Rgb888, bool)>>> = Box::new(lock.data.clone());
-            drop(lock);
-            for layer in (1..p_lock.len() - 1).rev() {
-                let lock = p_lock[layer].lock();
-                if !lock.enable { continue }
-                let tomix = &lock.data;
-                for x in sx..ex {
-                    for y in sy..ey {
-                        if !graph[x][y].1 && tomix[x][y].1 {
-                            graph[x][y] = tomix[x][y]
-                        }
-                    }
-                }
+        let region = Region::new(sx, sy, min(ex, self.height), min(ey, self.width));
+        if region.is_empty() {
+            return;
+        }
+        let _span = crate::trace::span("render");
+        perf::scope_timer!("render");
+        crate::trace::counter("render pixels", ((region.ex - region.sx) * (region.ey - region.sy)) as i64);
+        let frame = present::begin_frame();
+        if self.pages < 2 {
+            self.compose(region, self.front, frame);
+            present::end_frame(frame);
+            return;
+        }
+        // 后台页要补上它落后于前台页的部分，翻页之后新的后台页只落后这一次的改动
+        let back = 1 - self.front;
+        let target = self.stale.take().map_or(region, |stale| stale.union(region));
+        self.compose(target, back, frame);
+        if let Some(backend) = backend::active() {
+            unsafe { backend.set_y_offset(back * self.height) };
+        }
+        self.front = back;
+        self.stale = Some(region);
+        present::end_frame(frame);
+    }
+
+    // 把图层合成到显存的第 page 页，作为第 frame 帧
+    fn compose(&self, region: Region, page: usize, frame: u64) {
+        let page = self.page(page);
+        let p_lock = GL.read();
+        if p_lock.len() == 0 { return; }
+        let mut layers: Vec<_> = p_lock.iter().map(|layer| layer.lock()).collect();
+        // 这次合成覆盖了的改动不用再合成一遍
+        for layer in layers.iter_mut() {
+            if layer.dirty.map_or(false, |d| region.contains(&d)) {
+                layer.dirty = None;
             }
-            let tomix = &p_lock[0].lock().data;
-            for x in sx..ex {
-                for y in sy..ey {
-                    graph[x][y].0 = if graph[x][y].1 { graph[x][y].0 } else { tomix[x][y].0 };
+            layer.composed(frame);
+        }
+        let (background, layers) = layers.split_first().unwrap();
+        // 视频区域里只有鼠标画在视频上面
+        let video = self.video.as_ref().filter(|video| video.dest().intersects(&region));
+        let cursor = layers.last().map(|layer| &**layer);
+        let mixed: Vec<_> = layers.iter().rev()
+            .filter(|layer| layer.enable && layer.opacity > 0.0
+                && layer.extent().map_or(false, |b| b.intersects(&region)))
+            .map(|layer| (&**layer, layer.opacity, layer.shadow.as_ref()))
+            .collect();
+        // 一行合成到 line 里，再整行复制到显存
+        let bpp = self.bytes_per_pixel;
+        let mut line = vec![0; (region.ey - region.sy) * bpp];
+        for x in region.sx..region.ex {
+            for y in region.sy..region.ey {
+                let pixel = &mut line[(y - region.sy) * bpp..(y - region.sy + 1) * bpp];
+                if let Some(video) = video.filter(|video| video.dest().contains(&Region { sx: x, sy: y, ex: x + 1, ey: y + 1 })) {
+                    let color = match cursor.map(|cursor| cursor.pixel(x, y)) {
+                        Some((color, true)) => color,
+                        _ => video.sample(x, y),
+                    };
+                    self.encode(pixel, color);
+                    continue;
                 }
-            }
-            for x in sx..ex {
-                for y in sy..ey {
-                    self.0.chars[x][y].write(graph[x][y].0);
+                let cover = mixed.iter()
+                    .position(|(layer, opacity, _)| layer.pixel(x, y).1 && *opacity >= 1.0)
+                    .unwrap_or(mixed.len());
+                let mut color = match mixed.get(cover) {
+                    Some((layer, _, _)) => layer.pixel(x, y).0,
+                    None => background.pixel(x, y).0,
+                };
+                for (layer, opacity, shadow) in mixed[..cover].iter().rev() {
+                    let (fg, opaque) = layer.pixel(x, y);
+                    if opaque {
+                        color = alpha_mix_final(fg, *opacity, color);
+                    } else if let Some(alpha) = shadow.map(|shadow| shadow.alpha(x, y)).filter(|&alpha| alpha > 0) {
+                        color = alpha_mix_final(SHADOW_COLOR, alpha as f32 / 255.0 * *opacity, color);
+                    }
                 }
+                self.encode(pixel, color);
+            }
+            unsafe { self.copy_row(page, x, region.sy, &line) };
+        }
+    }
+
+    /// 开始显示视频，之后 `surface` 覆盖的区域直接显示视频画面
+    pub fn start_video(&mut self, mut surface: VideoSurface) {
+        self.stop_video();
+        let dest = surface.dest();
+        surface.mark_dirty(Region { sx: 0, sy: 0, ex: surface.height(), ey: surface.width() });
+        self.video = Some(surface);
+        self.render(dest.sx, dest.sy, dest.ex, dest.ey);
+    }
+
+    /// 停止显示视频，恢复原来的内容
+    pub fn stop_video(&mut self) -> Option<VideoSurface> {
+        let surface = self.video.take()?;
+        let dest = surface.dest();
+        self.render(dest.sx, dest.sy, dest.ex, dest.ey);
+        Some(surface)
+    }
+
+    pub fn video_mut(&mut self) -> Option<&mut VideoSurface> {
+        self.video.as_mut()
+    }
+
+    /// 把视频这一帧改动过的部分显示出来
+    pub fn present_video(&mut self) {
+        if let Some(region) = self.video.as_mut().and_then(|video| video.take_dirty()) {
+            self.render(region.sx, region.sy, region.ex, region.ey);
+        }
+    }
+
+    /// 合成所有图层自上次合成以来改动过的区域，再加上 `extra`，合并成一块合成一次
+    ///
+    /// 什么都没变时不合成，返回 false
+    pub fn render_dirty(&mut self, extra: Option<Region>) -> bool {
+        let dirty = GL.read().iter()
+            .filter_map(|layer| layer.lock().take_dirty())
+            .chain(extra)
+            .reduce(Region::union);
+        match dirty {
+            Some(region) if !region.is_empty() => {
+                self.render(region.sx, region.sy, region.ex, region.ey);
+                true
             }
+            _ => false,
         }
-        //qemu_print(format!("Finish Render... Now is {:?}\n", TIME.lock()).as_str());
     }
 }
 
 pub fn test_img() {
-    GD.lock().display_rect(0, 0, 800, 600, rgb888!(0xFFFFFFu32));
-
-    unimplemented!("请为我解除封印");
-    // let lpld = include_bytes!("../assets/91527085_p0.bmp");
-    // let cinea_os = include_bytes!("../assets/cinea-os.bmp");
-    // GD.lock().display_img(0, 0, lpld);
-    // GD.lock().display_img(400, 300, cinea_os);
+    let mut gd = GD.lock();
+    gd.display_rect(0, 0, 800, 600, rgb888!(0xFFFFFFu32));
+    // 图片从资源里取，theme/ 下的同名资源可以换掉它们
+    gd.display_asset(0, 0, "wallpapers/default.bmp");
+    gd.display_asset(400, 300, "icons/cursor.bmp");
 }
 
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
     use core::fmt::Write;
 
+    // 终端窗口打开后输出到窗口里
+    if crate::gui::terminal::_print(args) {
+        return;
+    }
     // 防止死锁
     interrupts::without_interrupts(|| {
         TEXT_WRITER.lock().write_fmt(args).unwrap();
@@ -368,10 +1007,10 @@ pub fn _print(args: fmt::Arguments) {
 // 1. **基本设置**：包括导入必要库和模块，定义屏幕尺寸。
 // 2. **核心数据结构**：
 //    - Pixel：表示单个像素。
-//    - Buffer：表示整个屏幕，由二维数组组成，每个元素是带有易变特性的Pixel。
-//    - Writer：封装了Buffer，实现了一些操作方法。
+//    - PhysicalWriter：直接写显存，按当前分辨率和色深计算像素位置。
+//    - Writer：内存中的图层，由 PhysicalWriter 合成到屏幕上。
 // 3. **全局变量**：使用LazyStatic创建全局静态缓冲区对象，并确保线程安全。
-// 4. **模式切换**：提供进入宽屏模式的方法，通过外部模块实现具体操作.
+// 4. **模式切换**：提供进入宽屏模式和运行时切换分辨率的方法，通过外部模块实现具体操作.
 // 5. **显示逻辑**：
 //    - 提供直接根据RGB或RGB88颜色写单个像素的方法.
 //    - 提供绘制矩形和展示图片的方法，通过遍历指定区域或图象数据逐点调用上述单点绘画接口来实现.
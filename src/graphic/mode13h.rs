@@ -0,0 +1,142 @@
+// VGA 320x200x256 图形模式（mode 13h）
+// 既没有 BGA 也没有引导程序给的帧缓冲时的最后一个显示后端。没有 BIOS 可以调用，直接按 mode 13h 的寄存器表设置 VGA，
+// 显存是 0xA0000 开始的 64000 字节，chain-4 下每个字节就是一个像素的调色板下标。
+// 调色板设成 RGB332：红绿各 3 位、蓝 2 位，写显存时颜色换成最接近的下标（color_index），读回时再换回来（index_color）。
+// 只有一页、不能切换分辨率；GUI 按屏幕大小摆放，分辨率小时窗口跟着变小
+
+use embedded_graphics::pixelcolor::Rgb888;
+use embedded_graphics::prelude::RgbColor;
+use x86_64::structures::paging::OffsetPageTable;
+use x86_64::{PhysAddr, VirtAddr};
+
+use crate::graphic::backend::{Backend, Mode};
+use crate::graphic::vbe::ModeError;
+use crate::io::pci::{pci_find, DeviceMatch};
+use crate::io::port::{self, Port};
+use crate::memory::vmm::map_mmio;
+use crate::memory::BootInfoFrameAllocator;
+
+pub const WIDTH: usize = 320;
+pub const HEIGHT: usize = 200;
+const FRAMEBUFFER: u64 = 0xA0000;
+
+// VGA 寄存器占用的端口 0x3C0-0x3DF
+const PORTS_START: u16 = 0x3C0;
+const PORTS_LEN: u16 = 0x20;
+const ATTRIBUTE: Port<u8> = Port::new(0x3C0);
+const MISC_OUTPUT: Port<u8> = Port::new(0x3C2);
+const SEQUENCER_INDEX: Port<u8> = Port::new(0x3C4);
+const SEQUENCER_DATA: Port<u8> = Port::new(0x3C5);
+const DAC_WRITE_INDEX: Port<u8> = Port::new(0x3C8);
+const DAC_DATA: Port<u8> = Port::new(0x3C9);
+const GRAPHICS_INDEX: Port<u8> = Port::new(0x3CE);
+const GRAPHICS_DATA: Port<u8> = Port::new(0x3CF);
+const CRTC_INDEX: Port<u8> = Port::new(0x3D4);
+const CRTC_DATA: Port<u8> = Port::new(0x3D5);
+// 读它复位属性控制器的下标/数据切换
+const INPUT_STATUS: Port<u8> = Port::new(0x3DA);
+
+// mode 13h 的寄存器值
+const MISC: u8 = 0x63;
+const SEQUENCER: [u8; 5] = [0x03, 0x01, 0x0F, 0x00, 0x0E];
+const CRTC: [u8; 25] = [
+    0x5F, 0x4F, 0x50, 0x82, 0x54, 0x80, 0xBF, 0x1F, 0x00, 0x41, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x9C, 0x0E, 0x8F, 0x28, 0x40, 0x96, 0xB9, 0xA3, 0xFF,
+];
+const GRAPHICS: [u8; 9] = [0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x05, 0x0F, 0xFF];
+const ATTRIBUTES: [u8; 21] = [
+    0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E, 0x0F,
+    0x41, 0x00, 0x0F, 0x00, 0x00,
+];
+
+/// 颜色在 RGB332 调色板里的下标
+pub fn color_index(color: Rgb888) -> u8 {
+    (color.r() & 0xE0) | ((color.g() >> 3) & 0x1C) | (color.b() >> 6)
+}
+
+/// 调色板里下标 `index` 的颜色
+pub fn index_color(index: u8) -> Rgb888 {
+    let scale = |value: u8, max: u8| (value as u16 * 255 / max as u16) as u8;
+    Rgb888::new(scale(index >> 5, 7), scale((index >> 2) & 7, 7), scale(index & 3, 3))
+}
+
+/// 作为显示后端的 VGA
+pub struct Mode13h;
+
+impl Backend for Mode13h {
+    fn name(&self) -> &'static str {
+        "vga mode 13h"
+    }
+
+    // 要有 VGA 兼容的显卡
+    fn probe(&self) -> bool {
+        pci_find(&[DeviceMatch::Class { class: 0x03, subclass: 0x00, prog_if: Some(0x00) }]).is_some()
+    }
+
+    unsafe fn enter(&self, mapper: &mut OffsetPageTable, frames: &mut BootInfoFrameAllocator) -> Result<(VirtAddr, Mode), ModeError> {
+        port::claim_or_log("vga", PORTS_START, PORTS_LEN);
+        let region = map_mmio(mapper, frames, PhysAddr::new(FRAMEBUFFER), (WIDTH * HEIGHT) as u64)
+            .map_err(|_| ModeError::OutOfVideoMemory)?;
+        set_registers();
+        set_palette();
+        Ok((region.start(), Mode::packed(WIDTH, HEIGHT, 8)))
+    }
+
+    unsafe fn set_mode(&self, width: usize, height: usize, bpp: usize) -> Result<Mode, ModeError> {
+        if (width, height, bpp) != (WIDTH, HEIGHT, 8) {
+            return Err(ModeError::Unsupported);
+        }
+        Ok(Mode::packed(WIDTH, HEIGHT, 8))
+    }
+
+    fn pages(&self) -> usize {
+        1
+    }
+
+    unsafe fn set_y_offset(&self, _y: usize) {}
+}
+
+unsafe fn set_registers() {
+    MISC_OUTPUT.write(MISC);
+    for (index, &value) in SEQUENCER.iter().enumerate() {
+        SEQUENCER_INDEX.write(index as u8);
+        SEQUENCER_DATA.write(value);
+    }
+    // 先解除 CRTC 0-7 号寄存器的写保护
+    CRTC_INDEX.write(0x03);
+    CRTC_DATA.write(CRTC_DATA.read() | 0x80);
+    CRTC_INDEX.write(0x11);
+    CRTC_DATA.write(CRTC_DATA.read() & !0x80);
+    for (index, &value) in CRTC.iter().enumerate() {
+        let value = match index {
+            0x03 => value | 0x80,
+            0x11 => value & !0x80,
+            _ => value,
+        };
+        CRTC_INDEX.write(index as u8);
+        CRTC_DATA.write(value);
+    }
+    for (index, &value) in GRAPHICS.iter().enumerate() {
+        GRAPHICS_INDEX.write(index as u8);
+        GRAPHICS_DATA.write(value);
+    }
+    for (index, &value) in ATTRIBUTES.iter().enumerate() {
+        INPUT_STATUS.read();
+        ATTRIBUTE.write(index as u8);
+        ATTRIBUTE.write(value);
+    }
+    // 设置完属性控制器后重新打开显示
+    INPUT_STATUS.read();
+    ATTRIBUTE.write(0x20);
+}
+
+// DAC 每个分量 6 位
+unsafe fn set_palette() {
+    DAC_WRITE_INDEX.write(0);
+    for index in 0..=255u8 {
+        let color = index_color(index);
+        for component in [color.r(), color.g(), color.b()] {
+            DAC_DATA.write(component >> 2);
+        }
+    }
+}
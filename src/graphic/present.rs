@@ -0,0 +1,57 @@
+// 帧编号和上屏时间
+// 每次把合成结果写到显存（双缓冲时是翻页之后）算一帧，帧编号从 1 开始单调递增。
+// 图层记下改动第一次提交的时间，和这些改动被合成进去的那一帧；
+// 动画可以按真正上屏的时间推算下一帧，基准测试可以量出从画出来到显示出来的完整延迟
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+use crate::io::timer;
+use crate::sync::IrqSafeMutex;
+
+// 记住最近多少帧的上屏时间，按帧编号取模存放
+const HISTORY: usize = 64;
+
+static NEXT_FRAME: AtomicU64 = AtomicU64::new(1);
+static PRESENTED: IrqSafeMutex<[(u64, Duration); HISTORY]> =
+    IrqSafeMutex::named("present history", [(0, Duration::ZERO); HISTORY]);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PresentFeedback {
+    /// 改动被显示出来的那一帧
+    pub frame: u64,
+    /// 改动第一次提交的时间
+    pub submitted: Duration,
+    /// 那一帧上屏的时间
+    pub presented: Duration,
+}
+
+impl PresentFeedback {
+    /// 从提交到显示的延迟
+    pub fn latency(&self) -> Duration {
+        self.presented.saturating_sub(self.submitted)
+    }
+}
+
+// 合成开始前分配帧编号
+pub(super) fn begin_frame() -> u64 {
+    NEXT_FRAME.fetch_add(1, Ordering::Relaxed)
+}
+
+// 合成结果已经显示出来
+pub(super) fn end_frame(frame: u64) {
+    let now = timer::uptime();
+    PRESENTED.lock()[frame as usize % HISTORY] = (frame, now);
+    crate::trace::latency::presented(frame);
+}
+
+/// 最近一次开始合成的帧编号，还没有合成过时为 0
+pub fn current_frame() -> u64 {
+    NEXT_FRAME.load(Ordering::Relaxed) - 1
+}
+
+/// 某一帧上屏的时间；还没显示出来，或者太久以前已经被覆盖时返回 None
+pub fn frame_time(frame: u64) -> Option<Duration> {
+    let (recorded, time) = PRESENTED.lock()[frame as usize % HISTORY];
+    (recorded == frame && frame != 0).then_some(time)
+}
@@ -0,0 +1,186 @@
+// 截图
+// screenshot 把合成好的画面（正在显示的页）复制一份，之后的编码和输出都在副本上做，不一直拿着 GD 的锁，
+// 画面也不会在输出到一半时变掉。副本可以编码成 24 位的 BMP，或者以 base64 写到串口（COM1），
+// 夹在 "-----BEGIN BMP <名字>-----" 和 "-----END BMP-----" 之间，格式和 golden 输出的差异图一样，
+// CI 脚本用同一段代码取出图片来和参考图比较。现在还没有文件系统，写到磁盘上要等 FAT32 加进来
+
+use alloc::collections::TryReserveError;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+
+use embedded_graphics::pixelcolor::Rgb888;
+use embedded_graphics::prelude::*;
+
+use crate::graphic::{Region, GD};
+use crate::io::qemu::SerialStream;
+
+const HEADER_SIZE: usize = 54;
+
+/// 一张截图，按行存放
+pub struct Screenshot {
+    width: usize,
+    height: usize,
+    pixels: Vec<Rgb888>,
+}
+
+/// 截下整个屏幕
+pub fn screenshot() -> Result<Screenshot, TryReserveError> {
+    capture(Region::new(0, 0, super::height(), super::width()))
+}
+
+/// 截下屏幕上的一块区域，超出屏幕的部分被裁掉
+pub fn capture(region: Region) -> Result<Screenshot, TryReserveError> {
+    let region = Region::new(region.sx, region.sy, region.ex, region.ey);
+    let (height, width) = (region.ex - region.sx, region.ey - region.sy);
+    let mut pixels = Vec::new();
+    pixels.try_reserve_exact(width * height)?;
+    let gd = GD.lock();
+    for x in region.sx..region.ex {
+        pixels.extend((region.sy..region.ey).map(|y| gd.read_pixel(x, y)));
+    }
+    Ok(Screenshot { width, height, pixels })
+}
+
+impl Screenshot {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// (x, y) 处的像素，超出截图时返回 None
+    pub fn pixel(&self, x: usize, y: usize) -> Option<Rgb888> {
+        (x < self.height && y < self.width).then(|| self.pixels[x * self.width + y])
+    }
+
+    /// 编码成 BMP 文件
+    pub fn to_bmp(&self) -> Result<Vec<u8>, TryReserveError> {
+        encode_bmp(self.height, self.width, |x, y| self.pixels[x * self.width + y])
+    }
+
+    /// 以 base64 编码的 BMP 写到串口
+    pub fn write_serial(&self, name: &str) {
+        // 写得很慢，每行喂一次看门狗
+        let _ = write_bmp(&mut SerialStream, name, self.height, self.width, |x, y| {
+            if y == 0 {
+                crate::debug::watchdog::touch();
+            }
+            self.pixels[x * self.width + y]
+        });
+    }
+}
+
+// 24 位 BMP 每行按 4 字节对齐后的字节数
+fn row_size(width: usize) -> usize {
+    (width * 3).div_ceil(4) * 4
+}
+
+fn bmp_header(height: usize, width: usize) -> [u8; HEADER_SIZE] {
+    let image_size = (row_size(width) * height) as u32;
+    let mut header = [0u8; HEADER_SIZE];
+    header[0..2].copy_from_slice(b"BM");
+    header[2..6].copy_from_slice(&(HEADER_SIZE as u32 + image_size).to_le_bytes());
+    header[10..14].copy_from_slice(&(HEADER_SIZE as u32).to_le_bytes());
+    // BITMAPINFOHEADER
+    header[14..18].copy_from_slice(&40u32.to_le_bytes());
+    header[18..22].copy_from_slice(&(width as u32).to_le_bytes());
+    header[22..26].copy_from_slice(&(height as u32).to_le_bytes());
+    header[26..28].copy_from_slice(&1u16.to_le_bytes());
+    header[28..30].copy_from_slice(&24u16.to_le_bytes());
+    header[34..38].copy_from_slice(&image_size.to_le_bytes());
+    header
+}
+
+// 按 BMP 的顺序（从最下面一行开始，每个像素 B、G、R，行尾补齐）依次给出像素数据
+fn pixel_data(height: usize, width: usize, pixel: impl Fn(usize, usize) -> Rgb888,
+                mut f: impl FnMut(&[u8]) -> fmt::Result) -> fmt::Result {
+    for x in (0..height).rev() {
+        for y in 0..width {
+            let color = pixel(x, y);
+            f(&[color.b(), color.g(), color.r()])?;
+        }
+        f(&[0; 3][..row_size(width) - width * 3])?;
+    }
+    Ok(())
+}
+
+/// 把 `height` 行 `width` 列、(x, y) 处颜色为 `pixel(x, y)` 的图像编码成 24 位 BMP
+pub fn encode_bmp(height: usize, width: usize, pixel: impl Fn(usize, usize) -> Rgb888) -> Result<Vec<u8>, TryReserveError> {
+    let mut data = Vec::new();
+    data.try_reserve_exact(HEADER_SIZE + row_size(width) * height)?;
+    data.extend_from_slice(&bmp_header(height, width));
+    let _ = pixel_data(height, width, pixel, |bytes| {
+        data.extend_from_slice(bytes);
+        Ok(())
+    });
+    Ok(data)
+}
+
+/// 把图像编码成 BMP，以 base64 写到 `out`，前后加上标记
+pub(crate) fn write_bmp(out: &mut impl Write, name: &str, height: usize, width: usize,
+                        pixel: impl Fn(usize, usize) -> Rgb888) -> fmt::Result {
+    writeln!(out, "-----BEGIN BMP {}-----", name)?;
+    let mut encoder = Base64::new(out);
+    encoder.write(&bmp_header(height, width))?;
+    pixel_data(height, width, pixel, |bytes| encoder.write(bytes))?;
+    encoder.finish()?;
+    writeln!(out, "-----END BMP-----")
+}
+
+// 流式 base64 编码，每 76 个字符换行；崩溃转储也用它
+pub(crate) struct Base64<'a, W: Write> {
+    out: &'a mut W,
+    pending: [u8; 3],
+    len: usize,
+    column: usize,
+}
+
+impl<'a, W: Write> Base64<'a, W> {
+    const ALPHABET: &'static [u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    const LINE_WIDTH: usize = 76;
+
+    pub(crate) fn new(out: &'a mut W) -> Self {
+        Self { out, pending: [0; 3], len: 0, column: 0 }
+    }
+
+    pub(crate) fn write(&mut self, bytes: &[u8]) -> fmt::Result {
+        for &byte in bytes {
+            self.pending[self.len] = byte;
+            self.len += 1;
+            if self.len == 3 {
+                self.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    // 编码攒下的 1 到 3 个字节，不足 3 个时用 '=' 补齐
+    fn flush(&mut self) -> fmt::Result {
+        let [a, b, c] = self.pending;
+        let indices = [a >> 2, (a & 0x03) << 4 | b >> 4, (b & 0x0F) << 2 | c >> 6, c & 0x3F];
+        for (i, &index) in indices.iter().enumerate() {
+            let ch = if i <= self.len { Self::ALPHABET[index as usize] as char } else { '=' };
+            self.out.write_char(ch)?;
+        }
+        self.pending = [0; 3];
+        self.len = 0;
+        self.column += 4;
+        if self.column >= Self::LINE_WIDTH {
+            self.column = 0;
+            self.out.write_char('\n')?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn finish(mut self) -> fmt::Result {
+        if self.len > 0 {
+            self.flush()?;
+        }
+        if self.column > 0 {
+            self.out.write_char('\n')?;
+        }
+        Ok(())
+    }
+}
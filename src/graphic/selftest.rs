@@ -0,0 +1,126 @@
+// 图形自检
+// 在内核里检查字形的摆放：基线对齐、拉丁字母和汉字的前进宽度、画布边缘的裁剪。
+// 一行拉丁字母加汉字和一个被裁掉一部分的字形画在内存里的 Surface 上，用 golden::check_canvas 和字符画的参考图逐像素比较；
+// 参考图按内置的 VonwaonBitmap 16 像素字体画出，换了内置字体要重新画。
+// 结果写到日志，格式和 golden 一样是 "selftest: <名字> ok/FAILED"，CI 可以在串口输出里检查
+
+use embedded_graphics::pixelcolor::{Rgb888, RgbColor};
+
+use crate::graphic::canvas::Canvas;
+use crate::graphic::font::{glyph, placement, FontId};
+use crate::graphic::golden;
+use crate::graphic::image::Image;
+use crate::graphic::surface::Surface;
+use crate::graphic::text::{draw_glyph, draw_text, Align};
+
+const SIZE: f32 = 16.0;
+const LINE_HEIGHT: usize = 16;
+
+// "Hg中" 按行高 16 画在 20 行 40 列的画布左上角：H 的底边比行底高一个像素（字体本身如此），
+// g 的下伸部分和汉字的底部伸出这一行 2 个像素，汉字从第 19 列开始（H、g 各前进 8 + 1 列）
+const LINE: [&str; 20] = [
+    "........................................",
+    "........................................",
+    ".........................#..............",
+    ".........................#..............",
+    ".........................#.....#........",
+    "##...##............##############.......",
+    "##...##............#.....#.....#........",
+    "##...##............#.....#.....#........",
+    "##...##...###.##...#.....#.....#........",
+    "#######..##..##....#.....#.....#........",
+    "##...##..##..##....#############........",
+    "##...##..##..##....#.....#.....#........",
+    "##...##..##..##..........#..............",
+    "##...##..##..##..........#..............",
+    "##...##...#####..........#..............",
+    ".............##..........#..............",
+    ".........##..##..........#..............",
+    "..........####...........#..............",
+    "........................................",
+    "........................................",
+];
+
+// "W" 按行高 8 画在 8 行 5 列的画布第 2 列：顶上 3 行伸出这一行被裁掉，右边只剩前 3 列
+const CLIPPED: [&str; 8] = [
+    "..##.",
+    "..##.",
+    "..##.",
+    "..##.",
+    "..###",
+    "..###",
+    "...##",
+    ".....",
+];
+
+/// 运行字形摆放的检查，全部通过时返回 true
+pub fn font() -> bool {
+    let checks: [(&str, fn() -> bool); 5] = [
+        ("placement", check_placement),
+        ("baseline", check_baseline),
+        ("advance", check_advance),
+        ("line", check_line),
+        ("clipping", check_clipping),
+    ];
+    let mut passed = true;
+    for (name, check) in checks {
+        if check() {
+            log::info!("selftest: font {} ok", name);
+        } else {
+            log::error!("selftest: font {} FAILED", name);
+            passed = false;
+        }
+    }
+    passed
+}
+
+// 基线在行高的底部，包围盒在基线以上的部分向上伸出
+fn check_placement() -> bool {
+    placement(0, -12, 16) == (4, 0)
+        && placement(1, -16, 16) == (0, 1)
+        && placement(-1, -20, 16) == (-4, -1)
+        && placement(0, 2, 16) == (18, 0)
+}
+
+// 没有下伸部分的字母底边对齐，有下伸部分的伸到更下面，都不高出行底太多
+fn check_baseline() -> bool {
+    let bottom = |ch| {
+        let glyph = glyph(FontId::MONOSPACE, ch, SIZE);
+        glyph.offset(LINE_HEIGHT).0 + glyph.size().0 as isize
+    };
+    let line = LINE_HEIGHT as isize;
+    bottom('x') == bottom('H') && bottom('g') > bottom('H') && (line - 2..=line).contains(&bottom('H'))
+}
+
+// 等宽字体里汉字占两个拉丁字母的宽度，按取整之后的像素比较
+fn check_advance() -> bool {
+    let advance = |ch| glyph(FontId::MONOSPACE, ch, SIZE).advance.round() as usize;
+    advance('a') > 0 && advance('W') == advance('a') && advance('中') == 2 * advance('a')
+}
+
+// 一行拉丁字母和汉字，和参考图比较基线和前进宽度
+fn check_line() -> bool {
+    let mut surface = blank(LINE[0].len(), LINE.len());
+    draw_text(&mut surface, 0, 0, LINE[0].len(), LINE.len(), "Hg中", FontId::MONOSPACE, SIZE, LINE_HEIGHT, Align::Left,
+              Rgb888::WHITE);
+    golden::check_canvas("font-line", &surface, 0, 0, &reference(&LINE))
+}
+
+// 画在画布边缘、行高又小于字形时，只有落在画布里和这一行里的像素被画出来
+fn check_clipping() -> bool {
+    let mut surface = blank(CLIPPED[0].len(), CLIPPED.len());
+    let glyph = glyph(FontId::MONOSPACE, 'W', SIZE);
+    draw_glyph(&mut surface, &glyph, 0, 2, CLIPPED.len(), Rgb888::WHITE);
+    golden::check_canvas("font-clipping", &surface, 0, 0, &reference(&CLIPPED))
+}
+
+// 填满黑色的画布，没画到的像素也能和参考图比较
+fn blank(width: usize, height: usize) -> Surface {
+    let mut surface = Surface::new(width, height);
+    (0..height).for_each(|x| surface.fill_span(x, 0, width, Rgb888::BLACK));
+    surface
+}
+
+fn reference(rows: &[&str]) -> Image {
+    golden::from_ascii(rows, '#', Rgb888::WHITE, Rgb888::BLACK)
+}
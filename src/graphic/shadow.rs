@@ -0,0 +1,121 @@
+// 投影
+// 窗口的阴影不画成像素，而是作为图层的属性交给合成器：合成时在图层下面按阴影的不透明度把已经合成好的颜色压暗，
+// 阴影随图层的不透明度一起变化，也会压暗下面的其他窗口。
+// 模糊后的矩形在横竖两个方向上可分离，只需要预先算好一个角（边长 2 * radius）的不透明度，
+// 四个角镜像使用，四条边取角的最后一行或一列，中间是最大不透明度。同样半径的阴影共用一个预计算的角
+
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use embedded_graphics::pixelcolor::Rgb888;
+use spin::Mutex;
+
+use crate::graphic::Region;
+
+pub const SHADOW_COLOR: Rgb888 = Rgb888::new(0, 0, 0);
+// 模糊的次数，三次盒式模糊已经很接近高斯模糊
+const BLUR_PASSES: usize = 3;
+
+// 最近一次用到的预计算结果，窗口通常都用同一个半径
+static CACHE: Mutex<Option<Arc<Corner>>> = Mutex::new(None);
+
+// 模糊后矩形左上角的不透明度（0 到 255），size = 2 * radius，矩形的边缘在第 radius 行、列
+#[derive(Debug)]
+struct Corner {
+    radius: usize,
+    size: usize,
+    alpha: Vec<u8>,
+}
+
+impl Corner {
+    fn new(radius: usize) -> Self {
+        let size = 2 * radius;
+        // 一维的阶跃：边缘之前是 0，之后是 255，两边各留 radius 防止模糊时越界
+        let mut profile: Vec<u32> = (0..3 * size).map(|i| if i >= size + radius { 255 } else { 0 }).collect();
+        let half = (radius / BLUR_PASSES).max(1);
+        for _ in 0..BLUR_PASSES {
+            profile = box_blur(&profile, half);
+        }
+        let profile = &profile[size..2 * size];
+        let mut alpha = vec![0; size * size];
+        for i in 0..size {
+            for j in 0..size {
+                alpha[i * size + j] = (profile[i] * profile[j] / 255) as u8;
+            }
+        }
+        Self { radius, size, alpha }
+    }
+
+    fn get(radius: usize) -> Arc<Corner> {
+        let mut cache = CACHE.lock();
+        match cache.as_ref() {
+            Some(corner) if corner.radius == radius => corner.clone(),
+            _ => {
+                let corner = Arc::new(Corner::new(radius));
+                *cache = Some(corner.clone());
+                corner
+            }
+        }
+    }
+}
+
+// 宽 2 * half + 1 的盒式模糊，两端之外当作和端点相同
+fn box_blur(values: &[u32], half: usize) -> Vec<u32> {
+    let last = values.len() - 1;
+    let width = 2 * half as u32 + 1;
+    (0..values.len())
+        .map(|i| (i as isize - half as isize..=(i + half) as isize)
+            .map(|k| values[k.clamp(0, last as isize) as usize])
+            .sum::<u32>() / width)
+        .collect()
+}
+
+/// 一个矩形投下的阴影
+#[derive(Debug, Clone)]
+pub struct Shadow {
+    // 阴影矩形（投影物体的位置加上偏移），模糊之前
+    sx: isize,
+    sy: isize,
+    ex: isize,
+    ey: isize,
+    // 最深处的不透明度
+    max_alpha: u32,
+    corner: Arc<Corner>,
+}
+
+impl Shadow {
+    /// `caster` 投下的阴影，向下、向右偏移 `offset`，模糊半径 `radius`（大于 0），最深处不透明度为 `alpha`
+    pub fn new(caster: Region, offset: (usize, usize), radius: usize, alpha: u8) -> Self {
+        // 矩形比模糊的范围还小时，对边的模糊会叠在一起，这里缩小半径
+        let radius = radius.clamp(1, ((caster.ex - caster.sx).min(caster.ey - caster.sy) / 2).max(1));
+        Self {
+            sx: (caster.sx + offset.0) as isize,
+            sy: (caster.sy + offset.1) as isize,
+            ex: (caster.ex + offset.0) as isize,
+            ey: (caster.ey + offset.1) as isize,
+            max_alpha: alpha as u32,
+            corner: Corner::get(radius),
+        }
+    }
+
+    /// 阴影覆盖的区域，超出屏幕的部分被裁掉
+    pub fn region(&self) -> Region {
+        let r = self.corner.radius as isize;
+        let clamp = |v: isize| v.max(0) as usize;
+        Region::new(clamp(self.sx - r), clamp(self.sy - r), clamp(self.ex + r), clamp(self.ey + r))
+    }
+
+    /// (x, y) 处的不透明度，0 到 255
+    pub fn alpha(&self, x: usize, y: usize) -> u8 {
+        let (x, y) = (x as isize, y as isize);
+        let (r, size) = (self.corner.radius as isize, self.corner.size as isize);
+        // 离最近的边多远：靠近起始边时从角的开头数，靠近结束边时镜像，中间取角的最后一格
+        let index = |v: isize, start: isize, end: isize| (v + r - start).min(end + r - 1 - v).min(size - 1);
+        let (i, j) = (index(x, self.sx, self.ex), index(y, self.sy, self.ey));
+        if i < 0 || j < 0 {
+            return 0;
+        }
+        (self.corner.alpha[(i * size + j) as usize] as u32 * self.max_alpha / 255) as u8
+    }
+}
@@ -0,0 +1,87 @@
+// 精灵
+// Sprite 持有解码好的图片，可以反复画到任意画布上，不用每次重新解码。
+// 画的时候可以缩放（最近邻）、水平翻转、限定裁剪区域，还可以指定一种颜色当作透明（color key）
+
+use embedded_graphics::pixelcolor::Rgb888;
+
+use crate::graphic::canvas::Canvas;
+use crate::graphic::image::Image;
+use crate::graphic::Region;
+
+#[derive(Debug, Clone)]
+pub struct Sprite {
+    image: Image,
+    color_key: Option<Rgb888>,
+}
+
+/// 画精灵时的选项
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BlitOptions {
+    /// 画出来的大小（高，宽），None 表示原始大小
+    pub size: Option<(usize, usize)>,
+    /// 左右翻转
+    pub flip_horizontal: bool,
+    /// 只画画布上这块区域内的部分
+    pub clip: Option<Region>,
+}
+
+impl Sprite {
+    pub fn new(image: Image) -> Self {
+        Self { image, color_key: None }
+    }
+
+    pub fn from_bmp(data: &[u8]) -> Option<Self> {
+        Image::from_bmp(data).map(Self::new)
+    }
+
+    pub fn from_bmp_32rgba(data: &[u8]) -> Option<Self> {
+        Image::from_bmp_32rgba(data).map(Self::new)
+    }
+
+    pub fn from_qoi(data: &[u8]) -> Option<Self> {
+        Image::from_qoi(data).map(Self::new)
+    }
+
+    /// 把这种颜色的像素当作透明
+    pub fn with_color_key(mut self, color: Rgb888) -> Self {
+        self.color_key = Some(color);
+        self
+    }
+
+    pub fn width(&self) -> usize {
+        self.image.width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.image.height()
+    }
+
+    /// 按原始大小画在 (x, y)
+    pub fn draw<C: Canvas + ?Sized>(&self, canvas: &mut C, x: usize, y: usize) {
+        self.draw_with(canvas, x, y, &BlitOptions::default());
+    }
+
+    pub fn draw_with<C: Canvas + ?Sized>(&self, canvas: &mut C, x: usize, y: usize, options: &BlitOptions) {
+        let (height, width) = options.size.unwrap_or((self.height(), self.width()));
+        if height == 0 || width == 0 {
+            return;
+        }
+        let (canvas_height, canvas_width) = canvas.size();
+        let clip = options.clip.unwrap_or(Region { sx: 0, sy: 0, ex: canvas_height, ey: canvas_width });
+        let (sx, ex) = (x.max(clip.sx), (x + height).min(clip.ex).min(canvas_height));
+        let (sy, ey) = (y.max(clip.sy), (y + width).min(clip.ey).min(canvas_width));
+        for i in sx..ex {
+            let src_x = (i - x) * self.height() / height;
+            for j in sy..ey {
+                let mut src_y = (j - y) * self.width() / width;
+                if options.flip_horizontal {
+                    src_y = self.width() - 1 - src_y;
+                }
+                match self.image.pixel(src_x, src_y) {
+                    Some(color) if Some(color) != self.color_key => canvas.put_pixel(i, j, color),
+                    _ => {}
+                }
+            }
+        }
+    }
+}
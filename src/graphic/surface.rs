@@ -0,0 +1,126 @@
+// 离屏画面
+// 任意大小的一块像素，按行连续存放，每个像素是颜色和是否不透明。图层（Writer）的内容就是一个 Surface，
+// 图层再记下它放在屏幕上的哪个位置，合成时按这个偏移取像素，窗口的图层只需要和窗口一样大，不用占满整个屏幕。
+// surface[x][y] 取第 x 行第 y 列，坐标和 graphic 模块一致：x 是行，y 是列
+
+use alloc::collections::TryReserveError;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::{Index, IndexMut};
+
+use embedded_graphics::pixelcolor::Rgb888;
+
+use crate::graphic::canvas::Canvas;
+
+/// 透明的像素
+pub const TRANSPARENT: (Rgb888, bool) = (Rgb888::new(0, 0, 0), false);
+
+// 画面很大，不实现 Clone，避免无意中整块复制
+#[derive(Debug)]
+pub struct Surface {
+    width: usize,
+    height: usize,
+    pixels: Vec<(Rgb888, bool)>,
+}
+
+impl Surface {
+    /// 宽 `width` 列、高 `height` 行的透明画面
+    pub fn new(width: usize, height: usize) -> Self {
+        Self { width, height, pixels: vec![TRANSPARENT; width * height] }
+    }
+
+    /// 和 new 一样，但内存不够时返回错误而不是 panic
+    pub fn try_new(width: usize, height: usize) -> Result<Self, TryReserveError> {
+        let mut pixels = Vec::new();
+        pixels.try_reserve_exact(width * height)?;
+        pixels.resize(width * height, TRANSPARENT);
+        Ok(Self { width, height, pixels })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// (x, y) 处的像素，超出画面时是透明的
+    pub fn pixel(&self, x: usize, y: usize) -> (Rgb888, bool) {
+        if x < self.height && y < self.width {
+            self.pixels[x * self.width + y]
+        } else {
+            TRANSPARENT
+        }
+    }
+
+    /// 把所有像素恢复成透明
+    pub fn clear(&mut self) {
+        self.pixels.fill(TRANSPARENT);
+    }
+
+    /// 改变大小，内容被清空
+    ///
+    /// 缩小时不释放缓冲区，所以缩小以后再放大回原来的大小不会失败。内存不够时返回错误，画面保持不变
+    pub fn resize(&mut self, width: usize, height: usize) -> Result<(), TryReserveError> {
+        let len = width * height;
+        self.pixels.try_reserve_exact(len.saturating_sub(self.pixels.len()))?;
+        self.pixels.clear();
+        self.pixels.resize(len, TRANSPARENT);
+        self.width = width;
+        self.height = height;
+        Ok(())
+    }
+
+    /// 把第 sx..ex 行整体上移 `lines` 行，空出来的行变成透明
+    pub fn scroll_up(&mut self, sx: usize, ex: usize, lines: usize) {
+        let ex = ex.min(self.height);
+        if sx >= ex {
+            return;
+        }
+        let lines = lines.min(ex - sx);
+        let width = self.width;
+        self.pixels.copy_within((sx + lines) * width..ex * width, sx * width);
+        self.pixels[(ex - lines) * width..ex * width].fill(TRANSPARENT);
+    }
+
+    /// 占用的字节数
+    pub fn footprint(&self) -> usize {
+        self.pixels.capacity() * core::mem::size_of::<(Rgb888, bool)>()
+    }
+}
+
+impl Index<usize> for Surface {
+    type Output = [(Rgb888, bool)];
+
+    fn index(&self, x: usize) -> &Self::Output {
+        &self.pixels[x * self.width..(x + 1) * self.width]
+    }
+}
+
+impl IndexMut<usize> for Surface {
+    fn index_mut(&mut self, x: usize) -> &mut Self::Output {
+        &mut self.pixels[x * self.width..(x + 1) * self.width]
+    }
+}
+
+impl Canvas for Surface {
+    fn size(&self) -> (usize, usize) {
+        (self.height, self.width)
+    }
+
+    fn put_pixel(&mut self, x: usize, y: usize, color: Rgb888) {
+        if x < self.height && y < self.width {
+            self[x][y] = (color, true);
+        }
+    }
+
+    fn get_pixel(&self, x: usize, y: usize) -> Option<Rgb888> {
+        let (color, opaque) = self.pixel(x, y);
+        opaque.then_some(color)
+    }
+
+    fn fill_span(&mut self, x: usize, y0: usize, y1: usize, color: Rgb888) {
+        self[x][y0..y1].fill((color, true));
+    }
+}
@@ -1,11 +1,23 @@
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec::Vec;
 use core::fmt;
+use core::mem::size_of;
+use core::sync::atomic::{AtomicBool, AtomicIsize, AtomicUsize, Ordering};
 
 use embedded_graphics::pixelcolor::Rgb888;
 use lazy_static::lazy_static;
-use rusttype::{ScaledGlyph};
+use rusttype::ScaledGlyph;
 use spin::Mutex;
-use crate::graphic::{DEFAULT_RGB888, GD, GL, rgb888};
-use crate::graphic::font::get_font;
+use x86_64::instructions::interrupts;
+use crate::allocator::shrinker::{self, Shrinker};
+use crate::graphic::layer::{self, LayerHandle};
+use crate::graphic::{GD, Region, Writer, rgb888};
+use crate::graphic::canvas::Canvas;
+use crate::graphic::font::{glyph, glyph_supersampled, line_pitch, FontId, Glyph};
+use crate::io::ansi::{self, Action, Parser};
+use crate::io::theme;
+use crate::io::vt::{self, Tty};
 
 // 提交到内存中的HD字符
 #[derive(Debug, Clone)]
@@ -15,108 +27,634 @@ struct ScreenChar {
     color: Rgb888,
 }
 
-const TEXT_AREA_HEIGHT: usize = super::HEIGHT - 30;
-const TEXT_AREA_WIDTH: usize = super::WIDTH;
 const TEXT_AREA_POS: (usize, usize) = (22, 0);
 const TEXT_SIZE: f32 = 16.0;
-const TEXT_HEIGHT: usize = TEXT_SIZE as usize;
 const TEXT_COLOR: Rgb888 = rgb888!(0xddddddu32);
 const TAB_SIZE: usize = 4 * 16;
+// 默认保留的滚出屏幕的行数
+const SCROLLBACK_LINES: usize = 1000;
+
+/// 控制台可选的字号，Ctrl+加号/减号在其间切换
+pub const ZOOM_LEVELS: [f32; 4] = [12.0, 16.0, 24.0, 32.0];
+
+// 翻页请求，由键盘中断记下，GUI 主循环处理
+static PENDING_SCROLL: AtomicIsize = AtomicIsize::new(0);
+// 当前字号在 ZOOM_LEVELS 中的下标
+static ZOOM: AtomicUsize = AtomicUsize::new(1);
+// 字号变了，还没有按新字号重画
+static ZOOM_CHANGED: AtomicBool = AtomicBool::new(false);
+
+// 文本区域的大小随分辨率变化
+fn text_area_height() -> usize {
+    super::height() - 30
+}
+
+fn text_area_width() -> usize {
+    super::width()
+}
+
+// 整个文本区域在图层上的位置
+fn text_area() -> Region {
+    Region::new(TEXT_AREA_POS.0, TEXT_AREA_POS.1, TEXT_AREA_POS.0 + text_area_height(), TEXT_AREA_POS.1 + text_area_width())
+}
+
+// 文字排版
+// 测量宽度、在空格处折行、按对齐方式画到任意画布上，控制台和 GUI 控件共用。
+// 每次画的时候可以选画质（Quality）：默认按覆盖率取舍，16 像素的等宽字体这样最清楚；别的字号用 Blended 按覆盖率和底色混合，
+// 大字号的标题用 Supersampled。混合需要画布能读回底色（Canvas::get_pixel），画在图层上时用 layer::with_layer_blended
+
+/// 对齐方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+/// 字形画到画布上的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Quality {
+    /// 覆盖率超过一半的像素画成实色，其余不画
+    #[default]
+    Threshold,
+    /// 按覆盖率和画布上原来的颜色混合
+    Blended,
+    /// 按两倍字号光栅化后缩小一半，再和 Blended 一样混合
+    Supersampled,
+}
+
+/// 一个字符的前进宽度（含 1 像素字距）
+pub fn advance(ch: char, font: FontId, size: f32) -> usize {
+    glyph(font, ch, size).advance as usize + 1
+}
+
+/// 字符串画出来的宽度，不考虑换行
+pub fn measure(s: &str, font: FontId, size: f32) -> usize {
+    s.chars().map(|ch| advance(ch, font, size)).sum()
+}
+
+/// 按 `max_width` 折行，返回每一行
+///
+/// '\n' 强制换行；尽量在空格处断开，一个单词比一行还长时按字符断开。行尾的空格不算宽度
+pub fn wrap(s: &str, font: FontId, size: f32, max_width: usize) -> Vec<&str> {
+    let mut lines = Vec::new();
+    for paragraph in s.split('\n') {
+        let mut line_start = 0;
+        let mut width = 0;
+        // 这一行里最后一个空格的位置，以及空格之后的宽度
+        let mut space = None;
+        let mut width_after_space = 0;
+        for (i, ch) in paragraph.char_indices() {
+            let w = advance(ch, font, size);
+            if ch == ' ' {
+                space = Some(i);
+                width_after_space = 0;
+                width += w;
+                continue;
+            }
+            if width + w > max_width && i > line_start {
+                match space {
+                    Some(space) => {
+                        lines.push(&paragraph[line_start..space]);
+                        line_start = space + 1;
+                        width = width_after_space;
+                    }
+                    None => {
+                        lines.push(&paragraph[line_start..i]);
+                        line_start = i;
+                        width = 0;
+                    }
+                }
+                space = None;
+            }
+            width += w;
+            width_after_space += w;
+        }
+        lines.push(&paragraph[line_start..]);
+    }
+    lines
+}
+
+/// 画一个字形，(x, y) 是这一行的左上角，和 Writer::display_font 一样按行高对齐基线
+pub fn draw_glyph<C: Canvas + ?Sized>(canvas: &mut C, glyph: &Glyph, x: usize, y: usize, line_height: usize, color: Rgb888) {
+    draw_glyph_with(canvas, glyph, x, y, line_height, color, Quality::Threshold);
+}
+
+/// 同 draw_glyph，按 `quality` 画；超采样的字形要用 font::glyph_supersampled 取
+pub fn draw_glyph_with<C: Canvas + ?Sized>(canvas: &mut C, glyph: &Glyph, x: usize, y: usize, line_height: usize,
+                                           color: Rgb888, quality: Quality) {
+    glyph.for_each_pixel(line_height, |gx, gy, v| match quality {
+        Quality::Threshold if v > 0.5 => canvas.put_pixel(x + gx, y + gy, color),
+        Quality::Threshold => {}
+        Quality::Blended | Quality::Supersampled => canvas.blend_pixel(x + gx, y + gy, color, v),
+    });
+}
+
+/// 在画布上 (x, y) 处宽 `width`、高 `height` 的框里排版并画出文字，放不下的行不画，返回用掉的高度
+pub fn draw_text<C: Canvas + ?Sized>(canvas: &mut C, x: usize, y: usize, width: usize, height: usize,
+                                     s: &str, font: FontId, size: f32, line_height: usize, align: Align, color: Rgb888) -> usize {
+    draw_text_with(canvas, x, y, width, height, s, font, size, line_height, align, color, Quality::Threshold)
+}
+
+/// 同 draw_text，按 `quality` 画；排版总是按原来字号的字形算，换画质不会改变折行和位置
+pub fn draw_text_with<C: Canvas + ?Sized>(canvas: &mut C, x: usize, y: usize, width: usize, height: usize, s: &str,
+                                          font: FontId, size: f32, line_height: usize, align: Align, color: Rgb888,
+                                          quality: Quality) -> usize {
+    let mut used = 0;
+    for line in wrap(s, font, size, width) {
+        if used + line_height > height {
+            break;
+        }
+        let line = line.trim_end();
+        let free = width.saturating_sub(measure(line, font, size));
+        let mut col = y + match align {
+            Align::Left => 0,
+            Align::Center => free / 2,
+            Align::Right => free,
+        };
+        for ch in line.chars() {
+            let glyph = glyph(font, ch, size);
+            match quality {
+                Quality::Supersampled => {
+                    let fine = glyph_supersampled(font, ch, size);
+                    draw_glyph_with(canvas, &fine, x + used, col, line_height, color, quality);
+                }
+                _ => draw_glyph_with(canvas, &glyph, x + used, col, line_height, color, quality),
+            }
+            col += glyph.advance as usize + 1;
+        }
+        used += line_height;
+    }
+    used
+}
+
+// 画在屏幕上的一个字符，翻看历史时按它重画；y 是在行内的位置
+#[derive(Debug, Clone, Copy)]
+struct Cell {
+    y: usize,
+    ch: char,
+    color: Rgb888,
+    background: Option<Rgb888>,
+    // 已经画到图层上了
+    drawn: bool,
+}
+
+// 屏幕上的一行或者历史中的一行
+#[derive(Debug, Clone, Default)]
+struct Row {
+    cells: Vec<Cell>,
+    // 这一行是上一行放不下自动折过来的，改变字号时和上一行接起来重新折行
+    continued: bool,
+    // 有还没画的字符
+    dirty: bool,
+}
+
+impl Row {
+    // 大约占用的字节数
+    fn footprint(&self) -> usize {
+        size_of::<Self>() + self.cells.capacity() * size_of::<Cell>()
+    }
+}
 
 // 输出器
 pub struct TextWriter {
     y_position: usize,
     line_position: usize,
+    // 字号，行高和字号相同
+    size: f32,
     line_height: usize,
     line_gap: usize,
     max_line: usize,
     color: Rgb888,
-    layer: usize,
+    // 背景色，None 时透明
+    background: Option<Rgb888>,
+    layer: LayerHandle,
+    // 当前行每个字符的前进宽度，用于退格
+    advances: Vec<usize>,
+    // 解析输出中的 ANSI 转义序列
+    parser: Parser,
+    // 上次同步颜色时的主题版本
+    theme_generation: usize,
+    // 屏幕上每一行的字符，下标是行号
+    rows: Vec<Row>,
+    // 滚出屏幕顶部的行
+    history: VecDeque<Row>,
+    scrollback_limit: usize,
+    // 向上翻看了多少行，0 表示显示当前画面
+    view: usize,
+    // 在前台，后台的虚拟控制台只记下字符，不画到图层上
+    visible: bool,
+    // 图层上改动过、还没提交到屏幕的区域
+    damage: Option<Region>,
 }
 
 lazy_static! {
     pub static ref TEXT_WRITER: Mutex<TextWriter> = {
-        Mutex::new(TextWriter{
-            y_position: 0,
-            line_position: 0,
-            line_height: TEXT_HEIGHT,
-            line_gap: 4,
-            max_line: TEXT_AREA_HEIGHT / (TEXT_HEIGHT+4),
-            color: TEXT_COLOR,
-            layer: 1
-        })
+        shrinker::register(&SCROLLBACK_SHRINKER);
+        Mutex::new(TextWriter::new(true))
     };
 }
 
+// 虚拟控制台，前台的控制台在 TEXT_WRITER 里，它在这里的位置上放的是一个空的控制台
+struct Consoles {
+    active: usize,
+    writers: Vec<TextWriter>,
+}
+
+lazy_static! {
+    static ref CONSOLES: Mutex<Consoles> = Mutex::new(Consoles {
+        active: vt::active().index(),
+        writers: (0..vt::COUNT).map(|_| TextWriter::new(false)).collect(),
+    });
+}
+
 impl TextWriter {
+    fn new(visible: bool) -> Self {
+        TextWriter {
+            y_position: 0,
+            line_position: 0,
+            size: TEXT_SIZE,
+            line_height: TEXT_SIZE as usize,
+            line_gap: line_pitch(TEXT_SIZE) - TEXT_SIZE as usize,
+            max_line: text_area_height() / line_pitch(TEXT_SIZE),
+            color: TEXT_COLOR,
+            background: None,
+            layer: layer::CONSOLE,
+            advances: Vec::new(),
+            parser: Parser::new(),
+            theme_generation: 0,
+            rows: Vec::new(),
+            history: VecDeque::new(),
+            scrollback_limit: SCROLLBACK_LINES,
+            view: 0,
+            visible,
+            damage: None,
+        }
+    }
+
+    // 一行文字占的像素行数
+    fn pitch(&self) -> usize {
+        self.line_height + self.line_gap
+    }
+
+    // 第 line 行在图层上的起始像素行
+    fn line_x(&self, line: usize) -> usize {
+        self.pitch() * line + TEXT_AREA_POS.0
+    }
+
+    // 记下图层上改动过的区域，present 时一起提交
+    fn damage(&mut self, region: Region) {
+        self.damage = Some(self.damage.map_or(region, |d| d.union(region)));
+    }
+
+    // 把还没画的字符画到图层上，再把所有改动合成一次提交到屏幕，在后台时什么都不做
+    fn present(&mut self) {
+        if !self.visible {
+            self.damage = None;
+            return;
+        }
+        let mut rows = core::mem::take(&mut self.rows);
+        layer::with_layer(self.layer, |layer| {
+            for (line, row) in rows.iter_mut().enumerate().filter(|(_, row)| row.dirty) {
+                for cell in row.cells.iter_mut().filter(|cell| !cell.drawn) {
+                    let region = self.draw_cell(layer, line, cell);
+                    self.damage(region);
+                    cell.drawn = true;
+                }
+                row.dirty = false;
+            }
+        });
+        self.rows = rows;
+        if let Some(region) = self.damage.take() {
+            GD.lock().render(region.sx, region.sy, region.ex, region.ey);
+        }
+    }
+
     fn _write_char(&mut self, ch: char) {
         match ch {
             '\t' => self.horizontal_tab(),
             '\n' => self.new_line(),
+            '\x08' => self.backspace(),
             ch => {
-                let (glyph, hm) = get_font(ch, TEXT_SIZE);
-                if self.y_position + hm.advance_width as usize > TEXT_AREA_WIDTH {
-                    self.new_line();
+                let glyph = glyph(FontId::MONOSPACE, ch, self.size);
+                if self.y_position + glyph.advance as usize > text_area_width() {
+                    self.wrap_line();
                 }
 
-                let p_lock = GL.read();
-                let mut lock = p_lock[self.layer].lock();
-                lock.display_font(glyph, (self.line_height + self.line_gap) * self.line_position + TEXT_AREA_POS.0,
-                self.y_position + TEXT_AREA_POS.1, TEXT_SIZE, self.line_height, self.color);
-
-                drop(lock);
+                // 先只记下来，present 时再画
+                let cell = Cell { y: self.y_position, ch, color: self.color, background: self.background, drawn: false };
+                let width = glyph.advance as usize + 1usize;
+                let row = self.row_mut(self.line_position);
+                row.cells.retain(|c| c.y + width <= cell.y || c.y >= cell.y + width);
+                row.cells.push(cell);
+                row.dirty = true;
 
-                self.y_position += hm.advance_width as usize + 1usize;
+                self.y_position += width;
+                self.advances.push(width);
             }
         }
     }
 
+    // 在第 line 行画一个字符，不提交到屏幕，返回这个字符的格子
+    fn draw_cell(&self, layer: &mut Writer, line: usize, cell: &Cell) -> Region {
+        let glyph = glyph(FontId::MONOSPACE, cell.ch, self.size);
+        let (x, y) = (self.line_x(line), cell.y + TEXT_AREA_POS.1);
+        if let Some(background) = cell.background {
+            layer.display_rect(x, y, glyph.advance as usize + 1, self.pitch(), background);
+        }
+        layer.display_font(&glyph, x, y, self.line_height, cell.color);
+        Region::new(x, y, x + self.pitch(), y + glyph.advance as usize + 1)
+    }
+
+    fn row_mut(&mut self, line: usize) -> &mut Row {
+        if self.rows.len() <= line {
+            self.rows.resize(line + 1, Row::default());
+        }
+        &mut self.rows[line]
+    }
+
+    // 当前行放不下，折到下一行
+    fn wrap_line(&mut self) {
+        self.new_line();
+        self.row_mut(self.line_position).continued = true;
+    }
+
     /// 提供外部调用的版本，内部勿调用
     pub fn write_char(&mut self, ch: char) {
+        self.scroll_to_live();
         self._write_char(ch);
+        self.present();
+    }
+
+    /// 设置之后输出的文字颜色和背景色，背景为 None 时透明
+    pub fn set_color(&mut self, color: Rgb888, background: Option<Rgb888>) {
+        self.color = color;
+        self.background = background;
+    }
+
+    pub fn color(&self) -> (Rgb888, Option<Rgb888>) {
+        (self.color, self.background)
+    }
 
-        GD.lock().render((self.line_height + self.line_gap) * self.line_position + TEXT_AREA_POS.0,
-                         self.y_position + TEXT_AREA_POS.1,
-                         (self.line_height + self.line_gap) * self.line_position + TEXT_AREA_POS.0 + (TEXT_SIZE * 1.5) as usize,
-                         self.y_position + TEXT_AREA_POS.1 + TEXT_SIZE as usize);
+    // 恢复成主题的颜色
+    fn reset_color(&mut self) {
+        let theme = theme::get();
+        self.set_color(theme.foreground.unwrap_or(TEXT_COLOR), theme.background);
+        self.theme_generation = theme::generation();
     }
 
     pub fn write_string(&mut self, s: &str) {
-        let sx = self.line_position;
+        // 有新的输出时回到当前画面
+        self.scroll_to_live();
+        if self.theme_generation != theme::generation() {
+            self.reset_color();
+        }
+        let mut parser = core::mem::take(&mut self.parser);
+        let mut word = String::new();
         for ch in s.chars() {
+            parser.feed(ch, |action| {
+                if let Action::Print(ch) = action {
+                    word.push(ch);
+                    if ch != ' ' && ch != '\n' {
+                        return;
+                    }
+                }
+                self.write_word(&word);
+                word.clear();
+                if !matches!(action, Action::Print(_)) {
+                    self.apply(action);
+                }
+            });
+        }
+        self.parser = parser;
+        self.write_word(&word);
+        // 整段输出（包括中间的滚屏）只合成一次
+        self.present();
+    }
+
+    // 放不下的单词整个换到下一行
+    fn write_word(&mut self, word: &str) {
+        let width = measure(word.trim_end(), FontId::MONOSPACE, self.size);
+        if self.y_position > 0 && self.y_position + width > text_area_width() {
+            self.wrap_line();
+        }
+        for ch in word.chars() {
             self._write_char(ch);
         }
-        let ex = self.line_position;
-        GD.lock().render((self.line_height + self.line_gap) * sx + TEXT_AREA_POS.0,
-                         TEXT_AREA_POS.1,
-                         (self.line_height + self.line_gap) * ex + TEXT_AREA_POS.0 + (TEXT_SIZE * 1.5) as usize,
-                         TEXT_AREA_POS.1 + TEXT_AREA_WIDTH);
     }
 
+    // 执行颜色、光标移动和清除，按一个空格的宽度算列
+    fn apply(&mut self, action: Action) {
+        let column = advance(' ', FontId::MONOSPACE, self.size);
+        match action {
+            Action::Print(ch) => self._write_char(ch),
+            Action::Foreground(color) => {
+                self.color = color.map_or_else(|| theme::get().foreground.unwrap_or(TEXT_COLOR), ansi::color);
+            }
+            Action::Background(color) => self.background = color.map(ansi::color).or(theme::get().background),
+            Action::CursorUp(n) => self.line_position = self.line_position.saturating_sub(n),
+            Action::CursorDown(n) => self.line_position = (self.line_position + n).min(self.max_line.saturating_sub(1)),
+            Action::CursorForward(n) => self.y_position = (self.y_position + n * column).min(text_area_width()),
+            Action::CursorBack(n) => self.y_position = self.y_position.saturating_sub(n * column),
+            Action::CursorPosition(row, col) => {
+                self.line_position = row.min(self.max_line.saturating_sub(1));
+                self.y_position = (col * column).min(text_area_width());
+            }
+            Action::ClearScreen => {
+                // 清屏不移动光标
+                let (line, y) = (self.line_position, self.y_position);
+                self.clear();
+                self.line_position = line;
+                self.y_position = y;
+            }
+            Action::ClearLine => self.erase(self.y_position, text_area_width()),
+        }
+        // 光标移动过，退格不再知道前面字符的宽度
+        self.advances.clear();
+    }
+
+    // 擦除当前行中 start..end 列（相对文本区域）
+    fn erase(&mut self, start: usize, end: usize) {
+        self.row_mut(self.line_position).cells.retain(|c| c.y < start || c.y >= end);
+        if !self.visible {
+            return;
+        }
+        let (x, y, width) = (self.line_x(self.line_position), start + TEXT_AREA_POS.1, end.saturating_sub(start));
+        layer::with_layer(self.layer, |layer| layer.clear_rect(x, y, width, self.pitch()));
+        self.damage(Region::new(x, y, x + self.pitch(), y + width));
+    }
 
     fn new_line(&mut self) {
         // 1. 回车
         self.y_position = 0;
+        self.advances.clear();
         // 2. 加新行
         if self.line_position + 1 < self.max_line {
             self.line_position += 1;
         } else {
-            let p_lock = GL.read();
-            let mut lock = p_lock[self.layer].lock();
-            for x in TEXT_AREA_POS.0..TEXT_AREA_POS.0 + (self.max_line - 1) * (self.line_height + self.line_gap) {
-                for y in TEXT_AREA_POS.1..TEXT_AREA_POS.1 + TEXT_AREA_WIDTH {
-                    lock.data[x][y] = lock.data[x + (self.line_height + self.line_gap)][y];
+            if !self.rows.is_empty() {
+                let row = self.rows.remove(0);
+                if self.scrollback_limit > 0 {
+                    if self.history.len() >= self.scrollback_limit {
+                        self.history.pop_front();
+                    }
+                    self.history.push_back(row);
                 }
             }
-            for x in TEXT_AREA_POS.0 + (self.max_line - 1) * (self.line_height + self.line_gap)..TEXT_AREA_POS.0 + TEXT_AREA_HEIGHT {
-                for y in TEXT_AREA_POS.1..TEXT_AREA_POS.1 + TEXT_AREA_WIDTH {
-                    lock.data[x][y] = (DEFAULT_RGB888, false);
+            if !self.visible {
+                return;
+            }
+            // 已经画好的行跟着图层的行缓冲区一起上移，还没画的字符 present 时画在新的位置上
+            let (start, end) = (TEXT_AREA_POS.0, self.line_x(self.max_line));
+            layer::with_layer(self.layer, |layer| layer.scroll_up(start, end, self.pitch()));
+            self.damage(Region::new(start, TEXT_AREA_POS.1, end, TEXT_AREA_POS.1 + text_area_width()));
+        }
+    }
+
+    // 擦除当前行的最后一个字符，行首时不做处理
+    fn backspace(&mut self) {
+        if let Some(advance) = self.advances.pop() {
+            self.y_position -= advance;
+            self.erase(self.y_position, self.y_position + advance);
+        }
+    }
+
+    /// 清空文本区域并回到第一行，历史保留
+    pub fn clear(&mut self) {
+        self.scroll_to_live();
+        self.rows.clear();
+        self.clear_area();
+        self.line_position = 0;
+        self.y_position = 0;
+        self.advances.clear();
+        self.present();
+    }
+
+    // 擦掉整个文本区域，不提交到屏幕
+    fn clear_area(&mut self) {
+        if !self.visible {
+            return;
+        }
+        let area = text_area();
+        layer::with_layer(self.layer, |layer| layer.clear_rect(area.sx, area.sy, area.ey - area.sy, area.ex - area.sx));
+        self.damage(area);
+    }
+
+    /// 保留的历史行数，0 表示不保留
+    pub fn set_scrollback_limit(&mut self, lines: usize) {
+        self.scroll_to_live();
+        self.scrollback_limit = lines;
+        while self.history.len() > lines {
+            self.history.pop_front();
+        }
+    }
+
+    /// 翻看滚出屏幕的历史，正数向上，每页比屏幕少一行，留一行上下文
+    pub fn scroll(&mut self, pages: isize) {
+        let lines = pages * (self.max_line as isize - 1).max(1);
+        let view = (self.view as isize + lines).clamp(0, self.history.len() as isize) as usize;
+        if view == self.view {
+            return;
+        }
+        self.view = view;
+        self.redraw();
+    }
+
+    fn scroll_to_live(&mut self) {
+        if self.view > 0 {
+            self.view = 0;
+            self.redraw();
+        }
+    }
+
+    // 把历史和当前画面接起来，画出从末尾往前数 view 行的那一屏
+    fn redraw(&mut self) {
+        self.clear_area();
+        if self.visible {
+            layer::with_layer(self.layer, |layer| {
+                let start = self.history.len() - self.view;
+                for line in 0..self.max_line {
+                    let index = start + line;
+                    let row = match self.history.get(index) {
+                        Some(row) => row,
+                        None => match self.rows.get(index - self.history.len()) {
+                            Some(row) => row,
+                            None => break,
+                        },
+                    };
+                    for cell in &row.cells {
+                        self.draw_cell(layer, line, cell);
+                    }
                 }
+            });
+        }
+        // 整个区域都重画过了，当前画面上没有要补画的字符；翻看历史时回来还会再重画一次
+        for row in &mut self.rows {
+            row.cells.iter_mut().for_each(|cell| cell.drawn = true);
+            row.dirty = false;
+        }
+        self.present();
+    }
+
+    /// 分辨率改变后重新计算行数并回到第一行，图层内容已经被清空
+    pub fn resize(&mut self) {
+        self.max_line = text_area_height() / self.pitch();
+        self.rows.clear();
+        self.view = 0;
+        self.line_position = 0;
+        self.y_position = 0;
+        self.advances.clear();
+    }
+
+    /// 换成 `size` 号字，重新计算行数，把历史和屏幕上的内容按新的宽度重新折行后重画
+    pub fn set_size(&mut self, size: f32) {
+        if size == self.size {
+            return;
+        }
+        // 把自动折过来的行和上一行接起来，还原成原来的逻辑行
+        let mut lines: Vec<Vec<Cell>> = Vec::new();
+        for mut row in self.history.drain(..).chain(self.rows.drain(..)) {
+            row.cells.sort_by_key(|cell| cell.y);
+            match lines.last_mut() {
+                Some(line) if row.continued => line.append(&mut row.cells),
+                _ => lines.push(row.cells),
             }
+        }
+        self.size = size;
+        self.line_height = size as usize;
+        self.line_gap = line_pitch(size) - self.line_height;
+        self.max_line = (text_area_height() / self.pitch()).max(1);
 
-            drop(lock);
-            GD.lock().render(TEXT_AREA_POS.0, TEXT_AREA_POS.1, TEXT_AREA_POS.0 + TEXT_AREA_HEIGHT, TEXT_AREA_POS.1 + TEXT_AREA_WIDTH);
+        // 按新字号逐行排版，光标移动留下的空白不保留
+        let mut rows = Vec::new();
+        for line in lines {
+            let mut row = Row::default();
+            let mut y = 0;
+            for cell in line {
+                let width = advance(cell.ch, FontId::MONOSPACE, size);
+                if y > 0 && y + width > text_area_width() {
+                    rows.push(core::mem::take(&mut row));
+                    row.continued = true;
+                    y = 0;
+                }
+                row.cells.push(Cell { y, ..cell });
+                y += width;
+            }
+            rows.push(row);
+        }
+        // 最后一屏留在屏幕上，其余的放回历史
+        self.rows = rows.split_off(rows.len().saturating_sub(self.max_line));
+        self.history = rows.into();
+        while self.history.len() > self.scrollback_limit {
+            self.history.pop_front();
         }
+        self.line_position = self.rows.len().saturating_sub(1);
+        self.y_position = self.rows.last().and_then(|row| row.cells.last())
+            .map_or(0, |cell| cell.y + advance(cell.ch, FontId::MONOSPACE, size));
+        self.advances.clear();
+        self.view = 0;
+        self.redraw();
     }
 
     fn horizontal_tab(&mut self) {
@@ -124,6 +662,137 @@ impl TextWriter {
     }
 }
 
+/// `tty` 在后台时对它执行 `f` 并返回 true，在前台时返回 false，由调用者照常输出
+pub fn with_background(tty: Tty, f: impl FnOnce(&mut TextWriter)) -> bool {
+    interrupts::without_interrupts(|| {
+        let mut consoles = CONSOLES.lock();
+        if consoles.active == tty.index() {
+            return false;
+        }
+        f(&mut consoles.writers[tty.index()]);
+        true
+    })
+}
+
+/// 把 `tty` 换到前台并重画
+pub fn switch(tty: Tty) {
+    interrupts::without_interrupts(|| {
+        let mut consoles = CONSOLES.lock();
+        let (active, index) = (consoles.active, tty.index());
+        if active == index {
+            return;
+        }
+        let mut writer = TEXT_WRITER.lock();
+        writer.view = 0;
+        writer.visible = false;
+        core::mem::swap(&mut *writer, &mut consoles.writers[index]);
+        consoles.writers.swap(active, index);
+        consoles.active = index;
+        writer.visible = true;
+        // 在后台时字号可能变过
+        if writer.size != zoom() {
+            writer.set_size(zoom());
+        } else {
+            writer.redraw();
+        }
+    })
+}
+
+/// 分辨率改变后重新计算所有控制台的行数，图层内容已经被清空
+pub fn resize() {
+    let mut consoles = CONSOLES.lock();
+    consoles.writers.iter_mut().for_each(TextWriter::resize);
+    TEXT_WRITER.lock().resize();
+}
+
+/// 请求翻看历史，正数向上，可以在中断中调用
+pub fn request_scroll(pages: isize) {
+    PENDING_SCROLL.fetch_add(pages, Ordering::Relaxed);
+}
+
+/// 控制台当前的字号，终端窗口也跟着它
+pub fn zoom() -> f32 {
+    ZOOM_LEVELS[ZOOM.load(Ordering::Relaxed)]
+}
+
+/// 设置字号，不是 ZOOM_LEVELS 之一时返回 false
+pub fn set_zoom(size: f32) -> bool {
+    let Some(index) = ZOOM_LEVELS.iter().position(|&level| level == size) else { return false };
+    ZOOM.store(index, Ordering::Relaxed);
+    ZOOM_CHANGED.store(true, Ordering::Release);
+    true
+}
+
+/// 放大（正数）或缩小若干级，可以在中断中调用
+pub fn request_zoom(steps: isize) {
+    let last = ZOOM_LEVELS.len() as isize - 1;
+    let _ = ZOOM.fetch_update(Ordering::Relaxed, Ordering::Relaxed,
+                              |index| Some((index as isize + steps).clamp(0, last) as usize));
+    ZOOM_CHANGED.store(true, Ordering::Release);
+}
+
+/// 处理翻页和缩放请求
+///
+/// 由 gui::poll 调用
+pub fn flush() {
+    let pages = PENDING_SCROLL.swap(0, Ordering::Relaxed);
+    let zoom_changed = ZOOM_CHANGED.swap(false, Ordering::Acquire);
+    if pages != 0 || zoom_changed {
+        interrupts::without_interrupts(|| {
+            let mut writer = TEXT_WRITER.lock();
+            writer.set_size(zoom());
+            if pages != 0 {
+                writer.scroll(pages);
+            }
+        });
+    }
+}
+
+struct ScrollbackShrinker;
+
+static SCROLLBACK_SHRINKER: ScrollbackShrinker = ScrollbackShrinker;
+
+impl Shrinker for ScrollbackShrinker {
+    fn name(&self) -> &'static str {
+        "console scrollback"
+    }
+
+    fn count(&self) -> usize {
+        interrupts::without_interrupts(|| {
+            TEXT_WRITER.lock().history.iter().map(Row::footprint).sum()
+        })
+    }
+
+    fn shrink(&self, target: usize) -> usize {
+        interrupts::without_interrupts(|| {
+            let Some(mut writer) = TEXT_WRITER.try_lock() else { return 0 };
+            // 正在翻看时历史的行号会错位，先不回收
+            if writer.view > 0 {
+                return 0;
+            }
+            let mut freed = 0;
+            while freed < target {
+                let Some(row) = writer.history.pop_front() else { break };
+                freed += row.footprint();
+            }
+            freed
+        })
+    }
+}
+
+/// 临时换成指定的颜色执行 `f`（比如在里面 println!），之后恢复原来的颜色
+pub fn with_color<R>(color: Rgb888, background: Option<Rgb888>, f: impl FnOnce() -> R) -> R {
+    let saved = interrupts::without_interrupts(|| {
+        let mut writer = TEXT_WRITER.lock();
+        let saved = writer.color();
+        writer.set_color(color, background);
+        saved
+    });
+    let result = f();
+    interrupts::without_interrupts(|| TEXT_WRITER.lock().set_color(saved.0, saved.1));
+    result
+}
+
 impl fmt::Write for TextWriter {
     fn write_str(&mut self, s: &str) -> Result<(), core::fmt::Error> {
         self.write_string(s);
@@ -1,17 +1,19 @@
-use alloc::format;
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
 
-// 引入 `x86` 库中的 `outw` 函数，用于向 I/O 端口写入数据
-use x86::io::outw;
 // 引入 x86_64 架构相关的分页模块和类型，包括帧分配器、偏移页表以及页面大小
 use x86_64::structures::paging::{FrameAllocator, OffsetPageTable, Size4KiB};
-use crate::io::pci::{pci_config_read_u32, pci_find_device};
+use x86_64::VirtAddr;
+use crate::drivers::pci::{self as pci_driver, PciDriver};
+use crate::graphic::backend::{Backend, Mode};
+use crate::io::format::Size;
+use crate::io::port::{self, Port};
+use crate::io::pci::{pci_find, DeviceMatch, PciDevice};
 use crate::memory::graphic_support::create_graphic_memory_mapping;
-// 引入自定义模块中的函数 `qemu_print`, 用于打印调试信息到 QEMU 控制台
-use crate::io::qemu::qemu_print;
+use crate::memory::BootInfoFrameAllocator;
 
 // 定义两个常量，表示VBE接口的I/O端口地址（INDEX和DATA）
-const VBE_DISPI_IOPORT_INDEX: u16 = 0x01CE;
-const VBE_DISPI_IOPORT_DATA: u16 = 0x01CF;
+const VBE_DISPI_IOPORT_INDEX: Port<u16> = Port::new(0x01CE);
+const VBE_DISPI_IOPORT_DATA: Port<u16> = Port::new(0x01CF);
 
 // 定义一个枚举类型，表示不同的VBE寄存器索引。使用u16表示这些索引值，并且允许未使用代码存在（dead code）
 #[allow(dead_code)]
@@ -28,6 +30,8 @@ enum VbeDispiIndex {
     VirtHeight,
     XOffset,
     YOffset,
+    // 显存大小，单位 64KiB
+    VideoMemory64K,
 }
 
 // 定义另一个枚举类型，表示不同颜色深度（bits per pixel, BPP）。同样允许未使用代码存在
@@ -42,43 +46,170 @@ enum VbeDispiBpp {
     // 省略了很多我不可能用得到的深度
 }
 
+// Id 寄存器读出的版本号范围，读出别的值说明没有 BGA
+const VBE_DISPI_ID_FIRST: u16 = 0xB0C0;
+const VBE_DISPI_ID_LAST: u16 = 0xB0CF;
+
+// BGA 支持的最大分辨率
+const VBE_DISPI_MAX_XRES: usize = 2560;
+const VBE_DISPI_MAX_YRES: usize = 1600;
+
+// 显存大小，进入宽屏模式时读出并全部映射，之后切换分辨率不需要重新映射
+static VIDEO_MEMORY_SIZE: AtomicUsize = AtomicUsize::new(0);
+// 显存里放得下几页画面，两页时通过 YOffset 翻页实现双缓冲
+static PAGES: AtomicUsize = AtomicUsize::new(1);
+// 正在使用的显卡的 BAR0，用来在驱动匹配时认出它
+static LFB_BAR: AtomicU32 = AtomicU32::new(0);
+
+/// QEMU 的 -vga std、-device VGA 和 bochs-display 都是 1234:1111；
+/// 其他兼容 BGA 的 VGA 控制器（比如 virtio-vga）按类别匹配
+pub static DRIVER: PciDriver = PciDriver {
+    name: "bga",
+    matches: &[
+        DeviceMatch::Id { vendor_id: 0x1234, device_id: 0x1111 },
+        DeviceMatch::Class { class: 0x03, subclass: 0x00, prog_if: None },
+    ],
+    probe,
+};
+
+// 只接管进入宽屏模式时用的那块显卡
+fn probe(device: &PciDevice) -> bool {
+    let bar = LFB_BAR.load(Ordering::Relaxed);
+    bar != 0 && device.bar(0) == bar
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModeError {
+    // 还没有进入图形模式
+    NotGraphic,
+    // 分辨率超出范围，或色深不是 24/32
+    Unsupported,
+    // 显存放不下
+    OutOfVideoMemory,
+}
+
 // 定义一个不安全函数，用于向指定寄存器写入数据。首先向INDEX端口写索引，再向DATA端口写值
 unsafe fn bga_write_register(index: u16, value: u16) {
-    outw(VBE_DISPI_IOPORT_INDEX, index);
-    outw(VBE_DISPI_IOPORT_DATA, value);
+    VBE_DISPI_IOPORT_INDEX.write(index);
+    VBE_DISPI_IOPORT_DATA.write(value);
+}
+
+unsafe fn bga_read_register(index: u16) -> u16 {
+    VBE_DISPI_IOPORT_INDEX.write(index);
+    VBE_DISPI_IOPORT_DATA.read()
+}
+
+// 关闭显示，设置分辨率和色深后重新打开
+// 虚拟高度设为 pages 页，打开显示时 BGA 会把它重置成可见高度，所以要在之后设置
+unsafe fn bga_set_registers(width: usize, height: usize, bpp: usize, pages: usize) {
+    bga_write_register(VbeDispiIndex::Enable as u16, 0);
+    bga_write_register(VbeDispiIndex::Xres as u16, width as u16);
+    bga_write_register(VbeDispiIndex::Yres as u16, height as u16);
+    bga_write_register(VbeDispiIndex::Bpp as u16, bpp as u16);
+    // 0x41：打开显示并使用线性帧缓冲
+    bga_write_register(VbeDispiIndex::Enable as u16, 0x41);
+    bga_write_register(VbeDispiIndex::VirtWidth as u16, width as u16);
+    bga_write_register(VbeDispiIndex::VirtHeight as u16, (height * pages) as u16);
+    bga_write_register(VbeDispiIndex::XOffset as u16, 0);
+    bga_write_register(VbeDispiIndex::YOffset as u16, 0);
+    PAGES.store(pages, Ordering::Relaxed);
+}
+
+/// 当前模式下显存里的页数，1 或 2
+pub fn pages() -> usize {
+    PAGES.load(Ordering::Relaxed)
+}
+
+/// 设置从虚拟画面的第几行开始显示，用于翻页
+pub unsafe fn set_y_offset(y: usize) {
+    bga_write_register(VbeDispiIndex::YOffset as u16, y as u16);
+}
+
+/// 切换分辨率和色深，只重新设置寄存器，显存在进入宽屏模式时已经全部映射
+pub unsafe fn set_mode(width: usize, height: usize, bpp: usize) -> Result<(), ModeError> {
+    if width == 0 || height == 0 || width > VBE_DISPI_MAX_XRES || height > VBE_DISPI_MAX_YRES
+        || (bpp != VbeDispiBpp::_24 as usize && bpp != VbeDispiBpp::_32 as usize) {
+        return Err(ModeError::Unsupported);
+    }
+    let page_size = width * height * bpp / 8;
+    let video_memory = VIDEO_MEMORY_SIZE.load(Ordering::Relaxed);
+    if page_size > video_memory {
+        return Err(ModeError::OutOfVideoMemory);
+    }
+    // 放得下两页就开启双缓冲；虚拟高度是 16 位寄存器
+    let pages = if 2 * page_size <= video_memory && 2 * height <= u16::MAX as usize { 2 } else { 1 };
+    bga_set_registers(width, height, bpp, pages);
+    Ok(())
 }
 
 // 宽屏模式进入函数
 pub unsafe fn bga_enter_wide(
     mapper: &mut OffsetPageTable,
     frame_allocator: &mut impl FrameAllocator<Size4KiB>,
-) {
+) -> VirtAddr {
     // 定义进入宽屏模式的不安全方法：
     // - 首先禁用VBE，通过将Enable寄存器设置为0实现
-    bga_write_register(VbeDispiIndex::Enable as u16, 0);
-
-    // - 然后设置显示分辨率和颜色深度。
-    // - 使用外部模块提供的常量WIDTH和HEIGHT设置X轴/ Y轴分辨率.
-    // - 设置颜色深度为24位.
-    // 设置显示模式
-    bga_write_register(VbeDispiIndex::Xres as u16, super::WIDTH as u16);
-    bga_write_register(VbeDispiIndex::Yres as u16, super::HEIGHT as u16);
-    bga_write_register(VbeDispiIndex::Bpp as u16, VbeDispiBpp::_32 as u16);
-
-    // 再次启用 VBE，将 Enable 寄存器设置为特殊值以开启图形模式
-    bga_write_register(VbeDispiIndex::Enable as u16, 0x41);
+    // - 然后使用外部模块提供的默认分辨率和色深设置显示模式
+    // - 再次启用 VBE，将 Enable 寄存器设置为特殊值以开启图形模式
+    port::claim_or_log("bga", VBE_DISPI_IOPORT_INDEX.number(), 2);
+    // 要先知道显存大小才能决定是否双缓冲，所以先读显存大小
+    // 老版本的 BGA 没有这个寄存器，读出 0 时至少保证默认模式可用
+    let video_memory = (bga_read_register(VbeDispiIndex::VideoMemory64K as u16) as usize * 64 * 1024)
+        .max(super::DEFAULT_WIDTH * super::DEFAULT_HEIGHT * super::DEFAULT_BPP / 8);
+    VIDEO_MEMORY_SIZE.store(video_memory, Ordering::Relaxed);
+    log::info!("Video memory size: {}", Size(video_memory as u64));
+    set_mode(super::DEFAULT_WIDTH, super::DEFAULT_HEIGHT, super::DEFAULT_BPP)
+        .expect("Default display mode is not supported");
 
     // 获取LFB地址
-    // - 查找特定PCI设备(假设厂商ID为1111，设备ID为1234)并获取其线性帧缓冲(LFB)地址.
+    // - 查找 BGA 显卡（优先精确的厂商/设备 ID，找不到时按 VGA 控制器类别）并获取其线性帧缓冲(LFB)地址.
     //  - 打印调试信息以确认设备及其地址
-    let device = pci_find_device(0x1111, 0x1234);
-    qemu_print(format!("LFB device is {:?}\n", device).as_str());
-    let address = pci_config_read_u32(device.0, device.1, device.2, 0x10);
-    qemu_print(format!("We get LFB address:{:?}\n", address).as_str());
+    let device = pci_find(DRIVER.matches).expect("No BGA-compatible display controller");
+    log::info!("LFB device is {:02x}:{:02x}.{} {:04x}:{:04x}",
+               device.bus, device.device, device.function, device.vendor_id, device.device_id);
+    let address = device.bar(0);
+    log::info!("We get LFB address:{:#x}", address);
+    LFB_BAR.store(address, Ordering::Relaxed);
+    pci_driver::register(&DRIVER);
 
     // 初始化显存
-    //  最后调用自定义方法初始化显存，即将LFB地址映射到虚拟内存空间中
-    create_graphic_memory_mapping(mapper, frame_allocator, address as u64);
+    //  最后调用自定义方法初始化显存，即将LFB地址映射到虚拟内存空间中，并返回映射到的虚拟地址
+    // BAR 的低 4 位是标志位，需要去掉
+    // 映射全部显存（QEMU 默认 16MiB），之后切换分辨率不需要重新映射；大小向上取整到 2MiB，这样可以使用大页
+    let size = (video_memory as u64 + 0x1F_FFFF) & !0x1F_FFFF;
+    create_graphic_memory_mapping(mapper, frame_allocator, (address & !0xF) as u64, size)
+}
+
+/// 作为显示后端的 BGA
+pub struct Bga;
+
+impl Backend for Bga {
+    fn name(&self) -> &'static str {
+        "bga"
+    }
+
+    fn probe(&self) -> bool {
+        let id = unsafe { bga_read_register(VbeDispiIndex::Id as u16) };
+        (VBE_DISPI_ID_FIRST..=VBE_DISPI_ID_LAST).contains(&id) && pci_find(DRIVER.matches).is_some()
+    }
+
+    unsafe fn enter(&self, mapper: &mut OffsetPageTable, frames: &mut BootInfoFrameAllocator) -> Result<(VirtAddr, Mode), ModeError> {
+        let base = bga_enter_wide(mapper, frames);
+        Ok((base, Mode::packed(super::DEFAULT_WIDTH, super::DEFAULT_HEIGHT, super::DEFAULT_BPP)))
+    }
+
+    unsafe fn set_mode(&self, width: usize, height: usize, bpp: usize) -> Result<Mode, ModeError> {
+        set_mode(width, height, bpp)?;
+        Ok(Mode::packed(width, height, bpp))
+    }
+
+    fn pages(&self) -> usize {
+        pages()
+    }
+
+    unsafe fn set_y_offset(&self, y: usize) {
+        set_y_offset(y);
+    }
 }
 
 // ## 总结：
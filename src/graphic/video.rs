@@ -0,0 +1,100 @@
+// 视频画面
+// 播放视频和动画时每帧都整屏变化，逐像素混合所有图层太慢。VideoSurface 是一块独立的帧缓冲，
+// 交给 PhysicalWriter::start_video 之后，合成时它覆盖的区域直接取帧里的像素（按最近邻缩放到目标区域），
+// 只有鼠标图层会画在它上面；程序写完一帧后调用 PhysicalWriter::present_video，只重画改动过的部分
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::min;
+
+use embedded_graphics::pixelcolor::Rgb888;
+
+use crate::graphic::Region;
+
+pub struct VideoSurface {
+    width: usize,
+    height: usize,
+    // 按行存放
+    frame: Vec<Rgb888>,
+    // 显示在屏幕上的区域
+    dest: Region,
+    // 自上次显示以来改动过的区域，帧内坐标
+    dirty: Option<Region>,
+}
+
+impl VideoSurface {
+    /// 新建一块 width x height 的帧缓冲，显示到屏幕上的 dest 区域，大小不同时缩放
+    pub fn new(width: usize, height: usize, dest: Region) -> Self {
+        Self {
+            width,
+            height,
+            frame: vec![Rgb888::new(0, 0, 0); width * height],
+            dest,
+            dirty: Some(Region { sx: 0, sy: 0, ex: height, ey: width }),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn dest(&self) -> Region {
+        self.dest
+    }
+
+    /// 整帧，按行存放；整帧都会被重画
+    pub fn frame_mut(&mut self) -> &mut [Rgb888] {
+        self.mark_dirty(Region { sx: 0, sy: 0, ex: self.height, ey: self.width });
+        &mut self.frame
+    }
+
+    /// 从第 x 行开始写入若干整行
+    pub fn write_rows(&mut self, x: usize, pixels: &[Rgb888]) {
+        let start = min(x * self.width, self.frame.len());
+        let end = min(start + pixels.len(), self.frame.len());
+        self.frame[start..end].copy_from_slice(&pixels[..end - start]);
+        let rows = (end - start + self.width - 1) / self.width.max(1);
+        self.mark_dirty(Region { sx: x, sy: 0, ex: x + rows, ey: self.width });
+    }
+
+    /// 解码一帧 RLE 数据：每段 4 字节，依次是重复次数和 R、G、B，按行铺满整帧。数据不完整时返回 false
+    pub fn load_rle(&mut self, data: &[u8]) -> bool {
+        let mut pos = 0;
+        for run in data.chunks_exact(4) {
+            let count = min(run[0] as usize, self.frame.len() - pos);
+            self.frame[pos..pos + count].fill(Rgb888::new(run[1], run[2], run[3]));
+            pos += count;
+        }
+        self.mark_dirty(Region { sx: 0, sy: 0, ex: self.height, ey: self.width });
+        pos == self.frame.len()
+    }
+
+    pub fn mark_dirty(&mut self, region: Region) {
+        self.dirty = Some(self.dirty.map_or(region, |d| d.union(region)));
+    }
+
+    /// 取出改动过的区域，换算成屏幕坐标
+    pub(super) fn take_dirty(&mut self) -> Option<Region> {
+        let dirty = self.dirty.take()?;
+        let (dh, dw) = (self.dest.ex - self.dest.sx, self.dest.ey - self.dest.sy);
+        let scale = |v: usize, dst: usize, src: usize| (v * dst + src - 1) / src.max(1);
+        Some(Region {
+            sx: self.dest.sx + dirty.sx * dh / self.height.max(1),
+            sy: self.dest.sy + dirty.sy * dw / self.width.max(1),
+            ex: self.dest.sx + min(scale(dirty.ex, dh, self.height), dh),
+            ey: self.dest.sy + min(scale(dirty.ey, dw, self.width), dw),
+        })
+    }
+
+    /// 屏幕上 (x, y) 处的像素，调用者保证在 dest 之内
+    pub(super) fn sample(&self, x: usize, y: usize) -> Rgb888 {
+        let (dh, dw) = (self.dest.ex - self.dest.sx, self.dest.ey - self.dest.sy);
+        let fx = (x - self.dest.sx) * self.height / dh;
+        let fy = (y - self.dest.sy) * self.width / dw;
+        self.frame[fx * self.width + fy]
+    }
+}
@@ -0,0 +1,40 @@
+// 关于对话框
+// 显示系统名、版本、git 提交、构建时间和打开的特性，窗口用右上角的按钮关闭
+
+use alloc::boxed::Box;
+use alloc::collections::TryReserveError;
+use alloc::format;
+use alloc::string::String;
+
+use crate::graphic;
+use crate::gui::widgets::{self, Bounds, Label};
+use crate::gui::window::{BORDER, WINDOW_MANAGER};
+use crate::i18n::{self, Msg};
+use crate::version::{self, GIT_HASH, NAME, PROFILE, VERSION};
+
+const WIDTH: usize = 360;
+const HEIGHT: usize = 150;
+const MARGIN: usize = 8;
+const LINE_HEIGHT: usize = 20;
+
+/// 在屏幕中间打开关于对话框
+pub fn show() -> Result<(), TryReserveError> {
+    let mut features = String::new();
+    let _ = version::write_features(&mut features);
+    let lines = [
+        format!("{} {}", NAME, VERSION),
+        format!("{}", i18n::fill(Msg::AboutCommit, &[&GIT_HASH])),
+        format!("{}", i18n::fill(Msg::AboutBuilt, &[&version::build_time(), &PROFILE])),
+        format!("{}", i18n::fill(Msg::AboutFeatures, &[&features])),
+    ];
+
+    let x = graphic::height().saturating_sub(HEIGHT) / 2;
+    let y = graphic::width().saturating_sub(WIDTH) / 2;
+    let id = WINDOW_MANAGER.lock().create(&format!("{}", i18n::fill(Msg::About, &[&NAME])), x, y, WIDTH, HEIGHT)?;
+    let width = WIDTH - 2 * BORDER - 2 * MARGIN;
+    for (i, line) in lines.iter().enumerate() {
+        let bounds = Bounds::new(MARGIN + i * LINE_HEIGHT, MARGIN, width, LINE_HEIGHT);
+        widgets::add(id, Box::new(Label::new(bounds, line)));
+    }
+    Ok(())
+}
@@ -0,0 +1,247 @@
+// 窗口动画
+// 把窗口的位置或不透明度在一段时间里从起点变到终点，进度经过缓动曲线换算后插值。
+// 动画不自己开定时器：frame::poll 每次合成之前用当前时间调用 step，把所有动画推进到这一刻再合成，
+// 所以动画的帧率跟着 GUI 的目标帧率走。同一个窗口的同一种属性只有一个动画，新的换掉旧的；
+// 动画可以延迟开始，结束后可以顺便关掉窗口（淡出的通知）。窗口被关掉后它的动画也就没了
+
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use spin::Mutex;
+
+use crate::gui::window::{WindowId, WINDOW_MANAGER};
+use crate::io::timer::uptime;
+
+/// 缓动曲线，把时间进度（0 到 1）换算成数值进度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Easing {
+    Linear,
+    /// 慢慢加速
+    EaseIn,
+    /// 慢慢减速，适合滑入
+    EaseOut,
+    /// 两头慢中间快
+    EaseInOut,
+}
+
+impl Easing {
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t * t,
+            Easing::EaseOut => {
+                let u = 1.0 - t;
+                1.0 - u * u * u
+            }
+            Easing::EaseInOut => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    let u = -2.0 * t + 2.0;
+                    1.0 - u * u * u / 2.0
+                }
+            }
+        }
+    }
+}
+
+/// 可以插值的属性值
+pub trait Animatable: Copy {
+    /// `progress` 为 0 时是 `from`，为 1 时是 `to`
+    fn lerp(from: Self, to: Self, progress: f32) -> Self;
+}
+
+impl Animatable for f32 {
+    fn lerp(from: f32, to: f32, progress: f32) -> f32 {
+        from + (to - from) * progress
+    }
+}
+
+// 坐标四舍五入到整数像素
+impl Animatable for usize {
+    fn lerp(from: usize, to: usize, progress: f32) -> usize {
+        let value = from as f32 + (to as f32 - from as f32) * progress;
+        (value + 0.5).max(0.0) as usize
+    }
+}
+
+impl Animatable for (usize, usize) {
+    fn lerp(from: (usize, usize), to: (usize, usize), progress: f32) -> (usize, usize) {
+        (usize::lerp(from.0, to.0, progress), usize::lerp(from.1, to.1, progress))
+    }
+}
+
+/// 被动画的属性和它的起点、终点
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Property {
+    /// 窗口左上角的位置（行，列）
+    Position((usize, usize), (usize, usize)),
+    /// 窗口的不透明度
+    Opacity(f32, f32),
+}
+
+impl Property {
+    fn same_kind(&self, other: &Property) -> bool {
+        matches!((self, other), (Property::Position(..), Property::Position(..)) | (Property::Opacity(..), Property::Opacity(..)))
+    }
+
+    /// 数值进度为 `progress` 时的值
+    pub fn at(&self, progress: f32) -> Property {
+        match *self {
+            Property::Position(from, to) => {
+                let value = Animatable::lerp(from, to, progress);
+                Property::Position(value, value)
+            }
+            Property::Opacity(from, to) => {
+                let value = f32::lerp(from, to, progress);
+                Property::Opacity(value, value)
+            }
+        }
+    }
+}
+
+/// 一个窗口动画，用 start 开始
+#[derive(Debug, Clone, Copy)]
+pub struct Animation {
+    window: WindowId,
+    property: Property,
+    easing: Easing,
+    duration: Duration,
+    delay: Duration,
+    close_when_done: bool,
+}
+
+impl Animation {
+    /// 把窗口从 `from` 移动到 `to`
+    pub fn position(window: WindowId, from: (usize, usize), to: (usize, usize), duration: Duration) -> Self {
+        Self::new(window, Property::Position(from, to), duration)
+    }
+
+    /// 把窗口的不透明度从 `from` 变到 `to`
+    pub fn opacity(window: WindowId, from: f32, to: f32, duration: Duration) -> Self {
+        Self::new(window, Property::Opacity(from, to), duration)
+    }
+
+    fn new(window: WindowId, property: Property, duration: Duration) -> Self {
+        Animation { window, property, easing: Easing::EaseOut, duration, delay: Duration::ZERO, close_when_done: false }
+    }
+
+    /// 缓动曲线，默认是 EaseOut
+    pub fn easing(mut self, easing: Easing) -> Self {
+        self.easing = easing;
+        self
+    }
+
+    /// 过一段时间再开始，开始之前窗口停在起点
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// 结束后关掉窗口
+    pub fn close_when_done(mut self) -> Self {
+        self.close_when_done = true;
+        self
+    }
+
+    /// 开始以后经过 `elapsed` 时的值，以及动画是否已经结束
+    pub fn sample(&self, elapsed: Duration) -> (Property, bool) {
+        let running = elapsed.saturating_sub(self.delay);
+        if running >= self.duration {
+            return (self.property.at(1.0), true);
+        }
+        let t = running.as_nanos() as f32 / self.duration.as_nanos() as f32;
+        (self.property.at(self.easing.apply(t)), false)
+    }
+}
+
+struct Running {
+    animation: Animation,
+    started: Duration,
+}
+
+static ANIMATIONS: Mutex<Vec<Running>> = Mutex::new(Vec::new());
+
+/// 开始动画，马上把窗口放到起点
+///
+/// 会锁住 WINDOW_MANAGER，不能在持有它的时候调用
+pub fn start(animation: Animation) {
+    let property = animation.property;
+    {
+        let mut animations = ANIMATIONS.lock();
+        animations.retain(|r| r.animation.window != animation.window || !r.animation.property.same_kind(&property));
+        animations.push(Running { animation, started: uptime() });
+    }
+    apply(animation.window, property.at(0.0));
+}
+
+/// 停止窗口的所有动画，窗口停在当前的状态
+pub fn cancel(window: WindowId) {
+    ANIMATIONS.lock().retain(|r| r.animation.window != window);
+}
+
+/// 窗口有没有正在进行（包括还在延迟）的动画
+pub fn is_animating(window: WindowId) -> bool {
+    ANIMATIONS.lock().iter().any(|r| r.animation.window == window)
+}
+
+/// 把窗口从 `from` 滑到它现在的位置
+pub fn slide_in(window: WindowId, from: (usize, usize), duration: Duration) {
+    let Some(to) = WINDOW_MANAGER.lock().window(window).map(|w| w.position()) else { return };
+    start(Animation::position(window, from, to, duration));
+}
+
+/// 过 `delay` 之后用 `duration` 把窗口淡出，然后关掉它
+pub fn fade_out(window: WindowId, delay: Duration, duration: Duration) {
+    let Some(from) = WINDOW_MANAGER.lock().opacity(window) else { return };
+    start(Animation::opacity(window, from, 0.0, duration).easing(Easing::EaseIn).delay(delay).close_when_done());
+}
+
+fn apply(window: WindowId, value: Property) {
+    let mut manager = WINDOW_MANAGER.lock();
+    match value {
+        Property::Position((x, y), _) => {
+            if manager.window(window).is_some_and(|w| w.position() != (x, y)) {
+                manager.move_to(window, x, y);
+            }
+        }
+        Property::Opacity(opacity, _) => {
+            if manager.opacity(window).is_some_and(|current| current != opacity) {
+                manager.set_opacity(window, opacity);
+            }
+        }
+    }
+}
+
+/// 把所有动画推进到 `now`
+///
+/// 由 frame::poll 在合成之前调用
+pub fn step(now: Duration) {
+    let mut updates = Vec::new();
+    let mut finished = Vec::new();
+    {
+        let Some(mut animations) = ANIMATIONS.try_lock() else { return };
+        if animations.is_empty() {
+            return;
+        }
+        animations.retain(|running| {
+            let elapsed = now.saturating_sub(running.started);
+            if elapsed < running.animation.delay {
+                return true;
+            }
+            let (value, done) = running.animation.sample(elapsed);
+            updates.push((running.animation.window, value));
+            if done && running.animation.close_when_done {
+                finished.push(running.animation.window);
+            }
+            !done
+        });
+    }
+    for (window, value) in updates {
+        apply(window, value);
+    }
+    for window in finished {
+        WINDOW_MANAGER.lock().close(window);
+    }
+}
@@ -0,0 +1,44 @@
+// 剪贴板
+// 控件和终端窗口共用的一段文字。Ctrl 加字母由键盘解码成 ASCII 控制字符，和终端里一样：
+// Ctrl+C 是 0x03，Ctrl+X 是 0x18，Ctrl+V 是 0x16。控件的事件循环把它们交给拥有焦点的控件，
+// 终端窗口没有控件，由 widgets::dispatch_key 复制终端里选中的文字，粘贴由 shell 读到 Ctrl+V 时完成。
+// 只在主循环里使用，不会在中断里访问
+
+use alloc::string::String;
+
+use spin::Mutex;
+
+/// 复制
+pub const COPY: char = '\x03';
+/// 剪切
+pub const CUT: char = '\x18';
+/// 粘贴
+pub const PASTE: char = '\x16';
+
+// 更长的文字被截断
+const MAX_LEN: usize = 64 * 1024;
+
+static CLIPBOARD: Mutex<String> = Mutex::new(String::new());
+
+/// 换成 `text`，超过 MAX_LEN 字节的部分被丢掉
+pub fn set_text(text: &str) {
+    let mut len = text.len().min(MAX_LEN);
+    while !text.is_char_boundary(len) {
+        len -= 1;
+    }
+    let mut clipboard = CLIPBOARD.lock();
+    clipboard.clear();
+    clipboard.push_str(&text[..len]);
+}
+
+pub fn get_text() -> String {
+    CLIPBOARD.lock().clone()
+}
+
+pub fn is_empty() -> bool {
+    CLIPBOARD.lock().is_empty()
+}
+
+pub fn clear() {
+    *CLIPBOARD.lock() = String::new();
+}
@@ -1,8 +1,66 @@
-use crate::graphic::{GL};
+use lazy_static::lazy_static;
+use spin::{Mutex, RwLock};
 
-const CURSOR: &[u8] = include_bytes!("../../assets/cursor.bmp");
+use crate::assets;
+use crate::graphic::sprite::Sprite;
+use crate::graphic::layer::{self, CURSOR};
+use crate::graphic;
+use crate::gui::frame;
+
+const CURSOR: &str = "icons/cursor.bmp";
+// 光标图片的大小
+const CURSOR_SIZE: usize = 32;
+
+// 光标左上角的位置（行，列）
+static POSITION: Mutex<(usize, usize)> = Mutex::new((0, 0));
+
+lazy_static! {
+    // 光标每次移动都要重画，只解码一次，换主题时由 reload 重新取
+    static ref CURSOR_SPRITE: RwLock<Sprite> = RwLock::new(assets::sprite(CURSOR).expect("Failed to decode cursor"));
+}
 
 pub fn display_cursor_first_time(x: usize, y: usize) {
-    let pos = GL.read().len() - 1;
-    GL.read()[pos].lock().display_img_32rgba(x, y, CURSOR);
+    layer::with_layer(CURSOR, |layer| CURSOR_SPRITE.read().draw(layer, x, y));
+    *POSITION.lock() = (x, y);
+}
+
+/// 当前光标位置
+pub fn position() -> (usize, usize) {
+    *POSITION.lock()
+}
+
+/// 按鼠标的位移移动光标并重绘，返回新的位置
+///
+/// `dx` 向右为正，`dy` 向上为正，光标不会移出屏幕
+pub fn move_by(dx: i32, dy: i32) -> (usize, usize) {
+    let (old_x, old_y) = position();
+    let x = (old_x as i32 - dy).clamp(0, graphic::height() as i32 - 1) as usize;
+    let y = (old_y as i32 + dx).clamp(0, graphic::width() as i32 - 1) as usize;
+    if (x, y) == (old_x, old_y) {
+        return (x, y);
+    }
+    layer::with_layer(CURSOR, |layer| {
+        layer.clear_rect(old_x, old_y, CURSOR_SIZE, CURSOR_SIZE);
+        CURSOR_SPRITE.read().draw(layer, x, y);
+    });
+    *POSITION.lock() = (x, y);
+    redraw(old_x, old_y);
+    redraw(x, y);
+    (x, y)
+}
+
+/// 重新取光标图片并画在原来的位置，取不到时保留原来的
+pub fn reload() {
+    let Some(sprite) = assets::sprite(CURSOR) else { return };
+    *CURSOR_SPRITE.write() = sprite;
+    let (x, y) = position();
+    layer::with_layer(CURSOR, |layer| {
+        layer.clear_rect(x, y, CURSOR_SIZE, CURSOR_SIZE);
+        CURSOR_SPRITE.read().draw(layer, x, y);
+    });
+    redraw(x, y);
+}
+
+fn redraw(x: usize, y: usize) {
+    frame::request(x, y, (x + CURSOR_SIZE).min(graphic::height()), (y + CURSOR_SIZE).min(graphic::width()));
 }
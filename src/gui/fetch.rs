@@ -0,0 +1,40 @@
+// 下载演示
+// 用 HTTP 下载一个地址，在窗口里显示状态行和按窗口宽度折行的文本内容，窗口用右上角的按钮关闭
+
+use alloc::boxed::Box;
+use alloc::collections::TryReserveError;
+use alloc::format;
+
+use crate::graphic;
+use crate::gui::widgets::{self, Bounds, Label};
+use crate::gui::window::{BORDER, TITLE_BAR_HEIGHT, WINDOW_MANAGER};
+use crate::io::format::Size;
+use crate::net::http::Response;
+
+const WIDTH: usize = 480;
+const HEIGHT: usize = 320;
+const MARGIN: usize = 8;
+const STATUS_HEIGHT: usize = 20;
+// 折行和绘制的开销随文本长度增长，只显示开头这么多字节
+const MAX_TEXT: usize = 8 * 1024;
+
+/// 在屏幕中间打开一个窗口显示 `url` 的响应
+pub fn show(url: &str, response: &Response) -> Result<(), TryReserveError> {
+    let x = graphic::height().saturating_sub(HEIGHT) / 2;
+    let y = graphic::width().saturating_sub(WIDTH) / 2;
+    let id = WINDOW_MANAGER.lock().create(url, x, y, WIDTH, HEIGHT)?;
+    let (width, height) = (WIDTH - 2 * BORDER - 2 * MARGIN, HEIGHT - TITLE_BAR_HEIGHT - BORDER - 2 * MARGIN);
+
+    let status = format!("{} {}, {}, {}", response.status, response.reason,
+                         response.header("content-type").unwrap_or("unknown type"), Size(response.body.len() as u64));
+    widgets::add(id, Box::new(Label::new(Bounds::new(MARGIN, MARGIN, width, STATUS_HEIGHT), &status)));
+
+    let text = response.text();
+    let mut end = text.len().min(MAX_TEXT);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    let bounds = Bounds::new(MARGIN + STATUS_HEIGHT, MARGIN, width, height - STATUS_HEIGHT);
+    widgets::add(id, Box::new(Label::new(bounds, &text[..end].replace("\r\n", "\n"))));
+    Ok(())
+}
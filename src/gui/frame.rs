@@ -0,0 +1,185 @@
+// 帧率控制
+// GUI 的改动不马上合成：request 记下要重画的区域，图层自己也记着改动过的区域，
+// 主循环空闲时调用 poll，到了下一帧的时间才把这些区域合并成一块合成一次，什么都没变时跳过这一帧。
+// 帧的时间按目标帧率对齐在时钟上，像垂直同步一样间隔均匀；目标帧率为 0 时不限速，request 直接合成。
+// 窗口动画、通知和屏幕保护程序在每一帧合成之前推进到这一帧的时间。每秒统计一次实际合成的帧数，打开计数器后显示在状态栏上
+
+use core::sync::atomic::Ordering;
+use core::time::Duration;
+
+use crate::graphic::{Region, GD};
+use crate::gui::{animation, screensaver};
+use crate::gui::status_bar::show_fps;
+use crate::gui::toast;
+use crate::io::timer::uptime;
+use crate::sync::IrqSafeMutex;
+
+/// 默认的目标帧率
+pub const DEFAULT_FPS: u32 = 60;
+// 目标帧率的上限，再高时钟中断的精度跟不上
+pub const MAX_FPS: u32 = 250;
+
+struct Governor {
+    // 两帧之间的间隔，None 表示不限速
+    period: Option<Duration>,
+    // 下一帧的时间
+    next: Duration,
+    // 等着下一帧合成的区域
+    pending: Option<Region>,
+    // 在状态栏显示帧率
+    overlay: bool,
+    frames: u64,
+    skipped: u64,
+    // 这一秒开始的时间和到现在合成的帧数
+    second_start: Duration,
+    second_frames: u32,
+    // 上一秒合成的帧数
+    fps: u32,
+}
+
+static GOVERNOR: IrqSafeMutex<Governor> = IrqSafeMutex::named("frame governor", Governor {
+    period: Some(period(DEFAULT_FPS)),
+    next: Duration::ZERO,
+    pending: None,
+    overlay: false,
+    frames: 0,
+    skipped: 0,
+    second_start: Duration::ZERO,
+    second_frames: 0,
+    fps: 0,
+});
+
+/// 帧率的统计
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// 目标帧率，0 表示不限速
+    pub target: u32,
+    /// 上一秒合成的帧数
+    pub fps: u32,
+    /// 合成过的帧数
+    pub frames: u64,
+    /// 因为什么都没变而跳过的帧数
+    pub skipped: u64,
+}
+
+const fn period(fps: u32) -> Duration {
+    Duration::from_nanos(1_000_000_000 / fps as u64)
+}
+
+/// 请求重画一块区域（行 sx..ex，列 sy..ey），在下一帧合成
+pub fn request(sx: usize, sy: usize, ex: usize, ey: usize) {
+    let region = Region::new(sx, sy, ex, ey);
+    if region.is_empty() {
+        return;
+    }
+    // GUI 初始化之前没有人调用 poll，也直接合成
+    let immediate = {
+        let mut governor = GOVERNOR.lock();
+        let immediate = governor.period.is_none() || !super::READY.load(Ordering::Acquire);
+        if !immediate {
+            governor.pending = Some(governor.pending.map_or(region, |p| p.union(region)));
+        }
+        immediate
+    };
+    if immediate {
+        GD.lock().render(region.sx, region.sy, region.ex, region.ey);
+        let mut governor = GOVERNOR.lock();
+        governor.frames += 1;
+        governor.second_frames += 1;
+    }
+}
+
+/// 到了下一帧的时间就合成积累下来的改动，并更新帧率的统计
+///
+/// 由 gui::poll 在主循环空闲时调用
+pub fn poll() {
+    let now = uptime();
+    // None 表示不限速，Some(true) 表示到了下一帧的时间
+    let due = {
+        let mut governor = GOVERNOR.lock();
+        governor.period.map(|period| {
+            let due = now >= governor.next;
+            if due {
+                // 对齐到下一个整数倍的时间点，落后很多时不补帧
+                let periods = now.as_nanos() / period.as_nanos() + 1;
+                governor.next = Duration::from_nanos((periods * period.as_nanos()) as u64);
+            }
+            due
+        })
+    };
+    // 不限速时每次都推进动画，动画改动的区域由 request 直接合成
+    if due != Some(false) {
+        animation::step(now);
+        toast::step(now);
+        screensaver::step(now);
+    }
+    if due == Some(true) {
+        // 包括动画这一步改动的区域
+        let pending = GOVERNOR.lock().pending.take();
+        let rendered = GD.lock().render_dirty(pending);
+        let mut governor = GOVERNOR.lock();
+        if rendered {
+            governor.frames += 1;
+            governor.second_frames += 1;
+        } else {
+            governor.skipped += 1;
+        }
+    }
+    let overlay = {
+        let mut governor = GOVERNOR.lock();
+        if now.saturating_sub(governor.second_start) < Duration::from_secs(1) {
+            return;
+        }
+        governor.fps = governor.second_frames;
+        governor.second_frames = 0;
+        governor.second_start = now;
+        governor.overlay.then_some(governor.fps)
+    };
+    if let Some(fps) = overlay {
+        show_fps(Some(fps));
+    }
+}
+
+/// 马上合成等着下一帧的改动，读屏幕内容之前调用
+pub fn flush() {
+    let pending = GOVERNOR.lock().pending.take();
+    if GD.lock().render_dirty(pending) {
+        let mut governor = GOVERNOR.lock();
+        governor.frames += 1;
+        governor.second_frames += 1;
+    }
+}
+
+/// 设置目标帧率，0 表示不限速
+pub fn set_fps(fps: u32) {
+    let pending = {
+        let mut governor = GOVERNOR.lock();
+        governor.period = (fps > 0).then(|| period(fps.min(MAX_FPS)));
+        governor.next = Duration::ZERO;
+        governor.pending.take()
+    };
+    // 改成不限速后没有人再合成等着的区域
+    if pending.is_some() {
+        GD.lock().render_dirty(pending);
+    }
+}
+
+/// 显示或者隐藏状态栏上的帧率
+pub fn set_overlay(show: bool) {
+    let fps = {
+        let mut governor = GOVERNOR.lock();
+        governor.overlay = show;
+        governor.fps
+    };
+    show_fps(show.then_some(fps));
+}
+
+pub fn stats() -> Stats {
+    let governor = GOVERNOR.lock();
+    Stats {
+        target: governor.period.map_or(0, |period| (1_000_000_000 / period.as_nanos()) as u32),
+        fps: governor.fps,
+        frames: governor.frames,
+        skipped: governor.skipped,
+    }
+}
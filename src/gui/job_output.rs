@@ -0,0 +1,38 @@
+// 后台任务输出窗口
+// 显示一个离屏控制台（io::console）里捕获的输出，每行一个标签，放不下时只显示最后几行；
+// 打开时取一次快照，任务之后的输出要再打开一次才看得到，窗口用右上角的按钮关闭
+
+use alloc::boxed::Box;
+use alloc::collections::TryReserveError;
+use alloc::format;
+
+use crate::graphic;
+use crate::gui::widgets::{self, Bounds, Label};
+use crate::gui::window::{BORDER, TITLE_BAR_HEIGHT, WINDOW_MANAGER};
+use crate::i18n::{self, Msg};
+use crate::io::console::{self, ConsoleId};
+
+const WIDTH: usize = 560;
+const HEIGHT: usize = 360;
+const MARGIN: usize = 8;
+const LINE_HEIGHT: usize = 20;
+
+/// 在屏幕中间打开窗口显示离屏控制台 `id` 的输出，没有这个控制台时返回 Ok(false)
+pub fn show(id: ConsoleId) -> Result<bool, TryReserveError> {
+    let Some(name) = console::list().into_iter().find(|(console, _)| *console == id).map(|(_, name)| name) else {
+        return Ok(false);
+    };
+    let lines = console::lines(id).unwrap_or_default();
+    let x = graphic::height().saturating_sub(HEIGHT) / 2;
+    let y = graphic::width().saturating_sub(WIDTH) / 2;
+    let title = format!("{}", i18n::fill(Msg::JobOutput, &[&id, &name]));
+    let window = WINDOW_MANAGER.lock().create(&title, x, y, WIDTH, HEIGHT)?;
+    let width = WIDTH - 2 * BORDER - 2 * MARGIN;
+    let rows = (HEIGHT - TITLE_BAR_HEIGHT - BORDER - 2 * MARGIN) / LINE_HEIGHT;
+    let skip = lines.len().saturating_sub(rows);
+    for (i, line) in lines[skip..].iter().enumerate() {
+        let bounds = Bounds::new(MARGIN + i * LINE_HEIGHT, MARGIN, width, LINE_HEIGHT);
+        widgets::add(window, Box::new(Label::new(bounds, line)));
+    }
+    Ok(true)
+}
@@ -0,0 +1,96 @@
+// 日志窗口
+// 显示内核日志环形缓冲区里最新的记录，一条一行，按级别着色，超出窗口宽度的部分不画。
+// 有新的日志或窗口大小变了时由 gui::poll 重画；只开一个，再次打开时把它提到最上面，窗口用右上角的按钮关闭
+
+use alloc::collections::TryReserveError;
+use alloc::format;
+
+use embedded_graphics::pixelcolor::Rgb888;
+use log::{Level, LevelFilter};
+use spin::Mutex;
+
+use crate::graphic;
+use crate::graphic::font::{line_pitch, FontId};
+use crate::gui::window::{WindowId, WindowManager, WINDOW_MANAGER};
+use crate::i18n::{self, Msg};
+use crate::logger::ring;
+use crate::rgb888;
+
+const WIDTH: usize = 640;
+const HEIGHT: usize = 360;
+const PADDING: usize = 4;
+const TEXT_SIZE: f32 = 12.0;
+const BACKGROUND_COLOR: Rgb888 = rgb888!(0x1E1E1Eu32);
+
+struct Viewer {
+    window: WindowId,
+    // 画过的最后一条日志之后的序号
+    shown: u64,
+    // 上一次绘制时客户区的宽和高
+    size: (usize, usize),
+}
+
+static VIEWER: Mutex<Option<Viewer>> = Mutex::new(None);
+
+fn color(level: Level) -> Rgb888 {
+    match level {
+        Level::Error => rgb888!(0xEF5350u32),
+        Level::Warn => rgb888!(0xFFCA28u32),
+        Level::Info => rgb888!(0xDDDDDDu32),
+        Level::Debug => rgb888!(0x90A4AEu32),
+        Level::Trace => rgb888!(0x607D8Bu32),
+    }
+}
+
+/// 打开日志窗口，已经打开时把它提到最上面
+pub fn show() -> Result<(), TryReserveError> {
+    let mut viewer = VIEWER.lock();
+    let mut manager = WINDOW_MANAGER.lock();
+    if let Some(window) = viewer.as_ref().map(|v| v.window).filter(|&w| manager.window(w).is_some()) {
+        manager.raise(window);
+        return Ok(());
+    }
+    let x = graphic::height().saturating_sub(HEIGHT) / 2;
+    let y = graphic::width().saturating_sub(WIDTH) / 2;
+    let window = manager.create(i18n::tr(Msg::KernelLog), x, y, WIDTH, HEIGHT)?;
+    let mut new = Viewer { window, shown: 0, size: (0, 0) };
+    new.redraw(&mut manager);
+    *viewer = Some(new);
+    Ok(())
+}
+
+/// 有新的日志时重画，窗口关掉后不再更新
+///
+/// 由 gui::poll 调用
+pub fn poll() {
+    let mut viewer = VIEWER.lock();
+    let Some(current) = viewer.as_mut() else { return };
+    let mut manager = WINDOW_MANAGER.lock();
+    let Some(size) = manager.window(current.window).map(|w| w.client_size()) else {
+        *viewer = None;
+        return;
+    };
+    if current.shown != ring::next_sequence() || current.size != size {
+        current.redraw(&mut manager);
+    }
+}
+
+impl Viewer {
+    fn redraw(&mut self, manager: &mut WindowManager) {
+        let Some(window) = manager.window_mut(self.window) else { return };
+        let (width, height) = window.client_size();
+        let line_height = line_pitch(TEXT_SIZE);
+        let rows = height.saturating_sub(2 * PADDING) / line_height;
+        let entries = ring::entries(0, LevelFilter::Trace);
+        self.shown = entries.last().map_or(ring::next_sequence(), |entry| entry.sequence + 1);
+        self.size = (width, height);
+
+        window.fill(BACKGROUND_COLOR);
+        for (row, entry) in entries[entries.len().saturating_sub(rows)..].iter().enumerate() {
+            let line = format!("{}", entry);
+            window.draw_text_sized(PADDING + row * line_height, PADDING, width.saturating_sub(2 * PADDING), &line,
+                                   FontId::MONOSPACE, TEXT_SIZE, color(entry.level));
+        }
+        manager.redraw(self.window);
+    }
+}
@@ -0,0 +1,32 @@
+// 内存窗口
+// 打开时统计一次 memory::report，每行一个标签；数字不会自动刷新，要看新的数字就再打开一个
+
+use alloc::boxed::Box;
+use alloc::collections::TryReserveError;
+use alloc::format;
+
+use crate::graphic;
+use crate::gui::widgets::{self, Bounds, Label};
+use crate::gui::window::{BORDER, TITLE_BAR_HEIGHT, WINDOW_MANAGER};
+use crate::i18n::{self, Msg};
+use crate::memory::report;
+
+const WIDTH: usize = 560;
+const MARGIN: usize = 8;
+const LINE_HEIGHT: usize = 20;
+
+/// 在屏幕中间打开内存窗口
+pub fn show() -> Result<(), TryReserveError> {
+    let text = format!("{}", report::report());
+    let lines = text.lines().count();
+    let height = TITLE_BAR_HEIGHT + 2 * BORDER + 2 * MARGIN + lines * LINE_HEIGHT;
+    let x = graphic::height().saturating_sub(height) / 2;
+    let y = graphic::width().saturating_sub(WIDTH) / 2;
+    let id = WINDOW_MANAGER.lock().create(i18n::tr(Msg::Memory), x, y, WIDTH, height)?;
+    let width = WIDTH - 2 * BORDER - 2 * MARGIN;
+    for (i, line) in text.lines().enumerate() {
+        let bounds = Bounds::new(MARGIN + i * LINE_HEIGHT, MARGIN, width, LINE_HEIGHT);
+        widgets::add(id, Box::new(Label::new(bounds, line)));
+    }
+    Ok(())
+}
@@ -1,33 +1,219 @@
-use crate::graphic::{GD, GL, HEIGHT, WIDTH};
+use alloc::format;
+use alloc::string::String;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use spin::Once;
+
+use crate::allocator::shrinker;
+use crate::assets;
+use crate::drivers::hotplug::{self, DeviceEvent};
+use crate::graphic::vbe::ModeError;
+use crate::graphic::layer::{self, BACKGROUND, CONSOLE};
+use crate::graphic::{self, GD};
 use crate::gui::cursor::display_cursor_first_time;
-use crate::gui::status_bar::show_status_bar;
-use crate::io::qemu::qemu_print;
+use crate::gui::status_bar::{show_network, show_notice, show_status_bar};
+use crate::gui::window::WINDOW_MANAGER;
+use crate::io::input::{self, Filter, InputEvent, MouseButton, Subscription};
+use crate::io::replay;
+use crate::io::theme::{self, ThemeError};
+use crate::net::{self, Status};
+use crate::trace::latency::{self, Source};
 
+pub mod about;
+pub mod animation;
+pub mod clipboard;
+pub mod fetch;
+pub mod job_output;
+pub mod log_viewer;
+pub mod meminfo;
+pub mod frame;
+pub mod reminder;
+pub mod screensaver;
 pub mod status_bar;
+pub mod sysmon;
+pub mod terminal;
+pub mod tiling;
+pub mod toast;
+pub mod widgets;
+pub mod window;
 mod cursor;
 
-/// 图层规则（暂定）
-///
-/// -1：鼠标
-/// .....
-/// 1: Console
-/// 0: 背景
+pub use toast::{notify, Level};
+
+const WALLPAPER: &str = "wallpapers/default.bmp";
+// 配色和窗口外观，格式见 io::theme::parse
+const THEME_FILE: &str = "theme/theme.conf";
+// 鼠标事件队列的容量，满了之后新的事件被丢弃
+const POINTER_QUEUE: usize = 256;
+
+// init_gui 之后才处理鼠标事件
+static READY: AtomicBool = AtomicBool::new(false);
+// GUI 收鼠标事件的订阅，init_gui 时订阅
+static POINTER: Once<Subscription> = Once::new();
+// 左键现在是不是按着
+static LEFT: AtomicBool = AtomicBool::new(false);
+
+/// 显示背景和控制台图层，画出状态栏和鼠标
 ///
+/// 图层从下往上是背景、控制台、窗口（由 window::WindowManager 按需加入）、通知（见 toast）和鼠标，见 graphic::layer
 pub fn init_gui() {
-    qemu_print("D\n");
-    GL.read()[0].lock().enable = true;
-    GL.read()[1].lock().enable = true;
-    GL.read()[2].lock().enable = true;
-    qemu_print("E\n");
+    log::debug!("Enabling GUI layers");
+    layer::set_visible(BACKGROUND, true);
+    layer::set_visible(CONSOLE, true);
+    log::debug!("GUI layers enabled");
+    show_wallpaper();
+    if let Err(error) = apply_theme_file() {
+        log::warn!("{}: {}", THEME_FILE, error);
+    }
     show_status_bar();
-    display_cursor_first_time(HEIGHT / 2, WIDTH / 2);
+    display_cursor_first_time(graphic::height() / 2, graphic::width() / 2);
+    hotplug::subscribe(notify_device);
+    POINTER.call_once(|| input::subscribe_measured(Filter::POINTER, POINTER_QUEUE, Source::Mouse));
+
+    GD.lock().render(0, 0, graphic::height(), graphic::width());
+    READY.store(true, Ordering::Release);
+}
+
+/// 切换分辨率和色深，然后重画状态栏、光标和所有窗口
+pub fn set_mode(width: usize, height: usize, bpp: usize) -> Result<(), ModeError> {
+    graphic::set_mode(width, height, bpp)?;
+    show_status_bar();
+    let (x, y) = cursor::position();
+    display_cursor_first_time(x.min(height - 1), y.min(width - 1));
+    WINDOW_MANAGER.lock().redraw_all();
+    GD.lock().render(0, 0, height, width);
+    Ok(())
+}
+
+/// 内存不够、`what` 没能创建或者调整大小时在状态栏提示
+pub fn report_oom(what: &str) {
+    log::error!("Out of memory: {}", what);
+    show_notice("Out of memory");
+    notify("Out of memory", &format!("Could not allocate {}", what), Level::Error);
+}
 
-    GD.lock().render(0, 0, HEIGHT, WIDTH);
+// 热插拔的设备弹出通知
+fn notify_device(event: &DeviceEvent) {
+    let (title, device) = match event {
+        DeviceEvent::Added(device) => ("Device added", device),
+        DeviceEvent::Removed(device) => ("Device removed", device),
+    };
+    let message = format!("{:02x}:{:02x}.{} {}", device.bus, device.device, device.function, device.class_name());
+    notify(title, &message, Level::Info);
 }
 
-fn show_command_area() {
-    //GL.read()[0].lock().display_rect(0, 0, WIDTH, HEIGHT, rgb888!(0x006699u32));
+/// 处理积压的鼠标事件：移动光标，交给窗口管理器处理拖动和点击，再交给光标下的控件
+///
+/// 由主循环在空闲时调用，连续的、按键状态相同的移动合并成一次处理。顺便补画终端窗口和日志窗口、执行平铺的按键、更新低内存提示和网络图标，
+/// 把窗口露出来的部分交给控件重画，最后到了下一帧的时间就把这些改动合成到屏幕上
+pub fn poll() {
+    if !READY.load(Ordering::Acquire) {
+        return;
+    }
+    if shrinker::take_change() {
+        let low = shrinker::is_low_memory();
+        if low {
+            log::warn!("Low memory");
+        }
+        show_notice(if low { "Low memory" } else { "" });
+        if low {
+            notify("Low memory", "Caches are being trimmed; close some windows to free memory", Level::Warning);
+        }
+    }
+    if net::take_change() {
+        let status = net::status();
+        show_network(status);
+        if status == Status::Up {
+            notify("Network", "Network is up", Level::Info);
+        }
+    }
+    terminal::flush();
+    log_viewer::poll();
+    sysmon::poll();
+    graphic::text::flush();
+    tiling::poll();
+    WINDOW_MANAGER.lock().apply_theme();
+    if replay::take_finished() {
+        report_replay();
+    }
+    poll_mouse();
+    let damage = WINDOW_MANAGER.lock().take_damage();
+    widgets::dispatch_damage(&damage);
+    frame::poll();
+}
+
+fn poll_mouse() {
+    let Some(pointer) = POINTER.get() else { return };
+    // 攒下的移动量，按键变化之前先处理掉，拖动的起点才对
+    let mut moved = None;
+    while let Some(event) = pointer.try_next() {
+        match event.event {
+            InputEvent::MouseMove { dx, dy } => {
+                let (x, y) = moved.unwrap_or((0, 0));
+                moved = Some((x + dx as i32, y + dy as i32));
+            }
+            InputEvent::MouseButton { button: MouseButton::Left, pressed } => {
+                if let Some((dx, dy)) = moved.take() {
+                    move_pointer(dx, dy);
+                }
+                LEFT.store(pressed, Ordering::Relaxed);
+                move_pointer(0, 0);
+            }
+            _ => {}
+        }
+    }
+    if let Some((dx, dy)) = moved {
+        move_pointer(dx, dy);
+    }
+}
+
+fn move_pointer(dx: i32, dy: i32) {
+    let left = LEFT.load(Ordering::Relaxed);
+    let (x, y) = cursor::move_by(dx, dy);
+    WINDOW_MANAGER.lock().handle_mouse(x, y, left);
+    widgets::dispatch_mouse(x, y, left);
+    latency::handled(Source::Mouse);
+}
+
+// 输入回放结束后比较画面，结果输出到日志，测试脚本据此判断是否通过
+fn report_replay() {
+    match replay::verify() {
+        None => log::info!("Replay finished"),
+        Some(mismatched) if mismatched.is_empty() => log::info!("Replay finished: screen matches"),
+        Some(mismatched) => log::error!("Replay finished: blocks {:?} differ", mismatched),
+    }
+}
+
+/// 重新读取壁纸、图标和主题文件并重画，见 assets 的 theme/ 目录
+///
+/// 主题文件有错时保留现在的配色和窗口外观，返回错误
+pub fn reload_theme() -> Result<(), ThemeError> {
+    assets::reload();
+    show_wallpaper();
+    cursor::reload();
+    window::reload_icons();
+    let result = apply_theme_file();
+    // 配色没变也要按新的图标重画窗口
+    if result.is_err() {
+        theme::set(theme::get());
+    }
+    frame::request(0, 0, graphic::height(), graphic::width());
+    result
+}
+
+// 有主题文件时按它切换主题，没有时不变
+fn apply_theme_file() -> Result<(), ThemeError> {
+    let Some(data) = assets::data(THEME_FILE) else {
+        theme::set(theme::get());
+        return Ok(());
+    };
+    theme::set(theme::parse(&String::from_utf8_lossy(&data))?);
+    Ok(())
+}
 
-    let background_img = include_bytes!("../../assets/OS_background.bmp");
-    GL.read()[0].lock().display_img(0,0,background_img);
+fn show_wallpaper() {
+    // 壁纸画上去之后不再需要，解码出的图片留在资源缓存里，内存紧张时可以回收
+    if let Some(wallpaper) = assets::sprite(WALLPAPER) {
+        layer::with_layer(BACKGROUND, |layer| wallpaper.draw(layer, 0, 0));
+    }
 }
@@ -0,0 +1,51 @@
+// 提醒
+// 到时间时从屏幕右边滑出一个小窗口显示提醒的内容，同时在状态栏提示并响一声提示音。时间由 io::alarm 负责，
+// 窗口可以用右上角的按钮关闭，没关的话过一会儿自己淡出
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use core::time::Duration;
+
+use crate::drivers::rtc::DateTime;
+use crate::graphic;
+use crate::gui::animation;
+use crate::gui::status_bar::{show_notice, STATUS_BAR_HEIGHT};
+use crate::gui::widgets::{self, Bounds, Label};
+use crate::gui::window::{BORDER, TITLE_BAR_HEIGHT, WINDOW_MANAGER};
+use crate::io::alarm::{self, AlarmId};
+use crate::io::time::to_local;
+use crate::sound;
+
+const WIDTH: usize = 280;
+const HEIGHT: usize = 96;
+// 文字和客户区边缘的距离
+const MARGIN: usize = 8;
+// 滑入用的时间
+const SLIDE_IN: Duration = Duration::from_millis(300);
+// 显示多久之后开始淡出，以及淡出用的时间
+const LINGER: Duration = Duration::from_secs(15);
+const FADE_OUT: Duration = Duration::from_secs(1);
+
+/// 在 `at`（UTC）弹出写着 `text` 的提醒
+pub fn remind_at(at: DateTime, text: &str) -> AlarmId {
+    let text = String::from(text);
+    alarm::schedule_at(at, move || show(at, &text))
+}
+
+fn show(at: DateTime, text: &str) {
+    let local = to_local(at);
+    let title = format!("Reminder {:02}:{:02}:{:02}", local.hour, local.minute, local.second);
+    show_notice("Reminder");
+    sound::notify();
+    // 放在屏幕右上角，状态栏下面
+    let x = STATUS_BAR_HEIGHT + MARGIN;
+    let y = graphic::width().saturating_sub(WIDTH + MARGIN);
+    let Ok(id) = WINDOW_MANAGER.lock().create(&title, x, y, WIDTH, HEIGHT) else { return };
+    let (width, height) = (WIDTH - 2 * BORDER, HEIGHT - TITLE_BAR_HEIGHT - BORDER);
+    let bounds = Bounds::new(MARGIN, MARGIN, width - MARGIN * 2, height - MARGIN * 2);
+    widgets::add(id, Box::new(Label::new(bounds, text)));
+    // 从屏幕右边缘滑进来，move_to 会让窗口露出一截在屏幕上
+    animation::slide_in(id, (x, graphic::width()), SLIDE_IN);
+    animation::fade_out(id, LINGER, FADE_OUT);
+}
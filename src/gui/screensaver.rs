@@ -0,0 +1,239 @@
+// 屏幕保护和锁屏
+// 键盘鼠标闲置超过启动参数 screensaver= 给的秒数（默认 300 秒，0 不启动）之后，加一个盖住整个屏幕的黑色图层，
+// 上面有一块写着系统名和时间的牌子在屏幕里慢慢弹来弹去，z 和鼠标一样但后加入，连鼠标也盖住。
+// 运行期间独占输入（io::input 的 grab），按键不会落到 shell 或窗口里；没有设置 lock= 时任意输入都会退出，
+// 去掉图层，下面的图层原样露出来。设置了 lock= 时要先输入密码再按回车，输错了清空重来，
+// 输入时牌子停下来显示已经输入的位数，一段时间没有输入又开始移动。
+// 闲置时间按 io::input 最后一个事件的时间算，由 frame::poll 在每一帧合成之前调用 step；shell 的 lock 命令立刻启动
+
+use alloc::format;
+use alloc::string::String;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+use embedded_graphics::pixelcolor::Rgb888;
+use spin::{Mutex, Once};
+
+use crate::config;
+use crate::graphic::layer::{self, LayerHandle, Z_SCREENSAVER};
+use crate::graphic::text::{self, Align, Quality};
+use crate::graphic::{self, font, Writer};
+use crate::gui::frame;
+use crate::i18n::{self, Msg};
+use crate::io::input::{self, Filter, InputEvent, Subscription};
+use crate::io::time::get_raw_time;
+use crate::rgb888;
+use crate::version;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(300);
+// 牌子的大小和每一步移动的像素
+const BADGE_WIDTH: usize = 240;
+const BADGE_HEIGHT: usize = 64;
+const SPEED: usize = 2;
+// 牌子每隔这么久移动一步
+const STEP: Duration = Duration::from_millis(50);
+// 锁屏时最后一次按键之后，提示框停留这么久
+const PROMPT_TIME: Duration = Duration::from_secs(15);
+const MAX_PASSPHRASE: usize = 64;
+const QUEUE: usize = 64;
+const TITLE_SIZE: f32 = 20.0;
+const LINE_SIZE: f32 = 14.0;
+const BACKGROUND_COLOR: Rgb888 = rgb888!(0x000000u32);
+const BADGE_COLOR: Rgb888 = rgb888!(0x263238u32);
+const TEXT_COLOR: Rgb888 = rgb888!(0xB0BEC5u32);
+const ERROR_COLOR: Rgb888 = rgb888!(0xE57373u32);
+
+struct Saver {
+    layer: LayerHandle,
+    input: Subscription,
+    // 牌子左上角的位置（行、列）和每一步的方向
+    position: (usize, usize),
+    forward: (bool, bool),
+    next_step: Duration,
+    // 已经输入的密码，提示框显示到什么时候，上一次是否输错
+    typed: String,
+    prompt_until: Option<Duration>,
+    wrong: bool,
+}
+
+static SAVER: Mutex<Option<Saver>> = Mutex::new(None);
+// 闲置多久启动，秒；0 表示不启动
+static TIMEOUT: AtomicU64 = AtomicU64::new(DEFAULT_TIMEOUT.as_secs());
+static CONFIGURED: Once = Once::new();
+
+/// 闲置多久之后启动，None 表示不启动
+pub fn timeout() -> Option<Duration> {
+    configure();
+    Some(TIMEOUT.load(Ordering::Relaxed)).filter(|&secs| secs > 0).map(Duration::from_secs)
+}
+
+/// 设置闲置多久之后启动，按秒取整，None 或者不到一秒表示不启动
+pub fn set_timeout(timeout: Option<Duration>) {
+    configure();
+    TIMEOUT.store(timeout.map_or(0, |timeout| timeout.as_secs()), Ordering::Relaxed);
+}
+
+// 第一次用到时按启动参数设置
+fn configure() {
+    CONFIGURED.call_once(|| {
+        if let Some(timeout) = config::screensaver() {
+            TIMEOUT.store(timeout.as_secs(), Ordering::Relaxed);
+        }
+    });
+}
+
+/// 屏幕保护程序是不是正在运行
+pub fn is_active() -> bool {
+    SAVER.lock().is_some()
+}
+
+/// 立刻启动；没有图形界面或者内存不够时返回 false
+pub fn activate() -> bool {
+    let mut saver = SAVER.lock();
+    if saver.is_none() {
+        *saver = start(crate::io::timer::uptime());
+    }
+    saver.is_some()
+}
+
+/// 到了闲置时间就启动，运行时处理输入、移动牌子；由 frame::poll 在合成之前调用
+pub fn step(now: Duration) {
+    let mut guard = SAVER.lock();
+    let Some(saver) = guard.as_mut() else {
+        if timeout().is_some_and(|timeout| now.saturating_sub(input::last_event()) >= timeout) {
+            *guard = start(now);
+        }
+        return;
+    };
+    if handle_input(saver, now) {
+        stop(guard.take().unwrap());
+        return;
+    }
+    if saver.prompt_until.is_some_and(|until| now >= until) {
+        saver.prompt_until = None;
+        saver.typed.clear();
+        saver.wrong = false;
+    }
+    if now >= saver.next_step {
+        saver.next_step = now + STEP;
+        if saver.prompt_until.is_none() {
+            advance(saver);
+        }
+        draw(saver);
+    }
+}
+
+// 处理积压的输入，该退出时返回 true
+fn handle_input(saver: &mut Saver, now: Duration) -> bool {
+    let passphrase = config::lock_passphrase();
+    while let Some(event) = saver.input.try_next() {
+        let Some(passphrase) = passphrase else { return true };
+        saver.prompt_until = Some(now + PROMPT_TIME);
+        saver.next_step = now;
+        match event.event {
+            InputEvent::Key('\r' | '\n') => {
+                if saver.typed == passphrase {
+                    return true;
+                }
+                log::warn!("screensaver: wrong passphrase");
+                saver.typed.clear();
+                saver.wrong = true;
+            }
+            InputEvent::Key('\x08' | '\x7f') => {
+                saver.typed.pop();
+            }
+            InputEvent::Key(ch) if !ch.is_control() && saver.typed.len() < MAX_PASSPHRASE => {
+                saver.typed.push(ch);
+                saver.wrong = false;
+            }
+            _ => {}
+        }
+    }
+    false
+}
+
+// 加上盖住整个屏幕的图层，独占输入
+fn start(now: Duration) -> Option<Saver> {
+    if !super::READY.load(Ordering::Acquire) {
+        return None;
+    }
+    let (width, height) = (graphic::width(), graphic::height());
+    let Ok(mut writer) = Writer::try_new() else {
+        log::warn!("screensaver: out of memory");
+        return None;
+    };
+    writer.display_rect(0, 0, width, height, BACKGROUND_COLOR);
+    let layer = layer::add_layer(writer, Z_SCREENSAVER);
+    layer::set_visible(layer, true);
+    let input = input::subscribe(Filter::ALL, QUEUE);
+    input.grab(true);
+    log::debug!("screensaver: started");
+    let position = (height.saturating_sub(BADGE_HEIGHT) / 2, width.saturating_sub(BADGE_WIDTH) / 2);
+    let saver = Saver {
+        layer,
+        input,
+        position,
+        forward: (true, true),
+        next_step: now,
+        typed: String::new(),
+        prompt_until: None,
+        wrong: false,
+    };
+    frame::request(0, 0, height, width);
+    Some(saver)
+}
+
+// 去掉图层，释放订阅时放开输入
+fn stop(saver: Saver) {
+    layer::remove_layer(saver.layer);
+    frame::request(0, 0, graphic::height(), graphic::width());
+    log::debug!("screensaver: stopped");
+}
+
+// 牌子沿对角线移动一步，碰到屏幕边缘就反弹
+fn advance(saver: &mut Saver) {
+    let limits = (graphic::height().saturating_sub(BADGE_HEIGHT), graphic::width().saturating_sub(BADGE_WIDTH));
+    let (x, forward_x) = bounce(saver.position.0, saver.forward.0, limits.0);
+    let (y, forward_y) = bounce(saver.position.1, saver.forward.1, limits.1);
+    let old = saver.position;
+    saver.position = (x, y);
+    saver.forward = (forward_x, forward_y);
+    layer::with_layer(saver.layer, |layer| {
+        layer.display_rect(old.0, old.1, BADGE_WIDTH, BADGE_HEIGHT, BACKGROUND_COLOR);
+    });
+    frame::request(old.0, old.1, old.0 + BADGE_HEIGHT, old.1 + BADGE_WIDTH);
+}
+
+fn bounce(position: usize, forward: bool, limit: usize) -> (usize, bool) {
+    match forward {
+        true if position + SPEED >= limit => (limit, false),
+        true => (position + SPEED, true),
+        false if position <= SPEED => (0, true),
+        false => (position - SPEED, false),
+    }
+}
+
+// 画牌子：平时是系统名和时间，锁屏输入时是密码提示
+fn draw(saver: &Saver) {
+    let (x, y) = saver.position;
+    let (title, line, color) = if saver.prompt_until.is_some() {
+        let line = match saver.wrong {
+            true => String::from(i18n::tr(Msg::WrongPassphrase)),
+            false => format!("{}", i18n::fill(Msg::Passphrase, &[&"*".repeat(saver.typed.chars().count())])),
+        };
+        (i18n::tr(Msg::Locked), line, if saver.wrong { ERROR_COLOR } else { TEXT_COLOR })
+    } else {
+        let time = get_raw_time();
+        (version::NAME, format!("{:02}:{:02}", time.hour, time.minute), TEXT_COLOR)
+    };
+    layer::with_layer(saver.layer, |layer| {
+        layer.display_rect(x, y, BADGE_WIDTH, BADGE_HEIGHT, BADGE_COLOR);
+        // 牌子是实色的，文字和图层自己的像素混合就够了，不用读下面的图层
+        let font = font::gui_font();
+        text::draw_text_with(layer, x + 8, y, BADGE_WIDTH, 24, title, font, TITLE_SIZE, 24, Align::Center, TEXT_COLOR,
+                             Quality::Supersampled);
+        text::draw_text_with(layer, x + 36, y, BADGE_WIDTH, 20, &line, font, LINE_SIZE, 20, Align::Center, color,
+                             Quality::Blended);
+    });
+    frame::request(x, y, x + BADGE_HEIGHT, y + BADGE_WIDTH);
+}
@@ -1,20 +1,78 @@
 use alloc::format;
-use crate::graphic::{GL, WIDTH};
+use crate::graphic::layer::{self, BACKGROUND, CONSOLE};
+use crate::graphic::width;
+use crate::gui::frame;
 use crate::io::time::get_raw_time;
+use crate::net::{self, Status};
 use crate::rgb888;
 
+/// 状态栏的高度，窗口不会盖住它
+pub const STATUS_BAR_HEIGHT: usize = 18;
+// 状态栏右侧提示区的宽度
+const NOTICE_WIDTH: usize = 120;
+// 网络图标在提示区左边
+const NETWORK_ICON_WIDTH: usize = 16;
+// 帧率在网络图标左边
+const FPS_WIDTH: usize = 64;
+
 pub fn show_status_bar() {
-    GL.read()[0].lock().display_rect(0, 0, WIDTH, 18, rgb888!(0x37474Fu32));
+    layer::with_layer(BACKGROUND, |layer| layer.display_rect(0, 0, width(), STATUS_BAR_HEIGHT, rgb888!(0x37474Fu32)));
     let time = get_raw_time();
-    unsafe {
-        GL.read()[1].lock().display_font_string(
+    layer::with_layer(CONSOLE, |layer| unsafe {
+        layer.display_font_string(
             format!("{:02}:{:02}", time.hour, time.minute).as_str(),
-            0, (WIDTH / 2) - ((16 * 5) / 2), 16.0, 16, rgb888!(0xffffffu32),
+            0, (width() / 2) - ((16 * 5) / 2), 16.0, 16, rgb888!(0xffffffu32),
         );
-        GL.read()[1].lock().display_font_string(
+        layer.display_font_string(
             "Cinea OS v1.0",
             0, 2, 16.0, 16, rgb888!(0xffffffu32),
         );
-    };
+    });
+    show_network(net::status());
+}
+
+/// 画网络图标：有地址时是绿色的三格信号，有网卡没地址时是灰色的，没有网卡时不画
+pub fn show_network(status: Status) {
+    let y = width().saturating_sub(NOTICE_WIDTH + NETWORK_ICON_WIDTH);
+    layer::with_layer(CONSOLE, |layer| {
+        layer.clear_rect(0, y, NETWORK_ICON_WIDTH, STATUS_BAR_HEIGHT);
+        let color = match status {
+            Status::Up => Some(rgb888!(0x81C784u32)),
+            Status::Unconfigured => Some(rgb888!(0x90A4AEu32)),
+            Status::Down => None,
+        };
+        if let Some(color) = color {
+            // 底边对齐，高度递增
+            for (i, height) in [5, 9, 13].into_iter().enumerate() {
+                layer.display_rect(STATUS_BAR_HEIGHT - 3 - height, y + 2 + i * 4, 3, height, color);
+            }
+        }
+    });
+    frame::request(0, y, STATUS_BAR_HEIGHT, y + NETWORK_ICON_WIDTH);
 }
 
+/// 在状态栏右侧显示一条提示，传入空字符串清除
+pub fn show_notice(text: &str) {
+    let y = width().saturating_sub(NOTICE_WIDTH);
+    layer::with_layer(CONSOLE, |layer| {
+        layer.clear_rect(0, y, NOTICE_WIDTH, STATUS_BAR_HEIGHT);
+        unsafe {
+            layer.display_font_string(text, 0, y, 16.0, 16, rgb888!(0xFFB74Du32));
+        }
+    });
+    frame::request(0, y, STATUS_BAR_HEIGHT, width());
+}
+
+/// 显示每秒的帧数，None 时清除
+pub fn show_fps(fps: Option<u32>) {
+    let y = width().saturating_sub(NOTICE_WIDTH + NETWORK_ICON_WIDTH + FPS_WIDTH);
+    layer::with_layer(CONSOLE, |layer| {
+        layer.clear_rect(0, y, FPS_WIDTH, STATUS_BAR_HEIGHT);
+        if let Some(fps) = fps {
+            unsafe {
+                layer.display_font_string(&format!("{} fps", fps), 0, y, 16.0, 16, rgb888!(0xB0BEC5u32));
+            }
+        }
+    });
+    frame::request(0, y, STATUS_BAR_HEIGHT, y + FPS_WIDTH);
+}
@@ -0,0 +1,222 @@
+// 系统监视器
+// 堆和物理帧的用量条、每个 CPU 忙的比例和执行过的任务数、每秒的中断次数。时钟中断每秒置一次标志，
+// gui::poll 看到标志后重新采样并重画控件，控件画的时候从 MONITOR 里取最近两次采样算出变化量。
+// 内核没有调度器，任务是 smp::task 交给 AP 执行的闭包；CPU 忙的比例是没有停在 hlt 上的时间占比（见 smp::percpu::idle）
+
+use alloc::boxed::Box;
+use alloc::collections::TryReserveError;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+
+use embedded_graphics::pixelcolor::Rgb888;
+use spin::{Mutex, Once};
+
+use crate::allocator::{self, HeapStats};
+use crate::graphic;
+use crate::graphic::font::FontId;
+use crate::gui::widgets::{self, Bounds, Event, Label, ProgressBar, Widget};
+use crate::gui::window::{Window, WindowId, BORDER, CLIENT_COLOR, TITLE_BAR_HEIGHT, WINDOW_MANAGER};
+use crate::i18n::{self, Msg};
+use crate::interrupts::{deferred, irq};
+use crate::io::format::{Size, Thousands};
+use crate::io::timer::{self, uptime};
+use crate::memory::{self, report::FrameStats};
+use crate::rgb888;
+use crate::smp::{self, task};
+
+const WIDTH: usize = 440;
+const MARGIN: usize = 8;
+const BAR_HEIGHT: usize = 22;
+const GAP: usize = 6;
+const LINE_HEIGHT: usize = 20;
+// 中断只列出最忙的几个向量
+const IRQ_LINES: usize = 6;
+const REFRESH: Duration = Duration::from_secs(1);
+const TEXT_SIZE: f32 = 16.0;
+const TEXT_COLOR: Rgb888 = rgb888!(0x212121u32);
+
+/// 一个 CPU 的计数
+#[derive(Debug, Clone, Copy)]
+pub struct CpuSample {
+    pub id: usize,
+    pub bsp: bool,
+    pub idle: Duration,
+    pub tasks: usize,
+}
+
+/// 一次采样
+#[derive(Clone)]
+pub struct Sample {
+    pub at: Duration,
+    pub heap: HeapStats,
+    pub frames: Option<FrameStats>,
+    pub irqs: Vec<irq::VectorStats>,
+    pub cpus: Vec<CpuSample>,
+    /// 排队等 AP 执行的任务数
+    pub pending: usize,
+    /// 中断下半部还没执行的工作数
+    pub deferred: usize,
+}
+
+/// 采样一次
+pub fn sample() -> Sample {
+    Sample {
+        at: uptime(),
+        heap: allocator::heap_stats(),
+        frames: memory::with_frames(|frames| frames.stats()),
+        irqs: irq::stats().collect(),
+        cpus: smp::cpus().iter().map(|cpu| CpuSample { id: cpu.id, bsp: cpu.is_bsp(), idle: cpu.idle_time(), tasks: cpu.tasks() }).collect(),
+        pending: task::pending(),
+        deferred: deferred::stats().iter().map(|queue| queue.pending).sum(),
+    }
+}
+
+struct Monitor {
+    window: WindowId,
+    previous: Sample,
+    current: Sample,
+}
+
+impl Monitor {
+    fn elapsed(&self) -> Duration {
+        self.current.at.saturating_sub(self.previous.at)
+    }
+
+    fn heap(&self) -> (f32, String) {
+        let heap = &self.current.heap;
+        let used = heap.size - heap.free;
+        let ratio = used as f32 / heap.size.max(1) as f32;
+        (ratio, format!("Heap {} / {}, {} free regions", Size(used as u64), Size(heap.size as u64), heap.free_regions))
+    }
+
+    fn frames(&self) -> (f32, String) {
+        let Some(frames) = self.current.frames else { return (0.0, String::from("Frames unavailable")) };
+        let ratio = frames.allocated as f32 / frames.usable.max(1) as f32;
+        (ratio, format!("Frames {} / {}, {} recycled", Thousands(frames.allocated as u64),
+                        Thousands(frames.usable as u64), Thousands(frames.recycled as u64)))
+    }
+
+    fn cpu(&self, index: usize) -> (f32, String) {
+        let Some(current) = self.current.cpus.get(index) else { return (0.0, format!("CPU {} offline", index)) };
+        let previous = self.previous.cpus.get(index).map_or(current.idle, |cpu| cpu.idle);
+        let elapsed = self.elapsed().as_secs_f32();
+        // 第一次采样之前没有间隔，按空闲算
+        let busy = if elapsed > 0.0 { 1.0 - current.idle.saturating_sub(previous).as_secs_f32() / elapsed } else { 0.0 };
+        let busy = busy.clamp(0.0, 1.0);
+        let role = if current.bsp { "main loop" } else { "tasks" };
+        (busy, format!("CPU {} ({}) {:.0}% busy, {} tasks run", current.id, role, busy * 100.0, Thousands(current.tasks as u64)))
+    }
+
+    fn interrupts(&self) -> String {
+        let elapsed = self.elapsed().as_secs_f32();
+        let mut rates: Vec<(u64, &irq::VectorStats)> = self.current.irqs.iter().map(|stats| {
+            let before = self.previous.irqs.iter().find(|p| p.vector == stats.vector).map_or(stats.count, |p| p.count);
+            (stats.count - before.min(stats.count), stats)
+        }).collect();
+        rates.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.vector.cmp(&b.1.vector)));
+        let mut text = format!("Interrupts ({} tasks queued, {} deferred)\n", self.current.pending, self.current.deferred);
+        for (delta, stats) in rates.iter().take(IRQ_LINES) {
+            let rate = if elapsed > 0.0 { *delta as f32 / elapsed } else { 0.0 };
+            let name = stats.names().next().unwrap_or("?");
+            let _ = writeln!(text, "  {:>3} {:<12} {:>6.0}/s  {} total", stats.vector, name, rate, Thousands(stats.count));
+        }
+        text
+    }
+}
+
+static MONITOR: Mutex<Option<Monitor>> = Mutex::new(None);
+// 时钟中断置位，poll 清除
+static DUE: AtomicBool = AtomicBool::new(false);
+static TIMER: Once<()> = Once::new();
+
+// 按当前的采样画出来的数字
+fn with_monitor<R: Default>(f: impl FnOnce(&Monitor) -> R) -> R {
+    MONITOR.lock().as_ref().map(f).unwrap_or_default()
+}
+
+// 每次重画时从 source 取文字，画之前先擦掉原来的
+struct Text {
+    bounds: Bounds,
+    source: fn() -> String,
+}
+
+impl Widget for Text {
+    fn bounds(&self) -> Bounds {
+        self.bounds
+    }
+
+    fn draw(&self, window: &mut Window, _focused: bool) {
+        let Bounds { x, y, width, height } = self.bounds;
+        window.fill_rect(x, y, width, height, CLIENT_COLOR);
+        for (i, line) in (self.source)().lines().take(height / LINE_HEIGHT).enumerate() {
+            window.draw_text_sized(x + i * LINE_HEIGHT, y, width, line, FontId::MONOSPACE, TEXT_SIZE, TEXT_COLOR);
+        }
+    }
+
+    fn handle_event(&mut self, _event: &Event) -> bool {
+        false
+    }
+}
+
+fn tick() {
+    DUE.store(true, Ordering::Relaxed);
+}
+
+/// 打开系统监视器，已经打开时提到最上层
+pub fn show() -> Result<(), TryReserveError> {
+    if let Some(window) = MONITOR.lock().as_ref().map(|monitor| monitor.window) {
+        let mut manager = WINDOW_MANAGER.lock();
+        if manager.window(window).is_some() {
+            manager.raise(window);
+            return Ok(());
+        }
+    }
+    let cpus = smp::cpus().len();
+    let bars = 2 + cpus;
+    let text_height = (IRQ_LINES + 1) * LINE_HEIGHT;
+    let height = TITLE_BAR_HEIGHT + 2 * BORDER + 2 * MARGIN + bars * (BAR_HEIGHT + GAP) + LINE_HEIGHT + text_height;
+    let x = graphic::height().saturating_sub(height) / 2;
+    let y = graphic::width().saturating_sub(WIDTH) / 2;
+    let window = WINDOW_MANAGER.lock().create(i18n::tr(Msg::SystemMonitor), x, y, WIDTH, height)?;
+    let first = sample();
+    *MONITOR.lock() = Some(Monitor { window, previous: first.clone(), current: first });
+
+    let width = WIDTH - 2 * BORDER - 2 * MARGIN;
+    let row = |i: usize| Bounds::new(MARGIN + i * (BAR_HEIGHT + GAP), MARGIN, width, BAR_HEIGHT);
+    widgets::add(window, Box::new(ProgressBar::new(row(0), || with_monitor(Monitor::heap))));
+    widgets::add(window, Box::new(ProgressBar::new(row(1), || with_monitor(Monitor::frames))));
+    for index in 0..cpus {
+        widgets::add(window, Box::new(ProgressBar::new(row(2 + index), move || with_monitor(|m| m.cpu(index)))));
+    }
+    let text_x = MARGIN + bars * (BAR_HEIGHT + GAP);
+    let refresh = format!("{}", i18n::fill(Msg::Refreshed, &[&REFRESH.as_secs()]));
+    widgets::add(window, Box::new(Label::new(Bounds::new(text_x, MARGIN, width, LINE_HEIGHT), &refresh)));
+    let bounds = Bounds::new(text_x + LINE_HEIGHT, MARGIN, width, text_height);
+    widgets::add(window, Box::new(Text { bounds, source: || with_monitor(Monitor::interrupts) }));
+    TIMER.call_once(|| timer::register_periodic(REFRESH, tick));
+    Ok(())
+}
+
+/// 到了刷新的时间就重新采样并重画，窗口已经关掉时清理
+///
+/// 由 gui::poll 调用
+pub fn poll() {
+    if !DUE.swap(false, Ordering::Relaxed) {
+        return;
+    }
+    let Some(window) = MONITOR.lock().as_ref().map(|monitor| monitor.window) else { return };
+    if WINDOW_MANAGER.lock().window(window).is_none() {
+        widgets::remove_all(window);
+        *MONITOR.lock() = None;
+        return;
+    }
+    let sample = sample();
+    if let Some(monitor) = MONITOR.lock().as_mut() {
+        monitor.previous = core::mem::replace(&mut monitor.current, sample);
+    }
+    widgets::refresh(window);
+}
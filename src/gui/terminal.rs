@@ -0,0 +1,503 @@
+// 终端窗口
+// 打开后图形模式下的控制台输出（println! 和屏幕日志）都写到这个窗口里，用 TTF 字体逐行绘制。
+// 保留最近 SCROLLBACK_LINES 行，PageUp/PageDown 翻页。字号跟随控制台的缩放（text::zoom），改变时重新折行。窗口拥有焦点时键盘输入照常交给 shell，
+// 所以可以直接在窗口里输入命令。内存不够时会丢掉最早的滚动缓冲。
+// 输出中的 ANSI 颜色和清屏序列会生效；终端只在末尾追加输出，移动光标的序列被忽略。
+// 按住左键拖动选择文字，Ctrl+C 把选中的文字复制到剪贴板（见 gui::clipboard）
+
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::fmt::Write;
+use core::mem::size_of;
+use core::ops::Range;
+use core::sync::atomic::{AtomicBool, AtomicIsize, Ordering};
+
+use embedded_graphics::pixelcolor::Rgb888;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::allocator::shrinker::{self, Shrinker};
+use crate::graphic::font::{glyph, line_pitch, FontId};
+use crate::graphic::text;
+use crate::gui::clipboard;
+use crate::gui::window::{WindowId, WindowManager, WINDOW_MANAGER};
+use crate::i18n::{self, Msg};
+use crate::io::ansi::{self, Action, Parser};
+use crate::rgb888;
+
+const SCROLLBACK_LINES: usize = 2000;
+const PADDING: usize = 4;
+const TAB_SIZE: usize = 4;
+const BACKGROUND_COLOR: Rgb888 = rgb888!(0x1E1E1Eu32);
+const TEXT_COLOR: Rgb888 = rgb888!(0xDDDDDDu32);
+const SELECTION_COLOR: Rgb888 = rgb888!(0x264F78u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Style {
+    foreground: Rgb888,
+    background: Option<Rgb888>,
+}
+
+const DEFAULT_STYLE: Style = Style { foreground: TEXT_COLOR, background: None };
+
+#[derive(Default)]
+struct Line {
+    text: String,
+    // 样式变化的位置（text 中的字节偏移）和之后的样式，之前的部分是默认样式
+    styles: Vec<(usize, Style)>,
+    // 这一行是上一行放不下自动折过来的
+    continued: bool,
+}
+
+impl Line {
+    fn style(&self) -> Style {
+        self.styles.last().map_or(DEFAULT_STYLE, |(_, style)| *style)
+    }
+
+    // 大约占用的字节数
+    fn footprint(&self) -> usize {
+        self.text.capacity() + self.styles.capacity() * size_of::<(usize, Style)>()
+    }
+}
+
+// 选中的文字：开始拖动和当前的位置，都是（行号，行内的字节偏移）。行号从终端打开时算起，
+// 丢掉最早的行之后不变
+#[derive(Debug, Clone, Copy)]
+struct Selection {
+    anchor: (usize, usize),
+    head: (usize, usize),
+}
+
+impl Selection {
+    fn ordered(&self) -> ((usize, usize), (usize, usize)) {
+        (self.anchor.min(self.head), self.anchor.max(self.head))
+    }
+}
+
+struct Terminal {
+    window: WindowId,
+    lines: VecDeque<Line>,
+    // 最后一行已经占用的宽度
+    line_width: usize,
+    // 上一次绘制时客户区的宽和高
+    size: (usize, usize),
+    // 从底部向上滚动的行数
+    scroll: usize,
+    dirty: bool,
+    // 之后输出的字符使用的样式
+    style: Style,
+    parser: Parser,
+    font_size: f32,
+    // 已经丢掉的行数，加上 lines 中的下标就是行号
+    dropped: usize,
+    selection: Option<Selection>,
+    // 正按着左键拖动
+    selecting: bool,
+}
+
+static TERMINAL: Mutex<Option<Terminal>> = Mutex::new(None);
+// 按键记下的翻页请求，正数向上
+static PENDING_SCROLL: AtomicIsize = AtomicIsize::new(0);
+// 终端窗口已经打开，中断里不能锁 TERMINAL，用它判断
+static OPEN: AtomicBool = AtomicBool::new(false);
+
+impl Terminal {
+    fn advance(&self, ch: char) -> usize {
+        glyph(FontId::MONOSPACE, ch, self.font_size).advance as usize + 1
+    }
+
+    fn line_height(&self) -> usize {
+        line_pitch(self.font_size)
+    }
+
+    fn text_width(&self) -> usize {
+        self.size.0.saturating_sub(2 * PADDING)
+    }
+
+    fn rows(&self) -> usize {
+        self.size.1.saturating_sub(2 * PADDING) / self.line_height()
+    }
+
+    fn new_line(&mut self) {
+        if self.lines.len() == SCROLLBACK_LINES {
+            self.lines.pop_front();
+            self.dropped += 1;
+        }
+        self.lines.push_back(Line::default());
+        self.line_width = 0;
+    }
+
+    fn clear(&mut self) {
+        self.lines.clear();
+        self.selection = None;
+        self.scroll = 0;
+        self.new_line();
+        self.dirty = true;
+    }
+
+    fn apply(&mut self, action: Action) {
+        match action {
+            Action::Print(ch) => self.write_char(ch),
+            Action::Foreground(color) => self.style.foreground = color.map_or(TEXT_COLOR, ansi::color),
+            Action::Background(color) => self.style.background = color.map(ansi::color),
+            Action::ClearScreen => self.clear(),
+            _ => {}
+        }
+    }
+
+    fn write_char(&mut self, ch: char) {
+        match ch {
+            '\n' => self.new_line(),
+            '\r' => {}
+            '\t' => {
+                for _ in 0..TAB_SIZE {
+                    self.write_char(' ');
+                }
+            }
+            // 退格只擦除当前行的字符
+            '\x08' => {
+                if let Some(ch) = self.lines.back_mut().and_then(|line| line.text.pop()) {
+                    self.line_width = self.line_width.saturating_sub(self.advance(ch));
+                }
+            }
+            ch => {
+                let width = self.advance(ch);
+                if self.line_width > 0 && self.line_width + width > self.text_width() {
+                    self.new_line();
+                    if let Some(line) = self.lines.back_mut() {
+                        line.continued = true;
+                    }
+                }
+                if let Some(line) = self.lines.back_mut() {
+                    if line.style() != self.style {
+                        line.styles.push((line.text.len(), self.style));
+                    }
+                    line.text.push(ch);
+                }
+                self.line_width += width;
+            }
+        }
+        self.dirty = true;
+    }
+
+    fn scroll_by(&mut self, pages: isize) {
+        let max_scroll = self.lines.len().saturating_sub(self.rows());
+        let lines = pages * self.rows() as isize;
+        self.scroll = (self.scroll as isize + lines).clamp(0, max_scroll as isize) as usize;
+        self.dirty = true;
+    }
+
+    /// 换成 `size` 号字，按新的宽度重新折行
+    fn set_font_size(&mut self, size: f32) {
+        self.font_size = size;
+        self.reflow();
+    }
+
+    // 把自动折行的行接回去，再按当前的宽度和字号重新写一遍
+    // 折行变了，选中的位置不再有效
+    fn reflow(&mut self) {
+        let lines = core::mem::take(&mut self.lines);
+        self.selection = None;
+        let style = self.style;
+        self.new_line();
+        for (i, line) in lines.iter().enumerate() {
+            if i > 0 && !line.continued {
+                self.new_line();
+            }
+            let mut styles = line.styles.iter().peekable();
+            self.style = DEFAULT_STYLE;
+            for (offset, ch) in line.text.char_indices() {
+                while let Some(&(_, next)) = styles.next_if(|(at, _)| *at <= offset) {
+                    self.style = next;
+                }
+                self.write_char(ch);
+            }
+        }
+        self.style = style;
+        self.scroll = 0;
+        self.dirty = true;
+    }
+
+    // 屏幕上显示的行在 lines 中的范围
+    fn visible(&self) -> Range<usize> {
+        let end = self.lines.len() - self.scroll.min(self.lines.len());
+        end.saturating_sub(self.rows())..end
+    }
+
+    // 客户区中 (x, y) 处的字符位置，点在字符的右半边时算作它后面的位置
+    fn hit(&self, x: usize, y: usize) -> Option<(usize, usize)> {
+        let visible = self.visible();
+        let row = x.saturating_sub(PADDING) / self.line_height();
+        let index = (visible.start + row).min(visible.end.checked_sub(1)?);
+        let line = &self.lines[index];
+        let mut width = PADDING;
+        let offset = line.text.char_indices().find(|&(_, ch)| {
+            let advance = self.advance(ch);
+            let hit = y < width + advance / 2;
+            width += advance;
+            hit
+        });
+        Some((self.dropped + index, offset.map_or(line.text.len(), |(offset, _)| offset)))
+    }
+
+    // lines[index] 中被选中的部分
+    fn selected(&self, index: usize) -> Option<Range<usize>> {
+        let (start, end) = self.selection?.ordered();
+        let number = self.dropped + index;
+        if number < start.0 || number > end.0 {
+            return None;
+        }
+        let from = if number == start.0 { start.1 } else { 0 };
+        let to = if number == end.0 { end.1 } else { self.lines[index].text.len() };
+        (from < to).then_some(from..to)
+    }
+
+    // 选中的文字，自动折行的地方不加换行
+    fn selected_text(&self) -> String {
+        let mut text = String::new();
+        let Some((start, end)) = self.selection.map(|s| s.ordered()) else { return text };
+        let first = start.0.saturating_sub(self.dropped);
+        let last = end.0.saturating_sub(self.dropped).min(self.lines.len().saturating_sub(1));
+        for index in first..=last {
+            if index > first && !self.lines[index].continued {
+                text.push('\n');
+            }
+            if let Some(range) = self.selected(index) {
+                text.push_str(&self.lines[index].text[range]);
+            }
+        }
+        text
+    }
+
+    fn redraw(&mut self, manager: &mut WindowManager) {
+        let Some(size) = manager.window(self.window).map(|w| w.client_size()) else { return };
+        // 宽度变了要重新折行
+        if size.0 != self.size.0 {
+            self.size = size;
+            self.reflow();
+        }
+        self.size = size;
+        let Some(window) = manager.window_mut(self.window) else { return };
+        let line_height = self.line_height();
+        let text_width = self.text_width();
+        let start = self.visible().start;
+
+        window.fill(BACKGROUND_COLOR);
+        for (row, line) in self.lines.range(self.visible()).enumerate() {
+            let x = PADDING + row * line_height;
+            let mut y = PADDING;
+            let selected = self.selected(start + row);
+            // 按样式分段画
+            let (mut from, mut style) = (0, DEFAULT_STYLE);
+            for &(to, next) in line.styles.iter().chain([(line.text.len(), DEFAULT_STYLE)].iter()) {
+                let segment = &line.text[from..to];
+                let max_width = text_width.saturating_sub(y - PADDING);
+                if let Some(background) = style.background {
+                    let width = text::measure(segment, FontId::MONOSPACE, self.font_size).min(max_width);
+                    window.fill_rect(x, y, width, line_height, background);
+                }
+                if let Some(selected) = &selected {
+                    let (a, b) = (selected.start.max(from), selected.end.min(to));
+                    if a < b {
+                        let offset = text::measure(&line.text[from..a], FontId::MONOSPACE, self.font_size);
+                        let width = text::measure(&line.text[a..b], FontId::MONOSPACE, self.font_size);
+                        window.fill_rect(x, y + offset, width.min(max_width.saturating_sub(offset)), line_height, SELECTION_COLOR);
+                    }
+                }
+                y += window.draw_text_sized(x, y, max_width, segment, FontId::MONOSPACE, self.font_size, style.foreground);
+                (from, style) = (to, next);
+            }
+        }
+        manager.redraw(self.window);
+        self.dirty = false;
+    }
+}
+
+impl fmt::Write for Terminal {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        // 有新的输出时回到底部
+        self.scroll = 0;
+        let mut parser = core::mem::take(&mut self.parser);
+        for ch in s.chars() {
+            parser.feed(ch, |action| self.apply(action));
+        }
+        self.parser = parser;
+        Ok(())
+    }
+}
+
+// 内存不够时从最早的一行开始丢弃滚动缓冲，至少保留一屏
+struct ScrollbackShrinker;
+
+static SCROLLBACK_SHRINKER: ScrollbackShrinker = ScrollbackShrinker;
+
+impl Shrinker for ScrollbackShrinker {
+    fn name(&self) -> &'static str {
+        "terminal scrollback"
+    }
+
+    fn count(&self) -> usize {
+        interrupts::without_interrupts(|| {
+            let terminal = TERMINAL.lock();
+            let Some(terminal) = terminal.as_ref() else { return 0 };
+            let excess = terminal.lines.len().saturating_sub(terminal.rows());
+            terminal.lines.iter().take(excess).map(Line::footprint).sum()
+        })
+    }
+
+    fn shrink(&self, target: usize) -> usize {
+        interrupts::without_interrupts(|| {
+            let Some(mut terminal) = TERMINAL.try_lock() else { return 0 };
+            let Some(terminal) = terminal.as_mut() else { return 0 };
+            let keep = terminal.rows().max(1);
+            let mut freed = 0;
+            while freed < target && terminal.lines.len() > keep {
+                freed += terminal.lines.pop_front().map_or(0, |line| line.footprint());
+                terminal.dropped += 1;
+            }
+            let max_scroll = terminal.lines.len().saturating_sub(terminal.rows());
+            terminal.scroll = terminal.scroll.min(max_scroll);
+            terminal.dirty = true;
+            freed
+        })
+    }
+}
+
+/// 打开终端窗口，之后的控制台输出都写到窗口里
+pub fn open(x: usize, y: usize, width: usize, height: usize) {
+    let mut manager = WINDOW_MANAGER.lock();
+    // 内存不够时继续输出到文本图层
+    let Ok(window) = manager.create(i18n::tr(Msg::Terminal), x, y, width, height) else { return };
+    manager.set_essential(window, true);
+    let size = manager.window(window).map(|w| w.client_size()).unwrap_or_default();
+    let mut terminal = Terminal {
+        window,
+        lines: VecDeque::new(),
+        line_width: 0,
+        size,
+        scroll: 0,
+        dirty: true,
+        style: DEFAULT_STYLE,
+        parser: Parser::new(),
+        font_size: text::zoom(),
+        dropped: 0,
+        selection: None,
+        selecting: false,
+    };
+    terminal.new_line();
+    terminal.redraw(&mut manager);
+    interrupts::without_interrupts(|| *TERMINAL.lock() = Some(terminal));
+    OPEN.store(true, Ordering::Release);
+    shrinker::register(&SCROLLBACK_SHRINKER);
+}
+
+/// 终端窗口是否已经打开，可以在中断中调用
+pub fn is_open() -> bool {
+    OPEN.load(Ordering::Acquire)
+}
+
+/// 写到终端窗口，终端没有打开时返回 false
+///
+/// 可能在中断中被调用，窗口管理器正忙时先只记下内容，由 flush 补画
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) -> bool {
+    interrupts::without_interrupts(|| {
+        let mut terminal = TERMINAL.lock();
+        let Some(terminal) = terminal.as_mut() else { return false };
+        terminal.write_fmt(args).unwrap();
+        if let Some(mut manager) = WINDOW_MANAGER.try_lock() {
+            terminal.redraw(&mut manager);
+        }
+        true
+    })
+}
+
+/// 清空终端，终端没有打开时返回 false
+pub fn clear() -> bool {
+    interrupts::without_interrupts(|| {
+        let mut terminal = TERMINAL.lock();
+        let Some(terminal) = terminal.as_mut() else { return false };
+        terminal.clear();
+        true
+    })
+}
+
+/// 请求翻页，正数向上，可以在中断中调用
+pub fn request_scroll(pages: isize) {
+    PENDING_SCROLL.fetch_add(pages, Ordering::Relaxed);
+}
+
+/// 处理翻页请求，补画没来得及画的内容，窗口大小变化后重新绘制
+///
+/// 由 gui::poll 调用
+pub fn flush() {
+    interrupts::without_interrupts(|| {
+        let mut terminal = TERMINAL.lock();
+        let Some(terminal) = terminal.as_mut() else { return };
+        if terminal.font_size != text::zoom() {
+            terminal.set_font_size(text::zoom());
+        }
+        let pages = PENDING_SCROLL.swap(0, Ordering::Relaxed);
+        if pages != 0 {
+            terminal.scroll_by(pages);
+        }
+        let mut manager = WINDOW_MANAGER.lock();
+        let size = manager.window(terminal.window).map(|w| w.client_size());
+        if terminal.dirty || size != Some(terminal.size) {
+            terminal.redraw(&mut manager);
+        }
+    })
+}
+
+/// 在终端窗口的客户区 (x, y) 处按下左键，开始选择文字；`window` 不是终端窗口时返回 false
+pub fn select_start(window: WindowId, x: usize, y: usize) -> bool {
+    interrupts::without_interrupts(|| {
+        let mut terminal = TERMINAL.lock();
+        let Some(terminal) = terminal.as_mut().filter(|t| t.window == window) else { return false };
+        terminal.selection = terminal.hit(x, y).map(|at| Selection { anchor: at, head: at });
+        terminal.selecting = true;
+        terminal.dirty = true;
+        true
+    })
+}
+
+/// 按住左键拖到屏幕上的 (x, y)，移出窗口时选到最近的边上
+pub fn select_to(x: usize, y: usize) {
+    interrupts::without_interrupts(|| {
+        let mut terminal = TERMINAL.lock();
+        let Some(terminal) = terminal.as_mut().filter(|t| t.selecting) else { return };
+        let Some((x, y)) = WINDOW_MANAGER.lock().client_point(terminal.window, x, y) else { return };
+        if let (Some(selection), Some(head)) = (terminal.selection.as_mut(), terminal.hit(x, y)) {
+            selection.head = head;
+            terminal.dirty = true;
+        }
+    })
+}
+
+/// 松开左键，只点了一下没有拖动时取消选择
+pub fn select_end() {
+    interrupts::without_interrupts(|| {
+        let mut terminal = TERMINAL.lock();
+        let Some(terminal) = terminal.as_mut().filter(|t| t.selecting) else { return };
+        terminal.selecting = false;
+        if terminal.selection.map_or(false, |s| s.anchor == s.head) {
+            terminal.selection = None;
+        }
+    })
+}
+
+/// 把终端窗口 `window` 中选中的文字复制到剪贴板，没有选中文字时返回 false
+pub fn copy_selection(window: WindowId) -> bool {
+    interrupts::without_interrupts(|| {
+        let terminal = TERMINAL.lock();
+        let Some(terminal) = terminal.as_ref().filter(|t| t.window == window) else { return false };
+        let text = terminal.selected_text();
+        if text.is_empty() {
+            return false;
+        }
+        clipboard::set_text(&text);
+        true
+    })
+}
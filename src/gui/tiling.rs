@@ -0,0 +1,208 @@
+// 键盘平铺
+// 鼠标不好用的时候也能用键盘摆放窗口：
+//   Alt+←/→    焦点窗口贴到左半边/右半边
+//   Alt+1..4   焦点窗口贴到左上、右上、左下、右下四分之一
+//   Alt+↑/↓    最大化 / 恢复贴靠之前的位置和大小
+//   Alt+空格   切换布局：浮动 → 并排 → 上下 → 主窗口 → 网格 → 浮动
+//   Alt+Tab    焦点切换到最下面的窗口
+// 非浮动布局下窗口管理器在新建、关闭窗口和切换焦点后重新平铺所有窗口，回到浮动布局时窗口恢复原来的位置。
+// 这里只计算每个窗口的位置，移动和调整大小都交给 WindowManager::set_geometry。
+// 键盘中断只记下请求，由 gui::poll 在主循环中执行
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use crate::graphic;
+use crate::gui::status_bar::{show_notice, STATUS_BAR_HEIGHT};
+use crate::gui::window::WINDOW_MANAGER;
+
+/// 焦点窗口贴靠的位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Snap {
+    LeftHalf,
+    RightHalf,
+    // 0 到 3 依次是左上、右上、左下、右下
+    Quadrant(u8),
+    Maximize,
+}
+
+/// 平铺布局
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    // 不自动摆放
+    Floating,
+    // 从左到右并排
+    Columns,
+    // 从上到下排列
+    Rows,
+    // 焦点窗口占左半边，其余的在右半边从上到下排列
+    Master,
+    // 尽量接近正方形的网格
+    Grid,
+}
+
+impl Layout {
+    pub fn next(self) -> Self {
+        match self {
+            Layout::Floating => Layout::Columns,
+            Layout::Columns => Layout::Rows,
+            Layout::Rows => Layout::Master,
+            Layout::Master => Layout::Grid,
+            Layout::Grid => Layout::Floating,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Layout::Floating => "floating",
+            Layout::Columns => "columns",
+            Layout::Rows => "rows",
+            Layout::Master => "master",
+            Layout::Grid => "grid",
+        }
+    }
+}
+
+/// 屏幕上的一块矩形，坐标和 graphic 模块一致：x 是行，y 是列
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    /// 和 `other` 重叠的部分，不重叠时返回 None
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        let (sx, sy) = (self.x.max(other.x), self.y.max(other.y));
+        let (ex, ey) = ((self.x + self.height).min(other.x + other.height), (self.y + self.width).min(other.y + other.width));
+        (sx < ex && sy < ey).then_some(Rect { x: sx, y: sy, width: ey - sy, height: ex - sx })
+    }
+
+    /// 同时包住两个矩形的最小矩形
+    pub fn union(&self, other: &Rect) -> Rect {
+        let (sx, sy) = (self.x.min(other.x), self.y.min(other.y));
+        let (ex, ey) = ((self.x + self.height).max(other.x + other.height), (self.y + self.width).max(other.y + other.width));
+        Rect { x: sx, y: sy, width: ey - sy, height: ex - sx }
+    }
+
+    /// `other` 整个在这个矩形里面
+    pub fn contains(&self, other: &Rect) -> bool {
+        other.x >= self.x && other.y >= self.y
+            && other.x + other.height <= self.x + self.height && other.y + other.width <= self.y + self.width
+    }
+}
+
+/// 窗口可以占用的区域：状态栏以下的整个屏幕
+pub fn work_area() -> Rect {
+    Rect {
+        x: STATUS_BAR_HEIGHT,
+        y: 0,
+        width: graphic::width(),
+        height: graphic::height().saturating_sub(STATUS_BAR_HEIGHT),
+    }
+}
+
+/// 贴靠到 `snap` 时窗口的位置和大小
+pub fn snap_rect(snap: Snap, area: Rect) -> Rect {
+    let (left, right) = (split(area.y, area.width, 2, 0), split(area.y, area.width, 2, 1));
+    let (top, bottom) = (split(area.x, area.height, 2, 0), split(area.x, area.height, 2, 1));
+    let ((x, height), (y, width)) = match snap {
+        Snap::LeftHalf => ((area.x, area.height), left),
+        Snap::RightHalf => ((area.x, area.height), right),
+        Snap::Quadrant(q) => (if q & 2 == 0 { top } else { bottom }, if q & 1 == 0 { left } else { right }),
+        Snap::Maximize => return area,
+    };
+    Rect { x, y, width, height }
+}
+
+/// `layout` 下 `count` 个窗口中第 `index` 个的位置和大小；主窗口布局中第 0 个是主窗口
+pub fn tile_rect(layout: Layout, area: Rect, count: usize, index: usize) -> Rect {
+    let ((x, height), (y, width)) = match layout {
+        Layout::Floating | Layout::Columns => ((area.x, area.height), split(area.y, area.width, count, index)),
+        Layout::Rows => (split(area.x, area.height, count, index), (area.y, area.width)),
+        Layout::Master if count == 1 => return area,
+        Layout::Master if index == 0 => ((area.x, area.height), split(area.y, area.width, 2, 0)),
+        Layout::Master => (split(area.x, area.height, count - 1, index - 1), split(area.y, area.width, 2, 1)),
+        Layout::Grid => {
+            let columns = (1..).find(|c| c * c >= count).unwrap_or(1);
+            let rows = (count + columns - 1) / columns;
+            let (row, column) = (index / columns, index % columns);
+            // 最后一行不满时这一行的窗口平分整个宽度
+            let in_row = if row == rows - 1 { count - row * columns } else { columns };
+            (split(area.x, area.height, rows, row), split(area.y, area.width, in_row, column))
+        }
+    };
+    Rect { x, y, width, height }
+}
+
+// 把从 start 开始、长 length 的一段平分成 parts 份，返回第 i 份的起点和长度
+fn split(start: usize, length: usize, parts: usize, i: usize) -> (usize, usize) {
+    let from = length * i / parts;
+    let to = length * (i + 1) / parts;
+    (start + from, to - from)
+}
+
+/// 键盘上的平铺命令
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Snap(Snap),
+    Restore,
+    CycleLayout,
+    FocusNext,
+}
+
+impl Command {
+    fn encode(self) -> u8 {
+        match self {
+            Command::Snap(Snap::LeftHalf) => 1,
+            Command::Snap(Snap::RightHalf) => 2,
+            Command::Snap(Snap::Maximize) => 3,
+            Command::Restore => 4,
+            Command::CycleLayout => 5,
+            Command::FocusNext => 6,
+            Command::Snap(Snap::Quadrant(q)) => 8 + (q & 3),
+        }
+    }
+
+    fn decode(value: u8) -> Option<Self> {
+        Some(match value {
+            1 => Command::Snap(Snap::LeftHalf),
+            2 => Command::Snap(Snap::RightHalf),
+            3 => Command::Snap(Snap::Maximize),
+            4 => Command::Restore,
+            5 => Command::CycleLayout,
+            6 => Command::FocusNext,
+            8..=11 => Command::Snap(Snap::Quadrant(value - 8)),
+            _ => return None,
+        })
+    }
+}
+
+// 键盘中断记下的请求，0 表示没有；来不及处理时后一个覆盖前一个
+static PENDING: AtomicU8 = AtomicU8::new(0);
+
+/// 由键盘中断处理函数调用
+pub fn request(command: Command) {
+    PENDING.store(command.encode(), Ordering::Relaxed);
+}
+
+/// 执行键盘中断记下的请求
+///
+/// 由 gui::poll 调用
+pub fn poll() {
+    let Some(command) = Command::decode(PENDING.swap(0, Ordering::Relaxed)) else { return };
+    let mut manager = WINDOW_MANAGER.lock();
+    match command {
+        Command::Snap(snap) => manager.snap_focused(snap),
+        Command::Restore => manager.restore_focused(),
+        Command::FocusNext => manager.focus_next(),
+        Command::CycleLayout => {
+            let layout = manager.layout().next();
+            manager.set_layout(layout);
+            drop(manager);
+            log::info!("Window layout: {}", layout.name());
+            show_notice(layout.name());
+        }
+    }
+}
@@ -0,0 +1,229 @@
+// 通知
+// notify 在屏幕右上角、状态栏下面弹出一条通知，多条通知从上往下排。每条通知占一个和它一样大的图层，
+// z 在所有窗口之上（Z_TOAST），不抢焦点，鼠标点击照常落到下面的窗口上。
+// 通知先淡入，停留一段时间（级别越高停得越久）再淡出，进度用 animation 的缓动曲线换算，
+// 和窗口动画一样由 frame::poll 在合成之前调用 step 推进。同时最多显示 MAX_VISIBLE 条，多出来的排队，
+// 前面的消失后下面的往上补位、排队的补上；队列也满了时丢掉最早排队的一条。
+// notify 只把通知放进队列，GUI 初始化之前也可以调用，不要在中断里调用
+
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use embedded_graphics::pixelcolor::Rgb888;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::graphic::font::{self, glyph};
+use crate::graphic::layer::{self, LayerHandle, Z_TOAST};
+use crate::graphic::{self, text, Region, Writer};
+use crate::gui::animation::Easing;
+use crate::gui::frame;
+use crate::gui::status_bar::STATUS_BAR_HEIGHT;
+use crate::rgb888;
+
+const WIDTH: usize = 280;
+const HEIGHT: usize = 60;
+const MARGIN: usize = 8;
+// 左边表示级别的色条
+const ACCENT_WIDTH: usize = 4;
+const MAX_VISIBLE: usize = 4;
+const MAX_QUEUED: usize = 16;
+const FADE_IN: Duration = Duration::from_millis(200);
+const FADE_OUT: Duration = Duration::from_millis(600);
+const TITLE_SIZE: f32 = 16.0;
+const MESSAGE_SIZE: f32 = 14.0;
+// 消息最多显示两行
+const MESSAGE_LINES: usize = 2;
+const BACKGROUND_COLOR: Rgb888 = rgb888!(0x263238u32);
+const TITLE_COLOR: Rgb888 = rgb888!(0xFFFFFFu32);
+const MESSAGE_COLOR: Rgb888 = rgb888!(0xB0BEC5u32);
+
+/// 通知的级别
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Level {
+    fn color(self) -> Rgb888 {
+        match self {
+            Level::Info => rgb888!(0x4FC3F7u32),
+            Level::Warning => rgb888!(0xFFB74Du32),
+            Level::Error => rgb888!(0xE57373u32),
+        }
+    }
+
+    // 淡入之后停留的时间
+    fn linger(self) -> Duration {
+        match self {
+            Level::Info => Duration::from_secs(4),
+            Level::Warning => Duration::from_secs(6),
+            Level::Error => Duration::from_secs(10),
+        }
+    }
+}
+
+struct Notification {
+    title: String,
+    message: String,
+    level: Level,
+}
+
+struct Toast {
+    notification: Notification,
+    layer: LayerHandle,
+    // 开始显示的时间
+    shown: Duration,
+    // 从上往下第几个位置
+    slot: usize,
+}
+
+impl Toast {
+    fn opacity(&self, now: Duration) -> f32 {
+        let elapsed = now.saturating_sub(self.shown);
+        let linger = self.notification.level.linger();
+        if elapsed < FADE_IN {
+            Easing::EaseOut.apply(elapsed.as_secs_f32() / FADE_IN.as_secs_f32())
+        } else if elapsed < FADE_IN + linger {
+            1.0
+        } else {
+            let t = (elapsed - FADE_IN - linger).as_secs_f32() / FADE_OUT.as_secs_f32();
+            1.0 - Easing::EaseIn.apply(t)
+        }
+    }
+
+    fn is_done(&self, now: Duration) -> bool {
+        now >= self.shown + FADE_IN + self.notification.level.linger() + FADE_OUT
+    }
+}
+
+struct Toasts {
+    shown: Vec<Toast>,
+    queue: VecDeque<Notification>,
+}
+
+static TOASTS: Mutex<Toasts> = Mutex::new(Toasts { shown: Vec::new(), queue: VecDeque::new() });
+
+/// 弹出一条通知
+pub fn notify(title: &str, message: &str, level: Level) {
+    let notification = Notification { title: String::from(title), message: String::from(message), level };
+    interrupts::without_interrupts(|| {
+        let mut toasts = TOASTS.lock();
+        if toasts.queue.len() == MAX_QUEUED {
+            if let Some(oldest) = toasts.queue.pop_front() {
+                log::warn!("toast: queue full, dropped \"{}\"", oldest.title);
+            }
+        }
+        toasts.queue.push_back(notification);
+    });
+}
+
+/// 正在显示和排队的通知条数
+pub fn count() -> (usize, usize) {
+    interrupts::without_interrupts(|| {
+        let toasts = TOASTS.lock();
+        (toasts.shown.len(), toasts.queue.len())
+    })
+}
+
+// 第 slot 个位置的左上角
+fn position(slot: usize) -> (usize, usize) {
+    (STATUS_BAR_HEIGHT + MARGIN + slot * (HEIGHT + MARGIN), graphic::width().saturating_sub(WIDTH + MARGIN))
+}
+
+fn request(region: Region) {
+    frame::request(region.sx, region.sy, region.ex, region.ey);
+}
+
+// 画在 (x, y)，超出 `max_width` 的字不画
+fn draw_line(layer: &mut Writer, x: usize, y: usize, max_width: usize, line: &str, size: f32, color: Rgb888) {
+    let mut width = 0;
+    for ch in line.chars() {
+        let glyph = glyph(font::gui_font(), ch, size);
+        let advance = glyph.advance as usize + 1;
+        if width + advance > max_width {
+            break;
+        }
+        layer.display_font(&glyph, x, y + width, size as usize, color);
+        width += advance;
+    }
+}
+
+fn draw(layer: &mut Writer, x: usize, y: usize, notification: &Notification) {
+    layer.display_rect(x, y, WIDTH, HEIGHT, BACKGROUND_COLOR);
+    layer.display_rect(x, y, ACCENT_WIDTH, HEIGHT, notification.level.color());
+    let left = y + ACCENT_WIDTH + MARGIN;
+    let max_width = WIDTH - ACCENT_WIDTH - 2 * MARGIN;
+    draw_line(layer, x + 6, left, max_width, &notification.title, TITLE_SIZE, TITLE_COLOR);
+    let lines = text::wrap(&notification.message, font::gui_font(), MESSAGE_SIZE, max_width);
+    for (i, line) in lines.iter().take(MESSAGE_LINES).enumerate() {
+        draw_line(layer, x + 26 + i * 16, left, max_width, line, MESSAGE_SIZE, MESSAGE_COLOR);
+    }
+}
+
+// 在第 slot 个位置显示，一开始完全透明；内存不够时返回 None
+fn show(notification: Notification, slot: usize, now: Duration) -> Option<Toast> {
+    let (x, y) = position(slot);
+    let mut writer = Writer::try_with_size(x, y, WIDTH, HEIGHT).ok()?;
+    draw(&mut writer, x, y, &notification);
+    writer.set_opacity(0.0);
+    let layer = layer::add_layer(writer, Z_TOAST);
+    layer::set_visible(layer, true);
+    Some(Toast { notification, layer, shown: now, slot })
+}
+
+/// 把所有通知推进到 `now`：移除显示完的，补位，显示排队的，更新不透明度
+///
+/// 由 frame::poll 在合成之前调用
+pub fn step(now: Duration) {
+    interrupts::without_interrupts(|| {
+        let mut toasts = TOASTS.lock();
+        if toasts.shown.is_empty() && toasts.queue.is_empty() {
+            return;
+        }
+        toasts.shown.retain(|toast| {
+            let done = toast.is_done(now);
+            if done {
+                if let Some(writer) = layer::remove_layer(toast.layer) {
+                    request(writer.area());
+                }
+            }
+            !done
+        });
+        for (slot, toast) in toasts.shown.iter_mut().enumerate() {
+            if toast.slot == slot {
+                continue;
+            }
+            toast.slot = slot;
+            let (x, y) = position(slot);
+            layer::with_layer(toast.layer, |layer| {
+                let old = layer.area();
+                if layer.place(x, y, WIDTH, HEIGHT).is_ok() {
+                    draw(layer, x, y, &toast.notification);
+                }
+                request(old.union(layer.area()));
+            });
+        }
+        while toasts.shown.len() < MAX_VISIBLE {
+            let Some(notification) = toasts.queue.pop_front() else { break };
+            let slot = toasts.shown.len();
+            match show(notification, slot, now) {
+                Some(toast) => toasts.shown.push(toast),
+                None => log::warn!("toast: out of memory, notification dropped"),
+            }
+        }
+        for toast in toasts.shown.iter() {
+            let opacity = toast.opacity(now);
+            layer::with_layer(toast.layer, |layer| {
+                if layer.opacity() != opacity {
+                    layer.set_opacity(opacity);
+                    request(layer.area());
+                }
+            });
+        }
+    })
+}
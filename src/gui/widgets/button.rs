@@ -0,0 +1,54 @@
+use alloc::string::String;
+
+use crate::graphic::canvas::Canvas;
+use crate::graphic::font;
+use crate::gui::widgets::{Bounds, Event, Widget, FACE_COLOR, FRAME_COLOR, LINE_HEIGHT, PRESSED_FACE_COLOR, TEXT_COLOR};
+use crate::gui::window::Window;
+
+// 圆角半径
+const CORNER_RADIUS: usize = 4;
+
+/// 按钮，在按钮上按下并松开左键时调用 `on_click`
+pub struct Button {
+    bounds: Bounds,
+    text: String,
+    pressed: bool,
+    on_click: fn(),
+}
+
+impl Button {
+    pub fn new(bounds: Bounds, text: &str, on_click: fn()) -> Self {
+        Self { bounds, text: String::from(text), pressed: false, on_click }
+    }
+}
+
+impl Widget for Button {
+    fn bounds(&self) -> Bounds {
+        self.bounds
+    }
+
+    fn draw(&self, window: &mut Window, _focused: bool) {
+        let Bounds { x, y, width, height } = self.bounds;
+        let face = if self.pressed { PRESSED_FACE_COLOR } else { FACE_COLOR };
+        window.fill_rounded_rect(x as isize, y as isize, width, height, CORNER_RADIUS, face);
+        window.draw_rounded_rect(x as isize, y as isize, width, height, CORNER_RADIUS, FRAME_COLOR);
+        window.draw_text(x + height.saturating_sub(LINE_HEIGHT) / 2, y + 4, width.saturating_sub(8), &self.text, font::gui_font(), TEXT_COLOR);
+    }
+
+    fn handle_event(&mut self, event: &Event) -> bool {
+        match *event {
+            Event::MouseDown { .. } => {
+                self.pressed = true;
+                true
+            }
+            Event::MouseUp { x, y } if self.pressed => {
+                self.pressed = false;
+                if self.bounds.contains(x, y) {
+                    (self.on_click)();
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use alloc::string::String;
+
+use crate::graphic::font;
+use crate::gui::widgets::{draw_frame, Bounds, Event, Widget, FRAME_COLOR, INPUT_COLOR, LINE_HEIGHT, TEXT_COLOR};
+use crate::gui::window::Window;
+
+// 方框的边长
+const BOX_SIZE: usize = 14;
+
+/// 复选框，点击时切换状态并调用 `on_change`
+pub struct Checkbox {
+    bounds: Bounds,
+    text: String,
+    checked: bool,
+    on_change: fn(bool),
+}
+
+impl Checkbox {
+    pub fn new(bounds: Bounds, text: &str, checked: bool, on_change: fn(bool)) -> Self {
+        Self { bounds, text: String::from(text), checked, on_change }
+    }
+
+    pub fn checked(&self) -> bool {
+        self.checked
+    }
+}
+
+impl Widget for Checkbox {
+    fn bounds(&self) -> Bounds {
+        self.bounds
+    }
+
+    fn draw(&self, window: &mut Window, _focused: bool) {
+        let Bounds { x, y, width, height } = self.bounds;
+        let box_x = x + height.saturating_sub(BOX_SIZE) / 2;
+        let square = Bounds::new(box_x, y, BOX_SIZE, BOX_SIZE);
+        window.fill_rect(box_x, y, BOX_SIZE, BOX_SIZE, INPUT_COLOR);
+        draw_frame(window, square, FRAME_COLOR);
+        if self.checked {
+            window.fill_rect(box_x + 3, y + 3, BOX_SIZE - 6, BOX_SIZE - 6, TEXT_COLOR);
+        }
+        let text_y = y + BOX_SIZE + 6;
+        window.draw_text(x + height.saturating_sub(LINE_HEIGHT) / 2, text_y,
+                         width.saturating_sub(BOX_SIZE + 6), &self.text, font::gui_font(), TEXT_COLOR);
+    }
+
+    fn handle_event(&mut self, event: &Event) -> bool {
+        match event {
+            Event::MouseDown { .. } => {
+                self.checked = !self.checked;
+                (self.on_change)(self.checked);
+                true
+            }
+            _ => false,
+        }
+    }
+}
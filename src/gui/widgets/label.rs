@@ -0,0 +1,49 @@
+use alloc::string::String;
+
+use crate::graphic::font::{self, FontId};
+use crate::graphic::text::{self, Align};
+use crate::gui::widgets::{Bounds, Event, Widget, LINE_HEIGHT, TEXT_COLOR};
+use crate::gui::window::Window;
+
+// 和 Window::draw_text 一致的字号
+const TEXT_SIZE: f32 = 16.0;
+
+/// 静态文字，超出宽度时在空格处折行，放不下的行不显示
+pub struct Label {
+    bounds: Bounds,
+    text: String,
+    align: Align,
+    // None 时用 GUI 字体
+    font: Option<FontId>,
+}
+
+impl Label {
+    pub fn new(bounds: Bounds, text: &str) -> Self {
+        Self { bounds, text: String::from(text), align: Align::Left, font: None }
+    }
+
+    pub fn with_align(mut self, align: Align) -> Self {
+        self.align = align;
+        self
+    }
+
+    pub fn with_font(mut self, font: FontId) -> Self {
+        self.font = Some(font);
+        self
+    }
+}
+
+impl Widget for Label {
+    fn bounds(&self) -> Bounds {
+        self.bounds
+    }
+
+    fn draw(&self, window: &mut Window, _focused: bool) {
+        let Bounds { x, y, width, height } = self.bounds;
+        text::draw_text(window, x, y, width, height, &self.text, self.font.unwrap_or_else(font::gui_font), TEXT_SIZE, LINE_HEIGHT, self.align, TEXT_COLOR);
+    }
+
+    fn handle_event(&mut self, _event: &Event) -> bool {
+        false
+    }
+}
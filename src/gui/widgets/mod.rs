@@ -0,0 +1,258 @@
+// 控件
+// 控件画在所属窗口的客户区中，坐标都相对于客户区左上角（x 是行，y 是列）。
+// 每个窗口的控件放在一个 Panel 里，事件循环把鼠标事件交给光标下的控件，
+// 把键盘输入交给拥有焦点的窗口中拥有焦点的控件。终端窗口没有控件，鼠标拖动和复制的快捷键转给 gui::terminal。
+// 窗口管理器记下的损坏区域交给和它重叠的控件（Event::Expose），有控件重叠时重画整个面板
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use embedded_graphics::pixelcolor::Rgb888;
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+use crate::graphic::canvas::Canvas;
+use crate::gui::clipboard;
+use crate::gui::terminal;
+use crate::gui::tiling::Rect;
+use crate::gui::window::{Damage, Window, WindowId, WINDOW_MANAGER};
+use crate::rgb888;
+
+pub use button::Button;
+pub use checkbox::Checkbox;
+pub use label::Label;
+pub use progress_bar::ProgressBar;
+pub use text_box::TextBox;
+
+mod button;
+mod checkbox;
+mod label;
+mod progress_bar;
+mod text_box;
+
+// 控件共用的配色
+const TEXT_COLOR: Rgb888 = rgb888!(0x212121u32);
+const FRAME_COLOR: Rgb888 = rgb888!(0x78909Cu32);
+const FOCUSED_FRAME_COLOR: Rgb888 = rgb888!(0x0277BDu32);
+const FACE_COLOR: Rgb888 = rgb888!(0xCFD8DCu32);
+const PRESSED_FACE_COLOR: Rgb888 = rgb888!(0x90A4AEu32);
+const INPUT_COLOR: Rgb888 = rgb888!(0xFFFFFFu32);
+// 文字的行高
+const LINE_HEIGHT: usize = 18;
+
+/// 控件在客户区中占据的矩形
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bounds {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Bounds {
+    pub const fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Self { x, y, width, height }
+    }
+
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        x >= self.x && x < self.x + self.height && y >= self.y && y < self.y + self.width
+    }
+
+    /// 和 `other` 重叠的部分
+    pub fn intersection(&self, other: &Bounds) -> Option<Bounds> {
+        let rect = |b: &Bounds| Rect { x: b.x, y: b.y, width: b.width, height: b.height };
+        let Rect { x, y, width, height } = rect(self).intersection(&rect(other))?;
+        Some(Bounds { x, y, width, height })
+    }
+}
+
+/// 控件收到的事件，鼠标坐标相对于客户区
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    MouseDown { x: usize, y: usize },
+    MouseUp { x: usize, y: usize },
+    Key(char),
+    /// 控件的这一部分被挡住后又露出来，或者窗口变大后才显示出来，需要重画
+    Expose(Bounds),
+}
+
+pub trait Widget: Send {
+    fn bounds(&self) -> Bounds;
+
+    fn draw(&self, window: &mut Window, focused: bool);
+
+    /// 处理事件，返回是否需要重绘
+    fn handle_event(&mut self, event: &Event) -> bool;
+
+    /// 能否获得键盘焦点
+    fn focusable(&self) -> bool {
+        false
+    }
+}
+
+// 画控件的边框
+fn draw_frame(window: &mut Window, bounds: Bounds, color: Rgb888) {
+    let Bounds { x, y, width, height } = bounds;
+    window.draw_rect(x as isize, y as isize, width, height, color);
+}
+
+/// 一个窗口中的所有控件
+struct Panel {
+    window: WindowId,
+    widgets: Vec<Box<dyn Widget>>,
+    focus: Option<usize>,
+}
+
+impl Panel {
+    fn redraw(&self) {
+        let mut manager = WINDOW_MANAGER.lock();
+        if let Some(window) = manager.window_mut(self.window) {
+            for (index, widget) in self.widgets.iter().enumerate() {
+                widget.draw(window, self.focus == Some(index));
+            }
+        }
+        manager.redraw(self.window);
+    }
+}
+
+struct EventLoop {
+    panels: Vec<Panel>,
+    // 左键按下时所在的窗口，松开事件也发给它
+    pressed: Option<WindowId>,
+    left_pressed: bool,
+}
+
+lazy_static! {
+    static ref EVENT_LOOP: Mutex<EventLoop> = Mutex::new(EventLoop {
+        panels: Vec::new(),
+        pressed: None,
+        left_pressed: false,
+    });
+}
+
+/// 往窗口中添加控件并立即画出来
+pub fn add(window: WindowId, widget: Box<dyn Widget>) {
+    let mut event_loop = EVENT_LOOP.lock();
+    let index = match event_loop.panels.iter().position(|p| p.window == window) {
+        Some(index) => index,
+        None => {
+            event_loop.panels.push(Panel { window, widgets: Vec::new(), focus: None });
+            event_loop.panels.len() - 1
+        }
+    };
+    let panel = &mut event_loop.panels[index];
+    panel.widgets.push(widget);
+    panel.redraw();
+}
+
+/// 重画窗口里的所有控件，控件显示的数据变了以后调用；窗口没有控件时返回 false
+pub fn refresh(window: WindowId) -> bool {
+    let event_loop = EVENT_LOOP.lock();
+    let Some(panel) = event_loop.panels.iter().find(|p| p.window == window) else { return false };
+    panel.redraw();
+    true
+}
+
+/// 把键盘焦点交给窗口里第 `index` 个加入的控件，控件不存在或者不能获得焦点时返回 false
+pub fn focus(window: WindowId, index: usize) -> bool {
+    let mut event_loop = EVENT_LOOP.lock();
+    let Some(panel) = event_loop.panels.iter_mut().find(|p| p.window == window) else { return false };
+    if !panel.widgets.get(index).is_some_and(|w| w.focusable()) {
+        return false;
+    }
+    panel.focus = Some(index);
+    panel.redraw();
+    true
+}
+
+/// 关闭窗口前移除它的控件
+pub fn remove_all(window: WindowId) {
+    EVENT_LOOP.lock().panels.retain(|p| p.window != window);
+}
+
+/// 处理鼠标，参数和 WindowManager::handle_mouse 相同，需在窗口管理器处理之后调用
+pub fn dispatch_mouse(x: usize, y: usize, left: bool) {
+    let mut event_loop = EVENT_LOOP.lock();
+    let pressed = left && !event_loop.left_pressed;
+    let released = !left && event_loop.left_pressed;
+    event_loop.left_pressed = left;
+
+    if pressed {
+        let Some((window, cx, cy)) = WINDOW_MANAGER.lock().client_at(x, y) else { return };
+        event_loop.pressed = Some(window);
+        if terminal::select_start(window, cx, cy) {
+            return;
+        }
+        let Some(panel) = event_loop.panels.iter_mut().find(|p| p.window == window) else { return };
+        let target = panel.widgets.iter().position(|w| w.bounds().contains(cx, cy));
+        let mut dirty = false;
+        // 点到可以获得焦点的控件时转移焦点，点到空白处时取消焦点
+        let focus = target.filter(|&i| panel.widgets[i].focusable());
+        if focus != panel.focus {
+            panel.focus = focus;
+            dirty = true;
+        }
+        if let Some(index) = target {
+            dirty |= panel.widgets[index].handle_event(&Event::MouseDown { x: cx, y: cy });
+        }
+        if dirty {
+            panel.redraw();
+        }
+    } else if left {
+        // 按住左键拖动
+        terminal::select_to(x, y);
+    } else if released {
+        terminal::select_end();
+        let Some(window) = event_loop.pressed.take() else { return };
+        let Some(panel) = event_loop.panels.iter_mut().find(|p| p.window == window) else { return };
+        // 光标可能已经移出窗口，坐标按窗口外处理
+        let (cx, cy) = match WINDOW_MANAGER.lock().client_at(x, y) {
+            Some((w, cx, cy)) if w == window => (cx, cy),
+            _ => (usize::MAX, usize::MAX),
+        };
+        let mut dirty = false;
+        for widget in panel.widgets.iter_mut() {
+            dirty |= widget.handle_event(&Event::MouseUp { x: cx, y: cy });
+        }
+        if dirty {
+            panel.redraw();
+        }
+    }
+}
+
+/// 把键盘输入交给拥有焦点的控件，返回是否被控件处理
+///
+/// 没有控件拥有焦点时返回 false，输入留给 shell；终端窗口里没有选中文字时 Ctrl+C 也留给 shell
+pub fn dispatch_key(ch: char) -> bool {
+    let Some(window) = WINDOW_MANAGER.lock().focused() else { return false };
+    if ch == clipboard::COPY && terminal::copy_selection(window) {
+        return true;
+    }
+    let mut event_loop = EVENT_LOOP.lock();
+    let Some(panel) = event_loop.panels.iter_mut().find(|p| p.window == window) else { return false };
+    let Some(index) = panel.focus else { return false };
+    if panel.widgets[index].handle_event(&Event::Key(ch)) {
+        panel.redraw();
+    }
+    true
+}
+
+/// 把窗口管理器取走的损坏区域交给重叠的控件，由 gui::poll 调用；不能在持有 WINDOW_MANAGER 的时候调用
+pub fn dispatch_damage(damage: &[Damage]) {
+    let mut event_loop = EVENT_LOOP.lock();
+    for damage in damage {
+        let Some(panel) = event_loop.panels.iter_mut().find(|p| p.window == damage.window) else { continue };
+        let Rect { x, y, width, height } = damage.region;
+        let region = Bounds { x, y, width, height };
+        let mut exposed = false;
+        for widget in panel.widgets.iter_mut() {
+            if let Some(part) = widget.bounds().intersection(&region) {
+                widget.handle_event(&Event::Expose(part));
+                exposed = true;
+            }
+        }
+        if exposed {
+            panel.redraw();
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use alloc::boxed::Box;
+use alloc::string::String;
+
+use embedded_graphics::pixelcolor::Rgb888;
+
+use crate::graphic::font;
+use crate::gui::widgets::{draw_frame, Bounds, Event, Widget, FRAME_COLOR, INPUT_COLOR, LINE_HEIGHT, TEXT_COLOR};
+use crate::gui::window::Window;
+use crate::rgb888;
+
+const FILL_COLOR: Rgb888 = rgb888!(0x81D4FAu32);
+// 超过这个比例时换成警告的颜色
+const HIGH: f32 = 0.9;
+const HIGH_FILL_COLOR: Rgb888 = rgb888!(0xEF9A9Au32);
+
+/// 进度条，画的时候调用 `source` 取当前的比例（0 到 1）和写在条上的文字
+///
+/// 数据会变时定时调用 widgets::refresh 重画
+pub struct ProgressBar {
+    bounds: Bounds,
+    source: Box<dyn Fn() -> (f32, String) + Send>,
+}
+
+impl ProgressBar {
+    pub fn new(bounds: Bounds, source: impl Fn() -> (f32, String) + Send + 'static) -> Self {
+        Self { bounds, source: Box::new(source) }
+    }
+}
+
+impl Widget for ProgressBar {
+    fn bounds(&self) -> Bounds {
+        self.bounds
+    }
+
+    fn draw(&self, window: &mut Window, _focused: bool) {
+        let Bounds { x, y, width, height } = self.bounds;
+        let (value, text) = (self.source)();
+        let value = value.clamp(0.0, 1.0);
+        window.fill_rect(x, y, width, height, INPUT_COLOR);
+        window.fill_rect(x, y, (width as f32 * value) as usize, height, if value > HIGH { HIGH_FILL_COLOR } else { FILL_COLOR });
+        draw_frame(window, self.bounds, FRAME_COLOR);
+        window.draw_text(x + height.saturating_sub(LINE_HEIGHT) / 2, y + 4, width.saturating_sub(8), &text, font::gui_font(), TEXT_COLOR);
+    }
+
+    fn handle_event(&mut self, _event: &Event) -> bool {
+        false
+    }
+}
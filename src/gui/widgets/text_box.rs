@@ -0,0 +1,79 @@
+use alloc::string::String;
+
+use crate::graphic::font;
+use crate::gui::clipboard;
+use crate::gui::widgets::{draw_frame, Bounds, Event, Widget, FOCUSED_FRAME_COLOR, FRAME_COLOR, INPUT_COLOR, LINE_HEIGHT, TEXT_COLOR};
+use crate::gui::window::Window;
+use crate::io::keyboard;
+
+/// 单行输入框，获得焦点后接收键盘输入，按回车时调用 `on_submit`
+///
+/// 没有选择文字的功能，Ctrl+C 和 Ctrl+X 复制或者剪切全部内容，Ctrl+V 粘贴剪贴板的第一行
+pub struct TextBox {
+    bounds: Bounds,
+    text: String,
+    on_submit: fn(&str),
+}
+
+impl TextBox {
+    pub fn new(bounds: Bounds, on_submit: fn(&str)) -> Self {
+        Self { bounds, text: String::new(), on_submit }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+impl Widget for TextBox {
+    fn bounds(&self) -> Bounds {
+        self.bounds
+    }
+
+    fn draw(&self, window: &mut Window, focused: bool) {
+        let Bounds { x, y, width, height } = self.bounds;
+        window.fill_rect(x, y, width, height, INPUT_COLOR);
+        draw_frame(window, self.bounds, if focused { FOCUSED_FRAME_COLOR } else { FRAME_COLOR });
+        let text_x = x + height.saturating_sub(LINE_HEIGHT) / 2;
+        let text_width = window.draw_text(text_x, y + 4, width.saturating_sub(10), &self.text, font::gui_font(), TEXT_COLOR);
+        // 光标
+        if focused {
+            window.fill_rect(text_x + 1, y + 4 + text_width, 1, LINE_HEIGHT - 2, TEXT_COLOR);
+        }
+    }
+
+    fn handle_event(&mut self, event: &Event) -> bool {
+        match *event {
+            Event::Key('\n') => {
+                (self.on_submit)(&self.text);
+                self.text.clear();
+                true
+            }
+            Event::Key('\x08') => self.text.pop().is_some(),
+            Event::Key(clipboard::COPY) => {
+                clipboard::set_text(&self.text);
+                false
+            }
+            Event::Key(clipboard::CUT) => {
+                clipboard::set_text(&self.text);
+                self.text.clear();
+                true
+            }
+            Event::Key(clipboard::PASTE) => {
+                let text = clipboard::get_text();
+                let line = text.lines().next().unwrap_or_default();
+                self.text.extend(line.chars().filter(|ch| !ch.is_control()));
+                !line.is_empty()
+            }
+            Event::Key(ch) if !ch.is_control() && !keyboard::is_special(ch) => {
+                self.text.push(ch);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn focusable(&self) -> bool {
+        true
+    }
+}
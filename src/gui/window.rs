@@ -0,0 +1,773 @@
+// 窗口管理
+// 每个窗口独占一个 z 为 layer::Z_WINDOW 的图层，在鼠标图层之下，提到最上层时重新设置一次 z，窗口图层的先后顺序就是叠放顺序。
+// 窗口的图层和窗口一样大，放在窗口所在的位置，移动和改变大小时跟着挪（见 Writer::place）。
+// 窗口内容画在各自的 surface 上，重绘时连同边框、标题栏一起画到窗口的图层，再请求在下一帧合成到屏幕（见 gui::frame）。
+// 拖动标题栏移动窗口，拖动右下角调整大小，点击窗口会把它提到最上层并获得焦点，点击标题栏右侧的按钮关闭窗口。
+// 窗口的图层和客户区按需分配，内存不够时先关掉最大的非必要窗口再试，还不够就放弃并在状态栏提示，不会 panic。
+// 也可以用键盘贴靠和平铺窗口，按键和布局见 tiling 模块。
+// 圆角和阴影按主题里的 WindowStyle 画：圆角之外的像素留成透明，阴影交给合成器（见 graphic::shadow）。
+// 窗口被挡住的部分因为上面的窗口移动、关闭或者自己被提到最上层而露出来，以及变大之后多出来的客户区，记为损坏区域（Damage），
+// 每个窗口合并成一个矩形，由 gui::poll 取走交给窗口里的控件（widgets::Event::Expose），程序只在这时重画，不用每一帧都画
+
+use alloc::collections::TryReserveError;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cmp::{max, min};
+
+use embedded_graphics::pixelcolor::Rgb888;
+use lazy_static::lazy_static;
+use spin::{Mutex, RwLock};
+
+use crate::assets;
+use crate::graphic::canvas::Canvas;
+use crate::graphic::font::{self, glyph, FontId};
+use crate::graphic::present::PresentFeedback;
+use crate::graphic::shadow::Shadow;
+use crate::graphic::sprite::Sprite;
+use crate::graphic::text;
+use crate::graphic::layer::{self, LayerHandle, Z_WINDOW};
+use crate::graphic::{self, Writer};
+use crate::gui::frame;
+use crate::gui::tiling::{self, Layout, Rect, Snap};
+use crate::io::theme::{self, WindowStyle};
+use crate::rgb888;
+
+pub const TITLE_BAR_HEIGHT: usize = 20;
+pub const BORDER: usize = 1;
+// 右下角可以拖动调整大小的区域
+const RESIZE_HANDLE: usize = 10;
+const MIN_WIDTH: usize = 80;
+const MIN_HEIGHT: usize = TITLE_BAR_HEIGHT + 20;
+const TITLE_FONT_SIZE: f32 = 16.0;
+
+const BORDER_COLOR: Rgb888 = rgb888!(0x263238u32);
+const TITLE_COLOR: Rgb888 = rgb888!(0x607D8Bu32);
+const FOCUSED_TITLE_COLOR: Rgb888 = rgb888!(0x0277BDu32);
+const TITLE_TEXT_COLOR: Rgb888 = rgb888!(0xFFFFFFu32);
+pub const CLIENT_COLOR: Rgb888 = rgb888!(0xECEFF1u32);
+
+const CLOSE_BUTTON: &str = "icons/window-close.qoi";
+// 关闭按钮的边长和它到标题栏边缘的距离
+const CLOSE_BUTTON_SIZE: usize = 16;
+const CLOSE_BUTTON_MARGIN: usize = 2;
+
+lazy_static! {
+    // 换主题时由 reload_icons 重新取
+    static ref CLOSE_BUTTON_SPRITE: RwLock<Sprite> =
+        RwLock::new(assets::sprite(CLOSE_BUTTON).expect("Failed to decode close button"));
+}
+
+/// 重新取窗口的图标，之后重画窗口时生效
+pub fn reload_icons() {
+    if let Some(sprite) = assets::sprite(CLOSE_BUTTON) {
+        *CLOSE_BUTTON_SPRITE.write() = sprite;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowId(usize);
+
+/// 窗口里需要重画的区域
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Damage {
+    pub window: WindowId,
+    /// 窗口的图层，区域在图层里的位置还要加上标题栏和边框
+    pub layer: LayerHandle,
+    /// 相对于客户区左上角
+    pub region: Rect,
+}
+
+/// 窗口，位置和大小都包含边框和标题栏
+///
+/// 坐标和 graphic 模块一致：x 是行，y 是列
+pub struct Window {
+    id: WindowId,
+    title: String,
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    // 客户区内容，按行存放
+    surface: Vec<Vec<Rgb888>>,
+    // 必要的窗口（比如终端）在内存不够时不会被关掉
+    essential: bool,
+    // 贴靠或平铺之前的位置和大小，恢复时用
+    floating: Option<Rect>,
+    layer: LayerHandle,
+}
+
+impl Window {
+    pub fn id(&self) -> WindowId {
+        self.id
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// 窗口左上角的位置
+    pub fn position(&self) -> (usize, usize) {
+        (self.x, self.y)
+    }
+
+    /// 客户区的宽和高
+    pub fn client_size(&self) -> (usize, usize) {
+        client_size(self.width, self.height)
+    }
+
+    /// 在客户区中画一个像素，超出客户区的部分被忽略
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: Rgb888) {
+        if let Some(pixel) = self.surface.get_mut(x).and_then(|row| row.get_mut(y)) {
+            *pixel = color;
+        }
+    }
+
+    /// 用一种颜色填满客户区
+    pub fn fill(&mut self, color: Rgb888) {
+        for row in self.surface.iter_mut() {
+            row.fill(color);
+        }
+    }
+
+    /// 在客户区中画一个实心矩形
+    pub fn fill_rect(&mut self, x: usize, y: usize, w: usize, h: usize, color: Rgb888) {
+        for i in x..x + h {
+            for j in y..y + w {
+                self.set_pixel(i, j, color);
+            }
+        }
+    }
+
+    /// 在客户区中用 `font` 写一行字，超过 `max_width` 的部分不画，返回实际占用的宽度
+    pub fn draw_text(&mut self, x: usize, y: usize, max_width: usize, text: &str, font: FontId, color: Rgb888) -> usize {
+        self.draw_text_sized(x, y, max_width, text, font, TITLE_FONT_SIZE, color)
+    }
+
+    /// 同 draw_text，字号为 `size`
+    pub fn draw_text_sized(&mut self, x: usize, y: usize, max_width: usize, text: &str, font: FontId, size: f32,
+                           color: Rgb888) -> usize {
+        let mut width = 0;
+        for ch in text.chars() {
+            let glyph = glyph(font, ch, size);
+            let advance = glyph.advance as usize + 1;
+            if width + advance > max_width {
+                break;
+            }
+            text::draw_glyph(self, &glyph, x, y + width, size as usize, color);
+            width += advance;
+        }
+        width
+    }
+
+    fn contains(&self, x: usize, y: usize) -> bool {
+        x >= self.x && x < self.x + self.height && y >= self.y && y < self.y + self.width
+    }
+
+    fn in_title_bar(&self, x: usize) -> bool {
+        x < self.x + TITLE_BAR_HEIGHT
+    }
+
+    // 关闭按钮左上角的位置，必要的窗口没有关闭按钮
+    fn close_button(&self) -> Option<(usize, usize)> {
+        if self.essential {
+            return None;
+        }
+        Some((self.x + CLOSE_BUTTON_MARGIN, self.y + self.width - BORDER - CLOSE_BUTTON_MARGIN - CLOSE_BUTTON_SIZE))
+    }
+
+    fn in_close_button(&self, x: usize, y: usize) -> bool {
+        self.close_button().map_or(false, |(bx, by)| {
+            x >= bx && x < bx + CLOSE_BUTTON_SIZE && y >= by && y < by + CLOSE_BUTTON_SIZE
+        })
+    }
+
+    fn in_resize_handle(&self, x: usize, y: usize) -> bool {
+        x + RESIZE_HANDLE >= self.x + self.height && y + RESIZE_HANDLE >= self.y + self.width
+    }
+
+    fn rect(&self) -> Rect {
+        Rect { x: self.x, y: self.y, width: self.width, height: self.height }
+    }
+
+    // 窗口连同阴影在屏幕上占据的区域 (sx, sy, ex, ey)
+    fn bounds(&self) -> (usize, usize, usize, usize) {
+        let style = theme::get().window;
+        let margin = if style.shadow_radius > 0 {
+            style.shadow_radius + max(style.shadow_offset.0, style.shadow_offset.1)
+        } else {
+            0
+        };
+        (self.x.saturating_sub(margin), self.y.saturating_sub(margin),
+         min(self.x + self.height + margin, graphic::height()), min(self.y + self.width + margin, graphic::width()))
+    }
+
+    fn shadow(&self, style: &WindowStyle) -> Option<Shadow> {
+        if style.shadow_radius == 0 || style.shadow_alpha == 0 {
+            return None;
+        }
+        let caster = graphic::Region::new(self.x, self.y, self.x + self.height, self.y + self.width);
+        Some(Shadow::new(caster, style.shadow_offset, style.shadow_radius, style.shadow_alpha))
+    }
+
+    // 改变大小后重新分配客户区，保留重叠部分的内容；内存不够时客户区保持原来的大小
+    fn resize_surface(&mut self) -> Result<(), TryReserveError> {
+        let (width, height) = self.client_size();
+        resize_client(&mut self.surface, width, height)
+    }
+
+    fn draw(&self, layer: &mut Writer, focused: bool, style: &WindowStyle) {
+        layer.display_rect(self.x, self.y, self.width, self.height, BORDER_COLOR);
+        let title_color = if focused { FOCUSED_TITLE_COLOR } else { TITLE_COLOR };
+        layer.display_rect(self.x, self.y + BORDER, self.width - 2 * BORDER, TITLE_BAR_HEIGHT - BORDER, title_color);
+
+        // 标题超出标题栏（或者碰到关闭按钮）的部分不画
+        let title_end = match self.close_button() {
+            Some((bx, by)) => {
+                CLOSE_BUTTON_SPRITE.read().draw(layer, bx, by);
+                by - 4
+            }
+            None => self.y + self.width - 4,
+        };
+        let mut y = self.y + 4;
+        for ch in self.title.chars() {
+            let glyph = glyph(font::gui_font(), ch, TITLE_FONT_SIZE);
+            let advance = glyph.advance as usize + 1;
+            if y + advance > title_end {
+                break;
+            }
+            layer.display_font(&glyph, self.x + 1, y, 16, TITLE_TEXT_COLOR);
+            y += advance;
+        }
+
+        let client_x = self.x + TITLE_BAR_HEIGHT;
+        let client_y = self.y + BORDER;
+        for (i, row) in self.surface.iter().enumerate() {
+            for (j, color) in row.iter().enumerate() {
+                layer.display_pixel_safe(client_x + i, client_y + j, *color);
+            }
+        }
+        self.round_corners(layer, style.corner_radius);
+    }
+
+    // 把四个角圆弧之外的像素改回透明，圆弧上的像素画成边框的颜色
+    fn round_corners(&self, layer: &mut Writer, radius: usize) {
+        let r = min(radius, min(self.width, self.height) / 2);
+        let (ex, ey) = (self.x + self.height - 1, self.y + self.width - 1);
+        for i in 0..r {
+            for j in 0..r {
+                // 像素中心到圆心的距离，以半个像素为单位
+                let (a, b) = (2 * (r - i) - 1, 2 * (r - j) - 1);
+                let distance = a * a + b * b;
+                if distance <= 4 * (r - 1) * (r - 1) {
+                    continue;
+                }
+                for (x, y) in [(self.x + i, self.y + j), (self.x + i, ey - j), (ex - i, self.y + j), (ex - i, ey - j)] {
+                    if distance > 4 * r * r {
+                        layer.clear_rect(x, y, 1, 1);
+                    } else {
+                        layer.display_pixel_safe(x, y, BORDER_COLOR);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// 宽 `width`、高 `height` 的窗口的客户区大小
+fn client_size(width: usize, height: usize) -> (usize, usize) {
+    (width - 2 * BORDER, height - TITLE_BAR_HEIGHT - BORDER)
+}
+
+// 把客户区改成宽 `width`、高 `height`，保留重叠部分的内容；内存不够时返回错误，客户区保持不变
+fn resize_client(surface: &mut Vec<Vec<Rgb888>>, width: usize, height: usize) -> Result<(), TryReserveError> {
+    // 先把要用的内存都申请好，失败时不改动客户区
+    surface.try_reserve(height.saturating_sub(surface.len()))?;
+    let mut new_rows = Vec::new();
+    new_rows.try_reserve_exact(height.saturating_sub(surface.len()))?;
+    for _ in surface.len()..height {
+        let mut row = Vec::new();
+        row.try_reserve_exact(width)?;
+        new_rows.push(row);
+    }
+    for row in surface.iter_mut().take(height) {
+        row.try_reserve(width.saturating_sub(row.len()))?;
+    }
+    surface.truncate(height);
+    surface.append(&mut new_rows);
+    for row in surface.iter_mut() {
+        row.resize(width, CLIENT_COLOR);
+    }
+    Ok(())
+}
+
+// 在客户区中画图
+impl Canvas for Window {
+    fn size(&self) -> (usize, usize) {
+        let (width, height) = self.client_size();
+        (height, width)
+    }
+
+    fn put_pixel(&mut self, x: usize, y: usize, color: Rgb888) {
+        self.set_pixel(x, y, color);
+    }
+
+    fn get_pixel(&self, x: usize, y: usize) -> Option<Rgb888> {
+        self.surface.get(x).and_then(|row| row.get(y)).copied()
+    }
+
+    fn fill_span(&mut self, x: usize, y0: usize, y1: usize, color: Rgb888) {
+        self.surface[x][y0..y1].fill(color);
+    }
+}
+
+enum Drag {
+    None,
+    // 上一次处理时的光标位置
+    Move { last_x: usize, last_y: usize },
+    Resize,
+}
+
+/// 窗口管理器，`windows` 中越靠后的窗口越靠上，最后一个拥有焦点
+pub struct WindowManager {
+    windows: Vec<Window>,
+    next_id: usize,
+    drag: Drag,
+    left_pressed: bool,
+    layout: Layout,
+    // 上次画窗口时的主题，主题变了要重画所有窗口
+    theme_generation: usize,
+    // 还没被取走的损坏区域，每个窗口最多一个
+    damage: Vec<Damage>,
+}
+
+lazy_static! {
+    pub static ref WINDOW_MANAGER: Mutex<WindowManager> = Mutex::new(WindowManager {
+        windows: Vec::new(),
+        next_id: 0,
+        drag: Drag::None,
+        left_pressed: false,
+        layout: Layout::Floating,
+        theme_generation: theme::generation(),
+        damage: Vec::new(),
+    });
+}
+
+impl WindowManager {
+    fn index_of(&self, id: WindowId) -> Option<usize> {
+        self.windows.iter().position(|w| w.id == id)
+    }
+
+    /// 新建一个窗口，放在最上层
+    ///
+    /// 内存不够时会关掉最大的非必要窗口再试，仍然不够时返回错误
+    pub fn create(&mut self, title: &str, x: usize, y: usize, width: usize, height: usize) -> Result<WindowId, TryReserveError> {
+        let x = min(x, graphic::height() - TITLE_BAR_HEIGHT);
+        let y = min(y, graphic::width() - MIN_WIDTH);
+        // 屏幕小（比如 320x200 的 VGA 模式）时缩小到屏幕里放得下
+        let width = max(min(width, graphic::width() - y), MIN_WIDTH);
+        let height = max(min(height, graphic::height() - x), MIN_HEIGHT);
+        let (client_width, client_height) = client_size(width, height);
+        let mut surface = Vec::new();
+        let mut layer = loop {
+            match resize_client(&mut surface, client_width, client_height).and_then(|_| Writer::try_with_size(x, y, width, height)) {
+                Ok(layer) => break layer,
+                Err(error) => {
+                    if self.close_largest().is_none() {
+                        crate::gui::report_oom(title);
+                        return Err(error);
+                    }
+                }
+            }
+        };
+        let id = WindowId(self.next_id);
+        self.next_id += 1;
+        layer.enable = true;
+        self.windows.push(Window {
+            id,
+            title: String::from(title),
+            x,
+            y,
+            width,
+            height,
+            surface,
+            essential: false,
+            floating: None,
+            layer: layer::add_layer(layer, Z_WINDOW),
+        });
+
+        // 原来的顶层窗口失去焦点
+        if self.windows.len() > 1 {
+            self.redraw_at(self.windows.len() - 2);
+        }
+        self.redraw_at(self.windows.len() - 1);
+        self.retile();
+        Ok(id)
+    }
+
+    /// 标记为必要窗口，内存不够时不会被关掉
+    pub fn set_essential(&mut self, id: WindowId, essential: bool) {
+        if let Some(index) = self.index_of(id) {
+            self.windows[index].essential = essential;
+        }
+    }
+
+    /// 内存不够时的最后手段：关掉面积最大的非必要窗口，返回它的标题
+    pub fn close_largest(&mut self) -> Option<String> {
+        let window = self.windows.iter()
+            .filter(|w| !w.essential)
+            .max_by_key(|w| w.width * w.height)?;
+        let (id, title) = (window.id, window.title.clone());
+        log::warn!("Out of memory, closing window \"{}\"", title);
+        self.close(id);
+        Some(title)
+    }
+
+    /// 关闭窗口
+    pub fn close(&mut self, id: WindowId) {
+        let Some(index) = self.index_of(id) else { return };
+        let window = self.windows.remove(index);
+        layer::remove_layer(window.layer);
+        let (sx, sy, ex, ey) = window.bounds();
+        frame::request(sx, sy, ex, ey);
+        self.damage.retain(|damage| damage.window != id);
+        self.expose(window.rect(), index);
+        if let Some(top) = self.windows.len().checked_sub(1) {
+            self.redraw_at(top);
+        }
+        self.retile();
+    }
+
+    pub fn window(&self, id: WindowId) -> Option<&Window> {
+        self.windows.iter().find(|w| w.id == id)
+    }
+
+    /// 修改窗口内容后需要调用 `redraw` 才会显示到屏幕上
+    pub fn window_mut(&mut self, id: WindowId) -> Option<&mut Window> {
+        self.windows.iter_mut().find(|w| w.id == id)
+    }
+
+    /// 光标所在的最上层窗口，以及光标在它客户区中的坐标；不在客户区时返回 None
+    pub fn client_at(&self, x: usize, y: usize) -> Option<(WindowId, usize, usize)> {
+        let window = self.windows.iter().rev().find(|w| w.contains(x, y))?;
+        let client_x = x.checked_sub(window.x + TITLE_BAR_HEIGHT)?;
+        let client_y = y.checked_sub(window.y + BORDER)?;
+        let (width, height) = window.client_size();
+        (client_x < height && client_y < width).then_some((window.id, client_x, client_y))
+    }
+
+    /// 屏幕上的点相对于窗口 `id` 客户区的坐标，在客户区外时取客户区里最近的点
+    pub fn client_point(&self, id: WindowId, x: usize, y: usize) -> Option<(usize, usize)> {
+        let window = self.window(id)?;
+        let (width, height) = window.client_size();
+        let client_x = x.saturating_sub(window.x + TITLE_BAR_HEIGHT).min(height.saturating_sub(1));
+        let client_y = y.saturating_sub(window.y + BORDER).min(width.saturating_sub(1));
+        Some((client_x, client_y))
+    }
+
+    /// 拥有焦点的窗口
+    pub fn focused(&self) -> Option<WindowId> {
+        self.windows.last().map(|w| w.id)
+    }
+
+    pub fn redraw(&self, id: WindowId) {
+        if let Some(index) = self.index_of(id) {
+            self.redraw_at(index);
+        }
+    }
+
+    /// 窗口最近一次重画的内容在哪一帧、什么时候显示到屏幕上
+    ///
+    /// 动画按它补偿合成的延迟，基准测试用它量端到端的延迟
+    pub fn present_feedback(&self, id: WindowId) -> Option<PresentFeedback> {
+        let window = self.window(id)?;
+        layer::with_layer(window.layer, |layer| layer.present_feedback()).flatten()
+    }
+
+    /// 重画所有窗口，切换分辨率后图层内容被清空时使用
+    pub fn redraw_all(&self) {
+        for index in 0..self.windows.len() {
+            // 大小没变，不会失败
+            let _ = self.place_layer(index);
+            self.redraw_at(index);
+        }
+    }
+
+    /// 主题变了的话按新的圆角和阴影重画所有窗口
+    ///
+    /// 由 gui::poll 调用
+    pub fn apply_theme(&mut self) {
+        let generation = theme::generation();
+        if generation == self.theme_generation {
+            return;
+        }
+        self.theme_generation = generation;
+        for index in 0..self.windows.len() {
+            layer::with_layer(self.windows[index].layer, Writer::clear);
+            self.redraw_at(index);
+        }
+        frame::request(0, 0, graphic::height(), graphic::width());
+    }
+
+    /// 把窗口提到最上层并获得焦点
+    pub fn raise(&mut self, id: WindowId) {
+        let Some(index) = self.index_of(id) else { return };
+        let top = self.windows.len() - 1;
+        if index == top {
+            return;
+        }
+        layer::set_z_order(self.windows[index].layer, Z_WINDOW);
+        // 原来压在它上面的窗口挡住的部分露出来
+        for above in index + 1..self.windows.len() {
+            if let Some(overlap) = self.windows[above].rect().intersection(&self.windows[index].rect()) {
+                self.damage_screen(index, overlap);
+            }
+        }
+        let window = self.windows.remove(index);
+        self.windows.push(window);
+        self.redraw_at(top - 1);
+        self.redraw_at(top);
+        // 主窗口布局中焦点窗口是主窗口
+        if self.layout == Layout::Master {
+            self.retile();
+        }
+    }
+
+    /// 窗口的不透明度，窗口不存在时返回 None
+    pub fn opacity(&self, id: WindowId) -> Option<f32> {
+        let window = self.window(id)?;
+        layer::with_layer(window.layer, |layer| layer.opacity())
+    }
+
+    /// 设置窗口的不透明度，1.0 为完全不透明
+    pub fn set_opacity(&mut self, id: WindowId, opacity: f32) {
+        let Some(window) = self.window(id) else { return };
+        layer::with_layer(window.layer, |layer| layer.set_opacity(opacity));
+        let (sx, sy, ex, ey) = window.bounds();
+        frame::request(sx, sy, ex, ey);
+    }
+
+    /// 移动窗口，标题栏始终留在屏幕内
+    pub fn move_to(&mut self, id: WindowId, x: usize, y: usize) {
+        let Some(window) = self.window(id) else { return };
+        let rect = Rect { x, y, ..window.rect() };
+        self.set_geometry(id, rect);
+    }
+
+    /// 调整窗口大小，客户区中原有的内容保留在左上角
+    pub fn resize(&mut self, id: WindowId, width: usize, height: usize) {
+        let Some(window) = self.window(id) else { return };
+        let rect = Rect { width, height, ..window.rect() };
+        self.set_geometry(id, rect);
+    }
+
+    /// 同时移动窗口和调整大小，只重画一次；内存不够时大小保持不变
+    pub fn set_geometry(&mut self, id: WindowId, rect: Rect) {
+        let Some(index) = self.index_of(id) else { return };
+        let old = self.windows[index].bounds();
+        let old_rect = self.windows[index].rect();
+        let window = &mut self.windows[index];
+        window.x = min(rect.x, graphic::height() - TITLE_BAR_HEIGHT);
+        window.y = min(rect.y, graphic::width() - MIN_WIDTH);
+        let (old_width, old_height) = (window.width, window.height);
+        window.width = max(rect.width, MIN_WIDTH);
+        window.height = max(rect.height, MIN_HEIGHT);
+        let resized = (window.width, window.height) != (old_width, old_height);
+        // 先挪图层再改客户区，客户区失败时图层改回原来的大小，缩小回去不会失败
+        if self.place_layer(index).is_err() || (resized && self.windows[index].resize_surface().is_err()) {
+            let window = &mut self.windows[index];
+            window.width = old_width;
+            window.height = old_height;
+            crate::gui::report_oom(&window.title);
+            let _ = self.place_layer(index);
+        }
+        self.redraw_at(index);
+        let (sx, sy, ex, ey) = old;
+        frame::request(sx, sy, ex, ey);
+
+        let window = &self.windows[index];
+        let (old_client_width, old_client_height) = client_size(old_width, old_height);
+        let (client_width, client_height) = window.client_size();
+        if window.rect() != old_rect {
+            self.expose(old_rect, index);
+        }
+        // 变大之后多出来的客户区
+        if client_width > old_client_width {
+            let region = Rect { x: 0, y: old_client_width, width: client_width - old_client_width, height: client_height };
+            self.damage_client(index, region);
+        }
+        if client_height > old_client_height {
+            let region = Rect { x: old_client_height, y: 0, width: client_width, height: client_height - old_client_height };
+            self.damage_client(index, region);
+        }
+    }
+
+    /// 取走积压的损坏区域，由 gui::poll 调用
+    pub fn take_damage(&mut self) -> Vec<Damage> {
+        core::mem::take(&mut self.damage)
+    }
+
+    // 屏幕上的 `area` 露出来了：下标小于 `below` 的窗口和它重叠、又没有被更上层的某个窗口整个挡住的部分记为损坏
+    fn expose(&mut self, area: Rect, below: usize) {
+        for index in 0..below.min(self.windows.len()) {
+            let Some(revealed) = area.intersection(&self.windows[index].rect()) else { continue };
+            if self.windows[index + 1..].iter().any(|w| w.rect().contains(&revealed)) {
+                continue;
+            }
+            self.damage_screen(index, revealed);
+        }
+    }
+
+    // 屏幕上的 `area` 落在第 `index` 个窗口客户区里的部分记为损坏，标题栏和边框由窗口管理器自己画
+    fn damage_screen(&mut self, index: usize, area: Rect) {
+        let window = &self.windows[index];
+        let (width, height) = window.client_size();
+        let client = Rect { x: window.x + TITLE_BAR_HEIGHT, y: window.y + BORDER, width, height };
+        if let Some(region) = area.intersection(&client) {
+            self.damage_client(index, Rect { x: region.x - client.x, y: region.y - client.y, ..region });
+        }
+    }
+
+    // 和这个窗口已有的损坏区域合并成一个矩形
+    fn damage_client(&mut self, index: usize, region: Rect) {
+        let (window, layer) = (self.windows[index].id, self.windows[index].layer);
+        match self.damage.iter_mut().find(|damage| damage.window == window) {
+            Some(damage) => damage.region = damage.region.union(&region),
+            None => self.damage.push(Damage { window, layer, region }),
+        }
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// 切换布局，回到浮动布局时所有窗口恢复平铺之前的位置和大小
+    pub fn set_layout(&mut self, layout: Layout) {
+        self.layout = layout;
+        if layout != Layout::Floating {
+            self.retile();
+            return;
+        }
+        for index in 0..self.windows.len() {
+            let window = &mut self.windows[index];
+            if let Some(rect) = window.floating.take() {
+                let id = window.id;
+                self.set_geometry(id, rect);
+            }
+        }
+    }
+
+    /// 把焦点窗口贴靠到屏幕的一半、四分之一或者最大化
+    pub fn snap_focused(&mut self, snap: Snap) {
+        let Some(window) = self.windows.last_mut() else { return };
+        let id = window.id;
+        if window.floating.is_none() {
+            window.floating = Some(window.rect());
+        }
+        self.set_geometry(id, tiling::snap_rect(snap, tiling::work_area()));
+    }
+
+    /// 焦点窗口恢复贴靠之前的位置和大小
+    pub fn restore_focused(&mut self) {
+        let Some(window) = self.windows.last_mut() else { return };
+        let id = window.id;
+        if let Some(rect) = window.floating.take() {
+            self.set_geometry(id, rect);
+        }
+    }
+
+    /// 焦点切换到最下面的窗口，连续使用时轮流经过每个窗口
+    pub fn focus_next(&mut self) {
+        if self.windows.len() > 1 {
+            self.raise(self.windows[0].id);
+        }
+    }
+
+    // 非浮动布局下按布局重新摆放所有窗口。按创建顺序排列，主窗口布局中焦点窗口排在最前面
+    fn retile(&mut self) {
+        if self.layout == Layout::Floating || self.windows.is_empty() {
+            return;
+        }
+        let mut order: Vec<WindowId> = self.windows.iter().map(|w| w.id).collect();
+        order.sort_by_key(|id| id.0);
+        if self.layout == Layout::Master {
+            let focused = self.windows[self.windows.len() - 1].id;
+            order.retain(|&id| id != focused);
+            order.insert(0, focused);
+        }
+        let area = tiling::work_area();
+        for (i, &id) in order.iter().enumerate() {
+            let rect = tiling::tile_rect(self.layout, area, order.len(), i);
+            if let Some(window) = self.window_mut(id) {
+                if window.floating.is_none() {
+                    window.floating = Some(window.rect());
+                }
+            }
+            self.set_geometry(id, rect);
+        }
+    }
+
+    /// 处理鼠标，`x`、`y` 是光标位置，`left` 是左键当前是否按下
+    pub fn handle_mouse(&mut self, x: usize, y: usize, left: bool) {
+        let pressed = left && !self.left_pressed;
+        self.left_pressed = left;
+        if !left {
+            self.drag = Drag::None;
+            return;
+        }
+
+        if pressed {
+            let Some(index) = self.windows.iter().rposition(|w| w.contains(x, y)) else { return };
+            let window = &self.windows[index];
+            let id = window.id;
+            if window.in_close_button(x, y) {
+                self.drag = Drag::None;
+                self.close(id);
+                return;
+            }
+            self.drag = if window.in_resize_handle(x, y) {
+                Drag::Resize
+            } else if window.in_title_bar(x) {
+                Drag::Move { last_x: x, last_y: y }
+            } else {
+                Drag::None
+            };
+            self.raise(id);
+            return;
+        }
+
+        let Some(window) = self.windows.last() else { return };
+        let id = window.id;
+        match self.drag {
+            Drag::None => {}
+            Drag::Move { last_x, last_y } => {
+                let new_x = (window.x + x).saturating_sub(last_x);
+                let new_y = (window.y + y).saturating_sub(last_y);
+                self.drag = Drag::Move { last_x: x, last_y: y };
+                self.move_to(id, new_x, new_y);
+            }
+            Drag::Resize => {
+                let width = (y + 1).saturating_sub(window.y);
+                let height = (x + 1).saturating_sub(window.x);
+                self.resize(id, width, height);
+            }
+        }
+    }
+
+    // 把窗口的图层挪到窗口现在的位置并改成窗口的大小，原有内容被清空
+    fn place_layer(&self, index: usize) -> Result<(), TryReserveError> {
+        let window = &self.windows[index];
+        layer::with_layer(window.layer, |layer| layer.place(window.x, window.y, window.width, window.height)).unwrap_or(Ok(()))
+    }
+
+    fn redraw_at(&self, index: usize) {
+        let window = &self.windows[index];
+        let focused = index == self.windows.len() - 1;
+        let style = theme::get().window;
+        layer::with_layer(window.layer, |layer| {
+            layer.set_shadow(window.shadow(&style));
+            window.draw(layer, focused, &style);
+        });
+        let (sx, sy, ex, ey) = window.bounds();
+        frame::request(sx, sy, ex, ey);
+    }
+}
@@ -0,0 +1,282 @@
+// 界面文字的翻译
+// 要给用户看的固定文字登记在下面的表里，每条一个 Msg，编译时就定下每种语言的写法，运行时按当前语言取出来。
+// 语言由启动参数 lang=en|zh 决定（默认 en），shell 的 lang 命令可以随时切换；已经打开的窗口标题不会跟着变。
+// 文字里的 {} 按顺序换成参数，和 format! 一样但只认 {}，也不支持对齐。
+// 日志、panic 报告和给脚本解析的输出保持英文，不经过这里；shell 里仿照 Linux 工具格式的表格和统计行（lspci、ifconfig、ping、uname 等）也一样。
+// VGA 文本模式的字库里没有汉字，zh 时进入图形模式之前打印的几行会显示成方块
+//
+// 加一条文字：在 messages! 里加一行，写上各语言的版本；取文字用 tr，带参数的用 fill：
+//   println!("{}", i18n::fill(Msg::BootTime, &[&time]));
+
+use core::fmt::{self, Display};
+use core::sync::atomic::{AtomicU8, Ordering};
+
+/// 界面语言
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(u8)]
+pub enum Language {
+    #[default]
+    En = 0,
+    Zh = 1,
+}
+
+impl Language {
+    pub const ALL: [Language; 2] = [Language::En, Language::Zh];
+
+    /// 按名字查找，名字就是启动参数里写的值
+    pub fn by_name(name: &str) -> Option<Language> {
+        match name {
+            "en" | "en_US" => Some(Language::En),
+            "zh" | "zh_CN" => Some(Language::Zh),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::En => "en",
+            Language::Zh => "zh",
+        }
+    }
+
+    /// 用这种语言自己写的名字
+    pub fn native_name(self) -> &'static str {
+        match self {
+            Language::En => "English",
+            Language::Zh => "中文",
+        }
+    }
+}
+
+// 用原子变量保存，panic 时也能读
+static LANGUAGE: AtomicU8 = AtomicU8::new(Language::En as u8);
+
+/// 当前的界面语言
+pub fn language() -> Language {
+    match LANGUAGE.load(Ordering::Relaxed) {
+        1 => Language::Zh,
+        _ => Language::En,
+    }
+}
+
+pub fn set_language(language: Language) {
+    LANGUAGE.store(language as u8, Ordering::Relaxed);
+}
+
+/// 按启动参数设置语言，由 cjn_os::init 在读完启动参数之后调用
+pub fn init() {
+    set_language(crate::config::language().unwrap_or_default());
+}
+
+// 定义 Msg 和每条文字各语言的写法，顺序和 Language 一致
+macro_rules! messages {
+    ($($id:ident => [$en:literal, $zh:literal],)*) => {
+        /// 界面文字的编号
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum Msg {
+            $($id,)*
+        }
+
+        const TABLE: &[[&str; Language::ALL.len()]] = &[$([$en, $zh],)*];
+    };
+}
+
+messages! {
+    // 启动
+    Loading => ["Loading Cjn's OS...", "正在加载 Cjn's OS……"],
+    BootTime => ["Boot time: {} UTC", "启动时间：{} UTC"],
+    HeapInit => ["Waiting for initializing the heap memory...", "正在初始化堆内存……"],
+    Motto => ["A journey of a thousand miles begins with a single step", "万里之行, 始于足下"],
+    // shell
+    ShellHint => ["Type `help` to list available commands.", "输入 `help` 列出可用的命令。"],
+    CommandNotFound => ["{}: command not found, try `help`", "{}：找不到命令，试试 `help`"],
+    LanguageCurrent => ["language: {} ({})", "语言：{}（{}）"],
+    LanguageUnknown => ["lang: unknown language {}", "lang：不认识的语言 {}"],
+    // help 列出的命令说明
+    HelpHelp => ["list available commands", "列出可用的命令"],
+    HelpMem => ["memory usage: mem [map|-w], map lists the boot memory map, -w opens a window", "内存使用情况：mem [map|-w]，map 列出启动时的内存布局，-w 打开窗口"],
+    HelpVmmap => ["page table mappings: vmmap [<start> <end>], or translate one address: vmmap <addr>", "页表映射：vmmap [<start> <end>]，或翻译一个地址：vmmap <addr>"],
+    HelpAssets => ["list fonts, icons and wallpapers with their cache usage", "列出字体、图标和壁纸以及它们占用的缓存"],
+    HelpLspci => ["list PCI devices: lspci [-v]", "列出 PCI 设备：lspci [-v]"],
+    HelpLsdev => ["alias of lspci", "lspci 的别名"],
+    HelpIrq => ["interrupt handlers, counts and deferred work queues", "中断处理函数、中断次数和延后执行的工作队列"],
+    HelpIoports => ["I/O port ranges and the drivers that own them", "I/O 端口范围和占用它们的驱动"],
+    HelpLsinput => ["list input devices and the number of events each has sent", "列出输入设备和各自发出的事件数"],
+    HelpRescan => ["rescan the PCI bus for added or removed devices", "重新扫描 PCI 总线，找出新增或移除的设备"],
+    HelpLsblk => ["list block devices", "列出块设备"],
+    HelpSync => ["write cached disk blocks back and show block cache statistics", "把缓存的磁盘块写回，并显示块缓存的统计"],
+    HelpUptime => ["show time since boot", "显示开机以来的时间"],
+    HelpLocale => ["number format: locale [c|en|de|fr]", "数字格式：locale [c|en|de|fr]"],
+    HelpLang => ["interface language: lang [en|zh]", "界面语言：lang [en|zh]"],
+    HelpUname => ["print system information: uname [-asnrvm]", "输出系统信息：uname [-asnrvm]"],
+    HelpAbout => ["show version and build information", "显示版本和构建信息"],
+    HelpBoot => ["list boot units in init order with their status and time", "按初始化顺序列出启动单元和它们的状态、耗时"],
+    HelpBg => ["run a command with its output captured for later: bg <command> [args]", "执行命令，把输出留下来以后看：bg <command> [args]"],
+    HelpJobs => ["list captured command output", "列出留下来的命令输出"],
+    HelpOutput => ["show captured output: output <id> [-w|-d], -w opens a window, -d deletes it", "显示留下来的输出：output <id> [-w|-d]，-w 打开窗口，-d 删除"],
+    HelpCpus => ["list CPUs, or run test tasks on them: cpus [test <count>]", "列出 CPU，或在上面执行测试任务：cpus [test <count>]"],
+    HelpEdit => ["edit a text file in a window: edit [<name>], without a name lists saved files", "在窗口里编辑文本文件：edit [<name>]，不带名字时列出保存的文件"],
+    HelpSnake => ["play snake in a window: arrows or WASD to steer, space to pause", "在窗口里玩贪吃蛇：方向键或 WASD 转向，空格暂停"],
+    HelpTop => ["open the system monitor window with live heap, frame, CPU and interrupt stats", "打开系统监视器窗口，实时显示堆、物理帧、CPU 和中断的统计"],
+    HelpAt => ["print a message at a local time: at [<hh:mm[:ss]> <message>|cancel <id>]", "到本地时间的某个时刻输出一条消息：at [<hh:mm[:ss]> <message>|cancel <id>]"],
+    HelpRemind => ["pop up a reminder at a local time: remind <hh:mm[:ss]> <text>", "到本地时间的某个时刻弹出提醒：remind <hh:mm[:ss]> <text>"],
+    HelpIfconfig => ["show or set the network address: ifconfig [dhcp|<ip>/<prefix> [gateway]]", "显示或设置网络地址：ifconfig [dhcp|<ip>/<prefix> [gateway]]"],
+    HelpHost => ["look up a host name: host <name>", "查询主机名：host <name>"],
+    HelpPing => ["send ICMP echo requests: ping [-c <count>] <host>", "发送 ICMP 回显请求：ping [-c <count>] <host>"],
+    HelpWget => ["download a web page and print it: wget <url>", "下载网页并输出：wget <url>"],
+    HelpFetch => ["download a web page into a window: fetch <url>", "下载网页并显示在窗口里：fetch <url>"],
+    HelpBeep => ["sound the PC speaker: beep [<hz> [<ms>]]", "让 PC 喇叭响一声：beep [<hz> [<ms>]]"],
+    HelpPlay => ["play a WAV asset on the sound card: play <name>|stop", "用声卡播放 WAV 资源：play <name>|stop"],
+    HelpRun => ["run a user program in ring 3: run [<program>]", "在 ring 3 运行用户程序：run [<program>]"],
+    HelpClear => ["clear the screen", "清屏"],
+    HelpMode => ["show or set display mode: mode [<width> <height> [bpp]]", "显示或设置显示模式：mode [<width> <height> [bpp]]"],
+    HelpTrace => ["event tracing: trace start|stop|clear|dump", "事件跟踪：trace start|stop|clear|dump"],
+    HelpDmesg => ["recent kernel log: dmesg [-c] [-l <level>] [-w], -w opens a window", "最近的内核日志：dmesg [-c] [-l <level>] [-w]，-w 打开窗口"],
+    HelpPerf => ["scope profiling: perf start|stop|clear|report [own]|folded", "按作用域采样分析：perf start|stop|clear|report [own]|folded"],
+    HelpLatency => ["measure input latency: latency [key|mouse [count]]", "测量输入延迟：latency [key|mouse [count]]"],
+    HelpInput => ["record and replay input: input record|stop|replay|dump", "录制和回放输入：input record|stop|replay|dump"],
+    HelpChvt => ["switch virtual console: chvt [1-4], or Alt+F1..F4", "切换虚拟控制台：chvt [1-4]，或者按 Alt+F1..F4"],
+    HelpLoadkeys => ["keyboard layout: loadkeys [us|uk|de]", "键盘布局：loadkeys [us|uk|de]"],
+    HelpZoom => ["console font size: zoom [12|16|24|32]", "控制台字号：zoom [12|16|24|32]"],
+    HelpFps => ["frame rate: fps [<target>|show|hide], target 0 means unlimited", "帧率：fps [<target>|show|hide]，目标为 0 表示不限制"],
+    HelpScreensaver => ["idle time before the screensaver starts: screensaver [<seconds>|off]", "空闲多久后启动屏幕保护：screensaver [<seconds>|off]"],
+    HelpLock => ["start the screensaver now, unlocking needs the lock= passphrase if set", "立即启动屏幕保护，设置了 lock= 时要输入密码才能解锁"],
+    HelpScreenshot => ["write the screen to COM1 as a base64 BMP: screenshot [<name>]", "把屏幕按 base64 编码的 BMP 写到 COM1：screenshot [<name>]"],
+    HelpDecor => ["window corners and shadow: decor [flat|default|<corner> <shadow> [alpha]]", "窗口圆角和阴影：decor [flat|default|<corner> <shadow> [alpha]]"],
+    HelpTheme => ["show the theme, or reload wallpaper, icons and theme/theme.conf: theme [reload]", "显示主题，或者重新加载壁纸、图标和 theme/theme.conf：theme [reload]"],
+    HelpSelftest => ["check glyph placement", "检查字形的位置"],
+    HelpWatchdog => ["soft lockup detector: watchdog [on|off|<seconds>|panic on|off]", "软死锁检测：watchdog [on|off|<seconds>|panic on|off]"],
+    HelpAddr2line => ["resolve kernel addresses to function+offset: addr2line <addr>...", "把内核地址解析成 函数+偏移：addr2line <addr>..."],
+    HelpGdb => ["stop and wait for the debugger on COM2", "停下来等 COM2 上的调试器"],
+    HelpReboot => ["reset the machine", "重启机器"],
+    HelpBench => ["run micro-benchmarks and print BENCH lines: bench [alloc|fill|text|compose]", "运行微基准测试，输出 BENCH 行：bench [alloc|fill|text|compose]"],
+    // 命令的用法和出错信息，第一个参数是命令名
+    Usage => ["usage: {}", "用法：{}"],
+    OutOfMemory => ["{}: out of memory", "{}：内存不足"],
+    BadAddress => ["{}: bad address {}", "{}：地址 {} 无效"],
+    BadRange => ["{}: bad range {} {}", "{}：范围 {} {} 无效"],
+    InvalidNumber => ["{}: invalid number", "{}：数字无效"],
+    InvalidCount => ["{}: invalid count", "{}：次数无效"],
+    InvalidAddress => ["{}: invalid address", "{}：地址无效"],
+    InvalidTime => ["{}: invalid time {}", "{}：时间 {} 无效"],
+    UnknownOption => ["{}: unknown option -{}", "{}：不认识的选项 -{}"],
+    UnknownLocale => ["{}: unknown locale {}", "{}：不认识的数字格式 {}"],
+    UnknownBenchmark => ["{}: unknown benchmark {}", "{}：没有测试 {}"],
+    NoNetworkDevice => ["{}: no network device", "{}：没有网卡"],
+    DhcpFailed => ["{}: DHCP failed: {}", "{}：DHCP 失败：{}"],
+    NoProgram => ["{}: no program named {}", "{}：没有叫 {} 的程序"],
+    NoAlarm => ["{}: no alarm {}", "{}：没有闹钟 {}"],
+    InvalidId => ["{}: invalid id {}", "{}：编号 {} 无效"],
+    NoJob => ["{}: no job {}", "{}：没有任务 {}"],
+    MeasurementRunning => ["{}: a measurement is already running", "{}：已经有一次测量在进行"],
+    NeedsGui => ["{}: needs the graphical interface", "{}：需要图形界面"],
+    NoSymbols => ["{}: no symbol table, build with --features symbols", "{}：没有符号表，构建时加上 --features symbols"],
+    NoCom2 => ["{}: no COM2, start QEMU with a second -serial", "{}：没有 COM2，启动 QEMU 时再加一个 -serial"],
+    ThemeReloadFailed => ["{}: {}, keeping the current colors", "{}：{}，继续用现在的颜色"],
+    // 命令输出的状态
+    On => ["on", "开"],
+    Off => ["off", "关"],
+    Enabled => ["enabled", "已启用"],
+    Disabled => ["disabled", "已停用"],
+    Done => ["done", "完成"],
+    MemoryLow => ["memory is low", "内存紧张"],
+    Reclaimable => ["  {}: {} reclaimable", "  {}：可回收 {}"],
+    DefragStats => ["defrag: {} regions merged, {} of {} movable blocks moved", "整理：合并了 {} 个区域，移动了 {} 个块，共有 {} 个可移动的块"],
+    CowStats => ["cow: {} shared frames saving {}, {} pages copied on write, {} reused, {} programs cached", "写时复制：{} 个共享的帧省下 {}，写时复制了 {} 页，重用 {} 页，缓存了 {} 个程序"],
+    NotMapped => ["{} is not mapped", "{} 没有映射"],
+    AssetCached => ["{} cached", "缓存了 {}"],
+    AssetCacheStats => ["cache: {} entries, {} of {}, {} hits, {} misses", "缓存：{} 项，{} / {}，命中 {} 次，未命中 {} 次"],
+    Subscribers => ["{} subscribers", "{} 个订阅者"],
+    NoChanges => ["no changes", "没有变化"],
+    NoBlockDevices => ["no block devices", "没有块设备"],
+    TasksWaiting => ["{} task(s) waiting", "{} 个任务在等待"],
+    NoSavedFiles => ["no saved files", "没有保存的文件"],
+    HostAddress => ["{} has address {}", "{} 的地址是 {}"],
+    ProgramExited => ["{} exited with {}", "{} 退出，返回 {}"],
+    ProgramKilled => ["{} killed: {}", "{} 被终止：{}"],
+    AlarmAt => ["alarm {} at {}", "闹钟 {}：{}"],
+    ReminderAt => ["reminder {} at {}", "提醒 {}：{}"],
+    JobDone => ["[{}] done, {} line(s) of output", "[{}] 完成，输出了 {} 行"],
+    JobListed => ["[{}] {} lines  {}", "[{}] {} 行  {}"],
+    TextMode => ["VGA text mode", "VGA 文本模式"],
+    TraceStatus => ["tracing {}, {} events, {} dropped", "跟踪：{}，{} 个事件，丢弃 {} 个"],
+    ProfileStatus => ["profiling {}, {} samples, {} dropped", "采样分析：{}，{} 个样本，丢弃 {} 个"],
+    NoSamples => ["no samples, start profiling with perf start", "没有样本，先用 perf start 开始采样"],
+    NoMeasurement => ["no measurement yet", "还没有测量过"],
+    Measuring => ["measuring {} {} events...", "正在测量 {} 个 {} 事件……"],
+    EventsRecorded => ["{} events recorded", "录制了 {} 个事件"],
+    Replaying => [", replaying", "，正在回放"],
+    RecordsOverwritten => ["({} older records overwritten)", "（{} 条较早的记录被覆盖了）"],
+    WatchdogStatus => ["{}, timeout {}s, panic {}, {} lockup(s) detected", "{}，超时 {} 秒，panic：{}，发现 {} 次死锁"],
+    WatchdogFed => ["cpu{}  fed {} ago", "cpu{}  {} 前喂过"],
+    WatchdogAt => [" at {}", "，在 {}"],
+    VtStatus => ["{} active, shell on {}, kernel log on {}", "当前是 {}，shell 在 {}，内核日志在 {}"],
+    LockKeys => ["caps lock {}, num lock {}", "大写锁定：{}，数字锁定：{}"],
+    FontSize => ["font size {}", "字号 {}"],
+    FpsUnlimited => ["target unlimited", "目标不限"],
+    FpsTarget => ["target {} fps", "目标 {} fps"],
+    FpsStats => [", {} fps in the last second, {} frames, {} skipped", "，最近一秒 {} fps，共 {} 帧，跳过 {} 帧"],
+    ScreensaverAfter => ["screensaver after {}s idle", "空闲 {} 秒后启动屏幕保护"],
+    ScreensaverOff => ["screensaver off", "屏幕保护已关闭"],
+    WritingScreenshot => ["writing {}x{} screenshot \"{}\" to COM1...", "正在把 {}x{} 的截图“{}”写到 COM1……"],
+    DecorStatus => ["corner radius {}, shadow radius {}, shadow alpha {}", "圆角半径 {}，阴影半径 {}，阴影透明度 {}"],
+    ThemeColors => ["foreground {}, background {}", "前景色 {}，背景色 {}"],
+    ThemeWindow => ["corner radius {}, shadow radius {}, shadow offset {}, shadow alpha {}", "圆角半径 {}，阴影半径 {}，阴影偏移 {}，阴影透明度 {}"],
+    ThemeReloaded => ["theme reloaded", "主题已重新加载"],
+    SelftestPassed => ["selftest passed", "自检通过"],
+    SelftestFailed => ["selftest FAILED, see the log", "自检失败，详见日志"],
+    WaitingForGdb => ["waiting for gdb on COM2...", "正在等 COM2 上的 gdb……"],
+    Rebooting => ["Rebooting...", "正在重启……"],
+    // 窗口标题
+    Terminal => ["Terminal", "终端"],
+    SystemMonitor => ["System Monitor", "系统监视器"],
+    Memory => ["Memory", "内存"],
+    KernelLog => ["Kernel log", "内核日志"],
+    About => ["About {}", "关于 {}"],
+    // 关于对话框和系统监视器
+    AboutCommit => ["commit {}", "提交 {}"],
+    AboutBuilt => ["built {} UTC ({})", "构建于 {} UTC（{}）"],
+    AboutFeatures => ["features: {}", "特性：{}"],
+    Refreshed => ["Refreshed every {} s", "每 {} 秒刷新一次"],
+    JobOutput => ["Output of job {}: {}", "任务 {} 的输出：{}"],
+    // 屏幕保护
+    Locked => ["Locked", "已锁定"],
+    WrongPassphrase => ["Wrong passphrase", "密码错误"],
+    Passphrase => ["Passphrase: {}", "密码：{}"],
+}
+
+/// `id` 在当前语言里的写法
+pub fn tr(id: Msg) -> &'static str {
+    tr_in(id, language())
+}
+
+/// `id` 在 `language` 里的写法
+pub fn tr_in(id: Msg, language: Language) -> &'static str {
+    TABLE[id as usize][language as usize]
+}
+
+/// 把 `args` 按顺序填进 `id` 的 {} 里，输出时才拼接，不分配内存
+pub fn fill<'a>(id: Msg, args: &'a [&'a dyn Display]) -> Fill<'a> {
+    Fill { template: tr(id), args }
+}
+
+/// fill 的结果，用 {} 输出
+pub struct Fill<'a> {
+    template: &'static str,
+    args: &'a [&'a dyn Display],
+}
+
+impl Display for Fill<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = self.template.split("{}");
+        // 参数不够时多出来的 {} 输出成空的，多的参数不输出
+        f.write_str(parts.next().unwrap_or_default())?;
+        for (i, part) in parts.enumerate() {
+            if let Some(arg) = self.args.get(i) {
+                arg.fmt(f)?;
+            }
+            f.write_str(part)?;
+        }
+        Ok(())
+    }
+}
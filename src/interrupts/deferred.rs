@@ -0,0 +1,169 @@
+// 延后执行的工作（下半部）
+// 中断处理函数只做必须马上做的事（读端口、确认中断），比较重的处理用 defer 放进队列，由主循环空闲时调用 run 执行，
+// 执行时中断是开着的。中断上下文里不能分配内存，所以一项工作是一个函数加一个 usize 参数，队列是固定大小的环。
+// 队列按优先级分三个，先执行优先级高的；一个有工作的队列连续 STARVE_LIMIT 次没轮到时下一次先执行它，
+// 持续不断的高优先级工作不会让低优先级的永远等下去。每次 run 最多执行 BUDGET 项，主循环的其他事情不会被耽误太久
+
+use core::fmt;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::sync::IrqSafeMutex;
+
+/// 每个优先级的队列长度，满了之后新的工作被丢弃
+pub const CAPACITY: usize = 256;
+/// 每次 run 最多执行的工作数
+pub const BUDGET: usize = 64;
+// 有工作的队列连续这么多次没轮到就先执行它
+const STARVE_LIMIT: u32 = 8;
+const LEVELS: usize = 3;
+
+/// 工作的优先级
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// 输入这类用户等着看结果的工作
+    High,
+    Normal,
+    /// 统计、清理这类晚一点也没关系的工作
+    Low,
+}
+
+impl Priority {
+    pub const ALL: [Priority; LEVELS] = [Priority::High, Priority::Normal, Priority::Low];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(match self {
+            Priority::High => "high",
+            Priority::Normal => "normal",
+            Priority::Low => "low",
+        })
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Work {
+    func: fn(usize),
+    arg: usize,
+}
+
+fn nothing(_arg: usize) {}
+
+struct Queue {
+    items: [Work; CAPACITY],
+    head: usize,
+    len: usize,
+    // 有工作但没轮到的连续次数
+    skipped: u32,
+}
+
+impl Queue {
+    const EMPTY: Self = Self { items: [Work { func: nothing, arg: 0 }; CAPACITY], head: 0, len: 0, skipped: 0 };
+
+    fn push(&mut self, work: Work) -> bool {
+        if self.len == CAPACITY {
+            return false;
+        }
+        self.items[(self.head + self.len) % CAPACITY] = work;
+        self.len += 1;
+        true
+    }
+
+    fn pop(&mut self) -> Option<Work> {
+        if self.len == 0 {
+            return None;
+        }
+        let work = self.items[self.head];
+        self.head = (self.head + 1) % CAPACITY;
+        self.len -= 1;
+        Some(work)
+    }
+}
+
+static QUEUES: IrqSafeMutex<[Queue; LEVELS]> = IrqSafeMutex::named("deferred work", [Queue::EMPTY; LEVELS]);
+static QUEUED: [AtomicU64; LEVELS] = [const { AtomicU64::new(0) }; LEVELS];
+static DONE: [AtomicU64; LEVELS] = [const { AtomicU64::new(0) }; LEVELS];
+static DROPPED: [AtomicU64; LEVELS] = [const { AtomicU64::new(0) }; LEVELS];
+
+/// 一个优先级的队列的统计
+#[derive(Debug, Clone, Copy)]
+pub struct QueueStats {
+    pub priority: Priority,
+    /// 还在排队的工作数
+    pub pending: usize,
+    pub queued: u64,
+    pub done: u64,
+    /// 队列满了被丢弃的工作数
+    pub dropped: u64,
+}
+
+/// 把 `func(arg)` 放进队列，之后由主循环执行；队列满时返回 false
+///
+/// 中断处理函数里也可以调用
+pub fn defer(priority: Priority, func: fn(usize), arg: usize) -> bool {
+    let index = priority.index();
+    if QUEUES.lock()[index].push(Work { func, arg }) {
+        QUEUED[index].fetch_add(1, Ordering::Relaxed);
+        true
+    } else {
+        DROPPED[index].fetch_add(1, Ordering::Relaxed);
+        false
+    }
+}
+
+// 取出下一项工作和它的优先级
+fn next() -> Option<(usize, Work)> {
+    let mut guard = QUEUES.lock();
+    let queues = &mut *guard;
+    let pick = (0..LEVELS)
+        .find(|&i| queues[i].len > 0 && queues[i].skipped >= STARVE_LIMIT)
+        .or_else(|| (0..LEVELS).find(|&i| queues[i].len > 0))?;
+    for (i, queue) in queues.iter_mut().enumerate() {
+        if i != pick && queue.len > 0 {
+            queue.skipped += 1;
+        }
+    }
+    queues[pick].skipped = 0;
+    queues[pick].pop().map(|work| (pick, work))
+}
+
+/// 执行排队的工作，最多 BUDGET 项，返回执行的项数
+///
+/// 由主循环在空闲时调用，必须在开中断的状态下调用
+pub fn run() -> usize {
+    let mut count = 0;
+    while count < BUDGET {
+        let Some((index, work)) = next() else { break };
+        (work.func)(work.arg);
+        DONE[index].fetch_add(1, Ordering::Relaxed);
+        count += 1;
+    }
+    count
+}
+
+/// 还在排队的工作数
+pub fn pending() -> usize {
+    QUEUES.lock().iter().map(|queue| queue.len).sum()
+}
+
+/// 各个优先级的统计，从高到低
+pub fn stats() -> [QueueStats; LEVELS] {
+    let pending: [usize; LEVELS] = {
+        let queues = QUEUES.lock();
+        core::array::from_fn(|i| queues[i].len)
+    };
+    Priority::ALL.map(|priority| {
+        let index = priority.index();
+        QueueStats {
+            priority,
+            pending: pending[index],
+            queued: QUEUED[index].load(Ordering::Relaxed),
+            done: DONE[index].load(Ordering::Relaxed),
+            dropped: DROPPED[index].load(Ordering::Relaxed),
+        }
+    })
+}
@@ -0,0 +1,193 @@
+// CPU 异常
+// 除了断点、双重错误、NMI 和页错误（见 interrupts/mod.rs），其余的异常都在这里挂上处理函数，不再因为没有处理函数而变成三重错误、QEMU 直接重启。
+// 来自用户程序（ring 3）的异常只结束这个程序；来自内核的记下栈帧后 panic，panic 画面上有出错的指令、当时在哪个 CPU 上运行什么和调用栈。
+// 错误码按异常的种类解码：段相关的异常给出出错的选择子在哪张表里、第几项，浮点异常给出 x87 状态字或 MXCSR 里置位的异常，
+// 机器检查把各个报告了错误的 bank 写到串口。机器检查要 CR4.MCE 打开才会产生，见 cpu::enable
+
+use core::fmt::{self, Write};
+
+use x86_64::registers::model_specific::Msr;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+
+use crate::debug::panic::record_fault;
+use crate::io::qemu::SerialStream;
+
+const IA32_MCG_CAP: u32 = 0x179;
+const IA32_MCG_STATUS: u32 = 0x17A;
+// 第 i 个 bank 的状态寄存器是 IA32_MC0_STATUS + 4 * i，出错的地址在它后面
+const IA32_MC0_STATUS: u32 = 0x401;
+const MC_STATUS_VALID: u64 = 1 << 63;
+const MC_STATUS_ADDRV: u64 = 1 << 58;
+
+/// 挂上异常处理函数
+pub fn install(idt: &mut InterruptDescriptorTable) {
+    idt.divide_error.set_handler_fn(divide_error_handler);
+    idt.overflow.set_handler_fn(overflow_handler);
+    idt.bound_range_exceeded.set_handler_fn(bound_range_handler);
+    idt.invalid_opcode.set_handler_fn(invalid_opcode_handler);
+    idt.device_not_available.set_handler_fn(device_not_available_handler);
+    idt.invalid_tss.set_handler_fn(invalid_tss_handler);
+    idt.segment_not_present.set_handler_fn(segment_not_present_handler);
+    idt.stack_segment_fault.set_handler_fn(stack_segment_fault_handler);
+    idt.general_protection_fault.set_handler_fn(general_protection_fault_handler);
+    idt.x87_floating_point.set_handler_fn(x87_floating_point_handler);
+    idt.alignment_check.set_handler_fn(alignment_check_handler);
+    idt.machine_check.set_handler_fn(machine_check_handler);
+    idt.simd_floating_point.set_handler_fn(simd_floating_point_handler);
+    idt.virtualization.set_handler_fn(virtualization_handler);
+    idt.security_exception.set_handler_fn(security_exception_handler);
+}
+
+// 来自用户程序时结束它，来自内核时无法恢复；`detail` 是解码后的错误码，接在异常名字后面
+fn fault(name: &'static str, reason: &'static str, frame: &InterruptStackFrame, error_code: Option<u64>,
+         detail: fmt::Arguments) -> ! {
+    if super::from_user(frame) {
+        log::warn!("User program: {} at {:#x}{}", name, frame.instruction_pointer.as_u64(), detail);
+        crate::usermode::kill(reason);
+    }
+    record_fault(name, frame, error_code);
+    panic!("EXCEPTION: {}{}", name, detail);
+}
+
+extern "x86-interrupt" fn divide_error_handler(frame: InterruptStackFrame) {
+    fault("DIVIDE ERROR", "divide error", &frame, None, format_args!(""));
+}
+
+extern "x86-interrupt" fn overflow_handler(frame: InterruptStackFrame) {
+    fault("OVERFLOW", "overflow", &frame, None, format_args!(""));
+}
+
+extern "x86-interrupt" fn bound_range_handler(frame: InterruptStackFrame) {
+    fault("BOUND RANGE EXCEEDED", "bound range exceeded", &frame, None, format_args!(""));
+}
+
+extern "x86-interrupt" fn invalid_opcode_handler(frame: InterruptStackFrame) {
+    fault("INVALID OPCODE", "invalid opcode", &frame, None, format_args!(""));
+}
+
+// fpu 模块不用延迟保存，CR0.TS 总是清零的，出现这个异常说明有代码改了 CR0
+extern "x86-interrupt" fn device_not_available_handler(frame: InterruptStackFrame) {
+    fault("DEVICE NOT AVAILABLE", "device not available", &frame, None, format_args!(""));
+}
+
+extern "x86-interrupt" fn invalid_tss_handler(frame: InterruptStackFrame, error_code: u64) {
+    fault("INVALID TSS", "invalid TSS", &frame, Some(error_code),
+          format_args!(" ({})", SelectorError(error_code)));
+}
+
+extern "x86-interrupt" fn segment_not_present_handler(frame: InterruptStackFrame, error_code: u64) {
+    fault("SEGMENT NOT PRESENT", "segment not present", &frame, Some(error_code),
+          format_args!(" ({})", SelectorError(error_code)));
+}
+
+extern "x86-interrupt" fn stack_segment_fault_handler(frame: InterruptStackFrame, error_code: u64) {
+    fault("STACK SEGMENT FAULT", "stack segment fault", &frame, Some(error_code),
+          format_args!(" ({})", SelectorError(error_code)));
+}
+
+// 非规范地址、特权指令、写只读的段寄存器这类错误码是 0，加载了有问题的选择子时错误码是那个选择子
+extern "x86-interrupt" fn general_protection_fault_handler(frame: InterruptStackFrame, error_code: u64) {
+    fault("GENERAL PROTECTION FAULT", "general protection fault", &frame, Some(error_code),
+          format_args!(" ({})", SelectorError(error_code)));
+}
+
+extern "x86-interrupt" fn x87_floating_point_handler(frame: InterruptStackFrame) {
+    let status: u16;
+    unsafe { core::arch::asm!("fnstsw ax", out("ax") status, options(nomem, nostack, preserves_flags)) };
+    fault("x87 FLOATING POINT", "x87 floating point exception", &frame, None,
+          format_args!(" ({}, status {:#06x})", FloatExceptions(status as u32 & 0x7F), status));
+}
+
+// 只有 ring 3 打开 RFLAGS.AC 和 CR0.AM 时才会产生
+extern "x86-interrupt" fn alignment_check_handler(frame: InterruptStackFrame, error_code: u64) {
+    fault("ALIGNMENT CHECK", "alignment check", &frame, Some(error_code), format_args!(""));
+}
+
+extern "x86-interrupt" fn simd_floating_point_handler(frame: InterruptStackFrame) {
+    let mut mxcsr: u32 = 0;
+    unsafe { core::arch::asm!("stmxcsr [{}]", in(reg) &mut mxcsr, options(nostack, preserves_flags)) };
+    fault("SIMD FLOATING POINT", "SIMD floating point exception", &frame, None,
+          format_args!(" ({}, mxcsr {:#010x})", FloatExceptions(mxcsr & 0x3F), mxcsr));
+}
+
+extern "x86-interrupt" fn virtualization_handler(frame: InterruptStackFrame) {
+    fault("VIRTUALIZATION", "virtualization exception", &frame, None, format_args!(""));
+}
+
+extern "x86-interrupt" fn security_exception_handler(frame: InterruptStackFrame, error_code: u64) {
+    fault("SECURITY EXCEPTION", "security exception", &frame, Some(error_code), format_args!(""));
+}
+
+// 硬件出错，不管打断的是谁都不能继续；各个 bank 的内容先写到串口，panic 信息里放不下
+extern "x86-interrupt" fn machine_check_handler(frame: InterruptStackFrame) -> ! {
+    // 没有机器检查架构时这些寄存器都不存在
+    let (status, banks) = if crate::cpu::features().mca {
+        unsafe { (Msr::new(IA32_MCG_STATUS).read(), Msr::new(IA32_MCG_CAP).read() & 0xFF) }
+    } else {
+        (0, 0)
+    };
+    let mut out = SerialStream;
+    let mut reporting = 0;
+    for bank in 0..banks as u32 {
+        let bank_status = unsafe { Msr::new(IA32_MC0_STATUS + 4 * bank).read() };
+        if bank_status & MC_STATUS_VALID == 0 {
+            continue;
+        }
+        reporting += 1;
+        let _ = write!(out, "machine check: bank {} status {:#018x}", bank, bank_status);
+        if bank_status & MC_STATUS_ADDRV != 0 {
+            let addr = unsafe { Msr::new(IA32_MC0_STATUS + 4 * bank + 1).read() };
+            let _ = write!(out, " address {:#x}", addr);
+        }
+        let _ = writeln!(out);
+    }
+    record_fault("MACHINE CHECK", &frame, None);
+    panic!("EXCEPTION: MACHINE CHECK (mcg_status {:#x}, {} of {} banks reporting)", status, reporting, banks);
+}
+
+/// 段相关异常的错误码：出错的选择子、它在哪张表里，以及是不是外部事件引起的
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SelectorError(pub u64);
+
+impl fmt::Display for SelectorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let code = self.0;
+        if code == 0 {
+            return f.write_str("no selector");
+        }
+        if code & 1 != 0 {
+            f.write_str("external, ")?;
+        }
+        let index = (code >> 3) & 0x1FFF;
+        match (code >> 1) & 3 {
+            0 => write!(f, "GDT selector {:#x}", index << 3),
+            2 => write!(f, "LDT selector {:#x}", (index << 3) | 4),
+            _ => write!(f, "IDT vector {}", index),
+        }
+    }
+}
+
+/// x87 状态字或 MXCSR 里置位的浮点异常，两者低 6 位的含义相同，x87 多一个栈错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FloatExceptions(pub u32);
+
+impl fmt::Display for FloatExceptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = ["invalid operation", "denormal operand", "divide by zero", "overflow", "underflow", "precision",
+                     "stack fault"];
+        let mut first = true;
+        for (bit, name) in names.iter().enumerate() {
+            if self.0 & (1 << bit) != 0 {
+                if !first {
+                    f.write_str(", ")?;
+                }
+                f.write_str(name)?;
+                first = false;
+            }
+        }
+        if first {
+            f.write_str("no exception flags")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,169 @@
+// 中断向量表里的外部中断
+// 向量 32..48 对应 8259 PIC 的 16 条 IRQ 线，48..80 留给运行时分配、由 local APIC 送来的中断（MSI 这类）。
+// 这些向量在 IDT 里都装着同一个分发函数的实例，分发函数按向量查表调用驱动注册的处理函数，
+// 再按来源给 PIC 或 local APIC 发 EOI，驱动不用自己写 extern "x86-interrupt" 函数，也不用改 IDT。
+// 一条 PIC 线上可以挂几个处理函数（PCI 设备共用中断线），由它们自己检查设备有没有发出中断。
+// 每个向量记下中断次数和最后一次的时间，irq 命令可以查看
+
+use core::fmt;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+use x86_64::structures::idt::{HandlerFunc, InterruptStackFrame};
+
+use crate::interrupts::pics::{self, PIC_1_OFFSET};
+use crate::io::timer::{self, TIMER_HZ};
+use crate::smp::lapic;
+use crate::sync::IrqSafeMutex;
+
+/// 第一个外部中断的向量，即 IRQ 0
+pub const FIRST_VECTOR: u8 = PIC_1_OFFSET;
+/// PIC 的 IRQ 线数
+pub const LINES: u8 = 16;
+/// 第一个运行时分配的向量
+pub const FIRST_DYNAMIC: u8 = FIRST_VECTOR + LINES;
+// 运行时分配的向量数
+const DYNAMIC: u8 = 32;
+const COUNT: usize = (LINES + DYNAMIC) as usize;
+/// 一个向量上最多挂的处理函数个数
+pub const MAX_SHARED: usize = 4;
+// 主片上接从片的线
+const CASCADE_LINE: u8 = 2;
+
+/// 中断处理函数，参数是被打断的代码的栈帧
+///
+/// 在中断上下文中执行，中断是关着的，不能分配内存，EOI 由分发函数发
+pub type Handler = fn(&InterruptStackFrame);
+
+/// 要挂处理函数的中断
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Irq {
+    /// PIC 的 IRQ 线（0 到 15），向量固定，可以和别的处理函数共用
+    Line(u8),
+    /// 分配一个空闲的向量，中断由 local APIC 送来
+    Dynamic,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterError {
+    /// 没有这条 IRQ 线，或者是接从片的 IRQ 2
+    InvalidLine(u8),
+    /// 这条线上挂满了处理函数，或者没有空闲的向量
+    Full,
+}
+
+impl fmt::Display for RegisterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RegisterError::InvalidLine(line) => write!(f, "IRQ {} is not available", line),
+            RegisterError::Full => write!(f, "no free interrupt vector"),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Slot {
+    handlers: [Option<(&'static str, Handler)>; MAX_SHARED],
+}
+
+impl Slot {
+    const EMPTY: Self = Self { handlers: [None; MAX_SHARED] };
+
+    fn is_empty(&self) -> bool {
+        self.handlers.iter().all(Option::is_none)
+    }
+}
+
+static TABLE: IrqSafeMutex<[Slot; COUNT]> = IrqSafeMutex::named("irq table", [Slot::EMPTY; COUNT]);
+static COUNTS: [AtomicU64; COUNT] = [const { AtomicU64::new(0) }; COUNT];
+// 最后一次中断时的时钟中断次数
+static LAST: [AtomicU64; COUNT] = [const { AtomicU64::new(0) }; COUNT];
+
+/// 挂上中断处理函数，返回它的向量；PIC 的线会被取消屏蔽
+///
+/// `name` 显示在 irq 命令里
+pub fn register_handler(irq: Irq, name: &'static str, handler: Handler) -> Result<u8, RegisterError> {
+    let index = {
+        let mut table = TABLE.lock();
+        let index = match irq {
+            Irq::Line(line) if line >= LINES || line == CASCADE_LINE => return Err(RegisterError::InvalidLine(line)),
+            Irq::Line(line) => line as usize,
+            Irq::Dynamic => (LINES as usize..COUNT).find(|&i| table[i].is_empty()).ok_or(RegisterError::Full)?,
+        };
+        let free = table[index].handlers.iter_mut().find(|h| h.is_none()).ok_or(RegisterError::Full)?;
+        *free = Some((name, handler));
+        index
+    };
+    if let Irq::Line(line) = irq {
+        pics::unmask_line(line);
+    }
+    Ok(FIRST_VECTOR + index as u8)
+}
+
+/// 一个挂了处理函数的向量
+#[derive(Clone, Copy)]
+pub struct VectorStats {
+    pub vector: u8,
+    /// 对应的 PIC 线，运行时分配的向量为 None
+    pub line: Option<u8>,
+    pub count: u64,
+    /// 最后一次中断的启动时间，还没来过中断时为 None
+    pub last: Option<Duration>,
+    handlers: [Option<&'static str>; MAX_SHARED],
+}
+
+impl VectorStats {
+    /// 挂在这个向量上的处理函数的名字
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.handlers.iter().flatten().copied()
+    }
+}
+
+/// 挂了处理函数的向量，按向量从小到大
+pub fn stats() -> impl Iterator<Item = VectorStats> {
+    let table = *TABLE.lock();
+    (0..COUNT).filter(move |&i| !table[i].is_empty()).map(move |i| {
+        let count = COUNTS[i].load(Ordering::Relaxed);
+        let last = LAST[i].load(Ordering::Relaxed);
+        VectorStats {
+            vector: FIRST_VECTOR + i as u8,
+            line: (i < LINES as usize).then_some(i as u8),
+            count,
+            last: (count > 0).then(|| Duration::from_millis(last * 1000 / TIMER_HZ as u64)),
+            handlers: table[i].handlers.map(|h| h.map(|(name, _)| name)),
+        }
+    })
+}
+
+fn dispatch(vector: u8, frame: &InterruptStackFrame) {
+    let index = (vector - FIRST_VECTOR) as usize;
+    COUNTS[index].fetch_add(1, Ordering::Relaxed);
+    LAST[index].store(timer::ticks(), Ordering::Relaxed);
+    // 复制一份再调用，处理函数里注册别的中断时不会死锁
+    let slot = TABLE.lock()[index];
+    for (_, handler) in slot.handlers.iter().flatten() {
+        handler(frame);
+    }
+    if vector < FIRST_DYNAMIC {
+        unsafe { pics::PICS.lock().notify_end_of_interrupt(vector) };
+    } else {
+        lapic::eoi();
+    }
+}
+
+extern "x86-interrupt" fn stub<const VECTOR: u8>(frame: InterruptStackFrame) {
+    dispatch(VECTOR, &frame);
+}
+
+macro_rules! stubs {
+    ($($vector:literal)*) => {
+        [$(stub::<$vector> as HandlerFunc),*]
+    };
+}
+
+/// 依次装到向量 FIRST_VECTOR.. 上
+pub(super) const STUBS: [HandlerFunc; COUNT] = stubs!(
+    32 33 34 35 36 37 38 39 40 41 42 43 44 45 46 47
+    48 49 50 51 52 53 54 55 56 57 58 59 60 61 62 63
+    64 65 66 67 68 69 70 71 72 73 74 75 76 77 78 79
+);
@@ -1,21 +1,25 @@
-use alloc::format;
+use core::sync::atomic::{AtomicBool, Ordering};
+
 use lazy_static::lazy_static;
-// 从spin库导入其版本的互斥锁（Mutex）。这种类型的锁特别适合操作系统级应用，因为操作系统不总是可以休眠线程以等待锁释放
-use spin::Mutex;
 // 导入用于低级别I/O端口操作的 `Port` 结构体，与硬件设备进行通信时常用到
-use x86_64::instructions::port::Port;
+use crate::io::port::{self, Port};
 // 从x86_64标准库中导入关于中断描述符表(Interrupt Descriptor Table, IDT)和中断栈帧(Interrupt Stack Frame) 的结构体定义。IDT用于定义中断服务例程(ISRs)，而中断栈帧保存发生中断时CPU寄存器状态
 use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
 
 // 引入前面定义好的枚举 `InterruptIndex` ，代表各个片段(PICS)相关联映射向量编号概念理解工具项
 use pics::InterruptIndex;
 
+use crate::sync::IrqSafeMutex;
+
 // 导出当前crate提供的打印函数 "`print!`" 和 "`println!"` 宏，方便其他模块输出信息至控制台或屏幕
-use crate::{print, println};
-use crate::io::qemu::qemu_print;
 
+pub mod deferred;
+pub mod exceptions;
+pub mod irq;
 pub mod pics;
 
+pub use irq::{register_handler, Handler, Irq, RegisterError};
+
 lazy_static! {
     // 定义了一个名为 `IDT` 的静态变量
     static ref IDT: InterruptDescriptorTable = {
@@ -23,13 +27,29 @@ lazy_static! {
         let mut idt = InterruptDescriptorTable::new();
         // 设置debugger breakpoint (调试器断点异常) 中断处理函数
         idt.breakpoint.set_handler_fn(breakpoint_handler);
+        // 有 COM2 时断点和单步异常交给 GDB stub
+        crate::debug::gdb::install(&mut idt);
         // 设置double fault (双重错误）异常 对应中断处理功能
-        idt.double_fault.set_handler_fn(double_fault_handler);
+        // 双重错误和 NMI 切换到 IST 中的独立栈，这样内核栈溢出时仍能正常处理
+        unsafe {
+            idt.double_fault.set_handler_fn(double_fault_handler)
+                .set_stack_index(crate::gdt::DOUBLE_FAULT_IST_INDEX);
+            idt.non_maskable_interrupt.set_handler_fn(nmi_handler)
+                .set_stack_index(crate::gdt::NMI_IST_INDEX);
+        }
         // 为IDT（中断描述符表）中的页面错误异常设置处理函数
         idt.page_fault.set_handler_fn(page_fault_handler);
-        // 将计时器和键盘中断索引映射到相应处理程序
-        idt[InterruptIndex::Timer.as_usize()].set_handler_fn(time_interrupt_handler);
-        idt[InterruptIndex::Keyboard.as_usize()].set_handler_fn(keyboard_interrupt_handler);
+        // 其余的 CPU 异常：除零、非法指令、一般保护错误、浮点异常、机器检查等
+        exceptions::install(&mut idt);
+        // 用户程序的系统调用入口
+        crate::syscall::install(&mut idt);
+        // 外部中断都交给 irq 模块分发，处理函数由 init 和各个驱动注册
+        for (i, &stub) in irq::STUBS.iter().enumerate() {
+            idt[irq::FIRST_VECTOR as usize + i].set_handler_fn(stub);
+        }
+        // local APIC 的处理器间中断和伪中断
+        idt[crate::smp::task::WAKEUP_VECTOR as usize].set_handler_fn(wakeup_interrupt_handler);
+        idt[crate::smp::lapic::SPURIOUS_VECTOR as usize].set_handler_fn(spurious_interrupt_handler);
         idt
     };
 }
@@ -39,93 +59,256 @@ pub fn init_idt() {
     IDT.load();
 }
 
+/// 挂上时钟、键盘、串口、RTC 和鼠标的中断处理函数并取消屏蔽这几条线，在开中断之前调用一次
+pub fn init() {
+    let builtin: [(InterruptIndex, &'static str, Handler); 5] = [
+        (InterruptIndex::Timer, "timer", timer_interrupt),
+        (InterruptIndex::Keyboard, "keyboard", keyboard_interrupt),
+        (InterruptIndex::Com1, "com1", com1_interrupt),
+        (InterruptIndex::Rtc, "rtc", rtc_interrupt),
+        (InterruptIndex::Mouse, "mouse", mouse_interrupt),
+    ];
+    for (index, name, handler) in builtin {
+        register_handler(Irq::Line(index.line()), name, handler).expect("built-in IRQ lines are valid");
+    }
+    // 主片和从片的命令、数据端口
+    port::claim_or_log("pic", 0x20, 2);
+    port::claim_or_log("pic", 0xA0, 2);
+}
+
 // 调试异常处理函数
 // `breakpoint_handler` 是断点异常的处理函数，使用 `"x86-interrupt"` 调用约定。当发生断点异常时，此函数会被调用。
 // - `_stack_frame`: 包含了发生中断时CPU寄存器状态的 `InterruptStackFrame` 结构体。
 // - 函数内部打印一条消息和栈帧信息
 extern "x86-interrupt" fn breakpoint_handler(_stack_frame: InterruptStackFrame) {
-    println!("EXCEPTION: BREAKPOINT\n{:#?}", _stack_frame);
-    qemu_print(format!("EXCEPTION: BREAKPOINT\n{:#?}\n", _stack_frame).as_str());
+    log::warn!("EXCEPTION: BREAKPOINT\n{:#?}", _stack_frame);
 }
 
 // 双重异常处理函数
 // `double_fault_handler` 是双重错误异常的处理函数。
 // - `_error_code`: 双重故障给出的错误码（在本例中未使用）。
-// - 函数内部打印一条消息和栈帧信息后进入无限循环，因为双重错误通常是致命的，不可能恢复执行；返回类型 `!` 表明该函数不返回
+// - 记下栈帧后 panic，由 panic 画面显示并停机，因为双重错误通常是致命的，不可能恢复执行；返回类型 `!` 表明该函数不返回
 extern "x86-interrupt" fn double_fault_handler(_stack_frame: InterruptStackFrame, _error_code: u64) -> ! {
-    println!("EXCEPTION: DOUBLE FAULT\n{:#?}", _stack_frame);
-    qemu_print(format!("EXCEPTION: DOUBLE FAULT\n{:#?}\n", _stack_frame).as_str());
-    loop {}
+    use x86_64::registers::control::Cr2;
+    // 访问保护页导致的双重错误基本可以断定是栈溢出
+    let accessed = Cr2::read();
+    if crate::memory::stacks::is_guard_page(accessed) {
+        log::error!("KERNEL STACK OVERFLOW: guard page {:?} hit", accessed);
+    }
+    crate::debug::panic::record_fault("DOUBLE FAULT", &_stack_frame, Some(_error_code));
+    panic!("EXCEPTION: DOUBLE FAULT");
 }
 
-lazy_static! {
-    pub static ref TIME: Mutex<u128> = Mutex::new(0);
+extern "x86-interrupt" fn nmi_handler(_stack_frame: InterruptStackFrame) {
+    log::error!("EXCEPTION: NMI\n{:#?}", _stack_frame);
 }
 
-// 定时器中断处理函数
-// - 每次定时器触发时打印出一个点(`.`)来表示时间流逝。
-// - `unsafe {}` 块包含潜在危险操作：锁定 PIC 控制器并发送 EOI (End Of Interrupt)，告知我们已经完成对当前中断的处理；需要unsafe因为如果错误地发送EOI可能导致中断管理混乱
-extern "x86-interrupt" fn time_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    *TIME.lock() += 1;
+// 只为了让空闲的 CPU 从 hlt 返回，回到任务队列
+extern "x86-interrupt" fn wakeup_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    crate::smp::lapic::eoi();
+}
 
-    unsafe {
-        pics::PICS.lock().notify_end_of_interrupt(pics::InterruptIndex::Timer.as_u8());
-    }
+// 伪中断不需要 EOI
+extern "x86-interrupt" fn spurious_interrupt_handler(_stack_frame: InterruptStackFrame) {}
+
+fn timer_interrupt(_stack_frame: &InterruptStackFrame) {
+    let _span = crate::trace::span("irq:timer");
+    crate::io::timer::tick();
+    // 主循环或者 AP 上的任务太久没有进展时报告
+    crate::debug::watchdog::check(_stack_frame);
+    // GDB 按了 Ctrl+C 时在这里停下来
+    crate::debug::gdb::poll();
 }
 
 // 页错异常处理函数
 // 定义了一个名为`page_fault_handler`的外部中断处理函数，用于在发生页面错误时被调用。它接收两个参数：一个是当前CPU堆栈帧信息，另一个是页面错误码
 extern "x86-interrupt" fn page_fault_handler(_stack_frame: InterruptStackFrame, _error_code: PageFaultErrorCode) {
-    // 导入模块内定义的`hlt_loop`函数以及从crate中获取CR2控制寄存器模块
     // CR2寄存器保存着最后一次产生页错异常时所访问的虚拟地址
-    use crate::hlt_loop;
     use x86_64::registers::control::Cr2;
-    // 格式化并打印出传入该错误处理程序时CPU堆栈帧（包括指令、堆栈、程序计数器等）的状态信息
-    println!("EXCEPTION: PAGE FAULT");
-    println!("Accessed Address: {:?}", Cr2::read());
-    println!("{:#?}", _stack_frame);
+    // 写时复制的页，复制之后回去重新执行那条指令
+    let write_protected = PageFaultErrorCode::PROTECTION_VIOLATION | PageFaultErrorCode::CAUSED_BY_WRITE;
+    if _error_code.contains(write_protected) && crate::memory::cow::handle_write_fault(Cr2::read()) {
+        return;
+    }
+    if from_user(&_stack_frame) {
+        log::warn!("User page fault accessing {:?} ({:?})", Cr2::read(), _error_code);
+        crate::usermode::kill("page fault");
+    }
+    // 记下异常时的栈帧，由 panic 画面显示出错的指令和访问的地址
+    crate::debug::panic::record_fault("PAGE FAULT", &_stack_frame, Some(_error_code.bits()));
+    panic!("EXCEPTION: PAGE FAULT accessing {:?} ({:?})", Cr2::read(), _error_code);
+}
 
-    qemu_print(format!("EXCEPTION: PAGE FAULT\n").as_str());
-    qemu_print(format!("Accessed Address: {:?}\n", Cr2::read()).as_str());
-    qemu_print(format!("{:#?}\n", _stack_frame).as_str());
-    // 调用之前导入的`hlt_loop()`函数执行无限循环，并置系统处于待机状态直到下一次中断到来
-    hlt_loop();
+// 异常发生在 ring 3，由用户程序引起
+fn from_user(stack_frame: &InterruptStackFrame) -> bool {
+    stack_frame.code_segment & 3 == 3
 }
-    
 
 // 键盘中断处理函数
-// 使用 `"x86-interrupt"` 调用约定，声明一个键盘中断处理器函数。它接收一个 `InterruptStackFrame` 参数 `_stack_frame`，包含发生中断时的CPU寄存器状态（在此函数不直接使用）
-extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStackFrame) {
+// 由 irq 模块分发的键盘中断处理函数。它接收一个 `InterruptStackFrame` 参数 `_stack_frame`，包含发生中断时的CPU寄存器状态（在此函数不直接使用）
+// 中断里只读出扫描码，解码放到下半部（deferred）里做
+fn keyboard_interrupt(_stack_frame: &InterruptStackFrame) {
+    let _span = crate::trace::span("irq:keyboard");
+    // 创建新的I/O端口对象以读取端口号为0x60的数据，0x60是标准PS/2键盘的数据端口号
+    let port = Port::<u8>::new(0x60);
+    // 从数据端口读取一个字节大小的扫描码。因为I/O端口读写可能与硬件直接交互且无法保证总是安全有效，所以这里需要使用unsafe块
+    let scancode: u8 = unsafe { port.read() };
+    // 队列满时丢掉这个扫描码，和键盘缓冲区满时一样
+    deferred::defer(deferred::Priority::High, decode_scancode, scancode as usize);
+    // 向PIC发送EOI（结束中断信号）由 irq 模块在处理函数返回后完成
+}
+
+// 在主循环里解码扫描码
+fn decode_scancode(scancode: usize) {
+    let _span = crate::trace::span("deferred:keyboard");
     // 在函数内部导入 `pc_keyboard` crate 的相关模块和类型，用于解码键盘扫描码
-    use pc_keyboard::{DecodedKey, HandleControl, Keyboard, layouts, ScancodeSet1};
-    // 使用 `lazy_static!` 定义了一个静态的 `KEYBOARD` 变量，它是一个互斥锁（Mutex），保护 `Keyboard` 结构体实例。这个结构体支持美国104键布局和扫描集1，并且选择忽略控制字符（例如Ctrl组合按键
+    use pc_keyboard::{HandleControl, Keyboard, KeyState, layouts, ScancodeSet1};
+    use crate::io::keyboard::keymap;
+    // 使用 `lazy_static!` 定义了一个静态的 `KEYBOARD` 变量，它是一个互斥锁（Mutex），保护 `Keyboard` 结构体实例。
+    // pc_keyboard 只负责把扫描集1的扫描码拼成按键事件，这里的布局用不到，按键对应的字符由 io::keyboard::keymap 决定
     lazy_static! {
-        static ref KEYBOARD: Mutex<Keyboard<layouts::Us104Key, ScancodeSet1>> =
-            Mutex::new(Keyboard::new(layouts::Us104Key, ScancodeSet1,
+        static ref KEYBOARD: IrqSafeMutex<Keyboard<layouts::Us104Key, ScancodeSet1>> =
+            IrqSafeMutex::new(Keyboard::new(layouts::Us104Key, ScancodeSet1,
                 HandleControl::Ignore)
             );
     }
-    // 通过锁获取对 `KEYBOARD` 的访问权限，并将其赋值给变量 `keyboard` 供后续操作使用
+    // 设置指示灯时键盘的应答也从数据端口送来
+    if keymap::is_reply(scancode as u8) {
+        return;
+    }
+    // 通过锁获取对 `KEYBOARD` 的访问权限，并将其赋值给变量 `keyboard` 供后续操作使用。
     let mut keyboard = KEYBOARD.lock();
-    // 创建新的I/O端口对象以读取端口号为0x60的数据，0x60是标准PS/2键盘的数据端口号
-    let mut port = Port::new(0x60);
-    // 从数据端口读取一个字节大小的扫描码。因为I/O端口读写可能与硬件直接交互且无法保证总是安全有效，所以这里需要使用unsafe块
-    let scancode: u8 = unsafe { port.read() };
-    // 将扫描码添加到之前初始化的 `keyboard` 实例中并尝试解析出具体的按键事件。如果成功处理按键事件，则输出相应字符或按键信息。
-    // - 如果成功解析成Unicode字符，则直接打印该字符。
-    // - 如果是特殊按键，则打印其原始按键值的Debug表示形式。
-    if let Ok(Some(key_event)) = keyboard.add_byte(scancode) {
-        if let Some(key) = keyboard.process_keyevent(key_event) {
-            match key {
-                DecodedKey::Unicode(character) => print!("{}", character),
-                DecodedKey::RawKey(key) => print!("{:?}", key),
+    // 将扫描码添加到之前初始化的 `keyboard` 实例中并尝试解析出具体的按键事件，交给 key_event 处理
+    if let Ok(Some(key_event)) = keyboard.add_byte(scancode as u8) {
+        key_event(key_event.code, key_event.state == KeyState::Down);
+    }
+}
+
+/// 处理一次按下或松开，PS/2 键盘和 USB 键盘（见 drivers::xhci）都从这里把按键交给输入队列
+// - 按当前布局翻译成Unicode字符后放进键盘输入队列，由 shell 读取并回显。
+// - PageUp/PageDown 用来翻看终端窗口，加上 Shift 时翻看当前控制台；Ctrl+加号/减号缩放字号；
+//   Ctrl 加字母输入控制字符；Alt+F1..F4 切换虚拟控制台（见 io::vt）；Alt 加方向键、数字、空格和 Tab 用来摆放窗口（见 gui::tiling）；
+//   方向键、Home 和 End 换成代替它们的字符放进输入队列，其他特殊按键暂不处理。
+// 处理期间关中断：下面的输入队列、翻页和切换控制台原来都在中断里调用，它们拿的锁中断里也会拿
+pub fn key_event(code: pc_keyboard::KeyCode, down: bool) {
+    use pc_keyboard::{DecodedKey, KeyCode};
+    use crate::io::keyboard::keymap::{self, Modifiers};
+    static SHIFT: AtomicBool = AtomicBool::new(false);
+    static CTRL: AtomicBool = AtomicBool::new(false);
+    static ALT: AtomicBool = AtomicBool::new(false);
+    static ALT_GR: AtomicBool = AtomicBool::new(false);
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        // 自己记下修饰键的状态
+        match code {
+            KeyCode::ShiftLeft | KeyCode::ShiftRight => SHIFT.store(down, Ordering::Relaxed),
+            KeyCode::ControlLeft | KeyCode::ControlRight => CTRL.store(down, Ordering::Relaxed),
+            KeyCode::AltLeft => ALT.store(down, Ordering::Relaxed),
+            KeyCode::AltRight => ALT_GR.store(down, Ordering::Relaxed),
+            code => {
+                keymap::lock_key(code, down);
             }
         }
-    }
-    // 通过向PIC发送EOI（结束中断信号），通知硬件我们已经完成对当前这个中断处理程序的工作。同样地，因为涉及到底层硬件交互操作必须在unsafe块内执行
-    unsafe {
-        pics::PICS.lock().notify_end_of_interrupt(pics::InterruptIndex::Keyboard.as_u8());
-    }
+        if !down {
+            return;
+        }
+        let keymap = keymap::current();
+        let shift = SHIFT.load(Ordering::Relaxed);
+        let ctrl = CTRL.load(Ordering::Relaxed);
+        // 布局没有 AltGr 时右 Alt 也当作 Alt
+        let altgr = ALT_GR.load(Ordering::Relaxed) && keymap.has_altgr();
+        let alt = ALT.load(Ordering::Relaxed) || (ALT_GR.load(Ordering::Relaxed) && !keymap.has_altgr());
+        let modifiers = Modifiers { shift, altgr, caps_lock: keymap::caps_lock(), num_lock: keymap::num_lock() };
+        match keymap.translate(code, modifiers) {
+            Some(DecodedKey::RawKey(code)) if alt && console_key(code) => {}
+            Some(key) if alt && tiling_key(key) => {}
+            // Ctrl+加号/减号缩放控制台的字
+            Some(DecodedKey::Unicode('+' | '=')) if ctrl => crate::graphic::text::request_zoom(1),
+            Some(DecodedKey::Unicode('-')) if ctrl => crate::graphic::text::request_zoom(-1),
+            // Ctrl 加字母是 ASCII 控制字符，比如 Ctrl+C 是 0x03，见 gui::clipboard
+            Some(DecodedKey::Unicode(ch)) if ctrl && ch.is_ascii_alphabetic() => {
+                crate::io::keyboard::push_key((ch.to_ascii_uppercase() as u8 - b'@') as char)
+            }
+            Some(DecodedKey::Unicode(character)) => crate::io::keyboard::push_key(character),
+            // Shift+PageUp/PageDown 翻看当前控制台的历史，不带 Shift 时翻看终端窗口
+            Some(DecodedKey::RawKey(KeyCode::PageUp)) if shift => crate::io::scroll_console(1),
+            Some(DecodedKey::RawKey(KeyCode::PageDown)) if shift => crate::io::scroll_console(-1),
+            Some(DecodedKey::RawKey(KeyCode::PageUp)) => crate::gui::terminal::request_scroll(1),
+            Some(DecodedKey::RawKey(KeyCode::PageDown)) => crate::gui::terminal::request_scroll(-1),
+            Some(DecodedKey::RawKey(code)) if editing_key(code) => {}
+            _ => {}
+        }
+    });
+}
+
+// Alt+F1..F4 切换到对应的虚拟控制台，不是这几个键时返回 false
+fn console_key(code: pc_keyboard::KeyCode) -> bool {
+    use crate::io::vt::{self, Tty};
+    use pc_keyboard::KeyCode;
+
+    let index = match code {
+        KeyCode::F1 => 0,
+        KeyCode::F2 => 1,
+        KeyCode::F3 => 2,
+        KeyCode::F4 => 3,
+        _ => return false,
+    };
+    Tty::new(index).map(vt::request_switch).is_some()
+}
+
+// Alt 组合键对应的平铺命令，不是平铺按键时返回 false，按键照常处理
+fn tiling_key(key: pc_keyboard::DecodedKey) -> bool {
+    use crate::gui::tiling::{self, Command, Snap};
+    use pc_keyboard::{DecodedKey, KeyCode};
+
+    let command = match key {
+        DecodedKey::RawKey(KeyCode::ArrowLeft) => Command::Snap(Snap::LeftHalf),
+        DecodedKey::RawKey(KeyCode::ArrowRight) => Command::Snap(Snap::RightHalf),
+        DecodedKey::RawKey(KeyCode::ArrowUp) => Command::Snap(Snap::Maximize),
+        DecodedKey::RawKey(KeyCode::ArrowDown) => Command::Restore,
+        DecodedKey::Unicode(ch @ '1'..='4') => Command::Snap(Snap::Quadrant(ch as u8 - b'1')),
+        DecodedKey::Unicode(' ') => Command::CycleLayout,
+        DecodedKey::Unicode('\t') => Command::FocusNext,
+        _ => return false,
+    };
+    tiling::request(command);
+    true
+}
+
+// 方向键、Home 和 End 换成 io::keyboard 里代替它们的字符放进输入队列，不是这些键时返回 false
+fn editing_key(code: pc_keyboard::KeyCode) -> bool {
+    use crate::io::keyboard::{self, DOWN, END, HOME, LEFT, RIGHT, UP};
+    use pc_keyboard::KeyCode;
+
+    let ch = match code {
+        KeyCode::ArrowUp => UP,
+        KeyCode::ArrowDown => DOWN,
+        KeyCode::ArrowLeft => LEFT,
+        KeyCode::ArrowRight => RIGHT,
+        KeyCode::Home => HOME,
+        KeyCode::End => END,
+        _ => return false,
+    };
+    keyboard::push_key(ch);
+    true
+}
+
+// 串口收到数据，交给 io::qemu 放进接收缓冲区
+fn com1_interrupt(_stack_frame: &InterruptStackFrame) {
+    let _span = crate::trace::span("irq:com1");
+    crate::io::qemu::receive_interrupt();
+}
+
+// 鼠标每次中断送来数据包中的一个字节，交给 io::mouse 拼包
+fn mouse_interrupt(_stack_frame: &InterruptStackFrame) {
+    let _span = crate::trace::span("irq:mouse");
+    let port = Port::<u8>::new(0x60);
+    let byte: u8 = unsafe { port.read() };
+    crate::io::mouse::receive_byte(byte);
+}
+
+// RTC 的闹钟，唤醒停在 hlt 上的主循环去执行到期的回调
+fn rtc_interrupt(_stack_frame: &InterruptStackFrame) {
+    crate::io::alarm::interrupt();
 }
 
 // 1. 为什么double_fault_handler和breakpoint_handler不用发送EOI?
@@ -21,6 +21,30 @@ pub enum InterruptIndex {
     // 定义枚举，其中每一项代表重要硬件中断的索引值。首项 'Timer' 设定等同于之上对齐基准静态常量处 (即中断向量起点数)，而 'Keyboard' 自动递增位次序(33)
     Timer = PIC_1_OFFSET,
     Keyboard,
+    // COM1 串口接在主片的 IRQ4 上
+    Com1 = PIC_1_OFFSET + 4,
+    // RTC 接在从片的 IRQ8 上
+    Rtc = PIC_2_OFFSET,
+    // PS/2 鼠标接在从片的 IRQ12 上
+    Mouse = PIC_2_OFFSET + 4,
+}
+
+/// 取消屏蔽某条 IRQ 线，从片上的 IRQ 还需要打开主片上的级联线 IRQ2
+pub fn unmask_irq(index: InterruptIndex) {
+    unmask_line(index.line());
+}
+
+/// 按编号（0 到 15）取消屏蔽 IRQ 线，由 irq::register_handler 调用
+pub fn unmask_line(irq: u8) {
+    let mut pics = PICS.lock();
+    unsafe {
+        let [master, slave] = pics.read_masks();
+        if irq < 8 {
+            pics.write_masks(master & !(1 << irq), slave);
+        } else {
+            pics.write_masks(master & !(1 << 2), slave & !(1 << (irq - 8)));
+        }
+    }
 }
 
 impl InterruptIndex {
@@ -32,6 +56,11 @@ impl InterruptIndex {
     pub fn as_usize(self) -> usize {
         usize::from(self.as_u8())
     }
+
+    /// 对应的 IRQ 线（0 到 15）
+    pub fn line(self) -> u8 {
+        self.as_u8() - PIC_1_OFFSET
+    }
 }
 
 // 1. ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET)
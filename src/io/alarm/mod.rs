@@ -0,0 +1,111 @@
+// 定时唤醒
+// schedule_at 在某个绝对时间（UTC）执行回调。闹钟按最早的一个设定 RTC 的闹钟，响的时候 IRQ 8 把 CPU 从 hlt 上唤醒。
+// 读 RTC 要等它更新完，比较慢，所以登记时把时间换算成 uptime 的期限，之后只和 uptime 比较。
+// 回调不在中断里执行，而是由主循环空闲时调用 poll 执行，可以分配内存和画图；shell 正在执行命令时会晚一点执行
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::time::Duration;
+
+use lazy_static::lazy_static;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::drivers::rtc::{self, DateTime};
+use crate::io::timer::uptime;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AlarmId(u64);
+
+impl AlarmId {
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+}
+
+impl From<u64> for AlarmId {
+    fn from(id: u64) -> Self {
+        AlarmId(id)
+    }
+}
+
+struct Alarm {
+    id: AlarmId,
+    at: DateTime,
+    deadline: Duration,
+    callback: Box<dyn FnOnce() + Send>,
+}
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+// RTC 的闹钟响过，poll 需要重新设定闹钟
+static RANG: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    // 按期限从早到晚排列
+    static ref ALARMS: Mutex<Vec<Alarm>> = Mutex::new(Vec::new());
+}
+
+/// 在 `at`（UTC）执行 `callback`，时间已经过了时在下一次 poll 执行
+pub fn schedule_at(at: DateTime, callback: impl FnOnce() + Send + 'static) -> AlarmId {
+    let id = AlarmId(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+    let delay = (at.timestamp() - rtc::now().timestamp()).max(0) as u64;
+    let deadline = uptime() + Duration::from_secs(delay);
+    let mut alarms = ALARMS.lock();
+    let index = alarms.partition_point(|alarm| alarm.deadline <= deadline);
+    alarms.insert(index, Alarm { id, at, deadline, callback: Box::new(callback) });
+    program(&alarms);
+    id
+}
+
+/// 取消还没有执行的闹钟，返回是否找到
+pub fn cancel(id: AlarmId) -> bool {
+    let mut alarms = ALARMS.lock();
+    let Some(index) = alarms.iter().position(|alarm| alarm.id == id) else { return false };
+    alarms.remove(index);
+    program(&alarms);
+    true
+}
+
+/// 还没有执行的闹钟和它们的时间，从早到晚排列
+pub fn pending() -> Vec<(AlarmId, DateTime)> {
+    ALARMS.lock().iter().map(|alarm| (alarm.id, alarm.at)).collect()
+}
+
+/// 由 RTC 中断处理函数调用
+pub fn interrupt() {
+    if rtc::acknowledge() {
+        RANG.store(true, Ordering::Release);
+    }
+}
+
+/// 执行到期的闹钟，由主循环在空闲时调用
+pub fn poll() {
+    let now = uptime();
+    let rang = RANG.swap(false, Ordering::Acquire);
+    let due: Vec<Alarm> = {
+        let mut alarms = ALARMS.lock();
+        let count = alarms.partition_point(|alarm| alarm.deadline <= now);
+        if count == 0 && !rang {
+            return;
+        }
+        let due = alarms.drain(..count).collect();
+        program(&alarms);
+        due
+    };
+    // 回调里可能再登记闹钟，执行时不能拿着锁
+    for alarm in due {
+        (alarm.callback)();
+    }
+}
+
+// 按最早的闹钟设定 RTC，没有闹钟时关掉闹钟中断
+fn program(alarms: &[Alarm]) {
+    interrupts::without_interrupts(|| match alarms.first() {
+        Some(first) => {
+            rtc::set_alarm(first.at.hour, first.at.minute, first.at.second);
+            rtc::enable_alarm(true);
+        }
+        None => rtc::enable_alarm(false),
+    });
+}
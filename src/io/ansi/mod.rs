@@ -0,0 +1,169 @@
+// ANSI/VT100 转义序列
+// 控制台的各个后端（VGA 文本模式、图形文本图层、终端窗口）共用这个解析器：逐个字符喂进去，
+// 普通字符原样交回，转义序列翻译成 Action，由后端各自执行。
+// 支持 SGR 颜色（标准 16 色、粗体变亮）、光标移动（CUU/CUD/CUF/CUB/CUP）、清屏（ED 2）和清除到行尾（EL 0），
+// 其他序列被吞掉不显示
+
+use embedded_graphics::pixelcolor::Rgb888;
+
+// CSI 序列最多记录的参数个数，多出的忽略
+const MAX_PARAMS: usize = 8;
+
+// 标准 16 色，和 VGA 文本模式的调色板一致
+const PALETTE: [u32; 16] = [
+    0x000000, 0xAA0000, 0x00AA00, 0xAA5500, 0x0000AA, 0xAA00AA, 0x00AAAA, 0xAAAAAA,
+    0x555555, 0xFF5555, 0x55FF55, 0xFFFF55, 0x5555FF, 0xFF55FF, 0x55FFFF, 0xFFFFFF,
+];
+
+/// 后端需要执行的动作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// 普通字符，包括 '\n'、'\t' 等控制字符
+    Print(char),
+    /// 设置前景色，参数是 0..16 的颜色号，None 表示恢复默认
+    Foreground(Option<u8>),
+    /// 设置背景色，None 表示恢复默认
+    Background(Option<u8>),
+    /// 光标上移若干行
+    CursorUp(usize),
+    CursorDown(usize),
+    /// 光标右移若干列
+    CursorForward(usize),
+    CursorBack(usize),
+    /// 光标移到第 row 行第 col 列，都从 0 开始
+    CursorPosition(usize, usize),
+    /// 清除整个屏幕，光标不动
+    ClearScreen,
+    /// 清除光标到行尾
+    ClearLine,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    // 收到 ESC
+    Escape,
+    // 收到 ESC [
+    Csi,
+}
+
+pub struct Parser {
+    state: State,
+    params: [usize; MAX_PARAMS],
+    count: usize,
+    // SGR 1：之后设置的标准色用高亮的那一半
+    bold: bool,
+    foreground: Option<u8>,
+}
+
+impl Parser {
+    pub const fn new() -> Self {
+        Self { state: State::Ground, params: [0; MAX_PARAMS], count: 0, bold: false, foreground: None }
+    }
+
+    /// 喂进一个字符，解析出的动作交给 `emit`
+    pub fn feed(&mut self, ch: char, mut emit: impl FnMut(Action)) {
+        match self.state {
+            State::Ground => match ch {
+                '\x1b' => self.state = State::Escape,
+                ch => emit(Action::Print(ch)),
+            },
+            State::Escape => match ch {
+                '[' => {
+                    self.params = [0; MAX_PARAMS];
+                    self.count = 0;
+                    self.state = State::Csi;
+                }
+                // 不认识的 ESC 序列只吞掉下一个字符
+                _ => self.state = State::Ground,
+            },
+            State::Csi => match ch {
+                '0'..='9' => {
+                    let index = self.count.min(MAX_PARAMS - 1);
+                    self.params[index] = self.params[index].saturating_mul(10) + (ch as usize - '0' as usize);
+                }
+                ';' => self.count += 1,
+                // 私有序列的前缀，比如 ESC [ ? 25 l
+                '?' | '>' | '=' => {}
+                '\x40'..='\x7e' => {
+                    self.state = State::Ground;
+                    self.dispatch(ch, &mut emit);
+                }
+                // 序列中出现其他字符时放弃这个序列
+                _ => self.state = State::Ground,
+            },
+        }
+    }
+
+    // 第 i 个参数，没有给出或者为 0 时取 default
+    fn param(&self, i: usize, default: usize) -> usize {
+        match self.params.get(i) {
+            Some(&value) if i <= self.count && value != 0 => value,
+            _ => default,
+        }
+    }
+
+    fn dispatch(&mut self, command: char, emit: &mut impl FnMut(Action)) {
+        match command {
+            'A' => emit(Action::CursorUp(self.param(0, 1))),
+            'B' => emit(Action::CursorDown(self.param(0, 1))),
+            'C' => emit(Action::CursorForward(self.param(0, 1))),
+            'D' => emit(Action::CursorBack(self.param(0, 1))),
+            'H' | 'f' => emit(Action::CursorPosition(self.param(0, 1) - 1, self.param(1, 1) - 1)),
+            'J' if matches!(self.param(0, 0), 2 | 3) => emit(Action::ClearScreen),
+            'K' if self.param(0, 0) == 0 => emit(Action::ClearLine),
+            'm' => {
+                for i in 0..=self.count.min(MAX_PARAMS - 1) {
+                    self.sgr(self.params[i], emit);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn sgr(&mut self, code: usize, emit: &mut impl FnMut(Action)) {
+        match code {
+            0 => {
+                self.bold = false;
+                self.foreground = None;
+                emit(Action::Foreground(None));
+                emit(Action::Background(None));
+            }
+            1 => {
+                self.bold = true;
+                // 已经设置的标准色变亮
+                if let Some(color @ 0..=7) = self.foreground {
+                    self.set_foreground(Some(color + 8), emit);
+                }
+            }
+            22 => self.bold = false,
+            30..=37 => {
+                let color = (code - 30) as u8 + if self.bold { 8 } else { 0 };
+                self.set_foreground(Some(color), emit);
+            }
+            39 => self.set_foreground(None, emit),
+            40..=47 => emit(Action::Background(Some((code - 40) as u8))),
+            49 => emit(Action::Background(None)),
+            90..=97 => self.set_foreground(Some((code - 90) as u8 + 8), emit),
+            100..=107 => emit(Action::Background(Some((code - 100) as u8 + 8))),
+            _ => {}
+        }
+    }
+
+    fn set_foreground(&mut self, color: Option<u8>, emit: &mut impl FnMut(Action)) {
+        self.foreground = color;
+        emit(Action::Foreground(color));
+    }
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 颜色号对应的 RGB 颜色
+pub fn color(index: u8) -> Rgb888 {
+    let rgb = PALETTE[index as usize % PALETTE.len()];
+    Rgb888::new((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)
+}
@@ -0,0 +1,152 @@
+// 离屏控制台
+// 不绑定任何显示设备的虚拟控制台，用于捕获后台任务的 print!/println! 输出。
+// shell 的 bg 命令把一条命令的输出捕获到新的控制台里，jobs 列出控制台，output 打印或者在窗口里（gui::job_output）查看。
+// 捕获是全局的：capture 期间所有 CPU 上的 print! 都写进同一个控制台；shell_print! 同步到串口的那一份不受影响
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::fmt::Write;
+
+use lazy_static::lazy_static;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+// 每个离屏控制台最多保留的行数，超出后丢弃最早的行
+const MAX_LINES: usize = 256;
+
+pub type ConsoleId = usize;
+
+pub struct OffscreenConsole {
+    name: String,
+    lines: VecDeque<String>,
+    current: String,
+}
+
+impl OffscreenConsole {
+    fn new(name: &str) -> Self {
+        OffscreenConsole {
+            name: String::from(name),
+            lines: VecDeque::new(),
+            current: String::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 已完成的行加上尚未换行的部分
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines: Vec<String> = self.lines.iter().cloned().collect();
+        if !self.current.is_empty() {
+            lines.push(self.current.clone());
+        }
+        lines
+    }
+
+    fn push_line(&mut self) {
+        if self.lines.len() >= MAX_LINES {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(core::mem::take(&mut self.current));
+    }
+}
+
+impl fmt::Write for OffscreenConsole {
+    fn write_str(&mut self, s: &str) -> Result<(), core::fmt::Error> {
+        for ch in s.chars() {
+            match ch {
+                '\n' => self.push_line(),
+                '\r' => self.current.clear(),
+                ch => self.current.push(ch),
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Consoles {
+    next_id: ConsoleId,
+    consoles: BTreeMap<ConsoleId, OffscreenConsole>,
+    // 当前正在捕获输出的控制台
+    capture: Option<ConsoleId>,
+}
+
+lazy_static! {
+    static ref CONSOLES: Mutex<Consoles> = Mutex::new(Consoles {
+        next_id: 1,
+        consoles: BTreeMap::new(),
+        capture: None,
+    });
+}
+
+/// 创建一个离屏控制台
+pub fn create(name: &str) -> ConsoleId {
+    interrupts::without_interrupts(|| {
+        let mut consoles = CONSOLES.lock();
+        let id = consoles.next_id;
+        consoles.next_id += 1;
+        consoles.consoles.insert(id, OffscreenConsole::new(name));
+        id
+    })
+}
+
+/// 销毁离屏控制台，返回其中保留的输出
+pub fn destroy(id: ConsoleId) -> Option<Vec<String>> {
+    interrupts::without_interrupts(|| {
+        let mut consoles = CONSOLES.lock();
+        if consoles.capture == Some(id) {
+            consoles.capture = None;
+        }
+        consoles.consoles.remove(&id).map(|console| console.lines())
+    })
+}
+
+/// 读取离屏控制台中的输出
+pub fn lines(id: ConsoleId) -> Option<Vec<String>> {
+    interrupts::without_interrupts(|| {
+        CONSOLES.lock().consoles.get(&id).map(|console| console.lines())
+    })
+}
+
+/// 列出所有离屏控制台的编号和名字
+pub fn list() -> Vec<(ConsoleId, String)> {
+    interrupts::without_interrupts(|| {
+        CONSOLES.lock().consoles.iter()
+            .map(|(id, console)| (*id, String::from(console.name())))
+            .collect()
+    })
+}
+
+/// 在执行 `f` 期间把 print!/println! 的输出重定向到离屏控制台
+pub fn capture<R>(id: ConsoleId, f: impl FnOnce() -> R) -> R {
+    let previous = interrupts::without_interrupts(|| {
+        let mut consoles = CONSOLES.lock();
+        core::mem::replace(&mut consoles.capture, Some(id))
+    });
+    let result = f();
+    interrupts::without_interrupts(|| {
+        CONSOLES.lock().capture = previous;
+    });
+    result
+}
+
+/// 如果当前有控制台在捕获输出，就写进去并返回 true
+#[doc(hidden)]
+pub fn _capture(args: fmt::Arguments) -> bool {
+    interrupts::without_interrupts(|| {
+        let mut consoles = CONSOLES.lock();
+        match consoles.capture {
+            Some(id) => match consoles.consoles.get_mut(&id) {
+                Some(console) => {
+                    console.write_fmt(args).unwrap();
+                    true
+                }
+                None => false,
+            },
+            None => false,
+        }
+    })
+}
@@ -0,0 +1,190 @@
+// 数字、大小和时长的格式化
+// 各处输出统计数字都用这里的包装类型，写法一致：
+//   Size(bytes)       1.5 KiB、60.0 MiB，不到 1 KiB 时写字节数
+//   Thousands(n)      1,234,567
+//   Clock(duration)   02:03:04.567，超过一天时前面加 "1d "
+//   Elapsed(duration) 850 ns、12.5 us、3.2 ms、1.5 s，适合耗时
+// 千位分隔符和小数点跟随当前的 Locale，用 set_locale 切换。
+// 格式化不分配内存，先写到栈上的缓冲区再交给 Formatter::pad，所以 {:>10} 这样的对齐照常可用，panic 和日志里也能用
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU32, Ordering};
+use core::time::Duration;
+
+/// 数字的写法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Locale {
+    /// 千位分隔符，None 表示不分组
+    pub group: Option<char>,
+    /// 小数点
+    pub decimal: char,
+}
+
+impl Locale {
+    pub const C: Locale = Locale { group: None, decimal: '.' };
+    pub const EN: Locale = Locale { group: Some(','), decimal: '.' };
+    pub const DE: Locale = Locale { group: Some('.'), decimal: ',' };
+    pub const FR: Locale = Locale { group: Some(' '), decimal: ',' };
+
+    /// 按名字查找预设的 Locale
+    pub fn by_name(name: &str) -> Option<Locale> {
+        match name {
+            "c" | "C" | "posix" => Some(Locale::C),
+            "en" => Some(Locale::EN),
+            "de" => Some(Locale::DE),
+            "fr" => Some(Locale::FR),
+            _ => None,
+        }
+    }
+}
+
+// 用原子变量保存，panic 时也能读；0 表示不分组
+static GROUP: AtomicU32 = AtomicU32::new(',' as u32);
+static DECIMAL: AtomicU32 = AtomicU32::new('.' as u32);
+
+pub fn locale() -> Locale {
+    Locale {
+        group: char::from_u32(GROUP.load(Ordering::Relaxed)).filter(|&c| c != '\0'),
+        decimal: char::from_u32(DECIMAL.load(Ordering::Relaxed)).unwrap_or('.'),
+    }
+}
+
+pub fn set_locale(locale: Locale) {
+    GROUP.store(locale.group.map_or(0, |c| c as u32), Ordering::Relaxed);
+    DECIMAL.store(locale.decimal as u32, Ordering::Relaxed);
+}
+
+/// 字节数，用 1024 进制的单位
+#[derive(Debug, Clone, Copy)]
+pub struct Size(pub u64);
+
+/// 带千位分隔符的整数
+#[derive(Debug, Clone, Copy)]
+pub struct Thousands(pub u64);
+
+/// 时钟式的时长，精确到毫秒
+#[derive(Debug, Clone, Copy)]
+pub struct Clock(pub Duration);
+
+/// 耗时，自动选择 ns、us、ms 或 s
+#[derive(Debug, Clone, Copy)]
+pub struct Elapsed(pub Duration);
+
+const SIZE_UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut buffer = Buffer::new();
+        if self.0 < 1024 {
+            write_number(&mut buffer, self.0, None)?;
+            buffer.write_str(" B")?;
+            return f.pad(buffer.as_str());
+        }
+        // 选第一个四舍五入后小于 1024 的单位，避免出现 1024.0 KiB
+        let mut base = 1u128;
+        for (i, unit) in SIZE_UNITS.iter().enumerate() {
+            base *= 1024;
+            let tenths = (self.0 as u128 * 10 + base / 2) / base;
+            if tenths < 10240 || i == SIZE_UNITS.len() - 1 {
+                write_number(&mut buffer, (tenths / 10) as u64, Some((tenths % 10) as u8))?;
+                write!(buffer, " {}", unit)?;
+                break;
+            }
+        }
+        f.pad(buffer.as_str())
+    }
+}
+
+impl fmt::Display for Thousands {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut buffer = Buffer::new();
+        write_number(&mut buffer, self.0, None)?;
+        f.pad(buffer.as_str())
+    }
+}
+
+impl fmt::Display for Clock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut buffer = Buffer::new();
+        let seconds = self.0.as_secs();
+        let days = seconds / 86400;
+        if days > 0 {
+            write!(buffer, "{}d ", days)?;
+        }
+        write!(buffer, "{:02}:{:02}:{:02}{}{:03}", seconds / 3600 % 24, seconds / 60 % 60, seconds % 60,
+               locale().decimal, self.0.subsec_millis())?;
+        f.pad(buffer.as_str())
+    }
+}
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut buffer = Buffer::new();
+        let nanos = self.0.as_nanos();
+        if nanos < 1000 {
+            write!(buffer, "{} ns", nanos)?;
+        } else {
+            // 和 Size 一样按四舍五入后的值选单位
+            let mut base = 1u128;
+            for (i, unit) in ["us", "ms", "s"].iter().enumerate() {
+                base *= 1000;
+                let tenths = (nanos * 10 + base / 2) / base;
+                if tenths < 10000 || i == 2 {
+                    write_number(&mut buffer, (tenths / 10) as u64, Some((tenths % 10) as u8))?;
+                    write!(buffer, " {}", unit)?;
+                    break;
+                }
+            }
+        }
+        f.pad(buffer.as_str())
+    }
+}
+
+// 按当前 Locale 写整数部分和可选的一位小数
+fn write_number(out: &mut impl Write, integer: u64, tenths: Option<u8>) -> fmt::Result {
+    let locale = locale();
+    let mut digits = Buffer::new();
+    write!(digits, "{}", integer)?;
+    let digits = digits.as_str();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            if let Some(group) = locale.group {
+                out.write_char(group)?;
+            }
+        }
+        out.write_char(digit)?;
+    }
+    if let Some(tenths) = tenths {
+        write!(out, "{}{}", locale.decimal, tenths)?;
+    }
+    Ok(())
+}
+
+// 栈上的定长缓冲区，足够放下最长的结果：20 位数字加 6 个最多 4 字节的分隔符，再加单位
+struct Buffer {
+    bytes: [u8; 64],
+    len: usize,
+}
+
+impl Buffer {
+    fn new() -> Self {
+        Buffer { bytes: [0; 64], len: 0 }
+    }
+
+    fn as_str(&self) -> &str {
+        // 只通过 write_str 写入完整的 UTF-8 字符串
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or("")
+    }
+}
+
+impl Write for Buffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        if end > self.bytes.len() {
+            return Err(fmt::Error);
+        }
+        self.bytes[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
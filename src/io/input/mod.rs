@@ -0,0 +1,343 @@
+// 输入事件
+// 键盘、鼠标（以后还有触摸屏）的驱动不再各自维护队列，而是把解码好的事件交给 post：事件带上设备号和时钟给出的时间，
+// 复制给每个关心这类事件的订阅者。每个订阅者有自己的有界队列，满了丢弃新事件并计数，读得慢的订阅者不影响别人。
+// 订阅者可以不阻塞地取（try_next，主循环里轮询）、阻塞地等（next，停在 hlt 上），
+// 也可以当作异步流用（poll_next / next_async，事件到来时唤醒登记的 Waker）。
+// shell 的 KeyboardStream 和 GUI 的鼠标处理都是订阅者，游戏等窗口程序经由 GUI 的窗口事件拿到按键。
+// 订阅者可以独占输入（grab），独占期间事件只交给它，锁屏用它挡住输入密码时的按键。
+// 最后一个事件的时间记下来，屏幕保护程序据此判断闲置了多久；最近 HISTORY 个事件也留着，panic 时写进崩溃转储。
+// 设备在驱动初始化时登记，登记表是定长数组，堆初始化之前也能登记；post 可以在中断处理函数里调用
+
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::future::Future;
+use core::ops::BitOr;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use core::task::{Context, Poll, Waker};
+use core::time::Duration;
+
+use crate::io::timer::uptime;
+use crate::sync::{IrqSafeMutex, WaitQueue};
+use crate::trace::latency::{self, Source};
+
+/// 能登记的设备数
+pub const MAX_DEVICES: usize = 16;
+/// 留给崩溃转储的最近事件数
+pub const HISTORY: usize = 32;
+
+/// 一个输入事件；触摸屏以后加上自己的变体
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputEvent {
+    /// 按键解码后的字符，方向键这类编辑键用 keyboard 里的私用区字符
+    Key(char),
+    /// 鼠标的相对移动，`dx` 向右为正，`dy` 向上为正
+    MouseMove { dx: i16, dy: i16 },
+    MouseButton { button: MouseButton, pressed: bool },
+    /// 滚轮，向上滚为正
+    Wheel(i16),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceKind {
+    Keyboard,
+    Mouse,
+    Touch,
+}
+
+impl DeviceKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            DeviceKind::Keyboard => "keyboard",
+            DeviceKind::Mouse => "mouse",
+            DeviceKind::Touch => "touch",
+        }
+    }
+}
+
+/// 登记时分到的设备号
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceId(pub u8);
+
+/// 一个登记过的输入设备
+#[derive(Debug, Clone, Copy)]
+pub struct Device {
+    pub id: DeviceId,
+    pub name: &'static str,
+    pub kind: DeviceKind,
+    /// 送出过的事件数
+    pub events: u64,
+}
+
+/// 带来源和时间的事件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Event {
+    pub device: DeviceId,
+    /// 启动后的时间
+    pub time: Duration,
+    pub event: InputEvent,
+}
+
+/// 订阅哪几类事件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Filter(u8);
+
+impl Filter {
+    pub const KEYS: Filter = Filter(1);
+    /// 鼠标的移动、按键和滚轮
+    pub const POINTER: Filter = Filter(2);
+    pub const ALL: Filter = Filter(0xFF);
+
+    pub fn matches(self, event: &InputEvent) -> bool {
+        let class = match event {
+            InputEvent::Key(_) => Filter::KEYS,
+            InputEvent::MouseMove { .. } | InputEvent::MouseButton { .. } | InputEvent::Wheel(_) => Filter::POINTER,
+        };
+        self.0 & class.0 != 0
+    }
+}
+
+impl BitOr for Filter {
+    type Output = Filter;
+
+    fn bitor(self, other: Filter) -> Filter {
+        Filter(self.0 | other.0)
+    }
+}
+
+// 登记表，`len` 之后的项没有用
+struct Registry {
+    devices: [Option<Device>; MAX_DEVICES],
+    len: usize,
+}
+
+static DEVICES: IrqSafeMutex<Registry> = IrqSafeMutex::new(Registry { devices: [None; MAX_DEVICES], len: 0 });
+
+// 订阅者共享的队列，订阅释放时从 SUBSCRIBERS 里删掉，中断处理函数里不会释放内存
+struct Queue {
+    events: IrqSafeMutex<VecDeque<Event>>,
+    capacity: usize,
+    filter: Filter,
+    // 输入延迟测量按这个订阅者的队列算
+    latency: Option<Source>,
+    ready: WaitQueue,
+    waker: IrqSafeMutex<Option<Waker>>,
+    dropped: AtomicUsize,
+    grabbed: AtomicBool,
+}
+
+static SUBSCRIBERS: IrqSafeMutex<Vec<Arc<Queue>>> = IrqSafeMutex::new(Vec::new());
+// 最后一个事件的时间，纳秒
+static LAST_EVENT: AtomicU64 = AtomicU64::new(0);
+
+// 最近的事件，`next` 是下一个要写的位置
+struct History {
+    events: [Option<Event>; HISTORY],
+    next: usize,
+}
+
+static RECENT: IrqSafeMutex<History> = IrqSafeMutex::new(History { events: [None; HISTORY], next: 0 });
+
+/// 登记一个输入设备，登记表满了时返回 None
+pub fn register_device(name: &'static str, kind: DeviceKind) -> Option<DeviceId> {
+    let mut registry = DEVICES.lock();
+    if registry.len == MAX_DEVICES {
+        log::warn!("input: no room for device {}", name);
+        return None;
+    }
+    let id = DeviceId(registry.len as u8);
+    let len = registry.len;
+    registry.devices[len] = Some(Device { id, name, kind, events: 0 });
+    registry.len += 1;
+    log::debug!("input: {} {} registered as device {}", kind.name(), name, id.0);
+    Some(id)
+}
+
+/// 所有登记过的设备
+pub fn devices() -> Vec<Device> {
+    let registry = DEVICES.lock();
+    registry.devices[..registry.len].iter().flatten().copied().collect()
+}
+
+/// 订阅者的个数
+pub fn subscribers() -> usize {
+    SUBSCRIBERS.lock().len()
+}
+
+/// 最后一个事件的时间，还没有过事件时是 0
+pub fn last_event() -> Duration {
+    Duration::from_nanos(LAST_EVENT.load(Ordering::Relaxed))
+}
+
+/// 最近的 HISTORY 个事件，从早到晚排列，不够时前面是 None；不等锁，锁正被占用时返回 None，panic 时用
+pub fn try_history() -> Option<[Option<Event>; HISTORY]> {
+    let recent = RECENT.try_lock()?;
+    Some(core::array::from_fn(|i| recent.events[(recent.next + i) % HISTORY]))
+}
+
+/// 有没有订阅者还没取走的事件
+pub fn pending() -> bool {
+    SUBSCRIBERS.lock().iter().any(|queue| !queue.events.lock().is_empty())
+}
+
+/// 由驱动调用，把事件交给所有关心它的订阅者；可以在中断处理函数里调用
+pub fn post(device: DeviceId, event: InputEvent) {
+    let event = Event { device, time: uptime(), event };
+    LAST_EVENT.store(event.time.as_nanos() as u64, Ordering::Relaxed);
+    if let Some(Some(entry)) = DEVICES.lock().devices.get_mut(device.0 as usize) {
+        entry.events += 1;
+    }
+    {
+        let mut recent = RECENT.lock();
+        let next = recent.next;
+        recent.events[next] = Some(event);
+        recent.next = (next + 1) % HISTORY;
+    }
+    let subscribers = SUBSCRIBERS.lock();
+    let grabbed = subscribers.iter().any(|queue| queue.grabbed.load(Ordering::Relaxed));
+    let receivers = subscribers.iter().filter(|queue| !grabbed || queue.grabbed.load(Ordering::Relaxed));
+    for queue in receivers.filter(|queue| queue.filter.matches(&event.event)) {
+        {
+            let mut events = queue.events.lock();
+            if events.len() >= queue.capacity {
+                queue.dropped.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+            if let Some(source) = queue.latency {
+                latency::queued(source, events.len());
+            }
+            events.push_back(event);
+        }
+        queue.ready.wake_one();
+        // 不取走 Waker，中断处理函数里不释放它；多唤醒一次没有害处
+        if let Some(waker) = queue.waker.lock().as_ref() {
+            waker.wake_by_ref();
+        }
+    }
+    super::INPUT.wake_all();
+}
+
+/// 订阅 `filter` 选中的事件，队列最多放 `capacity` 个
+pub fn subscribe(filter: Filter, capacity: usize) -> Subscription {
+    subscribe_queue(filter, capacity, None)
+}
+
+/// 和 subscribe 一样，`source` 类的输入延迟测量按这个订阅者取走事件的时间算
+pub fn subscribe_measured(filter: Filter, capacity: usize, source: Source) -> Subscription {
+    subscribe_queue(filter, capacity, Some(source))
+}
+
+fn subscribe_queue(filter: Filter, capacity: usize, latency: Option<Source>) -> Subscription {
+    let queue = Arc::new(Queue {
+        events: IrqSafeMutex::new(VecDeque::with_capacity(capacity)),
+        capacity,
+        filter,
+        latency,
+        ready: WaitQueue::new(),
+        waker: IrqSafeMutex::new(None),
+        dropped: AtomicUsize::new(0),
+        grabbed: AtomicBool::new(false),
+    });
+    SUBSCRIBERS.lock().push(queue.clone());
+    Subscription { queue }
+}
+
+/// 一个订阅者，释放时退订
+pub struct Subscription {
+    queue: Arc<Queue>,
+}
+
+impl Subscription {
+    /// 不阻塞地取一个事件
+    pub fn try_next(&self) -> Option<Event> {
+        let event = self.queue.events.lock().pop_front()?;
+        if let Some(source) = self.queue.latency {
+            latency::dequeued(source);
+        }
+        Some(event)
+    }
+
+    /// 取一个事件，没有时停下当前 CPU 等待
+    pub fn next(&self) -> Event {
+        let mut event = None;
+        self.queue.ready.block_on_condition(|| {
+            event = self.try_next();
+            event.is_some()
+        });
+        event.unwrap()
+    }
+
+    /// 和 next 一样，但最多等 `timeout`
+    pub fn next_timeout(&self, timeout: Duration) -> Option<Event> {
+        let mut event = None;
+        self.queue.ready.block_on_condition_timeout(timeout, || {
+            event = self.try_next();
+            event.is_some()
+        });
+        event
+    }
+
+    /// 异步流的接口：没有事件时登记 `cx` 的 Waker，下一个事件到来时唤醒
+    pub fn poll_next(&self, cx: &mut Context<'_>) -> Poll<Event> {
+        if let Some(event) = self.try_next() {
+            return Poll::Ready(event);
+        }
+        *self.queue.waker.lock() = Some(cx.waker().clone());
+        // 登记之前刚好来了事件时不会被唤醒，再看一次
+        match self.try_next() {
+            Some(event) => Poll::Ready(event),
+            None => Poll::Pending,
+        }
+    }
+
+    /// 等下一个事件的 Future
+    pub fn next_async(&self) -> Next<'_> {
+        Next { subscription: self }
+    }
+
+    /// 还没取走的事件数
+    pub fn len(&self) -> usize {
+        self.queue.events.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 独占或者放开输入：独占期间事件只交给独占的订阅者，释放订阅时自动放开
+    pub fn grab(&self, grabbed: bool) {
+        self.queue.grabbed.store(grabbed, Ordering::Relaxed);
+    }
+
+    /// 因为队列满了丢掉的事件数
+    pub fn dropped(&self) -> usize {
+        self.queue.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        SUBSCRIBERS.lock().retain(|queue| !Arc::ptr_eq(queue, &self.queue));
+    }
+}
+
+/// Subscription::next_async 返回的 Future
+pub struct Next<'a> {
+    subscription: &'a Subscription,
+}
+
+impl Future for Next<'_> {
+    type Output = Event;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Event> {
+        self.subscription.poll_next(cx)
+    }
+}
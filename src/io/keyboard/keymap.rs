@@ -0,0 +1,301 @@
+// 键盘布局
+// pc_keyboard 只用来把扫描码拼成按键事件（KeyCode），按键对应什么字符由这里的键位表决定，运行时可以用 loadkeys 切换。
+// 每个布局只列出和美式布局不一样的键，其余的键查美式布局的表；AltGr（右 Alt）另有一张表，
+// 没有 AltGr 表的布局里右 Alt 和左 Alt 一样用于组合键。
+// Caps Lock、Num Lock、Scroll Lock 的状态也记在这里，按下时切换并通过 8042 的数据端口更新键盘上的指示灯
+
+use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+use pc_keyboard::{DecodedKey, KeyCode};
+
+use crate::io::port::Port;
+
+// 键盘和鼠标共用 8042 控制器
+const DATA_PORT: Port<u8> = Port::new(0x60);
+const STATUS_PORT: Port<u8> = Port::new(0x64);
+const INPUT_FULL: u8 = 0x02;
+// 等待控制器的轮询次数上限
+const TIMEOUT: usize = 100_000;
+
+// 键盘命令和应答
+const SET_LEDS: u8 = 0xED;
+const ACK: u8 = 0xFA;
+const RESEND: u8 = 0xFE;
+
+// 指示灯，也是三个锁定键的状态位
+const SCROLL_LOCK: u8 = 0x01;
+const NUM_LOCK: u8 = 0x02;
+const CAPS_LOCK: u8 = 0x04;
+
+// 锁定键的状态和正按着的锁定键，按住不放时的重复按键不再切换
+static LOCKS: AtomicU8 = AtomicU8::new(0);
+static HELD: AtomicU8 = AtomicU8::new(0);
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+
+/// 一个键盘布局
+pub struct Keymap {
+    pub name: &'static str,
+    pub description: &'static str,
+    // 和美式布局不同的键：不按 Shift、按 Shift 时的字符
+    keys: &'static [(KeyCode, char, char)],
+    // AltGr 加这个键得到的字符
+    altgr: &'static [(KeyCode, char)],
+}
+
+/// 翻译按键时用到的修饰键和锁定键
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub altgr: bool,
+    pub caps_lock: bool,
+    pub num_lock: bool,
+}
+
+const US_KEYS: [(KeyCode, char, char); 48] = [
+    (KeyCode::BackTick, '`', '~'),
+    (KeyCode::Key1, '1', '!'),
+    (KeyCode::Key2, '2', '@'),
+    (KeyCode::Key3, '3', '#'),
+    (KeyCode::Key4, '4', '$'),
+    (KeyCode::Key5, '5', '%'),
+    (KeyCode::Key6, '6', '^'),
+    (KeyCode::Key7, '7', '&'),
+    (KeyCode::Key8, '8', '*'),
+    (KeyCode::Key9, '9', '('),
+    (KeyCode::Key0, '0', ')'),
+    (KeyCode::Minus, '-', '_'),
+    (KeyCode::Equals, '=', '+'),
+    (KeyCode::Q, 'q', 'Q'),
+    (KeyCode::W, 'w', 'W'),
+    (KeyCode::E, 'e', 'E'),
+    (KeyCode::R, 'r', 'R'),
+    (KeyCode::T, 't', 'T'),
+    (KeyCode::Y, 'y', 'Y'),
+    (KeyCode::U, 'u', 'U'),
+    (KeyCode::I, 'i', 'I'),
+    (KeyCode::O, 'o', 'O'),
+    (KeyCode::P, 'p', 'P'),
+    (KeyCode::BracketSquareLeft, '[', '{'),
+    (KeyCode::BracketSquareRight, ']', '}'),
+    (KeyCode::BackSlash, '\\', '|'),
+    (KeyCode::A, 'a', 'A'),
+    (KeyCode::S, 's', 'S'),
+    (KeyCode::D, 'd', 'D'),
+    (KeyCode::F, 'f', 'F'),
+    (KeyCode::G, 'g', 'G'),
+    (KeyCode::H, 'h', 'H'),
+    (KeyCode::J, 'j', 'J'),
+    (KeyCode::K, 'k', 'K'),
+    (KeyCode::L, 'l', 'L'),
+    (KeyCode::SemiColon, ';', ':'),
+    (KeyCode::Quote, '\'', '"'),
+    (KeyCode::Z, 'z', 'Z'),
+    (KeyCode::X, 'x', 'X'),
+    (KeyCode::C, 'c', 'C'),
+    (KeyCode::V, 'v', 'V'),
+    (KeyCode::B, 'b', 'B'),
+    (KeyCode::N, 'n', 'N'),
+    (KeyCode::M, 'm', 'M'),
+    (KeyCode::Comma, ',', '<'),
+    (KeyCode::Fullstop, '.', '>'),
+    (KeyCode::Slash, '/', '?'),
+    (KeyCode::Spacebar, ' ', ' '),
+];
+
+static KEYMAPS: [Keymap; 3] = [
+    Keymap { name: "us", description: "US QWERTY", keys: &[], altgr: &[] },
+    Keymap {
+        name: "uk",
+        description: "UK QWERTY",
+        keys: &[
+            (KeyCode::BackTick, '`', '¬'),
+            (KeyCode::Key2, '2', '"'),
+            (KeyCode::Key3, '3', '£'),
+            (KeyCode::Quote, '\'', '@'),
+            (KeyCode::BackSlash, '#', '~'),
+        ],
+        altgr: &[(KeyCode::BackTick, '¦'), (KeyCode::Key4, '€')],
+    },
+    Keymap {
+        name: "de",
+        description: "German QWERTZ",
+        keys: &[
+            (KeyCode::BackTick, '^', '°'),
+            (KeyCode::Key2, '2', '"'),
+            (KeyCode::Key3, '3', '§'),
+            (KeyCode::Key6, '6', '&'),
+            (KeyCode::Key7, '7', '/'),
+            (KeyCode::Key8, '8', '('),
+            (KeyCode::Key9, '9', ')'),
+            (KeyCode::Key0, '0', '='),
+            (KeyCode::Minus, 'ß', '?'),
+            (KeyCode::Equals, '´', '`'),
+            (KeyCode::Y, 'z', 'Z'),
+            (KeyCode::Z, 'y', 'Y'),
+            (KeyCode::BracketSquareLeft, 'ü', 'Ü'),
+            (KeyCode::BracketSquareRight, '+', '*'),
+            (KeyCode::SemiColon, 'ö', 'Ö'),
+            (KeyCode::Quote, 'ä', 'Ä'),
+            (KeyCode::BackSlash, '#', '\''),
+            (KeyCode::Comma, ',', ';'),
+            (KeyCode::Fullstop, '.', ':'),
+            (KeyCode::Slash, '-', '_'),
+        ],
+        altgr: &[
+            (KeyCode::Key2, '²'),
+            (KeyCode::Key3, '³'),
+            (KeyCode::Key7, '{'),
+            (KeyCode::Key8, '['),
+            (KeyCode::Key9, ']'),
+            (KeyCode::Key0, '}'),
+            (KeyCode::Minus, '\\'),
+            (KeyCode::Q, '@'),
+            (KeyCode::E, '€'),
+            (KeyCode::BracketSquareRight, '~'),
+            (KeyCode::M, 'µ'),
+        ],
+    },
+];
+
+impl Keymap {
+    /// 有没有 AltGr 层
+    pub fn has_altgr(&self) -> bool {
+        !self.altgr.is_empty()
+    }
+
+    /// 按键对应的字符，没有字符的键原样返回 RawKey；修饰键和锁定键返回 None
+    pub fn translate(&self, code: KeyCode, modifiers: Modifiers) -> Option<DecodedKey> {
+        match code {
+            KeyCode::ShiftLeft | KeyCode::ShiftRight | KeyCode::ControlLeft | KeyCode::ControlRight => return None,
+            KeyCode::AltLeft | KeyCode::AltRight => return None,
+            KeyCode::CapsLock | KeyCode::NumpadLock | KeyCode::ScrollLock => return None,
+            _ => {}
+        }
+        if let Some(ch) = control(code).or_else(|| numpad(code, modifiers.num_lock != modifiers.shift)) {
+            return Some(ch);
+        }
+        if modifiers.altgr {
+            if let Some(&(_, ch)) = self.altgr.iter().find(|(key, _)| *key == code) {
+                return Some(DecodedKey::Unicode(ch));
+            }
+        }
+        let Some(&(_, normal, shifted)) = self.keys.iter().chain(US_KEYS.iter()).find(|(key, ..)| *key == code) else {
+            return Some(DecodedKey::RawKey(code));
+        };
+        // Caps Lock 只影响有大小写的字母，和 Shift 一起按时又变回小写
+        let letter = normal.is_lowercase() && shifted.is_uppercase();
+        let shift = modifiers.shift != (letter && modifiers.caps_lock);
+        Some(DecodedKey::Unicode(if shift { shifted } else { normal }))
+    }
+}
+
+// 和布局无关的控制字符
+fn control(code: KeyCode) -> Option<DecodedKey> {
+    let ch = match code {
+        KeyCode::Enter | KeyCode::NumpadEnter => '\n',
+        KeyCode::Backspace => '\u{8}',
+        KeyCode::Tab => '\t',
+        KeyCode::Escape => '\u{1b}',
+        KeyCode::Delete => '\u{7f}',
+        _ => return None,
+    };
+    Some(DecodedKey::Unicode(ch))
+}
+
+// 小键盘：打开 Num Lock 时是数字，关闭时是方向键和翻页键，运算符不受影响
+fn numpad(code: KeyCode, digits: bool) -> Option<DecodedKey> {
+    let (digit, key) = match code {
+        KeyCode::Numpad0 => ('0', Some(KeyCode::Insert)),
+        KeyCode::Numpad1 => ('1', Some(KeyCode::End)),
+        KeyCode::Numpad2 => ('2', Some(KeyCode::ArrowDown)),
+        KeyCode::Numpad3 => ('3', Some(KeyCode::PageDown)),
+        KeyCode::Numpad4 => ('4', Some(KeyCode::ArrowLeft)),
+        KeyCode::Numpad5 => ('5', None),
+        KeyCode::Numpad6 => ('6', Some(KeyCode::ArrowRight)),
+        KeyCode::Numpad7 => ('7', Some(KeyCode::Home)),
+        KeyCode::Numpad8 => ('8', Some(KeyCode::ArrowUp)),
+        KeyCode::Numpad9 => ('9', Some(KeyCode::PageUp)),
+        KeyCode::NumpadPeriod => ('.', Some(KeyCode::Delete)),
+        KeyCode::NumpadSlash => return Some(DecodedKey::Unicode('/')),
+        KeyCode::NumpadStar => return Some(DecodedKey::Unicode('*')),
+        KeyCode::NumpadMinus => return Some(DecodedKey::Unicode('-')),
+        KeyCode::NumpadPlus => return Some(DecodedKey::Unicode('+')),
+        _ => return None,
+    };
+    match key {
+        _ if digits => Some(DecodedKey::Unicode(digit)),
+        Some(KeyCode::Delete) => control(KeyCode::Delete),
+        Some(key) => Some(DecodedKey::RawKey(key)),
+        // Num Lock 关闭时小键盘 5 什么也不做
+        None => Some(DecodedKey::RawKey(code)),
+    }
+}
+
+/// 所有布局
+pub fn keymaps() -> &'static [Keymap] {
+    &KEYMAPS
+}
+
+/// 当前的布局
+pub fn current() -> &'static Keymap {
+    &KEYMAPS[CURRENT.load(Ordering::Relaxed)]
+}
+
+/// 切换到名为 `name` 的布局，没有这个布局时返回 false
+pub fn load(name: &str) -> bool {
+    match KEYMAPS.iter().position(|keymap| keymap.name == name) {
+        Some(index) => {
+            CURRENT.store(index, Ordering::Relaxed);
+            log::info!("keymap: {}", KEYMAPS[index].description);
+            true
+        }
+        None => false,
+    }
+}
+
+pub fn caps_lock() -> bool {
+    LOCKS.load(Ordering::Relaxed) & CAPS_LOCK != 0
+}
+
+pub fn num_lock() -> bool {
+    LOCKS.load(Ordering::Relaxed) & NUM_LOCK != 0
+}
+
+pub fn scroll_lock() -> bool {
+    LOCKS.load(Ordering::Relaxed) & SCROLL_LOCK != 0
+}
+
+/// 处理锁定键的按下和松开，按下时切换状态并更新指示灯；不是锁定键时返回 false
+pub fn lock_key(code: KeyCode, down: bool) -> bool {
+    let bit = match code {
+        KeyCode::CapsLock => CAPS_LOCK,
+        KeyCode::NumpadLock => NUM_LOCK,
+        KeyCode::ScrollLock => SCROLL_LOCK,
+        _ => return false,
+    };
+    if !down {
+        HELD.fetch_and(!bit, Ordering::Relaxed);
+    } else if HELD.fetch_or(bit, Ordering::Relaxed) & bit == 0 {
+        let leds = LOCKS.fetch_xor(bit, Ordering::Relaxed) ^ bit;
+        set_leds(leds);
+    }
+    true
+}
+
+/// 键盘对命令的应答，不是按键，解码时要跳过
+pub fn is_reply(byte: u8) -> bool {
+    byte == ACK || byte == RESEND
+}
+
+// 键盘的两个应答由键盘中断收到，在 is_reply 里丢掉
+fn set_leds(leds: u8) {
+    unsafe {
+        for byte in [SET_LEDS, leds] {
+            if !(0..TIMEOUT).any(|_| STATUS_PORT.read() & INPUT_FULL == 0) {
+                log::warn!("keyboard: controller busy, LEDs not updated");
+                return;
+            }
+            DATA_PORT.write(byte);
+        }
+    }
+}
@@ -0,0 +1,82 @@
+// 键盘输入
+// 键盘中断把按当前布局（见 keymap）解码后的字符作为 Key 事件交给 io::input，USB 键盘经由 interrupts::key_event 走同一条路，
+// 所有键盘共用一个设备号。shell 从 KeyboardStream 读取，它是 io::input 的一个只收按键的订阅者，
+// read 在没有输入时停下 CPU 等待。post 同时唤醒等着任何输入的 io::INPUT。
+// 方向键、Home 和 End 没有字符，用 Unicode 私用区里的字符代替，和普通字符一起排队，控件按这些常量识别
+
+pub mod keymap;
+
+use lazy_static::lazy_static;
+use spin::Once;
+
+use crate::io::input::{self, DeviceId, DeviceKind, Filter, InputEvent, Subscription};
+use crate::io::replay;
+use crate::trace::latency::Source;
+
+// KeyboardStream 的队列容量，满了之后新按下的键被丢弃
+const INPUT_BUFFER_SIZE: usize = 128;
+
+pub const UP: char = '\u{F700}';
+pub const DOWN: char = '\u{F701}';
+pub const LEFT: char = '\u{F702}';
+pub const RIGHT: char = '\u{F703}';
+pub const HOME: char = '\u{F729}';
+pub const END: char = '\u{F72B}';
+
+/// 是不是代替编辑键的字符，这些字符不能当作文字显示
+pub fn is_special(ch: char) -> bool {
+    ('\u{F700}'..='\u{F8FF}').contains(&ch)
+}
+
+static DEVICE: Once<Option<DeviceId>> = Once::new();
+
+lazy_static! {
+    // 第一次读取时订阅，之前按下的键不会排进来
+    static ref KEYS: Subscription = input::subscribe_measured(Filter::KEYS, INPUT_BUFFER_SIZE, Source::Key);
+}
+
+/// 登记键盘设备，需在开中断之前调用
+pub fn init() {
+    DEVICE.call_once(|| input::register_device("keyboard", DeviceKind::Keyboard));
+}
+
+/// 由键盘中断处理函数调用；回放输入时真实的按键被丢弃
+pub fn push_key(ch: char) {
+    if replay::capture(replay::InputEvent::Key(ch)) {
+        enqueue(ch);
+    }
+}
+
+pub(super) fn enqueue(ch: char) {
+    if let Some(&Some(device)) = DEVICE.get() {
+        input::post(device, InputEvent::Key(ch));
+    }
+}
+
+/// 有没有还没读走的字符
+pub fn has_input() -> bool {
+    !KEYS.is_empty()
+}
+
+/// 键盘输入流
+pub struct KeyboardStream;
+
+impl KeyboardStream {
+    /// 不阻塞地读取一个字符
+    pub fn try_read(&mut self) -> Option<char> {
+        loop {
+            if let InputEvent::Key(ch) = KEYS.try_next()?.event {
+                return Some(ch);
+            }
+        }
+    }
+
+    /// 读取一个字符，没有输入时停下当前 CPU 等待
+    pub fn read(&mut self) -> char {
+        loop {
+            if let InputEvent::Key(ch) = KEYS.next().event {
+                return ch;
+            }
+        }
+    }
+}
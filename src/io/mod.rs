@@ -1,10 +1,26 @@
 use core::fmt;
 use lazy_static::lazy_static;
 use spin::Mutex;
+use x86_64::instructions::interrupts;
 
+use crate::io::vt::Tty;
+use crate::sync::WaitQueue;
+
+pub mod alarm;
+pub mod ansi;
+pub mod console;
+pub mod format;
+pub mod input;
+pub mod keyboard;
+pub mod mouse;
 pub mod pci;
+pub mod port;
 pub mod time;
+pub mod timer;
 pub mod qemu;
+pub mod replay;
+pub mod theme;
+pub mod vt;
 
 pub enum VideoMode {
     Text,
@@ -24,27 +40,50 @@ impl VideoMode {
     }
 }
 
+/// 有新的键盘、鼠标或串口输入时唤醒，等输入的地方在这上面等
+pub static INPUT: WaitQueue = WaitQueue::new();
+
+/// 有没有等着处理的输入：订阅者还没取走的键盘、鼠标事件，串口字节，或者中断留下还没解码的扫描码
+pub fn has_input() -> bool {
+    input::pending() || qemu::has_input() || crate::interrupts::deferred::pending() != 0
+}
+
 lazy_static! {
     pub static ref VIDEO_MODE : Mutex<VideoMode> = Mutex::new(VideoMode::Text);
 }
 
-#[macro_export]
-macro_rules! debug {
-    ($($arg:tt)*) => ($crate::io::qemu::_qemu_print(format_args!($($arg)*)));
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    // 后台任务的输出被离屏控制台捕获，不上屏
+    if console::_capture(args) {
+        return;
+    }
+    vt::write(Tty::SHELL, args);
 }
 
-#[macro_export]
-macro_rules! debugln {
-    () => ($crate::print!("\n"));
-    ($($arg:tt)*) => ($crate::debug!("{}\n", format_args!($($arg)*)));
+// 清空 shell 所在的控制台
+pub fn clear_screen() {
+    interrupts::without_interrupts(|| {
+        if VIDEO_MODE.lock().is_text() {
+            if !crate::vga_buffer::with_background(Tty::SHELL, |writer| writer.clear_screen()) {
+                crate::vga_buffer::WRITER.lock().clear_screen();
+            }
+        } else if !crate::graphic::text::with_background(Tty::SHELL, |writer| writer.clear())
+            && !crate::gui::terminal::clear() {
+            crate::graphic::text::TEXT_WRITER.lock().clear();
+        }
+    })
 }
 
-#[doc(hidden)]
-pub fn _print(args: fmt::Arguments) {
-    if VIDEO_MODE.lock().is_text() {
-        crate::vga_buffer::_print(args);
+/// 当前控制台翻看历史，正数向上；由键盘的扫描码解码调用
+pub fn scroll_console(pages: isize) {
+    let Some(mode) = VIDEO_MODE.try_lock() else { return };
+    if mode.is_text() {
+        crate::vga_buffer::scroll(pages);
+    } else if crate::gui::terminal::is_open() {
+        crate::gui::terminal::request_scroll(pages);
     } else {
-        crate::graphic::_print(args);
+        crate::graphic::text::request_scroll(pages);
     }
 }
 
@@ -0,0 +1,177 @@
+// PS/2 鼠标
+// 通过 8042 控制器的辅助端口打开鼠标，鼠标中断逐字节收集 3 字节的数据包。
+// 解码出的数据包（USB 鼠标的报告也换成同样的格式）拆成移动、按键变化和滚轮事件交给 io::input，GUI 订阅这些事件
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use spin::{Mutex, Once};
+
+use crate::io::input::{self, DeviceId, DeviceKind, InputEvent, MouseButton, MAX_DEVICES};
+use crate::io::port::{self, Port};
+use crate::io::replay;
+
+// 8042 控制器的端口，键盘也用数据端口
+pub const DATA_PORT: Port<u8> = Port::new(0x60);
+const STATUS_PORT: Port<u8> = Port::new(0x64);
+const COMMAND_PORT: Port<u8> = Port::new(0x64);
+
+// 状态寄存器
+const OUTPUT_FULL: u8 = 0x01;
+const INPUT_FULL: u8 = 0x02;
+
+// 控制器命令
+const ENABLE_AUX: u8 = 0xA8;
+const READ_CONFIG: u8 = 0x20;
+const WRITE_CONFIG: u8 = 0x60;
+const WRITE_AUX: u8 = 0xD4;
+
+// 配置字节：打开 IRQ12，打开鼠标时钟
+const CONFIG_AUX_IRQ: u8 = 0x02;
+const CONFIG_AUX_CLOCK_DISABLED: u8 = 0x20;
+
+// 鼠标命令
+const SET_DEFAULTS: u8 = 0xF6;
+const ENABLE_REPORTING: u8 = 0xF4;
+const ACK: u8 = 0xFA;
+
+// 等待控制器的轮询次数上限，防止没有鼠标时卡死
+const TIMEOUT: usize = 100_000;
+
+/// 一个鼠标数据包，`dx` 向右为正，`dy` 向上为正，`wheel` 向上滚为正
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MouseEvent {
+    pub dx: i16,
+    pub dy: i16,
+    pub left: bool,
+    pub right: bool,
+    pub middle: bool,
+    pub wheel: i16,
+}
+
+struct Decoder {
+    packet: [u8; 3],
+    index: usize,
+}
+
+static DECODER: Mutex<Decoder> = Mutex::new(Decoder { packet: [0; 3], index: 0 });
+
+static DEVICE: Once<Option<DeviceId>> = Once::new();
+
+// 每个设备上一个数据包的按键状态，按位是左、右、中，和数据包比较得出按键的变化
+static BUTTONS: [AtomicU8; MAX_DEVICES] = [const { AtomicU8::new(0) }; MAX_DEVICES];
+
+unsafe fn wait_write() -> bool {
+    (0..TIMEOUT).any(|_| STATUS_PORT.read() & INPUT_FULL == 0)
+}
+
+unsafe fn wait_read() -> bool {
+    (0..TIMEOUT).any(|_| STATUS_PORT.read() & OUTPUT_FULL != 0)
+}
+
+unsafe fn write_command(command: u8) {
+    wait_write();
+    COMMAND_PORT.write(command);
+}
+
+unsafe fn write_mouse(byte: u8) -> bool {
+    write_command(WRITE_AUX);
+    wait_write();
+    DATA_PORT.write(byte);
+    wait_read() && DATA_PORT.read() == ACK
+}
+
+/// 打开鼠标和 IRQ12，需在开中断之前调用
+pub fn init() {
+    DEVICE.call_once(|| input::register_device("PS/2 mouse", DeviceKind::Mouse));
+    port::claim_or_log("i8042", DATA_PORT.number(), 1);
+    port::claim_or_log("i8042", STATUS_PORT.number(), 1);
+    unsafe {
+        write_command(ENABLE_AUX);
+        write_command(READ_CONFIG);
+        if !wait_read() {
+            log::warn!("PS/2 controller did not return its configuration");
+            return;
+        }
+        let config = (DATA_PORT.read() | CONFIG_AUX_IRQ) & !CONFIG_AUX_CLOCK_DISABLED;
+        write_command(WRITE_CONFIG);
+        wait_write();
+        DATA_PORT.write(config);
+
+        if !write_mouse(SET_DEFAULTS) || !write_mouse(ENABLE_REPORTING) {
+            log::warn!("PS/2 mouse did not respond");
+            return;
+        }
+    }
+    log::debug!("PS/2 mouse enabled");
+}
+
+/// 由鼠标中断处理函数调用，凑齐一个数据包后解码交给 io::input
+pub fn receive_byte(byte: u8) {
+    let mut decoder = DECODER.lock();
+    // 第一个字节的 bit 3 恒为 1，用来重新对齐数据包
+    if decoder.index == 0 && byte & 0x08 == 0 {
+        return;
+    }
+    let index = decoder.index;
+    decoder.packet[index] = byte;
+    decoder.index += 1;
+    if decoder.index < 3 {
+        return;
+    }
+    decoder.index = 0;
+
+    let [flags, x, y] = decoder.packet;
+    // 溢出的数据包没有意义，直接丢弃
+    if flags & 0xC0 != 0 {
+        return;
+    }
+    let event = MouseEvent {
+        dx: x as i16 - (((flags as i16) << 4) & 0x100),
+        dy: y as i16 - (((flags as i16) << 3) & 0x100),
+        left: flags & 0x01 != 0,
+        right: flags & 0x02 != 0,
+        middle: flags & 0x04 != 0,
+        wheel: 0,
+    };
+    push_event(event);
+}
+
+/// 放进一个 PS/2 鼠标的数据包，和真实的鼠标移动一样处理；回放输入时被丢弃
+///
+/// 中断处理函数之外调用时要关着中断
+pub fn push_event(event: MouseEvent) {
+    if let Some(&Some(device)) = DEVICE.get() {
+        push_event_from(device, event);
+    }
+}
+
+/// 和 push_event 一样，数据包来自 `device`，USB 鼠标用
+pub fn push_event_from(device: DeviceId, event: MouseEvent) {
+    if replay::capture(replay::InputEvent::Mouse(event)) {
+        post(device, event);
+    }
+}
+
+// 回放的数据包算作 PS/2 鼠标的
+pub(super) fn enqueue(event: MouseEvent) {
+    if let Some(&Some(device)) = DEVICE.get() {
+        post(device, event);
+    }
+}
+
+// 拆成先移动、再按键变化、最后滚轮的事件
+fn post(device: DeviceId, event: MouseEvent) {
+    if event.dx != 0 || event.dy != 0 {
+        input::post(device, InputEvent::MouseMove { dx: event.dx, dy: event.dy });
+    }
+    let buttons = event.left as u8 | (event.right as u8) << 1 | (event.middle as u8) << 2;
+    let previous = BUTTONS[device.0 as usize].swap(buttons, Ordering::AcqRel);
+    for (bit, button) in [MouseButton::Left, MouseButton::Right, MouseButton::Middle].into_iter().enumerate() {
+        if (buttons ^ previous) & (1 << bit) != 0 {
+            input::post(device, InputEvent::MouseButton { button, pressed: buttons & (1 << bit) != 0 });
+        }
+    }
+    if event.wheel != 0 {
+        input::post(device, InputEvent::Wheel(event.wheel));
+    }
+}
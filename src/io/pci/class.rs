@@ -0,0 +1,76 @@
+// PCI 类别代码
+// 把配置空间里的类别、子类别和编程接口翻译成可读的名称，名称沿用 PCI ID 数据库（lspci）的叫法。
+// 只收录常见的组合，认不出子类别时退回到类别的名称
+
+/// 类别、子类别和编程接口对应的名称
+pub fn name(class: u8, subclass: u8, prog_if: u8) -> &'static str {
+    match (class, subclass, prog_if) {
+        (0x01, 0x01, _) => "IDE interface",
+        (0x01, 0x06, 0x01) => "SATA controller (AHCI)",
+        (0x01, 0x08, 0x02) => "Non-Volatile memory controller (NVMe)",
+        (0x0C, 0x03, 0x00) => "USB controller (UHCI)",
+        (0x0C, 0x03, 0x10) => "USB controller (OHCI)",
+        (0x0C, 0x03, 0x20) => "USB controller (EHCI)",
+        (0x0C, 0x03, 0x30) => "USB controller (xHCI)",
+        _ => subclass_name(class, subclass),
+    }
+}
+
+fn subclass_name(class: u8, subclass: u8) -> &'static str {
+    match (class, subclass) {
+        (0x00, 0x01) => "VGA compatible unclassified device",
+        (0x01, 0x00) => "SCSI storage controller",
+        (0x01, 0x05) => "ATA controller",
+        (0x01, 0x06) => "SATA controller",
+        (0x01, 0x07) => "Serial Attached SCSI controller",
+        (0x01, 0x08) => "Non-Volatile memory controller",
+        (0x02, 0x00) => "Ethernet controller",
+        (0x02, 0x80) => "Network controller",
+        (0x03, 0x00) => "VGA compatible controller",
+        (0x03, 0x01) => "XGA compatible controller",
+        (0x03, 0x02) => "3D controller",
+        (0x04, 0x01) => "Multimedia audio controller",
+        (0x04, 0x03) => "Audio device",
+        (0x05, 0x00) => "RAM memory",
+        (0x06, 0x00) => "Host bridge",
+        (0x06, 0x01) => "ISA bridge",
+        (0x06, 0x04) => "PCI bridge",
+        (0x06, 0x80) => "Bridge",
+        (0x07, 0x00) => "Serial controller",
+        (0x07, 0x80) => "Communication controller",
+        (0x08, 0x00) => "PIC",
+        (0x08, 0x03) => "RTC",
+        (0x08, 0x80) => "System peripheral",
+        (0x09, 0x00) => "Keyboard controller",
+        (0x09, 0x02) => "Mouse controller",
+        (0x0C, 0x03) => "USB controller",
+        (0x0C, 0x05) => "SMBus",
+        _ => class_name(class),
+    }
+}
+
+fn class_name(class: u8) -> &'static str {
+    match class {
+        0x00 => "Unclassified device",
+        0x01 => "Mass storage controller",
+        0x02 => "Network controller",
+        0x03 => "Display controller",
+        0x04 => "Multimedia controller",
+        0x05 => "Memory controller",
+        0x06 => "Bridge",
+        0x07 => "Communication controller",
+        0x08 => "Generic system peripheral",
+        0x09 => "Input device controller",
+        0x0A => "Docking station",
+        0x0B => "Processor",
+        0x0C => "Serial bus controller",
+        0x0D => "Wireless controller",
+        0x0E => "Intelligent controller",
+        0x0F => "Satellite communications controller",
+        0x10 => "Encryption controller",
+        0x11 => "Signal processing controller",
+        0x12 => "Processing accelerators",
+        0xFF => "Unassigned class",
+        _ => "Unknown class",
+    }
+}
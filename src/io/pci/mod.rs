@@ -1,8 +1,17 @@
-use x86::io::{inl, outl};
+use alloc::vec::Vec;
+
+use crate::io::port::{self, Port};
+
+pub mod class;
 
 // 定义两个常量，表示PCI配置空间的地址寄存器和数据寄存器的I/O端口地址
-const PCI_CONFIG_ADDRESS: u16 = 0xCF8;
-const PCI_CONFIG_DATA: u16 = 0xCFC;
+const PCI_CONFIG_ADDRESS: Port<u32> = Port::new(0xCF8);
+const PCI_CONFIG_DATA: Port<u32> = Port::new(0xCFC);
+
+/// 登记配置空间的两个端口，需在探测设备之前调用
+pub fn init() {
+    port::claim_or_log("pci", PCI_CONFIG_ADDRESS.number(), 8);
+}
 
 // 读取PCI配置空间
 // - 定义一个函数 `pci_config_read_u32`，用于读取指定位置的PCI配置空间。
@@ -20,11 +29,20 @@ const PCI_CONFIG_DATA: u16 = 0xCFC;
 pub fn pci_config_read_u32(bus: u8, device: u8, function: u8, offset: u8) -> u32 {
     let addr: u32 = ((bus as u32) << 16) | ((device as u32) << 11) | ((function as u32) << 8) | ((offset as u32) & 0xFC) | 0x8000_0000u32;
     return unsafe {
-        outl(PCI_CONFIG_ADDRESS, addr);
-        inl(PCI_CONFIG_DATA)
+        PCI_CONFIG_ADDRESS.write(addr);
+        PCI_CONFIG_DATA.read()
     };
 }
 
+/// 写 PCI 配置空间，地址的构造和读一样
+pub fn pci_config_write_u32(bus: u8, device: u8, function: u8, offset: u8, value: u32) {
+    let addr: u32 = ((bus as u32) << 16) | ((device as u32) << 11) | ((function as u32) << 8) | ((offset as u32) & 0xFC) | 0x8000_0000u32;
+    unsafe {
+        PCI_CONFIG_ADDRESS.write(addr);
+        PCI_CONFIG_DATA.write(value);
+    }
+}
+
 // - 定义一个函数 `pci_find_device`，用于查找特定厂商ID和设备ID的PCI设备。
 // - 参数包括目标设备ID (`device_id`) 和厂商ID (`vendor_id`)。返回值为找到的总线号、设备号和功能号（如果未找到，则返回 `(0xFF, 0xFF ,0xFF)`）。
 // 构建目标值：
@@ -54,6 +72,137 @@ pub fn pci_find_device(device_id: u16, vendor_id: u16) -> (u8, u8, u8) {
     (0xFF, 0xFF, 0xFF)
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PciDevice {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub prog_if: u8,
+    pub revision: u8,
+}
+
+impl PciDevice {
+    fn read(bus: u8, device: u8, function: u8) -> Option<Self> {
+        let id = pci_config_read_u32(bus, device, function, 0);
+        if id & 0xFFFF == 0xFFFF {
+            return None;
+        }
+        let class = pci_config_read_u32(bus, device, function, 0x08);
+        Some(Self {
+            bus,
+            device,
+            function,
+            vendor_id: id as u16,
+            device_id: (id >> 16) as u16,
+            class: (class >> 24) as u8,
+            subclass: (class >> 16) as u8,
+            prog_if: (class >> 8) as u8,
+            revision: class as u8,
+        })
+    }
+
+    pub fn config_read_u32(&self, offset: u8) -> u32 {
+        pci_config_read_u32(self.bus, self.device, self.function, offset)
+    }
+
+    pub fn config_write_u32(&self, offset: u8, value: u32) {
+        pci_config_write_u32(self.bus, self.device, self.function, offset, value)
+    }
+
+    /// 打开命令寄存器里的 I/O 空间、内存空间和总线主控位，设备做 DMA 之前需要
+    pub fn enable_bus_master(&self) {
+        // 命令寄存器是低 16 位，高 16 位的状态寄存器写 1 清除，写回 0 不影响
+        let command = self.config_read_u32(0x04) & 0xFFFF;
+        self.config_write_u32(0x04, command | 0x7);
+    }
+
+    /// 类别、子类别和编程接口对应的名称
+    pub fn class_name(&self) -> &'static str {
+        class::name(self.class, self.subclass, self.prog_if)
+    }
+
+    /// 第 index 个 BAR 的原始值，只有普通设备（头部类型 0）有 6 个 BAR
+    pub fn bar(&self, index: u8) -> u32 {
+        self.config_read_u32(0x10 + index * 4)
+    }
+
+    /// 中断线和中断引脚（1 到 4 对应 INTA# 到 INTD#，0 表示不使用中断）
+    pub fn interrupt(&self) -> (u8, u8) {
+        let value = self.config_read_u32(0x3C);
+        (value as u8, (value >> 8) as u8)
+    }
+}
+
+/// 驱动用来认领设备的规则
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceMatch {
+    /// 厂商 ID 和设备 ID 完全一致
+    Id { vendor_id: u16, device_id: u16 },
+    /// 类别和子类别一致，prog_if 为 None 时不比较编程接口，比如任何 AHCI 控制器是 01/06/01
+    Class { class: u8, subclass: u8, prog_if: Option<u8> },
+}
+
+impl DeviceMatch {
+    pub fn matches(&self, device: &PciDevice) -> bool {
+        match *self {
+            DeviceMatch::Id { vendor_id, device_id } =>
+                device.vendor_id == vendor_id && device.device_id == device_id,
+            DeviceMatch::Class { class, subclass, prog_if } =>
+                device.class == class && device.subclass == subclass
+                    && prog_if.map_or(true, |p| device.prog_if == p),
+        }
+    }
+}
+
+// 遍历所有总线、设备和功能，对存在的设备调用 `f`，`f` 返回 false 时停止
+// 厂商ID为 0xFFFF 表示该位置没有设备；功能 0 不存在时跳过该设备的其余功能
+fn for_each_device(mut f: impl FnMut(PciDevice) -> bool) {
+    for bus in 0..=255 {
+        for device in 0..32 {
+            for function in 0..8 {
+                match PciDevice::read(bus, device, function) {
+                    Some(found) => if !f(found) {
+                        return;
+                    },
+                    None if function == 0 => break,
+                    None => continue,
+                }
+            }
+        }
+    }
+}
+
+pub fn pci_enumerate() -> Vec<PciDevice> {
+    let mut devices = Vec::new();
+    for_each_device(|device| {
+        devices.push(device);
+        true
+    });
+    devices
+}
+
+/// 按规则的先后顺序查找设备：前面的规则没有匹配到任何设备时才试后面的，所以精确的 ID 应该放在类别前面。
+/// 不分配内存，堆初始化之前也可以使用
+pub fn pci_find(rules: &[DeviceMatch]) -> Option<PciDevice> {
+    for rule in rules {
+        let mut found = None;
+        for_each_device(|device| {
+            if rule.matches(&device) {
+                found = Some(device);
+            }
+            found.is_none()
+        });
+        if found.is_some() {
+            return found;
+        }
+    }
+    None
+}
+
 // ## 总结:
 
 // 本代码片段实现了基本操作来与系统中的 PCI 配置空间进行交互，其主要功能包括:
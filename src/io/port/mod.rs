@@ -0,0 +1,170 @@
+// I/O 端口
+// Port<u8>/Port<u16>/Port<u32> 包装 in/out 指令，端口号和宽度写在类型里，和 x86_64 crate 的 Port 用法一样。
+// 另外记下哪个驱动占用了哪段端口：驱动初始化时先 claim 自己的端口范围，和已有的范围重叠就返回错误，
+// 两个驱动争同一段端口（比如 VBE 的 0x1CE 和别的设备）在启动时就能发现，而不是读写到一半出怪事。
+// 登记表是固定大小的，不依赖堆，堆初始化之前的驱动也能用；ioports 命令列出所有登记的范围
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::marker::PhantomData;
+
+use crate::sync::IrqSafeMutex;
+
+// 登记表的大小
+const MAX_CLAIMS: usize = 48;
+
+/// 可以整个读写的端口宽度
+pub trait PortValue: Copy {
+    unsafe fn read_from(port: u16) -> Self;
+    unsafe fn write_to(port: u16, value: Self);
+}
+
+impl PortValue for u8 {
+    unsafe fn read_from(port: u16) -> Self {
+        x86::io::inb(port)
+    }
+
+    unsafe fn write_to(port: u16, value: Self) {
+        x86::io::outb(port, value)
+    }
+}
+
+impl PortValue for u16 {
+    unsafe fn read_from(port: u16) -> Self {
+        x86::io::inw(port)
+    }
+
+    unsafe fn write_to(port: u16, value: Self) {
+        x86::io::outw(port, value)
+    }
+}
+
+impl PortValue for u32 {
+    unsafe fn read_from(port: u16) -> Self {
+        x86::io::inl(port)
+    }
+
+    unsafe fn write_to(port: u16, value: Self) {
+        x86::io::outl(port, value)
+    }
+}
+
+/// 一个 I/O 端口，`T` 是每次读写的宽度
+///
+/// 读写端口可能改变设备的状态，所以 read 和 write 都是 unsafe 的
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Port<T> {
+    port: u16,
+    _value: PhantomData<T>,
+}
+
+impl<T: PortValue> Port<T> {
+    pub const fn new(port: u16) -> Self {
+        Self { port, _value: PhantomData }
+    }
+
+    /// 端口号
+    pub fn number(&self) -> u16 {
+        self.port
+    }
+
+    pub unsafe fn read(&self) -> T {
+        T::read_from(self.port)
+    }
+
+    pub unsafe fn write(&self, value: T) {
+        T::write_to(self.port, value)
+    }
+}
+
+/// 一段登记过的端口
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Claim {
+    /// 占用它的驱动
+    pub owner: &'static str,
+    pub start: u16,
+    pub len: u16,
+}
+
+impl Claim {
+    /// 最后一个端口
+    pub fn last(&self) -> u16 {
+        self.start + (self.len - 1)
+    }
+
+    fn overlaps(&self, start: u16, len: u16) -> bool {
+        start <= self.last() && self.start <= start + (len - 1)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClaimError {
+    /// 和别的驱动已经占用的范围重叠
+    Conflict(Claim),
+    /// 长度为 0，或者超出了端口空间
+    InvalidRange,
+    /// 登记表满了
+    Full,
+}
+
+impl fmt::Display for ClaimError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClaimError::Conflict(claim) => {
+                write!(f, "ports {:#x}-{:#x} are owned by {}", claim.start, claim.last(), claim.owner)
+            }
+            ClaimError::InvalidRange => write!(f, "invalid port range"),
+            ClaimError::Full => write!(f, "too many port ranges"),
+        }
+    }
+}
+
+static CLAIMS: IrqSafeMutex<[Option<Claim>; MAX_CLAIMS]> = IrqSafeMutex::named("io ports", [None; MAX_CLAIMS]);
+
+/// 登记 `owner` 占用从 `start` 开始的 `len` 个端口；同一个驱动重复登记同一段时直接成功
+pub fn claim(owner: &'static str, start: u16, len: u16) -> Result<(), ClaimError> {
+    if len == 0 || start.checked_add(len - 1).is_none() {
+        return Err(ClaimError::InvalidRange);
+    }
+    let mut claims = CLAIMS.lock();
+    let wanted = Claim { owner, start, len };
+    if let Some(existing) = claims.iter().flatten().find(|claim| claim.overlaps(start, len)) {
+        return if *existing == wanted { Ok(()) } else { Err(ClaimError::Conflict(*existing)) };
+    }
+    let slot = claims.iter_mut().find(|slot| slot.is_none()).ok_or(ClaimError::Full)?;
+    *slot = Some(wanted);
+    Ok(())
+}
+
+/// 登记，重叠时输出错误日志，给启动时固定存在的设备用
+pub fn claim_or_log(owner: &'static str, start: u16, len: u16) -> bool {
+    match claim(owner, start, len) {
+        Ok(()) => true,
+        Err(error) => {
+            log::error!("{}: cannot claim ports {:#x}-{:#x}: {}", owner, start, start.wrapping_add(len.max(1) - 1),
+                        error);
+            false
+        }
+    }
+}
+
+/// 释放从 `start` 开始的那段端口，设备移除时调用
+pub fn release(start: u16) {
+    let mut claims = CLAIMS.lock();
+    if let Some(slot) = claims.iter_mut().find(|slot| slot.is_some_and(|claim| claim.start == start)) {
+        *slot = None;
+    }
+}
+
+/// 占用 `port` 的驱动
+pub fn owner(port: u16) -> Option<&'static str> {
+    CLAIMS.lock().iter().flatten().find(|claim| claim.overlaps(port, 1)).map(|claim| claim.owner)
+}
+
+/// 登记过的所有范围，按起始端口排列
+pub fn claims() -> Vec<Claim> {
+    let table = *CLAIMS.lock();
+    let mut claims: Vec<Claim> = table.iter().flatten().copied().collect();
+    claims.sort_by_key(|claim| claim.start);
+    claims
+}
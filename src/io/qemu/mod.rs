@@ -1,18 +1,148 @@
+use alloc::string::String;
 use core::fmt;
 use core::fmt::Write;
+use core::sync::atomic::{AtomicU32, Ordering};
 use lazy_static::lazy_static;
-use x86::io::outb;
 use x86_64::instructions::interrupts;
 use spin::Mutex;
 
+use crate::io::port::Port;
+use crate::io::qemu::uart::Uart;
+
+pub mod uart;
+
 #[repr(u16)]
 enum IoPort {
     Com1 = 0x3F8
 }
 
+// 默认波特率
+pub const DEFAULT_BAUD_RATE: u32 = 115_200;
+// 接收缓冲区大小，满了之后新收到的字节被丢弃
+const INPUT_BUFFER_SIZE: usize = 256;
+
+static COM1: Uart = Uart::new("com1", IoPort::Com1 as u16);
+static BAUD_RATE: AtomicU32 = AtomicU32::new(DEFAULT_BAUD_RATE);
+
+// 中断处理函数写入、SerialStream 读取的环形缓冲区，固定大小，中断中不分配内存
+struct InputBuffer {
+    data: [u8; INPUT_BUFFER_SIZE],
+    head: usize,
+    len: usize,
+}
+
+impl InputBuffer {
+    fn push(&mut self, byte: u8) {
+        if self.len < INPUT_BUFFER_SIZE {
+            self.data[(self.head + self.len) % INPUT_BUFFER_SIZE] = byte;
+            self.len += 1;
+        }
+    }
+
+    fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let byte = self.data[self.head];
+        self.head = (self.head + 1) % INPUT_BUFFER_SIZE;
+        self.len -= 1;
+        Some(byte)
+    }
+}
+
+static INPUT: Mutex<InputBuffer> = Mutex::new(InputBuffer {
+    data: [0; INPUT_BUFFER_SIZE],
+    head: 0,
+    len: 0,
+});
+
+/// 初始化 COM1 并打开接收中断，需在开中断之前调用
+pub fn init(baud_rate: u32) {
+    COM1.init(baud_rate);
+    BAUD_RATE.store(baud_rate, Ordering::Relaxed);
+}
+
+/// 运行时修改波特率，返回实际生效的波特率
+pub fn set_baud_rate(baud_rate: u32) -> u32 {
+    let actual = interrupts::without_interrupts(|| unsafe { COM1.set_baud_rate(baud_rate) });
+    BAUD_RATE.store(actual, Ordering::Relaxed);
+    actual
+}
+
+pub fn baud_rate() -> u32 {
+    BAUD_RATE.load(Ordering::Relaxed)
+}
+
 pub fn qemu_print(content: &str) {
     for ch in content.as_bytes() {
-        unsafe { outb(IoPort::Com1 as u16, *ch); };
+        COM1.send(*ch);
+    }
+}
+
+/// 由 COM1 的中断处理函数调用，把 FIFO 中的数据全部搬进接收缓冲区
+pub fn receive_interrupt() {
+    let mut input = INPUT.lock();
+    while let Some(byte) = COM1.try_receive() {
+        input.push(byte);
+    }
+    if input.len != 0 {
+        crate::io::INPUT.wake_all();
+    }
+}
+
+/// 接收缓冲区里有没有还没读走的字节
+pub fn has_input() -> bool {
+    interrupts::without_interrupts(|| INPUT.lock().len != 0)
+}
+
+/// 串口输入流，shell 等从这里读取用户在 `-serial stdio` 中的输入
+pub struct SerialStream;
+
+impl SerialStream {
+    /// 不阻塞地读取一个字节
+    pub fn try_read(&mut self) -> Option<u8> {
+        interrupts::without_interrupts(|| INPUT.lock().pop())
+    }
+
+    /// 阻塞读取一个字节，等待期间 CPU 停在 hlt 上，收到数据时被 io::INPUT 唤醒
+    pub fn read(&mut self) -> u8 {
+        let mut byte = None;
+        crate::io::INPUT.block_on_condition(|| {
+            byte = self.try_read();
+            byte.is_some()
+        });
+        byte.unwrap()
+    }
+
+    /// 阻塞读取一行（不含行尾的 `\r` 或 `\n`），并回显输入
+    pub fn read_line(&mut self) -> String {
+        let mut line = String::new();
+        loop {
+            match self.read() {
+                b'\r' | b'\n' => {
+                    qemu_print("\n");
+                    return line;
+                }
+                // 退格
+                0x08 | 0x7F => {
+                    if line.pop().is_some() {
+                        qemu_print("\x08 \x08");
+                    }
+                }
+                byte => {
+                    let ch = byte as char;
+                    line.push(ch);
+                    COM1.send(byte);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Write for SerialStream {
+    fn write_str(&mut self, s: &str) -> Result<(), core::fmt::Error> {
+        qemu_print(s);
+        Ok(())
     }
 }
 
@@ -36,3 +166,31 @@ pub fn _qemu_print(args: fmt::Arguments) {
         QEMU_WRITER.lock().write_fmt(args).unwrap();
     })
 }
+
+/// 通过串口输出，测试结果和日志都走这里
+#[macro_export]
+macro_rules! serial_print {
+    ($($arg:tt)*) => ($crate::io::qemu::_qemu_print(format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! serial_println {
+    () => ($crate::serial_print!("\n"));
+    ($($arg:tt)*) => ($crate::serial_print!("{}\n", format_args!($($arg)*)));
+}
+
+// QEMU 的 isa-debug-exit 设备所在的端口，见 Cargo.toml 里 bootimage 的 test-args
+const DEBUG_EXIT_PORT: u16 = 0xf4;
+
+/// 退出 QEMU 时的状态码，QEMU 的退出码是 (code << 1) | 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum QemuExitCode {
+    Success = 0x10,
+    Failed = 0x11,
+}
+
+/// 让 QEMU 退出，只在带 isa-debug-exit 设备运行（cargo test）时有效，否则什么也不做
+pub fn exit_qemu(exit_code: QemuExitCode) {
+    unsafe { Port::<u32>::new(DEBUG_EXIT_PORT).write(exit_code as u32) };
+}
@@ -0,0 +1,116 @@
+// 16550 UART 串口驱动
+// 负责波特率、帧格式的设置，以及收发单个字节
+
+use crate::io::port::{self, Port};
+
+// 每个串口占 8 个端口
+const PORT_COUNT: u16 = 8;
+
+// UART 的输入时钟除以 16 后的最高波特率
+pub const MAX_BAUD_RATE: u32 = 115_200;
+
+// 寄存器相对基址的偏移
+#[repr(u16)]
+enum Register {
+    // DLAB=0 时为收发数据，DLAB=1 时为分频值低字节
+    Data = 0,
+    // DLAB=0 时为中断使能，DLAB=1 时为分频值高字节
+    InterruptEnable = 1,
+    FifoControl = 2,
+    LineControl = 3,
+    ModemControl = 4,
+    LineStatus = 5,
+    Scratch = 7,
+}
+
+const LINE_CONTROL_DLAB: u8 = 0x80;
+// 8 位数据、无校验、1 位停止位
+const LINE_CONTROL_8N1: u8 = 0x03;
+// 启用并清空 FIFO，14 字节触发
+const FIFO_ENABLE_CLEAR_14: u8 = 0xC7;
+// DTR、RTS，以及 OUT2（打开后才会向 PIC 发出中断）
+const MODEM_DTR_RTS_OUT2: u8 = 0x0B;
+// 接收到数据时产生中断
+const INTERRUPT_RX_AVAILABLE: u8 = 0x01;
+const LINE_STATUS_DATA_READY: u8 = 0x01;
+const LINE_STATUS_THR_EMPTY: u8 = 0x20;
+
+pub struct Uart {
+    // 登记端口时用的名字
+    name: &'static str,
+    base: u16,
+}
+
+impl Uart {
+    pub const fn new(name: &'static str, base: u16) -> Self {
+        Uart { name, base }
+    }
+
+    fn register(&self, register: Register) -> Port<u8> {
+        Port::new(self.base + register as u16)
+    }
+
+    unsafe fn write_register(&self, register: Register, value: u8) {
+        self.register(register).write(value);
+    }
+
+    unsafe fn read_register(&self, register: Register) -> u8 {
+        self.register(register).read()
+    }
+
+    /// 按给定波特率初始化为 8N1，并打开接收中断
+    pub fn init(&self, baud_rate: u32) {
+        self.init_polled(baud_rate);
+        unsafe { self.write_register(Register::InterruptEnable, INTERRUPT_RX_AVAILABLE) };
+    }
+
+    /// 同 init，但不打开中断，只能用 try_receive 轮询
+    pub fn init_polled(&self, baud_rate: u32) {
+        port::claim_or_log(self.name, self.base, PORT_COUNT);
+        unsafe {
+            self.write_register(Register::InterruptEnable, 0);
+            self.set_baud_rate(baud_rate);
+            self.write_register(Register::FifoControl, FIFO_ENABLE_CLEAR_14);
+            self.write_register(Register::ModemControl, MODEM_DTR_RTS_OUT2);
+        }
+    }
+
+    /// 串口是否存在：没有设备的端口读出来总是 0xFF，写进暂存寄存器的值读不回来
+    pub fn is_present(&self) -> bool {
+        unsafe {
+            self.write_register(Register::Scratch, 0x5A);
+            self.read_register(Register::Scratch) == 0x5A
+        }
+    }
+
+    /// 设置波特率，返回实际生效的波特率
+    pub unsafe fn set_baud_rate(&self, baud_rate: u32) -> u32 {
+        let divisor = (MAX_BAUD_RATE / baud_rate.max(1)).clamp(1, u16::MAX as u32) as u16;
+        self.write_register(Register::LineControl, LINE_CONTROL_DLAB);
+        self.write_register(Register::Data, divisor as u8);
+        self.write_register(Register::InterruptEnable, (divisor >> 8) as u8);
+        self.write_register(Register::LineControl, LINE_CONTROL_8N1);
+        MAX_BAUD_RATE / divisor as u32
+    }
+
+    /// 等待发送保持寄存器为空后发送一个字节
+    pub fn send(&self, byte: u8) {
+        unsafe {
+            while self.read_register(Register::LineStatus) & LINE_STATUS_THR_EMPTY == 0 {
+                core::hint::spin_loop();
+            }
+            self.write_register(Register::Data, byte);
+        }
+    }
+
+    /// 有数据时读取一个字节
+    pub fn try_receive(&self) -> Option<u8> {
+        unsafe {
+            if self.read_register(Register::LineStatus) & LINE_STATUS_DATA_READY != 0 {
+                Some(self.read_register(Register::Data))
+            } else {
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,258 @@
+// 输入录制和回放，用于 GUI 回归测试
+// 录制时键盘和鼠标事件连同时间（启动录制后的时钟中断数）记进定长缓冲区，dump 把它们和当前画面各块的校验和
+// 一起以文本输出到串口，保存下来就是一份测试脚本。测试启动时用 load 读入脚本再 start_replay：
+// 时钟中断按原来的时间把事件送进输入队列，期间真实的输入被丢弃；全部送完并等画面稳定后，
+// 由 GUI 主循环调用 verify 逐块比较画面的校验和。
+//
+// 脚本每行一条，'#' 开头的是注释：
+//   K <时间> <字符的 Unicode 码>
+//   M <时间> <dx> <dy> <按键，左 1、右 2、中 4>
+//   C <块号> <十六进制校验和>
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::graphic::{self, Region, GD};
+use crate::io::mouse::MouseEvent;
+use crate::io::{keyboard, mouse, timer};
+
+/// 画面按行、列各分成 GRID 份，逐块计算校验和
+pub const GRID: usize = 4;
+
+// 能录制的事件数，满了之后的事件不再记录
+const MAX_EVENTS: usize = 4096;
+// 最后一个事件送出后再等这么多个时钟中断，让 GUI 和 shell 处理完再比较画面
+const SETTLE_TICKS: u64 = 500;
+
+#[derive(Debug, Clone, Copy)]
+pub enum InputEvent {
+    Key(char),
+    Mouse(MouseEvent),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Recorded {
+    tick: u64,
+    event: InputEvent,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Idle,
+    Recording,
+    Replaying,
+}
+
+struct Log {
+    events: [Recorded; MAX_EVENTS],
+    len: usize,
+    // 回放时下一个要送出的事件
+    next: usize,
+    // 开始录制或回放时的时钟中断数
+    start: u64,
+    state: State,
+    // 脚本里期望的各块校验和
+    expected: [Option<u32>; GRID * GRID],
+}
+
+static LOG: Mutex<Log> = Mutex::new(Log {
+    events: [Recorded { tick: 0, event: InputEvent::Key('\0') }; MAX_EVENTS],
+    len: 0,
+    next: 0,
+    start: 0,
+    state: State::Idle,
+    expected: [None; GRID * GRID],
+});
+// 回放结束且画面已经稳定，还没有被 take_finished 取走
+static FINISHED: AtomicBool = AtomicBool::new(false);
+static TICK_REGISTERED: AtomicBool = AtomicBool::new(false);
+
+/// 由键盘、鼠标的中断处理函数调用：录制时记下事件，返回 false 表示正在回放、应丢弃这个真实的事件
+pub(super) fn capture(event: InputEvent) -> bool {
+    let mut log = LOG.lock();
+    match log.state {
+        State::Idle => true,
+        State::Replaying => false,
+        State::Recording => {
+            let len = log.len;
+            if len < MAX_EVENTS {
+                log.events[len] = Recorded { tick: timer::ticks() - log.start, event };
+                log.len += 1;
+            }
+            true
+        }
+    }
+}
+
+/// 清空之前的记录，开始录制
+pub fn start_recording() {
+    interrupts::without_interrupts(|| {
+        let mut log = LOG.lock();
+        log.len = 0;
+        log.expected = [None; GRID * GRID];
+        log.start = timer::ticks();
+        log.state = State::Recording;
+    });
+}
+
+/// 停止录制或回放，记录保留
+pub fn stop() {
+    interrupts::without_interrupts(|| LOG.lock().state = State::Idle);
+}
+
+/// 录制好的或者读入的事件数
+pub fn len() -> usize {
+    interrupts::without_interrupts(|| LOG.lock().len)
+}
+
+/// 读入脚本，替换现有的记录；格式错误时返回出错的行号（从 1 开始）
+pub fn load(script: &str) -> Result<usize, usize> {
+    interrupts::without_interrupts(|| {
+        let mut log = LOG.lock();
+        log.state = State::Idle;
+        log.len = 0;
+        log.expected = [None; GRID * GRID];
+        for (i, line) in script.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            parse_line(&mut log, line).ok_or(i + 1)?;
+        }
+        Ok(log.len)
+    })
+}
+
+fn parse_line(log: &mut Log, line: &str) -> Option<()> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let event = match fields.as_slice() {
+        ["C", index, checksum] => {
+            let index: usize = index.parse().ok()?;
+            *log.expected.get_mut(index)? = Some(u32::from_str_radix(checksum, 16).ok()?);
+            return Some(());
+        }
+        ["K", tick, code] => Recorded {
+            tick: tick.parse().ok()?,
+            event: InputEvent::Key(char::from_u32(code.parse().ok()?)?),
+        },
+        ["M", tick, dx, dy, buttons] => {
+            let buttons: u8 = buttons.parse().ok()?;
+            Recorded {
+                tick: tick.parse().ok()?,
+                event: InputEvent::Mouse(MouseEvent {
+                    dx: dx.parse().ok()?,
+                    dy: dy.parse().ok()?,
+                    left: buttons & 1 != 0,
+                    right: buttons & 2 != 0,
+                    middle: buttons & 4 != 0,
+                    wheel: 0,
+                }),
+            }
+        }
+        _ => return None,
+    };
+    let len = log.len;
+    *log.events.get_mut(len)? = event;
+    log.len += 1;
+    Some(())
+}
+
+/// 从头回放现有的记录
+pub fn start_replay() {
+    if !TICK_REGISTERED.swap(true, Ordering::Relaxed) {
+        timer::register_periodic(Duration::from_millis(1), replay_tick);
+    }
+    FINISHED.store(false, Ordering::Relaxed);
+    interrupts::without_interrupts(|| {
+        let mut log = LOG.lock();
+        log.next = 0;
+        log.start = timer::ticks();
+        log.state = State::Replaying;
+    });
+}
+
+// 在时钟中断中把到时间的事件送进输入队列
+fn replay_tick() {
+    let Some(mut log) = LOG.try_lock() else { return };
+    if log.state != State::Replaying {
+        return;
+    }
+    let elapsed = timer::ticks() - log.start;
+    while log.next < log.len && log.events[log.next].tick <= elapsed {
+        match log.events[log.next].event {
+            InputEvent::Key(ch) => keyboard::enqueue(ch),
+            InputEvent::Mouse(event) => mouse::enqueue(event),
+        }
+        log.next += 1;
+    }
+    let last = if log.len == 0 { 0 } else { log.events[log.len - 1].tick };
+    if log.next == log.len && elapsed >= last + SETTLE_TICKS {
+        log.state = State::Idle;
+        FINISHED.store(true, Ordering::Release);
+    }
+}
+
+pub fn is_replaying() -> bool {
+    interrupts::without_interrupts(|| LOG.lock().state == State::Replaying)
+}
+
+/// 回放是否自上次调用以来结束过
+pub fn take_finished() -> bool {
+    FINISHED.swap(false, Ordering::Acquire)
+}
+
+// 第 index 块的区域
+fn block(index: usize) -> Region {
+    let (height, width) = (graphic::height(), graphic::width());
+    let (row, col) = (index / GRID, index % GRID);
+    Region {
+        sx: row * height / GRID,
+        sy: col * width / GRID,
+        ex: (row + 1) * height / GRID,
+        ey: (col + 1) * width / GRID,
+    }
+}
+
+/// 当前画面各块的校验和
+pub fn checksums() -> [u32; GRID * GRID] {
+    crate::gui::frame::flush();
+    let gd = GD.lock();
+    core::array::from_fn(|i| gd.checksum(block(i)))
+}
+
+/// 和脚本里的校验和比较，返回不一样的块号；脚本里没有校验和时返回 None
+pub fn verify() -> Option<Vec<usize>> {
+    let expected = interrupts::without_interrupts(|| LOG.lock().expected);
+    if expected.iter().all(Option::is_none) {
+        return None;
+    }
+    let actual = checksums();
+    Some((0..GRID * GRID).filter(|&i| expected[i].map_or(false, |e| e != actual[i])).collect())
+}
+
+/// 把记录和当前画面的校验和以脚本格式写出
+pub fn dump(out: &mut impl fmt::Write) -> fmt::Result {
+    let checksums = checksums();
+    interrupts::without_interrupts(|| {
+        let log = LOG.lock();
+        writeln!(out, "# {} events, screen {}x{}", log.len, graphic::width(), graphic::height())?;
+        for recorded in &log.events[..log.len] {
+            match recorded.event {
+                InputEvent::Key(ch) => writeln!(out, "K {} {}", recorded.tick, ch as u32)?,
+                InputEvent::Mouse(e) => {
+                    let buttons = e.left as u8 | (e.right as u8) << 1 | (e.middle as u8) << 2;
+                    writeln!(out, "M {} {} {} {}", recorded.tick, e.dx, e.dy, buttons)?;
+                }
+            }
+        }
+        for (i, checksum) in checksums.iter().enumerate() {
+            writeln!(out, "C {} {:08x}", i, checksum)?;
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,129 @@
+// 控制台配色
+// 全局的默认前景色和背景色，VGA 文本模式和图形文本图层都遵守：切换主题后，下一次输出时两边的颜色都恢复成主题的颜色，
+// ANSI 的 SGR 0/39/49 也恢复到主题。没有指定的颜色用各个后端自己的默认值；
+// VGA 文本模式只有 16 种颜色，取调色板里最接近的一种。
+// 主题里还有窗口的外观（圆角和阴影），窗口管理器发现主题变了就重画所有窗口。
+// 主题也可以写成文本（见 parse），由 gui::reload_theme 从资源 theme/theme.conf 读取
+
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use embedded_graphics::pixelcolor::Rgb888;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Theme {
+    /// None 时用后端的默认前景色
+    pub foreground: Option<Rgb888>,
+    /// None 时用后端的默认背景（图形模式下是透明）
+    pub background: Option<Rgb888>,
+    pub window: WindowStyle,
+}
+
+/// 窗口的外观
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowStyle {
+    /// 圆角半径，0 为直角
+    pub corner_radius: usize,
+    /// 阴影的模糊半径，0 为没有阴影
+    pub shadow_radius: usize,
+    /// 阴影相对窗口向下、向右的偏移
+    pub shadow_offset: (usize, usize),
+    /// 阴影最深处的不透明度，0 到 255
+    pub shadow_alpha: u8,
+}
+
+impl WindowStyle {
+    pub const DEFAULT: WindowStyle = WindowStyle { corner_radius: 6, shadow_radius: 8, shadow_offset: (3, 3), shadow_alpha: 120 };
+    /// 直角、没有阴影，和加入圆角和阴影之前一样
+    pub const FLAT: WindowStyle = WindowStyle { corner_radius: 0, shadow_radius: 0, shadow_offset: (0, 0), shadow_alpha: 0 };
+}
+
+impl Default for WindowStyle {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+static THEME: Mutex<Theme> = Mutex::new(Theme { foreground: None, background: None, window: WindowStyle::DEFAULT });
+// 每次切换主题加一，输出器据此发现主题变了
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+pub fn get() -> Theme {
+    interrupts::without_interrupts(|| *THEME.lock())
+}
+
+/// 切换主题，之后的输出生效
+pub fn set(theme: Theme) {
+    interrupts::without_interrupts(|| *THEME.lock() = theme);
+    GENERATION.fetch_add(1, Ordering::Release);
+}
+
+pub fn generation() -> usize {
+    GENERATION.load(Ordering::Acquire)
+}
+
+/// 主题文本的错误，带出错的行号（从 1 开始）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThemeError {
+    /// 不是 key = value
+    Syntax(usize),
+    UnknownKey(usize),
+    InvalidValue(usize),
+}
+
+impl fmt::Display for ThemeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ThemeError::Syntax(line) => write!(f, "line {}: expected key = value", line),
+            ThemeError::UnknownKey(line) => write!(f, "line {}: unknown key", line),
+            ThemeError::InvalidValue(line) => write!(f, "line {}: invalid value", line),
+        }
+    }
+}
+
+/// 解析主题文本，没有写的项用默认值
+///
+/// 每行一项 `key = value`，# 开头的是注释。foreground 和 background 是 #rrggbb 或者 none，
+/// corner_radius、shadow_radius 和 shadow_alpha 是数字，shadow_offset 是两个数字
+pub fn parse(text: &str) -> Result<Theme, ThemeError> {
+    let mut theme = Theme::default();
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line.split_once('=').ok_or(ThemeError::Syntax(number))?;
+        let value = value.trim();
+        let invalid = ThemeError::InvalidValue(number);
+        let style = &mut theme.window;
+        match key.trim() {
+            "foreground" => theme.foreground = parse_color(value).ok_or(invalid)?,
+            "background" => theme.background = parse_color(value).ok_or(invalid)?,
+            "corner_radius" => style.corner_radius = value.parse().map_err(|_| invalid)?,
+            "shadow_radius" => style.shadow_radius = value.parse().map_err(|_| invalid)?,
+            "shadow_alpha" => style.shadow_alpha = value.parse().map_err(|_| invalid)?,
+            "shadow_offset" => {
+                let mut numbers = value.split_whitespace().map(str::parse);
+                let (Some(Ok(down)), Some(Ok(right)), None) = (numbers.next(), numbers.next(), numbers.next()) else {
+                    return Err(invalid);
+                };
+                style.shadow_offset = (down, right);
+            }
+            _ => return Err(ThemeError::UnknownKey(number)),
+        }
+    }
+    Ok(theme)
+}
+
+// #rrggbb 或者 none
+fn parse_color(value: &str) -> Option<Option<Rgb888>> {
+    if value == "none" {
+        return Some(None);
+    }
+    let digits = value.strip_prefix('#').filter(|digits| digits.len() == 6 && digits.bytes().all(|b| b.is_ascii_hexdigit()))?;
+    let rgb = u32::from_str_radix(digits, 16).ok()?;
+    Some(Some(Rgb888::new((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)))
+}
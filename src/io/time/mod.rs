@@ -1,10 +1,23 @@
-use crate::io::time::cmos::{RawTime, read_RTC};
-pub mod cmos;
+use crate::drivers::rtc::{now, DateTime};
 
-const TIME_ZONE: u8 = 8;
+const TIME_ZONE: i64 = 8;
 
-pub fn get_raw_time() -> RawTime {
-    let mut tm = read_RTC();
-    tm.hour += TIME_ZONE;
-    tm
+// 本地时间
+pub fn get_raw_time() -> DateTime {
+    now().with_offset_hours(TIME_ZONE)
+}
+
+/// 本地时间下一次到 `hour:minute:second` 的时刻，换算成 UTC，供闹钟使用
+pub fn next_local(hour: u8, minute: u8, second: u8) -> DateTime {
+    let local = get_raw_time();
+    let mut target = DateTime { hour, minute, second, ..local };
+    if target.timestamp() <= local.timestamp() {
+        target = DateTime::from_timestamp(target.timestamp() + 86400);
+    }
+    target.with_offset_hours(-TIME_ZONE)
+}
+
+/// UTC 时间换算成本地时间
+pub fn to_local(time: DateTime) -> DateTime {
+    time.with_offset_hours(TIME_ZONE)
 }
@@ -0,0 +1,68 @@
+// 高精度事件定时器(HPET)
+// 通过 ACPI 的 HPET 表找到寄存器基址，只使用主计数器作为高精度的单调时钟源
+
+use core::mem::size_of;
+
+use x86_64::structures::paging::{FrameAllocator, Mapper, Size2MiB, Size4KiB};
+use x86_64::PhysAddr;
+
+use crate::drivers::acpi;
+use crate::memory::mmio::{Mmio, ReadOnly, ReadWrite, Reserved};
+use crate::memory::vmm::map_mmio;
+
+const ENABLE_CNF: u64 = 1;
+
+// 用到的寄存器，后面各个比较器的寄存器不用
+#[repr(C)]
+struct Registers {
+    general_capabilities: ReadOnly<u64>,
+    _reserved0: Reserved<0x08>,
+    general_configuration: ReadWrite<u64>,
+    _reserved1: Reserved<0xD8>,
+    main_counter: ReadWrite<u64>,
+}
+
+pub struct Hpet {
+    registers: Mmio<Registers>,
+    // 主计数器每次加一经过的飞秒数
+    period_fs: u64,
+}
+
+impl Hpet {
+    /// 主计数器的当前值
+    pub fn counter(&self) -> u64 {
+        self.registers.main_counter.read()
+    }
+
+    /// 从计数器启动开始经过的纳秒数
+    pub fn nanos(&self) -> u64 {
+        (self.counter() as u128 * self.period_fs as u128 / 1_000_000) as u64
+    }
+}
+
+/// 查找 HPET 并启动主计数器，找不到时返回 None
+pub fn init<M>(mapper: &mut M, frame_allocator: &mut impl FrameAllocator<Size4KiB>) -> Option<Hpet>
+where
+    M: Mapper<Size4KiB> + Mapper<Size2MiB>,
+{
+    let base = find_hpet_base()?;
+    log::info!("HPET found at {:?}", base);
+    let region = map_mmio(mapper, frame_allocator, base, size_of::<Registers>() as u64).ok()?;
+    let registers: Mmio<Registers> = unsafe { Mmio::from_region(region) };
+
+    let period_fs = registers.general_capabilities.read() >> 32;
+    if period_fs == 0 {
+        return None;
+    }
+    // 先停下计数器并清零，再启动
+    let config = registers.general_configuration.update(|config| config & !ENABLE_CNF);
+    registers.main_counter.write(0);
+    registers.general_configuration.write(config | ENABLE_CNF);
+    Some(Hpet { registers, period_fs })
+}
+
+// HPET 表头之后是 4 字节的 block id，然后是通用地址结构，其中地址在第 4 字节处
+fn find_hpet_base() -> Option<PhysAddr> {
+    let table = acpi::find_table(b"HPET")?;
+    Some(PhysAddr::new(acpi::read_phys::<u64>(table + 44)))
+}
@@ -0,0 +1,131 @@
+// 定时器子系统
+// PIT 产生周期性的时钟中断并累加全局 tick 计数；有 HPET 时用它的主计数器提供更高精度的单调时钟。
+// 对外提供 uptime()、sleep()、周期回调注册和时间轮上的一次性超时，供调度和 GUI 动画使用
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+use lazy_static::lazy_static;
+use spin::{Mutex, Once};
+use x86_64::instructions::interrupts;
+use x86_64::structures::paging::{FrameAllocator, Mapper, Size2MiB, Size4KiB};
+
+use crate::boot::Unit;
+use crate::io::timer::hpet::Hpet;
+
+pub mod hpet;
+pub mod pit;
+pub mod wheel;
+
+// 时钟中断频率
+pub const TIMER_HZ: u32 = 1000;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+// 启动以来的时钟中断次数
+static TICKS: AtomicU64 = AtomicU64::new(0);
+// PIT 实际的中断频率，分频后可能和 TIMER_HZ 略有差别
+static FREQUENCY: AtomicU64 = AtomicU64::new(TIMER_HZ as u64);
+// HPET 以及启用它时的 uptime，保证切换时钟源后时间不会倒退
+static HPET: Once<(Hpet, Duration)> = Once::new();
+
+struct Periodic {
+    period: u64,
+    next: u64,
+    callback: fn(),
+}
+
+lazy_static! {
+    static ref PERIODIC: Mutex<Vec<Periodic>> = Mutex::new(Vec::new());
+}
+
+/// 设置 PIT 频率，需在开中断之前调用
+pub fn init() {
+    pit::claim_ports();
+    let hz = pit::set_frequency(TIMER_HZ);
+    FREQUENCY.store(hz as u64, Ordering::Relaxed);
+}
+
+/// 探测并启用 HPET，需在内存初始化之后调用
+pub fn init_hpet<M>(mapper: &mut M, frame_allocator: &mut impl FrameAllocator<Size4KiB>)
+where
+    M: Mapper<Size4KiB> + Mapper<Size2MiB>,
+{
+    if let Some(hpet) = hpet::init(mapper, frame_allocator) {
+        let offset = uptime();
+        HPET.call_once(|| (hpet, offset));
+    }
+}
+
+pub static HPET_UNIT: Unit = Unit {
+    name: "hpet",
+    depends: &[],
+    init: |context| {
+        let (mapper, frames) = context.paging()?;
+        init_hpet(mapper, frames);
+        Ok(())
+    },
+};
+
+/// 由时钟中断处理函数调用
+///
+/// 周期回调在中断上下文中执行，不能分配内存，也不能再注册回调
+pub fn tick() {
+    let now = TICKS.fetch_add(1, Ordering::Relaxed) + 1;
+    wheel::tick(now);
+    // 回调列表正被修改时跳过本次
+    if let Some(mut periodic) = PERIODIC.try_lock() {
+        for entry in periodic.iter_mut() {
+            if now >= entry.next {
+                entry.next = now + entry.period;
+                (entry.callback)();
+            }
+        }
+    }
+}
+
+/// 启动以来的时钟中断次数
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// 启动以来经过的时间，单调递增
+pub fn uptime() -> Duration {
+    match HPET.get() {
+        Some((hpet, offset)) => *offset + Duration::from_nanos(hpet.nanos()),
+        None => {
+            let nanos = ticks() as u128 * NANOS_PER_SEC as u128 / FREQUENCY.load(Ordering::Relaxed) as u128;
+            Duration::from_nanos(nanos as u64)
+        }
+    }
+}
+
+/// 睡眠一段时间，期间 CPU 停在 hlt 上等待中断
+///
+/// 依赖时钟中断唤醒，必须在开中断的状态下调用
+pub fn sleep(duration: Duration) {
+    let deadline = uptime() + duration;
+    while uptime() < deadline {
+        crate::debug::watchdog::touch();
+        x86_64::instructions::hlt();
+    }
+}
+
+/// 注册周期回调，每隔 `period` 在时钟中断中调用一次 `callback`
+pub fn register_periodic(period: Duration, callback: fn()) {
+    let period = duration_to_ticks(period).max(1);
+    interrupts::without_interrupts(|| {
+        PERIODIC.lock().push(Periodic {
+            period,
+            next: ticks() + period,
+            callback,
+        });
+    });
+}
+
+// 按 PIT 实际频率把时长换成 tick 数，向下取整
+fn duration_to_ticks(duration: Duration) -> u64 {
+    let frequency = FREQUENCY.load(Ordering::Relaxed) as u128;
+    (duration.as_nanos() * frequency / NANOS_PER_SEC as u128) as u64
+}
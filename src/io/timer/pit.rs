@@ -0,0 +1,74 @@
+// 可编程间隔定时器(PIT, Intel 8253/8254)
+// 通道 0 接在 IRQ0 上，用来产生周期性的时钟中断；通道 2 的输出接 PC 喇叭，见 drivers::pcspeaker，
+// 也用来做一次性的倒计时，给 TSC 校准频率
+
+use core::hint::spin_loop;
+
+use crate::io::port::{self, Port};
+
+// PIT 的输入时钟频率
+pub const BASE_FREQUENCY: u32 = 1_193_182;
+
+const CHANNEL0: Port<u8> = Port::new(0x40);
+const CHANNEL2: Port<u8> = Port::new(0x42);
+const COMMAND: Port<u8> = Port::new(0x43);
+
+// 通道 0，先写低字节再写高字节，模式 3（方波发生器），二进制计数
+const CHANNEL0_SQUARE_WAVE: u8 = 0x36;
+// 通道 2，其余同上
+const CHANNEL2_SQUARE_WAVE: u8 = 0xB6;
+// 通道 2，模式 0（数到 0 时输出变高）
+const CHANNEL2_ONE_SHOT: u8 = 0xB0;
+// 端口 0x61：通道 2 的门控、喇叭的数据位和通道 2 的输出
+// drivers::pcspeaker 也用它，归在 PIT 名下
+pub const PORT_B: Port<u8> = Port::new(0x61);
+const PORT_B_GATE: u8 = 1 << 0;
+const PORT_B_SPEAKER: u8 = 1 << 1;
+const PORT_B_OUT2: u8 = 1 << 5;
+
+/// 登记 PIT 用到的端口
+pub fn claim_ports() {
+    port::claim_or_log("pit", CHANNEL0.number(), 4);
+    port::claim_or_log("pit", PORT_B.number(), 1);
+}
+
+/// 设置通道 0 的中断频率，返回实际得到的频率
+pub fn set_frequency(hz: u32) -> u32 {
+    let divisor = (BASE_FREQUENCY / hz).clamp(1, u16::MAX as u32) as u16;
+    unsafe {
+        COMMAND.write(CHANNEL0_SQUARE_WAVE);
+        CHANNEL0.write(divisor as u8);
+        CHANNEL0.write((divisor >> 8) as u8);
+    }
+    BASE_FREQUENCY / divisor as u32
+}
+
+/// 让通道 2 输出频率为 `hz` 的方波，返回实际得到的频率
+pub fn set_channel2_frequency(hz: u32) -> u32 {
+    let divisor = (BASE_FREQUENCY / hz.max(1)).clamp(1, u16::MAX as u32) as u16;
+    unsafe {
+        COMMAND.write(CHANNEL2_SQUARE_WAVE);
+        CHANNEL2.write(divisor as u8);
+        CHANNEL2.write((divisor >> 8) as u8);
+    }
+    BASE_FREQUENCY / divisor as u32
+}
+
+/// 让通道 2 倒数 `count` 个输入时钟周期，数完后返回
+///
+/// 期间关掉 PC 喇叭，结束后恢复端口 0x61 原来的设置
+pub fn channel2_countdown(count: u16) {
+    unsafe {
+        let saved = PORT_B.read();
+        // 门控从低到高时开始计数
+        PORT_B.write(saved & !(PORT_B_GATE | PORT_B_SPEAKER));
+        COMMAND.write(CHANNEL2_ONE_SHOT);
+        CHANNEL2.write(count as u8);
+        CHANNEL2.write((count >> 8) as u8);
+        PORT_B.write((saved & !PORT_B_SPEAKER) | PORT_B_GATE);
+        while PORT_B.read() & PORT_B_OUT2 == 0 {
+            spin_loop();
+        }
+        PORT_B.write(saved);
+    }
+}
@@ -0,0 +1,256 @@
+// 分层时间轮
+// 大量带期限的超时（动画、重传、光标闪烁）不适合每个都在主循环里比较 uptime，也不适合排成有序表。
+// 时间轮有 4 层，每层 64 格，单位是一个时钟 tick：第 0 层每格 1 tick，第 1 层每格 64 tick，依此类推，最远约 4.6 小时，
+// 更远的先放在最高层，转到时再往下放。每个 tick 只看第 0 层的一格，第 0 层转完一圈才把上一层的一格拆到下面，
+// 所以登记、取消和每个 tick 的处理都是 O(1)（拆格子的开销分摊到每个 tick）。
+// 超时项放在固定大小的表里，用下标串成双向链表，时钟中断里不分配内存；表满时登记失败。
+// 到期的回调在时钟中断里执行，规矩和周期回调一样：不能分配内存，可以再登记或者取消超时
+
+use core::time::Duration;
+
+use crate::sync::{IrqSafeMutex, WaitQueue};
+
+const LEVELS: usize = 4;
+const SLOT_BITS: u32 = 6;
+const SLOTS: usize = 1 << SLOT_BITS;
+const SLOT_MASK: u64 = SLOTS as u64 - 1;
+// 最高层能放下的最远期限
+const MAX_DELTA: u64 = (1 << (SLOT_BITS * LEVELS as u32)) - 1;
+/// 同时登记的超时数上限
+pub const CAPACITY: usize = 512;
+
+// 链表：每层每格一条，另外是已经到期等着执行的和空闲的
+const EXPIRED: usize = LEVELS * SLOTS;
+const FREE: usize = EXPIRED + 1;
+const LISTS: usize = FREE + 1;
+const NIL: u16 = u16::MAX;
+
+/// 到期时做的事
+#[derive(Clone, Copy)]
+pub enum Action {
+    /// 调用函数，带一个参数
+    Call(fn(usize), usize),
+    /// 唤醒等待队列上的所有 CPU
+    Wake(&'static WaitQueue),
+}
+
+impl Action {
+    fn run(self) {
+        match self {
+            Action::Call(callback, arg) => callback(arg),
+            Action::Wake(queue) => queue.wake_all(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerError {
+    /// 登记的超时太多
+    Full,
+}
+
+/// 用来取消超时；超时执行或者取消之后再用它取消什么也不做
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeoutHandle {
+    index: u16,
+    generation: u32,
+}
+
+#[derive(Clone, Copy)]
+struct Entry {
+    // 到期的 tick
+    deadline: u64,
+    action: Option<Action>,
+    // 每次重用加一，旧的 TimeoutHandle 就对不上了
+    generation: u32,
+    list: u16,
+    prev: u16,
+    next: u16,
+}
+
+pub struct Wheel {
+    // 已经处理到的 tick
+    now: u64,
+    entries: [Entry; CAPACITY],
+    heads: [u16; LISTS],
+    len: usize,
+}
+
+impl Default for Wheel {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl Wheel {
+    /// 从第 `now` 个 tick 开始转的空时间轮
+    pub const fn new(now: u64) -> Self {
+        let empty = Entry { deadline: 0, action: None, generation: 0, list: FREE as u16, prev: NIL, next: NIL };
+        let mut entries = [empty; CAPACITY];
+        let mut i = 0;
+        while i < CAPACITY {
+            entries[i].prev = if i == 0 { NIL } else { i as u16 - 1 };
+            entries[i].next = if i + 1 == CAPACITY { NIL } else { i as u16 + 1 };
+            i += 1;
+        }
+        let mut heads = [NIL; LISTS];
+        heads[FREE] = 0;
+        Wheel { now, entries, heads, len: 0 }
+    }
+
+    /// 已经处理到的 tick
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    /// 登记了还没有执行的超时数
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 在第 `deadline` 个 tick 执行 `action`，已经过了的期限在下一个 tick 执行
+    pub fn insert(&mut self, deadline: u64, action: Action) -> Result<TimeoutHandle, TimerError> {
+        let index = self.heads[FREE];
+        if index == NIL {
+            return Err(TimerError::Full);
+        }
+        self.unlink(index);
+        let entry = &mut self.entries[index as usize];
+        entry.deadline = deadline.max(self.now + 1);
+        entry.action = Some(action);
+        entry.generation = entry.generation.wrapping_add(1);
+        let handle = TimeoutHandle { index, generation: entry.generation };
+        self.place(index);
+        self.len += 1;
+        Ok(handle)
+    }
+
+    /// 取消还没有执行的超时，返回是否找到
+    pub fn cancel(&mut self, handle: TimeoutHandle) -> bool {
+        let Some(entry) = self.entries.get(handle.index as usize) else { return false };
+        if entry.generation != handle.generation || entry.action.is_none() {
+            return false;
+        }
+        self.release(handle.index);
+        true
+    }
+
+    /// 转到第 `now` 个 tick，到期的超时移进待执行的链表，用 pop_expired 取出
+    pub fn advance(&mut self, now: u64) {
+        while self.now < now {
+            self.now += 1;
+            // 低一层转完一圈时把上一层的一格拆到下面
+            for level in 1..LEVELS {
+                let shift = SLOT_BITS * level as u32;
+                if self.now & ((1 << shift) - 1) != 0 {
+                    break;
+                }
+                let slot = ((self.now >> shift) & SLOT_MASK) as usize;
+                self.cascade(level * SLOTS + slot);
+            }
+            let list = (self.now & SLOT_MASK) as usize;
+            while let Some(index) = self.first(list) {
+                self.unlink(index);
+                self.link(index, EXPIRED);
+            }
+        }
+    }
+
+    /// 取出一个到期的超时
+    pub fn pop_expired(&mut self) -> Option<Action> {
+        let index = self.first(EXPIRED)?;
+        let action = self.entries[index as usize].action;
+        self.release(index);
+        action
+    }
+
+    // 按期限和现在的距离放进某一层的某一格
+    fn place(&mut self, index: u16) {
+        let deadline = self.entries[index as usize].deadline;
+        let delta = (deadline - self.now).min(MAX_DELTA);
+        let target = self.now + delta;
+        let level = (0..LEVELS).find(|&level| delta < 1 << (SLOT_BITS * (level as u32 + 1))).unwrap_or(LEVELS - 1);
+        let slot = ((target >> (SLOT_BITS * level as u32)) & SLOT_MASK) as usize;
+        self.link(index, level * SLOTS + slot);
+    }
+
+    fn cascade(&mut self, list: usize) {
+        while let Some(index) = self.first(list) {
+            self.unlink(index);
+            self.place(index);
+        }
+    }
+
+    fn first(&self, list: usize) -> Option<u16> {
+        Some(self.heads[list]).filter(|&index| index != NIL)
+    }
+
+    // 放回空闲链表
+    fn release(&mut self, index: u16) {
+        self.unlink(index);
+        self.entries[index as usize].action = None;
+        self.link(index, FREE);
+        self.len -= 1;
+    }
+
+    // 放到链表开头
+    fn link(&mut self, index: u16, list: usize) {
+        let head = self.heads[list];
+        let entry = &mut self.entries[index as usize];
+        entry.list = list as u16;
+        entry.prev = NIL;
+        entry.next = head;
+        if head != NIL {
+            self.entries[head as usize].prev = index;
+        }
+        self.heads[list] = index;
+    }
+
+    fn unlink(&mut self, index: u16) {
+        let Entry { list, prev, next, .. } = self.entries[index as usize];
+        if prev == NIL {
+            self.heads[list as usize] = next;
+        } else {
+            self.entries[prev as usize].next = next;
+        }
+        if next != NIL {
+            self.entries[next as usize].prev = prev;
+        }
+    }
+}
+
+static WHEEL: IrqSafeMutex<Wheel> = IrqSafeMutex::named("timer wheel", Wheel::new(0));
+
+/// 过 `duration` 之后在时钟中断里执行 `action`
+pub fn register_timeout(duration: Duration, action: Action) -> Result<TimeoutHandle, TimerError> {
+    let ticks = super::duration_to_ticks(duration).max(1);
+    let mut wheel = WHEEL.lock();
+    let deadline = wheel.now() + ticks;
+    wheel.insert(deadline, action)
+}
+
+/// 取消还没有执行的超时，返回是否找到
+pub fn cancel_timeout(handle: TimeoutHandle) -> bool {
+    WHEEL.lock().cancel(handle)
+}
+
+/// 登记了还没有执行的超时数
+pub fn pending() -> usize {
+    WHEEL.lock().len()
+}
+
+// 由 timer::tick 调用。锁被别的 CPU 占着时跳过，下一个 tick 一起补上；
+// 执行回调时不拿着锁，回调里可以再登记或者取消
+pub(super) fn tick(now: u64) {
+    match WHEEL.try_lock() {
+        Some(mut wheel) => wheel.advance(now),
+        None => return,
+    }
+    while let Some(action) = WHEEL.lock().pop_expired() {
+        action.run();
+    }
+}
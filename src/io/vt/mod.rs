@@ -0,0 +1,89 @@
+// 虚拟控制台
+// tty1 到 tty4 共用屏幕，各自有自己的光标、颜色和滚动历史，Alt+F1..F4 切换。只有前台的控制台画在屏幕上，
+// 其余的在后台照常接收输出，切换过去时再显示出来：文本模式下每个控制台有自己的一块字符缓冲区，切换时和显存交换内容；
+// 图形模式下后台的控制台只记下每一行的字符，切换时按字符重画。
+// shell 和 print!/println! 写到 tty1，屏幕上的内核日志写到 tty2。键盘输入不管在哪个控制台都交给 shell。
+// 切换由键盘中断记下，主循环空闲时执行，shell 正在执行命令时会晚一点切换。终端窗口打开时显示前台控制台的输出
+
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::io::VIDEO_MODE;
+
+/// 虚拟控制台的个数
+pub const COUNT: usize = 4;
+
+// 没有等着执行的切换
+const NO_SWITCH: usize = usize::MAX;
+
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+static PENDING: AtomicUsize = AtomicUsize::new(NO_SWITCH);
+
+/// 虚拟控制台，显示为 tty1 到 tty4
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tty(usize);
+
+impl Tty {
+    /// shell 和 print!/println! 的输出
+    pub const SHELL: Tty = Tty(0);
+    /// 屏幕上的内核日志
+    pub const LOG: Tty = Tty(1);
+
+    /// 第 `index` 个控制台（从 0 开始），超出范围时返回 None
+    pub fn new(index: usize) -> Option<Tty> {
+        (index < COUNT).then_some(Tty(index))
+    }
+
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+impl fmt::Display for Tty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "tty{}", self.0 + 1)
+    }
+}
+
+/// 前台的控制台
+pub fn active() -> Tty {
+    Tty(ACTIVE.load(Ordering::Relaxed))
+}
+
+/// 写到控制台，在后台时只记下来，切换过去时才显示
+pub fn write(tty: Tty, args: fmt::Arguments) {
+    use core::fmt::Write;
+
+    if VIDEO_MODE.lock().is_text() {
+        if !crate::vga_buffer::with_background(tty, |writer| writer.write_fmt(args).unwrap()) {
+            crate::vga_buffer::_print(args);
+        }
+    } else if !crate::graphic::text::with_background(tty, |writer| writer.write_fmt(args).unwrap()) {
+        crate::graphic::_print(args);
+    }
+}
+
+/// 请求切换到 `tty`，可以在中断中调用
+pub fn request_switch(tty: Tty) {
+    PENDING.store(tty.0, Ordering::Relaxed);
+}
+
+/// 执行等着的切换
+///
+/// 由 shell 在主循环空闲时调用
+pub fn poll() {
+    let pending = PENDING.swap(NO_SWITCH, Ordering::Relaxed);
+    if let Some(tty) = Tty::new(pending) {
+        switch(tty);
+    }
+}
+
+/// 马上切换到 `tty`
+pub fn switch(tty: Tty) {
+    if VIDEO_MODE.lock().is_text() {
+        crate::vga_buffer::switch(tty);
+    } else {
+        crate::graphic::text::switch(tty);
+    }
+    ACTIVE.store(tty.0, Ordering::Relaxed);
+}
@@ -5,6 +5,10 @@
 #![feature(abi_x86_interrupt)]
 #![feature(asm_const)]
 #![feature(const_mut_refs)]
+// 自定义测试框架：no_std 下没有标准的测试运行器，#[test_case] 收集到的测试交给 test_runner 在 QEMU 里运行
+#![feature(custom_test_frameworks)]
+#![test_runner(crate::test_runner)]
+#![reexport_test_harness_main = "test_main"]
 
 // 告知编译器应有相应模块存在，并指示它去特定位置寻找这些模块定义
 // - `interrupts`: 处理CPU中断和异常。
@@ -16,25 +20,86 @@
 
 extern crate alloc;
 
+use core::panic::PanicInfo;
+
+use bootloader::BootInfo;
+#[cfg(test)]
+use bootloader::entry_point;
+use x86_64::structures::paging::OffsetPageTable;
+use x86_64::VirtAddr;
+
+use crate::io::qemu::{exit_qemu, QemuExitCode};
+use crate::memory::BootInfoFrameAllocator;
+
 pub mod interrupts;
 pub mod vga_buffer;
 pub mod gdt;
 pub mod memory;
 pub mod allocator;
+pub mod apps;
+pub mod assets;
+#[cfg(feature = "bench")]
+pub mod bench;
+pub mod boot;
+pub mod config;
+pub mod cpu;
 pub mod graphic;
 pub mod gui;
+pub mod i18n;
 pub mod io;
+pub mod loader;
+pub mod net;
+pub mod perf;
+pub mod rand;
+pub mod debug;
+pub mod drivers;
+pub mod fs;
+pub mod logger;
+pub mod shell;
+pub mod smp;
+pub mod sound;
+pub mod sync;
+pub mod syscall;
+pub mod trace;
+pub mod usermode;
+pub mod version;
 
 pub fn init() {
+    // 最先读启动参数，日志级别、堆大小和分辨率都可以在里面设置
+    config::init();
+    // 接着安装日志，之后的初始化过程都可以输出日志
+    logger::init(config::log_level().unwrap_or(log::LevelFilter::Debug));
+    config::log_options();
+    // 界面语言也在启动参数里，之后打印的提示按它翻译
+    i18n::init();
+    // 检测 CPU 功能并输出摘要，打开 SSE 这类需要操作系统设置的功能
+    cpu::init();
+
     // 加载GDT
     // 初始化全局描述符表(GDT)。GDT是保护模式下x86 CPU使用来区分不同内存区域特性（如基址、大小和访问权限等）的数据结构
     gdt::init();
 
+    // 有 COM2 时启用 GDB stub，要在加载 IDT 之前，IDT 据此决定断点异常交给谁
+    debug::gdb::init();
+    // 有 COM3 时打开给测试脚本用的控制通道
+    debug::control::init();
+
     // 加载中断和异常处理
     // 初始化IDT（中断描述符表），此数据结构用来告诉CPU各种异常和中断应该由哪些处理函数来处理
     interrupts::init_idt();
     // 初始化可编程中断控制器(PIC)，配置它以接收硬件中断。因为PIC相关操作可能会引起未定义行为，所以需要放在unsafe块内执行。
     unsafe {interrupts::pics::PICS.lock().initialize()};
+    // 登记 PCI 配置空间的端口，之后探测到的设备按 BAR 登记自己的端口
+    io::pci::init();
+    // 设置 PIT 的时钟中断频率
+    io::timer::init();
+    // 初始化串口并打开接收中断
+    io::qemu::init(io::qemu::DEFAULT_BAUD_RATE);
+    // 登记键盘，打开 PS/2 鼠标
+    io::keyboard::init();
+    io::mouse::init();
+    // 挂上时钟、键盘、串口、鼠标和 RTC 的中断处理函数；RTC 只在设了闹钟时产生中断
+    interrupts::init();
     // 开启CPU中断，使得CPU能够响应外部设备发起的IRQ和其他形式的硬件请求
     x86_64::instructions::interrupts::enable();
 
@@ -49,6 +114,67 @@ pub fn hlt_loop() -> !{
     }
 }
 
+// 测试
+// cargo test 用 bootimage 把每个测试程序（库本身、main.rs 和 tests/ 下的每个文件）做成启动镜像，
+// 在带 isa-debug-exit 设备的 QEMU 里运行。测试名和结果写到串口，全部通过后以 Success 退出 QEMU，
+// 有测试 panic 时以 Failed 退出
+
+/// 可以被 test_runner 运行的测试，运行前后输出测试名和结果
+pub trait Testable {
+    fn run(&self);
+}
+
+impl<T: Fn()> Testable for T {
+    fn run(&self) {
+        serial_print!("{}...\t", core::any::type_name::<T>());
+        self();
+        serial_println!("[ok]");
+    }
+}
+
+pub fn test_runner(tests: &[&dyn Testable]) {
+    serial_println!("Running {} tests", tests.len());
+    for test in tests {
+        test.run();
+    }
+    exit_qemu(QemuExitCode::Success);
+}
+
+/// 测试程序的 panic 处理函数：报告失败并退出 QEMU
+pub fn test_panic_handler(info: &PanicInfo) -> ! {
+    serial_println!("[failed]\n");
+    serial_println!("Error: {}\n", info);
+    exit_qemu(QemuExitCode::Failed);
+    hlt_loop();
+}
+
+/// 测试内核共用的初始化：init 之后建立页表、帧分配器和堆，页表和帧分配器交给还要自己映射内存的测试
+pub fn test_kernel_init(boot_info: &'static BootInfo) -> (OffsetPageTable<'static>, BootInfoFrameAllocator) {
+    init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("Heap initialization failed");
+    (mapper, frame_allocator)
+}
+
+#[cfg(test)]
+entry_point!(test_kernel_main);
+
+// cargo test --lib 的入口
+#[cfg(test)]
+fn test_kernel_main(boot_info: &'static BootInfo) -> ! {
+    test_kernel_init(boot_info);
+    test_main();
+    hlt_loop();
+}
+
+#[cfg(test)]
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    test_panic_handler(info)
+}
+
 // 1. #![no_std]是工程里每个rs都要使用吗？为什么有的rs没使用？有的rs比如这个lib.rs又使用
 // 属性 `#![no_std]` 通常只在 crate 根（如库的根文件 lib.rs 或二进制项目的 main.rs）中设置一次。这是因为 `#![no_std]` 是一个属性(attribute)，它应用于整个 crate 的配置，而不仅仅是单个模块。
 // 当你在 crate 的根文件中声明 `#![no_std]`，你告诉编译器当前这个 crate 不链接到 Rust 的标准库（std），而是使用核心库（core），后者是适用于裸机或嵌入式系统的功能子集，没有操作系统特性依赖。
@@ -0,0 +1,288 @@
+// ELF64 可执行文件加载
+// 只支持 x86_64 上小端、静态链接的 ET_EXEC 文件：不做重定位，也没有动态链接器。
+// 每个 PT_LOAD 段按它的虚拟地址映射到新的地址空间里，页属性按段的读、写、执行标志设置，
+// 文件里没有的部分（.bss）保持清零。用户栈放在低半部分的顶端，栈顶按 System V 的约定
+// 放好空的 argc、argv、envp 和辅助向量。
+// 装好的段留在缓存里，再次运行同一个文件时新的地址空间直接共用这些页，写的时候才复制（见 memory::cow）
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec::Vec;
+use core::cmp::{max, min};
+use core::ptr::write_bytes;
+
+use spin::Mutex;
+use x86_64::structures::paging::{FrameAllocator, FrameDeallocator, Page, PageTableFlags, PhysFrame, Size4KiB};
+use x86_64::VirtAddr;
+
+use crate::memory::address_space::{AddressSpace, MapError};
+use crate::memory::{cow, phys_to_virt, wx};
+
+const PAGE_SIZE: u64 = 4096;
+// 用户地址空间是低半部分，第一页不映射，空指针总会出错
+const USER_START: u64 = PAGE_SIZE;
+const USER_END: u64 = 0x0000_8000_0000_0000;
+// 栈顶上面留一页不映射
+pub const STACK_TOP: u64 = USER_END - PAGE_SIZE;
+pub const STACK_SIZE: u64 = 0x4_0000;
+// argc、argv 和 envp 的结尾、辅助向量的 AT_NULL，凑成 16 字节对齐
+const INITIAL_STACK: u64 = 6 * 8;
+
+const MAGIC: &[u8; 4] = b"\x7fELF";
+const CLASS_64: u8 = 2;
+const LITTLE_ENDIAN: u8 = 1;
+const TYPE_EXEC: u16 = 2;
+const MACHINE_X86_64: u16 = 0x3E;
+const HEADER_SIZE: usize = 64;
+const PROGRAM_HEADER_SIZE: usize = 56;
+const PT_LOAD: u32 = 1;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+// 最多缓存的程序数，多了之后去掉最久没有用过的
+const MAX_CACHED: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadError {
+    /// 不是 ELF 文件，或者文件被截断了
+    Malformed,
+    /// 不是 x86_64 上的 64 位静态可执行文件
+    Unsupported,
+    /// 段不在用户地址空间里，或者和内核的映射重叠
+    BadAddress,
+    OutOfMemory,
+}
+
+impl From<MapError> for LoadError {
+    fn from(error: MapError) -> Self {
+        match error {
+            MapError::FrameAllocationFailed => LoadError::OutOfMemory,
+            MapError::AlreadyMapped => LoadError::BadAddress,
+        }
+    }
+}
+
+/// 装好、可以运行的程序
+pub struct Image {
+    pub space: AddressSpace,
+    pub entry: VirtAddr,
+    /// 程序开始运行时的栈指针
+    pub stack: VirtAddr,
+}
+
+struct Segment {
+    flags: u32,
+    offset: u64,
+    vaddr: u64,
+    filesz: u64,
+    memsz: u64,
+}
+
+// 装好的段：每一页的帧和页属性，缓存本身也算这些帧的一个使用者
+struct Template {
+    file: Vec<u8>,
+    entry: u64,
+    pages: BTreeMap<Page<Size4KiB>, (PhysFrame, PageTableFlags)>,
+}
+
+impl Template {
+    fn release(self, frames: &mut impl FrameDeallocator<Size4KiB>) {
+        for (frame, _) in self.pages.into_values() {
+            if cow::release(frame) {
+                unsafe { frames.deallocate_frame(frame) };
+            }
+        }
+    }
+}
+
+// 最近用过的在后面
+static CACHE: Mutex<VecDeque<Template>> = Mutex::new(VecDeque::new());
+
+/// 解析 `file`，建好它的地址空间；失败时已经分配的帧都还回去
+pub fn load<F>(file: &[u8], frames: &mut F) -> Result<Image, LoadError>
+where
+    F: FrameAllocator<Size4KiB> + FrameDeallocator<Size4KiB>,
+{
+    let mut cache = CACHE.lock();
+    let template = match cache.iter().position(|template| template.file == file) {
+        Some(index) => cache.remove(index).unwrap(),
+        None => {
+            let template = build(file, frames)?;
+            if cache.len() == MAX_CACHED {
+                cache.pop_front().unwrap().release(frames);
+            }
+            template
+        }
+    };
+    cache.push_back(template);
+    let template = cache.back().unwrap();
+
+    let mut space = AddressSpace::new(frames)?;
+    match populate(&mut space, template, frames) {
+        Ok(stack) => Ok(Image { space, entry: VirtAddr::new(template.entry), stack }),
+        Err(error) => {
+            space.destroy(frames);
+            Err(error)
+        }
+    }
+}
+
+/// 缓存着的程序数
+pub fn cached() -> usize {
+    CACHE.lock().len()
+}
+
+// 共用映像里的页，再映射私有的栈，返回初始的栈指针
+fn populate<F>(space: &mut AddressSpace, template: &Template, frames: &mut F) -> Result<VirtAddr, LoadError>
+where
+    F: FrameAllocator<Size4KiB> + FrameDeallocator<Size4KiB>,
+{
+    for (&page, &(frame, flags)) in &template.pages {
+        space.map_shared(page, frame, flags, frames)?;
+    }
+    let mut flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+    if wx::enabled() {
+        flags |= PageTableFlags::NO_EXECUTE;
+    }
+    let bottom = Page::containing_address(VirtAddr::new(STACK_TOP - STACK_SIZE));
+    let top = Page::containing_address(VirtAddr::new(STACK_TOP - 1));
+    for page in Page::range_inclusive(bottom, top) {
+        space.map(page, flags, frames)?;
+    }
+    // 新映射的页是清零的，初始的栈内容不用再写
+    Ok(VirtAddr::new(STACK_TOP - INITIAL_STACK))
+}
+
+// 检查文件头，把所有的段装进新分配的帧
+fn build<F>(file: &[u8], frames: &mut F) -> Result<Template, LoadError>
+where
+    F: FrameAllocator<Size4KiB> + FrameDeallocator<Size4KiB>,
+{
+    let header = file.get(..HEADER_SIZE).ok_or(LoadError::Malformed)?;
+    if &header[..4] != MAGIC {
+        return Err(LoadError::Malformed);
+    }
+    if header[4] != CLASS_64 || header[5] != LITTLE_ENDIAN
+        || read_u16(header, 16) != TYPE_EXEC || read_u16(header, 18) != MACHINE_X86_64 {
+        return Err(LoadError::Unsupported);
+    }
+    let entry = read_u64(header, 24);
+    if !(USER_START..USER_END).contains(&entry) {
+        return Err(LoadError::BadAddress);
+    }
+
+    let mut template = Template { file: Vec::from(file), entry, pages: BTreeMap::new() };
+    match load_segments(&mut template, frames) {
+        Ok(()) => Ok(template),
+        Err(error) => {
+            template.release(frames);
+            Err(error)
+        }
+    }
+}
+
+fn load_segments(template: &mut Template, frames: &mut impl FrameAllocator<Size4KiB>) -> Result<(), LoadError> {
+    let file = &template.file[..];
+    let phoff = read_u64(file, 32) as usize;
+    let phentsize = read_u16(file, 54) as usize;
+    let phnum = read_u16(file, 56) as usize;
+    if phentsize < PROGRAM_HEADER_SIZE {
+        return Err(LoadError::Malformed);
+    }
+
+    let mut segments = Vec::new();
+    for i in 0..phnum {
+        let start = i.checked_mul(phentsize).and_then(|offset| offset.checked_add(phoff)).ok_or(LoadError::Malformed)?;
+        let header = file.get(start..).and_then(|rest| rest.get(..PROGRAM_HEADER_SIZE)).ok_or(LoadError::Malformed)?;
+        if read_u32(header, 0) != PT_LOAD {
+            continue;
+        }
+        segments.push(Segment {
+            flags: read_u32(header, 4),
+            offset: read_u64(header, 8),
+            vaddr: read_u64(header, 16),
+            filesz: read_u64(header, 32),
+            memsz: read_u64(header, 40),
+        });
+    }
+    if segments.is_empty() {
+        return Err(LoadError::Malformed);
+    }
+    let nx = wx::enabled();
+    for segment in &segments {
+        load_segment(file, segment, nx, &mut template.pages, frames)?;
+    }
+    Ok(())
+}
+
+fn load_segment(
+    file: &[u8],
+    segment: &Segment,
+    nx: bool,
+    pages: &mut BTreeMap<Page<Size4KiB>, (PhysFrame, PageTableFlags)>,
+    frames: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), LoadError> {
+    if segment.memsz == 0 {
+        return Ok(());
+    }
+    if segment.filesz > segment.memsz {
+        return Err(LoadError::Malformed);
+    }
+    let data = usize::try_from(segment.offset).ok()
+        .and_then(|offset| file.get(offset..offset.checked_add(segment.filesz as usize)?))
+        .ok_or(LoadError::Malformed)?;
+    let start = segment.vaddr;
+    let end = start.checked_add(segment.memsz).ok_or(LoadError::BadAddress)?;
+    if start < USER_START || end > STACK_TOP - STACK_SIZE {
+        return Err(LoadError::BadAddress);
+    }
+
+    let mut flags = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+    if segment.flags & PF_W != 0 {
+        flags |= PageTableFlags::WRITABLE;
+    }
+    if segment.flags & PF_X == 0 && nx {
+        flags |= PageTableFlags::NO_EXECUTE;
+    }
+    let first = Page::<Size4KiB>::containing_address(VirtAddr::new(start));
+    let last = Page::containing_address(VirtAddr::new(end - 1));
+    for page in Page::range_inclusive(first, last) {
+        let frame = match pages.get_mut(&page) {
+            // 两个段落在同一页里，两边有一边可执行就可执行
+            Some((frame, existing)) => {
+                let mut merged = *existing | flags;
+                if !(*existing & flags).contains(PageTableFlags::NO_EXECUTE) {
+                    merged.remove(PageTableFlags::NO_EXECUTE);
+                }
+                *existing = merged;
+                *frame
+            }
+            None => {
+                let frame = frames.allocate_frame().ok_or(LoadError::OutOfMemory)?;
+                unsafe { write_bytes(phys_to_virt(frame.start_address()).as_mut_ptr::<u8>(), 0, PAGE_SIZE as usize) };
+                pages.insert(page, (frame, flags));
+                frame
+            }
+        };
+        // 这一页里来自文件的部分，其余保持清零
+        let page_start = page.start_address().as_u64();
+        let copy_start = max(page_start, start);
+        let copy_end = min(page_start + PAGE_SIZE, start + segment.filesz);
+        if copy_start < copy_end {
+            let source = &data[(copy_start - start) as usize..(copy_end - start) as usize];
+            let bytes = AddressSpace::page_bytes(frame);
+            bytes[(copy_start - page_start) as usize..(copy_end - page_start) as usize].copy_from_slice(source);
+        }
+    }
+    Ok(())
+}
+
+fn read_u16(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
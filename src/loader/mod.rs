@@ -0,0 +1,4 @@
+// 程序加载
+// 把可执行文件装进一个新的地址空间，再交给 usermode 运行
+
+pub mod elf;
@@ -0,0 +1,174 @@
+// 内核日志
+// 实现 `log` crate 的门面(facade)，各模块直接使用 log::info! 等宏：
+// - 级别：trace..error，可按模块路径前缀单独设置
+// - 时间戳：来自 io::timer 的启动时间
+// - 输出端(sink)：串口、VGA 文本模式、图形控制台，可以再注册自定义的输出端；上屏的日志写到虚拟控制台 tty2
+// - 最近的日志同时留在内存里的环形缓冲区（ring），用 dmesg 查看
+
+use core::fmt;
+
+use log::{LevelFilter, Log, Metadata, Record};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::io::timer::uptime;
+use crate::io::vt::{self, Tty};
+use crate::io::VIDEO_MODE;
+
+pub mod ring;
+
+const MAX_FILTERS: usize = 16;
+const MAX_SINKS: usize = 8;
+
+/// 日志输出端
+pub trait LogSink: Sync {
+    fn write(&self, args: fmt::Arguments);
+}
+
+/// 串口，QEMU 的 `-serial stdio`
+pub struct SerialSink;
+
+/// VGA 文本模式，仅在文本模式下输出
+pub struct VgaTextSink;
+
+/// 图形控制台，仅在图形模式下输出
+pub struct GuiConsoleSink;
+
+impl LogSink for SerialSink {
+    fn write(&self, args: fmt::Arguments) {
+        crate::io::qemu::_qemu_print(args);
+    }
+}
+
+impl LogSink for VgaTextSink {
+    fn write(&self, args: fmt::Arguments) {
+        if VIDEO_MODE.lock().is_text() {
+            vt::write(Tty::LOG, args);
+        }
+    }
+}
+
+impl LogSink for GuiConsoleSink {
+    fn write(&self, args: fmt::Arguments) {
+        if !VIDEO_MODE.lock().is_text() {
+            vt::write(Tty::LOG, args);
+        }
+    }
+}
+
+pub static SERIAL_SINK: SerialSink = SerialSink;
+pub static VGA_TEXT_SINK: VgaTextSink = VgaTextSink;
+pub static GUI_CONSOLE_SINK: GuiConsoleSink = GuiConsoleSink;
+
+#[derive(Clone, Copy)]
+struct SinkEntry {
+    sink: &'static dyn LogSink,
+    level: LevelFilter,
+}
+
+struct LoggerState {
+    level: LevelFilter,
+    // 模块路径前缀和对应的级别，最长前缀优先
+    filters: [Option<(&'static str, LevelFilter)>; MAX_FILTERS],
+    sinks: [Option<SinkEntry>; MAX_SINKS],
+}
+
+impl LoggerState {
+    fn level_for(&self, target: &str) -> LevelFilter {
+        let mut best: Option<(&'static str, LevelFilter)> = None;
+        for (prefix, level) in self.filters.iter().flatten() {
+            if target.starts_with(prefix) && best.map_or(true, |(b, _)| prefix.len() > b.len()) {
+                best = Some((*prefix, *level));
+            }
+        }
+        best.map_or(self.level, |(_, level)| level)
+    }
+}
+
+static STATE: Mutex<LoggerState> = Mutex::new(LoggerState {
+    level: LevelFilter::Info,
+    filters: [None; MAX_FILTERS],
+    sinks: [None; MAX_SINKS],
+});
+
+struct KernelLogger;
+
+static LOGGER: KernelLogger = KernelLogger;
+
+impl Log for KernelLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        interrupts::without_interrupts(|| metadata.level() <= STATE.lock().level_for(metadata.target()))
+    }
+
+    fn log(&self, record: &Record) {
+        interrupts::without_interrupts(|| {
+            // 复制一份输出端再写，写的过程中不持有锁
+            let sinks = {
+                let state = STATE.lock();
+                if record.level() > state.level_for(record.target()) {
+                    return;
+                }
+                state.sinks
+            };
+            let time = uptime();
+            ring::push(record.level(), time, record.target(), *record.args());
+            for entry in sinks.iter().flatten() {
+                if record.level() <= entry.level {
+                    entry.sink.write(format_args!("[{:>5}.{:06}] {:<5} {}: {}\n",
+                                                  time.as_secs(), time.subsec_micros(),
+                                                  record.level(), record.target(), record.args()));
+                }
+            }
+        })
+    }
+
+    fn flush(&self) {}
+}
+
+/// 安装内核日志，串口输出所有级别，屏幕只输出 info 及以上
+pub fn init(level: LevelFilter) {
+    set_level(level);
+    register_sink(&SERIAL_SINK, LevelFilter::Trace);
+    register_sink(&VGA_TEXT_SINK, LevelFilter::Info);
+    register_sink(&GUI_CONSOLE_SINK, LevelFilter::Info);
+    // 重复初始化时 set_logger 会失败，忽略即可
+    let _ = log::set_logger(&LOGGER);
+    log::set_max_level(LevelFilter::Trace);
+}
+
+/// 设置默认级别
+pub fn set_level(level: LevelFilter) {
+    interrupts::without_interrupts(|| STATE.lock().level = level);
+}
+
+/// 为某个模块路径前缀（如 `cjn_os::graphic`）单独设置级别，表满时返回 false
+pub fn set_module_level(prefix: &'static str, level: LevelFilter) -> bool {
+    interrupts::without_interrupts(|| {
+        let mut state = STATE.lock();
+        if let Some(slot) = state.filters.iter_mut().find(|f| matches!(f, Some((p, _)) if *p == prefix)) {
+            *slot = Some((prefix, level));
+            return true;
+        }
+        match state.filters.iter_mut().find(|f| f.is_none()) {
+            Some(slot) => {
+                *slot = Some((prefix, level));
+                true
+            }
+            None => false,
+        }
+    })
+}
+
+/// 注册输出端，只输出不低于 `level` 的日志，表满时返回 false
+pub fn register_sink(sink: &'static dyn LogSink, level: LevelFilter) -> bool {
+    interrupts::without_interrupts(|| {
+        let mut state = STATE.lock();
+        match state.sinks.iter_mut().find(|s| s.is_none()) {
+            Some(slot) => {
+                *slot = Some(SinkEntry { sink, level });
+                true
+            }
+            None => false,
+        }
+    })
+}
@@ -0,0 +1,175 @@
+// 日志环形缓冲区
+// 最近的 CAPACITY 条日志（级别、时间、模块和消息）留在内存里，满了覆盖最早的一条。
+// 缓冲区放在静态区，从 logger::init 开始记录，不依赖堆，也不受显示模式切换的影响；
+// dmesg 命令和日志窗口从这里读取。模块名和消息按定长保存，太长的部分被截掉
+
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use core::time::Duration;
+
+use log::{Level, LevelFilter};
+use spin::{Mutex, MutexGuard};
+use x86_64::instructions::interrupts;
+
+/// 保留的日志条数
+pub const CAPACITY: usize = 512;
+const TARGET_LEN: usize = 48;
+const MESSAGE_LEN: usize = 160;
+
+// 定长的字符串，写不下的部分被截掉，只保留完整的字符
+#[derive(Clone, Copy)]
+struct Text<const N: usize> {
+    bytes: [u8; N],
+    len: usize,
+    truncated: bool,
+}
+
+impl<const N: usize> Text<N> {
+    const EMPTY: Self = Self { bytes: [0; N], len: 0, truncated: false };
+
+    fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or("")
+    }
+}
+
+impl<const N: usize> Write for Text<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut end = s.len().min(N - self.len);
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.bytes[self.len..self.len + end].copy_from_slice(&s.as_bytes()[..end]);
+        self.len += end;
+        self.truncated |= end < s.len();
+        Ok(())
+    }
+}
+
+/// 一条日志
+#[derive(Clone, Copy)]
+pub struct Entry {
+    /// 从启动开始的序号，被覆盖和清除的日志也算在内
+    pub sequence: u64,
+    pub level: Level,
+    /// 启动以来的时间
+    pub time: Duration,
+    target: Text<TARGET_LEN>,
+    message: Text<MESSAGE_LEN>,
+}
+
+impl Entry {
+    const EMPTY: Self = Self {
+        sequence: 0,
+        level: Level::Trace,
+        time: Duration::ZERO,
+        target: Text::EMPTY,
+        message: Text::EMPTY,
+    };
+
+    /// 模块路径
+    pub fn target(&self) -> &str {
+        self.target.as_str()
+    }
+
+    pub fn message(&self) -> &str {
+        self.message.as_str()
+    }
+
+    /// 消息太长，后面被截掉了
+    pub fn is_truncated(&self) -> bool {
+        self.message.truncated
+    }
+}
+
+// 和上屏的日志一样的格式，不带换行
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{:>5}.{:06}] {:<5} {}: {}{}", self.time.as_secs(), self.time.subsec_micros(),
+               self.level, self.target(), self.message(), if self.is_truncated() { "..." } else { "" })
+    }
+}
+
+struct Ring {
+    entries: [Entry; CAPACITY],
+    // 下一条日志的序号
+    next: u64,
+    // 序号小于它的日志已经被清除
+    cleared: u64,
+}
+
+impl Ring {
+    // 还留着的第一条日志的序号
+    fn first(&self) -> u64 {
+        self.next.saturating_sub(CAPACITY as u64).max(self.cleared)
+    }
+}
+
+static RING: Mutex<Ring> = Mutex::new(Ring { entries: [Entry::EMPTY; CAPACITY], next: 0, cleared: 0 });
+
+/// 记下一条日志，由 logger 和 watchdog 调用
+pub fn push(level: Level, time: Duration, target: &str, args: fmt::Arguments) {
+    let mut entry = Entry { level, time, ..Entry::EMPTY };
+    let _ = entry.target.write_str(target);
+    let _ = entry.message.write_fmt(args);
+    // 多行的消息只保留第一行
+    if let Some(end) = entry.message().find('\n') {
+        entry.message.len = end;
+        entry.message.truncated = true;
+    }
+    interrupts::without_interrupts(|| {
+        let mut ring = RING.lock();
+        entry.sequence = ring.next;
+        let index = (ring.next % CAPACITY as u64) as usize;
+        ring.entries[index] = entry;
+        ring.next += 1;
+    })
+}
+
+/// 序号不小于 `since`、级别不低于 `level` 的日志，从早到晚排列
+pub fn entries(since: u64, level: LevelFilter) -> Vec<Entry> {
+    interrupts::without_interrupts(|| {
+        let ring = RING.lock();
+        (ring.first().max(since)..ring.next)
+            .map(|sequence| ring.entries[(sequence % CAPACITY as u64) as usize])
+            .filter(|entry| entry.level <= level)
+            .collect()
+    })
+}
+
+/// 锁住的日志缓冲区，持有期间不会有新的日志写进来
+pub struct Snapshot {
+    ring: MutexGuard<'static, Ring>,
+}
+
+impl Snapshot {
+    /// 还留着的日志，从早到晚排列
+    pub fn iter(&self) -> impl Iterator<Item = &Entry> + '_ {
+        (self.ring.first()..self.ring.next).map(|sequence| &self.ring.entries[(sequence % CAPACITY as u64) as usize])
+    }
+}
+
+/// 不等锁也不分配内存地锁住缓冲区，锁正被占用时返回 None；崩溃转储用，调用时中断已经关了
+pub fn try_snapshot() -> Option<Snapshot> {
+    RING.try_lock().map(|ring| Snapshot { ring })
+}
+
+/// 下一条日志的序号，用来判断有没有新的日志
+pub fn next_sequence() -> u64 {
+    interrupts::without_interrupts(|| RING.lock().next)
+}
+
+/// 上次清除之后因为缓冲区满了被覆盖的日志条数
+pub fn overwritten() -> u64 {
+    interrupts::without_interrupts(|| {
+        let ring = RING.lock();
+        ring.first() - ring.cleared
+    })
+}
+
+/// 清除所有日志，序号继续增加
+pub fn clear() {
+    interrupts::without_interrupts(|| {
+        let mut ring = RING.lock();
+        ring.cleared = ring.next;
+    })
+}
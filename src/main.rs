@@ -2,6 +2,9 @@
 #![no_std] // 不链接Rust标准库
 #![no_main] // 禁用所有Rust层级的入口点
 #![feature(abi_x86_interrupt)]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
 
 extern crate alloc;
 
@@ -9,18 +12,26 @@ use core::panic::PanicInfo;
 use bootloader::{BootInfo, entry_point};
 use x86_64::VirtAddr;
 use cjn_os::{allocator, println};
-use cjn_os::graphic::enter_wide_mode;
+use cjn_os::boot::{self, Context, Unit};
 use cjn_os::gui::init_gui;
+use cjn_os::i18n::{self, Msg};
+use cjn_os::io::VIDEO_MODE;
 use cjn_os::vga_buffer;
-use cjn_os::io::qemu::qemu_print;
 
 entry_point!(kernel_main);
 
 // 将会在panic时调用
+#[cfg(not(test))]
 #[panic_handler]
 fn panic(_info: &PanicInfo) -> ! {
-    println!("{}", _info);
-    cjn_os::hlt_loop();
+    // 写串口，再显示 panic 画面后停机
+    cjn_os::debug::panic::handle(_info);
+}
+
+#[cfg(test)]
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
 }
 
 
@@ -29,39 +40,74 @@ fn panic(_info: &PanicInfo) -> ! {
 // pub extern "C" fn _start() -> ! {
 // 内核主程序
 fn kernel_main(boot_info: &'static BootInfo) -> ! {
-    println!("Loading Cjn's OS...\n");
+    start(boot_info)
+}
+
+// 和引导协议无关的启动过程，引导信息见 boot::info
+fn start(boot_info: &'static impl boot::info::BootInfo) -> ! {
+    // 读完启动参数才知道界面语言，所以放在 init 之后
     cjn_os::init();
+    println!("{}\n", i18n::tr(Msg::Loading));
+    // cargo test 时只运行测试，不进入图形界面
+    #[cfg(test)]
+    test_main();
+    boot::info::record(boot_info);
+    println!("{}", i18n::fill(Msg::BootTime, &[&cjn_os::drivers::rtc::init()]));
     vga_buffer::print_something();
 
     use cjn_os::memory::BootInfoFrameAllocator;
-    qemu_print("A\n");
+    log::debug!("Kernel initialized");
 
-    println!("\n\nWaiting for initializing the heap memory...\n");
+    println!("\n\n{}\n", i18n::tr(Msg::HeapInit));
     // 使用来自于`boot_info.physical_memory_offset`的值创建了一个新的 `VirtAddr`(虚拟内存地址)实例。这个偏移量被用于在物理和虚拟地址之间进行转换
-    let phys_mem_offset: VirtAddr = VirtAddr::new(boot_info.physical_memory_offset.clone());
+    let phys_mem_offset: VirtAddr = VirtAddr::new(boot_info.physical_memory_offset());
     // 调用一个不安全函数 `cjn_os::memory::init` 并传入 `phys_mem_offset`，进行物理内存到虚拟内存的映射初始化，将返回值赋予变量 `mapper`。
     // - **原因**: 初始化内存映射器，用于将一些虚拟地址映射到物理地址上，通常在操作系统启动时设置
     // let mut mapper = unsafe{cjn_os::memory::init(phys_mem_offset)};
     let mut mapper = unsafe { cjn_os::memory::init(phys_mem_offset) };
+    // 取消 IST 栈保护页的映射，栈溢出时能得到明确的诊断信息
+    cjn_os::memory::stacks::init_guard_pages(&mut mapper);
     // 调用另一个不安全函数 `BootInfoFrameAllocator::init`，传入 boot info（引导信息）中的内存图（memory map），并生成帧分配器实例。
     // - **原因**: 帧分配器负责管理物理内存帧，它可以提供新的帧供使用或回收不再需要的帧
     let mut frame_allocator = unsafe {
-        BootInfoFrameAllocator::init(&boot_info.memory_map)
+        BootInfoFrameAllocator::init(boot_info.memory_map())
     };
-    qemu_print("B\n");
+    log::debug!("Paging and frame allocator ready");
     // 调用 `allocator::init_heap` 方法传入前面初始化好的内存映射器和帧分配器来初始化堆空间，如失败则输出错误信息"Heap initialization failed"。
     // - **原因**: 在无操作系统环境中，手动初始化堆非常重要，以便后续动态分配资源
     allocator::init_heap(&mut mapper, &mut frame_allocator)
         .expect("Heap initialization failed");
-    qemu_print("C\n");
-
-    qemu_print("The OS is leaving VGA now...\n");
-
-    enter_wide_mode(&mut mapper, &mut frame_allocator);
-    init_gui();
-    println!("\n\n\t\t万里之行, 始于足下");
-    // 进入无限循环防止 `_start` 函数,返回也确保内核不会意外退出到未定义行为状态中去
-    cjn_os::hlt_loop();
+    log::debug!("Heap initialized");
+    // 其余的子系统和驱动按依赖顺序初始化，依赖写在各自的 UNIT 里；
+    // 帧分配器交给 memory 之前用启动时的帧分配器，网卡、磁盘、声卡和 USB 的驱动要在交出之后分配 DMA 内存
+    let units: [&Unit; 12] = [
+        &cjn_os::drivers::hotplug::UNIT,
+        &cjn_os::drivers::pci::UNIT,
+        &cjn_os::io::timer::HPET_UNIT,
+        &cjn_os::smp::UNIT,
+        &cjn_os::usermode::UNIT,
+        &cjn_os::graphic::UNIT,
+        &cjn_os::memory::UNIT,
+        &cjn_os::net::UNIT,
+        &cjn_os::drivers::block::UNIT,
+        &cjn_os::fs::UNIT,
+        &cjn_os::sound::UNIT,
+        &cjn_os::drivers::xhci::UNIT,
+    ];
+    boot::run(&units, &mut Context::new(mapper, frame_allocator));
+    // 没有可用的显示后端时 shell 留在 VGA 文本模式
+    if !VIDEO_MODE.lock().is_text() {
+        init_gui();
+        // 控制台输出和 shell 都放进终端窗口，边距按屏幕大小算，800x600 时是 40
+        let (width, height) = (cjn_os::graphic::width(), cjn_os::graphic::height());
+        let (margin_x, margin_y) = (height / 15, width / 20);
+        cjn_os::gui::terminal::open(margin_x, margin_y, width - 2 * margin_y, height - 2 * margin_x);
+    }
+    #[cfg(feature = "bench")]
+    cjn_os::bench::register_command();
+    println!("\n\n\t\t{}", i18n::tr(Msg::Motto));
+    // 进入 shell，shell 主循环不会返回，也确保内核不会意外退出到未定义行为状态中去
+    cjn_os::shell::run();
 }
 
 // 总结： 
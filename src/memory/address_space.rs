@@ -0,0 +1,180 @@
+// 用户进程的地址空间
+// 每个进程有自己的 PML4。创建时复制当前 PML4 的所有表项，内核的映射和当前地址空间共用下面的页表；
+// 往进程里映射页时，路径上和内核共用的页表先复制一份再改，内核自己的页表不受影响。
+// 复制之后内核在共用部分之外新建的映射不会出现在进程里，所以进程运行期间内核不应该新建映射。
+// 进程自己分配的页表记下来；页打上 cow::COUNTED 标记，可以和别的地址空间共用（见 cow 模块），销毁时按引用计数还给帧分配器
+
+use alloc::collections::BTreeSet;
+use core::ptr::{copy_nonoverlapping, write_bytes};
+
+use x86_64::registers::control::{Cr3, Cr3Flags};
+use x86_64::structures::paging::{
+    FrameAllocator, FrameDeallocator, Page, PageTable, PageTableFlags, PageTableIndex, PhysFrame, Size4KiB,
+};
+
+use crate::memory::cow::{self, COUNTED, COW};
+use crate::memory::phys_to_virt;
+
+const PAGE_SIZE: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapError {
+    FrameAllocationFailed,
+    /// 这个地址已经被内核的页或者大页占用
+    AlreadyMapped,
+}
+
+pub struct AddressSpace {
+    pml4: PhysFrame,
+    // 这个地址空间自己的页表，包括 PML4
+    owned: BTreeSet<PhysFrame>,
+}
+
+impl AddressSpace {
+    /// 以当前地址空间里内核的映射为基础新建一个地址空间
+    pub fn new(frames: &mut impl FrameAllocator<Size4KiB>) -> Result<Self, MapError> {
+        let pml4 = frames.allocate_frame().ok_or(MapError::FrameAllocationFailed)?;
+        let (current, _) = Cr3::read();
+        unsafe { copy_nonoverlapping(table(current), table(pml4), 1) };
+        let mut owned = BTreeSet::new();
+        owned.insert(pml4);
+        Ok(AddressSpace { pml4, owned })
+    }
+
+    /// 在 `page` 上映射一页清零的内存，返回它的帧
+    pub fn map(
+        &mut self,
+        page: Page<Size4KiB>,
+        flags: PageTableFlags,
+        frames: &mut (impl FrameAllocator<Size4KiB> + FrameDeallocator<Size4KiB>),
+    ) -> Result<PhysFrame, MapError> {
+        let frame = frames.allocate_frame().ok_or(MapError::FrameAllocationFailed)?;
+        unsafe { write_bytes(phys_to_virt(frame.start_address()).as_mut_ptr::<u8>(), 0, PAGE_SIZE) };
+        if let Err(error) = self.set_entry(page, frame, flags, frames) {
+            unsafe { frames.deallocate_frame(frame) };
+            return Err(error);
+        }
+        Ok(frame)
+    }
+
+    /// 把别处也在用的 `frame` 映射到 `page`，可写的页先映射成只读，写的时候再复制
+    pub fn map_shared(
+        &mut self,
+        page: Page<Size4KiB>,
+        frame: PhysFrame,
+        flags: PageTableFlags,
+        frames: &mut impl FrameAllocator<Size4KiB>,
+    ) -> Result<(), MapError> {
+        let flags = if flags.contains(PageTableFlags::WRITABLE) {
+            (flags - PageTableFlags::WRITABLE) | COW
+        } else {
+            flags
+        };
+        self.set_entry(page, frame, flags, frames)?;
+        cow::share(frame);
+        Ok(())
+    }
+
+    // 让 `page` 指向 `frame`，页表项原来必须是空的
+    fn set_entry(
+        &mut self,
+        page: Page<Size4KiB>,
+        frame: PhysFrame,
+        flags: PageTableFlags,
+        frames: &mut impl FrameAllocator<Size4KiB>,
+    ) -> Result<(), MapError> {
+        let addr = page.start_address();
+        let mut current = self.pml4;
+        for index in [addr.p4_index(), addr.p3_index(), addr.p2_index()] {
+            current = self.next_table(current, index, frames)?;
+        }
+        let entry = unsafe { &mut (*table(current))[addr.p1_index()] };
+        if !entry.is_unused() {
+            return Err(MapError::AlreadyMapped);
+        }
+        entry.set_frame(frame, flags | PageTableFlags::PRESENT | COUNTED);
+        Ok(())
+    }
+
+    // 页表 `current` 第 `index` 项指向的下一级页表，保证是这个地址空间自己的
+    fn next_table(
+        &mut self,
+        current: PhysFrame,
+        index: PageTableIndex,
+        frames: &mut impl FrameAllocator<Size4KiB>,
+    ) -> Result<PhysFrame, MapError> {
+        let entry = unsafe { &mut (*table(current))[index] };
+        // 中间各级都要允许用户访问；不可执行留给最后一级决定
+        let user = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+        if entry.is_unused() {
+            let next = self.allocate_table(frames)?;
+            entry.set_frame(next, user);
+            return Ok(next);
+        }
+        let shared = entry.frame().map_err(|_| MapError::AlreadyMapped)?;
+        let flags = (entry.flags() | user) & !PageTableFlags::NO_EXECUTE;
+        if self.owned.contains(&shared) {
+            entry.set_flags(flags);
+            return Ok(shared);
+        }
+        // 和内核共用的页表，复制一份再改
+        let next = self.allocate_table(frames)?;
+        unsafe { copy_nonoverlapping(table(shared), table(next), 1) };
+        entry.set_frame(next, flags);
+        Ok(next)
+    }
+
+    // 分配一个清零的页表，记在这个地址空间名下
+    fn allocate_table(&mut self, frames: &mut impl FrameAllocator<Size4KiB>) -> Result<PhysFrame, MapError> {
+        let frame = frames.allocate_frame().ok_or(MapError::FrameAllocationFailed)?;
+        unsafe { write_bytes(phys_to_virt(frame.start_address()).as_mut_ptr::<u8>(), 0, PAGE_SIZE) };
+        self.owned.insert(frame);
+        Ok(frame)
+    }
+
+    /// 通过物理内存的整体映射访问这个地址空间里的一页，不用切换页表
+    pub fn page_bytes(frame: PhysFrame) -> &'static mut [u8; PAGE_SIZE] {
+        unsafe { &mut *phys_to_virt(frame.start_address()).as_mut_ptr() }
+    }
+
+    /// 切换到这个地址空间，返回原来的 CR3，之后用 `restore` 切回去
+    ///
+    /// 切换期间不能访问只在原来的地址空间里的用户内存
+    pub unsafe fn activate(&self) -> (PhysFrame, Cr3Flags) {
+        let previous = Cr3::read();
+        Cr3::write(self.pml4, previous.1);
+        previous
+    }
+
+    /// 切回 `activate` 之前的地址空间，`previous` 是 `activate` 的返回值
+    pub unsafe fn restore(previous: (PhysFrame, Cr3Flags)) {
+        Cr3::write(previous.0, previous.1);
+    }
+
+    /// 把页表和没有别人在用的页还给帧分配器，不能销毁当前正在用的地址空间
+    pub fn destroy(self, frames: &mut impl FrameDeallocator<Size4KiB>) {
+        assert_ne!(Cr3::read().0, self.pml4, "destroying the active address space");
+        self.release_pages(self.pml4, 4, frames);
+        for frame in self.owned {
+            unsafe { frames.deallocate_frame(frame) };
+        }
+    }
+
+    // 进程的页只会出现在它自己的页表里，沿着自己的页表往下找
+    fn release_pages(&self, current: PhysFrame, level: usize, frames: &mut impl FrameDeallocator<Size4KiB>) {
+        for entry in unsafe { (*table(current)).iter() } {
+            let Ok(frame) = entry.frame() else { continue };
+            if level > 1 {
+                if self.owned.contains(&frame) {
+                    self.release_pages(frame, level - 1, frames);
+                }
+            } else if entry.flags().contains(COUNTED) && cow::release(frame) {
+                unsafe { frames.deallocate_frame(frame) };
+            }
+        }
+    }
+}
+
+fn table(frame: PhysFrame) -> *mut PageTable {
+    phys_to_virt(frame.start_address()).as_mut_ptr()
+}
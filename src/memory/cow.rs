@@ -0,0 +1,118 @@
+// 写时复制
+// 进程的页可以被几个地址空间（以及 loader 缓存的程序映像）共用。共用的帧在这里记引用计数，没有记录的帧只有一个使用者。
+// 共用的可写页映射成只读并打上 COW 标记，写的时候触发保护错误，page fault 处理函数调用 handle_write_fault：
+// 还有别人在用就复制一份换上，只剩自己在用就直接改回可写。
+// 页表项里用两个留给软件的位：COW 表示写时复制，COUNTED 表示这一页属于进程、销毁地址空间时要释放
+
+use alloc::collections::BTreeMap;
+use core::ptr::copy_nonoverlapping;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use spin::Mutex;
+use x86_64::instructions::tlb;
+use x86_64::registers::control::Cr3;
+use x86_64::structures::paging::{FrameAllocator, FrameDeallocator, PageTable, PageTableFlags, PhysFrame};
+use x86_64::VirtAddr;
+
+use crate::memory::{phys_to_virt, with_frames};
+
+/// 写时复制的页，映射成只读
+pub const COW: PageTableFlags = PageTableFlags::BIT_9;
+/// 进程自己的页，按引用计数释放
+pub const COUNTED: PageTableFlags = PageTableFlags::BIT_10;
+
+// 使用者多于一个的帧和它的使用者数
+static REFS: Mutex<BTreeMap<PhysFrame, usize>> = Mutex::new(BTreeMap::new());
+static COPIED: AtomicU64 = AtomicU64::new(0);
+static REUSED: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy)]
+pub struct CowStats {
+    /// 正被共用的帧数
+    pub shared: usize,
+    /// 共用的帧省下的帧数，也就是所有共用的帧多出来的使用者数之和
+    pub saved: usize,
+    /// 写的时候复制的页数
+    pub copied: u64,
+    /// 写的时候只剩自己在用、直接改回可写的页数
+    pub reused: u64,
+}
+
+pub fn stats() -> CowStats {
+    let refs = REFS.lock();
+    CowStats {
+        shared: refs.len(),
+        saved: refs.values().map(|count| count - 1).sum(),
+        copied: COPIED.load(Ordering::Relaxed),
+        reused: REUSED.load(Ordering::Relaxed),
+    }
+}
+
+/// 给 `frame` 增加一个使用者
+pub fn share(frame: PhysFrame) {
+    *REFS.lock().entry(frame).or_insert(1) += 1;
+}
+
+/// 去掉 `frame` 的一个使用者，返回是否是最后一个，是的话调用者负责释放这个帧
+pub fn release(frame: PhysFrame) -> bool {
+    let mut refs = REFS.lock();
+    match refs.get_mut(&frame) {
+        Some(count) => {
+            *count -= 1;
+            if *count == 1 {
+                refs.remove(&frame);
+            }
+            false
+        }
+        None => true,
+    }
+}
+
+pub fn is_shared(frame: PhysFrame) -> bool {
+    REFS.lock().contains_key(&frame)
+}
+
+/// 处理写保护引起的页错误，`addr` 在当前地址空间的写时复制页上时复制或者改回可写，返回是否处理了
+pub fn handle_write_fault(addr: VirtAddr) -> bool {
+    let (pml4, _) = Cr3::read();
+    let mut table = pml4;
+    for index in [addr.p4_index(), addr.p3_index(), addr.p2_index()] {
+        let entry = unsafe { &(*table_ptr(table))[index] };
+        if !entry.flags().contains(PageTableFlags::PRESENT) || entry.flags().contains(PageTableFlags::HUGE_PAGE) {
+            return false;
+        }
+        table = entry.frame().unwrap();
+    }
+    let entry = unsafe { &mut (*table_ptr(table))[addr.p1_index()] };
+    let flags = entry.flags();
+    if !flags.contains(PageTableFlags::PRESENT | COW) {
+        return false;
+    }
+    let old = entry.frame().unwrap();
+    let writable = (flags - COW) | PageTableFlags::WRITABLE;
+    if !is_shared(old) {
+        entry.set_flags(writable);
+        REUSED.fetch_add(1, Ordering::Relaxed);
+    } else {
+        let Some(Some(new)) = with_frames(|frames| frames.allocate_frame()) else { return false };
+        unsafe {
+            copy_nonoverlapping(
+                phys_to_virt(old.start_address()).as_ptr::<u8>(),
+                phys_to_virt(new.start_address()).as_mut_ptr::<u8>(),
+                old.size() as usize,
+            );
+        }
+        entry.set_frame(new, writable);
+        // 检查之后别人可能已经不用了
+        if release(old) {
+            with_frames(|frames| unsafe { frames.deallocate_frame(old) });
+        }
+        COPIED.fetch_add(1, Ordering::Relaxed);
+    }
+    tlb::flush(addr);
+    true
+}
+
+fn table_ptr(frame: PhysFrame) -> *mut PageTable {
+    phys_to_virt(frame.start_address()).as_mut_ptr()
+}
@@ -0,0 +1,221 @@
+// 页表检查工具
+// 从 PML4 一级级走到页表项，列出一段虚拟地址里的映射：物理地址、页大小（4KiB、2MiB、1GiB）和标志位。
+// 标志位是各级表项合起来的效果：可写和用户态可访问要每一级都允许，不可执行只要有一级设置就算。
+// 相邻的映射如果虚拟地址和物理地址都连续、页大小和标志位相同，就合并成一行，访问位和脏位不参与比较。
+// vmmap 命令用它查看地址空间，memory::layout 用它统计各区域实际映射了多少。
+// 和 report 一样不加锁，别的 CPU 同时修改页表时看到的可能是中间状态
+
+use core::fmt;
+use core::ops::Range;
+
+use x86_64::structures::paging::{PageTable, PageTableFlags};
+use x86_64::{PhysAddr, VirtAddr};
+
+use crate::io::format::Size;
+use crate::memory::{cow, layout};
+
+const PAGE_SIZE: u64 = 4096;
+// 合并和显示时不看的标志位，每页都可能不一样
+const VOLATILE_FLAGS: PageTableFlags = PageTableFlags::ACCESSED.union(PageTableFlags::DIRTY);
+// PML4 之上没有限制
+pub(super) const TOP_FLAGS: PageTableFlags = PageTableFlags::WRITABLE.union(PageTableFlags::USER_ACCESSIBLE);
+
+/// 一段映射到连续物理内存的虚拟地址
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mapping {
+    pub virt: VirtAddr,
+    pub phys: PhysAddr,
+    pub size: u64,
+    /// 4KiB、2MiB 或 1GiB
+    pub page_size: u64,
+    /// 各级表项合起来的标志位
+    pub flags: PageTableFlags,
+}
+
+impl Mapping {
+    // `next` 接在这段后面，能合并成一段
+    fn continues_with(&self, next: &Mapping) -> bool {
+        self.virt.as_u64().checked_add(self.size) == Some(next.virt.as_u64())
+            && self.phys + self.size == next.phys
+            && self.page_size == next.page_size
+            && self.flags - VOLATILE_FLAGS == next.flags - VOLATILE_FLAGS
+    }
+}
+
+/// 一个虚拟地址的翻译结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Translation {
+    pub phys: PhysAddr,
+    /// 地址所在的页的大小
+    pub page_size: u64,
+    /// 各级表项合起来的标志位
+    pub flags: PageTableFlags,
+}
+
+/// 在当前页表里翻译一个虚拟地址，支持大页；内存初始化之前或者没有映射时返回 None
+pub fn translate(addr: VirtAddr) -> Option<Translation> {
+    lookup(addr, *super::PHYSICAL_MEMORY_OFFSET.get()?)
+}
+
+// 按给定的物理内存偏移走一遍当前页表
+pub(super) fn lookup(addr: VirtAddr, offset: VirtAddr) -> Option<Translation> {
+    let indexes = [addr.p4_index(), addr.p3_index(), addr.p2_index(), addr.p1_index()];
+    let mut table: &PageTable = unsafe { super::active_level_4_table(offset) };
+    let mut inherited = TOP_FLAGS;
+    for (depth, index) in indexes.into_iter().enumerate() {
+        let entry = &table[index];
+        let flags = effective(inherited, entry.flags());
+        if !flags.contains(PageTableFlags::PRESENT) {
+            return None;
+        }
+        let page_size = PAGE_SIZE << (9 * (3 - depth));
+        // 第 3 级和第 2 级的表项可以直接映射 1GiB、2MiB 的页
+        if depth == 3 || (depth > 0 && flags.contains(PageTableFlags::HUGE_PAGE)) {
+            let phys = entry.addr() + (addr.as_u64() & (page_size - 1));
+            return Some(Translation { phys, page_size, flags });
+        }
+        inherited = flags;
+        table = unsafe { &*(offset + entry.addr().as_u64()).as_ptr::<PageTable>() };
+    }
+    None
+}
+
+// 上一级合起来的标志位 `parent` 加上这一级的表项
+pub(super) fn effective(parent: PageTableFlags, entry: PageTableFlags) -> PageTableFlags {
+    let inherited = PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+    (entry & (parent | !inherited)) | (parent & PageTableFlags::NO_EXECUTE)
+}
+
+/// 按地址顺序列出和 `range` 相交的映射，合并相邻的；内存初始化之前什么都不列
+pub fn mappings(range: Range<u64>, mut f: impl FnMut(Mapping)) {
+    let mut pending: Option<Mapping> = None;
+    for_each_page(range, |mapping| match pending.as_mut() {
+        Some(current) if current.continues_with(&mapping) => current.size += mapping.size,
+        _ => {
+            if let Some(done) = pending.replace(mapping) {
+                f(done);
+            }
+        }
+    });
+    if let Some(done) = pending {
+        f(done);
+    }
+}
+
+/// 逐个列出和 `range` 相交的页，不合并；大页只列出一次
+pub fn for_each_page(range: Range<u64>, mut f: impl FnMut(Mapping)) {
+    let Some(&offset) = super::PHYSICAL_MEMORY_OFFSET.get() else { return };
+    if range.is_empty() {
+        return;
+    }
+    let table = unsafe { super::active_level_4_table(offset) };
+    walk(table, 4, 0, TOP_FLAGS, &range, offset, &mut f);
+}
+
+// `base` 是这张页表覆盖的起始地址；高半部分的地址按符号扩展算，和虚拟地址的大小顺序一致
+fn walk(
+    table: &PageTable,
+    level: u32,
+    base: u64,
+    inherited: PageTableFlags,
+    range: &Range<u64>,
+    offset: VirtAddr,
+    f: &mut impl FnMut(Mapping),
+) {
+    let span = PAGE_SIZE << (9 * (level - 1));
+    for (i, entry) in table.iter().enumerate() {
+        let mut start = base + i as u64 * span;
+        if level == 4 && i >= 256 {
+            start |= 0xFFFF_0000_0000_0000;
+        }
+        let last = start + (span - 1);
+        if last < range.start || start >= range.end || !entry.flags().contains(PageTableFlags::PRESENT) {
+            continue;
+        }
+        let flags = effective(inherited, entry.flags());
+        if level == 1 || flags.contains(PageTableFlags::HUGE_PAGE) {
+            let virt = VirtAddr::new_truncate(start);
+            f(Mapping { virt, phys: entry.addr(), size: span, page_size: span, flags });
+        } else {
+            let next = unsafe { &*(offset + entry.addr().as_u64()).as_ptr::<PageTable>() };
+            walk(next, level - 1, start, flags, range, offset, f);
+        }
+    }
+}
+
+/// 标志位的简写：w 可写、user 用户态可访问、wt 写透、uc 不缓存、g 全局、nx 不可执行，cow 写时复制
+#[derive(Debug, Clone, Copy)]
+pub struct Flags(pub PageTableFlags);
+
+impl fmt::Display for Flags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = [
+            (PageTableFlags::WRITABLE, "w"),
+            (PageTableFlags::USER_ACCESSIBLE, "user"),
+            (PageTableFlags::WRITE_THROUGH, "wt"),
+            (PageTableFlags::NO_CACHE, "uc"),
+            (PageTableFlags::GLOBAL, "g"),
+            (PageTableFlags::NO_EXECUTE, "nx"),
+            (cow::COW, "cow"),
+        ];
+        let mut first = true;
+        for (flag, name) in names {
+            if self.0.contains(flag) {
+                if !first {
+                    f.write_str(" ")?;
+                }
+                f.write_str(name)?;
+                first = false;
+            }
+        }
+        if first {
+            f.write_str("r")?;
+        }
+        Ok(())
+    }
+}
+
+/// 用 Display 输出 `range` 里的映射，每段一行，后面标出所在的布局区域
+pub fn dump_page_table(range: Range<u64>) -> PageTableDump {
+    PageTableDump { range }
+}
+
+pub struct PageTableDump {
+    range: Range<u64>,
+}
+
+impl fmt::Display for PageTableDump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut result = Ok(());
+        mappings(self.range.clone(), |mapping| {
+            if result.is_err() {
+                return;
+            }
+            let start = mapping.virt.as_u64();
+            result = writeln!(f, "{:#018x}-{:#018x} -> {:#014x} {:>10} {:>2} {:<14} {}", start,
+                              start + (mapping.size - 1), mapping.phys.as_u64(), Size(mapping.size),
+                              page_label(mapping.page_size), region_name(start), Flags(mapping.flags));
+        });
+        result
+    }
+}
+
+/// 页大小的简写：4K、2M、1G
+pub fn page_label(page_size: u64) -> &'static str {
+    match page_size {
+        0x1000 => "4K",
+        0x20_0000 => "2M",
+        _ => "1G",
+    }
+}
+
+// 地址所在的布局区域，物理内存整体映射叫 physical，都不在时为空
+fn region_name(addr: u64) -> &'static str {
+    if let Some(region) = layout::REGIONS.iter().find(|region| region.contains(addr)) {
+        return region.name;
+    }
+    match layout::physical_window() {
+        Some((start, end)) if addr >= start && addr < end => "physical",
+        _ => "",
+    }
+}
@@ -0,0 +1,237 @@
+// DMA 地址转换
+// 设备做总线主控 DMA 时看到的是总线地址，不一定等于物理地址：有 IOMMU（比如 QEMU 模拟的 VT-d）时
+// 要先在 IOMMU 里为设备建立映射。驱动统一通过 map 取得总线地址、用完后 unmap，不要自己把物理地址交给设备。
+// 默认的转换是恒等映射（总线地址就是物理地址），只检查地址没有超出设备的寻址范围；
+// 以后接入 IOMMU 时实现 DmaTranslator 并用 set_translator 换掉，驱动不需要修改。
+// 描述符环、包缓冲区这类内存用 alloc_coherent 分配：物理连续、清零、已经映射好，同时给出 CPU 和设备用的地址，释放时自动撤销映射
+
+use core::ptr;
+use core::slice;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use spin::RwLock;
+use x86_64::structures::paging::{FrameDeallocator, PhysFrame};
+use x86_64::{PhysAddr, VirtAddr};
+
+use crate::io::pci::PciDevice;
+use crate::memory::{phys_to_virt, with_frames};
+
+const PAGE_SIZE: usize = 4096;
+
+/// 设备看到的总线地址
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct BusAddr(u64);
+
+impl BusAddr {
+    pub const fn new(addr: u64) -> Self {
+        Self(addr)
+    }
+
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DmaError {
+    /// 地址超出设备能寻址的范围
+    OutOfRange,
+    /// 转换器没有空间再建立映射
+    NoSpace,
+    /// 长度为 0
+    Empty,
+    /// 分配不到物理连续的内存
+    NoMemory,
+}
+
+/// 一段已经对设备可见的内存
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DmaMapping {
+    bus: BusAddr,
+    phys: PhysAddr,
+    size: u64,
+    requester: u16,
+}
+
+impl DmaMapping {
+    /// 交给设备的地址
+    pub fn bus(&self) -> BusAddr {
+        self.bus
+    }
+
+    pub fn phys(&self) -> PhysAddr {
+        self.phys
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+/// 设备发起 DMA 时用来区分来源的请求者 ID：总线号、设备号、功能号
+pub fn requester_id(device: &PciDevice) -> u16 {
+    (device.bus as u16) << 8 | (device.device as u16) << 3 | device.function as u16
+}
+
+pub trait DmaTranslator: Sync {
+    /// 名字，用于显示
+    fn name(&self) -> &'static str;
+
+    /// 让请求者 `requester` 能访问物理地址 `phys` 开始的 `size` 字节，返回设备应使用的总线地址；
+    /// 总线地址加上长度不能超过 `mask`
+    fn map(&self, requester: u16, phys: PhysAddr, size: u64, mask: u64) -> Result<BusAddr, DmaError>;
+
+    /// 撤销 map 建立的映射
+    fn unmap(&self, requester: u16, bus: BusAddr, size: u64);
+}
+
+/// 恒等映射，总线地址等于物理地址
+pub struct Identity;
+
+impl DmaTranslator for Identity {
+    fn name(&self) -> &'static str {
+        "identity"
+    }
+
+    fn map(&self, _requester: u16, phys: PhysAddr, size: u64, mask: u64) -> Result<BusAddr, DmaError> {
+        let last = phys.as_u64().checked_add(size - 1).ok_or(DmaError::OutOfRange)?;
+        if last > mask {
+            return Err(DmaError::OutOfRange);
+        }
+        Ok(BusAddr(phys.as_u64()))
+    }
+
+    fn unmap(&self, _requester: u16, _bus: BusAddr, _size: u64) {}
+}
+
+/// 只能发出 32 位地址的设备
+pub const MASK_32: u64 = 0xFFFF_FFFF;
+/// 能发出 64 位地址的设备
+pub const MASK_64: u64 = u64::MAX;
+
+static TRANSLATOR: RwLock<&'static dyn DmaTranslator> = RwLock::new(&Identity);
+// 还没有 unmap 的映射数，换转换器时用来检查
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+/// 换掉地址转换器，已有的映射仍然有效时返回 false 并保持不变
+pub fn set_translator(translator: &'static dyn DmaTranslator) -> bool {
+    let mut current = TRANSLATOR.write();
+    if ACTIVE.load(Ordering::Acquire) != 0 {
+        return false;
+    }
+    log::info!("DMA translation: {} -> {}", current.name(), translator.name());
+    *current = translator;
+    true
+}
+
+/// 当前地址转换器的名字
+pub fn translator() -> &'static str {
+    TRANSLATOR.read().name()
+}
+
+/// 让设备能访问物理地址 `phys` 开始的 `size` 字节；`mask` 是设备能发出的最大地址，比如 MASK_32
+pub fn map(device: &PciDevice, phys: PhysAddr, size: u64, mask: u64) -> Result<DmaMapping, DmaError> {
+    if size == 0 {
+        return Err(DmaError::Empty);
+    }
+    let requester = requester_id(device);
+    let translator = TRANSLATOR.read();
+    let bus = translator.map(requester, phys, size, mask)?;
+    ACTIVE.fetch_add(1, Ordering::AcqRel);
+    Ok(DmaMapping { bus, phys, size, requester })
+}
+
+/// 设备不再访问这段内存之后调用
+pub fn unmap(mapping: DmaMapping) {
+    TRANSLATOR.read().unmap(mapping.requester, mapping.bus, mapping.size);
+    ACTIVE.fetch_sub(1, Ordering::AcqRel);
+}
+
+/// 还没有 unmap 的映射数
+pub fn active_mappings() -> usize {
+    ACTIVE.load(Ordering::Acquire)
+}
+
+/// 物理连续、设备能访问的一段内存，比如网卡的描述符环和包缓冲区，由 alloc_coherent 分配；
+/// 释放时撤销映射并把帧还给帧分配器
+pub struct DmaBuffer {
+    first: PhysFrame,
+    pages: usize,
+    size: usize,
+    mapping: DmaMapping,
+}
+
+/// 给 `device` 分配 `size` 字节物理连续、清零的内存，物理地址按 `align` 字节对齐（至少按页对齐），
+/// 设备能发出的最大地址是 `mask`
+pub fn alloc_coherent(device: &PciDevice, size: usize, align: usize, mask: u64) -> Result<DmaBuffer, DmaError> {
+    if size == 0 {
+        return Err(DmaError::Empty);
+    }
+    let pages = size.div_ceil(PAGE_SIZE);
+    let align = align.next_multiple_of(PAGE_SIZE) as u64;
+    let first = with_frames(|frames| frames.allocate_aligned(pages, align)).flatten().ok_or(DmaError::NoMemory)?;
+    let mapping = match map(device, first.start_address(), (pages * PAGE_SIZE) as u64, mask) {
+        Ok(mapping) => mapping,
+        Err(error) => {
+            free_frames(first, pages);
+            return Err(error);
+        }
+    };
+    let buffer = DmaBuffer { first, pages, size, mapping };
+    unsafe { ptr::write_bytes(buffer.ptr(), 0, pages * PAGE_SIZE) };
+    Ok(buffer)
+}
+
+impl DmaBuffer {
+    /// 交给设备的地址
+    pub fn bus(&self) -> BusAddr {
+        self.mapping.bus()
+    }
+
+    pub fn phys(&self) -> PhysAddr {
+        self.first.start_address()
+    }
+
+    /// CPU 访问用的虚拟地址，在物理内存的整体映射里
+    pub fn virt(&self) -> VirtAddr {
+        phys_to_virt(self.phys())
+    }
+
+    pub fn ptr(&self) -> *mut u8 {
+        self.virt().as_mut_ptr()
+    }
+
+    /// 分配时要求的字节数
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// 设备不会同时写入时才能用，比如准备发送的数据、设备交回的接收缓冲区
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr(), self.size) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.ptr(), self.size) }
+    }
+}
+
+impl Drop for DmaBuffer {
+    // 调用者要先让设备停下来，保证设备不再访问这段内存
+    fn drop(&mut self) {
+        unmap(self.mapping);
+        free_frames(self.first, self.pages);
+    }
+}
+
+fn free_frames(first: PhysFrame, count: usize) {
+    with_frames(|frames| {
+        for frame in PhysFrame::range(first, first + count as u64) {
+            unsafe { frames.deallocate_frame(frame) };
+        }
+    });
+}
@@ -0,0 +1,173 @@
+// 内核地址空间布局
+// 内核自己用到的固定虚拟地址区域都在这里声明，堆、动态映射区、内核栈、用户程序的起始地址和大小从这里取，不再各自写死。
+// 编译时检查每个区域按页对齐、在低半部分、按地址排列并且互不重叠。
+// 启动完成时 validate 遍历当前页表，检查实际的映射和声明的一致：该映射的已经映射，堆的后面和保护页没有多余的映射，
+// bootloader 放的物理内存整体映射没有压在声明的区域上；log_map 把各区域和它们实际映射了多少输出到日志
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use x86_64::{PhysAddr, VirtAddr};
+
+use crate::allocator::heap_size;
+use crate::io::format::Size;
+use crate::memory::debug;
+
+const PAGE_SIZE: u64 = 4096;
+const HUGE_PAGE_SIZE: u64 = 0x20_0000;
+// 低半部分的结尾，再往上是非规范地址
+const LOWER_HALF_END: u64 = 0x0000_8000_0000_0000;
+
+/// 一段虚拟地址区域
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub name: &'static str,
+    pub start: u64,
+    pub size: u64,
+}
+
+impl Region {
+    pub const fn end(&self) -> u64 {
+        self.start + self.size
+    }
+
+    pub const fn contains(&self, addr: u64) -> bool {
+        addr >= self.start && addr < self.end()
+    }
+
+    const fn overlaps(&self, start: u64, end: u64) -> bool {
+        start < self.end() && self.start < end
+    }
+}
+
+// 配置区域
+/// 第一页不映射，空指针访问总会出错
+pub const NULL_GUARD: Region = Region { name: "null guard", start: 0, size: PAGE_SIZE };
+/// VGA 文本缓冲区，bootloader 按物理地址原样映射
+pub const VGA_TEXT: Region = Region { name: "VGA text", start: 0xb8000, size: PAGE_SIZE };
+/// 内核映像，链接在 2MiB 处
+pub const KERNEL_IMAGE: Region = Region { name: "kernel image", start: 0x20_0000, size: 0x3FE0_0000 };
+/// 内置用户程序的代码和栈，从 1 GiB 开始
+pub const USER: Region = Region { name: "user", start: 0x4000_0000, size: 0x4000_0000 };
+/// 动态映射区，显存和设备寄存器映射在这里
+pub const VMM: Region = Region { name: "vmm", start: 0xC000_0000, size: 0x4000_0000 };
+/// 堆，实际大小由启动参数决定，这里是上限
+pub const HEAP: Region = Region { name: "heap", start: 0x_0001_0000_0000, size: 0x4000_0000 };
+/// 内核线程栈
+pub const KERNEL_STACKS: Region = Region { name: "kernel stacks", start: 0x_0002_0000_0000, size: 0x1000_0000 };
+
+/// 所有声明的区域，按地址排列
+pub const REGIONS: [Region; 7] = [NULL_GUARD, VGA_TEXT, KERNEL_IMAGE, USER, VMM, HEAP, KERNEL_STACKS];
+
+const _: () = check(&REGIONS);
+// 堆用 2MiB 大页映射
+const _: () = assert!(HEAP.start % HUGE_PAGE_SIZE == 0 && HEAP.size % HUGE_PAGE_SIZE == 0);
+
+const fn check(regions: &[Region]) {
+    let mut i = 0;
+    while i < regions.len() {
+        let region = &regions[i];
+        assert!(region.size > 0, "empty layout region");
+        assert!(region.start % PAGE_SIZE == 0 && region.size % PAGE_SIZE == 0, "layout region not page aligned");
+        assert!(region.end() <= LOWER_HALF_END, "layout region outside the lower half");
+        assert!(i == 0 || regions[i - 1].end() <= region.start, "layout regions overlap or are out of order");
+        i += 1;
+    }
+}
+
+/// 实际的映射和布局不一致的地方
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayoutError {
+    /// 应该映射的地址没有映射
+    NotMapped(&'static str),
+    /// 不应该映射的地址被映射了
+    UnexpectedMapping(&'static str),
+    /// 地址不在声明的区域里
+    Outside(&'static str),
+    /// 物理内存整体映射压在了这个区域上
+    PhysicalOverlap(&'static str),
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LayoutError::NotMapped(name) => write!(f, "{} is not mapped", name),
+            LayoutError::UnexpectedMapping(name) => write!(f, "unexpected mapping in {}", name),
+            LayoutError::Outside(name) => write!(f, "{} lies outside its region", name),
+            LayoutError::PhysicalOverlap(name) => write!(f, "physical memory mapping overlaps {}", name),
+        }
+    }
+}
+
+/// 按当前页表检查布局，返回所有不一致的地方；内存初始化之前什么都不检查
+pub fn validate() -> Vec<LayoutError> {
+    let mut errors = Vec::new();
+    if super::PHYSICAL_MEMORY_OFFSET.get().is_none() {
+        return errors;
+    }
+    if mapped(NULL_GUARD.start, NULL_GUARD.end()) != 0 {
+        errors.push(LayoutError::UnexpectedMapping(NULL_GUARD.name));
+    }
+    if super::translate(VirtAddr::new(VGA_TEXT.start)) != Some(PhysAddr::new(VGA_TEXT.start)) {
+        errors.push(LayoutError::NotMapped(VGA_TEXT.name));
+    }
+    // 拿一个函数的地址代表内核映像
+    let code = validate as usize as u64;
+    if !KERNEL_IMAGE.contains(code) {
+        errors.push(LayoutError::Outside(KERNEL_IMAGE.name));
+    }
+    let heap_end = HEAP.start + heap_size() as u64;
+    if mapped(HEAP.start, heap_end) != heap_end - HEAP.start {
+        errors.push(LayoutError::NotMapped(HEAP.name));
+    }
+    if mapped(heap_end, HEAP.end()) != 0 {
+        errors.push(LayoutError::UnexpectedMapping(HEAP.name));
+    }
+    // 第一个内核线程栈下面的保护页
+    if mapped(KERNEL_STACKS.start, KERNEL_STACKS.start + PAGE_SIZE) != 0 {
+        errors.push(LayoutError::UnexpectedMapping(KERNEL_STACKS.name));
+    }
+    if let Some((start, end)) = physical_window() {
+        errors.extend(REGIONS.iter().filter(|region| region.overlaps(start, end)).map(|region| {
+            LayoutError::PhysicalOverlap(region.name)
+        }));
+    }
+    errors
+}
+
+/// 每个区域一行 info 日志：地址范围、大小和实际映射了多少，物理内存整体映射也列出来
+pub fn log_map() {
+    log::info!("address space:");
+    for region in &REGIONS {
+        log::info!("  {:#014x}-{:#014x} {:<14} {:>10} {:>10} mapped", region.start, region.end(), region.name,
+                   Size(region.size), Size(mapped(region.start, region.end())));
+    }
+    if let Some((start, end)) = physical_window() {
+        log::info!("  {:#014x}-{:#014x} {:<14} {:>10}", start, end, "physical", Size(end - start));
+    }
+}
+
+/// 验证布局，把地址空间的分布和不一致的地方输出到日志；启动完成时调用
+pub fn check_and_log() {
+    log_map();
+    for error in validate() {
+        log::error!("address space layout: {}", error);
+    }
+}
+
+// bootloader 把全部物理内存映射到的虚拟地址范围
+pub(super) fn physical_window() -> Option<(u64, u64)> {
+    let offset = super::PHYSICAL_MEMORY_OFFSET.get()?.as_u64();
+    let top = super::report::regions().map(|region| region.end).max()?;
+    Some((offset, offset + top))
+}
+
+// [start, end) 里实际映射的字节数，内存初始化之前为 0
+fn mapped(start: u64, end: u64) -> u64 {
+    let mut bytes = 0;
+    debug::for_each_page(start..end, |page| {
+        let page_start = page.virt.as_u64();
+        bytes += (page_start + page.size).min(end) - page_start.max(start);
+    });
+    bytes
+}
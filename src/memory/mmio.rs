@@ -0,0 +1,132 @@
+// 内存映射的设备寄存器
+// 驱动把寄存器布局写成 #[repr(C)] 的结构体，字段用 ReadOnly/WriteOnly/ReadWrite 包起来，中间的空隙用 Reserved 填上，
+// 映射之后得到 Mmio<T>，直接 regs.status.read() 这样访问，不用再做偏移量的加法。每次 read/write 都是一次 volatile 访问，
+// 不会被编译器合并或省掉。map_mmio 映射的页是不可缓存的，x86 上对它们的访问本来就按顺序进行；
+// 寄存器和普通内存（DMA 缓冲区、写合并的显存）之间需要保证顺序时用 read_barrier/write_barrier
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem::size_of;
+use core::ops::Deref;
+use core::ptr::{read_volatile, write_volatile};
+
+use x86_64::structures::paging::mapper::MapToError;
+use x86_64::structures::paging::Size4KiB;
+use x86_64::PhysAddr;
+
+use crate::memory::vmm::VirtualRegion;
+
+/// 只读的寄存器
+#[repr(transparent)]
+pub struct ReadOnly<T: Copy>(UnsafeCell<T>);
+
+/// 只写的寄存器，读出来的值没有意义
+#[repr(transparent)]
+pub struct WriteOnly<T: Copy>(UnsafeCell<T>);
+
+/// 可读可写的寄存器
+#[repr(transparent)]
+pub struct ReadWrite<T: Copy>(UnsafeCell<T>);
+
+/// 寄存器之间保留不用的 `N` 个字节
+#[repr(transparent)]
+pub struct Reserved<const N: usize>([u8; N]);
+
+// 每次访问都是一条 volatile 指令，多个 CPU 同时访问时的含义由设备决定
+unsafe impl<T: Copy + Send> Sync for ReadOnly<T> {}
+unsafe impl<T: Copy + Send> Sync for WriteOnly<T> {}
+unsafe impl<T: Copy + Send> Sync for ReadWrite<T> {}
+
+impl<T: Copy> ReadOnly<T> {
+    pub fn read(&self) -> T {
+        unsafe { read_volatile(self.0.get()) }
+    }
+}
+
+impl<T: Copy> WriteOnly<T> {
+    pub fn write(&self, value: T) {
+        unsafe { write_volatile(self.0.get(), value) }
+    }
+}
+
+impl<T: Copy> ReadWrite<T> {
+    pub fn read(&self) -> T {
+        unsafe { read_volatile(self.0.get()) }
+    }
+
+    pub fn write(&self, value: T) {
+        unsafe { write_volatile(self.0.get(), value) }
+    }
+
+    /// 读出来、改一下再写回去，返回写回去的值
+    pub fn update(&self, f: impl FnOnce(T) -> T) -> T {
+        let value = f(self.read());
+        self.write(value);
+        value
+    }
+}
+
+impl<T: Copy + fmt::Debug> fmt::Debug for ReadOnly<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.read().fmt(f)
+    }
+}
+
+impl<T: Copy + fmt::Debug> fmt::Debug for ReadWrite<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.read().fmt(f)
+    }
+}
+
+/// 之前的读取都完成之后才进行之后的读取，比如读完设备的状态寄存器再读 DMA 缓冲区
+#[inline]
+pub fn read_barrier() {
+    unsafe { core::arch::asm!("lfence", options(nostack, preserves_flags)) };
+}
+
+/// 之前的写入都完成之后才进行之后的写入，比如填好 DMA 描述符再写设备的门铃寄存器
+#[inline]
+pub fn write_barrier() {
+    unsafe { core::arch::asm!("sfence", options(nostack, preserves_flags)) };
+}
+
+/// 映射好的寄存器块，解引用得到寄存器布局 `T`
+pub struct Mmio<T> {
+    region: VirtualRegion,
+    _registers: PhantomData<&'static T>,
+}
+
+// 寄存器本身是 Sync 的，Mmio 只是指向它们
+unsafe impl<T: Sync> Send for Mmio<T> {}
+unsafe impl<T: Sync> Sync for Mmio<T> {}
+
+impl<T> Mmio<T> {
+    /// 把已经映射好的区间当成寄存器块 `T`，区间不能比 `T` 小
+    ///
+    /// 启动过程中用 vmm::map_mmio 映射的驱动用它；区间必须确实映射着这个设备的寄存器
+    pub unsafe fn from_region(region: VirtualRegion) -> Self {
+        assert!(region.size() as usize >= size_of::<T>(), "MMIO region is smaller than the register block");
+        assert!(region.start().is_aligned(core::mem::align_of::<T>() as u64), "MMIO region is misaligned");
+        Self { region, _registers: PhantomData }
+    }
+
+    /// 映射的虚拟地址区间
+    pub fn region(&self) -> &VirtualRegion {
+        &self.region
+    }
+}
+
+impl<T> Deref for Mmio<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.region.start().as_ptr::<T>() }
+    }
+}
+
+/// 在当前页表里映射物理地址 `phys` 处的寄存器块 `T`，见 memory::map_mmio
+pub fn map<T>(phys: PhysAddr) -> Result<Mmio<T>, MapToError<Size4KiB>> {
+    let region = super::map_mmio(phys, size_of::<T>() as u64)?;
+    Ok(unsafe { Mmio::from_region(region) })
+}
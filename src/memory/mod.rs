@@ -1,4 +1,7 @@
+use alloc::vec::Vec;
+
 use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use spin::{Mutex, Once};
 
 // 引入`x86_64` crate 中的 `PageTable`, `VirtAddr`, 和 `PhysAddr` 类型。这些用于管理虚拟和物理地址以及页面表.
 use x86_64::{
@@ -7,15 +10,71 @@ use x86_64::{
     VirtAddr,
 };
 
-use x86_64::structures::paging::{FrameAllocator, Mapper, OffsetPageTable, Page, PhysFrame, Size4KiB};
+use x86_64::structures::paging::mapper::MapToError;
+use x86_64::structures::paging::{FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page, PageSize, PhysFrame, Size2MiB, Size4KiB};
+
+use crate::boot::Unit;
 
+pub mod address_space;
+pub mod cow;
+pub mod debug;
+pub mod dma;
 pub mod graphic_support;
+pub mod layout;
+pub mod mmio;
+pub mod report;
+pub mod stacks;
+pub mod vmm;
+pub mod wx;
+
+// 1MiB 以下的物理内存不交给帧分配器，留给只能用实模式地址的用途，例如 AP 的启动代码
+pub const LOW_MEMORY_END: u64 = 0x100000;
+
+// 物理内存被 bootloader 整体映射到的虚拟地址偏移，在 init 中记录
+static PHYSICAL_MEMORY_OFFSET: Once<VirtAddr> = Once::new();
+
+// 通过物理内存的整体映射访问物理地址，例如读取 ACPI 表
+// 必须在 init 之后调用
+pub fn phys_to_virt(addr: PhysAddr) -> VirtAddr {
+    let offset = PHYSICAL_MEMORY_OFFSET.get().expect("Memory is not initialized yet");
+    *offset + addr.as_u64()
+}
+
+// 在当前页表中翻译虚拟地址，支持大页；init 之前总是返回 None
+// 调试器访问内存前用它检查地址是否已映射，避免在异常处理中再触发页错误
+pub fn translate(addr: VirtAddr) -> Option<PhysAddr> {
+    use x86_64::structures::paging::Translate;
+
+    let offset = *PHYSICAL_MEMORY_OFFSET.get()?;
+    let table = unsafe { OffsetPageTable::new(active_level_4_table(offset), offset) };
+    table.translate_addr(addr)
+}
+
+// 当前页表里 [start, start + len) 的每一页都已映射，并且用户态可以访问
+// 系统调用用它检查用户程序传进来的指针
+pub fn is_user_accessible(start: VirtAddr, len: u64) -> bool {
+    use x86_64::structures::paging::mapper::{Translate, TranslateResult};
+    use x86_64::structures::paging::PageTableFlags;
+
+    let Some(&offset) = PHYSICAL_MEMORY_OFFSET.get() else { return false };
+    if len == 0 {
+        return true;
+    }
+    let Some(end) = start.as_u64().checked_add(len - 1).and_then(|end| VirtAddr::try_new(end).ok()) else {
+        return false;
+    };
+    let table = unsafe { OffsetPageTable::new(active_level_4_table(offset), offset) };
+    let mut pages = Page::<Size4KiB>::range_inclusive(Page::containing_address(start), Page::containing_address(end));
+    pages.all(|page| matches!(table.translate(page.start_address()),
+        TranslateResult::Mapped { flags, .. } if flags.contains(PageTableFlags::USER_ACCESSIBLE)))
+}
 
 // 初始化偏移页表
 //
 // 这个函数是危险的，因为其调用的函数具有危险性。
 // 详情请见active_level_4_table
 pub unsafe fn init(physical_memory_offset: VirtAddr) -> OffsetPageTable<'static> {
+    PHYSICAL_MEMORY_OFFSET.call_once(|| physical_memory_offset);
     let l4t = active_level_4_table(physical_memory_offset);
     OffsetPageTable::new(l4t, physical_memory_offset)
 }
@@ -46,37 +105,9 @@ pub unsafe fn translate_addr(addr: VirtAddr, physical_memory_offset: VirtAddr) -
 }
 
 // 定义一个私有辅助函数，逻辑上与公共函数相同，但它不被标记为unsafe（虽然实际上仍然是危险操作）
+// 遍历多级页表找到给定虚拟地址映射到哪个物理帧，2MiB 和 1GiB 的大页也能翻译，见 debug::lookup
 fn translate_addr_inner(addr: VirtAddr, physical_memory_offset: VirtAddr) -> Option<PhysAddr> {
-    use x86_64::structures::paging::page_table::FrameError;
-    use x86_64::registers::control::Cr3;
-
-    // 从CR3读当前活跃的L4页帧
-    let (level_4_table_frame, _) = Cr3::read();
-
-    let table_indexes = [
-        addr.p4_index(), addr.p3_index(), addr.p2_index(), addr.p1_index()
-    ];
-    let mut frame = level_4_table_frame;
-
-    // 遍历多级页表
-    // 遍历页面多级页表结构来找到给定虚拟地址映射到哪个物理帧。如果此过程中出现问题（如页不存在或不支持巨大页面），将返回None或产生panic异常
-    for &index in &table_indexes {
-        // 将帧转换为页表引用
-        let virt = physical_memory_offset + frame.start_address().as_u64();
-        let table_ptr: *const PageTable = virt.as_ptr();
-        let table = unsafe { &*table_ptr };
-
-        // 从页表读取位址并更新帧
-        let entry = &table[index];
-        frame = match entry.frame() {
-            Ok(frame) => frame,
-            Err(FrameError::FrameNotPresent) => return None,
-            Err(FrameError::HugeFrame) => panic!("Not Supported HugeFrame")
-        };
-    }
-
-    // 计算物理地址
-    Some(frame.start_address() + u64::from(addr.page_offset()))
+    debug::lookup(addr, physical_memory_offset).map(|translation| translation.phys)
 }
 
 // 总结逻辑：
@@ -166,6 +197,7 @@ impl BootInfoFrameAllocator {
     // - `memory_map`: 存储传入的内存映射引用。
     // - `next`: 初始化为0，用于跟踪下一个可用帧的位置
     pub unsafe fn init(memory_map: &'static MemoryMap) -> Self {
+        report::record_memory_map(memory_map);
         BootInfoFrameAllocator {
             memory_map,
             next: 0,
@@ -186,11 +218,58 @@ impl BootInfoFrameAllocator {
         // 转换为帧起始位置的迭代器
         // 使用 `flat_map` 方法，将每个地址区间按 4096 字节（即4KiB）的步长进行遍历，生成包含所有物理帧起始地址的迭代器。
         // - `step_by(4096)` 确保每次步进大小为一页（4KiB），因为每个物理帧通常是4KiB大小
-        let frame_addresses = addr_ranges.flat_map(|r| r.step_by(4096));
+        let frame_addresses = addr_ranges.flat_map(|r| r.step_by(4096)).filter(|&addr| addr >= LOW_MEMORY_END);
         // 通过帧起始位置创建PhysFrame类实例
         // 将上述步骤中得到的每个物理帧起始地址转换成 `PhysFrame` 实例。最终返回一个包含所有可用物理框架的迭代器
         frame_addresses.map(|addr| PhysFrame::containing_address(PhysAddr::new(addr)))
     }
+
+    // 从当前位置向后找一段 `count` 个物理连续、起始地址按 `align` 字节对齐的可用帧，跳过的零散帧不再使用
+    fn allocate_contiguous(&mut self, count: usize, align: u64) -> Option<PhysFrame> {
+        let mut run_start = (0, None);
+        let mut run_len = 0;
+        let mut expected = 0u64;
+        for (index, frame) in self.usable_frames().enumerate().skip(self.next) {
+            let addr = frame.start_address().as_u64();
+            if run_len == 0 || addr != expected {
+                if addr % align != 0 {
+                    run_len = 0;
+                    continue;
+                }
+                run_start = (index, Some(frame));
+                run_len = 0;
+            }
+            run_len += 1;
+            expected = addr + Size4KiB::SIZE;
+            if run_len == count {
+                self.next = run_start.0 + count;
+                return run_start.1;
+            }
+        }
+        None
+    }
+
+    // 可以分出去的帧数，和 usable_frames 数出来的一样
+    fn usable_frame_count(&self) -> usize {
+        self.memory_map
+            .iter()
+            .filter(|r| r.region_type == MemoryRegionType::Usable)
+            .map(|r| {
+                let start = r.range.start_addr().max(LOW_MEMORY_END);
+                (r.range.end_addr().saturating_sub(start) as usize).div_ceil(4096)
+            })
+            .sum()
+    }
+
+    /// 1MiB 以下第一个可用的帧（跳过 0 号帧），这些帧不会被 allocate_frame 分出去
+    pub fn low_memory_frame(&self) -> Option<PhysFrame> {
+        self.memory_map
+            .iter()
+            .filter(|r| r.region_type == MemoryRegionType::Usable)
+            .flat_map(|r| (r.range.start_addr()..r.range.end_addr().min(LOW_MEMORY_END)).step_by(4096))
+            .find(|&addr| addr != 0)
+            .map(|addr| PhysFrame::containing_address(PhysAddr::new(addr)))
+    }
 }
 
 // 这段代码展示了如何基于引导加载程序提供的信息来管理和分配系统启动时检测到的一系列可用物理内存框架:
@@ -207,3 +286,111 @@ unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
         frame
     }
 }
+
+// 分配 2MiB 大页帧：从当前位置向后找一段 2MiB 对齐且物理连续的 512 个可用帧
+// 跳过的零散帧不再使用，最多浪费不到 2MiB
+unsafe impl FrameAllocator<Size2MiB> for BootInfoFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size2MiB>> {
+        const FRAMES_PER_HUGE_PAGE: usize = 512;
+        let mut run_start: Option<(usize, u64)> = None;
+        let mut run_len = 0;
+        let mut expected = 0u64;
+        for (index, frame) in self.usable_frames().enumerate().skip(self.next) {
+            let addr = frame.start_address().as_u64();
+            if run_start.is_some() && addr == expected {
+                run_len += 1;
+            } else if addr % Size2MiB::SIZE == 0 {
+                run_start = Some((index, addr));
+                run_len = 1;
+            } else {
+                run_start = None;
+                run_len = 0;
+            }
+            expected = addr + Size4KiB::SIZE;
+
+            if run_len == FRAMES_PER_HUGE_PAGE {
+                let (start_index, start_addr) = run_start.unwrap();
+                self.next = start_index + FRAMES_PER_HUGE_PAGE;
+                return Some(PhysFrame::containing_address(PhysAddr::new(start_addr)));
+            }
+        }
+        None
+    }
+}
+
+/// 启动完成后接管的帧分配器，运行时（比如加载用户程序）从这里分配物理帧
+///
+/// 归还的帧放进空闲列表，之后优先分出去
+pub struct FramePool {
+    boot: BootInfoFrameAllocator,
+    free: Vec<PhysFrame>,
+}
+
+impl FramePool {
+    /// 分配 `count` 个物理连续的帧，返回第一个，给需要连续内存的设备（比如 virtio 的队列）用；
+    /// 释放时可以逐个归还
+    pub fn allocate_contiguous(&mut self, count: usize) -> Option<PhysFrame> {
+        self.allocate_aligned(count, Size4KiB::SIZE)
+    }
+
+    /// 同 allocate_contiguous，第一个帧的物理地址按 `align` 字节对齐，`align` 是页大小的整数倍
+    pub fn allocate_aligned(&mut self, count: usize, align: u64) -> Option<PhysFrame> {
+        if count <= 1 && align <= Size4KiB::SIZE {
+            return self.allocate_frame();
+        }
+        self.boot.allocate_contiguous(count.max(1), align)
+    }
+
+    /// 用量，见 report 模块
+    pub fn stats(&self) -> report::FrameStats {
+        let usable = self.boot.usable_frame_count();
+        let handed_out = self.boot.next.min(usable);
+        report::FrameStats { usable, allocated: handed_out - self.free.len().min(handed_out), recycled: self.free.len() }
+    }
+}
+
+unsafe impl FrameAllocator<Size4KiB> for FramePool {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
+        self.free.pop().or_else(|| self.boot.allocate_frame())
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for FramePool {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size4KiB>) {
+        self.free.push(frame);
+    }
+}
+
+static FRAMES: Mutex<Option<FramePool>> = Mutex::new(None);
+
+/// 启动过程用完帧分配器之后交给这里，需要在堆初始化之后调用
+pub fn install_frame_allocator(allocator: BootInfoFrameAllocator) {
+    *FRAMES.lock() = Some(FramePool { boot: allocator, free: Vec::new() });
+}
+
+/// 启动过程中用到帧分配器的单元都初始化完之后交出帧分配器，输出一次内存概况和地址空间布局，检查 W^X
+pub static UNIT: Unit = Unit {
+    name: "frame-pool",
+    depends: &["hpet", "smp", "usermode", "video"],
+    init: |context| {
+        install_frame_allocator(context.take_frames()?);
+        report::report().log();
+        layout::check_and_log();
+        wx::check();
+        Ok(())
+    },
+};
+
+/// 用运行时的帧分配器做一件事，还没有 install_frame_allocator 时返回 None
+pub fn with_frames<R>(f: impl FnOnce(&mut FramePool) -> R) -> Option<R> {
+    FRAMES.lock().as_mut().map(f)
+}
+
+/// 启动之后在当前页表里映射设备寄存器，给运行时初始化的驱动用，见 vmm::map_mmio，按寄存器布局映射用 mmio::map；
+/// 需要在 install_frame_allocator 之后、还没有进入用户进程的地址空间时调用
+pub fn map_mmio(phys_addr: PhysAddr, size: u64) -> Result<vmm::VirtualRegion, MapToError<Size4KiB>> {
+    let offset = *PHYSICAL_MEMORY_OFFSET.get().expect("Memory is not initialized yet");
+    let mut table = unsafe { OffsetPageTable::new(active_level_4_table(offset), offset) };
+    with_frames(|frames| vmm::map_mmio(&mut table, frames, phys_addr, size))
+        .unwrap_or(Err(MapToError::FrameAllocationFailed))
+}
@@ -0,0 +1,215 @@
+// 内存概况
+// 汇总 bootloader 给的内存映射（各类区域的个数和大小、总内存）、帧分配器的用量、堆的用量和当前页表的规模。
+// 启动完成时输出到日志，之后由 mem 命令或内存窗口按需查看。
+// 页表是边遍历边统计的，不加锁，别的 CPU 同时修改页表时数字只是近似值
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use spin::Once;
+use x86_64::structures::paging::{PageTable, PageTableFlags};
+use x86_64::VirtAddr;
+
+use crate::allocator::{heap_stats, HeapStats};
+use crate::io::format::{Size, Thousands};
+
+// BootInfoFrameAllocator::init 时记下
+static MEMORY_MAP: Once<&'static MemoryMap> = Once::new();
+
+pub(super) fn record_memory_map(memory_map: &'static MemoryMap) {
+    MEMORY_MAP.call_once(|| memory_map);
+}
+
+/// 内存映射里类型相同的一段连续区域，相邻的同类区域已经合并
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub start: u64,
+    pub end: u64,
+    pub region_type: MemoryRegionType,
+}
+
+impl Region {
+    pub fn size(&self) -> u64 {
+        self.end - self.start
+    }
+}
+
+/// 某一类区域的合计
+#[derive(Debug, Clone, Copy)]
+pub struct RegionSummary {
+    pub region_type: MemoryRegionType,
+    pub count: usize,
+    pub bytes: u64,
+}
+
+/// 帧分配器的用量
+#[derive(Debug, Clone, Copy)]
+pub struct FrameStats {
+    /// 1MiB 以上可以分配的帧
+    pub usable: usize,
+    /// 分出去还没归还的帧，包括分配连续帧时跳过的零散帧
+    pub allocated: usize,
+    /// 归还后等着再分出去的帧
+    pub recycled: usize,
+}
+
+/// 当前页表的规模
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PageTableStats {
+    /// 第 4 级到第 1 级页表各有几张
+    pub tables: [usize; 4],
+    /// 映射的 4KiB、2MiB、1GiB 页数
+    pub pages: [usize; 3],
+}
+
+impl PageTableStats {
+    /// 页表本身占用的内存
+    pub fn bytes(&self) -> u64 {
+        self.tables.iter().sum::<usize>() as u64 * 4096
+    }
+}
+
+/// 某一时刻的内存概况
+#[derive(Debug, Clone)]
+pub struct Report {
+    /// 按类型合计，按在内存映射里第一次出现的顺序
+    pub regions: Vec<RegionSummary>,
+    pub total_ram: u64,
+    pub usable: u64,
+    /// 启动代码还拿着帧分配器时为 None
+    pub frames: Option<FrameStats>,
+    pub heap: HeapStats,
+    /// 内存初始化之前为 None
+    pub page_tables: Option<PageTableStats>,
+}
+
+/// 区域类型的名字
+pub fn type_name(region_type: MemoryRegionType) -> &'static str {
+    match region_type {
+        MemoryRegionType::Usable => "usable",
+        MemoryRegionType::InUse => "in use",
+        MemoryRegionType::Reserved => "reserved",
+        MemoryRegionType::AcpiReclaimable => "ACPI reclaimable",
+        MemoryRegionType::AcpiNvs => "ACPI NVS",
+        MemoryRegionType::BadMemory => "bad",
+        MemoryRegionType::Kernel => "kernel",
+        MemoryRegionType::KernelStack => "kernel stack",
+        MemoryRegionType::PageTable => "page tables",
+        MemoryRegionType::Bootloader => "bootloader",
+        MemoryRegionType::FrameZero => "frame zero",
+        MemoryRegionType::Empty => "empty",
+        MemoryRegionType::BootInfo => "boot info",
+        MemoryRegionType::Package => "package",
+        _ => "other",
+    }
+}
+
+// 保留区、坏的内存和空洞不算在内存总量里
+fn is_ram(region_type: MemoryRegionType) -> bool {
+    !matches!(region_type, MemoryRegionType::Reserved | MemoryRegionType::BadMemory | MemoryRegionType::Empty)
+}
+
+/// 内存映射的区域，按地址排列；BootInfoFrameAllocator::init 之前没有
+pub fn regions() -> impl Iterator<Item = Region> {
+    let mut regions = MEMORY_MAP.get().into_iter().flat_map(|map| map.iter()).map(|region| Region {
+        start: region.range.start_addr(),
+        end: region.range.end_addr(),
+        region_type: region.region_type,
+    }).peekable();
+    core::iter::from_fn(move || {
+        let mut region = regions.next()?;
+        while let Some(next) = regions.next_if(|next| next.start == region.end && next.region_type == region.region_type) {
+            region.end = next.end;
+        }
+        Some(region)
+    })
+}
+
+/// 统计当前的内存概况，需要在堆初始化之后调用
+pub fn report() -> Report {
+    let mut summaries: Vec<RegionSummary> = Vec::new();
+    for region in regions() {
+        match summaries.iter_mut().find(|summary| summary.region_type == region.region_type) {
+            Some(summary) => {
+                summary.count += 1;
+                summary.bytes += region.size();
+            }
+            None => summaries.push(RegionSummary { region_type: region.region_type, count: 1, bytes: region.size() }),
+        }
+    }
+    let total_ram = summaries.iter().filter(|summary| is_ram(summary.region_type)).map(|summary| summary.bytes).sum();
+    let usable = summaries.iter()
+        .filter(|summary| summary.region_type == MemoryRegionType::Usable)
+        .map(|summary| summary.bytes)
+        .sum();
+    Report {
+        regions: summaries,
+        total_ram,
+        usable,
+        frames: super::with_frames(|frames| frames.stats()),
+        heap: heap_stats(),
+        page_tables: page_tables(),
+    }
+}
+
+/// 遍历当前页表，统计各级页表的张数和映射的页数
+pub fn page_tables() -> Option<PageTableStats> {
+    let offset = *super::PHYSICAL_MEMORY_OFFSET.get()?;
+    let mut stats = PageTableStats::default();
+    walk(unsafe { super::active_level_4_table(offset) }, 4, offset, &mut stats);
+    Some(stats)
+}
+
+fn walk(table: &PageTable, level: usize, offset: VirtAddr, stats: &mut PageTableStats) {
+    stats.tables[4 - level] += 1;
+    for entry in table.iter().filter(|entry| entry.flags().contains(PageTableFlags::PRESENT)) {
+        if level == 1 {
+            stats.pages[0] += 1;
+        } else if level <= 3 && entry.flags().contains(PageTableFlags::HUGE_PAGE) {
+            // 第 2 级的大页是 2MiB，第 3 级的是 1GiB
+            stats.pages[level - 1] += 1;
+        } else {
+            let next = unsafe { &*(offset + entry.addr().as_u64()).as_ptr::<PageTable>() };
+            walk(next, level - 1, offset, stats);
+        }
+    }
+}
+
+impl Report {
+    /// 每行输出一条 info 日志，启动完成时调用
+    pub fn log(&self) {
+        let text = alloc::format!("{}", self);
+        for line in text.lines() {
+            log::info!("{}", line);
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "RAM: {} total, {} usable", Size(self.total_ram), Size(self.usable))?;
+        for summary in &self.regions {
+            writeln!(f, "  {:<16} {:>3} regions {:>10}", type_name(summary.region_type), summary.count,
+                     Size(summary.bytes))?;
+        }
+        match self.frames {
+            Some(frames) => writeln!(f, "frames: {} of {} allocated ({}), {} recycled",
+                                     Thousands(frames.allocated as u64), Thousands(frames.usable as u64),
+                                     Size(frames.allocated as u64 * 4096), Thousands(frames.recycled as u64))?,
+            None => writeln!(f, "frames: still owned by the boot code")?,
+        }
+        writeln!(f, "heap: {} total, {} used, {} free in {} regions, largest {} ({}% fragmented)",
+                 Size(self.heap.size as u64), Size((self.heap.size - self.heap.free) as u64),
+                 Size(self.heap.free as u64), Thousands(self.heap.free_regions as u64),
+                 Size(self.heap.largest_free as u64), self.heap.fragmentation())?;
+        if let Some(tables) = self.page_tables {
+            let [l4, l3, l2, l1] = tables.tables;
+            let [small, large, huge] = tables.pages;
+            writeln!(f, "page tables: {}/{}/{}/{} (L4-L1, {}) mapping {} 4KiB, {} 2MiB and {} 1GiB pages",
+                     l4, l3, l2, Thousands(l1 as u64), Size(tables.bytes()),
+                     Thousands(small as u64), Thousands(large as u64), huge)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,157 @@
+// 内核栈管理
+// - IST 栈：双重错误(double fault)和 NMI 使用独立的栈，栈底留一页保护页(guard page)
+// - 特权级栈：用户程序陷入内核（系统调用、中断、异常）时 CPU 切换到 TSS 里的这个栈，同样有保护页
+// - 内核线程栈：在专用的虚拟地址区间中分配，每个栈下方都有一页不映射的保护页
+// 栈溢出时会访问到保护页从而触发页错误，再由双重错误处理函数在干净的 IST 栈上给出诊断信息，
+// 而不是悄无声息地三重错误(triple fault)重启
+
+use core::ptr::addr_of;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use x86_64::structures::paging::{
+    mapper::MapToError, FrameAllocator, Mapper, Page, PageTableFlags, Size4KiB,
+};
+use x86_64::VirtAddr;
+
+use crate::memory::{layout, wx};
+use crate::memory::vmm::map_fixed;
+
+const PAGE_SIZE: u64 = 4096;
+
+// 配置区域
+// IST 栈可用页数（不含保护页）
+pub const IST_STACK_PAGES: usize = 5;
+// 特权级栈可用页数（不含保护页），系统调用里会画窗口、输出文字，比 IST 栈大一些
+pub const PRIVILEGE_STACK_PAGES: usize = 16;
+// 内核线程栈区域的起始虚拟地址和大小，在堆之后，见 memory::layout
+pub const KERNEL_STACKS_START: u64 = layout::KERNEL_STACKS.start;
+pub const KERNEL_STACKS_SIZE: u64 = layout::KERNEL_STACKS.size;
+// 每个内核线程栈可用页数（不含保护页）
+pub const KERNEL_STACK_PAGES: u64 = 16;
+// 每个栈槽位 = 一页保护页 + 栈本身
+const KERNEL_STACK_SLOT: u64 = (KERNEL_STACK_PAGES + 1) * PAGE_SIZE;
+
+// 按页对齐的静态 IST 栈，第一页作为保护页，在分页初始化后被取消映射
+#[repr(C, align(4096))]
+struct IstStack([u8; PAGE_SIZE as usize * (IST_STACK_PAGES + 1)]);
+
+static mut DOUBLE_FAULT_STACK: IstStack = IstStack([0; PAGE_SIZE as usize * (IST_STACK_PAGES + 1)]);
+static mut NMI_STACK: IstStack = IstStack([0; PAGE_SIZE as usize * (IST_STACK_PAGES + 1)]);
+
+#[repr(C, align(4096))]
+struct PrivilegeStack([u8; PAGE_SIZE as usize * (PRIVILEGE_STACK_PAGES + 1)]);
+
+static mut PRIVILEGE_STACK: PrivilegeStack = PrivilegeStack([0; PAGE_SIZE as usize * (PRIVILEGE_STACK_PAGES + 1)]);
+
+// 下一个可用的内核线程栈槽位
+static NEXT_KERNEL_STACK: AtomicU64 = AtomicU64::new(KERNEL_STACKS_START);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IstKind {
+    DoubleFault,
+    Nmi,
+}
+
+impl IstKind {
+    fn stack_start(self) -> VirtAddr {
+        let ptr = match self {
+            IstKind::DoubleFault => unsafe { addr_of!(DOUBLE_FAULT_STACK) },
+            IstKind::Nmi => unsafe { addr_of!(NMI_STACK) },
+        };
+        VirtAddr::from_ptr(ptr)
+    }
+
+    fn guard_page(self) -> Page<Size4KiB> {
+        Page::containing_address(self.stack_start())
+    }
+}
+
+/// 栈的地址范围，`end` 为栈顶（栈向下增长）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackBounds {
+    start: VirtAddr,
+    end: VirtAddr,
+}
+
+impl StackBounds {
+    pub fn start(&self) -> VirtAddr {
+        self.start
+    }
+
+    pub fn end(&self) -> VirtAddr {
+        self.end
+    }
+
+    pub fn contains(&self, addr: VirtAddr) -> bool {
+        self.start <= addr && addr < self.end
+    }
+}
+
+/// 返回 IST 栈的栈顶地址，供 TSS 使用
+pub fn ist_stack_top(kind: IstKind) -> VirtAddr {
+    kind.stack_start() + PAGE_SIZE * (IST_STACK_PAGES as u64 + 1)
+}
+
+/// 返回特权级栈的栈顶地址，供 TSS 的 privilege_stack_table[0] 使用
+pub fn privilege_stack_top() -> VirtAddr {
+    privilege_stack_start() + PAGE_SIZE * (PRIVILEGE_STACK_PAGES as u64 + 1)
+}
+
+fn privilege_stack_start() -> VirtAddr {
+    VirtAddr::from_ptr(unsafe { addr_of!(PRIVILEGE_STACK) })
+}
+
+/// 取消 IST 栈和特权级栈保护页的映射
+///
+/// 必须在分页初始化之后调用，之前 IST 栈照常可用，只是没有保护页
+pub fn init_guard_pages(mapper: &mut impl Mapper<Size4KiB>) {
+    for kind in [IstKind::DoubleFault, IstKind::Nmi] {
+        match mapper.unmap(kind.guard_page()) {
+            Ok((_, flush)) => flush.flush(),
+            Err(error) => {
+                log::warn!("Unmap guard page of {:?} stack failed: {:?}", kind, error);
+            }
+        }
+    }
+    match mapper.unmap(Page::<Size4KiB>::containing_address(privilege_stack_start())) {
+        Ok((_, flush)) => flush.flush(),
+        Err(error) => log::warn!("Unmap guard page of privilege stack failed: {:?}", error),
+    }
+}
+
+/// 为内核线程分配一个栈，栈下方保留一页不映射的保护页
+pub fn alloc_kernel_stack(
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<StackBounds, MapToError<Size4KiB>> {
+    let slot = NEXT_KERNEL_STACK.fetch_add(KERNEL_STACK_SLOT, Ordering::Relaxed);
+    if slot + KERNEL_STACK_SLOT > KERNEL_STACKS_START + KERNEL_STACKS_SIZE {
+        // 栈区域耗尽，没有更贴切的错误类型，按分配失败处理
+        return Err(MapToError::FrameAllocationFailed);
+    }
+
+    // 跳过保护页
+    let start = VirtAddr::new(slot + PAGE_SIZE);
+    let end = start + KERNEL_STACK_PAGES * PAGE_SIZE;
+    let flags = wx::data_flags(PageTableFlags::PRESENT | PageTableFlags::WRITABLE);
+    map_fixed(mapper, frame_allocator, start, end - start, flags)?;
+
+    Ok(StackBounds { start, end })
+}
+
+/// 判断地址是否落在某个栈的保护页中，用于在异常处理函数中诊断栈溢出
+pub fn is_guard_page(addr: VirtAddr) -> bool {
+    for kind in [IstKind::DoubleFault, IstKind::Nmi] {
+        if Page::<Size4KiB>::containing_address(addr) == kind.guard_page() {
+            return true;
+        }
+    }
+    if Page::<Size4KiB>::containing_address(addr) == Page::containing_address(privilege_stack_start()) {
+        return true;
+    }
+
+    let addr = addr.as_u64();
+    addr >= KERNEL_STACKS_START
+        && addr < KERNEL_STACKS_START + KERNEL_STACKS_SIZE
+        && (addr - KERNEL_STACKS_START) % KERNEL_STACK_SLOT < PAGE_SIZE
+}
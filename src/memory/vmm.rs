@@ -0,0 +1,275 @@
+// 内核虚拟内存管理
+// 统一管理内核中动态映射的虚拟地址区间，驱动不再需要硬编码虚拟地址：
+// - map_region：分配一段虚拟地址并映射到新分配的物理帧
+// - map_mmio：把设备的物理地址（如显存、寄存器）映射到一段虚拟地址，对齐允许时使用 2MiB 大页，不可执行
+// - map_fixed：映射调用者指定的虚拟地址区间（如堆、内核栈）
+// - map_fixed_huge：同上，但在对齐允许时使用 2MiB 大页，减少 TLB 压力和映射耗时
+// - unmap_region / remap：取消映射、修改页属性
+
+use spin::Mutex;
+use x86_64::structures::paging::mapper::{FlagUpdateError, MapToError, UnmapError};
+use x86_64::structures::paging::{
+    FrameAllocator, Mapper, Page, PageRange, PageSize, PageTableFlags, PhysFrame,
+    Size2MiB, Size4KiB,
+};
+use x86_64::{PhysAddr, VirtAddr};
+
+use crate::memory::{layout, wx};
+
+const PAGE_SIZE: u64 = 4096;
+
+// 配置区域
+// 动态映射区域：从 3 GiB 开始，到堆的起始地址为止，见 memory::layout
+pub const VMM_START: u64 = layout::VMM.start;
+pub const VMM_END: u64 = layout::VMM.end();
+
+/// 一段已映射的虚拟地址区间
+///
+/// `start` 不一定按页对齐（MMIO 映射时会保留物理地址的页内偏移）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VirtualRegion {
+    start: VirtAddr,
+    size: u64,
+}
+
+impl VirtualRegion {
+    pub fn start(&self) -> VirtAddr {
+        self.start
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn end(&self) -> VirtAddr {
+        self.start + self.size
+    }
+
+    /// 区间覆盖的所有 4KiB 页，大小为 0 时为空
+    pub fn pages(&self) -> PageRange<Size4KiB> {
+        let start_page = Page::containing_address(self.start);
+        if self.size == 0 {
+            return Page::range(start_page, start_page);
+        }
+        let end_page = Page::containing_address(self.start + self.size - 1u64);
+        Page::range(start_page, end_page + 1)
+    }
+}
+
+/// 虚拟地址区间分配器
+///
+/// 只向前分配，取消映射后虚拟地址不回收；内核地址空间足够大，目前不成问题
+pub struct VirtualRegionAllocator {
+    next: u64,
+    end: u64,
+}
+
+impl VirtualRegionAllocator {
+    pub const fn new(start: u64, end: u64) -> Self {
+        VirtualRegionAllocator { next: start, end }
+    }
+
+    /// 保留一段按页对齐、大小至少为 `size` 的虚拟地址区间
+    pub fn reserve(&mut self, size: u64) -> Option<VirtualRegion> {
+        self.reserve_aligned(size, PAGE_SIZE)
+    }
+
+    /// 保留一段起始地址按 `align` 对齐的虚拟地址区间，`align` 必须是页大小的整数倍
+    pub fn reserve_aligned(&mut self, size: u64, align: u64) -> Option<VirtualRegion> {
+        let size = (size + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+        let start = self.next.checked_add(align - 1)? & !(align - 1);
+        let end = start.checked_add(size)?;
+        if size == 0 || end > self.end {
+            return None;
+        }
+        self.next = end;
+        Some(VirtualRegion { start: VirtAddr::new(start), size })
+    }
+}
+
+pub static KERNEL_VMM: Mutex<VirtualRegionAllocator> =
+    Mutex::new(VirtualRegionAllocator::new(VMM_START, VMM_END));
+
+/// 映射调用者指定的虚拟地址区间，每页分配新的物理帧
+pub fn map_fixed(
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+    start: VirtAddr,
+    size: u64,
+    flags: PageTableFlags,
+) -> Result<VirtualRegion, MapToError<Size4KiB>> {
+    let region = VirtualRegion { start, size };
+    for page in region.pages() {
+        let frame = frame_allocator
+            .allocate_frame()
+            .ok_or(MapToError::FrameAllocationFailed)?;
+        unsafe { mapper.map_to(page, frame, flags, frame_allocator)?.flush() };
+    }
+    Ok(region)
+}
+
+/// 映射调用者指定的虚拟地址区间，2MiB 对齐的部分尽量使用大页
+///
+/// 分配不到连续的 2MiB 物理帧时退回到 4KiB 页
+pub fn map_fixed_huge<M, A>(
+    mapper: &mut M,
+    frame_allocator: &mut A,
+    start: VirtAddr,
+    size: u64,
+    flags: PageTableFlags,
+) -> Result<VirtualRegion, MapToError<Size4KiB>>
+where
+    M: Mapper<Size4KiB> + Mapper<Size2MiB>,
+    A: FrameAllocator<Size4KiB> + FrameAllocator<Size2MiB>,
+{
+    let region = VirtualRegion { start, size };
+    let mut addr = start.align_down(PAGE_SIZE).as_u64();
+    let end = region.end().as_u64();
+    while addr < end {
+        if addr % Size2MiB::SIZE == 0 && end - addr >= Size2MiB::SIZE {
+            if let Some(frame) = FrameAllocator::<Size2MiB>::allocate_frame(frame_allocator) {
+                let page = Page::<Size2MiB>::containing_address(VirtAddr::new(addr));
+                unsafe { Mapper::<Size2MiB>::map_to(mapper, page, frame, flags, frame_allocator) }
+                    .map_err(huge_map_error)?
+                    .flush();
+                addr += Size2MiB::SIZE;
+                continue;
+            }
+        }
+        let frame = FrameAllocator::<Size4KiB>::allocate_frame(frame_allocator)
+            .ok_or(MapToError::FrameAllocationFailed)?;
+        let page = Page::<Size4KiB>::containing_address(VirtAddr::new(addr));
+        unsafe { Mapper::<Size4KiB>::map_to(mapper, page, frame, flags, frame_allocator)?.flush() };
+        addr += Size4KiB::SIZE;
+    }
+    Ok(region)
+}
+
+// 把大页映射的错误转换成 4KiB 的错误类型，方便调用者统一处理
+fn huge_map_error(error: MapToError<Size2MiB>) -> MapToError<Size4KiB> {
+    match error {
+        MapToError::FrameAllocationFailed => MapToError::FrameAllocationFailed,
+        MapToError::ParentEntryHugePage => MapToError::ParentEntryHugePage,
+        MapToError::PageAlreadyMapped(frame) => {
+            MapToError::PageAlreadyMapped(PhysFrame::containing_address(frame.start_address()))
+        }
+    }
+}
+
+/// 分配一段虚拟地址并映射到新分配的物理帧
+pub fn map_region(
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+    size: u64,
+    flags: PageTableFlags,
+) -> Result<VirtualRegion, MapToError<Size4KiB>> {
+    // 虚拟地址耗尽，没有更贴切的错误类型，按分配失败处理
+    let region = KERNEL_VMM.lock().reserve(size).ok_or(MapToError::FrameAllocationFailed)?;
+    map_fixed(mapper, frame_allocator, region.start(), region.size(), flags)
+}
+
+/// 把一段设备物理地址映射到内核虚拟地址空间，返回的区间保留物理地址的页内偏移
+///
+/// 映射带有 NO_CACHE 属性，适合显存和设备寄存器。区间不小于 2MiB 时，虚拟地址与物理地址
+/// 按 2MiB 同余对齐，其中完整落在区间内的 2MiB 块使用大页映射
+pub fn map_mmio<M>(
+    mapper: &mut M,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+    phys_addr: PhysAddr,
+    size: u64,
+) -> Result<VirtualRegion, MapToError<Size4KiB>>
+where
+    M: Mapper<Size4KiB> + Mapper<Size2MiB>,
+{
+    let huge_offset = phys_addr.as_u64() & (Size2MiB::SIZE - 1);
+    let page_offset = phys_addr.as_u64() & (PAGE_SIZE - 1);
+    let reserved = if size >= Size2MiB::SIZE {
+        // 多保留一段，使虚拟地址和物理地址在 2MiB 内的偏移一致
+        let reserved = KERNEL_VMM
+            .lock()
+            .reserve_aligned(huge_offset + size, Size2MiB::SIZE)
+            .ok_or(MapToError::FrameAllocationFailed)?;
+        VirtualRegion { start: reserved.start() + (huge_offset - page_offset), size: page_offset + size }
+    } else {
+        KERNEL_VMM
+            .lock()
+            .reserve(page_offset + size)
+            .ok_or(MapToError::FrameAllocationFailed)?
+    };
+
+    let flags = wx::data_flags(PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE);
+    let mut virt = reserved.start().as_u64();
+    let mut phys = phys_addr.as_u64() - page_offset;
+    let end = reserved.end().as_u64();
+    while virt < end {
+        if virt % Size2MiB::SIZE == 0 && phys % Size2MiB::SIZE == 0 && end - virt >= Size2MiB::SIZE {
+            let page = Page::<Size2MiB>::containing_address(VirtAddr::new(virt));
+            let frame = PhysFrame::<Size2MiB>::containing_address(PhysAddr::new(phys));
+            unsafe { Mapper::<Size2MiB>::map_to(mapper, page, frame, flags, frame_allocator) }
+                .map_err(huge_map_error)?
+                .flush();
+            virt += Size2MiB::SIZE;
+            phys += Size2MiB::SIZE;
+        } else {
+            let page = Page::<Size4KiB>::containing_address(VirtAddr::new(virt));
+            let frame = PhysFrame::<Size4KiB>::containing_address(PhysAddr::new(phys));
+            unsafe { Mapper::<Size4KiB>::map_to(mapper, page, frame, flags, frame_allocator)?.flush() };
+            virt += Size4KiB::SIZE;
+            phys += Size4KiB::SIZE;
+        }
+    }
+    Ok(VirtualRegion { start: reserved.start() + page_offset, size })
+}
+
+/// 取消一段区间的映射，大页映射的部分按 2MiB 整页取消
+///
+/// 物理帧不会归还：BootInfoFrameAllocator 目前不支持回收
+pub fn unmap_region<M>(mapper: &mut M, region: VirtualRegion) -> Result<(), UnmapError>
+where
+    M: Mapper<Size4KiB> + Mapper<Size2MiB>,
+{
+    let mut addr = region.start().align_down(PAGE_SIZE).as_u64();
+    let end = region.end().as_u64();
+    while addr < end {
+        let page = Page::<Size4KiB>::containing_address(VirtAddr::new(addr));
+        match Mapper::<Size4KiB>::unmap(mapper, page) {
+            Ok((_, flush)) => {
+                flush.flush();
+                addr += Size4KiB::SIZE;
+            }
+            Err(UnmapError::ParentEntryHugePage) => {
+                let page = Page::<Size2MiB>::containing_address(VirtAddr::new(addr));
+                let (_, flush) = Mapper::<Size2MiB>::unmap(mapper, page)?;
+                flush.flush();
+                addr = page.start_address().as_u64() + Size2MiB::SIZE;
+            }
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(())
+}
+
+/// 修改一段区间的页属性（如改为只读、不可执行），大页映射的部分按 2MiB 整页修改
+pub fn remap<M>(mapper: &mut M, region: VirtualRegion, flags: PageTableFlags) -> Result<(), FlagUpdateError>
+where
+    M: Mapper<Size4KiB> + Mapper<Size2MiB>,
+{
+    let mut addr = region.start().align_down(PAGE_SIZE).as_u64();
+    let end = region.end().as_u64();
+    while addr < end {
+        let page = Page::<Size4KiB>::containing_address(VirtAddr::new(addr));
+        match unsafe { Mapper::<Size4KiB>::update_flags(mapper, page, flags) } {
+            Ok(flush) => {
+                flush.flush();
+                addr += Size4KiB::SIZE;
+            }
+            Err(FlagUpdateError::ParentEntryHugePage) => {
+                let page = Page::<Size2MiB>::containing_address(VirtAddr::new(addr));
+                unsafe { Mapper::<Size2MiB>::update_flags(mapper, page, flags)? }.flush();
+                addr = page.start_address().as_u64() + Size2MiB::SIZE;
+            }
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,115 @@
+// W^X：可写的页不可执行，可执行的页不可写
+// cpu::enable 在每个 CPU 上打开 EFER.NXE，之后页表里的 NO_EXECUTE 才有效。内核自己建的数据映射（堆、内核栈、
+// 动态映射区、设备寄存器和显存、用户程序的栈）都用 data_flags 加上 NO_EXECUTE；内置用户程序的代码区只读，内容通过物理内存的整体映射写进去。
+// bootloader 按 ELF 段的属性映射内核映像，代码只读、数据不可执行，但物理内存的整体映射、它自己的栈和恒等映射的低端内存
+// 都是可写可执行的，harden 启动完成时给内核映像之外所有可写可执行的页补上 NO_EXECUTE。
+// check 再遍历一遍整个地址空间，还剩下可写可执行的页就报出来，启动参数 wx=panic 时直接 panic，默认只警告。
+// AP 的启动代码页要在实模式下执行又要写入参数，是唯一的例外，AP 都启动之后它就取消映射了
+
+use alloc::vec::Vec;
+
+use x86_64::instructions::tlb;
+use x86_64::registers::model_specific::{Efer, EferFlags};
+use x86_64::structures::paging::{PageTable, PageTableFlags};
+use x86_64::VirtAddr;
+
+use crate::config;
+use crate::memory::debug::{self, Mapping};
+use crate::memory::layout;
+
+const PAGE_SIZE: u64 = 4096;
+
+/// 当前 CPU 打开了 NXE，NO_EXECUTE 才能用；没打开时它是保留位，设置了会触发页错误
+pub fn enabled() -> bool {
+    Efer::read().contains(EferFlags::NO_EXECUTE_ENABLE)
+}
+
+/// 数据映射用的标志位：能用时加上 NO_EXECUTE
+pub fn data_flags(flags: PageTableFlags) -> PageTableFlags {
+    if enabled() {
+        flags | PageTableFlags::NO_EXECUTE
+    } else {
+        flags
+    }
+}
+
+fn is_writable_executable(flags: PageTableFlags) -> bool {
+    flags.contains(PageTableFlags::WRITABLE) && !flags.contains(PageTableFlags::NO_EXECUTE)
+}
+
+/// 给内核映像之外所有可写可执行的页加上 NO_EXECUTE，返回改了多少个表项；没打开 NXE 时什么都不做
+///
+/// 只刷新当前 CPU 的 TLB，别的 CPU 上留着的旧表项只是暂时少一层保护
+pub fn harden() -> usize {
+    let Some(&offset) = super::PHYSICAL_MEMORY_OFFSET.get() else { return 0 };
+    if !enabled() {
+        return 0;
+    }
+    let table = unsafe { super::active_level_4_table(offset) };
+    let changed = harden_table(table, 4, 0, debug::TOP_FLAGS, offset);
+    if changed > 0 {
+        tlb::flush_all();
+    }
+    changed
+}
+
+fn harden_table(table: &mut PageTable, level: u32, base: u64, inherited: PageTableFlags, offset: VirtAddr) -> usize {
+    let span = PAGE_SIZE << (9 * (level - 1));
+    let mut changed = 0;
+    for (i, entry) in table.iter_mut().enumerate() {
+        let mut start = base + i as u64 * span;
+        if level == 4 && i >= 256 {
+            start |= 0xFFFF_0000_0000_0000;
+        }
+        let flags = debug::effective(inherited, entry.flags());
+        if !flags.contains(PageTableFlags::PRESENT) || !is_writable_executable(flags) {
+            continue;
+        }
+        if level == 1 || flags.contains(PageTableFlags::HUGE_PAGE) {
+            // 内核映像里可写可执行说明链接出了问题，改了可能让内核没法运行，留给 check 报告
+            if !layout::KERNEL_IMAGE.contains(start) {
+                entry.set_flags(entry.flags() | PageTableFlags::NO_EXECUTE);
+                changed += 1;
+            }
+        } else {
+            let next = unsafe { &mut *(offset + entry.addr().as_u64()).as_mut_ptr::<PageTable>() };
+            changed += harden_table(next, level - 1, start, flags, offset);
+        }
+    }
+    changed
+}
+
+/// 可写又可执行的映射，相邻的合并；没打开 NXE 时所有可写的页都算
+pub fn violations() -> Vec<Mapping> {
+    let mut found: Vec<Mapping> = Vec::new();
+    debug::for_each_page(0..u64::MAX, |page| {
+        if !is_writable_executable(page.flags) {
+            return;
+        }
+        match found.last_mut() {
+            Some(last) if last.virt.as_u64() + last.size == page.virt.as_u64() => last.size += page.size,
+            _ => found.push(page),
+        }
+    });
+    found
+}
+
+/// 补上 NO_EXECUTE 之后检查整个地址空间，启动完成时调用
+pub fn check() {
+    if !enabled() {
+        log::warn!("W^X: NX is not supported, writable pages stay executable");
+        return;
+    }
+    let changed = harden();
+    if changed > 0 {
+        log::info!("W^X: marked {} writable page table entries no-execute", changed);
+    }
+    let violations = violations();
+    for mapping in &violations {
+        let start = mapping.virt.as_u64();
+        log::warn!("W^X: {:#018x}-{:#018x} is writable and executable", start, start + (mapping.size - 1));
+    }
+    if !violations.is_empty() && config::wx_panic() {
+        panic!("W^X: {} writable and executable mappings", violations.len());
+    }
+}
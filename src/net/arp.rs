@@ -0,0 +1,89 @@
+// ARP：IPv4 地址到 MAC 地址的解析
+// 缓存收到的所有 ARP 包里发送方的地址，过一段时间作废；同一个地址的请求至少间隔一秒
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use crate::net::ethernet::MacAddr;
+use crate::net::ipv4::Ipv4Addr;
+
+pub const REQUEST: u16 = 1;
+pub const REPLY: u16 = 2;
+const PACKET_LEN: usize = 28;
+const ENTRY_LIFETIME: Duration = Duration::from_secs(300);
+const REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct Packet {
+    pub operation: u16,
+    pub sender_mac: MacAddr,
+    pub sender_ip: Ipv4Addr,
+    pub target_mac: MacAddr,
+    pub target_ip: Ipv4Addr,
+}
+
+impl Packet {
+    /// 只接受以太网和 IPv4 的 ARP 包
+    pub fn parse(payload: &[u8]) -> Option<Self> {
+        if payload.len() < PACKET_LEN || payload[0..6] != [0, 1, 8, 0, 6, 4] {
+            return None;
+        }
+        Some(Packet {
+            operation: u16::from_be_bytes([payload[6], payload[7]]),
+            sender_mac: MacAddr(payload[8..14].try_into().unwrap()),
+            sender_ip: Ipv4Addr(payload[14..18].try_into().unwrap()),
+            target_mac: MacAddr(payload[18..24].try_into().unwrap()),
+            target_ip: Ipv4Addr(payload[24..28].try_into().unwrap()),
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(PACKET_LEN);
+        bytes.extend_from_slice(&[0, 1, 8, 0, 6, 4]);
+        bytes.extend_from_slice(&self.operation.to_be_bytes());
+        bytes.extend_from_slice(&self.sender_mac.0);
+        bytes.extend_from_slice(&self.sender_ip.0);
+        bytes.extend_from_slice(&self.target_mac.0);
+        bytes.extend_from_slice(&self.target_ip.0);
+        bytes
+    }
+}
+
+#[derive(Default)]
+pub struct Cache {
+    // 地址和记下的时间
+    entries: BTreeMap<Ipv4Addr, (MacAddr, Duration)>,
+    requested: BTreeMap<Ipv4Addr, Duration>,
+}
+
+impl Cache {
+    pub fn lookup(&self, ip: Ipv4Addr, now: Duration) -> Option<MacAddr> {
+        self.entries.get(&ip)
+            .filter(|(_, learned)| now - *learned < ENTRY_LIFETIME)
+            .map(|(mac, _)| *mac)
+    }
+
+    pub fn insert(&mut self, ip: Ipv4Addr, mac: MacAddr, now: Duration) {
+        self.entries.insert(ip, (mac, now));
+        self.requested.remove(&ip);
+    }
+
+    /// 现在是否应该为 `ip` 发一个请求，是的话记下时间
+    pub fn should_request(&mut self, ip: Ipv4Addr, now: Duration) -> bool {
+        match self.requested.get(&ip) {
+            Some(&last) if now - last < REQUEST_INTERVAL => false,
+            _ => {
+                self.requested.insert(ip, now);
+                true
+            }
+        }
+    }
+
+    /// 没有过期的条目
+    pub fn entries(&self, now: Duration) -> Vec<(Ipv4Addr, MacAddr)> {
+        self.entries.iter()
+            .filter(|(_, (_, learned))| now - *learned < ENTRY_LIFETIME)
+            .map(|(ip, (mac, _))| (*ip, *mac))
+            .collect()
+    }
+}
@@ -0,0 +1,215 @@
+// DHCP 客户端
+// DISCOVER、OFFER、REQUEST、ACK 四步拿到地址、掩码、网关和 DNS 服务器，然后用 net::configure 生效。
+// 等待时每秒重发一次，超时后恢复原来的配置。不续约：QEMU 用户网络给的租约是一天，需要时用 ifconfig dhcp 重新获取
+
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use spin::Mutex;
+use x86_64::instructions::hlt;
+
+use crate::io::timer::uptime;
+use crate::net::ethernet::MacAddr;
+use crate::net::ipv4::Ipv4Addr;
+use crate::net::udp::UdpSocket;
+use crate::net::{self, Config, NetError};
+use crate::rand;
+
+const SERVER_PORT: u16 = 67;
+const CLIENT_PORT: u16 = 68;
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+// 固定部分的长度，选项从这里开始
+const OPTIONS_OFFSET: usize = 240;
+const RETRANSMIT: Duration = Duration::from_secs(1);
+
+// 消息类型
+const DISCOVER: u8 = 1;
+const OFFER: u8 = 2;
+const REQUEST: u8 = 3;
+const ACK: u8 = 5;
+const NAK: u8 = 6;
+
+// 选项
+const OPTION_NETMASK: u8 = 1;
+const OPTION_ROUTER: u8 = 3;
+const OPTION_DNS: u8 = 6;
+const OPTION_REQUESTED_ADDRESS: u8 = 50;
+const OPTION_LEASE_TIME: u8 = 51;
+const OPTION_MESSAGE_TYPE: u8 = 53;
+const OPTION_SERVER: u8 = 54;
+const OPTION_PARAMETERS: u8 = 55;
+const OPTION_END: u8 = 255;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Lease {
+    pub config: Config,
+    pub server: Ipv4Addr,
+    pub duration: Duration,
+    /// 拿到租约时的运行时间
+    pub obtained: Duration,
+}
+
+impl Lease {
+    /// 剩余的时间
+    pub fn remaining(&self) -> Duration {
+        (self.obtained + self.duration).saturating_sub(uptime())
+    }
+}
+
+static LEASE: Mutex<Option<Lease>> = Mutex::new(None);
+
+/// 当前的租约，地址是手动设置的时候为 None
+pub fn lease() -> Option<Lease> {
+    *LEASE.lock()
+}
+
+/// 手动设置地址之后丢掉租约
+pub fn forget() {
+    *LEASE.lock() = None;
+}
+
+/// 获取地址并生效，`timeout` 是整个过程的时间
+pub fn run(timeout: Duration) -> Result<Lease, NetError> {
+    let mac = net::mac().ok_or(NetError::NoDevice)?;
+    let previous = net::config().ok_or(NetError::NoDevice)?;
+    let socket = UdpSocket::bind(CLIENT_PORT)?;
+    // 地址清空之后才能从 0.0.0.0 广播
+    net::configure(Config::UNCONFIGURED);
+    let result = negotiate(&socket, mac, uptime() + timeout);
+    match result {
+        Ok(lease) => {
+            net::configure(lease.config);
+            *LEASE.lock() = Some(lease);
+        }
+        Err(_) => {
+            net::configure(previous);
+        }
+    }
+    result
+}
+
+fn negotiate(socket: &UdpSocket, mac: MacAddr, deadline: Duration) -> Result<Lease, NetError> {
+    let xid = rand::next_u32();
+    let offer = exchange(socket, &message(DISCOVER, xid, mac, None), xid, mac, OFFER, deadline)?;
+    let server = offer.server.ok_or(NetError::Refused)?;
+    let request = message(REQUEST, xid, mac, Some((offer.address, server)));
+    let ack = exchange(socket, &request, xid, mac, ACK, deadline)?;
+    Ok(Lease {
+        config: Config {
+            address: ack.address,
+            netmask: ack.netmask.unwrap_or_else(|| Ipv4Addr::netmask(24)),
+            gateway: ack.router.unwrap_or(Ipv4Addr::UNSPECIFIED),
+            dns: ack.dns.unwrap_or(Ipv4Addr::UNSPECIFIED),
+        },
+        server,
+        duration: Duration::from_secs(ack.lease_time.unwrap_or(86400) as u64),
+        obtained: uptime(),
+    })
+}
+
+// 广播 `message`，等到事务号对得上、类型是 `expected` 的回应为止，每秒重发；收到 NAK 时返回 Refused
+fn exchange(socket: &UdpSocket, message: &[u8], xid: u32, mac: MacAddr, expected: u8, deadline: Duration) -> Result<Reply, NetError> {
+    loop {
+        socket.send_to(message, Ipv4Addr::BROADCAST, SERVER_PORT)?;
+        let resend = uptime() + RETRANSMIT;
+        while uptime() < resend {
+            net::poll();
+            while let Some(datagram) = socket.recv_from() {
+                match Reply::parse(&datagram.data) {
+                    Some(reply) if reply.xid != xid || reply.mac != mac => {}
+                    Some(reply) if reply.kind == expected => return Ok(reply),
+                    Some(reply) if reply.kind == NAK => return Err(NetError::Refused),
+                    _ => {}
+                }
+            }
+            if uptime() >= deadline {
+                return Err(NetError::Timeout);
+            }
+            hlt();
+        }
+    }
+}
+
+// 客户端发出的消息；REQUEST 要带上选中的地址和服务器
+fn message(kind: u8, xid: u32, mac: MacAddr, request: Option<(Ipv4Addr, Ipv4Addr)>) -> Vec<u8> {
+    let mut message = alloc::vec![0u8; OPTIONS_OFFSET];
+    // BOOTREQUEST，以太网，地址长 6
+    message[0..3].copy_from_slice(&[1, 1, 6]);
+    message[4..8].copy_from_slice(&xid.to_be_bytes());
+    // 还没有地址，让服务器广播回应
+    message[10] = 0x80;
+    message[28..34].copy_from_slice(&mac.0);
+    message[236..240].copy_from_slice(&MAGIC_COOKIE);
+    message.extend_from_slice(&[OPTION_MESSAGE_TYPE, 1, kind]);
+    if let Some((address, server)) = request {
+        message.extend_from_slice(&[OPTION_REQUESTED_ADDRESS, 4]);
+        message.extend_from_slice(&address.0);
+        message.extend_from_slice(&[OPTION_SERVER, 4]);
+        message.extend_from_slice(&server.0);
+    }
+    message.extend_from_slice(&[OPTION_PARAMETERS, 4, OPTION_NETMASK, OPTION_ROUTER, OPTION_DNS, OPTION_LEASE_TIME]);
+    message.push(OPTION_END);
+    // 有的服务器不接受短于 BOOTP 最小长度的消息
+    message.resize(message.len().max(300), 0);
+    message
+}
+
+// 服务器的回应里用到的部分
+struct Reply {
+    kind: u8,
+    xid: u32,
+    mac: MacAddr,
+    address: Ipv4Addr,
+    netmask: Option<Ipv4Addr>,
+    router: Option<Ipv4Addr>,
+    dns: Option<Ipv4Addr>,
+    server: Option<Ipv4Addr>,
+    lease_time: Option<u32>,
+}
+
+impl Reply {
+    fn parse(data: &[u8]) -> Option<Self> {
+        // BOOTREPLY
+        if data.len() < OPTIONS_OFFSET || data[0] != 2 || data[236..240] != MAGIC_COOKIE {
+            return None;
+        }
+        let mut reply = Reply {
+            kind: 0,
+            xid: u32::from_be_bytes(data[4..8].try_into().unwrap()),
+            mac: MacAddr(data[28..34].try_into().unwrap()),
+            address: Ipv4Addr(data[16..20].try_into().unwrap()),
+            netmask: None,
+            router: None,
+            dns: None,
+            server: None,
+            lease_time: None,
+        };
+        let mut options = &data[OPTIONS_OFFSET..];
+        while let &[code, ref rest @ ..] = options {
+            // 0 是填充
+            match code {
+                0 => {
+                    options = rest;
+                    continue;
+                }
+                OPTION_END => break,
+                _ => {}
+            }
+            let (&len, rest) = rest.split_first()?;
+            let value = rest.get(..len as usize)?;
+            // 地址类的选项取第一个
+            let address = value.get(..4).map(|bytes| Ipv4Addr(bytes.try_into().unwrap()));
+            match code {
+                OPTION_MESSAGE_TYPE => reply.kind = *value.first()?,
+                OPTION_NETMASK => reply.netmask = address,
+                OPTION_ROUTER => reply.router = address,
+                OPTION_DNS => reply.dns = address,
+                OPTION_SERVER => reply.server = address,
+                OPTION_LEASE_TIME => reply.lease_time = value.get(..4).map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap())),
+                _ => {}
+            }
+            options = &rest[len as usize..];
+        }
+        (reply.kind != 0).then_some(reply)
+    }
+}
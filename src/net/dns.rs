@@ -0,0 +1,185 @@
+// DNS 解析
+// 向配置里的 DNS 服务器查询 A 记录，只取回答里的第一个 IPv4 地址（CNAME 的目标通常一起给出）。
+// 结果按 TTL 缓存；每秒重发一次查询，总共等 TIMEOUT
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::time::Duration;
+
+use spin::Mutex;
+use x86_64::instructions::hlt;
+
+use crate::io::timer::uptime;
+use crate::net::ipv4::Ipv4Addr;
+use crate::net::udp::UdpSocket;
+use crate::net::{self, NetError};
+use crate::rand;
+
+const SERVER_PORT: u16 = 53;
+const TIMEOUT: Duration = Duration::from_secs(3);
+const RETRANSMIT: Duration = Duration::from_secs(1);
+const HEADER_LEN: usize = 12;
+const TYPE_A: u16 = 1;
+const CLASS_IN: u16 = 1;
+const MAX_CACHED: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DnsError {
+    Net(NetError),
+    /// 名字不合法
+    InvalidName,
+    /// 没有配置 DNS 服务器
+    NoServer,
+    /// 名字不存在，或者没有 A 记录
+    NotFound,
+    /// 服务器出错或者回应无法解析
+    ServerFailure,
+}
+
+impl From<NetError> for DnsError {
+    fn from(error: NetError) -> Self {
+        DnsError::Net(error)
+    }
+}
+
+impl fmt::Display for DnsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DnsError::Net(error) => write!(f, "{}", error),
+            DnsError::InvalidName => f.write_str("invalid name"),
+            DnsError::NoServer => f.write_str("no DNS server"),
+            DnsError::NotFound => f.write_str("name not found"),
+            DnsError::ServerFailure => f.write_str("server failure"),
+        }
+    }
+}
+
+// 名字（小写）到地址和过期时间
+static CACHE: Mutex<BTreeMap<String, (Ipv4Addr, Duration)>> = Mutex::new(BTreeMap::new());
+
+/// 把名字解析成地址，点分十进制的地址直接返回
+pub fn resolve(name: &str) -> Result<Ipv4Addr, DnsError> {
+    if let Some(address) = Ipv4Addr::parse(name) {
+        return Ok(address);
+    }
+    let name = name.trim_end_matches('.').to_ascii_lowercase();
+    let now = uptime();
+    if let Some(&(address, expires)) = CACHE.lock().get(&name) {
+        if now < expires {
+            return Ok(address);
+        }
+    }
+    let server = net::config().ok_or(NetError::NoDevice)?.dns;
+    if server == Ipv4Addr::UNSPECIFIED {
+        return Err(DnsError::NoServer);
+    }
+    // 随机的 id 让伪造的回答更难对上
+    let id = rand::next_u32() as u16;
+    let query = query(id, &name)?;
+    let socket = UdpSocket::bind(0)?;
+    let deadline = now + TIMEOUT;
+    loop {
+        socket.send_to(&query, server, SERVER_PORT)?;
+        let resend = uptime() + RETRANSMIT;
+        while uptime() < resend {
+            net::poll();
+            while let Some(datagram) = socket.recv_from() {
+                if datagram.from != server || datagram.port != SERVER_PORT {
+                    continue;
+                }
+                if let Some(answer) = parse_response(id, &datagram.data) {
+                    let (address, ttl) = answer?;
+                    remember(name, address, uptime() + Duration::from_secs(ttl as u64));
+                    return Ok(address);
+                }
+            }
+            if uptime() >= deadline {
+                return Err(NetError::Timeout.into());
+            }
+            hlt();
+        }
+    }
+}
+
+fn remember(name: String, address: Ipv4Addr, expires: Duration) {
+    let mut cache = CACHE.lock();
+    if cache.len() >= MAX_CACHED {
+        let now = uptime();
+        cache.retain(|_, (_, expires)| *expires > now);
+        // 都没过期时丢掉最早过期的
+        if cache.len() >= MAX_CACHED {
+            let oldest = cache.iter().min_by_key(|(_, (_, expires))| *expires).map(|(name, _)| name.clone());
+            if let Some(oldest) = oldest {
+                cache.remove(&oldest);
+            }
+        }
+    }
+    cache.insert(name, (address, expires));
+}
+
+/// 查询 `name` 的 A 记录的报文，要求递归查询
+pub fn query(id: u16, name: &str) -> Result<Vec<u8>, DnsError> {
+    if name.is_empty() || name.len() > 253 {
+        return Err(DnsError::InvalidName);
+    }
+    let mut message = Vec::with_capacity(HEADER_LEN + name.len() + 6);
+    message.extend_from_slice(&id.to_be_bytes());
+    // RD，一个问题
+    message.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(DnsError::InvalidName);
+        }
+        message.push(label.len() as u8);
+        message.extend_from_slice(label.as_bytes());
+    }
+    message.push(0);
+    message.extend_from_slice(&TYPE_A.to_be_bytes());
+    message.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(message)
+}
+
+/// 解析回应：不是 `id` 的回应或者格式不对时返回 None，否则返回地址和 TTL（秒）或者错误
+pub fn parse_response(id: u16, message: &[u8]) -> Option<Result<(Ipv4Addr, u32), DnsError>> {
+    let word = |pos: usize| message.get(pos..pos + 2).map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]));
+    let flags = word(2)?;
+    // 必须是回应
+    if word(0)? != id || flags & 0x8000 == 0 {
+        return None;
+    }
+    match flags & 0xF {
+        0 => {}
+        3 => return Some(Err(DnsError::NotFound)),
+        _ => return Some(Err(DnsError::ServerFailure)),
+    }
+    let (questions, answers) = (word(4)?, word(6)?);
+    let mut pos = HEADER_LEN;
+    for _ in 0..questions {
+        pos = skip_name(message, pos)? + 4;
+    }
+    for _ in 0..answers {
+        pos = skip_name(message, pos)?;
+        let (kind, class, len) = (word(pos)?, word(pos + 2)?, word(pos + 8)? as usize);
+        let ttl = u32::from_be_bytes(message.get(pos + 4..pos + 8)?.try_into().unwrap());
+        let data = message.get(pos + 10..pos + 10 + len)?;
+        if kind == TYPE_A && class == CLASS_IN && len == 4 {
+            return Some(Ok((Ipv4Addr(data.try_into().unwrap()), ttl)));
+        }
+        pos += 10 + len;
+    }
+    Some(Err(DnsError::NotFound))
+}
+
+// 跳过一个名字，返回之后的位置；压缩的名字以指向别处的两字节指针结束
+fn skip_name(message: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *message.get(pos)?;
+        match len {
+            0 => return Some(pos + 1),
+            len if len & 0xC0 == 0xC0 => return Some(pos + 2),
+            len => pos += 1 + len as usize,
+        }
+    }
+}
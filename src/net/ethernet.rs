@@ -0,0 +1,54 @@
+// 以太网帧：目的地址、源地址、类型，后面是负载。不带 VLAN 标签，也不带 FCS（网卡负责）
+
+use alloc::vec::Vec;
+use core::fmt;
+
+pub const TYPE_IPV4: u16 = 0x0800;
+pub const TYPE_ARP: u16 = 0x0806;
+pub const HEADER_LEN: usize = 14;
+// 不含 FCS 的最短帧，短的用 0 补齐
+const MIN_LEN: usize = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MacAddr(pub [u8; 6]);
+
+impl MacAddr {
+    pub const BROADCAST: MacAddr = MacAddr([0xFF; 6]);
+    pub const ZERO: MacAddr = MacAddr([0; 6]);
+}
+
+impl fmt::Display for MacAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(f, "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}", a, b, c, d, e, g)
+    }
+}
+
+pub struct Header {
+    pub destination: MacAddr,
+    pub source: MacAddr,
+    pub ether_type: u16,
+}
+
+/// 拆出帧头和负载，太短时返回 None
+pub fn parse(frame: &[u8]) -> Option<(Header, &[u8])> {
+    if frame.len() < HEADER_LEN {
+        return None;
+    }
+    let header = Header {
+        destination: MacAddr(frame[0..6].try_into().unwrap()),
+        source: MacAddr(frame[6..12].try_into().unwrap()),
+        ether_type: u16::from_be_bytes([frame[12], frame[13]]),
+    };
+    Some((header, &frame[HEADER_LEN..]))
+}
+
+pub fn build(header: &Header, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity((HEADER_LEN + payload.len()).max(MIN_LEN));
+    frame.extend_from_slice(&header.destination.0);
+    frame.extend_from_slice(&header.source.0);
+    frame.extend_from_slice(&header.ether_type.to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame.resize(frame.len().max(MIN_LEN), 0);
+    frame
+}
@@ -0,0 +1,203 @@
+// HTTP 客户端
+// 只有 GET：解析 http:// 地址，用 DNS 找到服务器，TCP 连上后发 HTTP/1.1 请求（Connection: close），读到服务器关闭连接为止。
+// 支持 Content-Length 和 chunked 编码，不支持 https 和重定向
+
+use alloc::borrow::Cow;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::time::Duration;
+
+use crate::io::timer::uptime;
+use crate::net::dns::{self, DnsError};
+use crate::net::tcp::TcpStream;
+use crate::net::NetError;
+use crate::version::VERSION;
+
+const DEFAULT_PORT: u16 = 80;
+/// 响应体最大的长度，超过时放弃
+pub const MAX_BODY: usize = 1024 * 1024;
+// 响应头最大的长度
+const MAX_HEADER: usize = 16 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HttpError {
+    Net(NetError),
+    Dns(DnsError),
+    /// 地址不合法
+    InvalidUrl,
+    /// 不是 http://
+    UnsupportedScheme,
+    /// 响应无法解析或者不完整
+    BadResponse,
+    /// 响应超过 MAX_BODY
+    TooLarge,
+}
+
+impl From<NetError> for HttpError {
+    fn from(error: NetError) -> Self {
+        HttpError::Net(error)
+    }
+}
+
+impl From<DnsError> for HttpError {
+    fn from(error: DnsError) -> Self {
+        HttpError::Dns(error)
+    }
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HttpError::Net(error) => write!(f, "{}", error),
+            HttpError::Dns(error) => write!(f, "{}", error),
+            HttpError::InvalidUrl => f.write_str("invalid URL"),
+            HttpError::UnsupportedScheme => f.write_str("only http:// is supported"),
+            HttpError::BadResponse => f.write_str("malformed response"),
+            HttpError::TooLarge => f.write_str("response too large"),
+        }
+    }
+}
+
+/// 拆开的地址，没写协议时当作 http://
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Url<'a> {
+    pub host: &'a str,
+    pub port: u16,
+    /// 以 / 开头，包括查询字符串
+    pub path: &'a str,
+}
+
+impl<'a> Url<'a> {
+    pub fn parse(text: &'a str) -> Result<Self, HttpError> {
+        let rest = match text.split_once("://") {
+            Some((scheme, rest)) if scheme.eq_ignore_ascii_case("http") => rest,
+            Some(_) => return Err(HttpError::UnsupportedScheme),
+            None => text,
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| HttpError::InvalidUrl)?),
+            None => (authority, DEFAULT_PORT),
+        };
+        if host.is_empty() || host.contains('@') || port == 0 {
+            return Err(HttpError::InvalidUrl);
+        }
+        Ok(Url { host, port, path })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub reason: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// 名为 `name` 的头部，不区分大小写
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+    }
+
+    /// 响应体当作 UTF-8 文本，不合法的字节换成 U+FFFD
+    pub fn text(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.body)
+    }
+}
+
+/// 下载 `url`，整个过程最多用 `timeout`
+pub fn get(url: &str, timeout: Duration) -> Result<Response, HttpError> {
+    let deadline = uptime() + timeout;
+    let url = Url::parse(url)?;
+    let address = dns::resolve(url.host)?;
+    let remaining = || deadline.saturating_sub(uptime());
+    let stream = TcpStream::connect(address, url.port, remaining())?;
+    let host = match url.port {
+        DEFAULT_PORT => Cow::Borrowed(url.host),
+        port => Cow::Owned(format!("{}:{}", url.host, port)),
+    };
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: cinea-os/{}\r\nAccept: */*\r\nConnection: close\r\n\r\n",
+        url.path, host, VERSION,
+    );
+    stream.write_all(request.as_bytes(), remaining())?;
+    let mut data = Vec::new();
+    let mut buffer = [0u8; 2048];
+    loop {
+        let len = stream.read(&mut buffer, remaining())?;
+        if len == 0 {
+            break;
+        }
+        data.extend_from_slice(&buffer[..len]);
+        if data.len() > MAX_HEADER + MAX_BODY {
+            return Err(HttpError::TooLarge);
+        }
+    }
+    parse_response(&data)
+}
+
+/// 解析完整的响应：状态行、头部和按 Content-Length 或 chunked 编码取出的响应体
+pub fn parse_response(data: &[u8]) -> Result<Response, HttpError> {
+    let end = data.windows(4).position(|w| w == b"\r\n\r\n").ok_or(HttpError::BadResponse)?;
+    if end > MAX_HEADER {
+        return Err(HttpError::TooLarge);
+    }
+    let head = core::str::from_utf8(&data[..end]).map_err(|_| HttpError::BadResponse)?;
+    let mut lines = head.split("\r\n");
+    let status_line = lines.next().ok_or(HttpError::BadResponse)?;
+    let mut parts = status_line.splitn(3, ' ');
+    if !parts.next().is_some_and(|version| version.starts_with("HTTP/1.")) {
+        return Err(HttpError::BadResponse);
+    }
+    let status = parts.next().and_then(|s| s.parse().ok()).ok_or(HttpError::BadResponse)?;
+    let reason = String::from(parts.next().unwrap_or(""));
+    let headers = lines
+        .map(|line| line.split_once(':').map(|(n, v)| (String::from(n.trim()), String::from(v.trim()))))
+        .collect::<Option<Vec<_>>>()
+        .ok_or(HttpError::BadResponse)?;
+    let mut response = Response { status, reason, headers, body: Vec::new() };
+
+    let body = &data[end + 4..];
+    response.body = if response.header("transfer-encoding").is_some_and(|v| v.eq_ignore_ascii_case("chunked")) {
+        dechunk(body)?
+    } else if let Some(len) = response.header("content-length") {
+        let len: usize = len.parse().map_err(|_| HttpError::BadResponse)?;
+        if len > MAX_BODY {
+            return Err(HttpError::TooLarge);
+        }
+        body.get(..len).ok_or(HttpError::BadResponse)?.to_vec()
+    } else {
+        body.to_vec()
+    };
+    if response.body.len() > MAX_BODY {
+        return Err(HttpError::TooLarge);
+    }
+    Ok(response)
+}
+
+/// 拼起 chunked 编码的各块，忽略块扩展和结尾的头部
+pub fn dechunk(mut data: &[u8]) -> Result<Vec<u8>, HttpError> {
+    let mut body = Vec::new();
+    loop {
+        let line_end = data.windows(2).position(|w| w == b"\r\n").ok_or(HttpError::BadResponse)?;
+        let line = core::str::from_utf8(&data[..line_end]).map_err(|_| HttpError::BadResponse)?;
+        let size = line.split(';').next().unwrap_or("").trim();
+        let size = usize::from_str_radix(size, 16).map_err(|_| HttpError::BadResponse)?;
+        data = &data[line_end + 2..];
+        if size == 0 {
+            return Ok(body);
+        }
+        if body.len() + size > MAX_BODY {
+            return Err(HttpError::TooLarge);
+        }
+        let chunk = data.get(..size).ok_or(HttpError::BadResponse)?;
+        body.extend_from_slice(chunk);
+        data = data[size..].strip_prefix(b"\r\n").ok_or(HttpError::BadResponse)?;
+    }
+}
@@ -0,0 +1,103 @@
+// ICMP：回应别人的 ping，以及发 ping 和收回应
+// 发出的回显请求的负载开头是发送时的运行时间（纳秒），收到回应时据此算出往返时间，不需要记住发出去的请求
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use spin::Mutex;
+use x86_64::instructions::hlt;
+
+use crate::io::timer::uptime;
+use crate::net::ipv4::{self, Ipv4Addr};
+use crate::net::{self, Interface, NetError};
+
+const ECHO_REPLY: u8 = 0;
+const ECHO_REQUEST: u8 = 8;
+const HEADER_LEN: usize = 8;
+/// ping 的负载长度，和其他系统的默认值一样
+pub const PING_PAYLOAD: usize = 56;
+// 没有人取走的回应最多保留这么多
+const MAX_REPLIES: usize = 32;
+
+#[derive(Debug, Clone, Copy)]
+pub struct EchoReply {
+    pub from: Ipv4Addr,
+    pub ident: u16,
+    pub seq: u16,
+    pub ttl: u8,
+    /// ICMP 报文的长度
+    pub len: usize,
+    /// 往返时间
+    pub time: Duration,
+}
+
+static REPLIES: Mutex<VecDeque<EchoReply>> = Mutex::new(VecDeque::new());
+
+pub(super) fn handle(interface: &mut Interface, header: &ipv4::Header, message: &[u8]) {
+    if message.len() < HEADER_LEN || ipv4::checksum(&[message]) != 0 {
+        return;
+    }
+    let ident = u16::from_be_bytes([message[4], message[5]]);
+    let seq = u16::from_be_bytes([message[6], message[7]]);
+    match message[0] {
+        ECHO_REQUEST => {
+            let reply = build(ECHO_REPLY, ident, seq, &message[HEADER_LEN..]);
+            let _ = interface.send_ipv4(header.source, ipv4::PROTOCOL_ICMP, &reply);
+        }
+        ECHO_REPLY => {
+            let sent = message.get(HEADER_LEN..HEADER_LEN + 8)
+                .map(|bytes| Duration::from_nanos(u64::from_be_bytes(bytes.try_into().unwrap())));
+            let time = sent.map_or(Duration::ZERO, |sent| uptime().saturating_sub(sent));
+            let mut replies = REPLIES.lock();
+            if replies.len() == MAX_REPLIES {
+                replies.pop_front();
+            }
+            replies.push_back(EchoReply { from: header.source, ident, seq, ttl: header.ttl, len: message.len(), time });
+        }
+        _ => {}
+    }
+}
+
+/// 向 `destination` 发一个回显请求
+pub fn send_echo(destination: Ipv4Addr, ident: u16, seq: u16) -> Result<(), NetError> {
+    let mut payload = Vec::with_capacity(PING_PAYLOAD);
+    payload.extend_from_slice(&(uptime().as_nanos() as u64).to_be_bytes());
+    // 剩下的部分和其他系统的 ping 一样填递增的字节
+    payload.extend((8..PING_PAYLOAD).map(|i| i as u8));
+    net::send_ipv4(destination, ipv4::PROTOCOL_ICMP, &build(ECHO_REQUEST, ident, seq, &payload))
+}
+
+/// 取走 `ident` 和 `seq` 对应的回应
+pub fn take_reply(ident: u16, seq: u16) -> Option<EchoReply> {
+    let mut replies = REPLIES.lock();
+    let index = replies.iter().position(|reply| reply.ident == ident && reply.seq == seq)?;
+    replies.remove(index)
+}
+
+/// 发一个回显请求并等待回应，超时返回 Ok(None)。等待时处理收到的包
+pub fn ping(destination: Ipv4Addr, ident: u16, seq: u16, timeout: Duration) -> Result<Option<EchoReply>, NetError> {
+    let deadline = uptime() + timeout;
+    send_echo(destination, ident, seq)?;
+    loop {
+        net::poll();
+        if let Some(reply) = take_reply(ident, seq) {
+            return Ok(Some(reply));
+        }
+        if uptime() >= deadline {
+            return Ok(None);
+        }
+        hlt();
+    }
+}
+
+fn build(kind: u8, ident: u16, seq: u16, payload: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(HEADER_LEN + payload.len());
+    message.extend_from_slice(&[kind, 0, 0, 0]);
+    message.extend_from_slice(&ident.to_be_bytes());
+    message.extend_from_slice(&seq.to_be_bytes());
+    message.extend_from_slice(payload);
+    let sum = ipv4::checksum(&[&message]);
+    message[2..4].copy_from_slice(&sum.to_be_bytes());
+    message
+}
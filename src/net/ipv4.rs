@@ -0,0 +1,125 @@
+// IPv4
+// 只处理不分片、不带选项的包：收到分片直接丢掉，发出的包不超过 MTU 并设置 DF
+
+use alloc::vec::Vec;
+use core::fmt;
+
+pub const PROTOCOL_ICMP: u8 = 1;
+pub const PROTOCOL_TCP: u8 = 6;
+pub const PROTOCOL_UDP: u8 = 17;
+pub const HEADER_LEN: usize = 20;
+pub const DEFAULT_TTL: u8 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Ipv4Addr(pub [u8; 4]);
+
+impl Ipv4Addr {
+    pub const UNSPECIFIED: Ipv4Addr = Ipv4Addr([0; 4]);
+    pub const BROADCAST: Ipv4Addr = Ipv4Addr([255; 4]);
+
+    pub const fn new(a: u8, b: u8, c: u8, d: u8) -> Self {
+        Ipv4Addr([a, b, c, d])
+    }
+
+    pub fn to_u32(self) -> u32 {
+        u32::from_be_bytes(self.0)
+    }
+
+    pub fn from_u32(value: u32) -> Self {
+        Ipv4Addr(value.to_be_bytes())
+    }
+
+    /// 前缀长度对应的子网掩码，比如 24 对应 255.255.255.0
+    pub fn netmask(prefix: u8) -> Self {
+        Ipv4Addr::from_u32(u32::MAX.checked_shl(32 - prefix.min(32) as u32).unwrap_or(0))
+    }
+
+    /// 点分十进制
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut octets = [0u8; 4];
+        let mut parts = text.split('.');
+        for octet in octets.iter_mut() {
+            *octet = parts.next()?.parse().ok()?;
+        }
+        parts.next().is_none().then_some(Ipv4Addr(octets))
+    }
+
+    /// 和 `other` 在掩码为 `netmask` 的同一个子网里
+    pub fn same_subnet(self, other: Ipv4Addr, netmask: Ipv4Addr) -> bool {
+        self.to_u32() & netmask.to_u32() == other.to_u32() & netmask.to_u32()
+    }
+}
+
+impl fmt::Display for Ipv4Addr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d] = self.0;
+        write!(f, "{}.{}.{}.{}", a, b, c, d)
+    }
+}
+
+pub struct Header {
+    pub source: Ipv4Addr,
+    pub destination: Ipv4Addr,
+    pub protocol: u8,
+    pub ttl: u8,
+}
+
+/// 拆出头部和负载；校验和不对、是分片或者长度不对时返回 None。以太网补齐的字节不算在负载里
+pub fn parse(packet: &[u8]) -> Option<(Header, &[u8])> {
+    if packet.len() < HEADER_LEN || packet[0] >> 4 != 4 {
+        return None;
+    }
+    let header_len = (packet[0] & 0xF) as usize * 4;
+    let total_len = u16::from_be_bytes([packet[2], packet[3]]) as usize;
+    if header_len < HEADER_LEN || total_len < header_len || total_len > packet.len() {
+        return None;
+    }
+    // MF 位或者片偏移不为 0
+    if u16::from_be_bytes([packet[6], packet[7]]) & 0x3FFF != 0 || checksum(&[&packet[..header_len]]) != 0 {
+        return None;
+    }
+    let header = Header {
+        source: Ipv4Addr(packet[12..16].try_into().unwrap()),
+        destination: Ipv4Addr(packet[16..20].try_into().unwrap()),
+        protocol: packet[9],
+        ttl: packet[8],
+    };
+    Some((header, &packet[header_len..total_len]))
+}
+
+pub fn build(id: u16, header: &Header, payload: &[u8]) -> Vec<u8> {
+    let total_len = (HEADER_LEN + payload.len()) as u16;
+    let mut packet = Vec::with_capacity(total_len as usize);
+    packet.extend_from_slice(&[0x45, 0]);
+    packet.extend_from_slice(&total_len.to_be_bytes());
+    packet.extend_from_slice(&id.to_be_bytes());
+    // DF
+    packet.extend_from_slice(&[0x40, 0, header.ttl, header.protocol, 0, 0]);
+    packet.extend_from_slice(&header.source.0);
+    packet.extend_from_slice(&header.destination.0);
+    let sum = checksum(&[&packet]);
+    packet[10..12].copy_from_slice(&sum.to_be_bytes());
+    packet.extend_from_slice(payload);
+    packet
+}
+
+/// 互联网校验和（RFC 1071），几段数据连起来计算，只有最后一段可以是奇数长度。
+/// 数据里已经带着正确的校验和时结果为 0
+pub fn checksum(parts: &[&[u8]]) -> u16 {
+    let mut sum = 0u32;
+    for part in parts {
+        let mut words = part.chunks_exact(2);
+        for word in &mut words {
+            sum += u16::from_be_bytes([word[0], word[1]]) as u32;
+        }
+        if let [last] = words.remainder() {
+            sum += (*last as u32) << 8;
+        }
+        // 及时进位，避免溢出
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    while sum >> 16 != 0 {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
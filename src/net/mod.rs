@@ -0,0 +1,384 @@
+// 网络
+// 一个网卡、一个 IPv4 地址，协议只有以太网、ARP、IPv4、ICMP、UDP 和 TCP（只有客户端），上面是 DHCP、DNS 和 HTTP 客户端。
+// 网卡驱动（virtio-net 或 e1000）实现 NetDevice 并用 attach 交给这里；收包靠轮询：shell 空闲时调用 poll，等待回应的代码也自己调用，
+// 支持中断的网卡只在中断之后才去检查接收队列。
+// 启动时用 DHCP 获取地址，失败时用 QEMU 用户网络的固定地址（本机 10.0.2.15，网关 10.0.2.2，DNS 10.0.2.3）；
+// 之后可以用 configure 修改。发往自己地址的包不经过网卡，直接当作收到的包处理
+
+pub mod arp;
+pub mod dhcp;
+pub mod dns;
+pub mod ethernet;
+pub mod http;
+pub mod icmp;
+pub mod ipv4;
+pub mod tcp;
+pub mod udp;
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+
+use spin::Mutex;
+
+use crate::boot::Unit;
+use crate::drivers::{e1000, pci, virtio};
+use crate::io::timer::uptime;
+use crate::net::ethernet::MacAddr;
+use crate::net::ipv4::Ipv4Addr;
+
+/// 不含以太网头的最大包长
+pub const MTU: usize = 1500;
+// 等待 ARP 解析的包最多这么多，多了丢掉最早的
+const MAX_WAITING: usize = 16;
+// poll 一次最多处理的帧数，不让网络流量占满空闲循环
+const POLL_BUDGET: usize = 64;
+// 启动时等待 DHCP 的时间
+const DHCP_TIMEOUT: Duration = Duration::from_secs(3);
+
+pub trait NetDevice: Send {
+    /// 驱动名，用于显示
+    fn name(&self) -> &'static str;
+    fn mac(&self) -> MacAddr;
+    /// 发送一个以太网帧，队列满了时返回 false
+    fn transmit(&mut self, frame: &[u8]) -> bool;
+    /// 取出一个收到的以太网帧
+    fn receive(&mut self) -> Option<Vec<u8>>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetError {
+    /// 没有网卡
+    NoDevice,
+    /// 超过 MTU
+    TooLarge,
+    /// 网卡的发送队列满了
+    NoBuffer,
+    /// 端口已经被绑定
+    AddressInUse,
+    /// 还没有地址，只能广播
+    Unreachable,
+    /// 等不到回应
+    Timeout,
+    /// 服务器拒绝了请求
+    Refused,
+    /// 连接被对方重置
+    Reset,
+}
+
+impl fmt::Display for NetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            NetError::NoDevice => "no network device",
+            NetError::TooLarge => "message too long",
+            NetError::NoBuffer => "no buffer space available",
+            NetError::AddressInUse => "address already in use",
+            NetError::Unreachable => "network is unreachable",
+            NetError::Timeout => "timed out",
+            NetError::Refused => "refused by server",
+            NetError::Reset => "connection reset by peer",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    pub address: Ipv4Addr,
+    pub netmask: Ipv4Addr,
+    pub gateway: Ipv4Addr,
+    /// DNS 服务器，没有时是 0.0.0.0
+    pub dns: Ipv4Addr,
+}
+
+impl Config {
+    /// 还没有地址
+    pub const UNCONFIGURED: Config = Config {
+        address: Ipv4Addr::UNSPECIFIED,
+        netmask: Ipv4Addr::UNSPECIFIED,
+        gateway: Ipv4Addr::UNSPECIFIED,
+        dns: Ipv4Addr::UNSPECIFIED,
+    };
+
+    /// QEMU 用户网络
+    pub const QEMU_USER: Config = Config {
+        address: Ipv4Addr::new(10, 0, 2, 15),
+        netmask: Ipv4Addr::new(255, 255, 255, 0),
+        gateway: Ipv4Addr::new(10, 0, 2, 2),
+        dns: Ipv4Addr::new(10, 0, 2, 3),
+    };
+
+    pub fn is_configured(&self) -> bool {
+        self.address != Ipv4Addr::UNSPECIFIED
+    }
+
+    fn broadcast(&self) -> Ipv4Addr {
+        Ipv4Addr::from_u32(self.address.to_u32() | !self.netmask.to_u32())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stats {
+    pub rx_packets: u64,
+    pub rx_bytes: u64,
+    pub tx_packets: u64,
+    pub tx_bytes: u64,
+    /// 发送失败或者等不到 ARP 回应丢掉的包
+    pub dropped: u64,
+}
+
+/// 给状态栏显示的连接状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// 没有网卡
+    Down,
+    /// 有网卡，没有地址
+    Unconfigured,
+    Up,
+}
+
+/// 网卡的状态，给 ifconfig 显示
+pub struct Info {
+    pub driver: &'static str,
+    pub mac: MacAddr,
+    pub config: Config,
+    pub stats: Stats,
+    pub neighbors: Vec<(Ipv4Addr, MacAddr)>,
+}
+
+pub(crate) struct Interface {
+    device: Box<dyn NetDevice>,
+    mac: MacAddr,
+    config: Config,
+    arp: arp::Cache,
+    // 等待 ARP 解析的包：下一跳的地址和整个 IP 包
+    waiting: VecDeque<(Ipv4Addr, Vec<u8>)>,
+    stats: Stats,
+    next_id: u16,
+}
+
+static INTERFACE: Mutex<Option<Interface>> = Mutex::new(None);
+// 状态变化之后还没有被 take_change 取走
+static CHANGED: AtomicBool = AtomicBool::new(false);
+
+/// 注册网卡驱动并用 DHCP 获取地址，需要在 memory::install_frame_allocator 之后调用，驱动要分配 DMA 内存
+pub fn init() {
+    pci::register(&virtio::net::DRIVER);
+    pci::register(&e1000::DRIVER);
+    if !is_up() {
+        return;
+    }
+    match dhcp::run(DHCP_TIMEOUT) {
+        Ok(lease) => log::info!("net: DHCP lease {} from {}", lease.config.address, lease.server),
+        Err(error) => {
+            log::warn!("net: DHCP failed ({}), using {}", error, Config::QEMU_USER.address);
+            configure(Config::QEMU_USER);
+        }
+    }
+}
+
+/// 网卡驱动要分配 DMA 内存
+pub static UNIT: Unit = Unit {
+    name: "net",
+    depends: &["pci", "frame-pool"],
+    init: |_| {
+        init();
+        Ok(())
+    },
+};
+
+/// 驱动初始化好网卡后调用；已经有网卡时忽略后来的
+pub fn attach(device: Box<dyn NetDevice>) {
+    let mut interface = INTERFACE.lock();
+    if interface.is_some() {
+        log::warn!("net: ignoring second device {}", device.name());
+        return;
+    }
+    log::info!("net: {} attached", device.name());
+    *interface = Some(Interface {
+        mac: device.mac(),
+        device,
+        config: Config::UNCONFIGURED,
+        arp: arp::Cache::default(),
+        waiting: VecDeque::new(),
+        stats: Stats::default(),
+        next_id: 0,
+    });
+    CHANGED.store(true, Ordering::Release);
+}
+
+/// 有网卡
+pub fn is_up() -> bool {
+    INTERFACE.lock().is_some()
+}
+
+pub fn status() -> Status {
+    match config() {
+        None => Status::Down,
+        Some(config) if !config.is_configured() => Status::Unconfigured,
+        Some(_) => Status::Up,
+    }
+}
+
+/// 状态从上次调用以来是否变过，给状态栏用
+pub fn take_change() -> bool {
+    CHANGED.swap(false, Ordering::AcqRel)
+}
+
+pub fn mac() -> Option<MacAddr> {
+    INTERFACE.lock().as_ref().map(|interface| interface.mac)
+}
+
+pub fn config() -> Option<Config> {
+    INTERFACE.lock().as_ref().map(|interface| interface.config)
+}
+
+/// 修改地址配置，没有网卡时返回 false
+pub fn configure(config: Config) -> bool {
+    let mut interface = INTERFACE.lock();
+    let Some(interface) = interface.as_mut() else { return false };
+    interface.config = config;
+    interface.waiting.clear();
+    CHANGED.store(true, Ordering::Release);
+    true
+}
+
+pub fn info() -> Option<Info> {
+    let interface = INTERFACE.lock();
+    let interface = interface.as_ref()?;
+    Some(Info {
+        driver: interface.device.name(),
+        mac: interface.mac,
+        config: interface.config,
+        stats: interface.stats,
+        neighbors: interface.arp.entries(uptime()),
+    })
+}
+
+/// 处理收到的帧
+pub fn poll() {
+    let mut interface = INTERFACE.lock();
+    let Some(interface) = interface.as_mut() else { return };
+    for _ in 0..POLL_BUDGET {
+        let Some(frame) = interface.device.receive() else { break };
+        interface.stats.rx_packets += 1;
+        interface.stats.rx_bytes += frame.len() as u64;
+        interface.handle_frame(&frame);
+    }
+}
+
+/// 发一个 IP 包，`payload` 是 IP 头之后的部分
+pub fn send_ipv4(destination: Ipv4Addr, protocol: u8, payload: &[u8]) -> Result<(), NetError> {
+    INTERFACE.lock().as_mut().ok_or(NetError::NoDevice)?.send_ipv4(destination, protocol, payload)
+}
+
+impl Interface {
+    fn handle_frame(&mut self, frame: &[u8]) {
+        let Some((header, payload)) = ethernet::parse(frame) else { return };
+        if header.destination != self.mac && header.destination != MacAddr::BROADCAST {
+            return;
+        }
+        match header.ether_type {
+            ethernet::TYPE_ARP => self.handle_arp(payload),
+            ethernet::TYPE_IPV4 => self.handle_ipv4(payload),
+            _ => {}
+        }
+    }
+
+    fn handle_arp(&mut self, payload: &[u8]) {
+        let Some(packet) = arp::Packet::parse(payload) else { return };
+        if packet.sender_ip != Ipv4Addr::UNSPECIFIED {
+            self.arp.insert(packet.sender_ip, packet.sender_mac, uptime());
+            self.flush_waiting(packet.sender_ip, packet.sender_mac);
+        }
+        if packet.operation == arp::REQUEST && packet.target_ip == self.config.address {
+            self.send_arp(arp::REPLY, packet.sender_mac, packet.sender_ip);
+        }
+    }
+
+    fn handle_ipv4(&mut self, packet: &[u8]) {
+        let Some((header, payload)) = ipv4::parse(packet) else { return };
+        let config = self.config;
+        // 还没有地址时（比如等待 DHCP 的回应）什么地址都收
+        let for_us = !config.is_configured()
+            || [config.address, config.broadcast(), Ipv4Addr::BROADCAST].contains(&header.destination);
+        if !for_us {
+            return;
+        }
+        match header.protocol {
+            ipv4::PROTOCOL_ICMP => icmp::handle(self, &header, payload),
+            ipv4::PROTOCOL_UDP => udp::handle(&header, payload),
+            ipv4::PROTOCOL_TCP => tcp::handle(self, &header, payload),
+            _ => {}
+        }
+    }
+
+    pub(crate) fn send_ipv4(&mut self, destination: Ipv4Addr, protocol: u8, payload: &[u8]) -> Result<(), NetError> {
+        if ipv4::HEADER_LEN + payload.len() > MTU {
+            return Err(NetError::TooLarge);
+        }
+        self.next_id = self.next_id.wrapping_add(1);
+        let header = ipv4::Header { source: self.config.address, destination, protocol, ttl: ipv4::DEFAULT_TTL };
+        let packet = ipv4::build(self.next_id, &header, payload);
+        let config = self.config;
+        if destination == config.address && config.is_configured() {
+            self.handle_ipv4(&packet);
+            return Ok(());
+        }
+        if destination == Ipv4Addr::BROADCAST || destination == config.broadcast() {
+            return self.send_frame(MacAddr::BROADCAST, ethernet::TYPE_IPV4, &packet);
+        }
+        if !config.is_configured() {
+            return Err(NetError::Unreachable);
+        }
+        let next_hop = if destination.same_subnet(config.address, config.netmask) { destination } else { config.gateway };
+        let now = uptime();
+        if let Some(mac) = self.arp.lookup(next_hop, now) {
+            return self.send_frame(mac, ethernet::TYPE_IPV4, &packet);
+        }
+        if self.waiting.len() == MAX_WAITING {
+            self.waiting.pop_front();
+            self.stats.dropped += 1;
+        }
+        self.waiting.push_back((next_hop, packet));
+        if self.arp.should_request(next_hop, now) {
+            self.send_arp(arp::REQUEST, MacAddr::ZERO, next_hop);
+        }
+        Ok(())
+    }
+
+    // 把等待 `ip` 的包发出去
+    fn flush_waiting(&mut self, ip: Ipv4Addr, mac: MacAddr) {
+        let (ready, waiting): (VecDeque<_>, VecDeque<_>) = core::mem::take(&mut self.waiting).into_iter().partition(|(hop, _)| *hop == ip);
+        self.waiting = waiting;
+        for (_, packet) in ready {
+            let _ = self.send_frame(mac, ethernet::TYPE_IPV4, &packet);
+        }
+    }
+
+    fn send_arp(&mut self, operation: u16, target_mac: MacAddr, target_ip: Ipv4Addr) {
+        let packet = arp::Packet {
+            operation,
+            sender_mac: self.mac,
+            sender_ip: self.config.address,
+            target_mac,
+            target_ip,
+        };
+        let destination = if operation == arp::REQUEST { MacAddr::BROADCAST } else { target_mac };
+        let _ = self.send_frame(destination, ethernet::TYPE_ARP, &packet.to_bytes());
+    }
+
+    fn send_frame(&mut self, destination: MacAddr, ether_type: u16, payload: &[u8]) -> Result<(), NetError> {
+        let header = ethernet::Header { destination, source: self.mac, ether_type };
+        let frame = ethernet::build(&header, payload);
+        if !self.device.transmit(&frame) {
+            self.stats.dropped += 1;
+            return Err(NetError::NoBuffer);
+        }
+        self.stats.tx_packets += 1;
+        self.stats.tx_bytes += frame.len() as u64;
+        Ok(())
+    }
+}
@@ -0,0 +1,492 @@
+// TCP
+// 只有客户端：connect 主动建立连接，得到的 TcpStream 阻塞地读写，等待时自己调用 net::poll 处理收到的包。
+// 收到的数据按顺序放进连接的接收缓冲区，乱序的段丢掉、回一个 ACK 让对方重发；
+// 发出去还没被确认的数据留在发送缓冲区，超时后从最早没被确认的字节开始全部重发，重发太多次算连接超时。
+// TcpStream 析构时发 FIN，连接留在表里直到双方都关闭；发给没有连接的端口的段回 RST
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU16, Ordering};
+use core::time::Duration;
+
+use spin::Mutex;
+use x86_64::instructions::hlt;
+
+use crate::io::timer::uptime;
+use crate::net::ipv4::{self, Ipv4Addr};
+use crate::net::{self, Interface, NetError, MTU};
+use crate::rand;
+
+const HEADER_LEN: usize = 20;
+const FIN: u8 = 0x01;
+const SYN: u8 = 0x02;
+const RST: u8 = 0x04;
+const PSH: u8 = 0x08;
+const ACK: u8 = 0x10;
+// SYN 里带的 MSS 选项
+const OPTION_MSS: u8 = 2;
+/// 一个段最多带的数据
+pub const MSS: usize = MTU - ipv4::HEADER_LEN - HEADER_LEN;
+// 接收缓冲区的大小，也就是通告的最大窗口
+const RECEIVE_BUFFER: usize = 32 * 1024;
+// 发送缓冲区的大小，写满之后 write 等对方确认
+const SEND_BUFFER: usize = 32 * 1024;
+// 重发超时，不随往返时间调整
+const RTO: Duration = Duration::from_secs(1);
+const MAX_RETRIES: u32 = 5;
+// 析构之后等对方关闭的最长时间
+const LINGER: Duration = Duration::from_secs(30);
+const EPHEMERAL_PORTS: core::ops::RangeInclusive<u16> = 49152..=65535;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segment<'a> {
+    pub source_port: u16,
+    pub destination_port: u16,
+    pub seq: u32,
+    pub ack: u32,
+    pub flags: u8,
+    pub window: u16,
+    pub payload: &'a [u8],
+}
+
+impl<'a> Segment<'a> {
+    /// 检查校验和并拆出各字段，忽略选项
+    pub fn parse(source: Ipv4Addr, destination: Ipv4Addr, bytes: &'a [u8]) -> Option<Self> {
+        if bytes.len() < HEADER_LEN || checksum(source, destination, bytes) != 0 {
+            return None;
+        }
+        let offset = (bytes[12] >> 4) as usize * 4;
+        if offset < HEADER_LEN || offset > bytes.len() {
+            return None;
+        }
+        let u16_at = |i: usize| u16::from_be_bytes([bytes[i], bytes[i + 1]]);
+        let u32_at = |i: usize| u32::from_be_bytes(bytes[i..i + 4].try_into().unwrap());
+        Some(Segment {
+            source_port: u16_at(0),
+            destination_port: u16_at(2),
+            seq: u32_at(4),
+            ack: u32_at(8),
+            flags: bytes[13],
+            window: u16_at(14),
+            payload: &bytes[offset..],
+        })
+    }
+
+    /// 构造带校验和的段，SYN 段带上 MSS 选项
+    pub fn build(&self, source: Ipv4Addr, destination: Ipv4Addr) -> Vec<u8> {
+        let options: &[u8] = if self.flags & SYN != 0 {
+            let mss = (MSS as u16).to_be_bytes();
+            &[OPTION_MSS, 4, mss[0], mss[1]]
+        } else {
+            &[]
+        };
+        let offset = HEADER_LEN + options.len();
+        let mut bytes = Vec::with_capacity(offset + self.payload.len());
+        bytes.extend_from_slice(&self.source_port.to_be_bytes());
+        bytes.extend_from_slice(&self.destination_port.to_be_bytes());
+        bytes.extend_from_slice(&self.seq.to_be_bytes());
+        bytes.extend_from_slice(&self.ack.to_be_bytes());
+        bytes.extend_from_slice(&[(offset as u8 / 4) << 4, self.flags]);
+        bytes.extend_from_slice(&self.window.to_be_bytes());
+        bytes.extend_from_slice(&[0, 0, 0, 0]);
+        bytes.extend_from_slice(options);
+        bytes.extend_from_slice(self.payload);
+        let sum = checksum(source, destination, &bytes);
+        bytes[16..18].copy_from_slice(&sum.to_be_bytes());
+        bytes
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    SynSent,
+    Established,
+    Closed,
+}
+
+struct Connection {
+    local: Ipv4Addr,
+    remote: Ipv4Addr,
+    remote_port: u16,
+    state: State,
+    // 连接出错的原因，读写时返回
+    error: Option<NetError>,
+    // 最早的没被确认的序号，发送缓冲区从这里开始；SYN 被确认之前是 SYN 的序号
+    send_unacked: u32,
+    send_buffer: VecDeque<u8>,
+    // 发送缓冲区里已经发出去的字节数
+    sent: usize,
+    // 对方通告的窗口
+    send_window: usize,
+    // 用户关闭了连接，数据发完之后发 FIN
+    fin_queued: bool,
+    fin_sent: bool,
+    fin_acked: bool,
+    receive_next: u32,
+    receive_buffer: VecDeque<u8>,
+    // 对方发来了 FIN，之后没有数据了
+    peer_fin: bool,
+    // 收到了需要确认的段，或者要告诉对方窗口变大了
+    ack_pending: bool,
+    // 最早的没被确认的段发出去的时间，以及已经重发的次数
+    timer: Option<Duration>,
+    retries: u32,
+    // TcpStream 析构的时间
+    orphaned: Option<Duration>,
+}
+
+static CONNECTIONS: Mutex<BTreeMap<u16, Connection>> = Mutex::new(BTreeMap::new());
+// 下一个尝试分配的本地端口，轮着用，刚关闭的端口不会马上被重用
+static NEXT_PORT: AtomicU16 = AtomicU16::new(*EPHEMERAL_PORTS.start());
+
+// 发出去的段：目的地址和 TCP 段
+type Outgoing = Vec<(Ipv4Addr, Vec<u8>)>;
+
+impl Connection {
+    // 下一个要发送的序号
+    fn send_next(&self) -> u32 {
+        let syn = (self.state == State::SynSent) as u32;
+        let fin = (self.fin_sent && !self.fin_acked) as u32;
+        self.send_unacked.wrapping_add(syn + self.sent as u32 + fin)
+    }
+
+    fn receive_window(&self) -> u16 {
+        (RECEIVE_BUFFER - self.receive_buffer.len()).min(u16::MAX as usize) as u16
+    }
+
+    fn segment<'a>(&self, port: u16, seq: u32, flags: u8, payload: &'a [u8]) -> Segment<'a> {
+        Segment {
+            source_port: port,
+            destination_port: self.remote_port,
+            seq,
+            ack: if flags & ACK != 0 { self.receive_next } else { 0 },
+            flags,
+            window: self.receive_window(),
+            payload,
+        }
+    }
+
+    fn push(&self, out: &mut Outgoing, segment: Segment) {
+        out.push((self.remote, segment.build(self.local, self.remote)));
+    }
+
+    // 双方都关闭了，或者连接已经断开
+    fn is_finished(&self) -> bool {
+        self.state == State::Closed || (self.fin_acked && self.peer_fin)
+    }
+
+    fn fail(&mut self, error: NetError) {
+        self.state = State::Closed;
+        self.error.get_or_insert(error);
+        self.timer = None;
+    }
+
+    fn receive(&mut self, segment: &Segment, now: Duration) {
+        if segment.flags & RST != 0 {
+            match self.state {
+                State::SynSent if segment.flags & ACK != 0 && segment.ack == self.send_next() => self.fail(NetError::Refused),
+                State::Established if segment.seq == self.receive_next => self.fail(NetError::Reset),
+                _ => {}
+            }
+            return;
+        }
+        match self.state {
+            State::SynSent => {
+                if segment.flags & (SYN | ACK) == SYN | ACK && segment.ack == self.send_next() {
+                    self.send_unacked = segment.ack;
+                    self.receive_next = segment.seq.wrapping_add(1);
+                    self.send_window = segment.window as usize;
+                    self.state = State::Established;
+                    self.timer = None;
+                    self.retries = 0;
+                    self.ack_pending = true;
+                }
+                return;
+            }
+            State::Closed => return,
+            State::Established => {}
+        }
+
+        if segment.flags & ACK != 0 {
+            // 重发时 sent 被清零，之前发出去的段的确认也要认，所以上限按缓冲区里的全部数据算
+            let acked = segment.ack.wrapping_sub(self.send_unacked) as usize;
+            let queued = self.send_buffer.len() + (self.fin_queued && !self.fin_acked) as usize;
+            if acked > 0 && acked <= queued {
+                let data = acked.min(self.send_buffer.len());
+                self.send_buffer.drain(..data);
+                self.sent = self.sent.saturating_sub(data);
+                if acked > data {
+                    self.fin_sent = true;
+                    self.fin_acked = true;
+                }
+                self.send_unacked = segment.ack;
+                self.timer = (self.sent > 0 || (self.fin_sent && !self.fin_acked)).then_some(now);
+                self.retries = 0;
+            }
+            self.send_window = segment.window as usize;
+        }
+
+        // 重发的 SYN-ACK 说明我们的 ACK 丢了
+        if !segment.payload.is_empty() || segment.flags & (SYN | FIN) != 0 {
+            self.ack_pending = true;
+        }
+        if segment.seq != self.receive_next || self.peer_fin {
+            return;
+        }
+        let room = RECEIVE_BUFFER - self.receive_buffer.len();
+        let taken = segment.payload.len().min(room);
+        self.receive_buffer.extend(&segment.payload[..taken]);
+        self.receive_next = self.receive_next.wrapping_add(taken as u32);
+        if segment.flags & FIN != 0 && taken == segment.payload.len() {
+            self.receive_next = self.receive_next.wrapping_add(1);
+            self.peer_fin = true;
+        }
+    }
+
+    // 发出该发的段：SYN、新数据、FIN，超时时重发，最后需要的话单独回一个 ACK
+    fn transmit(&mut self, port: u16, now: Duration, out: &mut Outgoing) {
+        let before = out.len();
+        let expired = self.timer.is_some_and(|sent| now.saturating_sub(sent) >= RTO);
+        if expired {
+            self.retries += 1;
+            if self.retries > MAX_RETRIES {
+                self.fail(NetError::Timeout);
+                return;
+            }
+            self.timer = None;
+            self.sent = 0;
+            self.fin_sent = false;
+        }
+        match self.state {
+            State::SynSent => {
+                if self.timer.is_none() {
+                    self.push(out, self.segment(port, self.send_unacked, SYN, &[]));
+                    self.timer = Some(now);
+                }
+                return;
+            }
+            State::Closed => return,
+            State::Established => {}
+        }
+
+        // 对方的窗口为 0 时也发一个字节，确认回来时会带上新的窗口
+        let window = self.send_window.max(1);
+        while self.sent < self.send_buffer.len() && self.sent < window {
+            let len = (self.send_buffer.len() - self.sent).min(window - self.sent).min(MSS);
+            let payload: Vec<u8> = self.send_buffer.range(self.sent..self.sent + len).copied().collect();
+            let seq = self.send_unacked.wrapping_add(self.sent as u32);
+            self.push(out, self.segment(port, seq, ACK | PSH, &payload));
+            self.sent += len;
+            self.timer.get_or_insert(now);
+        }
+        if self.fin_queued && !self.fin_sent && self.sent == self.send_buffer.len() {
+            let seq = self.send_next();
+            self.push(out, self.segment(port, seq, FIN | ACK, &[]));
+            self.fin_sent = true;
+            self.timer.get_or_insert(now);
+        }
+        if self.ack_pending && out.len() == before {
+            self.push(out, self.segment(port, self.send_next(), ACK, &[]));
+        }
+        self.ack_pending = false;
+    }
+}
+
+fn send(out: Outgoing) {
+    for (destination, segment) in out {
+        let _ = net::send_ipv4(destination, ipv4::PROTOCOL_TCP, &segment);
+    }
+}
+
+pub(super) fn handle(interface: &mut Interface, header: &ipv4::Header, bytes: &[u8]) {
+    let Some(segment) = Segment::parse(header.source, header.destination, bytes) else { return };
+    let now = uptime();
+    let mut out = Outgoing::new();
+    {
+        let mut connections = CONNECTIONS.lock();
+        let port = segment.destination_port;
+        match connections.get_mut(&port) {
+            Some(connection) if connection.remote == header.source && connection.remote_port == segment.source_port => {
+                connection.receive(&segment, now);
+                connection.transmit(port, now, &mut out);
+            }
+            _ => out.extend(reset(header, &segment)),
+        }
+        connections.retain(|_, c| c.orphaned.is_none() || !c.is_finished());
+    }
+    // 还拿着网卡的锁，直接用它发
+    for (destination, segment) in out {
+        let _ = interface.send_ipv4(destination, ipv4::PROTOCOL_TCP, &segment);
+    }
+}
+
+// 回应发给没有连接的端口的段
+fn reset(header: &ipv4::Header, segment: &Segment) -> Option<(Ipv4Addr, Vec<u8>)> {
+    if segment.flags & RST != 0 {
+        return None;
+    }
+    let (seq, ack, flags) = if segment.flags & ACK != 0 {
+        (segment.ack, 0, RST)
+    } else {
+        let len = segment.payload.len() as u32 + (segment.flags & SYN != 0) as u32 + (segment.flags & FIN != 0) as u32;
+        (0, segment.seq.wrapping_add(len), RST | ACK)
+    };
+    let reply = Segment {
+        source_port: segment.destination_port,
+        destination_port: segment.source_port,
+        seq,
+        ack,
+        flags,
+        window: 0,
+        payload: &[],
+    };
+    Some((header.source, reply.build(header.destination, header.source)))
+}
+
+/// 一个 TCP 连接，析构时关闭
+pub struct TcpStream {
+    port: u16,
+}
+
+impl TcpStream {
+    /// 连接 `address` 的 `port`，等待握手完成
+    pub fn connect(address: Ipv4Addr, port: u16, timeout: Duration) -> Result<Self, NetError> {
+        let config = net::config().ok_or(NetError::NoDevice)?;
+        if !config.is_configured() {
+            return Err(NetError::Unreachable);
+        }
+        let now = uptime();
+        let local_port = {
+            let mut connections = CONNECTIONS.lock();
+            connections.retain(|_, c| !c.orphaned.is_some_and(|since| now.saturating_sub(since) >= LINGER));
+            let local_port = allocate_port(&connections).ok_or(NetError::AddressInUse)?;
+            connections.insert(local_port, Connection {
+                local: config.address,
+                remote: address,
+                remote_port: port,
+                state: State::SynSent,
+                error: None,
+                // 初始序号随机取，别人猜不到（RFC 6528）
+                send_unacked: rand::next_u32(),
+                send_buffer: VecDeque::new(),
+                sent: 0,
+                send_window: 0,
+                fin_queued: false,
+                fin_sent: false,
+                fin_acked: false,
+                receive_next: 0,
+                receive_buffer: VecDeque::new(),
+                peer_fin: false,
+                ack_pending: false,
+                timer: None,
+                retries: 0,
+                orphaned: None,
+            });
+            local_port
+        };
+        let stream = TcpStream { port: local_port };
+        stream.wait(now + timeout, |connection| (connection.state == State::Established).then_some(()))?;
+        Ok(stream)
+    }
+
+    pub fn local_port(&self) -> u16 {
+        self.port
+    }
+
+    /// 把 `data` 全部放进发送缓冲区，缓冲区满了时等对方确认
+    pub fn write_all(&self, data: &[u8], timeout: Duration) -> Result<(), NetError> {
+        let mut rest = data;
+        self.wait(uptime() + timeout, |connection| {
+            let len = rest.len().min(SEND_BUFFER - connection.send_buffer.len());
+            connection.send_buffer.extend(&rest[..len]);
+            rest = &rest[len..];
+            rest.is_empty().then_some(())
+        })
+    }
+
+    /// 读出收到的数据，没有数据时等待；对方关闭之后返回 0
+    pub fn read(&self, buffer: &mut [u8], timeout: Duration) -> Result<usize, NetError> {
+        self.wait(uptime() + timeout, |connection| {
+            if connection.receive_buffer.is_empty() {
+                return connection.peer_fin.then_some(0);
+            }
+            // 窗口已经小于一个段时，读完告诉对方窗口变大了
+            connection.ack_pending |= (connection.receive_window() as usize) < MSS;
+            let len = buffer.len().min(connection.receive_buffer.len());
+            for (dst, src) in buffer.iter_mut().zip(connection.receive_buffer.drain(..len)) {
+                *dst = src;
+            }
+            Some(len)
+        })
+    }
+
+    // 处理收到的包直到 `ready` 返回 Some，连接出错或者超时时返回错误
+    fn wait<R>(&self, deadline: Duration, mut ready: impl FnMut(&mut Connection) -> Option<R>) -> Result<R, NetError> {
+        loop {
+            net::poll();
+            let mut out = Outgoing::new();
+            let result = {
+                let mut connections = CONNECTIONS.lock();
+                let connection = connections.get_mut(&self.port).ok_or(NetError::Reset)?;
+                let result = ready(connection);
+                connection.transmit(self.port, uptime(), &mut out);
+                match (result, connection.error) {
+                    (Some(result), _) => Some(Ok(result)),
+                    (None, Some(error)) => Some(Err(error)),
+                    (None, None) => None,
+                }
+            };
+            send(out);
+            if let Some(result) = result {
+                return result;
+            }
+            if uptime() >= deadline {
+                return Err(NetError::Timeout);
+            }
+            hlt();
+        }
+    }
+}
+
+impl Drop for TcpStream {
+    fn drop(&mut self) {
+        let mut out = Outgoing::new();
+        {
+            let mut connections = CONNECTIONS.lock();
+            let Some(connection) = connections.get_mut(&self.port) else { return };
+            if connection.state != State::Established || connection.is_finished() {
+                connections.remove(&self.port);
+                return;
+            }
+            let now = uptime();
+            connection.fin_queued = true;
+            connection.orphaned = Some(now);
+            connection.transmit(self.port, now, &mut out);
+        }
+        send(out);
+    }
+}
+
+fn allocate_port(connections: &BTreeMap<u16, Connection>) -> Option<u16> {
+    for _ in EPHEMERAL_PORTS {
+        let mut port = NEXT_PORT.fetch_add(1, Ordering::Relaxed);
+        if !EPHEMERAL_PORTS.contains(&port) {
+            port = *EPHEMERAL_PORTS.start();
+            NEXT_PORT.store(port + 1, Ordering::Relaxed);
+        }
+        if !connections.contains_key(&port) {
+            return Some(port);
+        }
+    }
+    None
+}
+
+// 带伪首部的校验和
+fn checksum(source: Ipv4Addr, destination: Ipv4Addr, segment: &[u8]) -> u16 {
+    let mut pseudo = [0u8; 12];
+    pseudo[0..4].copy_from_slice(&source.0);
+    pseudo[4..8].copy_from_slice(&destination.0);
+    pseudo[9] = ipv4::PROTOCOL_TCP;
+    pseudo[10..12].copy_from_slice(&(segment.len() as u16).to_be_bytes());
+    ipv4::checksum(&[&pseudo, segment])
+}
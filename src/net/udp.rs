@@ -0,0 +1,113 @@
+// UDP
+// 绑定端口得到一个 UdpSocket，收到的数据报放进它的队列，用 recv_from 轮询取出；socket 析构时解除绑定。
+// 发往没有绑定的端口的数据报直接丢掉
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec::Vec;
+
+use spin::Mutex;
+
+use crate::net::ipv4::{self, Ipv4Addr};
+use crate::net::{self, NetError};
+
+const HEADER_LEN: usize = 8;
+// 每个 socket 最多积压的数据报
+const MAX_QUEUED: usize = 64;
+// 绑定端口 0 时从这里开始分配
+const EPHEMERAL_PORTS: core::ops::RangeInclusive<u16> = 49152..=65535;
+
+#[derive(Debug, Clone)]
+pub struct Datagram {
+    pub from: Ipv4Addr,
+    pub port: u16,
+    pub data: Vec<u8>,
+}
+
+static SOCKETS: Mutex<BTreeMap<u16, VecDeque<Datagram>>> = Mutex::new(BTreeMap::new());
+
+pub struct UdpSocket {
+    port: u16,
+}
+
+impl UdpSocket {
+    /// 绑定 `port`，为 0 时分配一个空闲的端口
+    pub fn bind(port: u16) -> Result<Self, NetError> {
+        let mut sockets = SOCKETS.lock();
+        let port = match port {
+            0 => EPHEMERAL_PORTS.clone().find(|port| !sockets.contains_key(port)).ok_or(NetError::AddressInUse)?,
+            port if sockets.contains_key(&port) => return Err(NetError::AddressInUse),
+            port => port,
+        };
+        sockets.insert(port, VecDeque::new());
+        Ok(UdpSocket { port })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn send_to(&self, data: &[u8], destination: Ipv4Addr, port: u16) -> Result<(), NetError> {
+        let source = net::config().ok_or(NetError::NoDevice)?.address;
+        let len = u16::try_from(HEADER_LEN + data.len()).map_err(|_| NetError::TooLarge)?;
+        let mut datagram = Vec::with_capacity(len as usize);
+        datagram.extend_from_slice(&self.port.to_be_bytes());
+        datagram.extend_from_slice(&port.to_be_bytes());
+        datagram.extend_from_slice(&len.to_be_bytes());
+        datagram.extend_from_slice(&[0, 0]);
+        datagram.extend_from_slice(data);
+        // 计算结果为 0 时写成全 1，0 表示没有校验和
+        let sum = match checksum(source, destination, &datagram) {
+            0 => 0xFFFF,
+            sum => sum,
+        };
+        datagram[6..8].copy_from_slice(&sum.to_be_bytes());
+        net::send_ipv4(destination, ipv4::PROTOCOL_UDP, &datagram)
+    }
+
+    /// 取出一个收到的数据报，没有时返回 None
+    pub fn recv_from(&self) -> Option<Datagram> {
+        SOCKETS.lock().get_mut(&self.port)?.pop_front()
+    }
+}
+
+impl Drop for UdpSocket {
+    fn drop(&mut self) {
+        SOCKETS.lock().remove(&self.port);
+    }
+}
+
+pub(super) fn handle(header: &ipv4::Header, datagram: &[u8]) {
+    if datagram.len() < HEADER_LEN {
+        return;
+    }
+    let len = u16::from_be_bytes([datagram[4], datagram[5]]) as usize;
+    if len < HEADER_LEN || len > datagram.len() {
+        return;
+    }
+    let datagram = &datagram[..len];
+    let has_checksum = datagram[6..8] != [0, 0];
+    if has_checksum && checksum(header.source, header.destination, datagram) != 0 {
+        return;
+    }
+    let port = u16::from_be_bytes([datagram[2], datagram[3]]);
+    let mut sockets = SOCKETS.lock();
+    let Some(queue) = sockets.get_mut(&port) else { return };
+    if queue.len() == MAX_QUEUED {
+        queue.pop_front();
+    }
+    queue.push_back(Datagram {
+        from: header.source,
+        port: u16::from_be_bytes([datagram[0], datagram[1]]),
+        data: datagram[HEADER_LEN..].to_vec(),
+    });
+}
+
+// 带伪首部的校验和
+fn checksum(source: Ipv4Addr, destination: Ipv4Addr, datagram: &[u8]) -> u16 {
+    let mut pseudo = [0u8; 12];
+    pseudo[0..4].copy_from_slice(&source.0);
+    pseudo[4..8].copy_from_slice(&destination.0);
+    pseudo[9] = ipv4::PROTOCOL_UDP;
+    pseudo[10..12].copy_from_slice(&(datagram.len() as u16).to_be_bytes());
+    ipv4::checksum(&[&pseudo, datagram])
+}
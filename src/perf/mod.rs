@@ -0,0 +1,262 @@
+// 性能剖析
+// scope_timer! 在作用域开始时读一次 TSC，离开时把经过的周期数记进定长的环形缓冲区，满了覆盖最旧的记录。
+// 没有开启时只读一次原子变量，可以放在 render、display_font 这样的热路径上。
+// 报告按 CPU 和开始时间把记录排好，时间区间落在另一个区间里面的算它的子作用域（包括中途进来的中断），
+// 由此算出每个作用域去掉子作用域后自己用的周期数；folded 把同样的嵌套关系导出成 flamegraph.pl 能读的折叠栈格式。
+// TSC 的频率第一次需要时用 PIT 通道 2 倒数 50ms 校准
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::arch::x86_64::_rdtsc;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+
+use spin::{Mutex, Once};
+use x86_64::instructions::interrupts;
+
+use crate::io::qemu::SerialStream;
+use crate::io::timer::pit;
+use crate::smp::cpu_id;
+
+// 环形缓冲区能放的记录数
+const CAPACITY: usize = 4096;
+// 校准用的 PIT 倒数时间，50ms
+const CALIBRATION_COUNT: u16 = (pit::BASE_FREQUENCY / 20) as u16;
+
+/// 一次作用域的耗时
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+    pub name: &'static str,
+    pub cpu: usize,
+    /// 开始时的 TSC
+    pub start: u64,
+    pub cycles: u64,
+}
+
+impl Sample {
+    fn end(&self) -> u64 {
+        self.start + self.cycles
+    }
+}
+
+/// 一个作用域的统计
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    pub name: &'static str,
+    pub calls: usize,
+    /// 包括子作用域的周期数
+    pub total: u64,
+    /// 去掉子作用域的周期数
+    pub own: u64,
+    /// 单次最长的周期数
+    pub max: u64,
+}
+
+/// 报告的排序方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    Total,
+    Own,
+}
+
+struct Ring {
+    samples: [Option<Sample>; CAPACITY],
+    // 下一个写入的位置
+    next: usize,
+    // 被覆盖掉的记录数
+    dropped: usize,
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static RING: Mutex<Ring> = Mutex::new(Ring { samples: [None; CAPACITY], next: 0, dropped: 0 });
+static TSC_HZ: Once<u64> = Once::new();
+
+/// 记录所在作用域的耗时：`perf::scope_timer!("render");`
+#[macro_export]
+macro_rules! scope_timer {
+    ($name:expr) => {
+        let _scope_timer = $crate::perf::scope($name);
+    };
+}
+
+pub use crate::scope_timer;
+
+/// 一次作用域计时，离开作用域时记下经过的周期数
+pub struct ScopeTimer {
+    name: &'static str,
+    // 没有开启时是 0
+    start: u64,
+}
+
+impl Drop for ScopeTimer {
+    fn drop(&mut self) {
+        if self.start == 0 || !is_enabled() {
+            return;
+        }
+        let cycles = unsafe { _rdtsc() }.wrapping_sub(self.start);
+        record(Sample { name: self.name, cpu: cpu_id(), start: self.start, cycles });
+    }
+}
+
+/// 开始计时，一般通过 scope_timer! 使用
+pub fn scope(name: &'static str) -> ScopeTimer {
+    let start = if is_enabled() { unsafe { _rdtsc() } } else { 0 };
+    ScopeTimer { name, start }
+}
+
+/// 开始记录
+pub fn start() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// 停止记录，已经记下的保留到下次 clear
+pub fn stop() {
+    ENABLED.store(false, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// 清空缓冲区
+pub fn clear() {
+    interrupts::without_interrupts(|| {
+        let mut ring = RING.lock();
+        ring.samples = [None; CAPACITY];
+        ring.next = 0;
+        ring.dropped = 0;
+    });
+}
+
+fn record(sample: Sample) {
+    interrupts::without_interrupts(|| {
+        // 生成报告时缓冲区被占用，丢掉这条记录
+        if let Some(mut ring) = RING.try_lock() {
+            let next = ring.next;
+            if ring.samples[next].is_some() {
+                ring.dropped += 1;
+            }
+            ring.samples[next] = Some(sample);
+            ring.next = (next + 1) % CAPACITY;
+        }
+    });
+}
+
+/// 缓冲区里的记录数和被覆盖掉的记录数
+pub fn stats() -> (usize, usize) {
+    interrupts::without_interrupts(|| {
+        let ring = RING.lock();
+        (ring.samples.iter().flatten().count(), ring.dropped)
+    })
+}
+
+/// 缓冲区里的所有记录
+pub fn samples() -> Vec<Sample> {
+    let ring = interrupts::without_interrupts(|| RING.lock());
+    ring.samples.iter().flatten().copied().collect()
+}
+
+/// TSC 每秒的周期数，第一次调用时校准，要等 50ms
+pub fn tsc_hz() -> u64 {
+    *TSC_HZ.call_once(|| {
+        let cycles = interrupts::without_interrupts(|| {
+            let start = unsafe { _rdtsc() };
+            pit::channel2_countdown(CALIBRATION_COUNT);
+            unsafe { _rdtsc() }.wrapping_sub(start)
+        });
+        let hz = cycles * pit::BASE_FREQUENCY as u64 / CALIBRATION_COUNT as u64;
+        log::info!("TSC runs at {} MHz", hz / 1_000_000);
+        hz.max(1)
+    })
+}
+
+/// 周期数换算成时间
+pub fn cycles_to_duration(cycles: u64) -> Duration {
+    Duration::from_nanos((cycles as u128 * 1_000_000_000 / tsc_hz() as u128) as u64)
+}
+
+// 按 CPU 和开始时间排序，同时开始的长的在前，这样外层总在内层前面
+fn sort(samples: &mut [Sample]) {
+    samples.sort_unstable_by_key(|s| (s.cpu, s.start, u64::MAX - s.cycles));
+}
+
+// 每个记录的父作用域的下标，排好序之后调用
+fn parents(samples: &[Sample]) -> Vec<Option<usize>> {
+    let mut parents = Vec::with_capacity(samples.len());
+    let mut stack: Vec<usize> = Vec::new();
+    for (i, sample) in samples.iter().enumerate() {
+        while let Some(&top) = stack.last() {
+            let outer = &samples[top];
+            if outer.cpu == sample.cpu && sample.start >= outer.start && sample.end() <= outer.end() {
+                break;
+            }
+            stack.pop();
+        }
+        parents.push(stack.last().copied());
+        stack.push(i);
+    }
+    parents
+}
+
+// 每个记录去掉子作用域后的周期数
+fn own_cycles(samples: &[Sample], parents: &[Option<usize>]) -> Vec<u64> {
+    let mut own: Vec<u64> = samples.iter().map(|s| s.cycles).collect();
+    for (sample, parent) in samples.iter().zip(parents) {
+        if let Some(parent) = *parent {
+            own[parent] = own[parent].saturating_sub(sample.cycles);
+        }
+    }
+    own
+}
+
+/// 按作用域名汇总，按 `order` 从大到小排序
+pub fn summarize(samples: &[Sample], order: Order) -> Vec<Entry> {
+    let mut samples = samples.to_vec();
+    sort(&mut samples);
+    let parents = parents(&samples);
+    let own = own_cycles(&samples, &parents);
+    let mut entries: BTreeMap<&'static str, Entry> = BTreeMap::new();
+    for (sample, own) in samples.iter().zip(own) {
+        let entry = entries.entry(sample.name).or_insert(Entry { name: sample.name, calls: 0, total: 0, own: 0, max: 0 });
+        entry.calls += 1;
+        entry.total += sample.cycles;
+        entry.own += own;
+        entry.max = entry.max.max(sample.cycles);
+    }
+    let mut entries: Vec<Entry> = entries.into_values().collect();
+    match order {
+        Order::Total => entries.sort_by(|a, b| b.total.cmp(&a.total)),
+        Order::Own => entries.sort_by(|a, b| b.own.cmp(&a.own)),
+    }
+    entries
+}
+
+/// 按折叠栈格式导出：每行是从外到内用分号连起来的作用域名和这个栈自己用的周期数
+pub fn folded(samples: &[Sample], out: &mut impl fmt::Write) -> fmt::Result {
+    let mut samples = samples.to_vec();
+    sort(&mut samples);
+    let parents = parents(&samples);
+    let own = own_cycles(&samples, &parents);
+    // 父作用域排在前面，它的路径总是先算好
+    let mut paths: Vec<String> = Vec::with_capacity(samples.len());
+    let mut stacks: BTreeMap<String, u64> = BTreeMap::new();
+    for (i, sample) in samples.iter().enumerate() {
+        let path = match parents[i] {
+            Some(parent) => paths[parent].clone() + ";" + sample.name,
+            None => String::from(sample.name),
+        };
+        *stacks.entry(path.clone()).or_insert(0) += own[i];
+        paths.push(path);
+    }
+    for (path, cycles) in stacks {
+        writeln!(out, "{} {}", path, cycles)?;
+    }
+    Ok(())
+}
+
+/// 把折叠栈导出到串口
+pub fn dump_folded_serial() {
+    let _ = folded(&samples(), &mut SerialStream);
+}
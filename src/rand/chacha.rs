@@ -0,0 +1,63 @@
+// ChaCha20 分组函数（RFC 8439）
+// 32 位计数器加 96 位 nonce 的版本，给没有 RDRAND 时的伪随机数生成器用
+
+const CONSTANTS: [u32; 4] = [0x6170_7865, 0x3320_646E, 0x7962_2D32, 0x6B20_6574];
+const ROUNDS: usize = 20;
+
+pub const KEY_LEN: usize = 32;
+pub const NONCE_LEN: usize = 12;
+pub const BLOCK_LEN: usize = 64;
+
+#[derive(Clone)]
+pub struct ChaCha20 {
+    state: [u32; 16],
+}
+
+impl ChaCha20 {
+    pub fn new(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN], counter: u32) -> Self {
+        let word = |bytes: &[u8], i: usize| u32::from_le_bytes(bytes[4 * i..4 * i + 4].try_into().unwrap());
+        let mut state = [0; 16];
+        state[..4].copy_from_slice(&CONSTANTS);
+        for i in 0..8 {
+            state[4 + i] = word(key, i);
+        }
+        state[12] = counter;
+        for i in 0..3 {
+            state[13 + i] = word(nonce, i);
+        }
+        ChaCha20 { state }
+    }
+
+    /// 生成一块密钥流，之后计数器加一
+    pub fn next_block(&mut self) -> [u8; BLOCK_LEN] {
+        let mut x = self.state;
+        for _ in 0..ROUNDS / 2 {
+            // 列轮，然后对角线轮
+            quarter_round(&mut x, 0, 4, 8, 12);
+            quarter_round(&mut x, 1, 5, 9, 13);
+            quarter_round(&mut x, 2, 6, 10, 14);
+            quarter_round(&mut x, 3, 7, 11, 15);
+            quarter_round(&mut x, 0, 5, 10, 15);
+            quarter_round(&mut x, 1, 6, 11, 12);
+            quarter_round(&mut x, 2, 7, 8, 13);
+            quarter_round(&mut x, 3, 4, 9, 14);
+        }
+        let mut block = [0; BLOCK_LEN];
+        for (i, chunk) in block.chunks_mut(4).enumerate() {
+            chunk.copy_from_slice(&x[i].wrapping_add(self.state[i]).to_le_bytes());
+        }
+        self.state[12] = self.state[12].wrapping_add(1);
+        block
+    }
+}
+
+fn quarter_round(x: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    x[a] = x[a].wrapping_add(x[b]);
+    x[d] = (x[d] ^ x[a]).rotate_left(16);
+    x[c] = x[c].wrapping_add(x[d]);
+    x[b] = (x[b] ^ x[c]).rotate_left(12);
+    x[a] = x[a].wrapping_add(x[b]);
+    x[d] = (x[d] ^ x[a]).rotate_left(8);
+    x[c] = x[c].wrapping_add(x[d]);
+    x[b] = (x[b] ^ x[c]).rotate_left(7);
+}
@@ -0,0 +1,138 @@
+// 随机数
+// CPU 支持 RDRAND 时直接用它；不支持（或者连续失败）时用 ChaCha20 做伪随机数生成器。
+// 生成器第一次使用时取种子：RDSEED（支持时）、RTC 的时间和 TSC 的抖动——反复测量读一次 uptime 花的周期数，
+// 总线和缓存带来的差异混进种子里。每次取完随机数都用新生成的一块替换密钥，之后拿到状态也推不出之前的输出。
+// 网络协议的初始序号和事务号都从这里取，不要拿它生成需要长期保密的密钥
+
+pub mod chacha;
+
+use core::arch::x86_64::{_rdrand64_step, _rdseed64_step, _rdtsc};
+use core::fmt;
+
+use crate::cpu;
+use crate::drivers::rtc;
+use crate::io::timer::uptime;
+use crate::rand::chacha::{ChaCha20, BLOCK_LEN, KEY_LEN, NONCE_LEN};
+use crate::sync::IrqSafeMutex;
+
+// Intel 建议 RDRAND 失败时重试 10 次
+const RETRIES: usize = 10;
+// 测量 TSC 抖动的次数
+const JITTER_SAMPLES: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Rdrand,
+    ChaCha,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Source::Rdrand => "RDRAND",
+            Source::ChaCha => "ChaCha20",
+        })
+    }
+}
+
+/// 随机数从哪里来
+pub fn source() -> Source {
+    if cpu::features().rdrand { Source::Rdrand } else { Source::ChaCha }
+}
+
+fn rdrand() -> Option<u64> {
+    let mut value = 0;
+    (0..RETRIES).any(|_| unsafe { _rdrand64_step(&mut value) } == 1).then_some(value)
+}
+
+fn rdseed() -> Option<u64> {
+    let mut value = 0;
+    (0..RETRIES).any(|_| unsafe { _rdseed64_step(&mut value) } == 1).then_some(value)
+}
+
+struct Generator {
+    cipher: ChaCha20,
+}
+
+impl Generator {
+    fn seeded() -> Self {
+        let mut key = [0u8; KEY_LEN];
+        let mut mix = |i: usize, value: u64| {
+            for (j, byte) in value.to_le_bytes().iter().enumerate() {
+                let k = (i * 8 + j) % KEY_LEN;
+                key[k] = key[k].rotate_left(3) ^ byte;
+            }
+        };
+        mix(0, rtc::now().timestamp() as u64);
+        mix(1, uptime().as_nanos() as u64);
+        if cpu::features().rdseed {
+            for i in 0..4 {
+                mix(i, rdseed().unwrap_or(0));
+            }
+        }
+        // 每次只取最低的几位，它们抖动得最厉害
+        for i in 0..JITTER_SAMPLES {
+            let start = unsafe { _rdtsc() };
+            let _ = uptime();
+            let delta = unsafe { _rdtsc() }.wrapping_sub(start);
+            mix(i, delta & 0xFF);
+        }
+        // 用混好的池子做密钥生成一块，真正的密钥从这一块里取，消掉池子里的偏差
+        let mut cipher = ChaCha20::new(&key, &[0; NONCE_LEN], 0);
+        let block = cipher.next_block();
+        Generator { cipher: ChaCha20::new(block[..KEY_LEN].try_into().unwrap(), &[0; NONCE_LEN], 0) }
+    }
+
+    fn fill(&mut self, buffer: &mut [u8]) {
+        for chunk in buffer.chunks_mut(BLOCK_LEN) {
+            let block = self.cipher.next_block();
+            chunk.copy_from_slice(&block[..chunk.len()]);
+        }
+        // 换掉密钥
+        let block = self.cipher.next_block();
+        self.cipher = ChaCha20::new(block[..KEY_LEN].try_into().unwrap(), &[0; NONCE_LEN], 0);
+    }
+}
+
+static GENERATOR: IrqSafeMutex<Option<Generator>> = IrqSafeMutex::named("rand", None);
+
+fn fill_from_generator(buffer: &mut [u8]) {
+    GENERATOR.lock().get_or_insert_with(Generator::seeded).fill(buffer);
+}
+
+/// 用随机数填满 `buffer`
+pub fn fill_bytes(buffer: &mut [u8]) {
+    if cpu::features().rdrand {
+        for chunk in buffer.chunks_mut(8) {
+            match rdrand() {
+                Some(value) => chunk.copy_from_slice(&value.to_le_bytes()[..chunk.len()]),
+                None => fill_from_generator(chunk),
+            }
+        }
+    } else {
+        fill_from_generator(buffer);
+    }
+}
+
+pub fn next_u64() -> u64 {
+    let mut bytes = [0; 8];
+    fill_bytes(&mut bytes);
+    u64::from_le_bytes(bytes)
+}
+
+pub fn next_u32() -> u32 {
+    next_u64() as u32
+}
+
+/// `0..bound` 里均匀分布的随机数，`bound` 不能为 0
+pub fn below(bound: u64) -> u64 {
+    assert!(bound > 0);
+    // 丢掉会让低端的数多出现一次的那一段
+    let limit = u64::MAX - u64::MAX % bound;
+    loop {
+        let value = next_u64();
+        if value < limit {
+            return value % bound;
+        }
+    }
+}
@@ -0,0 +1,1104 @@
+// 内置命令
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU16, Ordering};
+use core::time::Duration;
+
+use embedded_graphics::pixelcolor::{Rgb888, RgbColor};
+use log::LevelFilter;
+use x86_64::VirtAddr;
+
+use crate::allocator::{defrag, shrinker};
+use crate::apps;
+use crate::assets;
+use crate::boot;
+use crate::debug::{gdb, symbols, watchdog};
+use crate::drivers::block;
+use crate::drivers::hotplug::{self, DeviceEvent};
+use crate::drivers::pci;
+use crate::fs::cache;
+use crate::graphic;
+use crate::gui::{self, about, fetch, frame, job_output, log_viewer, meminfo, reminder, screensaver, sysmon};
+use crate::i18n::{self, Language, Msg};
+use crate::interrupts;
+use crate::io::alarm::{self, AlarmId};
+use crate::io::console;
+use crate::io::format::{self, Clock, Elapsed, Locale, Size, Thousands};
+use crate::io::input;
+use crate::io::keyboard::keymap;
+use crate::io::pci::pci_enumerate;
+use crate::io::port::{self, Port};
+use crate::io::qemu::SerialStream;
+use crate::io::replay;
+use crate::io::theme::{self, WindowStyle};
+use crate::io::time::{next_local, to_local};
+use crate::io::timer::uptime;
+use crate::io::vt::{self, Tty};
+use crate::loader::elf;
+use crate::logger::ring;
+use crate::memory::{cow, debug, report};
+use crate::net::{self, dhcp, dns, http, icmp, ipv4::Ipv4Addr, Config};
+use crate::perf::Order;
+use crate::shell::{commands, Command};
+use crate::{shell_print, shell_println};
+use crate::smp;
+use crate::sound;
+use crate::trace::latency::{Source, MAX_SAMPLES};
+use crate::usermode::{self, programs, Exit};
+use crate::version::{self, Banner};
+
+pub(super) const BUILTINS: [Command; 56] = [
+    Command { name: "help", help: Msg::HelpHelp, run: help },
+    Command { name: "mem", help: Msg::HelpMem, run: mem },
+    Command { name: "vmmap", help: Msg::HelpVmmap, run: vmmap },
+    Command { name: "assets", help: Msg::HelpAssets, run: assets_command },
+    Command { name: "lspci", help: Msg::HelpLspci, run: lspci },
+    Command { name: "lsdev", help: Msg::HelpLsdev, run: lspci },
+    Command { name: "irq", help: Msg::HelpIrq, run: irq },
+    Command { name: "ioports", help: Msg::HelpIoports, run: ioports },
+    Command { name: "lsinput", help: Msg::HelpLsinput, run: lsinput },
+    Command { name: "rescan", help: Msg::HelpRescan, run: rescan },
+    Command { name: "lsblk", help: Msg::HelpLsblk, run: lsblk },
+    Command { name: "sync", help: Msg::HelpSync, run: sync },
+    Command { name: "uptime", help: Msg::HelpUptime, run: uptime_command },
+    Command { name: "locale", help: Msg::HelpLocale, run: locale },
+    Command { name: "lang", help: Msg::HelpLang, run: lang },
+    Command { name: "uname", help: Msg::HelpUname, run: uname },
+    Command { name: "about", help: Msg::HelpAbout, run: about },
+    Command { name: "boot", help: Msg::HelpBoot, run: boot },
+    Command { name: "bg", help: Msg::HelpBg, run: bg },
+    Command { name: "jobs", help: Msg::HelpJobs, run: jobs },
+    Command { name: "output", help: Msg::HelpOutput, run: output },
+    Command { name: "cpus", help: Msg::HelpCpus, run: cpus },
+    Command { name: "edit", help: Msg::HelpEdit, run: edit },
+    Command { name: "snake", help: Msg::HelpSnake, run: snake },
+    Command { name: "top", help: Msg::HelpTop, run: top },
+    Command { name: "at", help: Msg::HelpAt, run: at },
+    Command { name: "remind", help: Msg::HelpRemind, run: remind },
+    Command { name: "ifconfig", help: Msg::HelpIfconfig, run: ifconfig },
+    Command { name: "host", help: Msg::HelpHost, run: host },
+    Command { name: "ping", help: Msg::HelpPing, run: ping },
+    Command { name: "wget", help: Msg::HelpWget, run: wget },
+    Command { name: "fetch", help: Msg::HelpFetch, run: fetch },
+    Command { name: "beep", help: Msg::HelpBeep, run: beep },
+    Command { name: "play", help: Msg::HelpPlay, run: play },
+    Command { name: "run", help: Msg::HelpRun, run: run },
+    Command { name: "clear", help: Msg::HelpClear, run: clear },
+    Command { name: "mode", help: Msg::HelpMode, run: mode },
+    Command { name: "trace", help: Msg::HelpTrace, run: trace },
+    Command { name: "dmesg", help: Msg::HelpDmesg, run: dmesg },
+    Command { name: "perf", help: Msg::HelpPerf, run: perf },
+    Command { name: "latency", help: Msg::HelpLatency, run: latency },
+    Command { name: "input", help: Msg::HelpInput, run: input },
+    Command { name: "chvt", help: Msg::HelpChvt, run: chvt },
+    Command { name: "loadkeys", help: Msg::HelpLoadkeys, run: loadkeys },
+    Command { name: "zoom", help: Msg::HelpZoom, run: zoom },
+    Command { name: "fps", help: Msg::HelpFps, run: fps },
+    Command { name: "screensaver", help: Msg::HelpScreensaver, run: screensaver_command },
+    Command { name: "lock", help: Msg::HelpLock, run: lock },
+    Command { name: "screenshot", help: Msg::HelpScreenshot, run: screenshot },
+    Command { name: "decor", help: Msg::HelpDecor, run: decor },
+    Command { name: "theme", help: Msg::HelpTheme, run: theme_command },
+    Command { name: "selftest", help: Msg::HelpSelftest, run: selftest },
+    Command { name: "watchdog", help: Msg::HelpWatchdog, run: watchdog },
+    Command { name: "addr2line", help: Msg::HelpAddr2line, run: addr2line },
+    Command { name: "gdb", help: Msg::HelpGdb, run: gdb },
+    Command { name: "reboot", help: Msg::HelpReboot, run: reboot },
+];
+
+fn help(_args: &[&str]) {
+    for command in commands() {
+        shell_println!("{:<10} {}", command.name, i18n::tr(command.help));
+    }
+}
+
+// syntax 是命令的写法，不翻译
+fn usage(syntax: &str) {
+    shell_println!("{}", i18n::fill(Msg::Usage, &[&syntax]));
+}
+
+fn out_of_memory(command: &str) {
+    shell_println!("{}", i18n::fill(Msg::OutOfMemory, &[&command]));
+}
+
+fn on_off(on: bool) -> &'static str {
+    i18n::tr(if on { Msg::On } else { Msg::Off })
+}
+
+fn mem(args: &[&str]) {
+    match args {
+        [] => {}
+        ["map"] => {
+            for region in report::regions() {
+                shell_println!("{:#012x}-{:#012x} {:>10}  {}", region.start, region.end - 1, Size(region.size()),
+                               report::type_name(region.region_type));
+            }
+            return;
+        }
+        ["-w"] => {
+            if meminfo::show().is_err() {
+                out_of_memory("mem");
+            }
+            return;
+        }
+        _ => {
+            usage("mem [map|-w]");
+            return;
+        }
+    }
+    shell_print!("{}", report::report());
+    if shrinker::is_low_memory() {
+        shell_println!("{}", i18n::tr(Msg::MemoryLow));
+    }
+    shrinker::for_each(|name, bytes| shell_println!("{}", i18n::fill(Msg::Reclaimable, &[&name, &Size(bytes as u64)])));
+    let defrag = defrag::stats();
+    shell_println!("{}", i18n::fill(Msg::DefragStats, &[&defrag.merged, &defrag.moved, &defrag.movable]));
+    let cow = cow::stats();
+    shell_println!("{}", i18n::fill(Msg::CowStats, &[&Thousands(cow.shared as u64), &Size(cow.saved as u64 * 4096),
+                                                    &Thousands(cow.copied), &Thousands(cow.reused), &elf::cached()]));
+}
+
+fn vmmap(args: &[&str]) {
+    let address = |arg: &str| u64::from_str_radix(arg.trim_start_matches("0x").replace('_', "").as_str(), 16).ok();
+    match args {
+        [] => shell_print!("{}", debug::dump_page_table(0..u64::MAX)),
+        [addr] => match address(addr).and_then(|addr| VirtAddr::try_new(addr).ok()) {
+            Some(addr) => match debug::translate(addr) {
+                Some(translation) => shell_println!("{:#018x} -> {:#014x} in a {} page, {}", addr.as_u64(),
+                                                    translation.phys.as_u64(), debug::page_label(translation.page_size),
+                                                    debug::Flags(translation.flags)),
+                None => shell_println!("{}", i18n::fill(Msg::NotMapped, &[&format!("{:#018x}", addr.as_u64())])),
+            },
+            None => shell_println!("{}", i18n::fill(Msg::BadAddress, &[&"vmmap", addr])),
+        },
+        [start, end] => match (address(start), address(end)) {
+            (Some(start), Some(end)) if start < end => shell_print!("{}", debug::dump_page_table(start..end)),
+            _ => shell_println!("{}", i18n::fill(Msg::BadRange, &[&"vmmap", start, end])),
+        },
+        _ => usage("vmmap [<addr> | <start> <end>]"),
+    }
+}
+
+fn assets_command(_args: &[&str]) {
+    for info in assets::list() {
+        match info.cached {
+            0 => shell_println!("{:<28} {}", info.name, info.source),
+            bytes => shell_println!("{:<28} {:<10} {}", info.name, info.source, i18n::fill(Msg::AssetCached, &[&Size(bytes as u64)])),
+        }
+    }
+    let stats = assets::stats();
+    shell_println!("{}", i18n::fill(Msg::AssetCacheStats, &[&stats.entries, &Size(stats.bytes as u64), &Size(assets::CACHE_LIMIT as u64),
+                                                           &Thousands(stats.hits), &Thousands(stats.misses)]));
+}
+
+fn irq(_args: &[&str]) {
+    shell_println!("vector  source          count          last  handlers");
+    for stats in interrupts::irq::stats() {
+        match stats.line {
+            Some(line) => shell_print!("{:>6}  IRQ {:<3}", stats.vector, line),
+            None => shell_print!("{:>6}  APIC   ", stats.vector),
+        }
+        shell_print!("  {:>12}", Thousands(stats.count));
+        match stats.last {
+            Some(last) => shell_print!("  {:>12}", Clock(last)),
+            None => shell_print!("  {:>12}", "-"),
+        }
+        let names: Vec<&str> = stats.names().collect();
+        shell_println!("  {}", names.join(", "));
+    }
+    for queue in interrupts::deferred::stats() {
+        shell_println!("deferred {:<6}  {} pending, {} done, {} dropped", queue.priority, queue.pending,
+                       Thousands(queue.done), Thousands(queue.dropped));
+    }
+}
+
+fn ioports(_args: &[&str]) {
+    for claim in port::claims() {
+        shell_println!("{:04x}-{:04x}  {}", claim.start, claim.last(), claim.owner);
+    }
+}
+
+fn lsinput(_args: &[&str]) {
+    shell_println!("id  kind      events  name");
+    for device in input::devices() {
+        shell_println!("{:>2}  {:<8} {:>7}  {}", device.id.0, device.kind.name(), Thousands(device.events), device.name);
+    }
+    shell_println!("{}", i18n::fill(Msg::Subscribers, &[&input::subscribers()]));
+}
+
+fn lspci(args: &[&str]) {
+    let verbose = args.contains(&"-v");
+    for device in pci_enumerate() {
+        shell_println!("{:02x}:{:02x}.{} {} [{:02x}{:02x}]: {:04x}:{:04x} (rev {:02x})",
+                       device.bus, device.device, device.function,
+                       device.class_name(), device.class, device.subclass,
+                       device.vendor_id, device.device_id, device.revision);
+        if !verbose {
+            continue;
+        }
+        shell_println!("\tProg-if: {:02x}", device.prog_if);
+        let (line, pin) = device.interrupt();
+        if (1..=4).contains(&pin) {
+            shell_println!("\tInterrupt: pin {} routed to IRQ {}", (b'A' + pin - 1) as char, line);
+        }
+        // 桥的头部只有 2 个 BAR
+        let header_type = (device.config_read_u32(0x0C) >> 16) as u8 & 0x7F;
+        let bars = if header_type == 0 { 6 } else { 2 };
+        let mut index = 0;
+        while index < bars {
+            let bar = device.bar(index);
+            if bar & 1 == 1 {
+                shell_println!("\tBAR{}: I/O ports at {:#x}", index, bar & !0x3);
+            } else if bar & !0xF != 0 {
+                // 64 位的 BAR 占两个位置
+                let wide = (bar >> 1) & 0x3 == 0x2;
+                let high = if wide && index + 1 < bars { device.bar(index + 1) as u64 } else { 0 };
+                let prefetchable = if bar & 0x8 != 0 { ", prefetchable" } else { "" };
+                shell_println!("\tBAR{}: memory at {:#x} ({}-bit{})", index,
+                               high << 32 | (bar & !0xF) as u64, if wide { 64 } else { 32 }, prefetchable);
+                if wide {
+                    index += 1;
+                }
+            }
+            index += 1;
+        }
+        if let Some(driver) = pci::driver_of(&device) {
+            shell_println!("\tKernel driver in use: {}", driver);
+        }
+    }
+}
+
+fn rescan(_args: &[&str]) {
+    let events = hotplug::rescan();
+    if events.is_empty() {
+        shell_println!("{}", i18n::tr(Msg::NoChanges));
+    }
+    for event in events {
+        let (sign, d) = match event {
+            DeviceEvent::Added(d) => ('+', d),
+            DeviceEvent::Removed(d) => ('-', d),
+        };
+        shell_println!("{} {:02x}:{:02x}.{} {:04x}:{:04x}",
+                       sign, d.bus, d.device, d.function, d.vendor_id, d.device_id);
+    }
+}
+
+fn lsblk(_args: &[&str]) {
+    let devices = block::list();
+    if devices.is_empty() {
+        shell_println!("{}", i18n::tr(Msg::NoBlockDevices));
+    }
+    for device in devices {
+        shell_println!("{:<8} {:>10} {}", device.name,
+                       Size(device.block_count * device.block_size as u64), device.model);
+    }
+}
+
+fn sync(_args: &[&str]) {
+    if let Err(error) = cache::sync() {
+        shell_println!("sync: {}", error);
+    }
+    for info in cache::list() {
+        let stats = info.stats;
+        let lookups = stats.hits + stats.misses;
+        let ratio = if lookups == 0 { 0 } else { stats.hits * 100 / lookups };
+        shell_println!("{:<8} {} cached, {} dirty, {}% hits, {} read ahead, {} reads, {} writes", info.device,
+                       Size((info.cached * info.block_size) as u64), info.dirty, ratio,
+                       stats.read_ahead, stats.device_reads, stats.device_writes);
+    }
+}
+
+fn uptime_command(_args: &[&str]) {
+    shell_println!("up {}", Clock(uptime()));
+}
+
+fn locale(args: &[&str]) {
+    match args {
+        [] => {
+            let locale = format::locale();
+            shell_println!("{} (group {:?}, decimal {:?})", Thousands(1234567), locale.group, locale.decimal);
+        }
+        [name] => match Locale::by_name(name) {
+            Some(locale) => format::set_locale(locale),
+            None => shell_println!("{}", i18n::fill(Msg::UnknownLocale, &[&"locale", name])),
+        },
+        _ => usage("locale [c|en|de|fr]"),
+    }
+}
+
+fn lang(args: &[&str]) {
+    match args {
+        [] => {
+            let language = i18n::language();
+            shell_println!("{}", i18n::fill(Msg::LanguageCurrent, &[&language.name(), &language.native_name()]));
+        }
+        [name] => match Language::by_name(name) {
+            Some(language) => i18n::set_language(language),
+            None => shell_println!("{}", i18n::fill(Msg::LanguageUnknown, &[name])),
+        },
+        _ => usage("lang [en|zh]"),
+    }
+}
+
+// 和 Linux 一样按 系统名、主机名、发行版本、版本、硬件 的顺序输出选中的字段，不带参数时只输出系统名
+fn uname(args: &[&str]) {
+    let mut fields = [false; 5];
+    for arg in args {
+        let Some(flags) = arg.strip_prefix('-').filter(|flags| !flags.is_empty()) else {
+            usage("uname [-asnrvm]");
+            return;
+        };
+        for flag in flags.chars() {
+            match "snrvm".find(flag) {
+                Some(index) => fields[index] = true,
+                None if flag == 'a' => fields = [true; 5],
+                None => {
+                    shell_println!("{}", i18n::fill(Msg::UnknownOption, &[&"uname", &flag]));
+                    return;
+                }
+            }
+        }
+    }
+    if fields == [false; 5] {
+        fields[0] = true;
+    }
+    let build = format!("#{} {} UTC", version::GIT_HASH, version::build_time());
+    let values = [version::NAME, "cjn", version::VERSION, &build, version::MACHINE];
+    let selected: Vec<&str> = values.iter().zip(fields).filter(|(_, on)| *on).map(|(value, _)| *value).collect();
+    shell_println!("{}", selected.join(" "));
+}
+
+fn about(_args: &[&str]) {
+    shell_println!("{}", Banner);
+    if about::show().is_err() {
+        out_of_memory("about");
+    }
+}
+
+fn boot(_args: &[&str]) {
+    for record in boot::records() {
+        shell_println!("{:<12} {:>5} ms  {}", record.name, record.time.as_millis(), record.status);
+    }
+}
+
+fn cpus(args: &[&str]) {
+    match args {
+        [] => {
+            for cpu in smp::cpus() {
+                shell_println!("cpu{}  apic {:<3} {} tasks{}", cpu.id, cpu.apic_id, cpu.tasks(),
+                               if cpu.is_bsp() { "  (boot)" } else { "" });
+            }
+            shell_println!("{}", i18n::fill(Msg::TasksWaiting, &[&smp::task::pending()]));
+        }
+        ["test", count] => {
+            let Ok(count) = count.parse::<usize>() else {
+                shell_println!("{}", i18n::fill(Msg::InvalidNumber, &[&"cpus"]));
+                return;
+            };
+            // 每个任务做一点计算，在串口日志里报告自己在哪个 CPU 上执行
+            for i in 0..count {
+                smp::task::spawn(move || {
+                    let sum = (0..1_000_000u64).fold(0u64, |sum, n| sum.wrapping_add(n * n));
+                    log::info!("task {} finished on cpu{} ({})", i, smp::cpu_id(), sum);
+                });
+            }
+        }
+        _ => usage("cpus [test <count>]"),
+    }
+}
+
+// 命令在这里同步执行，只是输出不上屏，留在离屏控制台里
+fn bg(args: &[&str]) {
+    if args.is_empty() || args[0] == "bg" {
+        usage("bg <command> [args]");
+        return;
+    }
+    let line = args.join(" ");
+    let id = console::create(&line);
+    console::capture(id, || super::execute(&line));
+    let lines = console::lines(id).map_or(0, |lines| lines.len());
+    shell_println!("{}", i18n::fill(Msg::JobDone, &[&id, &lines]));
+}
+
+fn jobs(_args: &[&str]) {
+    for (id, name) in console::list() {
+        let lines = console::lines(id).map_or(0, |lines| lines.len());
+        shell_println!("{}", i18n::fill(Msg::JobListed, &[&id, &format!("{:>5}", lines), &name]));
+    }
+}
+
+fn output(args: &[&str]) {
+    let (id, flag) = match args {
+        [id] => (id, None),
+        [id, flag @ ("-w" | "-d")] => (id, Some(*flag)),
+        _ => {
+            usage("output <id> [-w|-d]");
+            return;
+        }
+    };
+    let Ok(id) = id.parse::<console::ConsoleId>() else {
+        shell_println!("{}", i18n::fill(Msg::InvalidId, &[&"output", id]));
+        return;
+    };
+    let found = match flag {
+        None => console::lines(id).map(|lines| lines.iter().for_each(|line| shell_println!("{}", line))).is_some(),
+        Some("-d") => console::destroy(id).is_some(),
+        _ => match job_output::show(id) {
+            Ok(found) => found,
+            Err(_) => {
+                out_of_memory("output");
+                return;
+            }
+        },
+    };
+    if !found {
+        shell_println!("{}", i18n::fill(Msg::NoJob, &[&"output", &id]));
+    }
+}
+
+fn edit(args: &[&str]) {
+    match args {
+        [] => {
+            let files = apps::edit::files();
+            if files.is_empty() {
+                shell_println!("{}", i18n::tr(Msg::NoSavedFiles));
+            }
+            for (name, size) in files {
+                shell_println!("{:>10}  {}", Size(size as u64), name);
+            }
+        }
+        [name] => {
+            if apps::edit::open(name).is_err() {
+                out_of_memory("edit");
+            }
+        }
+        _ => usage("edit [<name>]"),
+    }
+}
+
+fn snake(_args: &[&str]) {
+    if apps::snake::show().is_err() {
+        out_of_memory("snake");
+    }
+}
+
+fn top(_args: &[&str]) {
+    if sysmon::show().is_err() {
+        out_of_memory("top");
+    }
+}
+
+fn ifconfig(args: &[&str]) {
+    let Some(current) = net::config() else {
+        shell_println!("{}", i18n::fill(Msg::NoNetworkDevice, &[&"ifconfig"]));
+        return;
+    };
+    let config = match args {
+        [] => None,
+        ["dhcp"] => {
+            if let Err(error) = dhcp::run(Duration::from_secs(5)) {
+                shell_println!("{}", i18n::fill(Msg::DhcpFailed, &[&"ifconfig", &error]));
+            }
+            None
+        }
+        [address] | [address, _] => {
+            let gateway = match args.get(1) {
+                Some(gateway) => Ipv4Addr::parse(gateway),
+                None => Some(current.gateway),
+            };
+            let parsed = address.split_once('/')
+                .and_then(|(address, prefix)| Some((Ipv4Addr::parse(address)?, prefix.parse::<u8>().ok().filter(|&p| p <= 32)?)));
+            match (parsed, gateway) {
+                (Some((address, prefix)), Some(gateway)) => {
+                    Some(Config { address, netmask: Ipv4Addr::netmask(prefix), gateway, dns: current.dns })
+                }
+                _ => {
+                    shell_println!("{}", i18n::fill(Msg::InvalidAddress, &[&"ifconfig"]));
+                    return;
+                }
+            }
+        }
+        _ => {
+            usage("ifconfig [dhcp|<ip>/<prefix> [gateway]]");
+            return;
+        }
+    };
+    if let Some(config) = config {
+        net::configure(config);
+        dhcp::forget();
+    }
+    let Some(info) = net::info() else { return };
+    shell_println!("eth0  {}  ether {}", info.driver, info.mac);
+    shell_println!("      inet {}  netmask {}  gateway {}  dns {}",
+                   info.config.address, info.config.netmask, info.config.gateway, info.config.dns);
+    match dhcp::lease() {
+        Some(lease) => shell_println!("      DHCP lease from {}, {} left", lease.server, Clock(lease.remaining())),
+        None => shell_println!("      static address"),
+    }
+    shell_println!("      RX packets {}  bytes {}", Thousands(info.stats.rx_packets), Size(info.stats.rx_bytes));
+    shell_println!("      TX packets {}  bytes {}  dropped {}",
+                   Thousands(info.stats.tx_packets), Size(info.stats.tx_bytes), Thousands(info.stats.dropped));
+    for (ip, mac) in info.neighbors {
+        shell_println!("      neighbor {} at {}", ip, mac);
+    }
+}
+
+fn host(args: &[&str]) {
+    let [name] = args else {
+        usage("host <name>");
+        return;
+    };
+    match dns::resolve(name) {
+        Ok(address) => shell_println!("{}", i18n::fill(Msg::HostAddress, &[name, &address])),
+        Err(error) => shell_println!("host: {}: {}", name, error),
+    }
+}
+
+// 每次 ping 用不同的标识，迟到的回应不会算到下一次头上
+static PING_IDENT: AtomicU16 = AtomicU16::new(1);
+
+fn ping(args: &[&str]) {
+    let (count, target) = match args {
+        [target] => (4, *target),
+        ["-c", count, target] => match count.parse::<u16>() {
+            Ok(count) if count > 0 => (count, *target),
+            _ => {
+                shell_println!("{}", i18n::fill(Msg::InvalidCount, &[&"ping"]));
+                return;
+            }
+        },
+        _ => {
+            usage("ping [-c <count>] <host>");
+            return;
+        }
+    };
+    let destination = match dns::resolve(target) {
+        Ok(destination) => destination,
+        Err(error) => {
+            shell_println!("ping: {}: {}", target, error);
+            return;
+        }
+    };
+    let ident = PING_IDENT.fetch_add(1, Ordering::Relaxed);
+    shell_println!("PING {} {} bytes of data", destination, icmp::PING_PAYLOAD);
+    let mut received = 0;
+    for seq in 1..=count {
+        let start = uptime();
+        match icmp::ping(destination, ident, seq, Duration::from_secs(1)) {
+            Ok(Some(reply)) => {
+                received += 1;
+                shell_println!("{} bytes from {}: icmp_seq={} ttl={} time={}",
+                               reply.len, reply.from, reply.seq, reply.ttl, Elapsed(reply.time));
+            }
+            Ok(None) => shell_println!("request timeout for icmp_seq {}", seq),
+            Err(error) => {
+                shell_println!("ping: {}", error);
+                return;
+            }
+        }
+        // 每秒一个
+        while seq < count && uptime() - start < Duration::from_secs(1) {
+            net::poll();
+            x86_64::instructions::hlt();
+        }
+    }
+    shell_println!("--- {} ping statistics ---", destination);
+    shell_println!("{} packets transmitted, {} received, {}% packet loss",
+                   count, received, (count - received) as u32 * 100 / count as u32);
+}
+
+// 下载超过这个时间算失败
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn wget(args: &[&str]) {
+    let [url] = args else {
+        usage("wget <url>");
+        return;
+    };
+    match http::get(url, HTTP_TIMEOUT) {
+        Ok(response) => {
+            shell_println!("HTTP {} {}, {}", response.status, response.reason, Size(response.body.len() as u64));
+            shell_println!("{}", response.text());
+        }
+        Err(error) => shell_println!("wget: {}: {}", url, error),
+    }
+}
+
+fn fetch(args: &[&str]) {
+    let [url] = args else {
+        usage("fetch <url>");
+        return;
+    };
+    match http::get(url, HTTP_TIMEOUT) {
+        Ok(response) => {
+            if fetch::show(url, &response).is_err() {
+                out_of_memory("fetch");
+            }
+        }
+        Err(error) => shell_println!("fetch: {}: {}", url, error),
+    }
+}
+
+fn beep(args: &[&str]) {
+    let number = |arg: Option<&&str>, default: u32| arg.map_or(Some(default), |arg| arg.parse::<u32>().ok().filter(|&n| n > 0));
+    match (number(args.first(), 880), number(args.get(1), 200)) {
+        (Some(hz), Some(ms)) if args.len() <= 2 => sound::beep(hz, Duration::from_millis(ms as u64)),
+        _ => usage("beep [<hz> [<ms>]]"),
+    }
+}
+
+fn play(args: &[&str]) {
+    match args {
+        ["stop"] => sound::stop(),
+        [name] => {
+            if let Err(error) = sound::play_asset(name) {
+                shell_println!("play: {}: {}", name, error);
+            }
+        }
+        _ => usage("play <name>|stop"),
+    }
+}
+
+fn run(args: &[&str]) {
+    let [name] = args else {
+        for program in programs::all() {
+            shell_println!("{:<10}{}", program.name, program.description);
+        }
+        return;
+    };
+    let Some(program) = programs::find(name) else {
+        shell_println!("{}", i18n::fill(Msg::NoProgram, &[&"run", name]));
+        return;
+    };
+    match usermode::run(&program) {
+        Ok(Exit::Code(0)) => {}
+        Ok(Exit::Code(code)) => shell_println!("{}", i18n::fill(Msg::ProgramExited, &[name, &code])),
+        Ok(Exit::Killed(reason)) => shell_println!("{}", i18n::fill(Msg::ProgramKilled, &[name, &reason])),
+        Err(error) => shell_println!("run: {:?}", error),
+    }
+}
+
+fn at(args: &[&str]) {
+    match args {
+        [] => {
+            for (id, time) in alarm::pending() {
+                shell_println!("{:<4} {}", id.as_u64(), to_local(time));
+            }
+        }
+        ["cancel", id] => {
+            let cancelled = id.parse::<u64>().is_ok_and(|id| alarm::cancel(AlarmId::from(id)));
+            if !cancelled {
+                shell_println!("{}", i18n::fill(Msg::NoAlarm, &[&"at", id]));
+            }
+        }
+        [time, message @ ..] if !message.is_empty() => {
+            let Some((hour, minute, second)) = parse_time(time) else {
+                shell_println!("{}", i18n::fill(Msg::InvalidTime, &[&"at", time]));
+                return;
+            };
+            let message = message.join(" ");
+            let at = next_local(hour, minute, second);
+            let id = alarm::schedule_at(at, move || shell_println!("\n[at] {}", message));
+            shell_println!("{}", i18n::fill(Msg::AlarmAt, &[&id.as_u64(), &to_local(at)]));
+        }
+        _ => usage("at [<hh:mm[:ss]> <message>|cancel <id>]"),
+    }
+}
+
+fn remind(args: &[&str]) {
+    let [time, text @ ..] = args else {
+        usage("remind <hh:mm[:ss]> <text>");
+        return;
+    };
+    let Some((hour, minute, second)) = parse_time(time).filter(|_| !text.is_empty()) else {
+        usage("remind <hh:mm[:ss]> <text>");
+        return;
+    };
+    let at = next_local(hour, minute, second);
+    let id = reminder::remind_at(at, &text.join(" "));
+    shell_println!("{}", i18n::fill(Msg::ReminderAt, &[&id.as_u64(), &to_local(at)]));
+}
+
+// 解析 hh:mm 或 hh:mm:ss
+fn parse_time(text: &str) -> Option<(u8, u8, u8)> {
+    let parts: Option<Vec<u8>> = text.split(':').map(|part| part.parse().ok()).collect();
+    let (hour, minute, second) = match parts?.as_slice() {
+        [hour, minute] => (*hour, *minute, 0),
+        [hour, minute, second] => (*hour, *minute, *second),
+        _ => return None,
+    };
+    (hour < 24 && minute < 60 && second < 60).then_some((hour, minute, second))
+}
+
+fn clear(_args: &[&str]) {
+    crate::io::clear_screen();
+}
+
+fn mode(args: &[&str]) {
+    let parsed: Option<Vec<usize>> = args.iter().map(|arg| arg.parse().ok()).collect();
+    let (width, height, bpp) = match parsed.as_deref() {
+        Some([]) => {
+            match graphic::backend::active() {
+                Some(backend) => shell_println!("{}x{}x{} on {}", graphic::width(), graphic::height(), graphic::bpp(), backend.name()),
+                None => shell_println!("{}", i18n::tr(Msg::TextMode)),
+            }
+            return;
+        }
+        Some([width, height]) => (*width, *height, graphic::bpp()),
+        Some([width, height, bpp]) => (*width, *height, *bpp),
+        _ => {
+            usage("mode <width> <height> [bpp]");
+            return;
+        }
+    };
+    if let Err(error) = crate::gui::set_mode(width, height, bpp) {
+        shell_println!("mode: {:?}", error);
+    }
+}
+
+// 导出的 JSON 直接写到串口，不经过终端
+fn trace(args: &[&str]) {
+    match args {
+        ["start"] => crate::trace::start(),
+        ["stop"] => crate::trace::stop(),
+        ["clear"] => crate::trace::clear(),
+        ["dump"] => crate::trace::dump_serial(),
+        [] => {
+            let (events, dropped) = crate::trace::stats();
+            shell_println!("{}", i18n::fill(Msg::TraceStatus, &[&on_off(crate::trace::is_enabled()),
+                                                               &Thousands(events as u64), &Thousands(dropped as u64)]));
+        }
+        _ => usage("trace [start|stop|clear|dump]"),
+    }
+}
+
+// folded 的输出写到串口，宿主机上用 flamegraph.pl 画成火焰图
+fn perf(args: &[&str]) {
+    let order = match args {
+        ["start"] => return crate::perf::start(),
+        ["stop"] => return crate::perf::stop(),
+        ["clear"] => return crate::perf::clear(),
+        ["folded"] => return crate::perf::dump_folded_serial(),
+        ["report"] => Order::Total,
+        ["report", "own"] => Order::Own,
+        [] => {
+            let (samples, dropped) = crate::perf::stats();
+            shell_println!("{}", i18n::fill(Msg::ProfileStatus, &[&on_off(crate::perf::is_enabled()),
+                                                                 &Thousands(samples as u64), &Thousands(dropped as u64)]));
+            return;
+        }
+        _ => {
+            usage("perf [start|stop|clear|report [own]|folded]");
+            return;
+        }
+    };
+    let entries = crate::perf::summarize(&crate::perf::samples(), order);
+    if entries.is_empty() {
+        shell_println!("{}", i18n::tr(Msg::NoSamples));
+        return;
+    }
+    let time = |cycles| Elapsed(crate::perf::cycles_to_duration(cycles));
+    shell_println!("{:<20} {:>10} {:>10} {:>10} {:>10} {:>10}", "scope", "calls", "total", "own", "average", "max");
+    for entry in entries {
+        shell_println!("{:<20} {:>10} {:>10} {:>10} {:>10} {:>10}", entry.name, Thousands(entry.calls as u64),
+                       time(entry.total), time(entry.own), time(entry.total / entry.calls as u64), time(entry.max));
+    }
+}
+
+// 测量在后台进行，结束后日志里有一行摘要，再执行一次不带参数的 latency 看每一步的明细
+fn latency(args: &[&str]) {
+    let (source, count) = match args {
+        [] => {
+            match crate::trace::latency::last_report() {
+                Some(report) => shell_print!("{}", report),
+                None => shell_println!("{}", i18n::tr(Msg::NoMeasurement)),
+            }
+            return;
+        }
+        [source] => (source, Some(10)),
+        [source, count] => (source, count.parse().ok()),
+        _ => (&"", None),
+    };
+    let (Some(source), Some(count)) = (Source::by_name(source), count) else {
+        usage("latency [key|mouse [count]]");
+        return;
+    };
+    if !crate::trace::latency::start(source, count) {
+        shell_println!("{}", i18n::fill(Msg::MeasurementRunning, &[&"latency"]));
+        return;
+    }
+    shell_println!("{}", i18n::fill(Msg::Measuring, &[&Thousands(count.min(MAX_SAMPLES) as u64), &source.name()]));
+}
+
+// 录制的脚本写到串口，宿主机保存下来给测试启动用
+fn input(args: &[&str]) {
+    match args {
+        ["record"] => replay::start_recording(),
+        ["stop"] => replay::stop(),
+        ["replay"] => replay::start_replay(),
+        ["dump"] => {
+            let _ = replay::dump(&mut SerialStream);
+        }
+        [] => shell_println!("{}{}", i18n::fill(Msg::EventsRecorded, &[&replay::len()]),
+                             if replay::is_replaying() { i18n::tr(Msg::Replaying) } else { "" }),
+        _ => usage("input [record|stop|replay|dump]"),
+    }
+}
+
+fn dmesg(args: &[&str]) {
+    const USAGE: &str = "dmesg [-c] [-l error|warn|info|debug|trace] [-w]";
+    let (mut clear, mut window, mut level) = (false, false, LevelFilter::Trace);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "-c" => clear = true,
+            "-w" => window = true,
+            "-l" => match args.next().and_then(|level| level.parse().ok()) {
+                Some(filter) => level = filter,
+                None => {
+                    usage(USAGE);
+                    return;
+                }
+            },
+            _ => {
+                usage(USAGE);
+                return;
+            }
+        }
+    }
+    if window {
+        if log_viewer::show().is_err() {
+            out_of_memory("dmesg");
+        }
+        return;
+    }
+    let overwritten = ring::overwritten();
+    if overwritten > 0 {
+        shell_println!("{}", i18n::fill(Msg::RecordsOverwritten, &[&Thousands(overwritten)]));
+    }
+    for entry in ring::entries(0, level) {
+        shell_println!("{}", entry);
+    }
+    if clear {
+        ring::clear();
+    }
+}
+
+fn watchdog(args: &[&str]) {
+    match args {
+        [] => {
+            let enabled = i18n::tr(if watchdog::is_enabled() { Msg::Enabled } else { Msg::Disabled });
+            shell_println!("{}", i18n::fill(Msg::WatchdogStatus, &[&enabled, &watchdog::timeout().as_secs(),
+                                                                  &on_off(watchdog::panics()), &watchdog::lockups()]));
+            for status in watchdog::status() {
+                shell_print!("{}", i18n::fill(Msg::WatchdogFed, &[&status.cpu, &Elapsed(status.since)]));
+                match status.location {
+                    Some(location) => shell_println!("{}", i18n::fill(Msg::WatchdogAt, &[&location])),
+                    None => shell_println!(),
+                }
+            }
+        }
+        ["on"] => watchdog::set_enabled(true),
+        ["off"] => watchdog::set_enabled(false),
+        ["panic", "on"] => watchdog::set_panic(true),
+        ["panic", "off"] => watchdog::set_panic(false),
+        [seconds] => match seconds.parse() {
+            Ok(seconds) if seconds > 0 => watchdog::set_timeout(Duration::from_secs(seconds)),
+            _ => usage("watchdog [on|off|<seconds>|panic on|off]"),
+        },
+        _ => usage("watchdog [on|off|<seconds>|panic on|off]"),
+    }
+}
+
+fn chvt(args: &[&str]) {
+    let Some(number) = args.first() else {
+        shell_println!("{}", i18n::fill(Msg::VtStatus, &[&vt::active(), &Tty::SHELL, &Tty::LOG]));
+        return;
+    };
+    match number.parse::<usize>().ok().and_then(|n| n.checked_sub(1)).and_then(Tty::new) {
+        Some(tty) => vt::switch(tty),
+        None => usage(&format!("chvt [1-{}]", vt::COUNT)),
+    }
+}
+
+fn loadkeys(args: &[&str]) {
+    match args {
+        [] => {
+            let current = keymap::current();
+            for keymap in keymap::keymaps() {
+                let mark = if core::ptr::eq(keymap, current) { '*' } else { ' ' };
+                shell_println!("{} {:<4} {}", mark, keymap.name, keymap.description);
+            }
+            shell_println!("{}", i18n::fill(Msg::LockKeys, &[&on_off(keymap::caps_lock()), &on_off(keymap::num_lock())]));
+        }
+        [name] if keymap::load(name) => {}
+        _ => usage("loadkeys [us|uk|de]"),
+    }
+}
+
+fn zoom(args: &[&str]) {
+    let Some(size) = args.first() else {
+        shell_println!("{}", i18n::fill(Msg::FontSize, &[&graphic::text::zoom()]));
+        return;
+    };
+    match size.parse() {
+        Ok(size) if graphic::text::set_zoom(size) => {}
+        _ => usage("zoom [12|16|24|32]"),
+    }
+}
+
+fn fps(args: &[&str]) {
+    match args {
+        [] => {
+            let stats = frame::stats();
+            match stats.target {
+                0 => shell_print!("{}", i18n::tr(Msg::FpsUnlimited)),
+                target => shell_print!("{}", i18n::fill(Msg::FpsTarget, &[&target])),
+            }
+            shell_println!("{}", i18n::fill(Msg::FpsStats, &[&stats.fps, &Thousands(stats.frames), &Thousands(stats.skipped)]));
+        }
+        ["show"] => frame::set_overlay(true),
+        ["hide"] => frame::set_overlay(false),
+        [target] => match target.parse() {
+            Ok(target) => frame::set_fps(target),
+            Err(_) => usage("fps [<target>|show|hide]"),
+        },
+        _ => usage("fps [<target>|show|hide]"),
+    }
+}
+
+fn screensaver_command(args: &[&str]) {
+    match args {
+        [] => match screensaver::timeout() {
+            Some(timeout) => shell_println!("{}", i18n::fill(Msg::ScreensaverAfter, &[&timeout.as_secs()])),
+            None => shell_println!("{}", i18n::tr(Msg::ScreensaverOff)),
+        },
+        ["off"] => screensaver::set_timeout(None),
+        [seconds] => match seconds.parse() {
+            Ok(seconds) => screensaver::set_timeout(Some(Duration::from_secs(seconds))),
+            Err(_) => usage("screensaver [<seconds>|off]"),
+        },
+        _ => usage("screensaver [<seconds>|off]"),
+    }
+}
+
+fn lock(_args: &[&str]) {
+    if !screensaver::activate() {
+        shell_println!("{}", i18n::fill(Msg::NeedsGui, &[&"lock"]));
+    }
+}
+
+// 串口很慢，整个屏幕要写几分钟，写的时候画面照常更新
+fn screenshot(args: &[&str]) {
+    let name = match args {
+        [] => "screen",
+        [name] => name,
+        _ => {
+            usage("screenshot [<name>]");
+            return;
+        }
+    };
+    frame::flush();
+    let Ok(shot) = graphic::screenshot() else {
+        out_of_memory("screenshot");
+        return;
+    };
+    shell_println!("{}", i18n::fill(Msg::WritingScreenshot, &[&shot.width(), &shot.height(), &name]));
+    shot.write_serial(name);
+    shell_println!("{}", i18n::tr(Msg::Done));
+}
+
+fn decor(args: &[&str]) {
+    let mut theme = theme::get();
+    let style = &mut theme.window;
+    match args {
+        [] => {
+            shell_println!("{}", i18n::fill(Msg::DecorStatus, &[&style.corner_radius, &style.shadow_radius, &style.shadow_alpha]));
+            return;
+        }
+        ["flat"] => *style = WindowStyle::FLAT,
+        ["default"] => *style = WindowStyle::DEFAULT,
+        [corner, shadow, rest @ ..] if rest.len() <= 1 => {
+            let (Ok(corner), Ok(shadow), Ok(alpha)) = (corner.parse(), shadow.parse(),
+                                                       rest.first().map_or(Ok(style.shadow_alpha), |a| a.parse())) else {
+                shell_println!("{}", i18n::fill(Msg::InvalidNumber, &[&"decor"]));
+                return;
+            };
+            style.corner_radius = corner;
+            style.shadow_radius = shadow;
+            style.shadow_alpha = alpha;
+        }
+        _ => {
+            usage("decor [flat|default|<corner> <shadow> [alpha]]");
+            return;
+        }
+    }
+    theme::set(theme);
+}
+
+fn theme_command(args: &[&str]) {
+    match args {
+        [] => {
+            let theme = theme::get();
+            let color = |color: Option<Rgb888>| color.map_or(String::from("none"), |c| format!("#{:02x}{:02x}{:02x}", c.r(), c.g(), c.b()));
+            shell_println!("{}", i18n::fill(Msg::ThemeColors, &[&color(theme.foreground), &color(theme.background)]));
+            shell_println!("{}", i18n::fill(Msg::ThemeWindow, &[&theme.window.corner_radius, &theme.window.shadow_radius,
+                                                               &format!("{:?}", theme.window.shadow_offset), &theme.window.shadow_alpha]));
+        }
+        ["reload"] => match gui::reload_theme() {
+            Ok(()) => shell_println!("{}", i18n::tr(Msg::ThemeReloaded)),
+            Err(error) => shell_println!("{}", i18n::fill(Msg::ThemeReloadFailed, &[&"theme", &error])),
+        },
+        _ => usage("theme [reload]"),
+    }
+}
+
+fn selftest(_args: &[&str]) {
+    let passed = graphic::selftest::font();
+    shell_println!("{}", i18n::tr(if passed { Msg::SelftestPassed } else { Msg::SelftestFailed }));
+}
+
+fn addr2line(args: &[&str]) {
+    if args.is_empty() {
+        usage("addr2line <addr>...");
+        return;
+    }
+    if !symbols::available() {
+        shell_println!("{}", i18n::fill(Msg::NoSymbols, &[&"addr2line"]));
+        return;
+    }
+    for arg in args {
+        match usize::from_str_radix(arg.trim_start_matches("0x").replace('_', "").as_str(), 16) {
+            Ok(address) => match symbols::resolve(address) {
+                Some(symbol) => shell_println!("{:#018x} {}", address, symbol),
+                None => shell_println!("{:#018x} ??", address),
+            },
+            Err(_) => shell_println!("{}", i18n::fill(Msg::BadAddress, &[&"addr2line", arg])),
+        }
+    }
+}
+
+fn gdb(_args: &[&str]) {
+    if !gdb::enabled() {
+        shell_println!("{}", i18n::fill(Msg::NoCom2, &[&"gdb"]));
+        return;
+    }
+    shell_println!("{}", i18n::tr(Msg::WaitingForGdb));
+    gdb::breakpoint();
+}
+
+// 通过键盘控制器拉低 CPU 复位线
+fn reboot(_args: &[&str]) {
+    const KBC_STATUS: Port<u8> = Port::new(0x64);
+    const KBC_INPUT_FULL: u8 = 0x02;
+    const KBC_RESET_CPU: u8 = 0xFE;
+
+    shell_println!("{}", i18n::tr(Msg::Rebooting));
+    unsafe {
+        while KBC_STATUS.read() & KBC_INPUT_FULL != 0 {}
+        KBC_STATUS.write(KBC_RESET_CPU);
+    }
+    crate::hlt_loop();
+}
@@ -0,0 +1,166 @@
+// 内核 shell
+// 同时从键盘和串口读取命令行，输出写到当前控制台并同步到串口。
+// 命令通过注册表查找，其他子系统可以用 register 添加自己的命令
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::time::Duration;
+
+use lazy_static::lazy_static;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::gui::clipboard;
+use crate::i18n::{self, Msg};
+use crate::io::keyboard::KeyboardStream;
+use crate::io::qemu::SerialStream;
+use crate::trace::latency::{self, Source};
+
+mod commands;
+
+const PROMPT: &str = "cjn> ";
+// 没有输入时最多等多久再做一轮空闲时的轮询
+const IDLE_WAIT: Duration = Duration::from_millis(5);
+
+/// shell 命令，`run` 的参数不含命令名本身
+#[derive(Clone, Copy)]
+pub struct Command {
+    pub name: &'static str,
+    /// help 列出的说明，按当前语言显示
+    pub help: Msg,
+    pub run: fn(&[&str]),
+}
+
+lazy_static! {
+    static ref COMMANDS: Mutex<Vec<Command>> = Mutex::new(Vec::from(commands::BUILTINS));
+}
+
+/// 注册命令，重名时替换已有的命令
+pub fn register(command: Command) {
+    let mut commands = COMMANDS.lock();
+    match commands.iter_mut().find(|c| c.name == command.name) {
+        Some(existing) => *existing = command,
+        None => commands.push(command),
+    }
+}
+
+/// 所有已注册的命令
+pub fn commands() -> Vec<Command> {
+    COMMANDS.lock().clone()
+}
+
+/// 输出到当前控制台，同时同步到串口，方便通过 `-serial stdio` 使用 shell
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    crate::io::_print(args);
+    crate::io::qemu::_qemu_print(args);
+}
+
+#[macro_export]
+macro_rules! shell_print {
+    ($($arg:tt)*) => ($crate::shell::_print(format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! shell_println {
+    () => ($crate::shell_print!("\n"));
+    ($($arg:tt)*) => ($crate::shell_print!("{}\n", format_args!($($arg)*)));
+}
+
+// 读取一行输入，键盘和串口哪边有输入就用哪边，支持退格、Ctrl+C 放弃和 Ctrl+V 粘贴
+fn read_line() -> String {
+    let mut keyboard = KeyboardStream;
+    let mut serial = SerialStream;
+    let mut line = String::new();
+    loop {
+        crate::debug::watchdog::feed();
+        // 先执行中断处理函数留下的工作，键盘的扫描码在这里解码成字符
+        crate::interrupts::deferred::run();
+        let ch = match keyboard.try_read() {
+            // 图形界面中有控件拥有焦点时，键盘输入交给控件
+            Some(ch) if crate::gui::widgets::dispatch_key(ch) => {
+                latency::handled(Source::Key);
+                continue;
+            }
+            Some(ch) => ch,
+            None => match serial.try_read() {
+                Some(byte) => byte as char,
+                None => {
+                    // 空闲时顺便切换虚拟控制台、执行到期的闹钟和控制通道的请求、处理 USB 键盘鼠标的报告、收到的网络包和 GUI 的鼠标事件、推进打开着的程序和输入延迟测量、
+                    // 给声卡补充样本、写回放久了的脏块，再整理一小步堆
+                    crate::io::vt::poll();
+                    crate::io::alarm::poll();
+                    crate::debug::control::poll();
+                    crate::drivers::xhci::poll();
+                    crate::net::poll();
+                    crate::gui::poll();
+                    crate::apps::poll();
+                    latency::poll();
+                    crate::sound::poll();
+                    crate::fs::cache::idle();
+                    crate::allocator::defrag::idle();
+                    // 没有输入时停下来等，最多等 IDLE_WAIT：网卡、声卡和 GUI 的帧没有中断，还要按时轮询
+                    crate::io::INPUT.block_on_condition_timeout(IDLE_WAIT, crate::io::has_input);
+                    continue;
+                }
+            },
+        };
+        match ch {
+            '\r' | '\n' => {
+                shell_print!("\n");
+                return line;
+            }
+            '\x08' | '\x7f' => {
+                if line.pop().is_some() {
+                    shell_print!("\x08 \x08");
+                }
+            }
+            // Ctrl+C 放弃这一行
+            clipboard::COPY => {
+                shell_print!("^C\n");
+                line.clear();
+                return line;
+            }
+            // Ctrl+V 粘贴剪贴板的第一行，不执行
+            clipboard::PASTE => {
+                let text = clipboard::get_text();
+                for ch in text.lines().next().unwrap_or_default().chars().filter(|ch| !ch.is_control()) {
+                    line.push(ch);
+                    shell_print!("{}", ch);
+                }
+            }
+            ch if !ch.is_control() && !crate::io::keyboard::is_special(ch) => {
+                line.push(ch);
+                shell_print!("{}", ch);
+            }
+            _ => {}
+        }
+        latency::handled(Source::Key);
+    }
+}
+
+/// 执行一行命令
+pub fn execute(line: &str) {
+    let args: Vec<&str> = line.split_whitespace().collect();
+    let Some((name, args)) = args.split_first() else {
+        return;
+    };
+    let command = interrupts::without_interrupts(|| {
+        COMMANDS.lock().iter().find(|c| c.name == *name).copied()
+    });
+    match command {
+        Some(command) => (command.run)(args),
+        None => shell_println!("{}", i18n::fill(Msg::CommandNotFound, &[&name])),
+    }
+}
+
+/// shell 主循环
+pub fn run() -> ! {
+    shell_println!("{}", i18n::tr(Msg::ShellHint));
+    loop {
+        shell_print!("{}", PROMPT);
+        let line = read_line();
+        execute(&line);
+    }
+}
@@ -0,0 +1,144 @@
+// Local APIC
+// 每个 CPU 都有一个，寄存器映射在同一个物理地址上，访问到的总是当前 CPU 自己的。
+// 外部中断仍由 8259 PIC 送到 BSP，这里只用它发处理器间中断（IPI）：启动 AP 的 INIT/SIPI 和唤醒空闲的 CPU。
+// CPU 支持 x2APIC 时用 x2APIC 模式：寄存器改成 MSR（0x800 加上 MMIO 偏移除以 16），ICR 合成一个 64 位寄存器，
+// 写入就发出，不用等发送完成
+
+use core::hint::spin_loop;
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use x86_64::instructions::interrupts;
+use x86_64::registers::model_specific::Msr;
+use x86_64::VirtAddr;
+
+use crate::cpu;
+
+// 寄存器偏移
+const ID: usize = 0x020;
+const EOI: usize = 0x0B0;
+const SPURIOUS: usize = 0x0F0;
+const ICR_LOW: usize = 0x300;
+const ICR_HIGH: usize = 0x310;
+
+// x2APIC 模式下寄存器所在的 MSR
+const IA32_APIC_BASE: u32 = 0x1B;
+const X2APIC_MSR_BASE: u32 = 0x800;
+const APIC_GLOBAL_ENABLE: u64 = 1 << 11;
+const X2APIC_ENABLE: u64 = 1 << 10;
+
+/// 寄存器块的大小
+pub const REGISTER_BLOCK_SIZE: u64 = 0x1000;
+/// 伪中断的向量，不需要 EOI
+pub const SPURIOUS_VECTOR: u8 = 0xFF;
+
+const SOFTWARE_ENABLE: u32 = 1 << 8;
+// ICR 的字段
+const DELIVERY_PENDING: u32 = 1 << 12;
+const LEVEL_ASSERT: u32 = 1 << 14;
+const DELIVERY_INIT: u32 = 0b101 << 8;
+const DELIVERY_STARTUP: u32 = 0b110 << 8;
+const ALL_EXCLUDING_SELF: u32 = 0b11 << 18;
+
+// 寄存器映射后的虚拟地址，0 表示还没有映射
+static BASE: AtomicU64 = AtomicU64::new(0);
+// 是否使用 x2APIC 模式
+static X2APIC: AtomicBool = AtomicBool::new(false);
+
+/// 记录寄存器映射到的虚拟地址，并根据 CPU 功能选择 xAPIC 或 x2APIC 模式
+pub fn init(base: VirtAddr) {
+    let x2apic = cpu::features().x2apic;
+    X2APIC.store(x2apic, Ordering::Release);
+    BASE.store(base.as_u64(), Ordering::Release);
+    log::info!("Local APIC in {} mode", if x2apic { "x2APIC" } else { "xAPIC" });
+}
+
+fn is_x2apic() -> bool {
+    X2APIC.load(Ordering::Acquire)
+}
+
+fn msr(offset: usize) -> Msr {
+    Msr::new(X2APIC_MSR_BASE + (offset >> 4) as u32)
+}
+
+/// 寄存器是否已经映射
+pub fn is_ready() -> bool {
+    BASE.load(Ordering::Acquire) != 0
+}
+
+fn register(offset: usize) -> *mut u32 {
+    (BASE.load(Ordering::Acquire) as usize + offset) as *mut u32
+}
+
+fn read(offset: usize) -> u32 {
+    if is_x2apic() {
+        unsafe { msr(offset).read() as u32 }
+    } else {
+        unsafe { read_volatile(register(offset)) }
+    }
+}
+
+fn write(offset: usize, value: u32) {
+    if is_x2apic() {
+        unsafe { msr(offset).write(value as u64) }
+    } else {
+        unsafe { write_volatile(register(offset), value) }
+    }
+}
+
+/// 打开当前 CPU 的 local APIC
+///
+/// x2APIC 模式要在每个 CPU 上单独切换
+pub fn enable() {
+    if is_x2apic() {
+        let mut base = Msr::new(IA32_APIC_BASE);
+        unsafe { base.write(base.read() | APIC_GLOBAL_ENABLE | X2APIC_ENABLE) };
+    }
+    write(SPURIOUS, read(SPURIOUS) & !0xFF | SOFTWARE_ENABLE | SPURIOUS_VECTOR as u32);
+}
+
+/// 当前 CPU 的 local APIC id
+pub fn id() -> u8 {
+    // x2APIC 的 id 有 32 位，MADT 里的 id 只有 8 位，这里同样只取低 8 位
+    if is_x2apic() { read(ID) as u8 } else { (read(ID) >> 24) as u8 }
+}
+
+/// 结束当前中断
+pub fn eoi() {
+    write(EOI, 0);
+}
+
+/// 让 `apic_id` 进入等待 SIPI 的状态
+pub fn send_init(apic_id: u8) {
+    send_ipi(apic_id, DELIVERY_INIT | LEVEL_ASSERT);
+}
+
+/// 让 `apic_id` 从物理地址 `page * 4096` 开始以实模式执行
+pub fn send_startup(apic_id: u8, page: u8) {
+    send_ipi(apic_id, DELIVERY_STARTUP | LEVEL_ASSERT | page as u32);
+}
+
+/// 向 `apic_id` 发送中断
+pub fn send(apic_id: u8, vector: u8) {
+    send_ipi(apic_id, LEVEL_ASSERT | vector as u32);
+}
+
+/// 向除自己以外的所有 CPU 发送中断
+pub fn broadcast(vector: u8) {
+    send_ipi(0, ALL_EXCLUDING_SELF | LEVEL_ASSERT | vector as u32);
+}
+
+// 先写目标再写命令，写低 32 位时发出；两次写入之间不能被同一 CPU 上的中断打断
+fn send_ipi(destination: u8, command: u32) {
+    if is_x2apic() {
+        unsafe { msr(ICR_LOW).write((destination as u64) << 32 | command as u64) };
+        return;
+    }
+    interrupts::without_interrupts(|| {
+        write(ICR_HIGH, (destination as u32) << 24);
+        write(ICR_LOW, command);
+        while read(ICR_LOW) & DELIVERY_PENDING != 0 {
+            spin_loop();
+        }
+    });
+}
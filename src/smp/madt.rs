@@ -0,0 +1,46 @@
+// MADT（ACPI 中签名为 APIC 的表）
+// 列出所有处理器的 local APIC id，以及 local APIC 寄存器的物理地址
+
+use alloc::vec::Vec;
+
+use crate::drivers::acpi::{self, read_phys, HEADER_SIZE};
+
+// 条目类型
+const PROCESSOR_LOCAL_APIC: u8 = 0;
+const LOCAL_APIC_ADDRESS_OVERRIDE: u8 = 5;
+// 处理器条目的标志：处理器可用
+const PROCESSOR_ENABLED: u32 = 1;
+
+#[derive(Debug)]
+pub struct Madt {
+    /// local APIC 寄存器的物理地址
+    pub local_apic: u64,
+    /// 可用处理器的 local APIC id，包括 BSP
+    pub apic_ids: Vec<u8>,
+}
+
+/// 查找并解析 MADT，找不到时返回 None
+pub fn parse() -> Option<Madt> {
+    let table = acpi::find_table(b"APIC")?;
+    let end = table + acpi::table_length(table);
+    let mut local_apic = read_phys::<u32>(table + HEADER_SIZE) as u64;
+    let mut apic_ids = Vec::new();
+
+    // 表头之后是 4 字节的 local APIC 地址和 4 字节的标志，然后是变长的条目：类型、长度、内容
+    let mut entry = table + HEADER_SIZE + 8;
+    while entry + 2 <= end {
+        let (kind, length) = (read_phys::<u8>(entry), read_phys::<u8>(entry + 1) as u64);
+        if length < 2 {
+            break;
+        }
+        match kind {
+            PROCESSOR_LOCAL_APIC if read_phys::<u32>(entry + 4) & PROCESSOR_ENABLED != 0 => {
+                apic_ids.push(read_phys::<u8>(entry + 3));
+            }
+            LOCAL_APIC_ADDRESS_OVERRIDE => local_apic = read_phys::<u64>(entry + 4),
+            _ => {}
+        }
+        entry += length;
+    }
+    Some(Madt { local_apic, apic_ids })
+}
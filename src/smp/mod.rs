@@ -0,0 +1,156 @@
+// 多处理器
+// 从 ACPI 的 MADT 中找到其他处理器，用 INIT/SIPI 逐个启动（AP，application processor）。
+// 每个 AP 有自己的 GDT 和 TSS（以及 TSS 里的 IST 栈），和 BSP 共用同一个 IDT 和页表，
+// GS 基址指向自己的 PerCpu。启动后的 AP 只从 task 模块的队列里取任务执行，外部中断仍然只送到 BSP
+
+use alloc::vec::Vec;
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+
+use spin::RwLock;
+use x86_64::structures::paging::{Mapper, Size2MiB, Size4KiB};
+use x86_64::PhysAddr;
+
+use crate::boot::Unit;
+use crate::gdt::CpuTables;
+use crate::io::timer::{sleep, uptime};
+use crate::memory::stacks::alloc_kernel_stack;
+use crate::memory::vmm::map_mmio;
+use crate::memory::BootInfoFrameAllocator;
+use crate::smp::percpu::PerCpu;
+use crate::smp::trampoline::Trampoline;
+
+pub mod lapic;
+pub mod madt;
+pub mod percpu;
+pub mod task;
+mod trampoline;
+
+pub use percpu::cpu_id;
+
+// INIT 之后等待 10ms 再发 SIPI，每次 SIPI 之后最多等待的时间
+const INIT_DELAY: Duration = Duration::from_millis(10);
+const STARTUP_TIMEOUT: Duration = Duration::from_millis(100);
+
+// 已经上线的 CPU，下标就是逻辑编号
+static CPUS: RwLock<Vec<&'static PerCpu>> = RwLock::new(Vec::new());
+// 正在启动的 AP 完成初始化后置位
+static AP_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// 启动其他 CPU
+///
+/// 需在堆和时钟初始化之后、开中断的状态下调用；找不到 MADT 时只用 BSP
+pub fn init<M>(mapper: &mut M, frame_allocator: &mut BootInfoFrameAllocator)
+where
+    M: Mapper<Size4KiB> + Mapper<Size2MiB>,
+{
+    let Some(madt) = madt::parse() else {
+        log::info!("No MADT found, running on one CPU");
+        return;
+    };
+    match map_mmio(mapper, frame_allocator, PhysAddr::new(madt.local_apic), lapic::REGISTER_BLOCK_SIZE) {
+        Ok(region) => lapic::init(region.start()),
+        Err(error) => {
+            log::warn!("Map local APIC failed: {:?}", error);
+            return;
+        }
+    }
+    lapic::enable();
+    let bsp = PerCpu::new(0, lapic::id(), None);
+    percpu::install(bsp);
+    CPUS.write().push(bsp);
+
+    let others: Vec<u8> = madt.apic_ids.iter().copied().filter(|&id| id != bsp.apic_id).collect();
+    if !others.is_empty() {
+        boot_all(&others, mapper, frame_allocator);
+    }
+    log::info!("{} CPU(s) online", cpu_count());
+}
+
+/// INIT 和 SIPI 之间要用时钟等待
+pub static UNIT: Unit = Unit {
+    name: "smp",
+    depends: &["hpet"],
+    init: |context| {
+        let (mapper, frames) = context.paging()?;
+        init(mapper, frames);
+        Ok(())
+    },
+};
+
+fn boot_all(apic_ids: &[u8], mapper: &mut impl Mapper<Size4KiB>, frame_allocator: &mut BootInfoFrameAllocator) {
+    let Some(frame) = frame_allocator.low_memory_frame() else {
+        log::warn!("No memory below 1MiB for the AP trampoline");
+        return;
+    };
+    let trampoline = match Trampoline::install(mapper, frame_allocator, frame) {
+        Ok(trampoline) => trampoline,
+        Err(error) => {
+            log::warn!("Map AP trampoline failed: {:?}", error);
+            return;
+        }
+    };
+    for &apic_id in apic_ids {
+        // 启动失败的 AP 以后可能还会跑起来，不能再改启动代码的数据区，后面的 AP 也不再启动
+        if let Err(error) = boot_ap(apic_id, &trampoline, mapper, frame_allocator) {
+            log::warn!("CPU with APIC id {} did not start: {}", apic_id, error);
+            break;
+        }
+    }
+    trampoline.remove(mapper);
+}
+
+fn boot_ap(
+    apic_id: u8,
+    trampoline: &Trampoline,
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut BootInfoFrameAllocator,
+) -> Result<(), &'static str> {
+    let mut stack = || alloc_kernel_stack(mapper, frame_allocator).map_err(|_| "out of memory for stacks");
+    let (kernel, double_fault, nmi) = (stack()?, stack()?, stack()?);
+    let tables = CpuTables::new(double_fault.end(), nmi.end());
+    let cpu = PerCpu::new(cpu_count(), apic_id, Some(tables));
+
+    AP_STARTED.store(false, Ordering::Release);
+    trampoline.prepare(kernel.end(), ap_entry, cpu as *const PerCpu as u64);
+    lapic::send_init(apic_id);
+    sleep(INIT_DELAY);
+    // 按规范发两次 SIPI，第一次之后已经启动就不再发
+    for _ in 0..2 {
+        lapic::send_startup(apic_id, trampoline.page());
+        let deadline = uptime() + STARTUP_TIMEOUT;
+        while uptime() < deadline {
+            if AP_STARTED.load(Ordering::Acquire) {
+                CPUS.write().push(cpu);
+                return Ok(());
+            }
+            spin_loop();
+        }
+    }
+    Err("no response to startup IPI")
+}
+
+// AP 从启动代码跳到这里，参数是它的 PerCpu
+extern "C" fn ap_entry(cpu: u64) -> ! {
+    let cpu = unsafe { &*(cpu as *const PerCpu) };
+    if let Some(tables) = cpu.tables {
+        tables.load();
+    }
+    crate::interrupts::init_idt();
+    crate::cpu::enable();
+    percpu::install(cpu);
+    lapic::enable();
+    AP_STARTED.store(true, Ordering::Release);
+    task::run()
+}
+
+/// 已经上线的 CPU 数，包括 BSP
+pub fn cpu_count() -> usize {
+    CPUS.read().len().max(1)
+}
+
+/// 已经上线的 CPU
+pub fn cpus() -> Vec<&'static PerCpu> {
+    CPUS.read().clone()
+}
@@ -0,0 +1,98 @@
+// 每个 CPU 自己的数据
+// GS 基址指向当前 CPU 的 PerCpu，gs:[0] 里存着结构体自己的地址，一条指令就能取到。
+// smp::init 给 BSP 装上之前 GS 基址还是 0，这时 current 返回 None。
+// 每个 CPU 还记着执行过的任务数和停在 hlt 上的时间，系统监视器用它们算 CPU 忙的比例
+
+use alloc::boxed::Box;
+use core::ptr::null;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use core::time::Duration;
+
+use x86_64::registers::model_specific::Msr;
+
+use crate::gdt::CpuTables;
+use crate::io::timer::uptime;
+
+const IA32_GS_BASE: u32 = 0xC000_0101;
+
+// BSP 已经装上了 PerCpu；AP 总是先装上再做别的事
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+#[repr(C)]
+pub struct PerCpu {
+    // 必须是第一个字段
+    this: *const PerCpu,
+    /// 逻辑编号，BSP 是 0
+    pub id: usize,
+    pub apic_id: u8,
+    // AP 的 GDT 和 TSS，BSP 用 gdt 模块里的静态表
+    pub(super) tables: Option<&'static CpuTables>,
+    // 执行过的任务数
+    tasks: AtomicUsize,
+    // 停在 hlt 上的纳秒数
+    idle: AtomicU64,
+}
+
+// this 只指向自己，创建之后不再修改
+unsafe impl Sync for PerCpu {}
+unsafe impl Send for PerCpu {}
+
+impl PerCpu {
+    /// CPU 不会下线，PerCpu 也就不会释放
+    pub fn new(id: usize, apic_id: u8, tables: Option<&'static CpuTables>) -> &'static PerCpu {
+        let cpu = Box::leak(Box::new(PerCpu { this: null(), id, apic_id, tables, tasks: AtomicUsize::new(0), idle: AtomicU64::new(0) }));
+        let this: *const PerCpu = cpu;
+        cpu.this = this;
+        cpu
+    }
+
+    pub fn is_bsp(&self) -> bool {
+        self.id == 0
+    }
+
+    /// 这个 CPU 执行过的任务数
+    pub fn tasks(&self) -> usize {
+        self.tasks.load(Ordering::Relaxed)
+    }
+
+    pub(super) fn count_task(&self) {
+        self.tasks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 这个 CPU 停在 hlt 上的总时间
+    pub fn idle_time(&self) -> Duration {
+        Duration::from_nanos(self.idle.load(Ordering::Relaxed))
+    }
+}
+
+/// 执行 `hlt`（停下来等中断），等待的时间记作当前 CPU 的空闲时间
+pub fn idle(hlt: impl FnOnce()) {
+    let start = uptime();
+    hlt();
+    if let Some(cpu) = current() {
+        cpu.idle.fetch_add(uptime().saturating_sub(start).as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+/// 把 `cpu` 装到当前 CPU 的 GS 基址上
+pub fn install(cpu: &'static PerCpu) {
+    unsafe { Msr::new(IA32_GS_BASE).write(cpu as *const PerCpu as u64) };
+    INSTALLED.store(true, Ordering::Release);
+}
+
+/// 当前 CPU 的 PerCpu
+pub fn current() -> Option<&'static PerCpu> {
+    if !INSTALLED.load(Ordering::Acquire) {
+        return None;
+    }
+    let cpu: *const PerCpu;
+    unsafe {
+        core::arch::asm!("mov {}, gs:[0]", out(reg) cpu, options(nostack, readonly, preserves_flags));
+        Some(&*cpu)
+    }
+}
+
+/// 当前 CPU 的逻辑编号
+pub fn cpu_id() -> usize {
+    current().map_or(0, |cpu| cpu.id)
+}
@@ -0,0 +1,56 @@
+// 任务队列
+// 内核没有线程和调度器，shell 和 GUI 都跑在 BSP 的主循环里。这里给其他 CPU 一个共享的任务队列：
+// spawn 把闭包放进队列，用 IPI 唤醒停在 hlt 上的 AP，AP 取出任务一直执行到返回。
+// AP 收不到时钟中断，任务里不能用 timer::sleep；任务也不会被抢占，长时间运行的任务会一直占着那个 CPU，超过看门狗的超时时间会被报告
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+
+use x86_64::instructions::interrupts;
+
+use crate::debug::watchdog;
+use crate::smp::{self, lapic, percpu};
+use crate::sync::TicketLock;
+
+/// 唤醒空闲 CPU 的 IPI 向量
+pub const WAKEUP_VECTOR: u8 = 0xF0;
+
+type Task = Box<dyn FnOnce() + Send>;
+
+// 所有 CPU 都来取，用排队锁免得某个 CPU 总是抢不到
+static QUEUE: TicketLock<VecDeque<Task>> = TicketLock::named("task queue", VecDeque::new());
+
+/// 在某个 AP 上执行 `task`；没有 AP 时直接在当前 CPU 上执行
+pub fn spawn(task: impl FnOnce() + Send + 'static) {
+    if smp::cpu_count() < 2 {
+        task();
+        return;
+    }
+    interrupts::without_interrupts(|| QUEUE.lock().push_back(Box::new(task)));
+    lapic::broadcast(WAKEUP_VECTOR);
+}
+
+/// 还没有开始执行的任务数
+pub fn pending() -> usize {
+    interrupts::without_interrupts(|| QUEUE.lock().len())
+}
+
+// AP 的主循环
+pub(super) fn run() -> ! {
+    let cpu = percpu::current().expect("per-CPU data is not installed");
+    loop {
+        // 关着中断检查队列，队列为空时开中断和 hlt 之间不会漏掉唤醒
+        interrupts::disable();
+        let task = QUEUE.lock().pop_front();
+        match task {
+            Some(task) => {
+                interrupts::enable();
+                watchdog::feed();
+                task();
+                watchdog::disarm();
+                cpu.count_task();
+            }
+            None => percpu::idle(interrupts::enable_and_hlt),
+        }
+    }
+}
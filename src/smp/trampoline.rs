@@ -0,0 +1,174 @@
+// AP 启动代码
+// SIPI 之后 AP 在实模式下从一个 1MiB 以下的页开始执行。启动代码被复制到这一页，直接从实模式进入长模式：
+// 打开 PAE，使用 BSP 的页表和 EFER，同时打开保护模式和分页，再跳到 64 位代码段，换上为它分配的栈调用入口函数。
+// 打开分页后还在这一页里执行，所以这一页要恒等映射。代码不依赖自己被复制到哪里：
+// 实模式下用 cs 访问，64 位下用 rbx 里保存的页地址访问，需要绝对地址的 GDT 指针由 prepare 填好
+
+use core::ptr::{addr_of, copy_nonoverlapping, write_volatile};
+
+use x86_64::registers::control::{Cr0, Cr3, Cr4};
+use x86_64::registers::model_specific::Msr;
+use x86_64::structures::paging::{
+    mapper::MapToError, FrameAllocator, Mapper, Page, PageTableFlags, PhysFrame, Size4KiB,
+};
+use x86_64::{PhysAddr, VirtAddr};
+
+use crate::memory::{phys_to_virt, translate};
+
+const IA32_EFER: u32 = 0xC000_0080;
+// EFER 中只读的 LMA 位
+const EFER_LONG_MODE_ACTIVE: u64 = 1 << 10;
+// 64 位内核代码段：存在、DPL 0、可执行可读、L 位
+const CODE64_DESCRIPTOR: u64 = 0x0020_9A00_0000_0000;
+
+extern "C" {
+    static ap_trampoline: u8;
+    static ap_trampoline_data: u8;
+    static ap_trampoline_end: u8;
+}
+
+// 启动代码末尾的数据区，偏移要和汇编里的一致
+#[repr(C)]
+struct Data {
+    gdt: [u64; 2],
+    // lgdt 的操作数：界限和 32 位基址
+    gdt_pointer: [u16; 4],
+    cr3: u64,
+    efer: u64,
+    cr0: u64,
+    cr4: u64,
+    stack: u64,
+    entry: u64,
+    argument: u64,
+}
+
+core::arch::global_asm!(
+    ".pushsection .text.ap_trampoline, \"ax\"",
+    ".code16",
+    ".global ap_trampoline",
+    "ap_trampoline:",
+    "    cli",
+    "    cld",
+    // 数据和栈都在这一页里：ds = ss = cs，栈从页尾开始；rbx 保存这一页的物理地址
+    "    mov %cs, %ax",
+    "    mov %ax, %ds",
+    "    mov %ax, %ss",
+    "    mov $0x1000, %sp",
+    "    xor %ebx, %ebx",
+    "    mov %ax, %bx",
+    "    shl $4, %ebx",
+    "    lgdtl (ap_trampoline_data + 16 - ap_trampoline)",
+    // PAE
+    "    mov %cr4, %eax",
+    "    or $0x20, %eax",
+    "    mov %eax, %cr4",
+    "    movl (ap_trampoline_data + 24 - ap_trampoline), %eax",
+    "    mov %eax, %cr3",
+    "    mov $0xC0000080, %ecx",
+    "    movl (ap_trampoline_data + 32 - ap_trampoline), %eax",
+    "    movl (ap_trampoline_data + 36 - ap_trampoline), %edx",
+    "    wrmsr",
+    // 同时打开保护模式和分页，进入长模式
+    "    mov %cr0, %eax",
+    "    or $0x80000001, %eax",
+    "    mov %eax, %cr0",
+    "    pushl $8",
+    "    leal (ap_long_mode - ap_trampoline)(%ebx), %eax",
+    "    pushl %eax",
+    "    lretl",
+    ".code64",
+    "ap_long_mode:",
+    "    xor %eax, %eax",
+    "    mov %ax, %ds",
+    "    mov %ax, %es",
+    "    mov %ax, %ss",
+    "    mov %ax, %fs",
+    "    mov %ax, %gs",
+    "    mov %ebx, %ebx",
+    "    mov (ap_trampoline_data + 40 - ap_trampoline)(%rbx), %rax",
+    "    mov %rax, %cr0",
+    "    mov (ap_trampoline_data + 48 - ap_trampoline)(%rbx), %rax",
+    "    mov %rax, %cr4",
+    "    mov (ap_trampoline_data + 56 - ap_trampoline)(%rbx), %rsp",
+    "    mov (ap_trampoline_data + 72 - ap_trampoline)(%rbx), %rdi",
+    "    call *(ap_trampoline_data + 64 - ap_trampoline)(%rbx)",
+    "    ud2",
+    ".balign 8",
+    ".global ap_trampoline_data",
+    "ap_trampoline_data:",
+    "    .skip 80",
+    ".global ap_trampoline_end",
+    "ap_trampoline_end:",
+    ".popsection",
+    options(att_syntax)
+);
+
+/// 复制到低端内存并恒等映射好的启动代码
+pub struct Trampoline {
+    frame: PhysFrame,
+    // 恒等映射是这里建立的，用完要取消
+    mapped: bool,
+}
+
+impl Trampoline {
+    /// 把启动代码复制到 `frame`（必须在 1MiB 以下）并恒等映射
+    pub fn install(
+        mapper: &mut impl Mapper<Size4KiB>,
+        frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+        frame: PhysFrame,
+    ) -> Result<Trampoline, MapToError<Size4KiB>> {
+        let start = frame.start_address().as_u64();
+        let (code, size) = unsafe {
+            let code = addr_of!(ap_trampoline);
+            (code, addr_of!(ap_trampoline_end) as usize - code as usize)
+        };
+        unsafe { copy_nonoverlapping(code, phys_to_virt(frame.start_address()).as_mut_ptr(), size) };
+
+        let mapped = if translate(VirtAddr::new(start)) == Some(frame.start_address()) {
+            false
+        } else {
+            let page = Page::containing_address(VirtAddr::new(start));
+            // 既要执行又要写入参数，W^X 唯一的例外，AP 都启动之后取消映射
+            let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+            unsafe { mapper.map_to(page, frame, flags, frame_allocator)?.flush() };
+            true
+        };
+        Ok(Trampoline { frame, mapped })
+    }
+
+    /// SIPI 的参数：启动代码所在的页号
+    pub fn page(&self) -> u8 {
+        (self.frame.start_address().as_u64() >> 12) as u8
+    }
+
+    /// 为下一个 AP 填好数据区：在栈 `stack` 上调用 `entry(argument)`
+    pub fn prepare(&self, stack: VirtAddr, entry: extern "C" fn(u64) -> !, argument: u64) {
+        let start = self.frame.start_address().as_u64();
+        let offset = unsafe { addr_of!(ap_trampoline_data) as u64 - addr_of!(ap_trampoline) as u64 };
+        let gdt = start + offset;
+        let cr3 = Cr3::read().0.start_address().as_u64();
+        assert!(cr3 < 1 << 32, "page table above 4GiB cannot be loaded in real mode");
+        let data = Data {
+            gdt: [0, CODE64_DESCRIPTOR],
+            gdt_pointer: [15, gdt as u16, (gdt >> 16) as u16, 0],
+            cr3,
+            efer: unsafe { Msr::new(IA32_EFER).read() } & !EFER_LONG_MODE_ACTIVE,
+            cr0: Cr0::read_raw(),
+            cr4: Cr4::read_raw(),
+            stack: stack.as_u64(),
+            entry: entry as usize as u64,
+            argument,
+        };
+        unsafe { write_volatile(phys_to_virt(PhysAddr::new(gdt)).as_mut_ptr::<Data>(), data) };
+    }
+
+    /// 所有 AP 启动之后取消恒等映射
+    pub fn remove(self, mapper: &mut impl Mapper<Size4KiB>) {
+        if self.mapped {
+            match mapper.unmap(Page::<Size4KiB>::containing_address(VirtAddr::new(self.frame.start_address().as_u64()))) {
+                Ok((_, flush)) => flush.flush(),
+                Err(error) => log::warn!("Unmap AP trampoline failed: {:?}", error),
+            }
+        }
+    }
+}
@@ -0,0 +1,186 @@
+// 声音
+// 两种输出：PC 喇叭只能按某个频率响一声（drivers::pcspeaker），声卡（现在只有 AC'97）播放 PCM 样本。
+// 声卡驱动实现 AudioDevice 并用 attach 交给这里；样本统一是 16 位有符号、左右声道交错，采样率由声卡决定。
+// play 播放一个 WAV：不一次转换整个文件，而是由 poll（shell 空闲时调用）每次把声卡的队列补满，
+// 采样率不同时按最近的样本转换，不做插值。同一时间只播放一个声音，新的换掉旧的。
+// notify 是给 GUI 用的事件提示音：有声卡并且有 sounds/notify.wav 时播放它，否则用 PC 喇叭响一声
+
+pub mod wav;
+
+use alloc::boxed::Box;
+use core::fmt;
+use core::time::Duration;
+
+use spin::Mutex;
+
+use crate::assets::{self, Data};
+use crate::boot::Unit;
+use crate::drivers::{ac97, pcspeaker, pci};
+use crate::sound::wav::{Wav, WavError};
+
+/// 事件提示音
+pub const NOTIFY_SOUND: &str = "sounds/notify.wav";
+// 没有声卡时提示音的频率和长度
+const NOTIFY_HZ: u32 = 880;
+const NOTIFY_DURATION: Duration = Duration::from_millis(120);
+// poll 每次转换的样本数
+const CHUNK_SAMPLES: usize = 2048;
+
+pub trait AudioDevice: Send {
+    /// 驱动名，用于显示
+    fn name(&self) -> &'static str;
+    /// 采样率，固定是 16 位立体声
+    fn rate(&self) -> u32;
+    /// 把样本放进播放队列，返回放进去的样本数（左右声道各算一个），队列满时返回 0
+    fn write(&mut self, samples: &[i16]) -> usize;
+    /// 还没播完的样本数
+    fn queued(&self) -> usize;
+    /// 马上停止并清空队列
+    fn stop(&mut self);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoundError {
+    /// 没有声卡
+    NoDevice,
+    /// 没有这个资源
+    NotFound,
+    Wav(WavError),
+}
+
+impl From<WavError> for SoundError {
+    fn from(error: WavError) -> Self {
+        SoundError::Wav(error)
+    }
+}
+
+impl fmt::Display for SoundError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SoundError::NoDevice => f.write_str("no sound card"),
+            SoundError::NotFound => f.write_str("no such sound"),
+            SoundError::Wav(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+// 正在播放的 WAV
+struct Stream {
+    file: Data,
+    wav: Wav,
+    // 已经交给声卡的帧数，按声卡的采样率计
+    written: u64,
+}
+
+static DEVICE: Mutex<Option<Box<dyn AudioDevice>>> = Mutex::new(None);
+static STREAM: Mutex<Option<Stream>> = Mutex::new(None);
+
+/// 注册声卡驱动，需要在 memory::install_frame_allocator 之后调用，驱动要分配 DMA 内存
+pub fn init() {
+    pci::register(&ac97::DRIVER);
+}
+
+pub static UNIT: Unit = Unit {
+    name: "sound",
+    depends: &["pci", "frame-pool"],
+    init: |_| {
+        init();
+        Ok(())
+    },
+};
+
+/// 驱动初始化好声卡后调用，已经有声卡时返回 false
+pub fn attach(device: Box<dyn AudioDevice>) -> bool {
+    let mut current = DEVICE.lock();
+    if current.is_some() {
+        return false;
+    }
+    log::info!("sound: {} attached, {} Hz", device.name(), device.rate());
+    *current = Some(device);
+    true
+}
+
+/// 声卡的驱动名，没有声卡时返回 None
+pub fn device_name() -> Option<&'static str> {
+    DEVICE.lock().as_ref().map(|device| device.name())
+}
+
+/// 播放 WAV 文件 `file`，换掉正在播放的声音
+pub fn play(file: Data) -> Result<(), SoundError> {
+    let wav = Wav::parse(&file)?;
+    if DEVICE.lock().is_none() {
+        return Err(SoundError::NoDevice);
+    }
+    stop();
+    *STREAM.lock() = Some(Stream { file, wav, written: 0 });
+    poll();
+    Ok(())
+}
+
+/// 播放名为 `name` 的资源
+pub fn play_asset(name: &str) -> Result<(), SoundError> {
+    play(assets::data(name).ok_or(SoundError::NotFound)?)
+}
+
+/// 停止播放
+pub fn stop() {
+    let mut stream = STREAM.lock();
+    *stream = None;
+    if let Some(device) = DEVICE.lock().as_mut() {
+        device.stop();
+    }
+}
+
+/// 还在播放
+pub fn is_playing() -> bool {
+    let stream = STREAM.lock();
+    stream.is_some() || DEVICE.lock().as_ref().is_some_and(|device| device.queued() > 0)
+}
+
+/// 把正在播放的 WAV 转换后补进声卡的队列
+///
+/// 由 shell 在等待输入的空闲时间里调用
+pub fn poll() {
+    let Some(mut guard) = STREAM.try_lock() else { return };
+    let Some(stream) = guard.as_mut() else { return };
+    let mut device = DEVICE.lock();
+    let Some(device) = device.as_mut() else { return };
+    let rate = device.rate() as u64;
+    let frames = stream.wav.frames();
+    let mut chunk = [0i16; CHUNK_SAMPLES];
+    loop {
+        let mut len = 0;
+        while len < CHUNK_SAMPLES {
+            let index = (stream.written * stream.wav.rate as u64 / rate) as usize;
+            if index >= frames {
+                break;
+            }
+            chunk[len..len + 2].copy_from_slice(&stream.wav.frame(&stream.file, index));
+            len += 2;
+            stream.written += 1;
+        }
+        if len == 0 {
+            // 都交给声卡了，剩下的由声卡自己播完
+            *guard = None;
+            return;
+        }
+        let written = device.write(&chunk[..len]);
+        if written < len {
+            // 队列满了，没放进去的下次再转换
+            stream.written -= ((len - written) / 2) as u64;
+            return;
+        }
+    }
+}
+
+/// 以 `hz` 的频率用 PC 喇叭响 `duration`
+pub fn beep(hz: u32, duration: Duration) {
+    pcspeaker::beep(hz, duration);
+}
+
+/// 事件提示音
+pub fn notify() {
+    if play_asset(NOTIFY_SOUND).is_err() {
+        beep(NOTIFY_HZ, NOTIFY_DURATION);
+    }
+}
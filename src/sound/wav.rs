@@ -0,0 +1,96 @@
+// WAV 文件
+// 只支持未压缩的 PCM，8 位（无符号）或 16 位（有符号）样本，任意声道数；取样本时统一换成 16 位立体声，
+// 单声道两边一样，多于两个声道时只取前两个
+
+use core::fmt;
+use core::ops::Range;
+
+const FORMAT_PCM: u16 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WavError {
+    /// 不是 RIFF/WAVE 文件
+    NotWav,
+    /// 缺少 fmt 或者 data 块
+    Truncated,
+    /// 不是 PCM，或者样本位数不是 8 和 16
+    Unsupported,
+}
+
+impl fmt::Display for WavError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            WavError::NotWav => "not a WAV file",
+            WavError::Truncated => "truncated WAV file",
+            WavError::Unsupported => "only 8-bit and 16-bit PCM is supported",
+        })
+    }
+}
+
+/// 解析出的 WAV 头
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Wav {
+    pub rate: u32,
+    pub channels: u16,
+    pub bits: u16,
+    /// 样本数据在文件里的位置
+    pub data: Range<usize>,
+}
+
+impl Wav {
+    /// 解析 `file` 的头，按块查找 fmt 和 data，跳过其他块
+    pub fn parse(file: &[u8]) -> Result<Self, WavError> {
+        if file.len() < 12 || &file[..4] != b"RIFF" || &file[8..12] != b"WAVE" {
+            return Err(WavError::NotWav);
+        }
+        let u16_at = |offset: usize| u16::from_le_bytes([file[offset], file[offset + 1]]);
+        let u32_at = |offset: usize| u32::from_le_bytes(file[offset..offset + 4].try_into().unwrap());
+
+        let mut format = None;
+        let mut offset = 12;
+        while offset + 8 <= file.len() {
+            let size = u32_at(offset + 4) as usize;
+            let body = offset + 8;
+            match &file[offset..offset + 4] {
+                b"fmt " if size >= 16 && body + 16 <= file.len() => {
+                    format = Some((u16_at(body), u16_at(body + 2), u32_at(body + 4), u16_at(body + 14)));
+                }
+                b"data" => {
+                    let (tag, channels, rate, bits) = format.ok_or(WavError::Truncated)?;
+                    if tag != FORMAT_PCM || !(bits == 8 || bits == 16) || channels == 0 || rate == 0 {
+                        return Err(WavError::Unsupported);
+                    }
+                    // 录音中断的文件 data 块可能比声明的短
+                    let end = body.saturating_add(size).min(file.len());
+                    return Ok(Wav { rate, channels, bits, data: body..end });
+                }
+                _ => {}
+            }
+            // 块的长度是奇数时后面有一个填充字节
+            offset = body.saturating_add(size).saturating_add(size & 1);
+        }
+        Err(WavError::Truncated)
+    }
+
+    // 一帧（每个声道一个样本）的字节数
+    fn frame_size(&self) -> usize {
+        self.channels as usize * self.bits as usize / 8
+    }
+
+    /// 帧数
+    pub fn frames(&self) -> usize {
+        self.data.len() / self.frame_size()
+    }
+
+    /// 第 `index` 帧的左右声道，`file` 是 parse 时的那个文件
+    pub fn frame(&self, file: &[u8], index: usize) -> [i16; 2] {
+        let start = self.data.start + index * self.frame_size();
+        let sample = |channel: usize| match self.bits {
+            8 => (file[start + channel] as i16 - 128) << 8,
+            _ => i16::from_le_bytes([file[start + 2 * channel], file[start + 2 * channel + 1]]),
+        };
+        let left = sample(0);
+        let right = if self.channels == 1 { left } else { sample(1) };
+        [left, right]
+    }
+}
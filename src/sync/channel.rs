@@ -0,0 +1,132 @@
+// 多生产者单消费者通道
+// 队列有容量上限，满了 send 直接失败而不是等待，中断处理函数里也能发送；recv 在队列为空时用 WaitQueue 停下当前 CPU。
+// 所有 Sender 都释放之后 recv 取完剩下的消息就返回 None；Receiver 释放之后 send 失败
+
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use core::time::Duration;
+
+use crate::sync::{IrqSafeMutex, WaitQueue};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendError<T> {
+    /// 队列满了，消息还给调用者
+    Full(T),
+    /// 接收端已经释放
+    Disconnected(T),
+}
+
+struct Shared<T> {
+    queue: IrqSafeMutex<VecDeque<T>>,
+    capacity: usize,
+    ready: WaitQueue,
+    senders: AtomicUsize,
+    receiver: AtomicBool,
+}
+
+/// 发送端，可以复制给多个生产者
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// 接收端
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// 创建一个最多放 `capacity` 条消息的通道
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        queue: IrqSafeMutex::new(VecDeque::with_capacity(capacity)),
+        capacity,
+        ready: WaitQueue::new(),
+        senders: AtomicUsize::new(1),
+        receiver: AtomicBool::new(true),
+    });
+    (Sender { shared: shared.clone() }, Receiver { shared })
+}
+
+impl<T> Sender<T> {
+    /// 放进一条消息并唤醒等着的接收端，不会阻塞
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        if !self.shared.receiver.load(Ordering::Acquire) {
+            return Err(SendError::Disconnected(value));
+        }
+        {
+            let mut queue = self.shared.queue.lock();
+            if queue.len() >= self.shared.capacity {
+                return Err(SendError::Full(value));
+            }
+            queue.push_back(value);
+        }
+        self.shared.ready.wake_one();
+        Ok(())
+    }
+
+    /// 队列里还没取走的消息数
+    pub fn len(&self) -> usize {
+        self.shared.queue.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::AcqRel);
+        Sender { shared: self.shared.clone() }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    // 最后一个发送端释放时唤醒接收端，让 recv 返回 None
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.shared.ready.wake_all();
+        }
+    }
+}
+
+impl<T> Receiver<T> {
+    /// 不阻塞地取一条消息
+    pub fn try_recv(&self) -> Option<T> {
+        self.shared.queue.lock().pop_front()
+    }
+
+    /// 取一条消息，队列为空时等待；所有发送端都释放了并且队列已空时返回 None
+    pub fn recv(&self) -> Option<T> {
+        let mut value = None;
+        self.shared.ready.block_on_condition(|| {
+            value = self.try_recv();
+            value.is_some() || self.shared.senders.load(Ordering::Acquire) == 0
+        });
+        value
+    }
+
+    /// 和 recv 一样，但最多等 `timeout`
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<T> {
+        let mut value = None;
+        self.shared.ready.block_on_condition_timeout(timeout, || {
+            value = self.try_recv();
+            value.is_some() || self.shared.senders.load(Ordering::Acquire) == 0
+        });
+        value
+    }
+
+    pub fn len(&self) -> usize {
+        self.shared.queue.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.receiver.store(false, Ordering::Release);
+    }
+}
@@ -0,0 +1,191 @@
+// 关中断的锁
+// 主循环持有锁时来了中断，中断处理函数再去拿同一把锁，就会在同一个 CPU 上永远自旋。
+// 这里的锁在加锁之前关中断，守卫先释放锁、再恢复加锁前的中断状态，嵌套使用时只有最外层会重新开中断
+
+use core::ops::{Deref, DerefMut};
+
+use spin::{Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockUpgradableGuard, RwLockWriteGuard};
+use x86_64::instructions::interrupts;
+
+use crate::sync::lockdep;
+
+// 加锁前的中断状态，守卫里放在最后一个字段，锁释放之后才恢复
+struct IrqState(bool);
+
+impl IrqState {
+    fn save_and_disable() -> Self {
+        let enabled = interrupts::are_enabled();
+        interrupts::disable();
+        IrqState(enabled)
+    }
+}
+
+impl Drop for IrqState {
+    fn drop(&mut self) {
+        if self.0 {
+            interrupts::enable();
+        }
+    }
+}
+
+/// 持有期间关中断的互斥锁
+pub struct IrqSafeMutex<T> {
+    inner: Mutex<T>,
+    // 有名字的锁参与 lockdep 检查
+    name: Option<&'static str>,
+}
+
+impl<T> IrqSafeMutex<T> {
+    pub const fn new(value: T) -> Self {
+        Self { inner: Mutex::new(value), name: None }
+    }
+
+    /// 带名字的锁，打开 lockdep 特性时检查加锁顺序；只给静态的锁起名字
+    pub const fn named(name: &'static str, value: T) -> Self {
+        Self { inner: Mutex::new(value), name: Some(name) }
+    }
+
+    pub fn lock(&self) -> IrqSafeMutexGuard<T> {
+        let irq = IrqState::save_and_disable();
+        if let Some(name) = self.name {
+            lockdep::acquire(self.key(), name);
+        }
+        IrqSafeMutexGuard { guard: self.inner.lock(), key: self.name.map(|_| self.key()), _irq: irq }
+    }
+
+    /// 锁被占用时返回 None，中断状态不变
+    pub fn try_lock(&self) -> Option<IrqSafeMutexGuard<T>> {
+        let irq = IrqState::save_and_disable();
+        let guard = self.inner.try_lock()?;
+        if let Some(name) = self.name {
+            lockdep::acquire_try(self.key(), name);
+        }
+        Some(IrqSafeMutexGuard { guard, key: self.name.map(|_| self.key()), _irq: irq })
+    }
+
+    fn key(&self) -> usize {
+        self as *const Self as usize
+    }
+}
+
+pub struct IrqSafeMutexGuard<'a, T> {
+    guard: MutexGuard<'a, T>,
+    key: Option<usize>,
+    _irq: IrqState,
+}
+
+impl<T> Deref for IrqSafeMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for IrqSafeMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+// 字段在 drop 之后按声明顺序释放：先放锁，再恢复中断
+impl<T> Drop for IrqSafeMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            lockdep::release(key);
+        }
+    }
+}
+
+/// 持有期间关中断的读写锁
+///
+/// 可升级的读锁和读锁共存，升级成写锁时等其他读者离开；读锁可以嵌套，所以不参与 lockdep 检查
+pub struct IrqSafeRwLock<T> {
+    inner: RwLock<T>,
+}
+
+impl<T> IrqSafeRwLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self { inner: RwLock::new(value) }
+    }
+
+    pub fn read(&self) -> IrqSafeRwLockReadGuard<T> {
+        let irq = IrqState::save_and_disable();
+        IrqSafeRwLockReadGuard { guard: self.inner.read(), _irq: irq }
+    }
+
+    pub fn write(&self) -> IrqSafeRwLockWriteGuard<T> {
+        let irq = IrqState::save_and_disable();
+        IrqSafeRwLockWriteGuard { guard: self.inner.write(), _irq: irq }
+    }
+
+    /// 先读，需要修改时再 upgrade；同一时间只有一个可升级的读者
+    pub fn upgradeable_read(&self) -> IrqSafeRwLockUpgradableGuard<T> {
+        let irq = IrqState::save_and_disable();
+        IrqSafeRwLockUpgradableGuard { guard: self.inner.upgradeable_read(), _irq: irq }
+    }
+}
+
+pub struct IrqSafeRwLockReadGuard<'a, T> {
+    guard: RwLockReadGuard<'a, T>,
+    _irq: IrqState,
+}
+
+pub struct IrqSafeRwLockWriteGuard<'a, T> {
+    guard: RwLockWriteGuard<'a, T>,
+    _irq: IrqState,
+}
+
+pub struct IrqSafeRwLockUpgradableGuard<'a, T> {
+    guard: RwLockUpgradableGuard<'a, T>,
+    _irq: IrqState,
+}
+
+impl<'a, T> IrqSafeRwLockUpgradableGuard<'a, T> {
+    /// 升级成写锁，一直关着中断
+    pub fn upgrade(self) -> IrqSafeRwLockWriteGuard<'a, T> {
+        IrqSafeRwLockWriteGuard { guard: self.guard.upgrade(), _irq: self._irq }
+    }
+
+    /// 降级成普通的读锁，让别的读者也能升级
+    pub fn downgrade(self) -> IrqSafeRwLockReadGuard<'a, T> {
+        IrqSafeRwLockReadGuard { guard: self.guard.downgrade(), _irq: self._irq }
+    }
+}
+
+impl<'a, T> IrqSafeRwLockWriteGuard<'a, T> {
+    /// 改完之后降级成可升级的读锁，期间不会有别的写者插进来
+    pub fn downgrade_to_upgradeable(self) -> IrqSafeRwLockUpgradableGuard<'a, T> {
+        IrqSafeRwLockUpgradableGuard { guard: self.guard.downgrade_to_upgradeable(), _irq: self._irq }
+    }
+}
+
+impl<T> Deref for IrqSafeRwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> Deref for IrqSafeRwLockUpgradableGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> Deref for IrqSafeRwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for IrqSafeRwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
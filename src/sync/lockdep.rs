@@ -0,0 +1,164 @@
+// 加锁顺序检查
+// 打开 lockdep 特性后，带名字的锁每次加锁都记下"持有 A 时获取了 B"这条边。新的边和已有的边构成环，
+// 说明有两处（两个 CPU，或者主循环和中断）按相反的顺序加锁，迟早会死锁，这时直接 panic 并给出两把锁的名字；
+// 同一个 CPU 再次获取自己持有的锁也会 panic。检查在自旋之前做，真的死锁之前就能报出来。
+// try_lock 不会等待，拿到锁时只记为持有，不检查顺序。
+// 没有打开特性时这里都是空函数
+
+#[cfg(feature = "lockdep")]
+pub use imp::{acquire, acquire_try, release};
+
+#[cfg(not(feature = "lockdep"))]
+#[inline(always)]
+pub fn acquire(_lock: usize, _name: &'static str) {}
+
+#[cfg(not(feature = "lockdep"))]
+#[inline(always)]
+pub fn acquire_try(_lock: usize, _name: &'static str) {}
+
+#[cfg(not(feature = "lockdep"))]
+#[inline(always)]
+pub fn release(_lock: usize) {}
+
+#[cfg(feature = "lockdep")]
+mod imp {
+    use spin::Mutex;
+    use x86_64::instructions::interrupts;
+
+    use crate::smp;
+
+    // 登记的锁最多 64 把，边用位图表示
+    const MAX_LOCKS: usize = 64;
+    const MAX_CPUS: usize = 16;
+    // 每个 CPU 同时持有的锁，超过的部分不再记录
+    const MAX_DEPTH: usize = 16;
+
+    struct State {
+        // 锁的地址和名字
+        locks: [Option<(usize, &'static str)>; MAX_LOCKS],
+        // after[a] 的第 b 位：持有 a 时获取过 b
+        after: [u64; MAX_LOCKS],
+        held: [[u8; MAX_DEPTH]; MAX_CPUS],
+        depth: [usize; MAX_CPUS],
+    }
+
+    static STATE: Mutex<State> = Mutex::new(State {
+        locks: [None; MAX_LOCKS],
+        after: [0; MAX_LOCKS],
+        held: [[0; MAX_DEPTH]; MAX_CPUS],
+        depth: [0; MAX_CPUS],
+    });
+
+    enum Violation {
+        Recursive(&'static str),
+        Inversion { holding: &'static str, acquiring: &'static str },
+    }
+
+    impl State {
+        // 锁的编号，第一次见到时登记；登记满了返回 None，不再检查这把锁
+        fn index(&mut self, lock: usize, name: &'static str) -> Option<usize> {
+            if let Some(index) = self.locks.iter().position(|entry| matches!(entry, Some((key, _)) if *key == lock)) {
+                return Some(index);
+            }
+            let index = self.locks.iter().position(|entry| entry.is_none())?;
+            self.locks[index] = Some((lock, name));
+            Some(index)
+        }
+
+        fn name(&self, index: usize) -> &'static str {
+            self.locks[index].map_or("?", |(_, name)| name)
+        }
+
+        // 沿着边从 from 能不能走到 to
+        fn reachable(&self, from: usize, to: usize) -> bool {
+            let (mut visited, mut frontier) = (1u64 << from, 1u64 << from);
+            while frontier != 0 {
+                let node = frontier.trailing_zeros() as usize;
+                frontier &= frontier - 1;
+                if node == to {
+                    return true;
+                }
+                let next = self.after[node] & !visited;
+                visited |= next;
+                frontier |= next;
+            }
+            false
+        }
+
+        fn push(&mut self, cpu: usize, index: usize) {
+            let depth = self.depth[cpu];
+            if depth < MAX_DEPTH {
+                self.held[cpu][depth] = index as u8;
+                self.depth[cpu] += 1;
+            }
+        }
+    }
+
+    pub fn acquire(lock: usize, name: &'static str) {
+        let cpu = smp::cpu_id();
+        if cpu >= MAX_CPUS {
+            return;
+        }
+        // 发现问题时先放开 STATE 再 panic，panic 处理中还可能加锁
+        let violation = interrupts::without_interrupts(|| {
+            let mut state = STATE.lock();
+            let index = state.index(lock, name)?;
+            let depth = state.depth[cpu];
+            for i in 0..depth {
+                let held = state.held[cpu][i] as usize;
+                if held == index {
+                    return Some(Violation::Recursive(name));
+                }
+                if state.reachable(index, held) {
+                    return Some(Violation::Inversion { holding: state.name(held), acquiring: name });
+                }
+            }
+            for i in 0..depth {
+                let held = state.held[cpu][i] as usize;
+                state.after[held] |= 1 << index;
+            }
+            state.push(cpu, index);
+            None
+        });
+        match violation {
+            None => {}
+            Some(Violation::Recursive(name)) => panic!("lockdep: cpu{} acquires `{}` twice", cpu, name),
+            Some(Violation::Inversion { holding, acquiring }) => panic!(
+                "lockdep: cpu{} acquires `{}` while holding `{}`, but elsewhere `{}` is taken before `{}`",
+                cpu, acquiring, holding, acquiring, holding
+            ),
+        }
+    }
+
+    pub fn acquire_try(lock: usize, name: &'static str) {
+        let cpu = smp::cpu_id();
+        if cpu >= MAX_CPUS {
+            return;
+        }
+        interrupts::without_interrupts(|| {
+            let mut state = STATE.lock();
+            if let Some(index) = state.index(lock, name) {
+                state.push(cpu, index);
+            }
+        });
+    }
+
+    pub fn release(lock: usize) {
+        let cpu = smp::cpu_id();
+        if cpu >= MAX_CPUS {
+            return;
+        }
+        interrupts::without_interrupts(|| {
+            let mut state = STATE.lock();
+            let Some(index) = state.locks.iter().position(|entry| matches!(entry, Some((key, _)) if *key == lock)) else {
+                return;
+            };
+            // 通常是最后获取的那把，但不要求按相反的顺序释放
+            let depth = state.depth[cpu];
+            if let Some(i) = (0..depth).rev().find(|&i| state.held[cpu][i] as usize == index) {
+                state.held[cpu].copy_within(i + 1..depth, i);
+                state.depth[cpu] -= 1;
+            }
+        });
+    }
+}
@@ -0,0 +1,21 @@
+// 同步原语
+// 内核到处直接用 spin::Mutex，和中断处理函数共享的数据只能每次手写 without_interrupts，忘了就会在同一个 CPU 上自己等自己。
+// - IrqSafeMutex、IrqSafeRwLock：加锁前关中断，守卫释放锁之后恢复原来的中断状态
+// - TicketLock：按到达顺序排队的自旋锁，多个 CPU 争抢时不会有谁一直拿不到
+// - lockdep：打开 lockdep 特性后检查带名字的锁的加锁顺序，发现可能死锁的环时 panic
+// - WaitQueue：等条件成立时让 CPU 停在 hlt 上，由 wake_one / wake_all 唤醒，不用自旋
+// - channel：多生产者单消费者的通道，recv 在没有消息时用 WaitQueue 等待
+
+pub mod channel;
+mod irq;
+pub mod lockdep;
+mod ticket;
+mod wait;
+
+pub use irq::{
+    IrqSafeMutex, IrqSafeMutexGuard, IrqSafeRwLock, IrqSafeRwLockReadGuard, IrqSafeRwLockUpgradableGuard,
+    IrqSafeRwLockWriteGuard,
+};
+pub use channel::{channel, Receiver, Sender};
+pub use ticket::{TicketLock, TicketLockGuard};
+pub use wait::WaitQueue;
@@ -0,0 +1,98 @@
+// 排队自旋锁
+// 每个加锁的人先取一个号，等到叫号再进去。spin::Mutex 在多个 CPU 同时争抢时谁抢到全看运气，
+// 排队锁保证先来先得，适合多个 CPU 频繁争用的数据（例如 smp 的任务队列）。
+// 不关中断，和中断处理函数共享的数据用 IrqSafeMutex
+
+use core::cell::UnsafeCell;
+use core::hint::spin_loop;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::sync::lockdep;
+
+pub struct TicketLock<T> {
+    // 下一个要发出去的号
+    next: AtomicUsize,
+    // 正在叫的号
+    serving: AtomicUsize,
+    name: Option<&'static str>,
+    value: UnsafeCell<T>,
+}
+
+// 和 spin::Mutex 一样，能在 CPU 之间传递的数据加上锁就能共享
+unsafe impl<T: Send> Sync for TicketLock<T> {}
+unsafe impl<T: Send> Send for TicketLock<T> {}
+
+impl<T> TicketLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self { next: AtomicUsize::new(0), serving: AtomicUsize::new(0), name: None, value: UnsafeCell::new(value) }
+    }
+
+    /// 带名字的锁，打开 lockdep 特性时检查加锁顺序；只给静态的锁起名字
+    pub const fn named(name: &'static str, value: T) -> Self {
+        Self {
+            next: AtomicUsize::new(0),
+            serving: AtomicUsize::new(0),
+            name: Some(name),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn lock(&self) -> TicketLockGuard<T> {
+        if let Some(name) = self.name {
+            lockdep::acquire(self.key(), name);
+        }
+        let ticket = self.next.fetch_add(1, Ordering::Relaxed);
+        while self.serving.load(Ordering::Acquire) != ticket {
+            spin_loop();
+        }
+        TicketLockGuard { lock: self }
+    }
+
+    /// 没有人持有也没有人排队时才拿得到
+    pub fn try_lock(&self) -> Option<TicketLockGuard<T>> {
+        let serving = self.serving.load(Ordering::Relaxed);
+        self.next.compare_exchange(serving, serving + 1, Ordering::Acquire, Ordering::Relaxed).ok()?;
+        if let Some(name) = self.name {
+            lockdep::acquire_try(self.key(), name);
+        }
+        Some(TicketLockGuard { lock: self })
+    }
+
+    /// 正在等待的人数，不含持有者
+    pub fn waiters(&self) -> usize {
+        let (next, serving) = (self.next.load(Ordering::Relaxed), self.serving.load(Ordering::Relaxed));
+        next.wrapping_sub(serving).saturating_sub(1)
+    }
+
+    fn key(&self) -> usize {
+        self as *const Self as usize
+    }
+}
+
+pub struct TicketLockGuard<'a, T> {
+    lock: &'a TicketLock<T>,
+}
+
+impl<T> Deref for TicketLockGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> DerefMut for TicketLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for TicketLockGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.serving.fetch_add(1, Ordering::Release);
+        if self.lock.name.is_some() {
+            lockdep::release(self.lock.key());
+        }
+    }
+}
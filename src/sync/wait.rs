@@ -0,0 +1,103 @@
+// 等待队列
+// 内核没有线程，"阻塞"就是让当前 CPU 停在 hlt 上，直到条件成立，而不是一直自旋检查。
+// 等待的 CPU 把自己的 APIC id 记进位图；wake_one、wake_all 取出等待者，给别的 CPU 发唤醒 IPI，
+// 唤醒的正是自己时（比如中断处理函数里唤醒被它打断的等待）什么都不用做，hlt 已经因为这个中断返回。
+// 条件在关中断时检查，检查完用 sti; hlt 一起开中断并停下，之间到来的唤醒不会丢。
+// 醒来后总是重新检查条件，所以多余的唤醒没有害处，和 futex 一样
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+use x86_64::instructions::interrupts;
+
+use crate::io::timer::uptime;
+use crate::kdebug_assert;
+use crate::smp::task::WAKEUP_VECTOR;
+use crate::smp::{lapic, percpu};
+
+/// 一组等着同一件事的 CPU
+pub struct WaitQueue {
+    // 按 APIC id 的位图
+    waiters: [AtomicU64; 4],
+}
+
+impl Default for WaitQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        WaitQueue { waiters: [AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0)] }
+    }
+
+    /// 停下当前 CPU，直到 `condition` 返回 true；必须在开中断的状态下调用
+    pub fn block_on_condition(&self, condition: impl FnMut() -> bool) {
+        self.block(None, condition);
+    }
+
+    /// 和 block_on_condition 一样，但最多等 `timeout`，条件成立时返回 true
+    pub fn block_on_condition_timeout(&self, timeout: Duration, condition: impl FnMut() -> bool) -> bool {
+        self.block(Some(uptime() + timeout), condition)
+    }
+
+    /// 唤醒一个等待者
+    pub fn wake_one(&self) {
+        for (word, waiters) in self.waiters.iter().enumerate() {
+            let mut bits = waiters.load(Ordering::Acquire);
+            while bits != 0 {
+                let bit = bits.trailing_zeros();
+                match waiters.compare_exchange(bits, bits & !(1 << bit), Ordering::AcqRel, Ordering::Acquire) {
+                    Ok(_) => return wake((word * 64) as u8 + bit as u8),
+                    Err(current) => bits = current,
+                }
+            }
+        }
+    }
+
+    /// 唤醒所有等待者
+    pub fn wake_all(&self) {
+        for (word, waiters) in self.waiters.iter().enumerate() {
+            let mut bits = waiters.swap(0, Ordering::AcqRel);
+            while bits != 0 {
+                let bit = bits.trailing_zeros();
+                bits &= !(1 << bit);
+                wake((word * 64) as u8 + bit as u8);
+            }
+        }
+    }
+
+    /// 有没有 CPU 在等
+    pub fn has_waiters(&self) -> bool {
+        self.waiters.iter().any(|waiters| waiters.load(Ordering::Acquire) != 0)
+    }
+
+    fn block(&self, deadline: Option<Duration>, mut condition: impl FnMut() -> bool) -> bool {
+        kdebug_assert!(interrupts::are_enabled(), "blocking with interrupts disabled would never wake up");
+        let id = percpu::current().map_or(0, |cpu| cpu.apic_id);
+        let (word, bit) = (id as usize / 64, 1 << (id % 64));
+        loop {
+            interrupts::disable();
+            if condition() {
+                interrupts::enable();
+                return true;
+            }
+            // 时钟中断每个 tick 都会唤醒 hlt，到时间了就不再等
+            if deadline.map_or(false, |deadline| uptime() >= deadline) {
+                interrupts::enable();
+                return false;
+            }
+            self.waiters[word].fetch_or(bit, Ordering::AcqRel);
+            percpu::idle(interrupts::enable_and_hlt);
+            self.waiters[word].fetch_and(!bit, Ordering::AcqRel);
+        }
+    }
+}
+
+// 唤醒停在 hlt 上的 CPU，自己就不用了
+fn wake(apic_id: u8) {
+    if lapic::is_ready() && percpu::current().map_or(false, |cpu| cpu.apic_id != apic_id) {
+        lapic::send(apic_id, WAKEUP_VECTOR);
+    }
+}
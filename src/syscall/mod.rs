@@ -0,0 +1,148 @@
+// 系统调用
+// 用户程序用 int 0x80 陷入内核：rax 是调用号，参数依次放在 rdi、rsi、rdx，返回值放在 rax，其他寄存器不变。
+// 中断门的 DPL 是 3，ring 3 才能用 int 指令进来；CPU 按 TSS 切换到特权级栈，入口把通用寄存器压栈后交给 dispatch。
+// 出错时返回 u64::MAX
+
+use alloc::string::String;
+use core::time::Duration;
+
+use x86_64::instructions::interrupts;
+use x86_64::structures::idt::InterruptDescriptorTable;
+use x86_64::{PrivilegeLevel, VirtAddr};
+
+use crate::io::timer;
+use crate::rgb888;
+use crate::usermode;
+
+pub const VECTOR: usize = 0x80;
+
+/// exit(code)：结束程序，不返回
+pub const EXIT: u64 = 0;
+/// write(buf, len)：把用户内存里的文字输出到控制台，返回写出的字节数
+pub const WRITE: u64 = 1;
+/// sleep(ms)：睡眠若干毫秒，期间显示程序画的内容
+pub const SLEEP: u64 = 2;
+/// draw_pixel(x, y, color)：在程序的窗口里画一个像素，x 是行，y 是列，color 是 0xRRGGBB
+pub const DRAW_PIXEL: u64 = 3;
+
+const ERROR: u64 = u64::MAX;
+// 一次 write 最多输出的字节数
+const MAX_WRITE: u64 = 4096;
+
+// 入口压栈后的寄存器，和汇编的压栈顺序相反
+#[repr(C)]
+#[derive(Debug)]
+pub struct SyscallFrame {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+// 进入时栈上有 CPU 压的 5 个值（压之前对齐到 16 字节），再压 15 个通用寄存器，正好对齐到 16 字节
+core::arch::global_asm!(
+    ".global syscall_entry",
+    "syscall_entry:",
+    "    push rax",
+    "    push rbx",
+    "    push rcx",
+    "    push rdx",
+    "    push rsi",
+    "    push rdi",
+    "    push rbp",
+    "    push r8",
+    "    push r9",
+    "    push r10",
+    "    push r11",
+    "    push r12",
+    "    push r13",
+    "    push r14",
+    "    push r15",
+    "    mov rdi, rsp",
+    "    cld",
+    "    call {dispatch}",
+    "    pop r15",
+    "    pop r14",
+    "    pop r13",
+    "    pop r12",
+    "    pop r11",
+    "    pop r10",
+    "    pop r9",
+    "    pop r8",
+    "    pop rbp",
+    "    pop rdi",
+    "    pop rsi",
+    "    pop rdx",
+    "    pop rcx",
+    "    pop rbx",
+    "    pop rax",
+    "    iretq",
+    dispatch = sym dispatch,
+);
+
+extern "C" {
+    fn syscall_entry();
+}
+
+/// 把 int 0x80 接到系统调用入口上
+pub fn install(idt: &mut InterruptDescriptorTable) {
+    unsafe {
+        idt[VECTOR]
+            .set_handler_addr(VirtAddr::new(syscall_entry as usize as u64))
+            .set_privilege_level(PrivilegeLevel::Ring3);
+    }
+}
+
+// 执行系统调用，返回时按 frame 恢复寄存器；进来时中断是关着的
+extern "sysv64" fn dispatch(frame: &mut SyscallFrame) {
+    let (a, b, c) = (frame.rdi, frame.rsi, frame.rdx);
+    frame.rax = match frame.rax {
+        EXIT => usermode::exit(a),
+        WRITE => write(a, b),
+        SLEEP => sleep(a),
+        DRAW_PIXEL => draw_pixel(a, b, c),
+        number => {
+            log::warn!("Unknown syscall {}", number);
+            ERROR
+        }
+    };
+}
+
+fn write(buf: u64, len: u64) -> u64 {
+    let Some(bytes) = usermode::user_slice(buf, len.min(MAX_WRITE)) else { return ERROR };
+    crate::print!("{}", String::from_utf8_lossy(bytes));
+    bytes.len() as u64
+}
+
+fn sleep(ms: u64) -> u64 {
+    usermode::flush();
+    // 睡眠靠时钟中断唤醒
+    interrupts::enable();
+    timer::sleep(Duration::from_millis(ms));
+    interrupts::disable();
+    0
+}
+
+fn draw_pixel(x: u64, y: u64, color: u64) -> u64 {
+    if usermode::draw_pixel(x as usize, y as usize, rgb888!(color as u32)) {
+        0
+    } else {
+        ERROR
+    }
+}
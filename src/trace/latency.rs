@@ -0,0 +1,361 @@
+// 输入延迟测量
+// 注入一个合成的按键或鼠标事件作为探针，记下它经过每一步的时间：
+//   inject    注入，相当于中断处理函数收到它
+//   queue     放进输入队列
+//   dispatch  被 shell 或 GUI 从队列里取出
+//   handle    控件或 shell 处理完
+//   present   之后第一帧显示出来
+// 同一时间只跟踪一个探针。没有探针时各处的打点只读一次原子变量。
+// 一次测量连续注入若干个探针，由 shell 主循环空闲时调用 poll 推进，结束后汇总出每一步的最小、平均和最大延迟。
+// 按键探针是一个 x 加一个退格，鼠标探针左右交替移动一个像素，测完画面和原来一样
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::graphic::present;
+use crate::io::format::Elapsed;
+use crate::io::keyboard;
+use crate::io::mouse::{self, MouseEvent};
+use crate::io::timer::uptime;
+use crate::sync::IrqSafeMutex;
+
+// 一个探针最多等多久，超时的步骤记为缺失
+const TIMEOUT: Duration = Duration::from_secs(1);
+// 一次测量最多的探针数
+pub const MAX_SAMPLES: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Key,
+    Mouse,
+}
+
+impl Source {
+    pub fn name(self) -> &'static str {
+        match self {
+            Source::Key => "key",
+            Source::Mouse => "mouse",
+        }
+    }
+
+    pub fn by_name(name: &str) -> Option<Source> {
+        match name {
+            "key" => Some(Source::Key),
+            "mouse" => Some(Source::Mouse),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Inject,
+    Queue,
+    Dispatch,
+    Handle,
+    Present,
+}
+
+pub const STAGES: usize = 5;
+
+impl Stage {
+    pub const ALL: [Stage; STAGES] = [Stage::Inject, Stage::Queue, Stage::Dispatch, Stage::Handle, Stage::Present];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Inject => "inject",
+            Stage::Queue => "queue",
+            Stage::Dispatch => "dispatch",
+            Stage::Handle => "handle",
+            Stage::Present => "present",
+        }
+    }
+}
+
+/// 一个探针经过各步的时间
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+    pub source: Source,
+    pub stamps: [Option<Duration>; STAGES],
+}
+
+impl Sample {
+    pub fn new(source: Source) -> Self {
+        Self { source, stamps: [None; STAGES] }
+    }
+
+    /// 从上一步到这一步用的时间，两步有一个没有记下时为 None
+    pub fn stage(&self, stage: Stage) -> Option<Duration> {
+        let index = stage as usize;
+        let start = self.stamps[index.checked_sub(1)?]?;
+        Some(self.stamps[index]?.saturating_sub(start))
+    }
+
+    /// 从注入到显示出来的时间
+    pub fn total(&self) -> Option<Duration> {
+        let end = self.stamps[Stage::Present as usize]?;
+        Some(end.saturating_sub(self.stamps[Stage::Inject as usize]?))
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.stamps.iter().all(Option::is_some)
+    }
+}
+
+/// 一步延迟的统计
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StageStats {
+    /// 记下了这一步的探针数
+    pub count: usize,
+    pub min: Duration,
+    pub max: Duration,
+    pub sum: Duration,
+}
+
+impl StageStats {
+    const EMPTY: StageStats = StageStats { count: 0, min: Duration::MAX, max: Duration::ZERO, sum: Duration::ZERO };
+
+    fn add(&mut self, value: Duration) {
+        self.count += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += value;
+    }
+
+    pub fn average(&self) -> Option<Duration> {
+        (self.count > 0).then(|| self.sum / self.count as u32)
+    }
+}
+
+/// 一次测量的汇总
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Report {
+    pub source: Source,
+    pub samples: usize,
+    /// 每一步（除了 inject）的延迟，下标是 Stage 的值
+    pub stages: [StageStats; STAGES],
+    /// 从注入到显示出来
+    pub total: StageStats,
+}
+
+impl Report {
+    pub fn new(source: Source, samples: &[Sample]) -> Self {
+        let mut report = Report {
+            source,
+            samples: samples.len(),
+            stages: [StageStats::EMPTY; STAGES],
+            total: StageStats::EMPTY,
+        };
+        for sample in samples {
+            for stage in Stage::ALL {
+                if let Some(value) = sample.stage(stage) {
+                    report.stages[stage as usize].add(value);
+                }
+            }
+            if let Some(value) = sample.total() {
+                report.total.add(value);
+            }
+        }
+        report
+    }
+}
+
+// 每行：步骤、记下的探针数、最小、平均、最大
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} latency, {} samples", self.source.name(), self.samples)?;
+        writeln!(f, "{:<10}{:>6}{:>10}{:>10}{:>10}", "stage", "count", "min", "avg", "max")?;
+        let rows = Stage::ALL[1..].iter().map(|&stage| (stage.name(), &self.stages[stage as usize]))
+            .chain([("total", &self.total)]);
+        for (name, stats) in rows {
+            write!(f, "{:<10}{:>6}", name, stats.count)?;
+            match stats.average() {
+                Some(average) => writeln!(f, "{:>10}{:>10}{:>10}", Elapsed(stats.min), Elapsed(average), Elapsed(stats.max))?,
+                None => writeln!(f, "{:>10}{:>10}{:>10}", "-", "-", "-")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Probe {
+    sample: Sample,
+    // 放进队列时排在探针前面的事件数，每取出一个减一
+    ahead: usize,
+    // 取出探针时的帧编号，之后开始合成的帧才算把它显示出来
+    frame: u64,
+}
+
+// 正在跟踪的探针，队列和中断里也会用
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+static PROBE: IrqSafeMutex<Option<Probe>> = IrqSafeMutex::named("latency probe", None);
+
+struct Run {
+    source: Source,
+    count: usize,
+    samples: Vec<Sample>,
+}
+
+static RUN: Mutex<Option<Run>> = Mutex::new(None);
+static LAST: Mutex<Option<Report>> = Mutex::new(None);
+
+// 开始跟踪一个新探针，之后这个来源第一个放进队列的事件就是它
+fn arm(source: Source) {
+    let mut sample = Sample::new(source);
+    sample.stamps[Stage::Inject as usize] = Some(uptime());
+    *PROBE.lock() = Some(Probe { sample, ahead: 0, frame: 0 });
+    ACTIVE.store(true, Ordering::Release);
+}
+
+/// 注入一个按键作为探针，替换掉还没完成的探针
+pub fn inject_key(ch: char) {
+    arm(Source::Key);
+    interrupts::without_interrupts(|| keyboard::push_key(ch));
+}
+
+/// 注入一个鼠标事件作为探针，替换掉还没完成的探针
+pub fn inject_mouse(event: MouseEvent) {
+    arm(Source::Mouse);
+    interrupts::without_interrupts(|| mouse::push_event(event));
+}
+
+// 对正在跟踪的这个来源的探针执行 `f`
+fn with_probe(source: Source, f: impl FnOnce(&mut Probe)) {
+    if !ACTIVE.load(Ordering::Acquire) {
+        return;
+    }
+    if let Some(probe) = PROBE.lock().as_mut().filter(|p| p.sample.source == source) {
+        f(probe);
+    }
+}
+
+fn stamped(probe: &Probe, stage: Stage) -> bool {
+    probe.sample.stamps[stage as usize].is_some()
+}
+
+fn stamp(probe: &mut Probe, stage: Stage) {
+    probe.sample.stamps[stage as usize] = Some(uptime());
+}
+
+/// 输入队列放进一个事件，`ahead` 是它前面还没取走的事件数
+pub fn queued(source: Source, ahead: usize) {
+    with_probe(source, |probe| {
+        if !stamped(probe, Stage::Queue) {
+            stamp(probe, Stage::Queue);
+            probe.ahead = ahead;
+        }
+    });
+}
+
+/// 输入队列取走一个事件
+pub fn dequeued(source: Source) {
+    with_probe(source, |probe| {
+        if !stamped(probe, Stage::Queue) || stamped(probe, Stage::Dispatch) {
+            return;
+        }
+        if probe.ahead > 0 {
+            probe.ahead -= 1;
+            return;
+        }
+        stamp(probe, Stage::Dispatch);
+        probe.frame = present::current_frame();
+    });
+}
+
+/// 控件或 shell 处理完一个事件，事件按顺序处理，取出探针后的第一次就是探针
+pub fn handled(source: Source) {
+    with_probe(source, |probe| {
+        if stamped(probe, Stage::Dispatch) && !stamped(probe, Stage::Handle) {
+            stamp(probe, Stage::Handle);
+        }
+    });
+}
+
+/// 第 `frame` 帧显示出来了
+pub fn presented(frame: u64) {
+    if !ACTIVE.load(Ordering::Acquire) {
+        return;
+    }
+    if let Some(probe) = PROBE.lock().as_mut() {
+        if stamped(probe, Stage::Dispatch) && !stamped(probe, Stage::Present) && frame > probe.frame {
+            stamp(probe, Stage::Present);
+        }
+    }
+}
+
+/// 正在跟踪的探针已经走完的步骤
+pub fn probe() -> Option<Sample> {
+    PROBE.lock().as_ref().map(|p| p.sample)
+}
+
+// 结束跟踪，返回探针的记录
+fn take_probe() -> Option<Sample> {
+    ACTIVE.store(false, Ordering::Release);
+    PROBE.lock().take().map(|p| p.sample)
+}
+
+/// 开始一次测量，注入 `count` 个探针，返回是否开始了；已经有测量在进行时返回 false
+pub fn start(source: Source, count: usize) -> bool {
+    let mut run = RUN.lock();
+    if run.is_some() || count == 0 {
+        return false;
+    }
+    let count = count.min(MAX_SAMPLES);
+    *run = Some(Run { source, count, samples: Vec::with_capacity(count) });
+    drop(run);
+    inject(source, 0);
+    true
+}
+
+pub fn is_running() -> bool {
+    RUN.lock().is_some()
+}
+
+/// 最近一次测量的结果
+pub fn last_report() -> Option<Report> {
+    *LAST.lock()
+}
+
+// 第 `index` 个探针
+fn inject(source: Source, index: usize) {
+    match source {
+        Source::Key => {
+            inject_key('x');
+            interrupts::without_interrupts(|| keyboard::push_key('\x08'));
+        }
+        Source::Mouse => {
+            let dx = if index % 2 == 0 { 1 } else { -1 };
+            inject_mouse(MouseEvent { dx, dy: 0, left: false, right: false, middle: false, wheel: 0 });
+        }
+    }
+}
+
+/// 由 shell 主循环空闲时调用：探针走完或者超时后记下来，注入下一个或者结束测量
+pub fn poll() {
+    let Some(mut guard) = RUN.try_lock() else { return };
+    let Some(run) = guard.as_mut() else { return };
+    let Some(current) = probe() else { return };
+    let injected = current.stamps[Stage::Inject as usize].unwrap_or_default();
+    if !current.is_complete() && uptime().saturating_sub(injected) < TIMEOUT {
+        return;
+    }
+    take_probe();
+    run.samples.push(current);
+    if run.samples.len() < run.count {
+        inject(run.source, run.samples.len());
+        return;
+    }
+    let report = Report::new(run.source, &run.samples);
+    *guard = None;
+    match report.total.average() {
+        Some(average) => log::info!("latency: {} {} samples, {} complete, average {}",
+                                    report.source.name(), report.samples, report.total.count, Elapsed(average)),
+        None => log::warn!("latency: {} {} samples, none reached the screen", report.source.name(), report.samples),
+    }
+    *LAST.lock() = Some(report);
+}
@@ -0,0 +1,166 @@
+// 事件追踪
+// 在中断处理、合成等路径上打点（开始/结束一段区间、计数器的当前值），记进定长的环形缓冲区，满了覆盖最旧的事件。
+// 打点不分配内存，没有开启时只读一次原子变量；导出成 Chrome trace_event 格式的 JSON，
+// 经串口输出后保存成文件，可以用 chrome://tracing 或 Perfetto 打开。
+// latency 子模块用合成的输入事件量出从注入到显示的每一步延迟
+
+pub mod latency;
+
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::io::qemu::SerialStream;
+use crate::io::timer::uptime;
+
+// 环形缓冲区能放的事件数
+const CAPACITY: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Begin,
+    End,
+    Counter(i64),
+    Instant,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Event {
+    pub name: &'static str,
+    pub kind: Kind,
+    // 启动以来的纳秒数
+    pub timestamp: u64,
+}
+
+struct Ring {
+    events: [Option<Event>; CAPACITY],
+    // 下一个写入的位置
+    next: usize,
+    // 被覆盖掉的事件数
+    dropped: usize,
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static RING: Mutex<Ring> = Mutex::new(Ring { events: [None; CAPACITY], next: 0, dropped: 0 });
+
+/// 一段区间，离开作用域时记下结束
+pub struct Span {
+    name: &'static str,
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        end(self.name);
+    }
+}
+
+/// 开始记录
+pub fn start() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// 停止记录，已经记下的事件保留到下次 clear
+pub fn stop() {
+    ENABLED.store(false, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// 清空缓冲区
+pub fn clear() {
+    interrupts::without_interrupts(|| {
+        let mut ring = RING.lock();
+        ring.events = [None; CAPACITY];
+        ring.next = 0;
+        ring.dropped = 0;
+    });
+}
+
+fn record(name: &'static str, kind: Kind) {
+    if !is_enabled() {
+        return;
+    }
+    let event = Event { name, kind, timestamp: uptime().as_nanos() as u64 };
+    interrupts::without_interrupts(|| {
+        // 导出时缓冲区被占用，丢掉这个事件
+        if let Some(mut ring) = RING.try_lock() {
+            let next = ring.next;
+            if ring.events[next].is_some() {
+                ring.dropped += 1;
+            }
+            ring.events[next] = Some(event);
+            ring.next = (next + 1) % CAPACITY;
+        }
+    });
+}
+
+/// 开始一段区间，需要和同名的 end 配对
+pub fn begin(name: &'static str) {
+    record(name, Kind::Begin);
+}
+
+pub fn end(name: &'static str) {
+    record(name, Kind::End);
+}
+
+/// 开始一段区间，返回的 Span 离开作用域时结束
+pub fn span(name: &'static str) -> Span {
+    begin(name);
+    Span { name }
+}
+
+/// 记下计数器的当前值
+pub fn counter(name: &'static str, value: i64) {
+    record(name, Kind::Counter(value));
+}
+
+/// 记下一个瞬时事件
+pub fn instant(name: &'static str) {
+    record(name, Kind::Instant);
+}
+
+/// 缓冲区里的事件数和被覆盖掉的事件数
+pub fn stats() -> (usize, usize) {
+    interrupts::without_interrupts(|| {
+        let ring = RING.lock();
+        (ring.events.iter().flatten().count(), ring.dropped)
+    })
+}
+
+/// 按时间顺序导出成 Chrome trace_event 格式的 JSON
+///
+/// 导出期间缓冲区被锁住，新的事件会被丢掉；写入的目标不能再打点
+pub fn export(out: &mut impl fmt::Write) -> fmt::Result {
+    let ring = interrupts::without_interrupts(|| RING.lock());
+    out.write_str("{\"traceEvents\":[")?;
+    let (newer, older) = ring.events.split_at(ring.next);
+    for (i, event) in older.iter().chain(newer).flatten().enumerate() {
+        if i > 0 {
+            out.write_str(",\n")?;
+        }
+        let ph = match event.kind {
+            Kind::Begin => "B",
+            Kind::End => "E",
+            Kind::Counter(_) => "C",
+            Kind::Instant => "i",
+        };
+        // ts 的单位是微秒
+        write!(out, "{{\"name\":\"{}\",\"ph\":\"{}\",\"ts\":{}.{:03},\"pid\":0,\"tid\":0",
+               event.name, ph, event.timestamp / 1000, event.timestamp % 1000)?;
+        match event.kind {
+            Kind::Counter(value) => write!(out, ",\"args\":{{\"value\":{}}}}}", value)?,
+            Kind::Instant => out.write_str(",\"s\":\"g\"}")?,
+            _ => out.write_str("}")?,
+        }
+    }
+    out.write_str("]}\n")
+}
+
+/// 把缓冲区导出到串口
+pub fn dump_serial() {
+    let _ = export(&mut SerialStream);
+}
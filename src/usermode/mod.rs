@@ -0,0 +1,305 @@
+// 用户态程序
+// 用户程序运行在 ring 3，只能访问这里映射的一块用户内存：代码区放程序本身，栈区在上面隔开一页。
+// 内核的页都没有 USER 标志，用户程序碰到内核内存、执行特权指令时触发异常，只会结束这个程序。
+// ELF 程序由 loader::elf 装进自己的地址空间，运行期间切换到它的页表，用户内存的布局由程序自己决定。
+// 同一时间只运行一个程序，由 shell 在 BSP 上同步执行：run 保存内核的寄存器后 iretq 进入 ring 3，
+// 程序调用 exit 或者被结束时恢复这些寄存器，像从 run 返回一样回到 shell。浮点和 SSE 寄存器也在进出时保存恢复，
+// 程序拿到的是复位后的状态，它改的舍入方式之类的设置不会留给内核。
+// 用户程序和内核共用 GS 基址上的 PerCpu，程序不能修改 GS，否则内核取不到当前 CPU 的数据
+use alloc::format;
+use core::ptr::write_bytes;
+use core::slice;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use embedded_graphics::pixelcolor::Rgb888;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::structures::paging::{mapper::MapToError, FrameAllocator, Mapper, PageTableFlags, Size4KiB};
+use x86_64::VirtAddr;
+
+use crate::boot::{InitError, Unit};
+use crate::cpu::fpu::FpuState;
+use crate::gdt;
+use crate::gui::window::{WindowId, BORDER, TITLE_BAR_HEIGHT, WINDOW_MANAGER};
+use crate::loader::elf::{self, LoadError};
+use crate::memory::address_space::AddressSpace;
+use crate::memory::{layout, wx};
+use crate::memory::vmm::map_fixed;
+use crate::memory::{self, is_user_accessible};
+
+pub mod programs;
+
+// 配置区域
+// 用户内存在 1 GiB 处，内核映像之上、动态映射区域之下，见 memory::layout
+pub const USER_CODE_START: u64 = layout::USER.start;
+pub const USER_CODE_SIZE: u64 = 0x1_0000;
+// 栈和代码区之间空一页不映射，栈溢出时触发页错误
+pub const USER_STACK_START: u64 = USER_CODE_START + USER_CODE_SIZE + 0x1000;
+pub const USER_STACK_SIZE: u64 = 0x1_0000;
+const _: () = assert!(USER_STACK_START + USER_STACK_SIZE <= layout::USER.end());
+const PAGE_SIZE: usize = 4096;
+// 用户程序画图的窗口大小（客户区）
+const WINDOW_WIDTH: usize = 256;
+const WINDOW_HEIGHT: usize = 256;
+
+static READY: AtomicBool = AtomicBool::new(false);
+static RUNNING: AtomicBool = AtomicBool::new(false);
+// 进入用户态前保存的内核栈指针，返回时从这里恢复
+static KERNEL_RSP: AtomicU64 = AtomicU64::new(0);
+// 程序被结束的原因
+static KILLED: Mutex<Option<&'static str>> = Mutex::new(None);
+// 正在运行的程序的名字，用作窗口标题
+static NAME: Mutex<&'static str> = Mutex::new("");
+// 程序画图用的窗口，第一次 draw_pixel 时创建，程序结束后留着
+static WINDOW: Mutex<Option<WindowId>> = Mutex::new(None);
+
+/// 内置的用户程序，代码与位置无关
+pub struct Program {
+    pub name: &'static str,
+    pub description: &'static str,
+    // 程序代码在内核映像里的位置，运行时复制到用户内存
+    start: *const u8,
+    end: *const u8,
+}
+
+impl Program {
+    /// 程序的机器码，从第一个字节开始执行
+    pub fn code(&self) -> &'static [u8] {
+        unsafe { slice::from_raw_parts(self.start, self.end as usize - self.start as usize) }
+    }
+}
+
+/// 程序是怎么结束的
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    /// 调用了 exit
+    Code(u64),
+    /// 触发异常被结束
+    Killed(&'static str),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunError {
+    NotReady,
+    Busy,
+    TooLarge,
+    Load(LoadError),
+}
+
+extern "sysv64" {
+    fn user_enter(entry: u64, stack: u64, saved_rsp: *mut u64, code_selector: u64, stack_selector: u64) -> u64;
+    fn user_leave(saved_rsp: u64, code: u64) -> !;
+}
+
+// 保存 System V 约定中由被调用者保存的寄存器和栈指针，然后伪造一个中断返回的栈帧进入 ring 3；
+// user_leave 切回保存的栈，恢复这些寄存器后从 user_enter 返回
+core::arch::global_asm!(
+    ".global user_enter",
+    "user_enter:",
+    "    push rbx",
+    "    push rbp",
+    "    push r12",
+    "    push r13",
+    "    push r14",
+    "    push r15",
+    "    mov [rdx], rsp",
+    "    push r8",
+    "    push rsi",
+    "    push 0x202",
+    "    push rcx",
+    "    push rdi",
+    // 不把内核的值留给用户程序
+    "    xor eax, eax",
+    "    xor ebx, ebx",
+    "    xor ecx, ecx",
+    "    xor edx, edx",
+    "    xor esi, esi",
+    "    xor edi, edi",
+    "    xor ebp, ebp",
+    "    xor r8d, r8d",
+    "    xor r9d, r9d",
+    "    xor r10d, r10d",
+    "    xor r11d, r11d",
+    "    xor r12d, r12d",
+    "    xor r13d, r13d",
+    "    xor r14d, r14d",
+    "    xor r15d, r15d",
+    "    iretq",
+    ".global user_leave",
+    "user_leave:",
+    "    mov rsp, rdi",
+    "    mov rax, rsi",
+    "    pop r15",
+    "    pop r14",
+    "    pop r13",
+    "    pop r12",
+    "    pop rbp",
+    "    pop rbx",
+    "    ret",
+);
+
+/// 映射用户内存，需要在分页和堆初始化之后调用
+pub fn init(
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), MapToError<Size4KiB>> {
+    // 代码区只读可执行，栈可写不可执行
+    let code_flags = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+    let stack_flags = wx::data_flags(code_flags | PageTableFlags::WRITABLE);
+    map_fixed(mapper, frame_allocator, VirtAddr::new(USER_CODE_START), USER_CODE_SIZE, code_flags)?;
+    map_fixed(mapper, frame_allocator, VirtAddr::new(USER_STACK_START), USER_STACK_SIZE, stack_flags)?;
+    READY.store(true, Ordering::Release);
+    Ok(())
+}
+
+pub static UNIT: Unit = Unit {
+    name: "usermode",
+    depends: &[],
+    init: |context| {
+        let (mapper, frames) = context.paging()?;
+        init(mapper, frames).map_err(|error| InitError::Failed(format!("map user memory: {:?}", error)))
+    },
+};
+
+/// 在 ring 3 运行 `program`，直到它调用 exit 或者被结束
+pub fn run(program: &Program) -> Result<Exit, RunError> {
+    if !READY.load(Ordering::Acquire) {
+        return Err(RunError::NotReady);
+    }
+    let code = program.code();
+    if code.len() as u64 > USER_CODE_SIZE {
+        return Err(RunError::TooLarge);
+    }
+    if RUNNING.swap(true, Ordering::Acquire) {
+        return Err(RunError::Busy);
+    }
+    // 清掉上一个程序留下的数据
+    load_code(code);
+    unsafe { write_bytes(USER_STACK_START as *mut u8, 0, USER_STACK_SIZE as usize) };
+    let exit = enter(program.name, USER_CODE_START, USER_STACK_START + USER_STACK_SIZE);
+    RUNNING.store(false, Ordering::Release);
+    Ok(exit)
+}
+
+// 代码区是只读的，通过物理内存的整体映射一页页写进去，剩下的部分清零
+fn load_code(code: &[u8]) {
+    for offset in (0..USER_CODE_SIZE).step_by(PAGE_SIZE) {
+        let phys = memory::translate(VirtAddr::new(USER_CODE_START + offset)).expect("user code is not mapped");
+        let page = unsafe { slice::from_raw_parts_mut(memory::phys_to_virt(phys).as_mut_ptr::<u8>(), PAGE_SIZE) };
+        let chunk = code.get(offset as usize..).unwrap_or_default();
+        let len = chunk.len().min(PAGE_SIZE);
+        page[..len].copy_from_slice(&chunk[..len]);
+        page[len..].fill(0);
+    }
+}
+
+/// 加载 ELF 可执行文件，在它自己的地址空间里运行，结束后释放它的内存
+pub fn run_elf(name: &'static str, file: &[u8]) -> Result<Exit, RunError> {
+    if RUNNING.swap(true, Ordering::Acquire) {
+        return Err(RunError::Busy);
+    }
+    let image = match memory::with_frames(|frames| elf::load(file, frames)) {
+        Some(Ok(image)) => image,
+        result => {
+            RUNNING.store(false, Ordering::Release);
+            return Err(match result {
+                Some(Err(error)) => RunError::Load(error),
+                _ => RunError::NotReady,
+            });
+        }
+    };
+    let previous = unsafe { image.space.activate() };
+    let exit = enter(name, image.entry.as_u64(), image.stack.as_u64());
+    unsafe { AddressSpace::restore(previous) };
+    memory::with_frames(|frames| image.space.destroy(frames));
+    RUNNING.store(false, Ordering::Release);
+    Ok(exit)
+}
+
+// 进入 ring 3 从 `entry` 开始执行，直到程序结束
+fn enter(name: &'static str, entry: u64, stack: u64) -> Exit {
+    *KILLED.lock() = None;
+    *NAME.lock() = name;
+    *WINDOW.lock() = None;
+    let enabled = interrupts::are_enabled();
+    let (code_selector, stack_selector) = gdt::user_selectors();
+    let mut kernel_fpu = FpuState::new();
+    kernel_fpu.save();
+    FpuState::new().restore();
+    let value = unsafe {
+        user_enter(entry, stack, KERNEL_RSP.as_ptr(), code_selector.0 as u64, stack_selector.0 as u64)
+    };
+    kernel_fpu.restore();
+    // 从系统调用或异常处理函数里回来，中断是关着的
+    if enabled {
+        interrupts::enable();
+    }
+    match KILLED.lock().take() {
+        Some(reason) => Exit::Killed(reason),
+        None => Exit::Code(value),
+    }
+}
+
+/// 正在运行的程序的名字，异常处理函数里也能调用
+pub fn current_program() -> Option<&'static str> {
+    if !RUNNING.load(Ordering::Acquire) {
+        return None;
+    }
+    NAME.try_lock().map(|name| *name)
+}
+
+/// 结束当前程序，回到 run 的调用者
+///
+/// 由 exit 系统调用调用，调用前要放开所有的锁
+pub fn exit(code: u64) -> ! {
+    flush();
+    unsafe { user_leave(KERNEL_RSP.load(Ordering::Relaxed), code) }
+}
+
+/// 用户程序触发了异常，结束它
+///
+/// 由异常处理函数在确认异常来自 ring 3 之后调用
+pub fn kill(reason: &'static str) -> ! {
+    log::warn!("User program killed: {}", reason);
+    *KILLED.lock() = Some(reason);
+    exit(u64::MAX)
+}
+
+/// 当前程序内存里的一段数据，不完全在用户可以访问的页里时返回 None
+pub fn user_slice(ptr: u64, len: u64) -> Option<&'static [u8]> {
+    let start = VirtAddr::try_new(ptr).ok()?;
+    if !is_user_accessible(start, len) {
+        return None;
+    }
+    Some(unsafe { slice::from_raw_parts(ptr as *const u8, len as usize) })
+}
+
+/// 在程序的窗口里画一个像素，第一次画的时候创建窗口
+pub fn draw_pixel(x: usize, y: usize, color: Rgb888) -> bool {
+    let mut window = WINDOW.lock();
+    let mut manager = WINDOW_MANAGER.lock();
+    let id = match *window {
+        Some(id) => id,
+        None => {
+            let (width, height) = (WINDOW_WIDTH + 2 * BORDER, WINDOW_HEIGHT + TITLE_BAR_HEIGHT + BORDER);
+            let Ok(id) = manager.create(*NAME.lock(), 80, 480, width, height) else { return false };
+            *window = Some(id);
+            id
+        }
+    };
+    match manager.window_mut(id) {
+        Some(window) => {
+            window.set_pixel(x, y, color);
+            true
+        }
+        None => false,
+    }
+}
+
+/// 把程序画的内容显示出来
+pub fn flush() {
+    let window = *WINDOW.lock();
+    if let Some(id) = window {
+        WINDOW_MANAGER.lock().redraw(id);
+    }
+}
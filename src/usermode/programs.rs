@@ -0,0 +1,137 @@
+// 内置的用户程序
+// 还没有文件系统和 ELF 加载器，程序直接用汇编写在内核里，运行时整段复制到用户内存。
+// 代码只能用 rip 相对寻址，数据放在代码后面；系统调用的约定见 syscall 模块
+
+use core::ptr::addr_of;
+
+use crate::syscall;
+use crate::usermode::Program;
+
+// 动画的边长和帧数
+const PATTERN_SIZE: usize = 128;
+const PATTERN_FRAMES: usize = 32;
+
+extern "C" {
+    static user_hello: u8;
+    static user_hello_end: u8;
+    static user_pattern: u8;
+    static user_pattern_end: u8;
+    static user_fault: u8;
+    static user_fault_end: u8;
+}
+
+core::arch::global_asm!(
+    ".pushsection .text.user_programs, \"ax\"",
+    // hello：打印一行字后退出
+    ".balign 16",
+    ".global user_hello",
+    "user_hello:",
+    "    mov eax, {write}",
+    "    lea rdi, [rip + .Lhello_message]",
+    "    lea rsi, [rip + .Lhello_message_end]",
+    "    sub rsi, rdi",
+    "    int 0x80",
+    "    mov eax, {exit}",
+    "    xor edi, edi",
+    "    int 0x80",
+    ".Lhello_message:",
+    "    .ascii \"Hello from ring 3!\\n\"",
+    ".Lhello_message_end:",
+    ".global user_hello_end",
+    "user_hello_end:",
+    // pattern：在窗口里画一段渐变动画，r12 是帧号，r13 和 r14 是行和列
+    ".balign 16",
+    ".global user_pattern",
+    "user_pattern:",
+    "    xor r12d, r12d",
+    "2:",
+    "    xor r13d, r13d",
+    "3:",
+    "    xor r14d, r14d",
+    "4:",
+    "    mov eax, {draw_pixel}",
+    "    mov rdi, r13",
+    "    mov rsi, r14",
+    "    lea rdx, [r13 + r12 * 4]",
+    "    and edx, 0xff",
+    "    shl edx, 16",
+    "    lea rcx, [r14 + r12 * 4]",
+    "    and ecx, 0xff",
+    "    shl ecx, 8",
+    "    or edx, ecx",
+    "    or edx, 0x60",
+    "    int 0x80",
+    "    inc r14",
+    "    cmp r14, {size}",
+    "    jb 4b",
+    "    inc r13",
+    "    cmp r13, {size}",
+    "    jb 3b",
+    "    mov eax, {sleep}",
+    "    mov edi, 40",
+    "    int 0x80",
+    "    inc r12",
+    "    cmp r12, {frames}",
+    "    jb 2b",
+    "    mov eax, {exit}",
+    "    xor edi, edi",
+    "    int 0x80",
+    ".global user_pattern_end",
+    "user_pattern_end:",
+    // fault：读内核映像所在的地址，应该被结束，不会走到 exit
+    ".balign 16",
+    ".global user_fault",
+    "user_fault:",
+    "    mov eax, {write}",
+    "    lea rdi, [rip + .Lfault_message]",
+    "    lea rsi, [rip + .Lfault_message_end]",
+    "    sub rsi, rdi",
+    "    int 0x80",
+    "    mov eax, 0x200000",
+    "    mov rax, [rax]",
+    "    mov eax, {exit}",
+    "    mov edi, 1",
+    "    int 0x80",
+    ".Lfault_message:",
+    "    .ascii \"Reading kernel memory from ring 3...\\n\"",
+    ".Lfault_message_end:",
+    ".global user_fault_end",
+    "user_fault_end:",
+    ".popsection",
+    exit = const syscall::EXIT,
+    write = const syscall::WRITE,
+    sleep = const syscall::SLEEP,
+    draw_pixel = const syscall::DRAW_PIXEL,
+    size = const PATTERN_SIZE,
+    frames = const PATTERN_FRAMES,
+);
+
+/// 所有内置的程序
+pub fn all() -> [Program; 3] {
+    unsafe {
+        [
+            Program {
+                name: "hello",
+                description: "print a greeting and exit",
+                start: addr_of!(user_hello),
+                end: addr_of!(user_hello_end),
+            },
+            Program {
+                name: "pattern",
+                description: "animate a gradient in a window",
+                start: addr_of!(user_pattern),
+                end: addr_of!(user_pattern_end),
+            },
+            Program {
+                name: "fault",
+                description: "read kernel memory and get killed",
+                start: addr_of!(user_fault),
+                end: addr_of!(user_fault_end),
+            },
+        ]
+    }
+}
+
+pub fn find(name: &str) -> Option<Program> {
+    all().into_iter().find(|program| program.name == name)
+}
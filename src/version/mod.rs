@@ -0,0 +1,41 @@
+// 构建信息
+// 版本号、git 提交、构建时间和打开的特性由 build.rs 在编译时生成，这里只负责显示。
+// 提交后面带 -dirty 表示构建时工作区有没提交的修改；panic 画面也会用到，所以这里的格式化都不分配内存
+
+use core::fmt;
+
+use crate::drivers::rtc::DateTime;
+
+include!(concat!(env!("OUT_DIR"), "/version.rs"));
+
+pub const NAME: &str = "Cinea OS";
+pub const MACHINE: &str = "x86_64";
+
+/// 构建时间（UTC）
+pub fn build_time() -> DateTime {
+    DateTime::from_timestamp(BUILD_TIMESTAMP)
+}
+
+/// 和 Linux 的 /proc/version 一样的一行说明
+pub struct Banner;
+
+impl fmt::Display for Banner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} ({}) {} built {} UTC, features: ", NAME, VERSION, GIT_HASH, PROFILE, build_time())?;
+        write_features(f)
+    }
+}
+
+/// 用空格分开的特性，没有时写 none
+pub fn write_features(f: &mut impl fmt::Write) -> fmt::Result {
+    if FEATURES.is_empty() {
+        return f.write_str("none");
+    }
+    for (i, feature) in FEATURES.iter().enumerate() {
+        if i > 0 {
+            f.write_char(' ')?;
+        }
+        f.write_str(feature)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,51 @@
+// 代码页 437
+// VGA 文本模式的字库是 CP437：0x20..0x7E 和 ASCII 一样，0x01..0x1F、0x7F 和 0x80..0xFF 是图形符号、
+// 西欧字母、制表符和希腊字母。这里把 Unicode 字符换成对应的字节，没有对应时由调用者画一个方块
+
+// 0x01..0x1F，0x00 不显示
+const LOW: [char; 32] = [
+    '\0', '☺', '☻', '♥', '♦', '♣', '♠', '•', '◘', '○', '◙', '♂', '♀', '♪', '♫', '☼',
+    '►', '◄', '↕', '‼', '¶', '§', '▬', '↨', '↑', '↓', '→', '←', '∟', '↔', '▲', '▼',
+];
+
+// 0x80..0xFF
+const HIGH: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å',
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ',
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»',
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐',
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧',
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀',
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩',
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}',
+];
+
+// 字库里没有、但有形状相近的字符
+const ALIASES: [(char, u8); 10] = [
+    ('β', 0xE1), ('μ', 0xE6), ('∑', 0xE4), ('∈', 0xEE), ('⌂', 0x7F),
+    ('‘', b'\''), ('’', b'\''), ('“', b'"'), ('”', b'"'), ('–', b'-'),
+];
+
+/// 字符对应的 CP437 字节
+pub fn encode(ch: char) -> Option<u8> {
+    if (' '..='~').contains(&ch) {
+        return Some(ch as u8);
+    }
+    if let Some(i) = LOW.iter().skip(1).position(|&c| c == ch) {
+        return Some(i as u8 + 1);
+    }
+    if let Some(i) = HIGH.iter().position(|&c| c == ch) {
+        return Some(i as u8 + 0x80);
+    }
+    ALIASES.iter().find(|(c, _)| *c == ch).map(|&(_, byte)| byte)
+}
+
+/// 字符在文本模式下占几格：全角字符（中日韩文字等）占两格，和图形模式下的宽度一致；组合用字符不占位置
+pub fn width(ch: char) -> usize {
+    match ch as u32 {
+        0x0300..=0x036F | 0x200B..=0x200F | 0xFE00..=0xFE0F => 0,
+        0x1100..=0x115F | 0x2E80..=0xA4CF | 0xAC00..=0xD7A3 | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F | 0xFF00..=0xFF60 | 0xFFE0..=0xFFE6 | 0x20000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
@@ -1,13 +1,23 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
 // 引入Rust的格式化模块，用于输出显示
 use core::fmt;
 // 引入写接口，使得可以使用write!宏来打印
 use lazy_static::lazy_static;
 use spin::Mutex;
 // 引入`Volatile`类型封装内存，确保每次修改都是直接对硬件的
+use embedded_graphics::pixelcolor::{Rgb888, RgbColor};
 use volatile::Volatile;
 use x86_64::instructions::interrupts;
+use crate::allocator;
+use crate::io::ansi::{self, Action, Parser};
+use crate::io::theme;
+use crate::io::vt::{self, Tty};
+use crate::memory::layout;
 use crate::println;
 
+mod cp437;
+
 // VGA标准颜色
 // 允许未使用代码不被警告
 #[allow(dead_code)]
@@ -46,6 +56,36 @@ impl ColorCode {
     }
 }
 
+impl Color {
+    /// 调色板里的 RGB 颜色
+    pub fn rgb(self) -> Rgb888 {
+        let index = ANSI_COLORS.iter().position(|&color| color == self).unwrap_or(0);
+        ansi::color(index as u8)
+    }
+
+    /// 调色板里和 `rgb` 最接近的颜色
+    pub fn nearest(rgb: Rgb888) -> Color {
+        let distance = |color: &Color| {
+            let c = color.rgb();
+            let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+            d(c.r(), rgb.r()) + d(c.g(), rgb.g()) + d(c.b(), rgb.b())
+        };
+        *ANSI_COLORS.iter().min_by_key(|color| distance(color)).unwrap()
+    }
+}
+
+// 没有设置主题时的颜色，ANSI 的 SGR 0 恢复到主题
+const DEFAULT_FOREGROUND: Color = Color::LightCyan;
+const DEFAULT_BACKGROUND: Color = Color::Black;
+
+// ANSI 颜色号到 VGA 颜色，两者的顺序不同
+const ANSI_COLORS: [Color; 16] = [
+    Color::Black, Color::Red, Color::Green, Color::Brown,
+    Color::Blue, Color::Magenta, Color::Cyan, Color::LightGray,
+    Color::DarkGray, Color::LightRed, Color::LightGreen, Color::Yellow,
+    Color::LightBlue, Color::Pink, Color::LightCyan, Color::White,
+];
+
 // 提交到内存中的VGA字符
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 // 设置此结构体在内存中的表示应遵循C语言的排列方式
@@ -62,6 +102,10 @@ const BUFFER_HEIGHT: usize = 25;
 const BUFFER_WIDTH: usize = 80;
 // 定义Tab键对应空格数
 const TAB_SIZE: usize = 4;
+// 默认保留的滚出屏幕的行数
+const SCROLLBACK_LINES: usize = 1000;
+
+type Row = [ScreenChar; BUFFER_WIDTH];
 
 // 表示 VGA 文本模式下屏幕的整个字符缓冲区
 #[repr(transparent)]
@@ -75,39 +119,155 @@ pub struct Writer {
     row_position: usize,
     column_position: usize,
     color_code: ColorCode,
+    // 解析输出中的 ANSI 转义序列
+    parser: Parser,
+    // 上次同步颜色时的主题版本
+    theme_generation: usize,
+    // 滚出屏幕顶部的行，堆初始化之前滚出的行不保留
+    history: VecDeque<Row>,
+    scrollback_limit: usize,
+    // 向上翻看了多少行，0 表示显示当前画面
+    view: usize,
+    // 翻看历史时暂存的当前画面
+    saved: Vec<Row>,
     // 静态生命周期引用当前VGA缓冲区 允许整个程序运行期间可变地访问这个Buffer
     buffer: &'static mut Buffer,
 }
 
 impl Writer {
+    fn new(buffer: &'static mut Buffer) -> Writer {
+        Writer {
+            row_position: 0,
+            column_position: 0,
+            color_code: ColorCode::new(DEFAULT_FOREGROUND, DEFAULT_BACKGROUND),
+            parser: Parser::new(),
+            theme_generation: 0,
+            history: VecDeque::new(),
+            scrollback_limit: SCROLLBACK_LINES,
+            view: 0,
+            saved: Vec::new(),
+            buffer,
+        }
+    }
+
+    // 和另一个控制台交换屏幕内容和状态，各自的缓冲区不变
+    fn exchange(&mut self, other: &mut Writer) {
+        self.scroll_to_live();
+        other.scroll_to_live();
+        for row in 0..BUFFER_HEIGHT {
+            let (mine, theirs) = (self.read_row(row), other.read_row(row));
+            self.write_row(row, &theirs);
+            other.write_row(row, &mine);
+        }
+        core::mem::swap(self, other);
+        core::mem::swap(&mut self.buffer, &mut other.buffer);
+    }
+
     pub fn write_byte(&mut self, byte: u8) {
         match byte {
             0x08 => self.backspace(),
             b'\t' => self.horizontal_tab(),
             b'\n' => self.new_line(),
             b'\r' => self.carriage_return(),
-            byte => {
-                if self.column_position >= BUFFER_WIDTH {
-                    self.new_line()
-                }
-                let row = self.row_position.clone();
-                let col = self.column_position.clone();
-                let color_code = self.color_code.clone();
-                self.buffer.chars[row][col].write(ScreenChar {
-                    ascii_character: byte,
-                    color_code,
-                });
-
-                self.column_position += 1;
-            }
+            byte => self.put_byte(byte),
+        }
+    }
+
+    // 在光标处显示字库里的第 byte 个字符，控制字符也按字库里的图形显示
+    fn put_byte(&mut self, byte: u8) {
+        if self.column_position >= BUFFER_WIDTH {
+            self.new_line()
         }
+        let row = self.row_position.clone();
+        let col = self.column_position.clone();
+        let color_code = self.color_code.clone();
+        self.buffer.chars[row][col].write(ScreenChar {
+            ascii_character: byte,
+            color_code,
+        });
+
+        self.column_position += 1;
+    }
+
+    /// 设置之后输出的前景色和背景色
+    pub fn set_color(&mut self, foreground: Color, background: Color) {
+        self.color_code = ColorCode::new(foreground, background);
+    }
+
+    /// 换成指定的颜色清屏，之后不再跟随主题；panic 画面用
+    pub fn clear_with_color(&mut self, foreground: Color, background: Color) {
+        self.set_color(foreground, background);
+        self.theme_generation = theme::generation();
+        self.parser = Parser::new();
+        self.clear_screen();
+    }
+
+    // 恢复成主题的颜色
+    fn reset_color(&mut self) {
+        let theme = theme::get();
+        self.color_code = ColorCode::new(
+            theme.foreground.map_or(DEFAULT_FOREGROUND, Color::nearest),
+            theme.background.map_or(DEFAULT_BACKGROUND, Color::nearest),
+        );
+        self.theme_generation = theme::generation();
     }
 
     pub fn write_string(&mut self, s: &str) {
-        for byte in s.bytes() {
-            match byte {
-                0x20..=0x7e | b'\n' | b'\r' | b'\t' | 0x08 => self.write_byte(byte),
-                _ => self.write_byte(0xfe),
+        // 有新的输出时回到当前画面
+        self.scroll_to_live();
+        if self.theme_generation != theme::generation() {
+            self.reset_color();
+        }
+        let mut parser = core::mem::take(&mut self.parser);
+        for ch in s.chars() {
+            parser.feed(ch, |action| self.apply(action));
+        }
+        self.parser = parser;
+    }
+
+    fn apply(&mut self, action: Action) {
+        match action {
+            Action::Print(ch) => match ch {
+                '\n' | '\r' | '\t' | '\x08' => self.write_byte(ch as u8),
+                ch => match cp437::encode(ch) {
+                    Some(byte) => self.put_byte(byte),
+                    // 字库里没有的字符画成方块，全角字符画两个
+                    None => {
+                        for _ in 0..cp437::width(ch) {
+                            self.put_byte(0xfe);
+                        }
+                    }
+                },
+            },
+            Action::Foreground(color) => {
+                let default = || theme::get().foreground.map_or(DEFAULT_FOREGROUND, Color::nearest);
+                let color = color.map_or_else(default, |i| ANSI_COLORS[i as usize % 16]);
+                self.color_code = ColorCode(self.color_code.0 & 0xF0 | color as u8);
+            }
+            Action::Background(color) => {
+                // 只有 8 种背景色，高亮的背景色会被当成闪烁
+                let default = || theme::get().background.map_or(DEFAULT_BACKGROUND, Color::nearest);
+                let color = color.map_or_else(default, |i| ANSI_COLORS[i as usize % 8]);
+                self.color_code = ColorCode((color as u8) << 4 | self.color_code.0 & 0x0F);
+            }
+            Action::CursorUp(n) => self.row_position = self.row_position.saturating_sub(n),
+            Action::CursorDown(n) => self.row_position = (self.row_position + n).min(BUFFER_HEIGHT - 1),
+            Action::CursorForward(n) => self.column_position = (self.column_position + n).min(BUFFER_WIDTH - 1),
+            Action::CursorBack(n) => self.column_position = self.column_position.saturating_sub(n),
+            Action::CursorPosition(row, col) => {
+                self.row_position = row.min(BUFFER_HEIGHT - 1);
+                self.column_position = col.min(BUFFER_WIDTH - 1);
+            }
+            Action::ClearScreen => {
+                for row in 0..BUFFER_HEIGHT {
+                    self.clear_row(row);
+                }
+            }
+            Action::ClearLine => {
+                let blank = ScreenChar { ascii_character: b' ', color_code: self.color_code };
+                for col in self.column_position..BUFFER_WIDTH {
+                    self.buffer.chars[self.row_position][col].write(blank);
+                }
             }
         }
     }
@@ -127,6 +287,13 @@ impl Writer {
         self.column_position = 0;
 
         if self.row_position >= BUFFER_HEIGHT {
+            if self.scrollback_limit > 0 && allocator::is_initialized() {
+                if self.history.len() >= self.scrollback_limit {
+                    self.history.pop_front();
+                }
+                let row = self.read_row(0);
+                self.history.push_back(row);
+            }
             // 向上滚屏
             for row in 0..BUFFER_HEIGHT - 1 {
                 for col in 0..BUFFER_WIDTH {
@@ -144,6 +311,71 @@ impl Writer {
         }
     }
 
+    fn read_row(&self, row: usize) -> Row {
+        core::array::from_fn(|col| self.buffer.chars[row][col].read())
+    }
+
+    fn write_row(&mut self, row: usize, chars: &Row) {
+        for col in 0..BUFFER_WIDTH {
+            self.buffer.chars[row][col].write(chars[col]);
+        }
+    }
+
+    /// 保留的历史行数，0 表示不保留
+    pub fn set_scrollback_limit(&mut self, lines: usize) {
+        self.scroll_to_live();
+        self.scrollback_limit = lines;
+        while self.history.len() > lines {
+            self.history.pop_front();
+        }
+    }
+
+    /// 翻看滚出屏幕的历史，正数向上，每页比屏幕少一行，留一行上下文
+    pub fn scroll(&mut self, pages: isize) {
+        let lines = pages * (BUFFER_HEIGHT as isize - 1);
+        let view = (self.view as isize + lines).clamp(0, self.history.len() as isize) as usize;
+        if view == self.view {
+            return;
+        }
+        if self.view == 0 {
+            self.saved = (0..BUFFER_HEIGHT).map(|row| self.read_row(row)).collect();
+        }
+        self.view = view;
+        if view == 0 {
+            let saved = core::mem::take(&mut self.saved);
+            for (row, chars) in saved.iter().enumerate() {
+                self.write_row(row, chars);
+            }
+            return;
+        }
+        // 把历史和暂存的当前画面接起来，显示从末尾往前数 view 行的那一屏
+        let start = self.history.len() - view;
+        for row in 0..BUFFER_HEIGHT {
+            let index = start + row;
+            let chars = match self.history.get(index) {
+                Some(chars) => *chars,
+                None => self.saved[index - self.history.len()],
+            };
+            self.write_row(row, &chars);
+        }
+    }
+
+    fn scroll_to_live(&mut self) {
+        if self.view > 0 {
+            self.scroll(-(self.view as isize));
+        }
+    }
+
+    // 清屏并把光标移回左上角
+    pub fn clear_screen(&mut self) {
+        self.scroll_to_live();
+        for row in 0..BUFFER_HEIGHT {
+            self.clear_row(row);
+        }
+        self.row_position = 0;
+        self.column_position = 0;
+    }
+
     fn carriage_return(&mut self) {
         self.column_position = 0;
     }
@@ -162,11 +394,27 @@ impl Writer {
 // - 设置开始时光标位置和颜色代码。
 // - 因为访问裸指针和硬件资源是不安全的操作，所以需要unsafe块
 lazy_static! {
-    pub static ref WRITER: Mutex<Writer> = Mutex::new(Writer {
-        row_position: 0,
-        column_position: 0,
-        color_code: ColorCode::new(Color::LightCyan, Color::Black),
-        buffer: unsafe { &mut *(0xb8000 as *mut Buffer) },
+    pub static ref WRITER: Mutex<Writer> = Mutex::new(Writer::new(unsafe { &mut *(layout::VGA_TEXT.start as *mut Buffer) }));
+}
+
+// 虚拟控制台各自的字符缓冲区，不在前台的控制台写在这里；放在静态区，堆初始化之前也能用
+static mut BACKING: [[u16; BUFFER_WIDTH * BUFFER_HEIGHT]; vt::COUNT] = [[0; BUFFER_WIDTH * BUFFER_HEIGHT]; vt::COUNT];
+
+// 虚拟控制台，前台的控制台在 WRITER 里，它在这里的位置上放的是一个空的控制台
+struct Consoles {
+    active: usize,
+    writers: [Writer; vt::COUNT],
+}
+
+lazy_static! {
+    static ref CONSOLES: Mutex<Consoles> = Mutex::new(Consoles {
+        active: vt::active().index(),
+        writers: core::array::from_fn(|i| {
+            let buffer = unsafe { &mut *(core::ptr::addr_of_mut!(BACKING[i]) as *mut Buffer) };
+            let mut writer = Writer::new(buffer);
+            writer.clear_screen();
+            writer
+        }),
     });
 }
 
@@ -196,6 +444,52 @@ pub fn _print(args: fmt::Arguments) {
     })
 }
 
+/// `tty` 在后台时对它执行 `f` 并返回 true，在前台时返回 false，由调用者对 WRITER 执行
+pub fn with_background(tty: Tty, f: impl FnOnce(&mut Writer)) -> bool {
+    interrupts::without_interrupts(|| {
+        let mut consoles = CONSOLES.lock();
+        if consoles.active == tty.index() {
+            return false;
+        }
+        f(&mut consoles.writers[tty.index()]);
+        true
+    })
+}
+
+/// 把 `tty` 换到前台
+pub fn switch(tty: Tty) {
+    interrupts::without_interrupts(|| {
+        let mut consoles = CONSOLES.lock();
+        let (active, index) = (consoles.active, tty.index());
+        if active == index {
+            return;
+        }
+        WRITER.lock().exchange(&mut consoles.writers[index]);
+        consoles.writers.swap(active, index);
+        consoles.active = index;
+    })
+}
+
+/// 翻看历史，由键盘的扫描码解码调用；输出正在进行时放弃这次翻页
+pub fn scroll(pages: isize) {
+    if let Some(mut writer) = WRITER.try_lock() {
+        writer.scroll(pages);
+    }
+}
+
+/// 临时换成指定的颜色执行 `f`（比如在里面 println!），之后恢复原来的颜色
+pub fn with_color<R>(foreground: Color, background: Color, f: impl FnOnce() -> R) -> R {
+    let saved = interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        let saved = writer.color_code;
+        writer.set_color(foreground, background);
+        saved
+    });
+    let result = f();
+    interrupts::without_interrupts(|| WRITER.lock().color_code = saved);
+    result
+}
+
 // // 定义了一个宏 `print!`, 当调用此宏时将展开成对上面定义的 `_print()` 函数的调用，传递给定参数作为格式化参数列表。这个宏可以在crate中任何地方使用
 // #[macro_export]
 // macro_rules! print {
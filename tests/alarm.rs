@@ -0,0 +1,81 @@
+// 闹钟测试：到期的回调在 poll 时执行，RTC 闹钟按时响，取消的闹钟不会执行
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::time::Duration;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::drivers::rtc::{self, DateTime};
+use cjn_os::io::{alarm, timer};
+use x86_64::instructions::hlt;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    cjn_os::test_kernel_init(boot_info);
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+static FIRED: AtomicUsize = AtomicUsize::new(0);
+
+fn seconds_from_now(seconds: i64) -> DateTime {
+    DateTime::from_timestamp(rtc::now().timestamp() + seconds)
+}
+
+// 像 shell 的主循环一样边 hlt 边 poll，直到 `done` 或者超时
+fn wait_until(timeout: Duration, done: impl Fn() -> bool) {
+    let deadline = timer::uptime() + timeout;
+    while !done() && timer::uptime() < deadline {
+        hlt();
+        alarm::poll();
+    }
+}
+
+#[test_case]
+fn past_alarm_runs_on_poll() {
+    FIRED.store(0, Ordering::SeqCst);
+    alarm::schedule_at(seconds_from_now(-5), || {
+        FIRED.fetch_add(1, Ordering::SeqCst);
+    });
+    alarm::poll();
+    assert_eq!(FIRED.load(Ordering::SeqCst), 1);
+    assert!(alarm::pending().is_empty());
+}
+
+#[test_case]
+fn alarm_fires_on_time() {
+    FIRED.store(0, Ordering::SeqCst);
+    let start = timer::uptime();
+    alarm::schedule_at(seconds_from_now(2), || {
+        FIRED.fetch_add(1, Ordering::SeqCst);
+    });
+    alarm::poll();
+    assert_eq!(FIRED.load(Ordering::SeqCst), 0);
+    wait_until(Duration::from_secs(5), || FIRED.load(Ordering::SeqCst) > 0);
+    assert_eq!(FIRED.load(Ordering::SeqCst), 1);
+    // RTC 的时间只精确到秒
+    assert!(timer::uptime() - start >= Duration::from_secs(1));
+}
+
+#[test_case]
+fn cancelled_alarm_does_not_run() {
+    FIRED.store(0, Ordering::SeqCst);
+    let id = alarm::schedule_at(seconds_from_now(1), || {
+        FIRED.fetch_add(1, Ordering::SeqCst);
+    });
+    assert!(alarm::cancel(id));
+    assert!(!alarm::cancel(id));
+    wait_until(Duration::from_secs(3), || false);
+    assert_eq!(FIRED.load(Ordering::SeqCst), 0);
+}
@@ -0,0 +1,64 @@
+// 窗口动画：缓动曲线的端点和单调性，位置和不透明度的插值
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::gui::animation::{Animatable, Easing, Property};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    cjn_os::test_kernel_init(boot_info);
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+const CURVES: [Easing; 4] = [Easing::Linear, Easing::EaseIn, Easing::EaseOut, Easing::EaseInOut];
+
+#[test_case]
+fn easing_endpoints() {
+    for easing in CURVES {
+        assert_eq!(easing.apply(0.0), 0.0);
+        assert_eq!(easing.apply(1.0), 1.0);
+        // 超出范围的进度被限制住
+        assert_eq!(easing.apply(-1.0), 0.0);
+        assert_eq!(easing.apply(2.0), 1.0);
+    }
+    assert_eq!(Easing::EaseInOut.apply(0.5), 0.5);
+}
+
+#[test_case]
+fn easing_is_monotonic() {
+    for easing in CURVES {
+        let mut last = 0.0;
+        for i in 0..=100 {
+            let value = easing.apply(i as f32 / 100.0);
+            assert!(value >= last);
+            last = value;
+        }
+    }
+    // 加速的曲线开头比直线慢，减速的比直线快
+    assert!(Easing::EaseIn.apply(0.25) < 0.25);
+    assert!(Easing::EaseOut.apply(0.25) > 0.25);
+}
+
+#[test_case]
+fn interpolation() {
+    assert_eq!(f32::lerp(1.0, 0.0, 0.25), 0.75);
+    assert_eq!(usize::lerp(100, 0, 0.5), 50);
+    // 四舍五入到整数像素
+    assert_eq!(usize::lerp(0, 3, 0.5), 2);
+    assert_eq!(<(usize, usize)>::lerp((10, 800), (10, 500), 1.0), (10, 500));
+    assert_eq!(Property::Position((0, 0), (20, 40)).at(0.5), Property::Position((10, 20), (10, 20)));
+    assert_eq!(Property::Opacity(1.0, 0.0).at(1.0), Property::Opacity(0.0, 0.0));
+}
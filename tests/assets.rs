@@ -0,0 +1,159 @@
+// 资源管理：按格式解码、缓存共用同一份、没人用的可以回收、来源的优先级、theme/ 下的资源和主题文件
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::allocator::shrinker;
+use cjn_os::assets::{self, embedded, Data, Source, PRIORITY_DISK};
+use cjn_os::graphic::font::FontId;
+use cjn_os::io::theme::{self, ThemeError, WindowStyle};
+use embedded_graphics::pixelcolor::Rgb888;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    cjn_os::test_kernel_init(boot_info);
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+#[test_case]
+fn images_decode_by_format() {
+    let cursor = assets::image("icons/cursor.bmp").unwrap();
+    assert_eq!((cursor.width(), cursor.height()), (32, 32));
+    let close = assets::image("icons/window-close.qoi").unwrap();
+    assert_eq!((close.width(), close.height()), (16, 16));
+    assert!(assets::image("icons/missing.bmp").is_none());
+    // 字体不是图片
+    assert!(assets::image(embedded::MONOSPACE_FONT).is_none());
+    assert_eq!(assets::font(embedded::MONOSPACE_FONT), Some(FontId::MONOSPACE));
+}
+
+#[test_case]
+fn cache_shares_one_copy() {
+    let first = assets::image("icons/cursor.bmp").unwrap();
+    let second = assets::image("icons/cursor.bmp").unwrap();
+    assert!(core::ptr::eq(&*first, &*second));
+}
+
+fn reclaimable() -> usize {
+    let mut bytes = 0;
+    shrinker::for_each(|name, reclaimable| {
+        if name == "asset cache" {
+            bytes = reclaimable;
+        }
+    });
+    bytes
+}
+
+#[test_case]
+fn unused_images_are_reclaimable() {
+    let wallpaper = assets::image("wallpapers/default.bmp").unwrap();
+    let size = wallpaper.width() * wallpaper.height() * 4;
+    let held = reclaimable();
+    drop(wallpaper);
+    assert!(reclaimable() >= held + size);
+}
+
+struct TestSource;
+
+impl Source for TestSource {
+    fn name(&self) -> &'static str {
+        "test"
+    }
+
+    fn load(&self, name: &str) -> Option<Data> {
+        match name {
+            "sounds/beep.raw" | "icons/cursor.bmp" => Some(Data::Shared(Arc::from([1u8, 2, 3].as_slice()))),
+            _ => None,
+        }
+    }
+
+    fn names(&self) -> Vec<String> {
+        vec![String::from("sounds/beep.raw"), String::from("icons/cursor.bmp")]
+    }
+}
+
+static TEST_SOURCE: TestSource = TestSource;
+
+#[test_case]
+fn sources_are_searched_by_priority() {
+    assets::register_source(PRIORITY_DISK, &TEST_SOURCE);
+    assert_eq!(&*assets::data("sounds/beep.raw").unwrap(), &[1, 2, 3]);
+    // 编进内核的优先
+    assert!(matches!(assets::data("icons/cursor.bmp"), Some(Data::Static(_))));
+
+    let list = assets::list();
+    let source = |name: &str| list.iter().find(|info| info.name == name).map(|info| info.source);
+    assert_eq!(source("sounds/beep.raw"), Some("test"));
+    assert_eq!(source("icons/cursor.bmp"), Some("embedded"));
+}
+
+#[test_case]
+fn sprites_share_cached_image() {
+    let before = assets::stats();
+    let sprite = assets::sprite("icons/cursor.bmp").unwrap();
+    assert_eq!((sprite.width(), sprite.height()), (32, 32));
+    let after = assets::stats();
+    assert_eq!(after.hits + after.misses, before.hits + before.misses + 1);
+    assert!(after.bytes <= assets::CACHE_LIMIT);
+    // 精灵还在用，缓存里的图片不能被淘汰
+    let held = reclaimable();
+    drop(sprite);
+    assert!(reclaimable() >= held + 32 * 32 * 4);
+}
+
+struct ThemeSource;
+
+impl Source for ThemeSource {
+    fn name(&self) -> &'static str {
+        "theme"
+    }
+
+    fn load(&self, name: &str) -> Option<Data> {
+        match name {
+            "theme/icons/window-close.qoi" => Some(Data::Shared(Arc::from([9u8].as_slice()))),
+            "theme/theme.conf" => Some(Data::Static(b"# dark\nforeground = #e0e0e0\nbackground = none\nshadow_offset = 2 4\n")),
+            _ => None,
+        }
+    }
+
+    fn names(&self) -> Vec<String> {
+        vec![String::from("theme/icons/window-close.qoi"), String::from("theme/theme.conf")]
+    }
+}
+
+static THEME_SOURCE: ThemeSource = ThemeSource;
+
+#[test_case]
+fn theme_overrides_assets() {
+    assets::register_source(PRIORITY_DISK, &THEME_SOURCE);
+    // 比编进内核的优先
+    assert_eq!(&*assets::data("icons/window-close.qoi").unwrap(), &[9]);
+    assert!(matches!(assets::data("icons/cursor.bmp"), Some(Data::Static(_))));
+
+    let conf = assets::data("theme/theme.conf").unwrap();
+    let theme = theme::parse(core::str::from_utf8(&conf).unwrap()).unwrap();
+    assert_eq!((theme.foreground, theme.background), (Some(Rgb888::new(0xE0, 0xE0, 0xE0)), None));
+    assert_eq!(theme.window.shadow_offset, (2, 4));
+    assert_eq!(theme.window.corner_radius, WindowStyle::DEFAULT.corner_radius);
+    assert_eq!(theme::parse("corner_radius = 4\nshadow = 1"), Err(ThemeError::UnknownKey(2)));
+    assert_eq!(theme::parse("foreground = #12345"), Err(ThemeError::InvalidValue(1)));
+    assert_eq!(theme::parse("\n\nnone"), Err(ThemeError::Syntax(3)));
+}
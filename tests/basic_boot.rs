@@ -0,0 +1,37 @@
+// 最基本的启动测试：只做 init，检查控制台输出能用
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::println;
+
+entry_point!(main);
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    cjn_os::init();
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+#[test_case]
+fn test_println() {
+    println!("test_println output");
+}
+
+#[test_case]
+fn test_println_many() {
+    // 超过一屏，触发滚动
+    for i in 0..200 {
+        println!("test_println_many output {}", i);
+    }
+}
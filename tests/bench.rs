@@ -0,0 +1,32 @@
+// 微基准测试：把每一项跑一遍，BENCH 行写到串口，只在打开 bench 特性时编译
+// 测试的显示是 none，没有进入图形模式，compose 会报告跳过
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::bench::{self, Outcome};
+use cjn_os::io::qemu::{exit_qemu, QemuExitCode};
+use cjn_os::{perf, serial_println};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    cjn_os::test_kernel_init(boot_info);
+    perf::tsc_hz();
+    for benchmark in &bench::BENCHMARKS {
+        let outcome = benchmark.run();
+        serial_println!("{}", outcome);
+        if let Outcome::Done { min, max, .. } = outcome {
+            assert!(min > 0 && min <= max, "benchmark {} measured nothing", benchmark.name);
+        }
+    }
+    exit_qemu(QemuExitCode::Success);
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
@@ -0,0 +1,102 @@
+// 块设备：按名字读写，长度和范围不对的请求不会交给驱动
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::drivers::block::{self, BlockDevice, BlockError};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    cjn_os::test_kernel_init(boot_info);
+    block::attach(Box::new(RamDisk { data: vec![0; BLOCK_SIZE * BLOCKS] }));
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+const BLOCK_SIZE: usize = 512;
+const BLOCKS: usize = 8;
+
+// 驱动收到的请求数
+static REQUESTS: AtomicUsize = AtomicUsize::new(0);
+
+struct RamDisk {
+    data: Vec<u8>,
+}
+
+impl BlockDevice for RamDisk {
+    fn name(&self) -> &str {
+        "ram0"
+    }
+
+    fn model(&self) -> &str {
+        "test"
+    }
+
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        BLOCKS as u64
+    }
+
+    fn read_blocks(&mut self, lba: u64, buffer: &mut [u8]) -> Result<(), BlockError> {
+        REQUESTS.fetch_add(1, Ordering::Relaxed);
+        let start = lba as usize * BLOCK_SIZE;
+        buffer.copy_from_slice(&self.data[start..start + buffer.len()]);
+        Ok(())
+    }
+
+    fn write_blocks(&mut self, lba: u64, data: &[u8]) -> Result<(), BlockError> {
+        REQUESTS.fetch_add(1, Ordering::Relaxed);
+        let start = lba as usize * BLOCK_SIZE;
+        self.data[start..start + data.len()].copy_from_slice(data);
+        Ok(())
+    }
+}
+
+#[test_case]
+fn written_blocks_read_back() {
+    let data: Vec<u8> = (0..2 * BLOCK_SIZE).map(|i| i as u8).collect();
+    block::write("ram0", 3, &data).unwrap();
+    let mut buffer = vec![0; 2 * BLOCK_SIZE];
+    block::read("ram0", 3, &mut buffer).unwrap();
+    assert_eq!(buffer, data);
+}
+
+#[test_case]
+fn bad_requests_are_rejected() {
+    let requests = REQUESTS.load(Ordering::Relaxed);
+    let mut buffer = vec![0; BLOCK_SIZE];
+    assert_eq!(block::read("ram0", 0, &mut buffer[..100]), Err(BlockError::Unaligned));
+    assert_eq!(block::read("ram0", BLOCKS as u64, &mut buffer), Err(BlockError::OutOfRange));
+    assert_eq!(block::write("ram0", u64::MAX, &buffer), Err(BlockError::OutOfRange));
+    assert_eq!(block::read("sata9", 0, &mut buffer), Err(BlockError::NoDevice));
+    // 最后一块还能读
+    block::read("ram0", BLOCKS as u64 - 1, &mut buffer).unwrap();
+    assert_eq!(REQUESTS.load(Ordering::Relaxed), requests + 1);
+}
+
+#[test_case]
+fn devices_are_listed() {
+    let list = block::list();
+    let ram = list.iter().find(|info| info.name == "ram0").unwrap();
+    assert_eq!((ram.block_size, ram.block_count, ram.model.as_str()), (BLOCK_SIZE, BLOCKS as u64, "test"));
+}
@@ -0,0 +1,137 @@
+// 块缓存：预读、命中、延迟写回和 sync
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::drivers::block::{self, BlockDevice, BlockError};
+use cjn_os::fs::cache;
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    cjn_os::test_kernel_init(boot_info);
+    block::attach(Box::new(RamDisk { data: DATA.clone() }));
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+const BLOCK_SIZE: usize = 512;
+const BLOCKS: usize = 256;
+
+lazy_static! {
+    // 磁盘的内容，测试直接看它判断有没有写回
+    static ref DATA: Arc<Mutex<Vec<u8>>> =
+        Arc::new(Mutex::new((0..BLOCK_SIZE * BLOCKS).map(|i| (i / BLOCK_SIZE) as u8).collect()));
+}
+
+struct RamDisk {
+    data: Arc<Mutex<Vec<u8>>>,
+}
+
+impl BlockDevice for RamDisk {
+    fn name(&self) -> &str {
+        "ram0"
+    }
+
+    fn model(&self) -> &str {
+        "test"
+    }
+
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        BLOCKS as u64
+    }
+
+    fn read_blocks(&mut self, lba: u64, buffer: &mut [u8]) -> Result<(), BlockError> {
+        let start = lba as usize * BLOCK_SIZE;
+        buffer.copy_from_slice(&self.data.lock()[start..start + buffer.len()]);
+        Ok(())
+    }
+
+    fn write_blocks(&mut self, lba: u64, data: &[u8]) -> Result<(), BlockError> {
+        let start = lba as usize * BLOCK_SIZE;
+        self.data.lock()[start..start + data.len()].copy_from_slice(data);
+        Ok(())
+    }
+}
+
+fn stats() -> cache::Stats {
+    cache::list().into_iter().find(|info| info.device == "ram0").map(|info| info.stats).unwrap_or_default()
+}
+
+#[test_case]
+fn reads_ahead_and_hits() {
+    let before = stats();
+    let mut buffer = vec![0; BLOCK_SIZE];
+    cache::read("ram0", 10, &mut buffer).unwrap();
+    assert!(buffer.iter().all(|&b| b == 10));
+    // 后面几块已经预读进来了，不再访问磁盘
+    for lba in 11..14 {
+        cache::read("ram0", lba, &mut buffer).unwrap();
+        assert!(buffer.iter().all(|&b| b == lba as u8));
+    }
+    let after = stats();
+    assert_eq!(after.device_reads, before.device_reads + 1);
+    assert_eq!(after.misses, before.misses + 1);
+    assert_eq!(after.hits, before.hits + 3);
+    assert!(after.read_ahead > before.read_ahead);
+}
+
+#[test_case]
+fn writes_are_delayed_until_sync() {
+    let data = vec![0xAA; 2 * BLOCK_SIZE];
+    cache::write("ram0", 100, &data).unwrap();
+    // 读到的是缓存里的新数据，磁盘上还是旧的
+    let mut buffer = vec![0; 2 * BLOCK_SIZE];
+    cache::read("ram0", 100, &mut buffer).unwrap();
+    assert_eq!(buffer, data);
+    assert_eq!(DATA.lock()[100 * BLOCK_SIZE], 100);
+
+    let before = stats();
+    cache::sync().unwrap();
+    let after = stats();
+    assert!(DATA.lock()[100 * BLOCK_SIZE..102 * BLOCK_SIZE].iter().all(|&b| b == 0xAA));
+    // 相邻的两块一次写回
+    assert_eq!(after.device_writes, before.device_writes + 1);
+    assert_eq!(after.written_back, before.written_back + 2);
+}
+
+#[test_case]
+fn read_ahead_keeps_dirty_blocks() {
+    // 先写脏第 201 块，再读第 200 块，预读不能用磁盘上的旧数据盖掉它
+    cache::write("ram0", 201, &[0x55; BLOCK_SIZE]).unwrap();
+    let mut buffer = vec![0; 2 * BLOCK_SIZE];
+    cache::read("ram0", 200, &mut buffer).unwrap();
+    assert!(buffer[..BLOCK_SIZE].iter().all(|&b| b == 200));
+    assert!(buffer[BLOCK_SIZE..].iter().all(|&b| b == 0x55));
+    cache::sync().unwrap();
+}
+
+#[test_case]
+fn bad_requests_are_rejected() {
+    let mut buffer = vec![0; BLOCK_SIZE];
+    assert_eq!(cache::read("ram0", 0, &mut buffer[..1]), Err(BlockError::Unaligned));
+    assert_eq!(cache::read("ram0", BLOCKS as u64, &mut buffer), Err(BlockError::OutOfRange));
+    assert_eq!(cache::write("sata9", 0, &buffer), Err(BlockError::NoDevice));
+}
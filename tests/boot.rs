@@ -0,0 +1,154 @@
+// 启动单元：按依赖顺序初始化，依赖失败时跳过，依赖不存在和有环时报错；解析 Multiboot2 引导信息
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use bootloader::bootinfo::MemoryRegionType;
+use cjn_os::boot::info::{BootInfo as _, Framebuffer};
+use cjn_os::boot::multiboot2::{self, ParseError};
+use cjn_os::boot::{self, InitError, Record, Status, Unit};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    cjn_os::test_kernel_init(boot_info);
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+// 测试用的单元把自己的名字记进上下文
+type Log = Vec<&'static str>;
+
+fn record(log: &mut Log, name: &'static str) -> Result<(), InitError> {
+    log.push(name);
+    Ok(())
+}
+
+static BUS: Unit<Log> = Unit { name: "bus", depends: &[], init: |log| record(log, "bus") };
+static DISK: Unit<Log> = Unit { name: "disk", depends: &["bus", "dma"], init: |log| record(log, "disk") };
+static DMA: Unit<Log> = Unit { name: "dma", depends: &[], init: |log| record(log, "dma") };
+static FS: Unit<Log> = Unit { name: "fs", depends: &["disk"], init: |log| record(log, "fs") };
+static BROKEN: Unit<Log> = Unit {
+    name: "dma",
+    depends: &[],
+    init: |_| Err(InitError::Failed(String::from("no memory"))),
+};
+static PING: Unit<Log> = Unit { name: "ping", depends: &["pong"], init: |log| record(log, "ping") };
+static PONG: Unit<Log> = Unit { name: "pong", depends: &["ping"], init: |log| record(log, "pong") };
+
+fn statuses(records: &[Record]) -> Vec<(&'static str, Status)> {
+    records.iter().map(|record| (record.name, record.status.clone())).collect()
+}
+
+#[test_case]
+fn dependency_order() {
+    let mut log = Log::new();
+    let records = boot::run(&[&FS, &DISK, &BUS, &DMA], &mut log);
+    assert_eq!(log, ["bus", "dma", "disk", "fs"]);
+    assert!(records.iter().all(|record| record.status == Status::Ready));
+    assert!(boot::records().len() >= 4);
+}
+
+#[test_case]
+fn skips_after_failure() {
+    let mut log = Log::new();
+    let records = boot::run(&[&BUS, &BROKEN, &DISK, &FS], &mut log);
+    assert_eq!(log, ["bus"]);
+    assert_eq!(statuses(&records), [
+        ("bus", Status::Ready),
+        ("dma", Status::Failed(InitError::Failed(String::from("no memory")))),
+        ("disk", Status::Skipped("dma")),
+        ("fs", Status::Skipped("disk")),
+    ]);
+}
+
+#[test_case]
+fn missing_dependency() {
+    let mut log = Log::new();
+    let records = boot::run(&[&DISK, &BUS], &mut log);
+    assert_eq!(log, ["bus"]);
+    assert_eq!(statuses(&records), [
+        ("bus", Status::Ready),
+        ("disk", Status::Failed(InitError::MissingDependency("dma"))),
+    ]);
+}
+
+#[test_case]
+fn cycle() {
+    let mut log = Log::new();
+    let records = boot::run(&[&PING, &BUS, &PONG], &mut log);
+    assert_eq!(log, ["bus"]);
+    assert_eq!(statuses(&records), [
+        ("bus", Status::Ready),
+        ("ping", Status::Failed(InitError::Cycle)),
+        ("pong", Status::Failed(InitError::Cycle)),
+    ]);
+}
+
+// 追加一个标签，按 8 字节对齐
+fn tag(info: &mut Vec<u8>, kind: u32, body: &[u8]) {
+    info.extend_from_slice(&kind.to_le_bytes());
+    info.extend_from_slice(&(8 + body.len() as u32).to_le_bytes());
+    info.extend_from_slice(body);
+    info.resize(info.len().next_multiple_of(8), 0);
+}
+
+#[test_case]
+fn multiboot2_info() {
+    let mut info = vec![0; 8];
+    tag(&mut info, 1, b"video=800x600\0");
+    let mut memory_map = Vec::new();
+    memory_map.extend_from_slice(&24u32.to_le_bytes());
+    memory_map.extend_from_slice(&0u32.to_le_bytes());
+    for (base, length, kind) in [(0u64, 0x9F000u64, 1u32), (0x100000, 0x7F00000, 1), (0xF0000, 0x10000, 2)] {
+        memory_map.extend_from_slice(&base.to_le_bytes());
+        memory_map.extend_from_slice(&length.to_le_bytes());
+        memory_map.extend_from_slice(&kind.to_le_bytes());
+        memory_map.extend_from_slice(&0u32.to_le_bytes());
+    }
+    tag(&mut info, 6, &memory_map);
+    let mut framebuffer = Vec::new();
+    framebuffer.extend_from_slice(&0xFD00_0000u64.to_le_bytes());
+    for value in [3200u32, 800, 600] {
+        framebuffer.extend_from_slice(&value.to_le_bytes());
+    }
+    framebuffer.extend_from_slice(&[32, 1, 0, 0]);
+    tag(&mut info, 8, &framebuffer);
+    tag(&mut info, 0, &[]);
+    let total = info.len() as u32;
+    info[..4].copy_from_slice(&total.to_le_bytes());
+    let info: &'static [u8] = info.leak();
+
+    assert_eq!(multiboot2::parse(&info[..16], 0x300000, 0x100000..0x200000).err(), Some(ParseError::Truncated));
+    let parsed = multiboot2::parse(info, 0x300000, 0x100000..0x200000).unwrap();
+    assert_eq!(parsed.protocol(), "multiboot2");
+    assert_eq!(parsed.command_line(), Some("video=800x600"));
+    assert_eq!(parsed.framebuffer(), Some(Framebuffer { address: 0xFD00_0000, width: 800, height: 600, pitch: 3200, bpp: 32 }));
+    // 内核和引导信息从可用内存里挖掉
+    let regions: Vec<_> = parsed.memory_map().iter()
+        .map(|region| (region.range.start_addr(), region.range.end_addr(), region.region_type))
+        .collect();
+    assert_eq!(regions, [
+        (0, 0x9F000, MemoryRegionType::Usable),
+        (0xF0000, 0x100000, MemoryRegionType::Reserved),
+        (0x100000, 0x200000, MemoryRegionType::Kernel),
+        (0x200000, 0x300000, MemoryRegionType::Usable),
+        (0x300000, 0x301000, MemoryRegionType::BootInfo),
+        (0x301000, 0x8000000, MemoryRegionType::Usable),
+    ]);
+}
@@ -0,0 +1,81 @@
+// 通道和等待队列：容量上限、发送端和接收端释放之后的行为、等待超时、中断里唤醒
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::io::timer::{self, uptime};
+use cjn_os::sync::channel::SendError;
+use cjn_os::sync::{channel, WaitQueue};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    cjn_os::test_kernel_init(boot_info);
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+#[test_case]
+fn bounded_queue() {
+    let (sender, receiver) = channel(2);
+    let other = sender.clone();
+    assert_eq!(sender.send(1), Ok(()));
+    assert_eq!(other.send(2), Ok(()));
+    assert_eq!(sender.send(3), Err(SendError::Full(3)));
+    assert_eq!(receiver.recv(), Some(1));
+    assert_eq!(receiver.try_recv(), Some(2));
+    assert_eq!(receiver.try_recv(), None);
+}
+
+#[test_case]
+fn disconnected() {
+    let (sender, receiver) = channel(4);
+    sender.send('a').unwrap();
+    drop(sender);
+    // 剩下的消息还能取走，之后 recv 不再等待
+    assert_eq!(receiver.recv(), Some('a'));
+    assert_eq!(receiver.recv(), None);
+
+    let (sender, receiver) = channel(4);
+    drop(receiver);
+    assert_eq!(sender.send(1), Err(SendError::Disconnected(1)));
+}
+
+#[test_case]
+fn recv_timeout() {
+    let (_sender, receiver) = channel::<u8>(1);
+    let start = uptime();
+    assert_eq!(receiver.recv_timeout(Duration::from_millis(20)), None);
+    assert!(uptime() - start >= Duration::from_millis(20));
+}
+
+static QUEUE: WaitQueue = WaitQueue::new();
+static FLAG: AtomicBool = AtomicBool::new(false);
+
+fn set_flag() {
+    FLAG.store(true, Ordering::Release);
+    QUEUE.wake_all();
+}
+
+#[test_case]
+fn woken_from_interrupt() {
+    // 时钟中断里的周期回调设置条件并唤醒
+    timer::register_periodic(Duration::from_millis(10), set_flag);
+    QUEUE.block_on_condition(|| FLAG.load(Ordering::Acquire));
+    assert!(FLAG.load(Ordering::Acquire));
+    assert!(!QUEUE.has_waiters());
+}
@@ -0,0 +1,65 @@
+// 剪贴板：输入框的复制、剪切和粘贴，过长的文字按字符边界截断
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::string::String;
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::gui::clipboard;
+use cjn_os::gui::widgets::{Bounds, Event, TextBox, Widget};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    cjn_os::test_kernel_init(boot_info);
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+fn type_text(text_box: &mut TextBox, text: &str) {
+    for ch in text.chars() {
+        text_box.handle_event(&Event::Key(ch));
+    }
+}
+
+#[test_case]
+fn text_box_copy_cut_paste() {
+    let mut from = TextBox::new(Bounds::new(0, 0, 100, 20), |_| {});
+    let mut to = TextBox::new(Bounds::new(0, 0, 100, 20), |_| {});
+    type_text(&mut from, "hello");
+    from.handle_event(&Event::Key(clipboard::COPY));
+    assert_eq!(clipboard::get_text(), "hello");
+    assert_eq!(from.text(), "hello");
+    from.handle_event(&Event::Key(clipboard::CUT));
+    assert_eq!(from.text(), "");
+    // 只粘贴第一行
+    clipboard::set_text("ls -l\nreboot");
+    type_text(&mut to, "$ ");
+    assert!(to.handle_event(&Event::Key(clipboard::PASTE)));
+    assert_eq!(to.text(), "$ ls -l");
+}
+
+#[test_case]
+fn long_text_is_truncated() {
+    let mut text = String::new();
+    while text.len() < 70 * 1024 {
+        text.push('中');
+    }
+    clipboard::set_text(&text);
+    let copied = clipboard::get_text();
+    assert!(copied.len() <= 64 * 1024);
+    assert!(copied.chars().all(|ch| ch == '中'));
+    clipboard::clear();
+    assert!(clipboard::is_empty());
+}
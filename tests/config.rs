@@ -0,0 +1,64 @@
+// 启动参数：key=value 的拆分、注释、重复的键，分辨率和大小的解析；测试时没有给 fw_cfg 文件
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::config::{self, Video};
+use cjn_os::drivers::fw_cfg;
+
+entry_point!(main);
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    cjn_os::init();
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+const CMDLINE: &str = "video=1024x768 loglevel=debug\n# heap=1G\nheap=128M quiet # 行尾的注释\nloglevel=warn";
+
+#[test_case]
+fn lookup() {
+    assert_eq!(config::lookup(CMDLINE, "video"), Some("1024x768"));
+    assert_eq!(config::lookup(CMDLINE, "heap"), Some("128M"));
+    assert_eq!(config::lookup(CMDLINE, "loglevel"), Some("warn"));
+    assert_eq!(config::lookup(CMDLINE, "quiet"), Some(""));
+    assert_eq!(config::lookup(CMDLINE, "missing"), None);
+    assert_eq!(config::options(CMDLINE).count(), 5);
+}
+
+#[test_case]
+fn parse_video() {
+    assert_eq!(config::parse_video("1024x768"), Some(Video { width: 1024, height: 768, bpp: None }));
+    assert_eq!(config::parse_video("640x480x24"), Some(Video { width: 640, height: 480, bpp: Some(24) }));
+    assert_eq!(config::parse_video("1024"), None);
+    assert_eq!(config::parse_video("0x768"), None);
+    assert_eq!(config::parse_video("1x2x3x4"), None);
+}
+
+#[test_case]
+fn parse_size() {
+    assert_eq!(config::parse_size("4096"), Some(4096));
+    assert_eq!(config::parse_size("512k"), Some(512 * 1024));
+    assert_eq!(config::parse_size("128M"), Some(128 << 20));
+    assert_eq!(config::parse_size("1G"), Some(1 << 30));
+    assert_eq!(config::parse_size("M"), None);
+    assert_eq!(config::parse_size("-1M"), None);
+}
+
+#[test_case]
+fn no_cmdline_under_test() {
+    assert!(fw_cfg::is_present());
+    assert!(fw_cfg::find(config::FW_CFG_FILE).is_none());
+    assert_eq!(config::get("video"), None);
+    assert_eq!(config::log_level(), None);
+}
@@ -0,0 +1,56 @@
+// 离屏控制台：capture 期间的 println! 写进控制台而不上屏，按行保存，超出的行丢掉最早的
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::string::String;
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::io::console;
+use cjn_os::{print, println};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    cjn_os::test_kernel_init(boot_info);
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+#[test_case]
+fn capture_println() {
+    let id = console::create("job");
+    let result = console::capture(id, || {
+        println!("first");
+        print!("second, ");
+        print!("unfinished");
+        7
+    });
+    assert_eq!(result, 7);
+    println!("not captured");
+    assert_eq!(console::lines(id).unwrap(), ["first", "second, unfinished"]);
+    assert!(console::list().contains(&(id, String::from("job"))));
+    assert!(console::destroy(id).is_some());
+    assert!(console::lines(id).is_none());
+}
+
+#[test_case]
+fn keeps_latest_lines() {
+    let id = console::create("long");
+    console::capture(id, || (0..300).for_each(|i| println!("{}", i)));
+    let lines = console::lines(id).unwrap();
+    assert_eq!(lines.len(), 256);
+    assert_eq!(lines[0], "44");
+    assert_eq!(lines[255], "299");
+    console::destroy(id);
+}
@@ -0,0 +1,70 @@
+// 控制通道的 JSON：解析请求、转义、输出紧凑的一行
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::string::ToString;
+use alloc::vec;
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::debug::control::json::{self, Value};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    cjn_os::test_kernel_init(boot_info);
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+#[test_case]
+fn parses_request() {
+    let request = json::parse(r#" {"jsonrpc":"2.0","id":7,"method":"mouse","params":{"dx":-3,"left":true}} "#).unwrap();
+    assert_eq!(request.get("id").and_then(Value::as_i64), Some(7));
+    assert_eq!(request.get("method").and_then(Value::as_str), Some("mouse"));
+    let params = request.get("params").unwrap();
+    assert_eq!(params.get("dx").and_then(Value::as_i64), Some(-3));
+    assert_eq!(params.get("left").and_then(Value::as_bool), Some(true));
+    assert_eq!(params.get("right"), None);
+}
+
+#[test_case]
+fn parses_strings_and_arrays() {
+    let value = json::parse(r#"["a\"b\\c\n", "é😀", [], 1.5e2, null]"#).unwrap();
+    assert_eq!(value, Value::Array(vec![
+        "a\"b\\c\n".into(),
+        "é😀".into(),
+        Value::Array(vec![]),
+        Value::Number(150.0),
+        Value::Null,
+    ]));
+}
+
+#[test_case]
+fn rejects_malformed() {
+    for text in ["", "{", r#"{"a" 1}"#, "[1,]", "tru", r#""\x""#, "1 2", r#"{"a":1}}"#] {
+        assert_eq!(json::parse(text), None, "{}", text);
+    }
+}
+
+#[test_case]
+fn writes_compact_line() {
+    let value = Value::object([
+        ("id", 3u64.into()),
+        ("text", "tab\there \"quoted\"".into()),
+        ("ratio", 0.5f64.into()),
+        ("ok", true.into()),
+    ]);
+    assert_eq!(value.to_string(), r#"{"id":3,"text":"tab\there \"quoted\"","ratio":0.5,"ok":true}"#);
+    assert_eq!(json::parse(&value.to_string()), Some(value));
+}
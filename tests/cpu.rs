@@ -0,0 +1,39 @@
+// CPU 功能检测：x86_64 必有的功能一定能检测到，厂商字符串可读
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::cpu;
+
+entry_point!(main);
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    cjn_os::init();
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+#[test_case]
+fn baseline_features_are_present() {
+    let features = cpu::features();
+    // 长模式要求 SSE2、FXSR 和 TSC
+    assert!(features.sse && features.sse2 && features.fxsr && features.tsc);
+    assert!(features.flags().any(|name| name == "sse2"));
+}
+
+#[test_case]
+fn vendor_is_readable() {
+    let vendor = cpu::features().vendor();
+    assert_eq!(vendor.len(), 12);
+    assert!(vendor.bytes().all(|b| b.is_ascii_graphic()));
+}
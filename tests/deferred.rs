@@ -0,0 +1,93 @@
+// 延后执行的工作：按优先级执行，低优先级不会饿死，每次执行的项数有上限，队列满了丢弃
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::interrupts::deferred::{self, Priority, BUDGET, CAPACITY};
+use spin::Mutex;
+
+entry_point!(main);
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    cjn_os::init();
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+// 执行过的工作的参数，按执行顺序
+static ORDER: Mutex<([usize; 64], usize)> = Mutex::new(([0; 64], 0));
+
+fn record(arg: usize) {
+    let mut order = ORDER.lock();
+    let (items, len) = &mut *order;
+    if *len < items.len() {
+        items[*len] = arg;
+        *len += 1;
+    }
+}
+
+fn ignore(_arg: usize) {}
+
+// 执行完所有排队的工作，返回执行顺序
+fn drain() -> ([usize; 64], usize) {
+    *ORDER.lock() = ([0; 64], 0);
+    while deferred::run() > 0 {}
+    *ORDER.lock()
+}
+
+#[test_case]
+fn priority_order() {
+    assert!(deferred::defer(Priority::Low, record, 1));
+    assert!(deferred::defer(Priority::Normal, record, 2));
+    assert!(deferred::defer(Priority::High, record, 3));
+    let (order, len) = drain();
+    assert_eq!(&order[..len], &[3, 2, 1]);
+}
+
+#[test_case]
+fn low_priority_is_not_starved() {
+    deferred::defer(Priority::Low, record, 0);
+    for i in 1..=20 {
+        deferred::defer(Priority::High, record, i);
+    }
+    let (order, len) = drain();
+    assert_eq!(len, 21);
+    let position = order[..len].iter().position(|&arg| arg == 0).unwrap();
+    assert!(position > 0 && position < 20);
+}
+
+#[test_case]
+fn budget() {
+    for _ in 0..BUDGET + 10 {
+        deferred::defer(Priority::Normal, ignore, 0);
+    }
+    assert_eq!(deferred::run(), BUDGET);
+    assert_eq!(deferred::pending(), 10);
+    assert_eq!(deferred::run(), 10);
+    assert_eq!(deferred::pending(), 0);
+}
+
+#[test_case]
+fn full_queue_drops() {
+    let dropped = deferred::stats()[2].dropped;
+    for _ in 0..CAPACITY {
+        assert!(deferred::defer(Priority::Low, ignore, 0));
+    }
+    assert!(!deferred::defer(Priority::Low, ignore, 0));
+    let stats = deferred::stats()[2];
+    assert_eq!(stats.priority, Priority::Low);
+    assert_eq!(stats.pending, CAPACITY);
+    assert_eq!(stats.dropped, dropped + 1);
+    drain();
+    assert_eq!(deferred::pending(), 0);
+}
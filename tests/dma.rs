@@ -0,0 +1,60 @@
+// DMA 内存：物理连续、按要求对齐、清零，释放时撤销映射
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::io::pci::{pci_enumerate, PciDevice};
+use cjn_os::memory::dma::{self, DmaError, MASK_32};
+use cjn_os::memory::{self, translate};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    let (_, frame_allocator) = cjn_os::test_kernel_init(boot_info);
+    memory::install_frame_allocator(frame_allocator);
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+// 只用来给映射记上请求者，随便哪个设备都行
+fn device() -> PciDevice {
+    pci_enumerate().into_iter().next().expect("no PCI device")
+}
+
+#[test_case]
+fn coherent_buffer_is_contiguous_and_aligned() {
+    const SIZE: usize = 3 * 4096 + 1;
+    const ALIGN: usize = 64 * 1024;
+    let active = dma::active_mappings();
+    let mut buffer = dma::alloc_coherent(&device(), SIZE, ALIGN, MASK_32).unwrap();
+    assert_eq!(buffer.len(), SIZE);
+    assert_eq!(buffer.phys().as_u64() % ALIGN as u64, 0);
+    assert!(buffer.phys().as_u64() + SIZE as u64 <= MASK_32);
+    // 默认的恒等映射
+    assert_eq!(buffer.bus().as_u64(), buffer.phys().as_u64());
+    assert!(buffer.as_slice().iter().all(|&b| b == 0));
+    for page in 0..4 {
+        let offset = page * 4096;
+        assert_eq!(translate(buffer.virt() + offset), Some(buffer.phys() + offset));
+    }
+    buffer.as_mut_slice()[SIZE - 1] = 0xAB;
+    assert_eq!(unsafe { *buffer.ptr().add(SIZE - 1) }, 0xAB);
+    assert_eq!(dma::active_mappings(), active + 1);
+    drop(buffer);
+    assert_eq!(dma::active_mappings(), active);
+}
+
+#[test_case]
+fn empty_buffer_is_rejected() {
+    assert!(matches!(dma::alloc_coherent(&device(), 0, 4096, MASK_32), Err(DmaError::Empty)));
+}
@@ -0,0 +1,80 @@
+// 日志环形缓冲区：记下的内容、级别过滤、太长的消息被截断、清除
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::format;
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::logger::ring;
+use log::{Level, LevelFilter};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    cjn_os::test_kernel_init(boot_info);
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+#[test_case]
+fn records() {
+    let since = ring::next_sequence();
+    log::info!("ring test {}", 42);
+    let entries = ring::entries(since, LevelFilter::Trace);
+    assert_eq!(entries.len(), 1);
+    let entry = &entries[0];
+    assert_eq!(entry.sequence, since);
+    assert_eq!(entry.level, Level::Info);
+    assert_eq!(entry.target(), "dmesg");
+    assert_eq!(entry.message(), "ring test 42");
+    assert!(!entry.is_truncated());
+    assert!(format!("{}", entry).ends_with("INFO  dmesg: ring test 42"));
+}
+
+#[test_case]
+fn level_filter() {
+    let since = ring::next_sequence();
+    log::warn!("warning");
+    log::debug!("detail");
+    assert_eq!(ring::entries(since, LevelFilter::Trace).len(), 2);
+    let entries = ring::entries(since, LevelFilter::Warn);
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].message(), "warning");
+}
+
+#[test_case]
+fn truncated() {
+    let since = ring::next_sequence();
+    log::info!("{}", "é".repeat(200));
+    log::info!("first line\nsecond line");
+    let entries = ring::entries(since, LevelFilter::Trace);
+    assert!(entries[0].is_truncated());
+    assert!(entries[0].message().chars().all(|ch| ch == 'é'));
+    assert!(format!("{}", entries[0]).ends_with("..."));
+    assert_eq!(entries[1].message(), "first line");
+    assert!(entries[1].is_truncated());
+}
+
+#[test_case]
+fn clear() {
+    log::info!("before clear");
+    ring::clear();
+    let next = ring::next_sequence();
+    assert!(ring::entries(0, LevelFilter::Trace).is_empty());
+    assert_eq!(ring::overwritten(), 0);
+    log::info!("after clear");
+    let entries = ring::entries(0, LevelFilter::Trace);
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].sequence, next);
+}
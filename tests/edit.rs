@@ -0,0 +1,90 @@
+// 文本编辑器：插入和删除、跨行移动光标、整行剪切，保存后按名字读回来
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::apps::edit::{self, Buffer};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    cjn_os::test_kernel_init(boot_info);
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+#[test_case]
+fn insert_and_delete() {
+    let mut buffer = Buffer::new("héllo\r\nworld\tx");
+    assert_eq!(buffer.lines(), ["héllo", "world    x"]);
+    assert!(!buffer.is_modified());
+    buffer.move_end();
+    buffer.insert('!');
+    buffer.insert('\n');
+    assert_eq!(buffer.cursor(), (1, 0));
+    buffer.insert_str("new");
+    assert_eq!(buffer.text(), "héllo!\nnew\nworld    x");
+    // 行首退格和上一行合并，行尾删除和下一行合并
+    buffer.move_home();
+    assert!(buffer.backspace());
+    assert_eq!(buffer.cursor(), (0, 6));
+    buffer.move_end();
+    assert!(buffer.delete());
+    assert_eq!(buffer.text(), "héllo!newworld    x");
+    assert!(!buffer.delete());
+    assert!(buffer.is_modified());
+}
+
+#[test_case]
+fn cursor_movement() {
+    let mut buffer = Buffer::new("long line\nab\n");
+    buffer.move_end();
+    buffer.move_down();
+    assert_eq!(buffer.cursor(), (1, 2));
+    buffer.move_right();
+    assert_eq!(buffer.cursor(), (2, 0));
+    buffer.move_right();
+    assert_eq!(buffer.cursor(), (2, 0));
+    buffer.move_left();
+    buffer.move_left();
+    assert_eq!(buffer.cursor(), (1, 1));
+    buffer.move_up();
+    buffer.move_up();
+    assert_eq!(buffer.cursor(), (0, 1));
+    buffer.move_home();
+    assert!(!buffer.backspace());
+}
+
+#[test_case]
+fn cut_line() {
+    let mut buffer = Buffer::new("one\ntwo\nthree");
+    buffer.move_down();
+    buffer.move_down();
+    assert_eq!(buffer.delete_line(), "three");
+    assert_eq!(buffer.cursor(), (1, 0));
+    assert_eq!(buffer.delete_line(), "two");
+    assert_eq!(buffer.delete_line(), "one");
+    assert_eq!(buffer.lines(), [""]);
+}
+
+#[test_case]
+fn save_and_load() {
+    assert_eq!(edit::load("notes.txt"), None);
+    edit::save("notes.txt", "first\nsecond");
+    assert_eq!(edit::load("notes.txt").as_deref(), Some("first\nsecond"));
+    assert!(edit::files().iter().any(|(name, size)| name == "notes.txt" && *size == 12));
+    // 二进制的资源不能当作文本打开
+    assert_eq!(edit::load("icons/cursor.bmp"), None);
+}
@@ -0,0 +1,55 @@
+// 异常处理：内核里加载越过 GDT 末尾的选择子触发一般保护错误，处理函数必须 panic，而且 panic 信息里有解码后的选择子
+#![no_std]
+#![no_main]
+
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::io::qemu::{exit_qemu, QemuExitCode};
+use cjn_os::{serial_print, serial_println};
+
+// GDT 只有几项，索引 0x246 的选择子超出了界限
+const SELECTOR: u16 = 0x1230;
+
+entry_point!(main);
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    cjn_os::gdt::init();
+    cjn_os::interrupts::init_idt();
+    serial_print!("exception::general_protection_fault...\t");
+    unsafe { core::arch::asm!("mov es, {0:x}", in(reg) SELECTOR, options(nostack, preserves_flags)) };
+    serial_println!("[test did not panic]");
+    exit_qemu(QemuExitCode::Failed);
+    cjn_os::hlt_loop();
+}
+
+// 把 panic 信息写进定长的缓冲区，测试里没有堆
+struct Buffer {
+    bytes: [u8; 256],
+    len: usize,
+}
+
+impl Write for Buffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let len = s.len().min(self.bytes.len() - self.len);
+        self.bytes[self.len..self.len + len].copy_from_slice(&s.as_bytes()[..len]);
+        self.len += len;
+        Ok(())
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    let mut buffer = Buffer { bytes: [0; 256], len: 0 };
+    let _ = write!(buffer, "{}", info);
+    let message = core::str::from_utf8(&buffer.bytes[..buffer.len]).unwrap_or("");
+    if message.contains("GENERAL PROTECTION FAULT (GDT selector 0x1230)") {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    } else {
+        serial_println!("[failed]\n{}", message);
+        exit_qemu(QemuExitCode::Failed);
+    }
+    cjn_os::hlt_loop();
+}
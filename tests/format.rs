@@ -0,0 +1,72 @@
+// 数字、大小和时长的格式化，以及切换 Locale
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::ToString;
+use core::panic::PanicInfo;
+use core::time::Duration;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::io::format::{self, Clock, Elapsed, Locale, Size, Thousands};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    cjn_os::test_kernel_init(boot_info);
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+#[test_case]
+fn sizes() {
+    format::set_locale(Locale::EN);
+    assert_eq!(Size(0).to_string(), "0 B");
+    assert_eq!(Size(1023).to_string(), "1,023 B");
+    assert_eq!(Size(1536).to_string(), "1.5 KiB");
+    assert_eq!(Size(60 * 1024 * 1024).to_string(), "60.0 MiB");
+    // 四舍五入到 1024.0 KiB 时进到 MiB
+    assert_eq!(Size(1024 * 1024 - 1).to_string(), "1.0 MiB");
+    assert_eq!(Size(u64::MAX).to_string(), "16.0 EiB");
+}
+
+#[test_case]
+fn thousands_follow_locale() {
+    format::set_locale(Locale::EN);
+    assert_eq!(Thousands(999).to_string(), "999");
+    assert_eq!(Thousands(1234567).to_string(), "1,234,567");
+    format::set_locale(Locale::DE);
+    assert_eq!(Thousands(1234567).to_string(), "1.234.567");
+    assert_eq!(Size(1536).to_string(), "1,5 KiB");
+    format::set_locale(Locale::C);
+    assert_eq!(Thousands(1234567).to_string(), "1234567");
+    format::set_locale(Locale::EN);
+}
+
+#[test_case]
+fn durations() {
+    format::set_locale(Locale::EN);
+    assert_eq!(Clock(Duration::from_millis(3_723_045)).to_string(), "01:02:03.045");
+    assert_eq!(Clock(Duration::from_secs(90_000)).to_string(), "1d 01:00:00.000");
+    assert_eq!(Elapsed(Duration::from_nanos(850)).to_string(), "850 ns");
+    assert_eq!(Elapsed(Duration::from_nanos(12_500)).to_string(), "12.5 us");
+    assert_eq!(Elapsed(Duration::from_micros(999_960)).to_string(), "1.0 s");
+    assert_eq!(Elapsed(Duration::from_secs(90)).to_string(), "90.0 s");
+}
+
+#[test_case]
+fn padding_applies() {
+    format::set_locale(Locale::EN);
+    assert_eq!(format!("[{:>9}]", Size(1536)), "[  1.5 KiB]");
+    assert_eq!(format!("[{:<6}]", Thousands(1000)), "[1,000 ]");
+}
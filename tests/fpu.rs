@@ -0,0 +1,71 @@
+// 浮点状态的保存和恢复：MXCSR 被改动后能恢复，preserve 不影响外面的状态
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::arch::asm;
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::cpu::fpu::{self, FpuState};
+
+entry_point!(main);
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    cjn_os::init();
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+// 向零舍入
+const ROUND_TOWARD_ZERO: u32 = 0b11 << 13;
+
+fn mxcsr() -> u32 {
+    let mut value = 0u32;
+    unsafe { asm!("stmxcsr [{}]", in(reg) &mut value, options(nostack, preserves_flags)) };
+    value
+}
+
+fn set_mxcsr(value: u32) {
+    unsafe { asm!("ldmxcsr [{}]", in(reg) &value, options(nostack, preserves_flags, readonly)) };
+}
+
+#[test_case]
+fn save_and_restore() {
+    let original = mxcsr();
+    let mut state = FpuState::new();
+    state.save();
+    assert_eq!(state.mxcsr(), original);
+    set_mxcsr(original | ROUND_TOWARD_ZERO);
+    assert_ne!(mxcsr(), original);
+    state.restore();
+    assert_eq!(mxcsr(), original);
+}
+
+#[test_case]
+fn new_state_is_reset() {
+    let mut saved = FpuState::new();
+    saved.save();
+    set_mxcsr(mxcsr() | ROUND_TOWARD_ZERO);
+    FpuState::new().restore();
+    assert_eq!(mxcsr(), 0x1F80);
+    saved.restore();
+}
+
+#[test_case]
+fn preserve_restores_state() {
+    let original = mxcsr();
+    let inside = fpu::preserve(|| {
+        set_mxcsr(original | ROUND_TOWARD_ZERO);
+        mxcsr()
+    });
+    assert_eq!(inside, original | ROUND_TOWARD_ZERO);
+    assert_eq!(mxcsr(), original);
+}
@@ -0,0 +1,355 @@
+// 图形测试：不依赖显卡的部分——图元、制表符、字形摆放、文字的混合和超采样、窗口露出来的损坏区域、脏区域的计算、图层滚动、不占满屏幕的图层、图层的叠放顺序、通知的排队、截图的 BMP 编码和 mode 13h 的调色板
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::collections::BTreeSet;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::graphic::canvas::Canvas;
+use cjn_os::graphic::image::Image;
+use cjn_os::graphic::screenshot::encode_bmp;
+use cjn_os::graphic::font::{glyph, glyph_supersampled, FontId};
+use cjn_os::graphic::layer::{self, CONSOLE, CURSOR, Z_WINDOW};
+use cjn_os::graphic::mode13h::{color_index, index_color};
+use cjn_os::graphic::surface::Surface;
+use cjn_os::graphic::text::{self, Align, Quality};
+use cjn_os::graphic::{boxdraw, selftest, Region, Writer};
+use cjn_os::gui::tiling::Rect;
+use cjn_os::gui::window::{WindowId, WindowManager, WINDOW_MANAGER};
+use cjn_os::gui::{self, sysmon, toast};
+use embedded_graphics::pixelcolor::{Rgb888, RgbColor};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    cjn_os::test_kernel_init(boot_info);
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+const WHITE: Rgb888 = Rgb888::new(0xFF, 0xFF, 0xFF);
+
+// 只记录哪些像素被画过的画布
+struct Grid {
+    height: usize,
+    width: usize,
+    pixels: Vec<bool>,
+}
+
+impl Grid {
+    fn new(height: usize, width: usize) -> Self {
+        Self { height, width, pixels: vec![false; height * width] }
+    }
+
+    fn get(&self, x: usize, y: usize) -> bool {
+        self.pixels[x * self.width + y]
+    }
+
+    fn count(&self) -> usize {
+        self.pixels.iter().filter(|&&p| p).count()
+    }
+}
+
+impl Canvas for Grid {
+    fn size(&self) -> (usize, usize) {
+        (self.height, self.width)
+    }
+
+    fn put_pixel(&mut self, x: usize, y: usize, _color: Rgb888) {
+        if x < self.height && y < self.width {
+            self.pixels[x * self.width + y] = true;
+        }
+    }
+}
+
+#[test_case]
+fn line_endpoints() {
+    let mut grid = Grid::new(16, 16);
+    grid.draw_line(1, 2, 12, 9, WHITE);
+    assert!(grid.get(1, 2) && grid.get(12, 9));
+    // Bresenham 每一步只走一格，像素数等于较长一边的长度加一
+    assert_eq!(grid.count(), 12);
+}
+
+#[test_case]
+fn primitives_are_clipped() {
+    let mut grid = Grid::new(8, 8);
+    grid.draw_line(-10, -10, 20, 20, WHITE);
+    assert_eq!(grid.count(), 8);
+    grid.hline(-1, 0, 7, WHITE);
+    grid.hline(8, 0, 7, WHITE);
+    assert_eq!(grid.count(), 8);
+}
+
+#[test_case]
+fn rect_outline() {
+    let mut grid = Grid::new(10, 10);
+    grid.draw_rect(2, 3, 4, 5, WHITE);
+    // 4 列 5 行的边框
+    assert_eq!(grid.count(), 2 * 4 + 2 * 5 - 4);
+    assert!(grid.get(2, 3) && !grid.get(3, 4));
+}
+
+#[test_case]
+fn circle_is_symmetric() {
+    let mut grid = Grid::new(21, 21);
+    grid.draw_circle(10, 10, 7, WHITE);
+    for x in 0..21 {
+        for y in 0..21 {
+            assert_eq!(grid.get(x, y), grid.get(20 - x, y));
+            assert_eq!(grid.get(x, y), grid.get(y, x));
+        }
+    }
+    assert!(grid.get(3, 10) && grid.get(10, 17) && !grid.get(10, 10));
+}
+
+#[test_case]
+fn box_drawing_connects_across_cells() {
+    let (height, width) = (20, 9);
+    let coverage = |ch| boxdraw::rasterize(ch, height, width);
+    let filled = |cell: &[u8], x: usize, y: usize| cell[x * width + y] == 255;
+    // 横线画满整个宽度，竖线画满整个高度，中心的位置一致
+    let horizontal = coverage('─');
+    let vertical = coverage('│');
+    assert!((0..width).all(|y| filled(&horizontal, height / 2, y)));
+    assert!((0..height).all(|x| filled(&vertical, x, width / 2)));
+    let cross = coverage('┼');
+    assert!(filled(&cross, 0, width / 2) && filled(&cross, height - 1, width / 2));
+    assert!(filled(&cross, height / 2, 0) && filled(&cross, height / 2, width - 1));
+    // 角只向两个方向伸出
+    let corner = coverage('┌');
+    assert!(filled(&corner, height - 1, width / 2) && filled(&corner, height / 2, width - 1));
+    assert!(!filled(&corner, 0, width / 2) && !filled(&corner, height / 2, 0));
+}
+
+#[test_case]
+fn block_elements() {
+    let (height, width) = (16, 8);
+    let count = |ch| boxdraw::rasterize(ch, height, width).iter().filter(|&&v| v == 255).count();
+    assert_eq!(count('█'), height * width);
+    assert_eq!(count('▀'), height * width / 2);
+    assert_eq!(count('▄'), height * width / 2);
+    assert_eq!(count('▌'), height * width / 2);
+    assert_eq!(count('▒'), height * width / 2);
+    assert_eq!(count('▚'), height * width / 2);
+    assert_eq!(count('▙'), height * width * 3 / 4);
+}
+
+#[test_case]
+fn box_drawing_range() {
+    assert!(boxdraw::contains('─') && boxdraw::contains('▟'));
+    assert!(!boxdraw::contains('a') && !boxdraw::contains('■'));
+    for ch in '\u{2500}'..='\u{259F}' {
+        assert_eq!(boxdraw::rasterize(ch, 18, 9).len(), 18 * 9);
+    }
+}
+
+#[test_case]
+fn font_placement() {
+    assert!(selftest::font());
+}
+
+#[test_case]
+fn region_union_and_intersection() {
+    let a = Region { sx: 0, sy: 0, ex: 10, ey: 10 };
+    let b = Region { sx: 5, sy: 20, ex: 15, ey: 30 };
+    let union = a.union(b);
+    assert!(union.contains(&a) && union.contains(&b));
+    assert_eq!((union.sx, union.sy, union.ex, union.ey), (0, 0, 15, 30));
+    assert!(!a.intersects(&b));
+    assert!(a.intersects(&Region { sx: 9, sy: 9, ex: 11, ey: 11 }));
+    assert!(Region { sx: 3, sy: 3, ex: 3, ey: 8 }.is_empty());
+}
+
+#[test_case]
+fn layer_scroll_up() {
+    let mut layer = Writer::new();
+    layer.take_dirty();
+    layer.display_font(&glyph(FontId::MONOSPACE, 'A', 16.0), 40, 8, 16, WHITE);
+    // 整个字形只记一次改动，范围不超出这一行
+    let dirty = layer.take_dirty().unwrap();
+    assert!(dirty.sx >= 40 && dirty.ex <= 56 && dirty.sy >= 8);
+    let before: Vec<_> = (dirty.sx..dirty.ex).map(|x| layer.data[x].to_vec()).collect();
+    layer.scroll_up(20, 60, 20);
+    for (i, row) in before.iter().enumerate() {
+        assert!(layer.data[dirty.sx - 20 + i] == *row);
+    }
+    assert!((40..60).all(|x| layer.data[x].iter().all(|&(_, opaque)| !opaque)));
+    assert_eq!(layer.take_dirty().map(|d| (d.sx, d.ex)), Some((20, 60)));
+}
+
+#[test_case]
+fn positioned_layer() {
+    // 放在第 100 行、第 200 列，30 行 50 列
+    let mut layer = Writer::try_with_size(100, 200, 50, 30).unwrap();
+    assert_eq!((layer.data.height(), layer.data.width()), (30, 50));
+    layer.display_rect(90, 190, 20, 20, WHITE);
+    assert_eq!(layer.take_dirty(), Some(Region::new(100, 200, 110, 210)));
+    assert_eq!(layer.pixel(100, 200), (WHITE, true));
+    assert_eq!(layer.data.pixel(9, 9), (WHITE, true));
+    assert!(!layer.pixel(95, 195).1 && !layer.pixel(110, 210).1);
+    // 挪走以后内容被清空，原来的位置也要重新合成
+    layer.place(0, 0, 10, 10).unwrap();
+    assert!(!layer.pixel(0, 0).1 && !layer.pixel(100, 200).1);
+    let dirty = layer.take_dirty().unwrap();
+    assert!(dirty.contains(&Region::new(100, 200, 130, 250)) && dirty.contains(&layer.area()));
+}
+
+#[test_case]
+fn layer_order() {
+    let position = |handle| layer::layers().iter().position(|&(h, _)| h == handle);
+    let a = layer::add_layer(Writer::try_with_size(0, 0, 4, 4).unwrap(), Z_WINDOW);
+    let b = layer::add_layer(Writer::try_with_size(0, 0, 4, 4).unwrap(), Z_WINDOW);
+    // 同样的 z 后加入的在上面，都在控制台之上、鼠标之下
+    assert!(position(CONSOLE) < position(a) && position(a) < position(b) && position(b) < position(CURSOR));
+    assert!(layer::set_z_order(a, Z_WINDOW));
+    assert!(position(b) < position(a) && position(a) < position(CURSOR));
+    assert!(layer::set_visible(b, true));
+    assert_eq!(layer::with_layer(b, |layer| layer.enable), Some(true));
+    assert!(layer::remove_layer(a).is_some() && layer::remove_layer(b).is_some());
+    assert_eq!(position(a), None);
+    assert!(!layer::set_z_order(a, 0) && layer::with_layer(b, |_| ()).is_none());
+}
+
+#[test_case]
+fn toast_queue_is_bounded() {
+    // GUI 没有初始化，通知只排队不显示，多出来的丢掉最早的
+    for _ in 0..20 {
+        gui::notify("Test", "queued", gui::Level::Info);
+    }
+    assert_eq!(toast::count(), (0, 16));
+}
+
+#[test_case]
+fn system_monitor_sample() {
+    let sample = sysmon::sample();
+    assert!(sample.heap.size > 0 && sample.heap.free <= sample.heap.size);
+    let timer = |s: &sysmon::Sample| s.irqs.iter().find(|v| v.line == Some(0)).map(|v| v.count);
+    let before = timer(&sample);
+    x86_64::instructions::hlt();
+    let after = timer(&sysmon::sample());
+    assert!(after > before);
+}
+
+#[test_case]
+fn bmp_encoding_round_trip() {
+    // 宽 5 列，每行 15 字节，要补 1 字节对齐
+    let color = |x: usize, y: usize| Rgb888::new(x as u8 * 40, y as u8 * 50, 0x80);
+    let data = encode_bmp(3, 5, color).unwrap();
+    assert_eq!(&data[..2], b"BM");
+    assert_eq!(data.len(), 54 + 16 * 3);
+    let image = Image::from_bmp(&data).unwrap();
+    assert_eq!((image.height(), image.width()), (3, 5));
+    for x in 0..3 {
+        for y in 0..5 {
+            assert_eq!(image.pixel(x, y), Some(color(x, y)));
+        }
+    }
+}
+
+#[test_case]
+fn mode13h_palette() {
+    // 调色板里的颜色换成下标再换回来不变
+    for index in 0..=255u8 {
+        assert_eq!(color_index(index_color(index)), index);
+    }
+    assert_eq!(index_color(color_index(WHITE)), WHITE);
+    assert_eq!(color_index(Rgb888::new(0, 0, 0)), 0);
+    assert_eq!(color_index(Rgb888::new(0xFF, 0, 0)), 0xE0);
+}
+
+// 把文字按 `quality` 白底黑字画出来，返回出现过的颜色
+fn text_colors(quality: Quality) -> BTreeSet<(u8, u8, u8)> {
+    let mut surface = Surface::new(64, 24);
+    (0..24).for_each(|x| surface.fill_span(x, 0, 64, WHITE));
+    let black = Rgb888::new(0, 0, 0);
+    text::draw_text_with(&mut surface, 0, 0, 64, 24, "Ag", FontId::MONOSPACE, 18.0, 24, Align::Left, black, quality);
+    (0..24).flat_map(|x| (0..64).map(move |y| (x, y)))
+        .map(|(x, y)| surface.get_pixel(x, y).unwrap())
+        .map(|color| (color.r(), color.g(), color.b()))
+        .collect()
+}
+
+#[test_case]
+fn blended_text_mixes_with_background() {
+    assert_eq!(text_colors(Quality::Threshold).len(), 2);
+    assert!(text_colors(Quality::Blended).len() > 2);
+    assert!(text_colors(Quality::Supersampled).len() > 2);
+}
+
+#[test_case]
+fn supersampled_glyph_matches_normal_size() {
+    let normal = glyph(FontId::MONOSPACE, 'M', 24.0);
+    let fine = glyph_supersampled(FontId::MONOSPACE, 'M', 24.0);
+    let ((w0, h0), (w1, h1)) = (normal.size(), fine.size());
+    assert!(w0.abs_diff(w1) <= 1 && h0.abs_diff(h1) <= 1);
+    assert!((fine.advance - normal.advance).abs() <= 1.0);
+}
+
+// 取走积压的损坏区域，只留下 `window` 的；别的测试留下的窗口不影响结果
+fn damage_of(manager: &mut WindowManager, window: WindowId) -> Vec<Rect> {
+    manager.take_damage().into_iter().filter(|damage| damage.window == window).map(|damage| damage.region).collect()
+}
+
+// A 在 (40, 10)，B 在 (90, 60)，都是宽 200 高 150，B 压在 A 的右下方。
+// 重叠部分是屏幕上的第 90..190 行、第 60..210 列，A 的客户区从 (60, 11) 开始、高 129 宽 198，
+// 所以露出来的客户区是相对客户区的第 30..129 行、第 49..198 列
+const REVEALED: Rect = Rect { x: 30, y: 49, width: 149, height: 99 };
+
+fn overlapping_windows(manager: &mut WindowManager) -> (WindowId, WindowId) {
+    let a = manager.create("A", 40, 10, 200, 150).unwrap();
+    let b = manager.create("B", 90, 60, 200, 150).unwrap();
+    manager.take_damage();
+    (a, b)
+}
+
+#[test_case]
+fn raising_window_damages_covered_part() {
+    let mut manager = WINDOW_MANAGER.lock();
+    let (a, b) = overlapping_windows(&mut manager);
+    manager.raise(a);
+    let damage = manager.take_damage();
+    assert_eq!(damage.iter().filter(|d| d.window == a).map(|d| d.region).collect::<Vec<_>>(), [REVEALED]);
+    assert!(damage.iter().all(|d| d.window != b));
+    manager.close(a);
+    manager.close(b);
+}
+
+#[test_case]
+fn closing_window_damages_window_below() {
+    let mut manager = WINDOW_MANAGER.lock();
+    let (a, b) = overlapping_windows(&mut manager);
+    manager.close(b);
+    assert_eq!(damage_of(&mut manager, a), [REVEALED]);
+    manager.close(a);
+}
+
+#[test_case]
+fn growing_window_damages_new_client_area() {
+    let mut manager = WINDOW_MANAGER.lock();
+    let window = manager.create("C", 40, 10, 200, 150).unwrap();
+    manager.take_damage();
+    // 客户区从 198 列变成 248 列，高 129 行
+    manager.resize(window, 250, 150);
+    assert_eq!(damage_of(&mut manager, window), [Rect { x: 0, y: 198, width: 50, height: 129 }]);
+    // 再从 129 行变成 159 行，宽 248 列
+    manager.resize(window, 250, 180);
+    assert_eq!(damage_of(&mut manager, window), [Rect { x: 129, y: 0, width: 248, height: 30 }]);
+    // 缩小不产生损坏
+    manager.resize(window, 200, 150);
+    assert!(damage_of(&mut manager, window).is_empty());
+    manager.close(window);
+}
@@ -0,0 +1,122 @@
+// 堆分配器测试：分配、释放后的复用、超过一半堆大小的总分配量、原地 realloc、对齐留下的空隙不丢失
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::alloc::{alloc, dealloc, realloc, Layout};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::allocator::{self, heap_stats, HEAP_SIZE};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    cjn_os::test_kernel_init(boot_info);
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+#[test_case]
+fn heap_is_initialized() {
+    assert!(allocator::is_initialized());
+}
+
+#[test_case]
+fn simple_allocation() {
+    let heap_value_1 = Box::new(41);
+    let heap_value_2 = Box::new(13);
+    assert_eq!(*heap_value_1, 41);
+    assert_eq!(*heap_value_2, 13);
+}
+
+#[test_case]
+fn large_vec() {
+    let n = 1000;
+    let mut vec = Vec::new();
+    for i in 0..n {
+        vec.push(i);
+    }
+    assert_eq!(vec.iter().sum::<u64>(), (n - 1) * n / 2);
+}
+
+// 每次分配的大小，many_boxes 里分配的总量是堆大小的两倍
+const BLOCK: usize = 1024;
+
+#[test_case]
+fn many_boxes() {
+    // 释放的内存必须能被再次使用，否则总量超过堆大小时会分配失败
+    for i in 0..2 * HEAP_SIZE / BLOCK {
+        let x = Box::new([i as u8; BLOCK]);
+        assert_eq!(x[BLOCK - 1], i as u8);
+    }
+}
+
+#[test_case]
+fn many_boxes_long_lived() {
+    let long_lived = Box::new(1);
+    for i in 0..2 * HEAP_SIZE / BLOCK {
+        let x = Box::new([i as u8; BLOCK]);
+        assert_eq!(x[BLOCK - 1], i as u8);
+    }
+    assert_eq!(*long_lived, 1);
+}
+
+#[test_case]
+fn large_allocation() {
+    // 大于 2MiB，跨过堆里的大页边界
+    let size = 4 * 1024 * 1024;
+    let mut buffer = Vec::<u8>::with_capacity(size);
+    buffer.resize(size, 0xA5);
+    assert!(buffer.iter().all(|&byte| byte == 0xA5));
+}
+
+#[test_case]
+fn realloc_in_place() {
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    unsafe {
+        // 刚分配的块后面就是分出它的空闲区域剩下的部分
+        let ptr = alloc(layout);
+        ptr.write_bytes(0x5A, 64);
+        let grown = realloc(ptr, layout, 4096);
+        if !cfg!(feature = "heapcheck") {
+            assert_eq!(grown, ptr);
+        }
+        assert!((0..64).all(|i| *grown.add(i) == 0x5A));
+        let grown_layout = Layout::from_size_align(4096, 8).unwrap();
+        let shrunk = realloc(grown, grown_layout, 32);
+        if !cfg!(feature = "heapcheck") {
+            assert_eq!(shrunk, ptr);
+        }
+        assert!((0..32).all(|i| *shrunk.add(i) == 0x5A));
+        dealloc(shrunk, Layout::from_size_align(32, 8).unwrap());
+    }
+}
+
+#[test_case]
+fn aligned_allocation_keeps_padding() {
+    let before = heap_stats().free;
+    for align in [64, 4096, 65536] {
+        let layout = Layout::from_size_align(100, align).unwrap();
+        unsafe {
+            let ptr = alloc(layout);
+            assert_eq!(ptr as usize % align, 0);
+            dealloc(ptr, layout);
+        }
+    }
+    assert_eq!(heap_stats().free, before);
+    let stats = heap_stats();
+    assert!(stats.largest_free <= stats.free);
+    assert!(stats.fragmentation() <= 100);
+}
@@ -0,0 +1,39 @@
+// heapcheck：写过分配的末尾之后释放必须 panic，只在打开 heapcheck 特性时编译
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::io::qemu::{exit_qemu, QemuExitCode};
+use cjn_os::{serial_print, serial_println};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    cjn_os::test_kernel_init(boot_info);
+    overflow();
+    serial_println!("[test did not panic]");
+    exit_qemu(QemuExitCode::Failed);
+    cjn_os::hlt_loop();
+}
+
+fn overflow() {
+    serial_print!("heap_overflow::overflow...\t");
+    // 正常的分配和释放不报错
+    drop(Vec::<u64>::with_capacity(4));
+    let mut buffer: Vec<u8> = Vec::with_capacity(10);
+    // 越过末尾两个字节，落在红区里
+    unsafe { buffer.as_mut_ptr().add(buffer.capacity() + 2).write(0) };
+    drop(buffer);
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+    cjn_os::hlt_loop();
+}
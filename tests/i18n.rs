@@ -0,0 +1,52 @@
+// 界面文字的翻译：按语言取文字、填参数、切换语言；测试时没有给 lang=，默认是英文
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::format;
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::i18n::{self, Language, Msg};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    cjn_os::test_kernel_init(boot_info);
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+#[test_case]
+fn default_is_english() {
+    assert_eq!(i18n::language(), Language::En);
+    assert_eq!(i18n::tr(Msg::Terminal), "Terminal");
+    assert_eq!(i18n::tr_in(Msg::Terminal, Language::Zh), "终端");
+}
+
+#[test_case]
+fn fill_placeholders() {
+    assert_eq!(format!("{}", i18n::fill(Msg::AboutBuilt, &[&"2024-01-01", &"release"])), "built 2024-01-01 UTC (release)");
+    // 参数不够时留空，多余的忽略
+    assert_eq!(format!("{}", i18n::fill(Msg::AboutBuilt, &[&1])), "built 1 UTC ()");
+    assert_eq!(format!("{}", i18n::fill(Msg::Memory, &[&1])), "Memory");
+}
+
+#[test_case]
+fn switch_language() {
+    assert_eq!(Language::by_name("zh_CN"), Some(Language::Zh));
+    assert_eq!(Language::by_name("fr"), None);
+    i18n::set_language(Language::Zh);
+    assert_eq!(format!("{}", i18n::fill(Msg::CommandNotFound, &[&"foo"])), "foo：找不到命令，试试 `help`");
+    i18n::set_language(Language::En);
+    assert_eq!(i18n::tr(Msg::Locked), "Locked");
+}
@@ -0,0 +1,99 @@
+// 输入事件：鼠标数据包拆成移动和按键事件，订阅按类别过滤，队列满了丢弃，异步接口在事件到来时唤醒，最近的事件留给崩溃转储
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::io::input::{self, DeviceKind, Filter, InputEvent, MouseButton};
+use cjn_os::io::keyboard;
+use cjn_os::io::mouse::{self, MouseEvent};
+use x86_64::instructions::interrupts;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    cjn_os::test_kernel_init(boot_info);
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+#[test_case]
+fn mouse_packet_becomes_move_and_button() {
+    let pointer = input::subscribe(Filter::POINTER, 16);
+    let event = MouseEvent { dx: 3, dy: -2, left: true, ..MouseEvent::default() };
+    interrupts::without_interrupts(|| mouse::push_event(event));
+    let moved = pointer.try_next().expect("no move event");
+    assert_eq!(moved.event, InputEvent::MouseMove { dx: 3, dy: -2 });
+    let device = input::devices().into_iter().find(|device| device.id == moved.device).unwrap();
+    assert_eq!(device.kind, DeviceKind::Mouse);
+    let pressed = pointer.try_next().expect("no button event");
+    assert_eq!(pressed.event, InputEvent::MouseButton { button: MouseButton::Left, pressed: true });
+    assert!(pressed.time >= moved.time);
+    // 按键没有变化时只有移动
+    interrupts::without_interrupts(|| mouse::push_event(MouseEvent { dx: 1, left: true, ..MouseEvent::default() }));
+    assert_eq!(pointer.try_next().map(|e| e.event), Some(InputEvent::MouseMove { dx: 1, dy: 0 }));
+    assert!(pointer.try_next().is_none());
+    interrupts::without_interrupts(|| mouse::push_event(MouseEvent::default()));
+}
+
+#[test_case]
+fn filter_and_overflow() {
+    let keys = input::subscribe(Filter::KEYS, 2);
+    interrupts::without_interrupts(|| {
+        mouse::push_event(MouseEvent { dx: 5, ..MouseEvent::default() });
+        "abc".chars().for_each(keyboard::push_key);
+    });
+    assert_eq!(keys.try_next().map(|e| e.event), Some(InputEvent::Key('a')));
+    assert_eq!(keys.try_next().map(|e| e.event), Some(InputEvent::Key('b')));
+    assert!(keys.try_next().is_none());
+    assert_eq!(keys.dropped(), 1);
+}
+
+static WAKES: AtomicUsize = AtomicUsize::new(0);
+
+fn counting_waker() -> Waker {
+    fn clone(_: *const ()) -> RawWaker {
+        RawWaker::new(ptr::null(), &VTABLE)
+    }
+    fn wake(_: *const ()) {
+        WAKES.fetch_add(1, Ordering::SeqCst);
+    }
+    fn drop(_: *const ()) {}
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, wake, wake, drop);
+    unsafe { Waker::from_raw(RawWaker::new(ptr::null(), &VTABLE)) }
+}
+
+#[test_case]
+fn async_stream_wakes_on_event() {
+    let keys = input::subscribe(Filter::KEYS, 4);
+    let waker = counting_waker();
+    let mut cx = Context::from_waker(&waker);
+    assert!(keys.poll_next(&mut cx).is_pending());
+    let before = WAKES.load(Ordering::SeqCst);
+    interrupts::without_interrupts(|| keyboard::push_key('z'));
+    assert!(WAKES.load(Ordering::SeqCst) > before);
+    match keys.poll_next(&mut cx) {
+        Poll::Ready(event) => assert_eq!(event.event, InputEvent::Key('z')),
+        Poll::Pending => panic!("event was not delivered"),
+    }
+}
+
+#[test_case]
+fn history_keeps_recent_events() {
+    interrupts::without_interrupts(|| "xyz".chars().for_each(keyboard::push_key));
+    let history = input::try_history().expect("history is locked");
+    let keys: [Option<InputEvent>; 3] = core::array::from_fn(|i| history[input::HISTORY - 3 + i].map(|e| e.event));
+    assert_eq!(keys, [Some(InputEvent::Key('x')), Some(InputEvent::Key('y')), Some(InputEvent::Key('z'))]);
+}
@@ -0,0 +1,96 @@
+// 中断测试：断点异常能返回，时钟中断在走，without_interrupts 恢复中断状态，动态注册的处理函数和统计
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::interrupts::irq::{self, FIRST_DYNAMIC, FIRST_VECTOR};
+use cjn_os::interrupts::{register_handler, Irq, RegisterError};
+use cjn_os::io::timer;
+use x86_64::instructions::{hlt, interrupts};
+use x86_64::structures::idt::InterruptStackFrame;
+
+entry_point!(main);
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    cjn_os::init();
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+#[test_case]
+fn breakpoint_exception_returns() {
+    interrupts::int3();
+}
+
+#[test_case]
+fn interrupts_enabled_after_init() {
+    assert!(interrupts::are_enabled());
+}
+
+#[test_case]
+fn timer_ticks_advance() {
+    let start = timer::ticks();
+    // 每次 hlt 至少等到下一个中断
+    for _ in 0..10 {
+        hlt();
+    }
+    assert!(timer::ticks() > start);
+}
+
+#[test_case]
+fn without_interrupts_restores_state() {
+    interrupts::without_interrupts(|| assert!(!interrupts::are_enabled()));
+    assert!(interrupts::are_enabled());
+}
+
+static SHARED: AtomicU64 = AtomicU64::new(0);
+
+fn shared_handler(_frame: &InterruptStackFrame) {
+    SHARED.fetch_add(1, Ordering::Relaxed);
+}
+
+fn unused_handler(_frame: &InterruptStackFrame) {}
+
+#[test_case]
+fn shared_timer_line() {
+    assert_eq!(register_handler(Irq::Line(0), "test", shared_handler), Ok(FIRST_VECTOR));
+    let before = irq::stats().find(|s| s.vector == FIRST_VECTOR).expect("timer is registered").count;
+    for _ in 0..10 {
+        hlt();
+    }
+    assert!(SHARED.load(Ordering::Relaxed) > 0);
+    let stats = irq::stats().find(|s| s.vector == FIRST_VECTOR).expect("timer is registered");
+    assert_eq!(stats.line, Some(0));
+    assert!(stats.count > before);
+    assert!(stats.last.is_some());
+    assert!(stats.names().eq(["timer", "test"]));
+}
+
+#[test_case]
+fn dynamic_vector() {
+    let vector = register_handler(Irq::Dynamic, "dynamic", unused_handler).expect("a vector is free");
+    assert!(vector >= FIRST_DYNAMIC);
+    let stats = irq::stats().find(|s| s.vector == vector).expect("vector is registered");
+    assert_eq!(stats.line, None);
+    assert_eq!(stats.count, 0);
+    assert!(stats.last.is_none());
+    // 下一个分配到的是另一个向量
+    assert_ne!(register_handler(Irq::Dynamic, "dynamic", unused_handler), Ok(vector));
+}
+
+#[test_case]
+fn invalid_lines() {
+    assert_eq!(register_handler(Irq::Line(2), "cascade", unused_handler), Err(RegisterError::InvalidLine(2)));
+    assert_eq!(register_handler(Irq::Line(16), "none", unused_handler), Err(RegisterError::InvalidLine(16)));
+}
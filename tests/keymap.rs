@@ -0,0 +1,83 @@
+// 键盘布局：美式、英式、德式布局的字符，Shift、Caps Lock、AltGr 和小键盘的 Num Lock
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::io::keyboard::keymap::{self, Modifiers};
+use pc_keyboard::{DecodedKey, KeyCode};
+
+entry_point!(main);
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    cjn_os::init();
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+fn find(name: &str) -> &'static keymap::Keymap {
+    keymap::keymaps().iter().find(|keymap| keymap.name == name).unwrap()
+}
+
+fn unicode(name: &str, code: KeyCode, modifiers: Modifiers) -> Option<char> {
+    match find(name).translate(code, modifiers) {
+        Some(DecodedKey::Unicode(ch)) => Some(ch),
+        _ => None,
+    }
+}
+
+const PLAIN: Modifiers = Modifiers { shift: false, altgr: false, caps_lock: false, num_lock: false };
+const SHIFT: Modifiers = Modifiers { shift: true, ..PLAIN };
+const CAPS: Modifiers = Modifiers { caps_lock: true, ..PLAIN };
+const ALTGR: Modifiers = Modifiers { altgr: true, ..PLAIN };
+
+#[test_case]
+fn layouts_differ() {
+    assert_eq!(unicode("us", KeyCode::Key2, SHIFT), Some('@'));
+    assert_eq!(unicode("uk", KeyCode::Key2, SHIFT), Some('"'));
+    assert_eq!(unicode("uk", KeyCode::Key3, SHIFT), Some('£'));
+    assert_eq!(unicode("de", KeyCode::Y, PLAIN), Some('z'));
+    assert_eq!(unicode("de", KeyCode::SemiColon, SHIFT), Some('Ö'));
+    assert_eq!(unicode("de", KeyCode::Q, ALTGR), Some('@'));
+    // 没有 AltGr 字符的键照常输入
+    assert_eq!(unicode("de", KeyCode::A, ALTGR), Some('a'));
+    assert_eq!(find("us").translate(KeyCode::ShiftLeft, PLAIN), None);
+    assert_eq!(find("us").translate(KeyCode::F1, PLAIN), Some(DecodedKey::RawKey(KeyCode::F1)));
+}
+
+#[test_case]
+fn caps_lock_only_affects_letters() {
+    assert_eq!(unicode("us", KeyCode::A, CAPS), Some('A'));
+    assert_eq!(unicode("us", KeyCode::A, Modifiers { shift: true, ..CAPS }), Some('a'));
+    assert_eq!(unicode("us", KeyCode::Key1, CAPS), Some('1'));
+    assert_eq!(unicode("de", KeyCode::Quote, CAPS), Some('Ä'));
+    assert_eq!(unicode("de", KeyCode::Minus, CAPS), Some('ß'));
+}
+
+#[test_case]
+fn numpad_follows_num_lock() {
+    let num = Modifiers { num_lock: true, ..PLAIN };
+    assert_eq!(unicode("us", KeyCode::Numpad8, num), Some('8'));
+    assert_eq!(find("us").translate(KeyCode::Numpad8, PLAIN), Some(DecodedKey::RawKey(KeyCode::ArrowUp)));
+    assert_eq!(unicode("us", KeyCode::NumpadPlus, PLAIN), Some('+'));
+    assert_eq!(unicode("us", KeyCode::NumpadEnter, PLAIN), Some('\n'));
+}
+
+#[test_case]
+fn load_switches_layout() {
+    assert_eq!(keymap::current().name, "us");
+    assert!(keymap::load("de"));
+    assert_eq!(keymap::current().name, "de");
+    assert!(!keymap::load("dvorak"));
+    assert_eq!(keymap::current().name, "de");
+    assert!(keymap::load("us"));
+}
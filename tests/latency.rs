@@ -0,0 +1,93 @@
+// 输入延迟测量：探针按顺序经过各步，排在前面的事件不算，汇总的统计
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+use core::time::Duration;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::graphic::present;
+use cjn_os::io::keyboard::{self, KeyboardStream};
+use cjn_os::trace::latency::{self, Report, Sample, Source, Stage};
+use x86_64::instructions::interrupts;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    cjn_os::test_kernel_init(boot_info);
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+fn reached(stage: Stage) -> bool {
+    latency::probe().is_some_and(|sample| sample.stamps[stage as usize].is_some())
+}
+
+#[test_case]
+fn probe_passes_every_stage() {
+    let mut stream = KeyboardStream;
+    while stream.try_read().is_some() {}
+    // 排在探针前面的按键
+    interrupts::without_interrupts(|| keyboard::push_key('a'));
+    latency::inject_key('x');
+    assert!(reached(Stage::Inject) && reached(Stage::Queue));
+
+    assert_eq!(stream.try_read(), Some('a'));
+    latency::handled(Source::Key);
+    assert!(!reached(Stage::Dispatch) && !reached(Stage::Handle));
+
+    assert_eq!(stream.try_read(), Some('x'));
+    assert!(reached(Stage::Dispatch));
+    // 其他来源的事件不算
+    latency::handled(Source::Mouse);
+    assert!(!reached(Stage::Handle));
+    latency::handled(Source::Key);
+    assert!(reached(Stage::Handle));
+
+    // 取出探针之前开始的帧不算
+    latency::presented(present::current_frame());
+    assert!(!reached(Stage::Present));
+    latency::presented(present::current_frame() + 1);
+    let sample = latency::probe().unwrap();
+    assert!(sample.is_complete());
+    let stamps = sample.stamps.map(Option::unwrap);
+    assert!(stamps.windows(2).all(|pair| pair[0] <= pair[1]));
+}
+
+fn sample(stamps: [Option<u64>; 5]) -> Sample {
+    Sample { source: Source::Mouse, stamps: stamps.map(|s| s.map(Duration::from_micros)) }
+}
+
+#[test_case]
+fn report_breaks_down_stages() {
+    let samples = [
+        sample([Some(0), Some(10), Some(110), Some(130), Some(1130)]),
+        sample([Some(0), Some(20), Some(320), Some(330), Some(2330)]),
+        // 超时，没有显示出来
+        sample([Some(0), Some(10), None, None, None]),
+    ];
+    let report = Report::new(Source::Mouse, &samples);
+    assert_eq!(report.samples, 3);
+
+    let queue = report.stages[Stage::Queue as usize];
+    assert_eq!(queue.count, 3);
+    assert_eq!(queue.min, Duration::from_micros(10));
+    assert_eq!(queue.max, Duration::from_micros(20));
+
+    let dispatch = report.stages[Stage::Dispatch as usize];
+    assert_eq!(dispatch.count, 2);
+    assert_eq!(dispatch.average(), Some(Duration::from_micros(200)));
+
+    assert_eq!(report.stages[Stage::Present as usize].max, Duration::from_micros(2000));
+    assert_eq!(report.stages[Stage::Inject as usize].average(), None);
+    assert_eq!(report.total.count, 2);
+    assert_eq!(report.total.average(), Some(Duration::from_micros(1730)));
+}
@@ -0,0 +1,85 @@
+// 内存概况：内存映射的区域按地址排列且已合并，总量和页表的统计合理，交出帧分配器之后有帧的用量，实际映射和地址空间布局一致，没有可写又可执行的页
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::format;
+use core::panic::PanicInfo;
+
+use bootloader::bootinfo::MemoryRegionType;
+use bootloader::{entry_point, BootInfo};
+use cjn_os::allocator;
+use cjn_os::memory::{self, debug, layout, report, wx};
+use x86_64::structures::paging::PageTableFlags;
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    let (_, frame_allocator) = cjn_os::test_kernel_init(boot_info);
+    memory::install_frame_allocator(frame_allocator);
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+#[test_case]
+fn regions_are_sorted_and_merged() {
+    let mut previous: Option<report::Region> = None;
+    for region in report::regions() {
+        assert!(region.start < region.end);
+        if let Some(previous) = previous {
+            assert!(previous.end <= region.start);
+            assert!(previous.end != region.start || previous.region_type != region.region_type);
+        }
+        previous = Some(region);
+    }
+    assert!(previous.is_some());
+}
+
+#[test_case]
+fn totals() {
+    let report = report::report();
+    assert!(report.usable > 0 && report.usable <= report.total_ram);
+    let usable = report.regions.iter().find(|summary| summary.region_type == MemoryRegionType::Usable).unwrap();
+    assert_eq!(usable.bytes, report.usable);
+    let frames = report.frames.unwrap();
+    assert!(frames.allocated > 0 && frames.allocated <= frames.usable);
+    assert!(frames.usable as u64 * 4096 <= report.usable);
+}
+
+#[test_case]
+fn page_tables() {
+    let tables = report::page_tables().unwrap();
+    assert_eq!(tables.tables[0], 1);
+    assert!(tables.tables[3] > 0);
+    assert!(tables.pages[0] > 0);
+    assert!(format!("{}", report::report()).contains("page tables: 1/"));
+}
+
+#[test_case]
+fn layout_matches_page_tables() {
+    assert_eq!(layout::validate(), []);
+    assert!(layout::HEAP.contains(allocator::HEAP_START as u64));
+    assert!(!layout::VMM.contains(layout::HEAP.start));
+}
+
+#[test_case]
+fn writable_pages_are_not_executable() {
+    assert!(wx::enabled());
+    let heap = debug::translate(VirtAddr::new(allocator::HEAP_START as u64)).unwrap();
+    assert!(heap.flags.contains(PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE));
+    wx::harden();
+    assert_eq!(wx::violations(), []);
+    // 内核代码还能执行
+    let code = debug::translate(VirtAddr::new(writable_pages_are_not_executable as usize as u64)).unwrap();
+    assert!(!code.flags.contains(PageTableFlags::NO_EXECUTE));
+}
@@ -0,0 +1,58 @@
+// 寄存器块：布局和声明的偏移一致，读写和 update 落到对应的内存上
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::mem::{offset_of, size_of};
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::memory::mmio::{read_barrier, write_barrier, ReadOnly, ReadWrite, Reserved, WriteOnly};
+
+entry_point!(main);
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    cjn_os::init();
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+#[repr(C)]
+struct Registers {
+    id: ReadOnly<u32>,
+    control: ReadWrite<u32>,
+    _reserved: Reserved<0x08>,
+    doorbell: WriteOnly<u64>,
+}
+
+// 用普通内存代替设备
+fn registers(memory: &mut [u64; 3]) -> &Registers {
+    unsafe { &*(memory.as_mut_ptr() as *const Registers) }
+}
+
+#[test_case]
+fn layout() {
+    assert_eq!(offset_of!(Registers, control), 0x04);
+    assert_eq!(offset_of!(Registers, doorbell), 0x10);
+    assert_eq!(size_of::<Registers>(), 0x18);
+}
+
+#[test_case]
+fn read_write() {
+    let mut memory = [0x0000_0005_1234_5678, 0, 0];
+    let registers = registers(&mut memory);
+    assert_eq!(registers.id.read(), 0x1234_5678);
+    assert_eq!(registers.control.read(), 5);
+    assert_eq!(registers.control.update(|value| value | 0x100), 0x105);
+    write_barrier();
+    registers.doorbell.write(0xABCD);
+    read_barrier();
+    assert_eq!(memory, [0x0000_0105_1234_5678, 0, 0xABCD]);
+}
@@ -0,0 +1,198 @@
+// 网络：校验和、各层报文的构造和解析，HTTP 响应的解析，以及通过 QEMU 的用户网络获取地址、ping 网关
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use core::panic::PanicInfo;
+use core::time::Duration;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::memory;
+use cjn_os::net::ethernet::MacAddr;
+use cjn_os::net::ipv4::{self, Ipv4Addr};
+use cjn_os::net::udp::UdpSocket;
+use cjn_os::net::dns::{self, DnsError};
+use cjn_os::net::http::{self, HttpError, Url};
+use cjn_os::net::tcp::{Segment, TcpStream};
+use cjn_os::net::{self, arp, dhcp, icmp, Config};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    let (_, frame_allocator) = cjn_os::test_kernel_init(boot_info);
+    memory::install_frame_allocator(frame_allocator);
+    net::init();
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+#[test_case]
+fn checksum_matches_rfc1071_example() {
+    let data = [0x00, 0x01, 0xF2, 0x03, 0xF4, 0xF5, 0xF6, 0xF7];
+    assert_eq!(ipv4::checksum(&[&data]), !0xDDF2);
+    // 分成两段结果一样，奇数长度的最后一段在低位补 0
+    assert_eq!(ipv4::checksum(&[&data[..4], &data[4..]]), !0xDDF2);
+    assert_eq!(ipv4::checksum(&[&[0x12, 0x34, 0x56]]), !0x6834);
+}
+
+#[test_case]
+fn ipv4_round_trip() {
+    let header = ipv4::Header {
+        source: Ipv4Addr::new(10, 0, 2, 15),
+        destination: Ipv4Addr::new(10, 0, 2, 2),
+        protocol: ipv4::PROTOCOL_UDP,
+        ttl: 64,
+    };
+    let mut packet = ipv4::build(7, &header, b"hello");
+    // 以太网补齐的字节不算负载
+    packet.extend_from_slice(&[0; 4]);
+    let (parsed, payload) = ipv4::parse(&packet).unwrap();
+    assert_eq!(parsed.source, header.source);
+    assert_eq!(parsed.destination, header.destination);
+    assert_eq!(parsed.protocol, ipv4::PROTOCOL_UDP);
+    assert_eq!(payload, b"hello");
+    packet[8] ^= 1;
+    assert!(ipv4::parse(&packet).is_none(), "bad checksum accepted");
+}
+
+#[test_case]
+fn addresses() {
+    assert_eq!(Ipv4Addr::parse("192.168.1.20"), Some(Ipv4Addr::new(192, 168, 1, 20)));
+    for text in ["", "1.2.3", "1.2.3.4.5", "1.2.3.256", "a.b.c.d"] {
+        assert_eq!(Ipv4Addr::parse(text), None, "{}", text);
+    }
+    assert_eq!(Ipv4Addr::netmask(24), Ipv4Addr::new(255, 255, 255, 0));
+    assert_eq!(Ipv4Addr::netmask(0), Ipv4Addr::UNSPECIFIED);
+    assert!(Ipv4Addr::new(10, 0, 2, 2).same_subnet(Ipv4Addr::new(10, 0, 2, 15), Ipv4Addr::netmask(24)));
+    assert_eq!(alloc::format!("{}", MacAddr([0x52, 0x54, 0, 0x12, 0x34, 0x56])), "52:54:00:12:34:56");
+}
+
+#[test_case]
+fn arp_round_trip() {
+    let packet = arp::Packet {
+        operation: arp::REQUEST,
+        sender_mac: MacAddr([2, 0, 0, 0, 0, 1]),
+        sender_ip: Ipv4Addr::new(10, 0, 2, 15),
+        target_mac: MacAddr::ZERO,
+        target_ip: Ipv4Addr::new(10, 0, 2, 2),
+    };
+    let parsed = arp::Packet::parse(&packet.to_bytes()).unwrap();
+    assert_eq!(parsed.operation, arp::REQUEST);
+    assert_eq!(parsed.sender_mac, packet.sender_mac);
+    assert_eq!(parsed.target_ip, packet.target_ip);
+}
+
+#[test_case]
+fn dns_response() {
+    let mut message = dns::query(0x1234, "example.com").unwrap();
+    assert_eq!(&message[12..25], b"\x07example\x03com\x00");
+    // 改成回应：QR、RD、RA，一个回答，名字指向问题里的名字
+    message[2..4].copy_from_slice(&[0x81, 0x80]);
+    message[6..8].copy_from_slice(&[0, 1]);
+    message.extend_from_slice(&[0xC0, 0x0C, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 93, 184, 216, 34]);
+    assert_eq!(dns::parse_response(0x1234, &message), Some(Ok((Ipv4Addr::new(93, 184, 216, 34), 60))));
+    assert_eq!(dns::parse_response(0x4321, &message), None);
+    message[3] = 0x83;
+    assert_eq!(dns::parse_response(0x1234, &message), Some(Err(DnsError::NotFound)));
+    assert_eq!(dns::query(1, "bad..name"), Err(DnsError::InvalidName));
+    assert_eq!(dns::resolve("10.0.2.3"), Ok(Ipv4Addr::new(10, 0, 2, 3)));
+}
+
+#[test_case]
+fn dhcp_lease_from_qemu() {
+    // 启动时 net::init 已经用 DHCP 获取了地址
+    let lease = dhcp::lease().expect("no DHCP lease");
+    assert_eq!(lease.config, net::config().unwrap());
+    assert_eq!(lease.config.address, Config::QEMU_USER.address);
+    assert_eq!(lease.config.gateway, Config::QEMU_USER.gateway);
+    assert_eq!(lease.config.dns, Config::QEMU_USER.dns);
+    assert_eq!(net::status(), net::Status::Up);
+}
+
+#[test_case]
+fn udp_to_own_address() {
+    let Some(config) = net::config() else { return };
+    let receiver = UdpSocket::bind(7777).unwrap();
+    assert!(UdpSocket::bind(7777).is_err());
+    let sender = UdpSocket::bind(0).unwrap();
+    sender.send_to(b"ping", config.address, 7777).unwrap();
+    let datagram = receiver.recv_from().unwrap();
+    assert_eq!(datagram.data, b"ping");
+    assert_eq!(datagram.port, sender.port());
+}
+
+#[test_case]
+fn tcp_segment_round_trip() {
+    let (source, destination) = (Ipv4Addr::new(10, 0, 2, 15), Ipv4Addr::new(10, 0, 2, 2));
+    let segment = Segment {
+        source_port: 49152,
+        destination_port: 80,
+        seq: 0x01020304,
+        ack: 0xA0B0C0D0,
+        flags: 0x18,
+        window: 4096,
+        payload: b"GET / HTTP/1.1\r\n\r\n",
+    };
+    let mut bytes = segment.build(source, destination);
+    assert_eq!(Segment::parse(source, destination, &bytes), Some(segment));
+    // 伪首部的地址不对，校验和就不对
+    assert_eq!(Segment::parse(Ipv4Addr::new(10, 0, 2, 16), destination, &bytes), None);
+    bytes[20] ^= 1;
+    assert_eq!(Segment::parse(source, destination, &bytes), None);
+}
+
+#[test_case]
+fn tcp_to_closed_port_is_refused() {
+    let Some(config) = net::config() else { return };
+    // 发给自己的 SYN 在本机处理，没有连接的端口回 RST
+    let result = TcpStream::connect(config.address, 9, Duration::from_secs(1));
+    assert_eq!(result.err(), Some(net::NetError::Refused));
+}
+
+#[test_case]
+fn http_url() {
+    assert_eq!(Url::parse("http://example.com"), Ok(Url { host: "example.com", port: 80, path: "/" }));
+    assert_eq!(Url::parse("10.0.2.2:8000/a/b?c=d"), Ok(Url { host: "10.0.2.2", port: 8000, path: "/a/b?c=d" }));
+    assert_eq!(Url::parse("https://example.com/"), Err(HttpError::UnsupportedScheme));
+    assert_eq!(Url::parse("http://:80/"), Err(HttpError::InvalidUrl));
+    assert_eq!(Url::parse("http://host:http/"), Err(HttpError::InvalidUrl));
+}
+
+#[test_case]
+fn http_response() {
+    let response = http::parse_response(b"HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 5\r\n\r\nhello, extra").unwrap();
+    assert_eq!(response.status, 200);
+    assert_eq!(response.reason, "OK");
+    assert_eq!(response.header("content-type"), Some("text/plain"));
+    assert_eq!(response.body, b"hello");
+
+    let chunked = http::parse_response(b"HTTP/1.1 404 Not Found\r\nTransfer-Encoding: chunked\r\n\r\n4\r\nWiki\r\n6;x=y\r\npedia \r\n0\r\n\r\n").unwrap();
+    assert_eq!(chunked.status, 404);
+    assert_eq!(chunked.text(), "Wikipedia ");
+
+    // 没有长度时读到连接关闭为止
+    assert_eq!(http::parse_response(b"HTTP/1.0 200 OK\r\n\r\nall of it").unwrap().body, b"all of it");
+    assert_eq!(http::parse_response(b"HTTP/1.1 200 OK\r\nContent-Length: 9\r\n\r\nshort"), Err(HttpError::BadResponse));
+    assert_eq!(http::parse_response(b"SSH-2.0-OpenSSH\r\n\r\n"), Err(HttpError::BadResponse));
+    assert_eq!(http::dechunk(b"2\r\nab"), Err(HttpError::BadResponse));
+}
+
+#[test_case]
+fn ping_gateway() {
+    // 测试参数里配了 virtio-net，没有网卡时说明驱动没有认出设备
+    assert!(net::is_up(), "no network device");
+    assert!(net::configure(Config::QEMU_USER));
+    let reply = icmp::ping(Config::QEMU_USER.gateway, 1, 1, Duration::from_secs(3)).unwrap();
+    let reply = reply.expect("no reply from the gateway");
+    assert_eq!(reply.from, Config::QEMU_USER.gateway);
+    assert_eq!(reply.seq, 1);
+}
@@ -0,0 +1,134 @@
+// 分页测试：地址翻译（包括 2MiB 大页）、按需映射一段虚拟地址后读写、取消映射、列出映射
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::memory::{self, debug, layout, vmm, BootInfoFrameAllocator};
+use spin::{Mutex, Once};
+use x86_64::structures::paging::{OffsetPageTable, PageTableFlags};
+use x86_64::{PhysAddr, VirtAddr};
+
+// 测试函数没有参数，页表和帧分配器放在这里
+static MEMORY: Mutex<Option<(OffsetPageTable<'static>, BootInfoFrameAllocator)>> = Mutex::new(None);
+static PHYS_MEM_OFFSET: Once<VirtAddr> = Once::new();
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    cjn_os::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    PHYS_MEM_OFFSET.call_once(|| phys_mem_offset);
+    let mapper = unsafe { memory::init(phys_mem_offset) };
+    let frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    *MEMORY.lock() = Some((mapper, frame_allocator));
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+fn translate(addr: VirtAddr) -> Option<PhysAddr> {
+    unsafe { memory::translate_addr(addr, *PHYS_MEM_OFFSET.get().unwrap()) }
+}
+
+#[test_case]
+fn translate_vga_buffer() {
+    // bootloader 把 VGA 文本缓冲区恒等映射
+    assert_eq!(translate(VirtAddr::new(0xb8000)), Some(PhysAddr::new(0xb8000)));
+}
+
+#[test_case]
+fn translate_physical_memory_mapping() {
+    for phys in [0x1000, 0x10_0000, 0x20_0123] {
+        let phys = PhysAddr::new(phys);
+        assert_eq!(translate(memory::phys_to_virt(phys)), Some(phys));
+    }
+}
+
+#[test_case]
+fn null_page_is_unmapped() {
+    assert_eq!(translate(VirtAddr::new(0)), None);
+}
+
+#[test_case]
+fn map_region_read_write_unmap() {
+    const SIZE: u64 = 3 * 4096;
+    let mut memory = MEMORY.lock();
+    let (mapper, frame_allocator) = memory.as_mut().unwrap();
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    let region = vmm::map_region(mapper, frame_allocator, SIZE, flags).expect("map_region failed");
+    assert_eq!(region.size(), SIZE);
+    for page in region.pages() {
+        assert!(translate(page.start_address()).is_some());
+    }
+
+    let words = region.start().as_mut_ptr::<u64>();
+    let count = (SIZE / 8) as usize;
+    for i in 0..count {
+        unsafe { words.add(i).write_volatile(i as u64 ^ 0x5A5A) };
+    }
+    for i in 0..count {
+        assert_eq!(unsafe { words.add(i).read_volatile() }, i as u64 ^ 0x5A5A);
+    }
+
+    vmm::unmap_region(mapper, region).expect("unmap_region failed");
+    for page in region.pages() {
+        assert_eq!(translate(page.start_address()), None);
+    }
+}
+
+#[test_case]
+fn zero_sized_region_has_no_pages() {
+    let mut memory = MEMORY.lock();
+    let (mapper, frame_allocator) = memory.as_mut().unwrap();
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    for start in [0, vmm::VMM_START + 0x123] {
+        let region = vmm::map_fixed(mapper, frame_allocator, VirtAddr::new(start), 0, flags).expect("map_fixed failed");
+        assert_eq!(region.pages().count(), 0);
+    }
+}
+
+#[test_case]
+fn regions_do_not_overlap() {
+    let mut memory = MEMORY.lock();
+    let (mapper, frame_allocator) = memory.as_mut().unwrap();
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    let a = vmm::map_region(mapper, frame_allocator, 4096, flags).expect("map_region failed");
+    let b = vmm::map_region(mapper, frame_allocator, 4096, flags).expect("map_region failed");
+    assert!(a.end() <= b.start() || b.end() <= a.start());
+    assert_ne!(translate(a.start()), translate(b.start()));
+    vmm::unmap_region(mapper, a).unwrap();
+    vmm::unmap_region(mapper, b).unwrap();
+}
+
+#[test_case]
+fn huge_pages() {
+    // 这个测试没有初始化堆，借用堆的地址映射一个 2MiB 大页
+    const SIZE: u64 = 0x20_0000;
+    let start = VirtAddr::new(layout::HEAP.start);
+    let mut memory = MEMORY.lock();
+    let (mapper, frame_allocator) = memory.as_mut().unwrap();
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+    vmm::map_fixed_huge(mapper, frame_allocator, start, SIZE, flags).expect("map_fixed_huge failed");
+
+    let translation = debug::translate(start + 0x1234u64).unwrap();
+    assert_eq!(translation.page_size, SIZE);
+    assert!(translation.flags.contains(PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE));
+    assert_eq!(translate(start + 0x1234u64), Some(translation.phys));
+    assert_eq!(translation.phys.as_u64() % SIZE, 0x1234);
+
+    let mut mappings = 0;
+    debug::mappings(start.as_u64()..start.as_u64() + SIZE, |mapping| {
+        assert_eq!((mapping.virt, mapping.size, mapping.page_size), (start, SIZE, SIZE));
+        mappings += 1;
+    });
+    assert_eq!(mappings, 1);
+}
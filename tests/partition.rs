@@ -0,0 +1,199 @@
+// 分区表：MBR、GPT、GPT 的备份，以及分区读写时的块号换算
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::drivers::block::{self, BlockDevice, BlockError};
+use cjn_os::fs::partition::{self, Scheme};
+use spin::Mutex;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    cjn_os::test_kernel_init(boot_info);
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+const BLOCK_SIZE: usize = 512;
+const BLOCKS: usize = 1024;
+// GPT 的 128 个分区项占 32 块
+const GPT_ENTRIES: usize = 128;
+const GPT_ENTRY_BLOCKS: usize = GPT_ENTRIES * 128 / BLOCK_SIZE;
+const GUID_LINUX: [u8; 16] =
+    [0xAF, 0x3D, 0xC6, 0x0F, 0x83, 0x84, 0x72, 0x47, 0x8E, 0x79, 0x3D, 0x69, 0xD8, 0x47, 0x7D, 0xE4];
+
+struct RamDisk {
+    name: &'static str,
+    data: Arc<Mutex<Vec<u8>>>,
+}
+
+impl BlockDevice for RamDisk {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn model(&self) -> &str {
+        "test"
+    }
+
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        BLOCKS as u64
+    }
+
+    fn read_blocks(&mut self, lba: u64, buffer: &mut [u8]) -> Result<(), BlockError> {
+        let start = lba as usize * BLOCK_SIZE;
+        buffer.copy_from_slice(&self.data.lock()[start..start + buffer.len()]);
+        Ok(())
+    }
+
+    fn write_blocks(&mut self, lba: u64, data: &[u8]) -> Result<(), BlockError> {
+        let start = lba as usize * BLOCK_SIZE;
+        self.data.lock()[start..start + data.len()].copy_from_slice(data);
+        Ok(())
+    }
+}
+
+// 登记一块内容是 `image` 的磁盘，返回它的内容，测试直接看里面的数据
+fn attach(name: &'static str, image: Vec<u8>) -> Arc<Mutex<Vec<u8>>> {
+    let data = Arc::new(Mutex::new(image));
+    block::attach(Box::new(RamDisk { name, data: data.clone() }));
+    data
+}
+
+// 在 MBR 的第 `index` 项写一个分区
+fn mbr_entry(image: &mut [u8], index: usize, kind: u8, start: u32, count: u32) {
+    let entry = &mut image[446 + 16 * index..446 + 16 * (index + 1)];
+    entry[4] = kind;
+    entry[8..12].copy_from_slice(&start.to_le_bytes());
+    entry[12..16].copy_from_slice(&count.to_le_bytes());
+    image[510] = 0x55;
+    image[511] = 0xAA;
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = u32::MAX;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+// 在第 `lba` 块写 GPT 头，分区项在第 `entries_lba` 块开始
+fn gpt_header(image: &mut [u8], lba: usize, alternate: usize, entries_lba: usize) {
+    let entries_crc = crc32(&image[entries_lba * BLOCK_SIZE..(entries_lba + GPT_ENTRY_BLOCKS) * BLOCK_SIZE]);
+    let header = &mut image[lba * BLOCK_SIZE..(lba + 1) * BLOCK_SIZE];
+    header[..8].copy_from_slice(b"EFI PART");
+    header[8..12].copy_from_slice(&0x0001_0000u32.to_le_bytes());
+    header[12..16].copy_from_slice(&92u32.to_le_bytes());
+    header[24..32].copy_from_slice(&(lba as u64).to_le_bytes());
+    header[32..40].copy_from_slice(&(alternate as u64).to_le_bytes());
+    header[40..48].copy_from_slice(&(2 + GPT_ENTRY_BLOCKS as u64).to_le_bytes());
+    header[48..56].copy_from_slice(&((BLOCKS - 2 - GPT_ENTRY_BLOCKS) as u64).to_le_bytes());
+    header[72..80].copy_from_slice(&(entries_lba as u64).to_le_bytes());
+    header[80..84].copy_from_slice(&(GPT_ENTRIES as u32).to_le_bytes());
+    header[84..88].copy_from_slice(&128u32.to_le_bytes());
+    header[88..92].copy_from_slice(&entries_crc.to_le_bytes());
+    let crc = crc32(&header[..92]);
+    header[16..20].copy_from_slice(&crc.to_le_bytes());
+}
+
+// 保护性 MBR 加上主 GPT 和备份，第 2 项是名为 data 的 Linux 分区，第 1 项空着
+fn gpt_image() -> Vec<u8> {
+    let mut image = vec![0; BLOCK_SIZE * BLOCKS];
+    mbr_entry(&mut image, 0, 0xEE, 1, BLOCKS as u32 - 1);
+    let backup_entries = BLOCKS - 1 - GPT_ENTRY_BLOCKS;
+    for entries_lba in [2, backup_entries] {
+        let entry = &mut image[entries_lba * BLOCK_SIZE + 128..entries_lba * BLOCK_SIZE + 256];
+        entry[..16].copy_from_slice(&GUID_LINUX);
+        entry[32..40].copy_from_slice(&100u64.to_le_bytes());
+        entry[40..48].copy_from_slice(&199u64.to_le_bytes());
+        for (i, c) in "data".encode_utf16().enumerate() {
+            entry[56 + 2 * i..58 + 2 * i].copy_from_slice(&c.to_le_bytes());
+        }
+    }
+    gpt_header(&mut image, 1, BLOCKS - 1, 2);
+    gpt_header(&mut image, BLOCKS - 1, 1, backup_entries);
+    image
+}
+
+#[test_case]
+fn mbr_partitions_are_found() {
+    let mut image = vec![0; BLOCK_SIZE * BLOCKS];
+    mbr_entry(&mut image, 0, 0x0C, 64, 100);
+    // 扩展分区不支持，越界的分区跳过
+    mbr_entry(&mut image, 1, 0x05, 200, 100);
+    mbr_entry(&mut image, 2, 0x83, 512, 600);
+    mbr_entry(&mut image, 3, 0x83, 512, 50);
+    attach("mbr0", image);
+    let (scheme, table) = partition::read_table("mbr0").unwrap();
+    assert_eq!(scheme, Scheme::Mbr);
+    let found: Vec<_> = table.iter().map(|entry| (entry.index, entry.start, entry.count, entry.kind)).collect();
+    assert_eq!(found, [(1, 64, 100, "FAT32"), (4, 512, 50, "Linux")]);
+}
+
+#[test_case]
+fn gpt_partitions_are_found() {
+    attach("gpt0", gpt_image());
+    let (scheme, table) = partition::read_table("gpt0").unwrap();
+    assert_eq!(scheme, Scheme::Gpt);
+    assert_eq!(table.len(), 1);
+    let entry = &table[0];
+    assert_eq!((entry.index, entry.start, entry.count, entry.kind), (2, 100, 100, "Linux"));
+    assert_eq!(entry.label, "data");
+}
+
+#[test_case]
+fn corrupted_gpt_falls_back_to_backup() {
+    let mut image = gpt_image();
+    image[BLOCK_SIZE + 72] ^= 0xFF;
+    attach("gpt1", image);
+    let (_, table) = partition::read_table("gpt1").unwrap();
+    assert_eq!(table.len(), 1);
+    assert_eq!(table[0].start, 100);
+}
+
+#[test_case]
+fn partitions_translate_block_numbers() {
+    let disk = attach("gpt2", gpt_image());
+    assert_eq!(partition::scan("gpt2"), Ok(1));
+    let info = block::find("gpt2p2").unwrap();
+    assert_eq!((info.block_count, info.model.as_str()), (100, "data (Linux)"));
+
+    block::write("gpt2p2", 3, &[0x5A; BLOCK_SIZE]).unwrap();
+    assert!(disk.lock()[103 * BLOCK_SIZE..104 * BLOCK_SIZE].iter().all(|&b| b == 0x5A));
+    let mut buffer = vec![0; BLOCK_SIZE];
+    block::read("gpt2p2", 3, &mut buffer).unwrap();
+    assert!(buffer.iter().all(|&b| b == 0x5A));
+    // 不能越过分区的末尾
+    assert_eq!(block::read("gpt2p2", 100, &mut buffer), Err(BlockError::OutOfRange));
+}
+
+#[test_case]
+fn disk_without_table_is_reported() {
+    attach("raw0", vec![0; BLOCK_SIZE * BLOCKS]);
+    assert_eq!(partition::read_table("raw0"), Err(partition::PartitionError::NoTable));
+}
@@ -0,0 +1,87 @@
+// 性能剖析：嵌套的作用域算出自己的周期数和折叠栈，计时只在开启时记录，TSC 校准的结果合理
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::panic::PanicInfo;
+use core::time::Duration;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::perf::{self, Order, Sample};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    cjn_os::test_kernel_init(boot_info);
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+fn sample(name: &'static str, cpu: usize, start: u64, cycles: u64) -> Sample {
+    Sample { name, cpu, start, cycles }
+}
+
+// render 里面画了两个字，第一个字要光栅化；另一个 CPU 上的 render 和它同时开始，但不是它的子作用域
+fn samples() -> [Sample; 5] {
+    [
+        sample("rasterize", 0, 12, 3),
+        sample("render", 0, 10, 100),
+        sample("display_font", 0, 11, 10),
+        sample("display_font", 0, 30, 5),
+        sample("render", 1, 10, 40),
+    ]
+}
+
+#[test_case]
+fn own_time_excludes_children() {
+    let entries = perf::summarize(&samples(), Order::Own);
+    let names: Vec<_> = entries.iter().map(|e| e.name).collect();
+    assert_eq!(names, ["render", "display_font", "rasterize"]);
+    let render = entries[0];
+    assert_eq!((render.calls, render.total, render.own, render.max), (2, 140, 125, 100));
+    let font = entries[1];
+    assert_eq!((font.calls, font.total, font.own), (2, 15, 12));
+}
+
+#[test_case]
+fn folded_stacks() {
+    let mut out = String::new();
+    perf::folded(&samples(), &mut out).unwrap();
+    assert_eq!(out, "render 125\nrender;display_font 12\nrender;display_font;rasterize 3\n");
+}
+
+#[test_case]
+fn records_only_when_enabled() {
+    perf::clear();
+    {
+        perf::scope_timer!("disabled");
+    }
+    assert_eq!(perf::stats(), (0, 0));
+    perf::start();
+    {
+        perf::scope_timer!("enabled");
+    }
+    perf::stop();
+    let samples = perf::samples();
+    assert_eq!(samples.len(), 1);
+    assert_eq!(samples[0].name, "enabled");
+    perf::clear();
+}
+
+#[test_case]
+fn tsc_is_calibrated() {
+    assert!(perf::tsc_hz() > 1_000_000);
+    let second = perf::cycles_to_duration(perf::tsc_hz());
+    assert_eq!(second, Duration::from_secs(1));
+}
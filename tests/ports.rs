@@ -0,0 +1,57 @@
+// I/O 端口登记：重叠的范围被拒绝，释放后可以重新登记，启动时固定的设备已经登记好
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::io::port::{self, Claim, ClaimError};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    cjn_os::test_kernel_init(boot_info);
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+#[test_case]
+fn builtin_claims() {
+    assert_eq!(port::owner(0xCFC), Some("pci"));
+    assert_eq!(port::owner(0x43), Some("pit"));
+    assert_eq!(port::owner(0x3FD), Some("com1"));
+    assert_eq!(port::owner(0x64), Some("i8042"));
+    let claims = port::claims();
+    assert!(claims.windows(2).all(|pair| pair[0].start < pair[1].start));
+}
+
+#[test_case]
+fn conflict_and_release() {
+    port::claim("first", 0xE000, 0x10).unwrap();
+    // 同一个驱动重复登记同一段没有问题
+    port::claim("first", 0xE000, 0x10).unwrap();
+    assert_eq!(port::claim("second", 0xE00F, 4),
+               Err(ClaimError::Conflict(Claim { owner: "first", start: 0xE000, len: 0x10 })));
+    assert_eq!(port::owner(0xE008), Some("first"));
+    port::release(0xE000);
+    assert_eq!(port::owner(0xE008), None);
+    port::claim("second", 0xE00F, 4).unwrap();
+    assert_eq!(port::owner(0xE012), Some("second"));
+    port::release(0xE00F);
+}
+
+#[test_case]
+fn invalid_range() {
+    assert_eq!(port::claim("empty", 0xE100, 0), Err(ClaimError::InvalidRange));
+    assert_eq!(port::claim("wrap", 0xFFF0, 0x20), Err(ClaimError::InvalidRange));
+    port::claim("top", 0xFFF0, 0x10).unwrap();
+    port::release(0xFFF0);
+}
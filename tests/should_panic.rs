@@ -0,0 +1,30 @@
+// kassert! 失败时必须 panic：panic 处理函数报告成功，没有 panic 就算失败
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::io::qemu::{exit_qemu, QemuExitCode};
+use cjn_os::{kassert, serial_print, serial_println};
+
+entry_point!(main);
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    should_fail();
+    serial_println!("[test did not panic]");
+    exit_qemu(QemuExitCode::Failed);
+    cjn_os::hlt_loop();
+}
+
+fn should_fail() {
+    serial_print!("should_panic::should_fail...\t");
+    kassert!(1 + 1 == 3, "arithmetic is broken");
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+    cjn_os::hlt_loop();
+}
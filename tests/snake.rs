@@ -0,0 +1,104 @@
+// 贪吃蛇的规则：前进、吃食物变长、不能掉头、撞墙和撞到自己
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::apps::snake::{Direction, Game, Step};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    cjn_os::test_kernel_init(boot_info);
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+// 把食物放到不会挡路的角落
+fn park_food(game: &mut Game) {
+    assert!(game.place_food((0, 0)));
+}
+
+#[test_case]
+fn moves_and_grows() {
+    let mut game = Game::new(10, 10);
+    assert_eq!(game.body().collect::<Vec<_>>(), [(5, 5), (5, 4), (5, 3)]);
+    assert!(!game.place_food((5, 4)));
+    assert!(game.place_food((5, 6)));
+    assert_eq!(game.step(), Step::Ate);
+    assert_eq!((game.length(), game.score()), (4, 1));
+    assert!(game.food().is_some_and(|food| !game.body().any(|cell| cell == food)));
+    park_food(&mut game);
+    assert_eq!(game.step(), Step::Moved);
+    assert_eq!(game.head(), (5, 7));
+    assert_eq!(game.length(), 4);
+}
+
+#[test_case]
+fn cannot_reverse() {
+    let mut game = Game::new(10, 10);
+    park_food(&mut game);
+    game.steer(Direction::Left);
+    assert_eq!(game.step(), Step::Moved);
+    assert_eq!(game.head(), (5, 6));
+    // 连按两次：先向下再向左，两步都会走
+    game.steer(Direction::Down);
+    game.steer(Direction::Left);
+    game.step();
+    game.step();
+    assert_eq!((game.head(), game.direction()), ((6, 5), Direction::Left));
+}
+
+#[test_case]
+fn crashes_into_wall() {
+    let mut game = Game::new(5, 5);
+    park_food(&mut game);
+    game.steer(Direction::Up);
+    assert_eq!(game.step(), Step::Moved);
+    assert_eq!(game.step(), Step::Moved);
+    assert_eq!(game.step(), Step::Crashed);
+    assert!(game.is_over());
+    assert_eq!(game.step(), Step::Crashed);
+}
+
+#[test_case]
+fn crashes_into_itself() {
+    let mut game = Game::new(10, 10);
+    for food in [(5, 6), (5, 7)] {
+        game.place_food(food);
+        assert_eq!(game.step(), Step::Ate);
+    }
+    for direction in [Direction::Down, Direction::Left] {
+        park_food(&mut game);
+        game.steer(direction);
+        assert_eq!(game.step(), Step::Moved);
+    }
+    game.steer(Direction::Up);
+    assert_eq!(game.step(), Step::Crashed);
+    assert_eq!(game.length(), 5);
+}
+
+#[test_case]
+fn follows_its_tail() {
+    // 长度为 4 时绕一个 2x2 的圈，头每次走进尾巴刚离开的格子
+    let mut game = Game::new(10, 10);
+    game.place_food((5, 6));
+    game.step();
+    for direction in [Direction::Down, Direction::Left, Direction::Up, Direction::Right] {
+        park_food(&mut game);
+        game.steer(direction);
+        assert_eq!(game.step(), Step::Moved);
+    }
+}
@@ -0,0 +1,86 @@
+// WAV 解析：格式、跳过其他块、样本统一换成 16 位立体声
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::sound::wav::{Wav, WavError};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    cjn_os::test_kernel_init(boot_info);
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+fn chunk(file: &mut Vec<u8>, id: &[u8; 4], body: &[u8]) {
+    file.extend_from_slice(id);
+    file.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    file.extend_from_slice(body);
+    if body.len() % 2 == 1 {
+        file.push(0);
+    }
+}
+
+fn wav(tag: u16, channels: u16, rate: u32, bits: u16, samples: &[u8]) -> Vec<u8> {
+    let mut format = Vec::new();
+    let block_align = channels * bits / 8;
+    format.extend_from_slice(&tag.to_le_bytes());
+    format.extend_from_slice(&channels.to_le_bytes());
+    format.extend_from_slice(&rate.to_le_bytes());
+    format.extend_from_slice(&(rate * block_align as u32).to_le_bytes());
+    format.extend_from_slice(&block_align.to_le_bytes());
+    format.extend_from_slice(&bits.to_le_bytes());
+
+    let mut file = Vec::from(*b"RIFF\0\0\0\0WAVE");
+    chunk(&mut file, b"fmt ", &format);
+    // 长度是奇数的其他块，后面有填充字节
+    chunk(&mut file, b"LIST", b"abc");
+    chunk(&mut file, b"data", samples);
+    let size = (file.len() - 8) as u32;
+    file[4..8].copy_from_slice(&size.to_le_bytes());
+    file
+}
+
+#[test_case]
+fn stereo_16_bit() {
+    let samples: Vec<u8> = [100i16, -100, 32767, -32768].iter().flat_map(|s| s.to_le_bytes()).collect();
+    let file = wav(1, 2, 44100, 16, &samples);
+    let wav = Wav::parse(&file).unwrap();
+    assert_eq!((wav.rate, wav.channels, wav.bits, wav.frames()), (44100, 2, 16, 2));
+    assert_eq!(wav.frame(&file, 0), [100, -100]);
+    assert_eq!(wav.frame(&file, 1), [32767, -32768]);
+}
+
+#[test_case]
+fn mono_8_bit_is_widened() {
+    let file = wav(1, 1, 8000, 8, &[128, 255, 0]);
+    let wav = Wav::parse(&file).unwrap();
+    assert_eq!(wav.frames(), 3);
+    assert_eq!(wav.frame(&file, 0), [0, 0]);
+    assert_eq!(wav.frame(&file, 1), [127 << 8, 127 << 8]);
+    assert_eq!(wav.frame(&file, 2), [-128 << 8, -128 << 8]);
+}
+
+#[test_case]
+fn bad_files_are_rejected() {
+    assert_eq!(Wav::parse(b"RIFF\0\0\0\0AVI "), Err(WavError::NotWav));
+    // 浮点样本
+    assert_eq!(Wav::parse(&wav(3, 2, 48000, 32, &[0; 8])), Err(WavError::Unsupported));
+    let mut truncated = wav(1, 2, 48000, 16, &[0; 4]);
+    truncated.truncate(36);
+    assert_eq!(Wav::parse(&truncated), Err(WavError::Truncated));
+}
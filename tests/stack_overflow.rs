@@ -0,0 +1,53 @@
+// 栈溢出测试：无限递归碰到保护页触发页错误，压栈失败变成双重错误，
+// 双重错误处理函数必须在独立的 IST 栈上运行，否则会三重错误直接重启
+#![no_std]
+#![no_main]
+#![feature(abi_x86_interrupt)]
+
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::io::qemu::{exit_qemu, QemuExitCode};
+use cjn_os::{serial_print, serial_println};
+use lazy_static::lazy_static;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        unsafe {
+            idt.double_fault
+                .set_handler_fn(test_double_fault_handler)
+                .set_stack_index(cjn_os::gdt::DOUBLE_FAULT_IST_INDEX);
+        }
+        idt
+    };
+}
+
+entry_point!(main);
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    serial_print!("stack_overflow::stack_overflow...\t");
+    cjn_os::gdt::init();
+    TEST_IDT.load();
+    stack_overflow();
+    panic!("Execution continued after stack overflow");
+}
+
+#[allow(unconditional_recursion)]
+fn stack_overflow() {
+    stack_overflow();
+    // 防止尾递归优化
+    volatile::Volatile::new(0).read();
+}
+
+extern "x86-interrupt" fn test_double_fault_handler(_stack_frame: InterruptStackFrame, _error_code: u64) -> ! {
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
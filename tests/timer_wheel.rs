@@ -0,0 +1,114 @@
+// 时间轮：按期限执行、跨层的超时准时到期、取消之后不执行、表满时登记失败、时钟中断里唤醒等待
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::io::timer::uptime;
+use cjn_os::io::timer::wheel::{self, Action, TimerError, Wheel, CAPACITY};
+use cjn_os::sync::WaitQueue;
+use spin::Mutex;
+
+entry_point!(main);
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    cjn_os::init();
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+// 表很大，放在静态变量里而不是栈上
+static WHEEL: Mutex<Wheel> = Mutex::new(Wheel::new(0));
+
+fn ignore(_arg: usize) {}
+
+// 转到 `now`，返回到期的超时的参数之和和个数
+fn expire(wheel: &mut Wheel, now: u64) -> (usize, usize) {
+    wheel.advance(now);
+    let (mut sum, mut count) = (0, 0);
+    while let Some(action) = wheel.pop_expired() {
+        if let Action::Call(_, arg) = action {
+            sum += arg;
+            count += 1;
+        }
+    }
+    (sum, count)
+}
+
+#[test_case]
+fn expires_on_deadline() {
+    let mut wheel = WHEEL.lock();
+    *wheel = Wheel::new(100);
+    // 第 0 层、第 1 层、第 2 层和超出最高层的期限
+    for deadline in [105, 100 + 64 * 3 + 7, 100 + 64 * 64 * 5, 100 + (1 << 24) + 1000] {
+        wheel.insert(deadline, Action::Call(ignore, deadline as usize)).unwrap();
+    }
+    for deadline in [105, 100 + 64 * 3 + 7, 100 + 64 * 64 * 5, 100 + (1 << 24) + 1000] {
+        assert_eq!(expire(&mut wheel, deadline - 1), (0, 0));
+        assert_eq!(expire(&mut wheel, deadline), (deadline as usize, 1));
+    }
+    assert!(wheel.is_empty());
+    // 已经过了的期限在下一个 tick 到期
+    let now = wheel.now();
+    wheel.insert(0, Action::Call(ignore, 1)).unwrap();
+    assert_eq!(expire(&mut wheel, now + 1), (1, 1));
+}
+
+#[test_case]
+fn cancel() {
+    let mut wheel = WHEEL.lock();
+    *wheel = Wheel::new(0);
+    let first = wheel.insert(200, Action::Call(ignore, 1)).unwrap();
+    let second = wheel.insert(200, Action::Call(ignore, 2)).unwrap();
+    assert!(wheel.cancel(first));
+    assert!(!wheel.cancel(first));
+    assert_eq!(expire(&mut wheel, 200), (2, 1));
+    // 已经执行过的超时取消不了，位置重用之后旧的句柄也不会取消新的超时
+    assert!(!wheel.cancel(second));
+    let third = wheel.insert(300, Action::Call(ignore, 3)).unwrap();
+    assert!(!wheel.cancel(second));
+    assert!(wheel.cancel(third));
+    assert!(wheel.is_empty());
+}
+
+#[test_case]
+fn full() {
+    let mut wheel = WHEEL.lock();
+    *wheel = Wheel::new(0);
+    for i in 0..CAPACITY {
+        wheel.insert(i as u64 * 37, Action::Call(ignore, 1)).unwrap();
+    }
+    assert_eq!(wheel.insert(1, Action::Call(ignore, 1)), Err(TimerError::Full));
+    assert_eq!(expire(&mut wheel, CAPACITY as u64 * 37), (CAPACITY, CAPACITY));
+    assert!(wheel.insert(1, Action::Call(ignore, 1)).is_ok());
+}
+
+static QUEUE: WaitQueue = WaitQueue::new();
+static FIRED: AtomicBool = AtomicBool::new(false);
+
+fn fire(_arg: usize) {
+    FIRED.store(true, Ordering::Release);
+}
+
+#[test_case]
+fn timeout_from_interrupt() {
+    let start = uptime();
+    wheel::register_timeout(Duration::from_millis(20), Action::Call(fire, 0)).unwrap();
+    wheel::register_timeout(Duration::from_millis(20), Action::Wake(&QUEUE)).unwrap();
+    let cancelled = wheel::register_timeout(Duration::from_millis(10), Action::Call(fire, 0)).unwrap();
+    assert!(wheel::cancel_timeout(cancelled));
+    QUEUE.block_on_condition(|| FIRED.load(Ordering::Acquire));
+    assert!(uptime() - start >= Duration::from_millis(19));
+    assert_eq!(wheel::pending(), 0);
+}
@@ -0,0 +1,65 @@
+// USB HID 引导协议：键盘报告比较出按下和松开、修饰键，鼠标报告的移动方向和按键
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::drivers::xhci::hid::{self, BootKeyboard};
+use pc_keyboard::KeyCode;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    cjn_os::test_kernel_init(boot_info);
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+fn events(keyboard: &mut BootKeyboard, report: [u8; 8]) -> Vec<(KeyCode, bool)> {
+    let mut events = Vec::new();
+    keyboard.report(&report, |code, down| events.push((code, down)));
+    events
+}
+
+#[test_case]
+fn keyboard_report() {
+    let mut keyboard = BootKeyboard::new();
+    // 按下左 Shift 和 a，再按 b，松开 a 和 Shift
+    assert_eq!(events(&mut keyboard, [0x02, 0, 0x04, 0, 0, 0, 0, 0]), [(KeyCode::ShiftLeft, true), (KeyCode::A, true)]);
+    assert_eq!(events(&mut keyboard, [0x02, 0, 0x04, 0x05, 0, 0, 0, 0]), [(KeyCode::B, true)]);
+    assert_eq!(events(&mut keyboard, [0x00, 0, 0x05, 0, 0, 0, 0, 0]), [(KeyCode::A, false), (KeyCode::ShiftLeft, false)]);
+    // 按键太多的报告被忽略，不当作全部松开
+    assert_eq!(events(&mut keyboard, [0x00, 0, 1, 1, 1, 1, 1, 1]), []);
+    assert_eq!(events(&mut keyboard, [0x00, 0, 0, 0, 0, 0, 0, 0]), [(KeyCode::B, false)]);
+}
+
+#[test_case]
+fn key_codes() {
+    assert_eq!(hid::key_code(0x1E), Some(KeyCode::Key1));
+    assert_eq!(hid::key_code(0x27), Some(KeyCode::Key0));
+    assert_eq!(hid::key_code(0x45), Some(KeyCode::F12));
+    assert_eq!(hid::key_code(0x52), Some(KeyCode::ArrowUp));
+    assert_eq!(hid::key_code(0x62), Some(KeyCode::Numpad0));
+    assert_eq!(hid::key_code(0x00), None);
+}
+
+#[test_case]
+fn mouse_report() {
+    // Y 向下为正，换成向上为正
+    let event = hid::mouse_event(&[0x05, 0xFE, 0x03]).unwrap();
+    assert_eq!((event.dx, event.dy), (-2, -3));
+    assert!(event.left && !event.right && event.middle);
+    assert!(hid::mouse_event(&[0x01, 0x00]).is_none());
+}
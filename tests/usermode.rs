@@ -0,0 +1,131 @@
+// 用户态测试：程序在 ring 3 运行并通过系统调用退出，访问内核内存时只结束程序；
+// ELF 程序在自己的地址空间里运行，同一个文件的页共用，写的时候才复制
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::loader::elf::LoadError;
+use cjn_os::memory::{self, cow};
+use cjn_os::usermode::{self, programs, Exit, RunError};
+use x86_64::instructions::interrupts;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    let (mut mapper, mut frame_allocator) = cjn_os::test_kernel_init(boot_info);
+    usermode::init(&mut mapper, &mut frame_allocator).expect("Mapping user memory failed");
+    memory::install_frame_allocator(frame_allocator);
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+#[test_case]
+fn hello_exits_normally() {
+    let hello = programs::find("hello").unwrap();
+    assert_eq!(usermode::run(&hello), Ok(Exit::Code(0)));
+}
+
+#[test_case]
+fn fault_is_killed() {
+    let fault = programs::find("fault").unwrap();
+    assert_eq!(usermode::run(&fault), Ok(Exit::Killed("page fault")));
+}
+
+#[test_case]
+fn kernel_survives_and_reruns() {
+    let hello = programs::find("hello").unwrap();
+    for _ in 0..3 {
+        assert_eq!(usermode::run(&hello), Ok(Exit::Code(0)));
+    }
+    assert!(interrupts::are_enabled());
+}
+
+// 只有一个 PT_LOAD 段的最小 ELF 文件，段从文件开头映射到 `vaddr`，代码紧跟在程序头后面
+fn elf_with(code: &[u8], vaddr: u64, flags: u32) -> Vec<u8> {
+    let header_size = 64 + 56;
+    let size = (header_size + code.len()) as u64;
+    let mut file = Vec::new();
+    file.extend_from_slice(b"\x7fELF");
+    file.extend_from_slice(&[2, 1, 1, 0]);
+    file.resize(16, 0);
+    file.extend_from_slice(&2u16.to_le_bytes());
+    file.extend_from_slice(&0x3Eu16.to_le_bytes());
+    file.extend_from_slice(&1u32.to_le_bytes());
+    file.extend_from_slice(&(vaddr + header_size as u64).to_le_bytes());
+    file.extend_from_slice(&64u64.to_le_bytes());
+    file.extend_from_slice(&0u64.to_le_bytes());
+    file.extend_from_slice(&0u32.to_le_bytes());
+    for value in [64u16, 56, 1, 0, 0, 0] {
+        file.extend_from_slice(&value.to_le_bytes());
+    }
+    file.extend_from_slice(&1u32.to_le_bytes());
+    file.extend_from_slice(&flags.to_le_bytes());
+    for value in [0, vaddr, vaddr, size, size, 0x1000] {
+        file.extend_from_slice(&value.to_le_bytes());
+    }
+    file.extend_from_slice(code);
+    file
+}
+
+const READ_EXECUTE: u32 = 5;
+const READ_WRITE_EXECUTE: u32 = 7;
+
+// 改写紧跟在代码后面的一个字节，然后 exit(0)
+const WRITER: [u8; 17] = [
+    0xC6, 0x05, 0x09, 0x00, 0x00, 0x00, 0x01, // mov byte ptr [rip + 9], 1
+    0xB8, 0x00, 0x00, 0x00, 0x00,             // mov eax, EXIT
+    0x31, 0xFF,                               // xor edi, edi
+    0xCD, 0x80,                               // int 0x80
+    0x00,
+];
+
+#[test_case]
+fn elf_hello_exits_normally() {
+    let hello = programs::find("hello").unwrap();
+    let file = elf_with(hello.code(), 0x80_0000_0000, READ_EXECUTE);
+    for _ in 0..3 {
+        assert_eq!(usermode::run_elf("hello", &file), Ok(Exit::Code(0)));
+    }
+}
+
+#[test_case]
+fn elf_fault_is_killed() {
+    let fault = programs::find("fault").unwrap();
+    let file = elf_with(fault.code(), 0x80_0000_0000, READ_EXECUTE);
+    assert_eq!(usermode::run_elf("fault", &file), Ok(Exit::Killed("page fault")));
+}
+
+#[test_case]
+fn elf_rejects_bad_files() {
+    let hello = programs::find("hello").unwrap();
+    let mut file = elf_with(hello.code(), 0x80_0000_0000, READ_EXECUTE);
+    assert_eq!(usermode::run_elf("bad", &file[..40]), Err(RunError::Load(LoadError::Malformed)));
+    file[18] = 0x28;
+    assert_eq!(usermode::run_elf("bad", &file), Err(RunError::Load(LoadError::Unsupported)));
+    let kernel = elf_with(hello.code(), 0xFFFF_8000_0000_0000, READ_EXECUTE);
+    assert_eq!(usermode::run_elf("bad", &kernel), Err(RunError::Load(LoadError::BadAddress)));
+}
+
+#[test_case]
+fn elf_writes_copy_shared_pages() {
+    let file = elf_with(&WRITER, 0x80_0000_0000, READ_WRITE_EXECUTE);
+    let before = cow::stats().copied;
+    // 装好的页留在缓存里，每次运行写的都是自己的副本
+    for _ in 0..2 {
+        assert_eq!(usermode::run_elf("writer", &file), Ok(Exit::Code(0)));
+    }
+    assert_eq!(cow::stats().copied, before + 2);
+}
@@ -0,0 +1,45 @@
+// 构建信息：build.rs 生成的常量和一行说明
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::string::ToString;
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::version::{self, Banner};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    cjn_os::test_kernel_init(boot_info);
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+#[test_case]
+fn constants_are_filled_in() {
+    assert_eq!(version::VERSION, env!("CARGO_PKG_VERSION"));
+    assert!(!version::GIT_HASH.is_empty());
+    // 2020 年以后构建的
+    assert!(version::BUILD_TIMESTAMP > 1_577_836_800);
+    assert!(version::FEATURES.windows(2).all(|pair| pair[0] < pair[1]));
+}
+
+#[test_case]
+fn banner_names_the_build() {
+    let banner = Banner.to_string();
+    assert!(banner.starts_with("Cinea OS "));
+    assert!(banner.contains(version::VERSION));
+    assert!(banner.contains(version::GIT_HASH));
+    assert!(banner.contains(&version::build_time().to_string()));
+}
@@ -0,0 +1,52 @@
+// 虚拟控制台：编号和名字，切换后前台的控制台，往后台的控制台输出
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::format;
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::io::vt::{self, Tty, COUNT};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    cjn_os::test_kernel_init(boot_info);
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+#[test_case]
+fn names() {
+    assert_eq!(format!("{}", Tty::SHELL), "tty1");
+    assert_eq!(format!("{}", Tty::LOG), "tty2");
+    assert_eq!(Tty::new(COUNT - 1).map(Tty::index), Some(COUNT - 1));
+    assert!(Tty::new(COUNT).is_none());
+    assert_ne!(Tty::SHELL, Tty::LOG);
+}
+
+#[test_case]
+fn switch_and_write_to_background() {
+    assert_eq!(vt::active(), Tty::SHELL);
+    let tty3 = Tty::new(2).unwrap();
+    // 请求切换要等 poll 才执行
+    vt::request_switch(tty3);
+    assert_eq!(vt::active(), Tty::SHELL);
+    vt::poll();
+    assert_eq!(vt::active(), tty3);
+    // shell 的输出现在写在后台
+    cjn_os::println!("written to tty1 in the background");
+    vt::write(tty3, format_args!("written to tty3\n"));
+    vt::switch(Tty::SHELL);
+    assert_eq!(vt::active(), Tty::SHELL);
+}
@@ -0,0 +1,76 @@
+// 看门狗：喂狗的位置，没喂狗时不监视，超时后被检测到，再喂狗后重新计时
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cjn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use core::panic::PanicInfo;
+use core::time::Duration;
+
+use bootloader::{entry_point, BootInfo};
+use cjn_os::debug::watchdog;
+use cjn_os::io::timer::uptime;
+use cjn_os::logger::ring;
+use log::LevelFilter;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    cjn_os::test_kernel_init(boot_info);
+    test_main();
+    cjn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cjn_os::test_panic_handler(info)
+}
+
+// 开着中断空转，时钟中断照常进来
+fn spin(duration: Duration) {
+    let deadline = uptime() + duration;
+    while uptime() < deadline {
+        core::hint::spin_loop();
+    }
+}
+
+#[test_case]
+fn disarmed() {
+    watchdog::disarm();
+    assert_eq!(watchdog::status().count(), 0);
+    // 没喂过狗的时候 sleep 不会开始监视
+    cjn_os::io::timer::sleep(Duration::from_millis(10));
+    assert_eq!(watchdog::status().count(), 0);
+}
+
+#[test_case]
+fn location() {
+    watchdog::feed();
+    let status = watchdog::status().next().expect("cpu0 is watched");
+    assert_eq!(status.cpu, 0);
+    assert!(status.since < Duration::from_secs(1));
+    assert!(status.location.is_some_and(|location| location.file().ends_with("watchdog.rs")));
+    watchdog::disarm();
+}
+
+#[test_case]
+fn lockup() {
+    watchdog::set_timeout(Duration::from_millis(300));
+    let before = watchdog::lockups();
+    let since = ring::next_sequence();
+    watchdog::feed();
+    spin(Duration::from_millis(600));
+    assert_eq!(watchdog::lockups(), before + 1);
+    let entries = ring::entries(since, LevelFilter::Warn);
+    assert!(entries.iter().any(|entry| entry.message().starts_with("soft lockup: cpu0")));
+
+    // 喂狗后重新计时，同一次卡住只报告一次
+    watchdog::feed();
+    spin(Duration::from_millis(100));
+    assert_eq!(watchdog::lockups(), before + 1);
+    watchdog::disarm();
+    watchdog::set_timeout(watchdog::DEFAULT_TIMEOUT);
+}